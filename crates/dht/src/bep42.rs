@@ -0,0 +1,166 @@
+// BEP 42: DHT security extension. Node IDs are tied to the node's external IP address,
+// which makes it much harder to place nodes at chosen positions in the keyspace.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+};
+
+use librqbit_core::hash_id::Id20;
+use rand::RngCore;
+
+const V4_MASK: [u8; 4] = [0x03, 0x0f, 0x3f, 0xff];
+const V6_MASK: [u8; 8] = [0x01, 0x03, 0x07, 0x0f, 0x1f, 0x3f, 0x7f, 0xff];
+
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in data {
+        crc ^= u32::from(*b);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn ip_crc(ip: IpAddr, r: u8) -> u32 {
+    let mut buf = [0u8; 8];
+    let len = match ip {
+        IpAddr::V4(ip) => {
+            for ((b, o), m) in buf.iter_mut().zip(ip.octets()).zip(V4_MASK) {
+                *b = o & m;
+            }
+            4
+        }
+        IpAddr::V6(ip) => {
+            for ((b, o), m) in buf.iter_mut().zip(ip.octets()).zip(V6_MASK) {
+                *b = o & m;
+            }
+            8
+        }
+    };
+    buf[0] |= (r & 0x7) << 5;
+    crc32c(&buf[..len])
+}
+
+/// Addresses that BEP 42 doesn't apply to: nodes on these can have any ID.
+pub fn is_exempt(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_loopback() || ip.is_link_local(),
+        IpAddr::V6(ip) => ip.is_loopback() || ip.is_unique_local() || ip.is_unicast_link_local(),
+    }
+}
+
+/// Generate a random node ID that is valid for the given external IP.
+pub fn generate_node_id(ip: IpAddr) -> Id20 {
+    let mut id = [0u8; 20];
+    rand::rng().fill_bytes(&mut id);
+    let crc = ip_crc(ip.to_canonical(), id[19]).to_be_bytes();
+    id[0] = crc[0];
+    id[1] = crc[1];
+    id[2] = (crc[2] & 0xf8) | (id[2] & 0x7);
+    Id20::new(id)
+}
+
+/// Check if the node ID is valid for the IP it was seen from.
+pub fn is_node_id_secure(id: &Id20, ip: IpAddr) -> bool {
+    if is_exempt(ip) {
+        return true;
+    }
+    let crc = ip_crc(ip.to_canonical(), id.0[19]).to_be_bytes();
+    id.0[0] == crc[0] && id.0[1] == crc[1] && id.0[2] & 0xf8 == crc[2] & 0xf8
+}
+
+// How many distinct nodes must report the same external IP before we believe it.
+const EXTERNAL_IP_MIN_VOTES: usize = 3;
+// Bounds the memory used by nodes reporting garbage.
+const EXTERNAL_IP_MAX_CANDIDATES: usize = 16;
+
+/// Reports of our external IP from other nodes ("ip" in responses). A single node could lie
+/// about it, so an IP is only accepted once enough nodes with different IPs agree.
+#[derive(Default)]
+pub struct ExternalIpVotes {
+    // Reported IP => IPs of the nodes that reported it.
+    votes: HashMap<IpAddr, HashSet<IpAddr>>,
+}
+
+impl ExternalIpVotes {
+    /// Count a report of our IP from a node. Returns the IP once enough nodes reported it, and
+    /// starts counting from scratch after that.
+    pub fn vote(&mut self, voter: IpAddr, ip: IpAddr) -> Option<IpAddr> {
+        if !self.votes.contains_key(&ip) && self.votes.len() >= EXTERNAL_IP_MAX_CANDIDATES {
+            self.votes.clear();
+        }
+        let voters = self.votes.entry(ip).or_default();
+        voters.insert(voter.to_canonical());
+        if voters.len() < EXTERNAL_IP_MIN_VOTES {
+            return None;
+        }
+        self.votes.clear();
+        Some(ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, str::FromStr};
+
+    use librqbit_core::hash_id::Id20;
+
+    use super::{ExternalIpVotes, generate_node_id, is_node_id_secure};
+
+    #[test]
+    fn test_bep42_vectors() {
+        // From the BEP 42 text.
+        for (ip, id) in [
+            ("124.31.75.21", "5fbfbff10c5d6a4ec8a88e4c6ab4c28b95eee401"),
+            ("21.75.31.124", "5a3ce9c14e7a08645677bbd1cfe7d8f956d53256"),
+            ("65.23.51.170", "a5d43220bc8f112a3d426c84764f8c2a1150e616"),
+            ("84.124.73.14", "1b0321dd1bb1fe518101ceef99462b947a01ff41"),
+            ("43.213.53.83", "e56f6cbf5b7c4be0237986d5243b87aa6d51305a"),
+        ] {
+            let ip: IpAddr = ip.parse().unwrap();
+            let id = Id20::from_str(id).unwrap();
+            assert!(is_node_id_secure(&id, ip), "{ip}");
+            assert!(!is_node_id_secure(&id, "1.2.3.4".parse().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_generate_node_id() {
+        for ip in ["124.31.75.21", "2001:db8::1", "::ffff:65.23.51.170"] {
+            let ip: IpAddr = ip.parse().unwrap();
+            for _ in 0..16 {
+                assert!(is_node_id_secure(&generate_node_id(ip), ip), "{ip}");
+            }
+        }
+
+        // Local addresses are exempt.
+        let id = Id20::new([0; 20]);
+        assert!(is_node_id_secure(&id, "192.168.1.1".parse().unwrap()));
+        assert!(is_node_id_secure(&id, "::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_external_ip_votes() {
+        let ip: IpAddr = "124.31.75.21".parse().unwrap();
+        let liar: IpAddr = "1.2.3.4".parse().unwrap();
+        let node = |i: u8| IpAddr::from([10, 0, 0, i]);
+        let mut votes = ExternalIpVotes::default();
+
+        // The same node reporting many times counts once.
+        for _ in 0..10 {
+            assert_eq!(votes.vote(node(1), liar), None);
+        }
+        assert_eq!(votes.vote(node(1), ip), None);
+        assert_eq!(votes.vote(node(2), ip), None);
+        assert_eq!(votes.vote(node(3), ip), Some(ip));
+
+        // Counting starts over once accepted.
+        assert_eq!(votes.vote(node(4), ip), None);
+    }
+}
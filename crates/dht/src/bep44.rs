@@ -0,0 +1,489 @@
+// BEP 44: storing arbitrary data in the DHT.
+//
+// Immutable items are stored under the SHA-1 of their bencoded value. Mutable items are stored
+// under the SHA-1 of the owner's ed25519 public key (plus an optional salt), and can be updated
+// by the owner by signing a value with a higher sequence number.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use bencode::ByteBufOwned;
+use bytes::Bytes;
+use librqbit_core::hash_id::Id20;
+use parking_lot::RwLock;
+use serde::Serialize;
+use sha1w::ISha1;
+use tracing::trace;
+
+use crate::{
+    bprotocol::{PutRequest, RawBencode, Response},
+    ed25519::{self, Keypair, PUBLIC_KEY_LEN, SIGNATURE_LEN},
+};
+
+/// Max length of the bencoded value.
+pub const MAX_VALUE_LEN: usize = 1000;
+pub const MAX_SALT_LEN: usize = 64;
+
+// Items have to be put again within this time to stay in the DHT.
+const ITEM_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+const MAX_STORED_ITEMS: usize = 1000;
+
+fn sha1(parts: &[&[u8]]) -> Id20 {
+    let mut h = sha1w::Sha1::new();
+    for p in parts {
+        h.update(p);
+    }
+    Id20::new(h.finish())
+}
+
+pub fn immutable_target(value: &[u8]) -> Id20 {
+    sha1(&[value])
+}
+
+pub fn mutable_target(public_key: &[u8; PUBLIC_KEY_LEN], salt: &[u8]) -> Id20 {
+    sha1(&[public_key, salt])
+}
+
+// The buffer that gets signed: the bencoded salt, seq and v keys, without the outer dict.
+/// The bytes that are signed for a mutable item, e.g. "4:salt6:foobar3:seqi1e1:v12:Hello World!".
+pub fn signature_buf(salt: &[u8], seq: i64, value: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(salt.len() + value.len() + 32);
+    if !salt.is_empty() {
+        buf.extend_from_slice(format!("4:salt{}:", salt.len()).as_bytes());
+        buf.extend_from_slice(salt);
+    }
+    buf.extend_from_slice(format!("3:seqi{seq}e1:v").as_bytes());
+    buf.extend_from_slice(value);
+    buf
+}
+
+fn check_value(value: &[u8]) -> Result<(), PutError> {
+    if value.len() > MAX_VALUE_LEN {
+        return Err(PutError::VALUE_TOO_BIG);
+    }
+    if bencode::dyn_from_bytes::<bencode::ByteBuf>(value).is_err() {
+        return Err(PutError::INVALID_VALUE);
+    }
+    Ok(())
+}
+
+/// A signed mutable item. `value` is the bencoded "v".
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct MutableItem {
+    #[serde(serialize_with = "serialize_hex")]
+    pub public_key: [u8; PUBLIC_KEY_LEN],
+    #[serde(serialize_with = "serialize_hex")]
+    pub salt: Bytes,
+    pub seq: i64,
+    #[serde(serialize_with = "serialize_hex")]
+    pub signature: [u8; SIGNATURE_LEN],
+    #[serde(serialize_with = "serialize_hex")]
+    pub value: Bytes,
+}
+
+fn serialize_hex<S: serde::Serializer>(v: impl AsRef<[u8]>, s: S) -> Result<S::Ok, S::Error> {
+    use std::fmt::Write;
+    let mut out = String::with_capacity(v.as_ref().len() * 2);
+    for b in v.as_ref() {
+        write!(out, "{b:02x}").unwrap();
+    }
+    s.serialize_str(&out)
+}
+
+impl MutableItem {
+    pub fn new(keypair: &Keypair, salt: Bytes, seq: i64, value: Bytes) -> crate::Result<Self> {
+        if salt.len() > MAX_SALT_LEN {
+            return Err(crate::Error::Bep44(PutError::SALT_TOO_BIG));
+        }
+        check_value(&value).map_err(crate::Error::Bep44)?;
+        Ok(Self {
+            public_key: *keypair.public_key(),
+            signature: keypair.sign(&signature_buf(&salt, seq, &value)),
+            salt,
+            seq,
+            value,
+        })
+    }
+
+    /// An item that was signed elsewhere, so that the secret key doesn't have to be shared.
+    /// The signature is over [`signature_buf`].
+    pub fn from_signed(
+        public_key: [u8; PUBLIC_KEY_LEN],
+        signature: [u8; SIGNATURE_LEN],
+        salt: Bytes,
+        seq: i64,
+        value: Bytes,
+    ) -> crate::Result<Self> {
+        if salt.len() > MAX_SALT_LEN {
+            return Err(crate::Error::Bep44(PutError::SALT_TOO_BIG));
+        }
+        check_value(&value).map_err(crate::Error::Bep44)?;
+        let item = Self {
+            public_key,
+            salt,
+            seq,
+            signature,
+            value,
+        };
+        if !item.verify() {
+            return Err(crate::Error::Bep44(PutError::INVALID_SIGNATURE));
+        }
+        Ok(item)
+    }
+
+    pub fn target(&self) -> Id20 {
+        mutable_target(&self.public_key, &self.salt)
+    }
+
+    pub fn verify(&self) -> bool {
+        ed25519::verify(
+            &self.public_key,
+            &signature_buf(&self.salt, self.seq, &self.value),
+            &self.signature,
+        )
+    }
+}
+
+/// A BEP 44 error, sent back to the node that tried to put an item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PutError {
+    pub code: i32,
+    pub message: &'static str,
+}
+
+impl PutError {
+    pub(crate) const BAD_TOKEN: PutError = PutError {
+        code: 203,
+        message: "bad token",
+    };
+    const INVALID_VALUE: PutError = PutError {
+        code: 203,
+        message: "invalid value",
+    };
+    const VALUE_TOO_BIG: PutError = PutError {
+        code: 205,
+        message: "message (v field) too big",
+    };
+    const INVALID_SIGNATURE: PutError = PutError {
+        code: 206,
+        message: "invalid signature",
+    };
+    const SALT_TOO_BIG: PutError = PutError {
+        code: 207,
+        message: "salt (salt field) too big",
+    };
+    const CAS_MISMATCH: PutError = PutError {
+        code: 301,
+        message: "the CAS hash mismatched, re-read value and try again",
+    };
+    const SEQ_TOO_LOW: PutError = PutError {
+        code: 302,
+        message: "sequence number less than current",
+    };
+}
+
+impl std::fmt::Display for PutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Item {
+    Immutable(Bytes),
+    Mutable(MutableItem),
+}
+
+impl Item {
+    pub fn target(&self) -> Id20 {
+        match self {
+            Item::Immutable(v) => immutable_target(v),
+            Item::Mutable(m) => m.target(),
+        }
+    }
+
+    fn from_parts(
+        v: &ByteBufOwned,
+        k: Option<&ByteBufOwned>,
+        sig: Option<&ByteBufOwned>,
+        seq: Option<i64>,
+        salt: Option<&ByteBufOwned>,
+    ) -> Result<Item, PutError> {
+        let Some(k) = k else {
+            return Ok(Item::Immutable(v.0.clone()));
+        };
+        let (Ok(public_key), Some(Ok(signature)), Some(seq)) = (
+            k.as_ref().try_into(),
+            sig.map(|s| s.as_ref().try_into()),
+            seq,
+        ) else {
+            return Err(PutError::INVALID_VALUE);
+        };
+        Ok(Item::Mutable(MutableItem {
+            public_key,
+            salt: salt.map(|s| s.0.clone()).unwrap_or_default(),
+            seq,
+            signature,
+            value: v.0.clone(),
+        }))
+    }
+
+    pub(crate) fn from_put_request(req: &PutRequest<ByteBufOwned>) -> Result<Item, PutError> {
+        Self::from_parts(
+            &req.v.0,
+            req.k.as_ref(),
+            req.sig.as_ref(),
+            req.seq,
+            req.salt.as_ref(),
+        )
+    }
+
+    pub(crate) fn to_put_request(
+        &self,
+        id: Id20,
+        token: ByteBufOwned,
+        cas: Option<i64>,
+    ) -> PutRequest<ByteBufOwned> {
+        match self {
+            Item::Immutable(v) => PutRequest {
+                id,
+                token,
+                v: RawBencode(ByteBufOwned(v.clone())),
+                k: None,
+                sig: None,
+                seq: None,
+                salt: None,
+                cas: None,
+            },
+            Item::Mutable(m) => PutRequest {
+                id,
+                token,
+                v: RawBencode(ByteBufOwned(m.value.clone())),
+                k: Some(ByteBufOwned::from(&m.public_key[..])),
+                sig: Some(ByteBufOwned::from(&m.signature[..])),
+                seq: Some(m.seq),
+                salt: (!m.salt.is_empty()).then(|| ByteBufOwned(m.salt.clone())),
+                cas,
+            },
+        }
+    }
+
+    /// The item from a get response, if it's there and valid for the target.
+    pub(crate) fn from_response(target: &Id20, resp: &Response<ByteBufOwned>) -> Option<Item> {
+        let v = resp.v.as_ref()?;
+        let item = Self::from_parts(&v.0, resp.k.as_ref(), resp.sig.as_ref(), resp.seq, None);
+        let item = match item {
+            Ok(Item::Mutable(mut m)) => {
+                // The salt isn't sent back, it's part of the target instead.
+                m.salt = Bytes::new();
+                Item::Mutable(m)
+            }
+            other => other.ok()?,
+        };
+        (item.target() == *target && item.is_valid()).then_some(item)
+    }
+
+    fn is_valid(&self) -> bool {
+        match self {
+            Item::Immutable(v) => check_value(v).is_ok(),
+            Item::Mutable(m) => check_value(&m.value).is_ok() && m.verify(),
+        }
+    }
+
+    /// Add the item to a get response. If the requester already has this seq, skip the value.
+    pub(crate) fn fill_response(&self, resp: &mut Response<ByteBufOwned>, seq: Option<i64>) {
+        match self {
+            Item::Immutable(v) => resp.v = Some(RawBencode(ByteBufOwned(v.clone()))),
+            Item::Mutable(m) => {
+                resp.k = Some(ByteBufOwned::from(&m.public_key[..]));
+                resp.sig = Some(ByteBufOwned::from(&m.signature[..]));
+                resp.seq = Some(m.seq);
+                if seq.is_none_or(|seq| seq < m.seq) {
+                    resp.v = Some(RawBencode(ByteBufOwned(m.value.clone())));
+                }
+            }
+        }
+    }
+}
+
+struct StoredItem {
+    item: Item,
+    time: Instant,
+}
+
+/// Items that other nodes put on us.
+#[derive(Default)]
+pub struct ItemStore {
+    items: RwLock<HashMap<Id20, StoredItem>>,
+}
+
+impl ItemStore {
+    pub fn get(&self, target: &Id20) -> Option<Item> {
+        let items = self.items.read();
+        let stored = items.get(target)?;
+        if stored.time.elapsed() > ITEM_TTL {
+            return None;
+        }
+        Some(stored.item.clone())
+    }
+
+    /// Validate and store the item. The target is the key it was put under.
+    pub fn put(&self, target: Id20, item: Item, cas: Option<i64>) -> Result<(), PutError> {
+        match &item {
+            Item::Immutable(v) => {
+                check_value(v)?;
+                if immutable_target(v) != target {
+                    return Err(PutError::INVALID_VALUE);
+                }
+            }
+            Item::Mutable(m) => {
+                check_value(&m.value)?;
+                if m.salt.len() > MAX_SALT_LEN {
+                    return Err(PutError::SALT_TOO_BIG);
+                }
+                if m.target() != target || !m.verify() {
+                    return Err(PutError::INVALID_SIGNATURE);
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let mut items = self.items.write();
+        if let (Some(existing), Item::Mutable(new)) = (items.get(&target), &item)
+            && existing.time.elapsed() <= ITEM_TTL
+            && let Item::Mutable(existing) = &existing.item
+        {
+            if cas.is_some_and(|cas| cas != existing.seq) {
+                return Err(PutError::CAS_MISMATCH);
+            }
+            if new.seq < existing.seq || (new.seq == existing.seq && new.value != existing.value) {
+                return Err(PutError::SEQ_TOO_LOW);
+            }
+        }
+
+        if items.len() >= MAX_STORED_ITEMS && !items.contains_key(&target) {
+            items.retain(|_, s| s.time.elapsed() <= ITEM_TTL);
+            if items.len() >= MAX_STORED_ITEMS {
+                let oldest = items.iter().min_by_key(|(_, s)| s.time).map(|(k, _)| *k);
+                if let Some(oldest) = oldest {
+                    items.remove(&oldest);
+                }
+            }
+        }
+        trace!(?target, "storing item");
+        items.insert(target, StoredItem { item, time: now });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Item, ItemStore, MutableItem, PutError, immutable_target, signature_buf};
+    use crate::ed25519::Keypair;
+
+    #[test]
+    fn test_bep44_vectors() {
+        // From the BEP 44 text.
+        assert_eq!(
+            immutable_target(b"12:Hello World!").as_string(),
+            "e5f96f6f38320f0f33959cb4d3d656452117aadb"
+        );
+        assert_eq!(
+            signature_buf(b"", 1, b"12:Hello World!"),
+            b"3:seqi1e1:v12:Hello World!"
+        );
+        assert_eq!(
+            signature_buf(b"foobar", 1, b"12:Hello World!"),
+            b"4:salt6:foobar3:seqi1e1:v12:Hello World!"
+        );
+
+        let keypair = Keypair::generate();
+        let item = MutableItem::new(&keypair, Bytes::new(), 1, Bytes::from_static(b"1:a")).unwrap();
+        assert!(item.verify());
+        let mut tampered = item.clone();
+        tampered.seq = 2;
+        assert!(!tampered.verify());
+
+        let signed = MutableItem::from_signed(
+            item.public_key,
+            keypair.sign(&signature_buf(b"", 1, b"1:a")),
+            Bytes::new(),
+            1,
+            Bytes::from_static(b"1:a"),
+        )
+        .unwrap();
+        assert_eq!(signed, item);
+        assert!(
+            MutableItem::from_signed(
+                item.public_key,
+                item.signature,
+                Bytes::new(),
+                2,
+                Bytes::from_static(b"1:a"),
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_item_store() {
+        let store = ItemStore::default();
+
+        let v = Bytes::from_static(b"12:Hello World!");
+        let target = immutable_target(&v);
+        store.put(target, Item::Immutable(v.clone()), None).unwrap();
+        assert_eq!(store.get(&target), Some(Item::Immutable(v.clone())));
+        assert_eq!(
+            store.put(immutable_target(b"x"), Item::Immutable(v), None),
+            Err(PutError::INVALID_VALUE)
+        );
+        assert_eq!(
+            store.put(
+                immutable_target(b"not bencode"),
+                Item::Immutable(Bytes::from_static(b"not bencode")),
+                None
+            ),
+            Err(PutError::INVALID_VALUE)
+        );
+
+        let keypair = Keypair::generate();
+        let salt = Bytes::from_static(b"salt");
+        let item = |seq, v: &'static [u8]| {
+            MutableItem::new(&keypair, salt.clone(), seq, Bytes::from_static(v)).unwrap()
+        };
+        let first = item(1, b"1:a");
+        let target = first.target();
+        store
+            .put(target, Item::Mutable(first.clone()), None)
+            .unwrap();
+        // Same item again is fine.
+        store.put(target, Item::Mutable(first), None).unwrap();
+        assert_eq!(
+            store.put(target, Item::Mutable(item(0, b"1:b")), None),
+            Err(PutError::SEQ_TOO_LOW)
+        );
+        assert_eq!(
+            store.put(target, Item::Mutable(item(1, b"1:b")), None),
+            Err(PutError::SEQ_TOO_LOW)
+        );
+        assert_eq!(
+            store.put(target, Item::Mutable(item(2, b"1:b")), Some(5)),
+            Err(PutError::CAS_MISMATCH)
+        );
+        store
+            .put(target, Item::Mutable(item(2, b"1:b")), Some(1))
+            .unwrap();
+        assert_eq!(store.get(&target), Some(Item::Mutable(item(2, b"1:b"))));
+
+        let mut bad = item(3, b"1:c");
+        bad.value = Bytes::from_static(b"1:d");
+        assert_eq!(
+            store.put(target, Item::Mutable(bad), None),
+            Err(PutError::INVALID_SIGNATURE)
+        );
+    }
+}
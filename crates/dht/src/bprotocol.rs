@@ -328,6 +328,29 @@ pub struct FindNodeRequest {
     pub want: Option<Want>,
 }
 
+/// An arbitrary bencoded value, kept as raw bytes. Used for BEP 44 "v".
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct RawBencode<BufT>(pub BufT);
+
+impl<BufT: AsRef<[u8]>> Serialize for RawBencode<BufT> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        bencode::raw_value::RawValue(self.0.as_ref()).serialize(serializer)
+    }
+}
+
+impl<'de, BufT: Deserialize<'de>> Deserialize<'de> for RawBencode<BufT> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        bencode::WithRawBytes::<IgnoredAny, BufT>::deserialize(deserializer)
+            .map(|v| RawBencode(v.raw_bytes))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Response<BufT: ByteBufT> {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -339,6 +362,43 @@ pub struct Response<BufT: ByteBufT> {
     pub nodes6: Option<CompactNodeInfo<BufT, SocketAddrV6>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub token: Option<BufT>,
+    // BEP 44 get responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub v: Option<RawBencode<BufT>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k: Option<BufT>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sig: Option<BufT>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetRequest {
+    pub id: Id20,
+    pub target: Id20,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub want: Option<Want>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PutRequest<BufT: ByteBufT> {
+    pub id: Id20,
+    pub token: BufT,
+    pub v: RawBencode<BufT>,
+    // The rest is only set for mutable items.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k: Option<BufT>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sig: Option<BufT>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub salt: Option<BufT>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cas: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -390,6 +450,8 @@ pub enum MessageKind<BufT: ByteBufT> {
     Response(Response<BufT>),
     PingRequest(PingRequest),
     AnnouncePeer(AnnouncePeer<BufT>),
+    GetRequest(GetRequest),
+    PutRequest(PutRequest<BufT>),
 }
 
 impl<BufT: ByteBufT> core::fmt::Debug for MessageKind<BufT> {
@@ -401,6 +463,8 @@ impl<BufT: ByteBufT> core::fmt::Debug for MessageKind<BufT> {
             Self::Response(r) => write!(f, "{r:?}"),
             Self::PingRequest(r) => write!(f, "{r:?}"),
             Self::AnnouncePeer(r) => write!(f, "{r:?}"),
+            Self::GetRequest(r) => write!(f, "{r:?}"),
+            Self::PutRequest(r) => write!(f, "{r:?}"),
         }
     }
 }
@@ -492,6 +556,32 @@ pub fn serialize_message<'a, W: Write, BufT: ByteBufT + From<&'a [u8]>>(
             };
             Ok(bencode::bencode_serialize_to_writer(msg, writer)?)
         }
+        MessageKind::GetRequest(req) => {
+            let msg: RawMessage<BufT, _, ()> = RawMessage {
+                message_type: MessageType::Request,
+                transaction_id,
+                error: None,
+                response: None,
+                method_name: Some(BufT::from(b"get")),
+                arguments: Some(req),
+                ip,
+                version,
+            };
+            Ok(bencode::bencode_serialize_to_writer(msg, writer)?)
+        }
+        MessageKind::PutRequest(req) => {
+            let msg: RawMessage<BufT, _, ()> = RawMessage {
+                message_type: MessageType::Request,
+                transaction_id,
+                error: None,
+                response: None,
+                method_name: Some(BufT::from(b"put")),
+                arguments: Some(req),
+                ip,
+                version,
+            };
+            Ok(bencode::bencode_serialize_to_writer(msg, writer)?)
+        }
     }
 }
 
@@ -543,6 +633,26 @@ where
                         kind: MessageKind::AnnouncePeer(de.arguments.unwrap()),
                     })
                 }
+                b"get" => {
+                    let de: RawMessage<BufT, GetRequest> =
+                        bencode::from_bytes(buf).map_err(|e| e.into_anyhow())?;
+                    Ok(Message {
+                        transaction_id: de.transaction_id,
+                        version: de.version,
+                        ip: de.ip.map(|c| c.0),
+                        kind: MessageKind::GetRequest(de.arguments.unwrap()),
+                    })
+                }
+                b"put" => {
+                    let de: RawMessage<BufT, PutRequest<BufT>> =
+                        bencode::from_bytes(buf).map_err(|e| e.into_anyhow())?;
+                    Ok(Message {
+                        transaction_id: de.transaction_id,
+                        version: de.version,
+                        ip: de.ip.map(|c| c.0),
+                        kind: MessageKind::PutRequest(de.arguments.unwrap()),
+                    })
+                }
                 other => anyhow::bail!("unsupported method {:?}", ByteBuf(other)),
             },
             _ => anyhow::bail!(
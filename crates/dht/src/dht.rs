@@ -1,6 +1,6 @@
 use std::{
     cmp::Reverse,
-    net::{Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
     str::FromStr,
     sync::{
         Arc,
@@ -11,16 +11,20 @@ use std::{
 };
 
 use crate::{
-    Error, INACTIVITY_TIMEOUT, REQUERY_INTERVAL, RESPONSE_TIMEOUT,
+    Error, INACTIVITY_TIMEOUT, REQUERY_INTERVAL, RESPONSE_TIMEOUT, bep42,
+    bep44::{self, Item, ItemStore, MutableItem, PutError},
     bprotocol::{
         self, AnnouncePeer, CompactNodeInfo, CompactNodeInfoOwned, ErrorDescription,
-        FindNodeRequest, GetPeersRequest, Message, MessageKind, Node, PingRequest, Response, Want,
+        FindNodeRequest, GetPeersRequest, GetRequest, Message, MessageKind, Node, PingRequest,
+        Response, Want,
     },
+    ed25519::Keypair,
     peer_store::PeerStore,
     routing_table::{InsertResult, NodeStatus, RoutingTable},
 };
 use backon::{ExponentialBuilder, Retryable};
 use bencode::ByteBufOwned;
+use bytes::Bytes;
 use dashmap::DashMap;
 use futures::{
     FutureExt, Stream, StreamExt, TryFutureExt, future::BoxFuture, stream::FuturesUnordered,
@@ -32,10 +36,11 @@ use librqbit_core::{
     crate_version,
     hash_id::Id20,
     peer_id::generate_azereus_style,
+    socks5_udp::{RelayDst, Socks5UdpRelay},
     spawn_utils::{spawn, spawn_with_cancel},
 };
 use librqbit_dualstack_sockets::{BindDevice, UdpSocket};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
 use serde::Serialize;
 use tokio::sync::mpsc::{Sender, UnboundedReceiver, UnboundedSender, channel, unbounded_channel};
@@ -54,6 +59,10 @@ pub struct DhtStats {
     pub outstanding_requests: usize,
     pub routing_table_size: usize,
     pub routing_table_size_v6: usize,
+    /// Our address as reported by other nodes.
+    pub external_ip: Option<IpAddr>,
+    /// Whether our ID is valid for external_ip (BEP 42).
+    pub id_secure: Option<bool>,
 }
 
 struct OutstandingRequest {
@@ -577,10 +586,16 @@ pub struct DhtState {
     // Created requests: (transaction_id, addr) => Requests.
     // If we get a response, it gets removed from here.
     inflight_by_transaction_id: DashMap<(u16, SocketAddr), OutstandingRequest>,
+    // Pings sent to a hostname through the SOCKS5 relay: transaction_id => where to send the
+    // address the response came from.
+    inflight_by_hostname: DashMap<u16, tokio::sync::oneshot::Sender<SocketAddr>>,
 
     routing_table_v4: RwLock<RoutingTable>,
     routing_table_v6: RwLock<RoutingTable>,
     listen_addr: SocketAddr,
+    // Our address as reported by other nodes in their responses.
+    external_ip: RwLock<Option<IpAddr>>,
+    external_ip_votes: Mutex<bep42::ExternalIpVotes>,
 
     // Sending requests to the worker.
     rate_limiter: RateLimiter,
@@ -590,6 +605,8 @@ pub struct DhtState {
     cancellation_token: CancellationToken,
 
     pub(crate) peer_store: PeerStore,
+    // BEP 44 items put on us by other nodes.
+    items: ItemStore,
 }
 
 impl DhtState {
@@ -608,12 +625,16 @@ impl DhtState {
             id,
             next_transaction_id: AtomicU16::new(0),
             inflight_by_transaction_id: Default::default(),
+            inflight_by_hostname: Default::default(),
             routing_table_v4: RwLock::new(routing_table_v4),
             routing_table_v6: RwLock::new(routing_table_v6),
             worker_sender: sender,
             listen_addr,
+            external_ip: Default::default(),
+            external_ip_votes: Default::default(),
             rate_limiter: make_rate_limiter(),
             peer_store,
+            items: Default::default(),
             cancellation_token,
         }
     }
@@ -706,6 +727,23 @@ impl DhtState {
                 version: None,
                 ip: None,
             },
+            Request::Get { target, seq } => Message {
+                kind: MessageKind::GetRequest(GetRequest {
+                    id: self.id,
+                    target,
+                    seq,
+                    want,
+                }),
+                transaction_id: ByteBufOwned::from(transaction_id_buf.as_ref()),
+                version: None,
+                ip: None,
+            },
+            Request::Put { token, item, cas } => Message {
+                kind: MessageKind::PutRequest(item.to_put_request(self.id, token, cas)),
+                transaction_id: ByteBufOwned::from(transaction_id_buf.as_ref()),
+                version: None,
+                ip: None,
+            },
         };
         (transaction_id, message)
     }
@@ -786,14 +824,22 @@ impl DhtState {
                 {
                     Some(req) => req,
                     None => {
+                        if let Some((_, tx)) = self.inflight_by_hostname.remove(&tid) {
+                            let _ = tx.send(addr);
+                            return Ok(());
+                        }
                         trace!(?msg, "outstanding request not found");
                         return Err(Error::RequestNotFound);
                     }
                 };
 
+                if let Some(ip) = msg.ip {
+                    self.on_external_ip(addr, ip.ip().to_canonical());
+                }
+
                 let response_or_error = match msg.kind {
                     MessageKind::Error(e) => ResponseOrError::Error(e),
-                    MessageKind::Response(r) => ResponseOrError::Response(r),
+                    MessageKind::Response(r) => ResponseOrError::Response(Box::new(r)),
                     _ => unreachable!(),
                 };
                 match request.done.send(Ok(response_or_error)) {
@@ -841,16 +887,28 @@ impl DhtState {
                 self.get_table_for_addr(addr)
                     .write()
                     .mark_last_query(&ann.id, now());
-                let added = self.peer_store.store_peer(ann, addr);
-                trace!("{addr}: added_peer={added}, announce={ann:?}");
+                let kind = if self
+                    .peer_store
+                    .is_valid_token(ann.token.as_ref(), ann.id, addr)
+                {
+                    let added = self.peer_store.store_peer(ann, addr);
+                    trace!("{addr}: added_peer={added}, announce={ann:?}");
+                    MessageKind::Response(bprotocol::Response {
+                        id: self.id,
+                        ..Default::default()
+                    })
+                } else {
+                    trace!("{addr}: bad token in announce={ann:?}");
+                    MessageKind::Error(ErrorDescription {
+                        code: 203,
+                        description: ByteBufOwned::from(&b"bad token"[..]),
+                    })
+                };
                 let message = Message {
                     transaction_id: msg.transaction_id,
                     version: None,
                     ip: Some(addr),
-                    kind: MessageKind::Response(bprotocol::Response {
-                        id: self.id,
-                        ..Default::default()
-                    }),
+                    kind,
                 };
                 self.worker_sender
                     .send(WorkerSendRequest {
@@ -879,10 +937,11 @@ impl DhtState {
                         id: self.id,
                         nodes,
                         nodes6,
-                        values: Some(compact_peer_info),
+                        values: (!compact_peer_info.is_empty()).then_some(compact_peer_info),
                         token: Some(ByteBufOwned::from(
                             &self.peer_store.gen_token_for(req.id, addr)[..],
                         )),
+                        ..Default::default()
                     }),
                 };
                 self.worker_sender
@@ -924,17 +983,123 @@ impl DhtState {
                     .ok_or(Error::DhtDead)?;
                 Ok(())
             }
+            MessageKind::GetRequest(req) => {
+                let want = req
+                    .want
+                    .unwrap_or(if addr.is_ipv6() { Want::V6 } else { Want::V4 });
+                let (nodes, nodes6) = self.generate_compact_nodes_both(req.target, want);
+                self.get_table_for_addr(addr)
+                    .write()
+                    .mark_last_query(&req.id, now());
+                let mut response = bprotocol::Response {
+                    id: self.id,
+                    nodes,
+                    nodes6,
+                    token: Some(ByteBufOwned::from(
+                        &self.peer_store.gen_token_for(req.id, addr)[..],
+                    )),
+                    ..Default::default()
+                };
+                if let Some(item) = self.items.get(&req.target) {
+                    item.fill_response(&mut response, req.seq);
+                }
+                let message = Message {
+                    transaction_id: msg.transaction_id,
+                    version: None,
+                    ip: Some(addr),
+                    kind: MessageKind::Response(response),
+                };
+                self.worker_sender
+                    .send(WorkerSendRequest {
+                        our_tid: None,
+                        message,
+                        addr,
+                    })
+                    .ok()
+                    .ok_or(Error::DhtDead)?;
+                Ok(())
+            }
+            MessageKind::PutRequest(req) => {
+                self.get_table_for_addr(addr)
+                    .write()
+                    .mark_last_query(&req.id, now());
+                let result = if self
+                    .peer_store
+                    .is_valid_token(req.token.as_ref(), req.id, addr)
+                {
+                    Item::from_put_request(req)
+                        .and_then(|item| self.items.put(item.target(), item, req.cas))
+                } else {
+                    Err(PutError::BAD_TOKEN)
+                };
+                let kind = match result {
+                    Ok(()) => MessageKind::Response(bprotocol::Response {
+                        id: self.id,
+                        ..Default::default()
+                    }),
+                    Err(e) => {
+                        trace!("{addr}: rejected put: {e}");
+                        MessageKind::Error(ErrorDescription {
+                            code: e.code,
+                            description: ByteBufOwned::from(e.message.as_bytes()),
+                        })
+                    }
+                };
+                let message = Message {
+                    transaction_id: msg.transaction_id,
+                    version: None,
+                    ip: Some(addr),
+                    kind,
+                };
+                self.worker_sender
+                    .send(WorkerSendRequest {
+                        our_tid: None,
+                        message,
+                        addr,
+                    })
+                    .ok()
+                    .ok_or(Error::DhtDead)?;
+                Ok(())
+            }
             _ => unreachable!(),
         }
     }
 
     pub fn get_stats(&self) -> DhtStats {
+        let external_ip = *self.external_ip.read();
         DhtStats {
             id: self.id,
             outstanding_requests: self.inflight_by_transaction_id.len(),
             routing_table_size: self.routing_table_v4.read().len(),
             routing_table_size_v6: self.routing_table_v6.read().len(),
+            external_ip,
+            id_secure: external_ip.map(|ip| bep42::is_node_id_secure(&self.id, ip)),
+        }
+    }
+
+    fn on_external_ip(&self, reported_by: SocketAddr, ip: IpAddr) {
+        if bep42::is_exempt(ip) {
+            return;
+        }
+        let Some(ip) = self.external_ip_votes.lock().vote(reported_by.ip(), ip) else {
+            return;
+        };
+        let mut external_ip = self.external_ip.write();
+        // The node ID is shared between IPv4 and IPv6, so derive it from IPv4 when we have both.
+        if *external_ip == Some(ip) || (external_ip.is_some_and(|e| e.is_ipv4()) && ip.is_ipv6()) {
+            return;
+        }
+        if !bep42::is_node_id_secure(&self.id, ip) {
+            info!(
+                %ip,
+                "our DHT id is not valid for our external IP (BEP 42)"
+            );
         }
+        *external_ip = Some(ip);
+    }
+
+    pub fn external_ip(&self) -> Option<IpAddr> {
+        *self.external_ip.read()
     }
 }
 
@@ -948,10 +1113,19 @@ enum Request {
         port: u16,
     },
     Ping,
+    Get {
+        target: Id20,
+        seq: Option<i64>,
+    },
+    Put {
+        token: ByteBufOwned,
+        item: Item,
+        cas: Option<i64>,
+    },
 }
 
 enum ResponseOrError {
-    Response(Response<ByteBufOwned>),
+    Response(Box<Response<ByteBufOwned>>),
     Error(ErrorDescription<ByteBufOwned>),
 }
 
@@ -966,6 +1140,8 @@ impl core::fmt::Debug for ResponseOrError {
 
 struct DhtWorker {
     socket: UdpSocket,
+    // If set, all datagrams go through this SOCKS5 relay.
+    socks5: Option<Socks5UdpRelay>,
     dht: Arc<DhtState>,
 }
 
@@ -978,11 +1154,67 @@ impl DhtWorker {
         };
     }
 
+    // Through a SOCKS5 proxy, looking up the hostname here would bypass the proxy. Instead ping
+    // the hostname through the relay, which resolves it, and take the address the response comes
+    // from.
+    async fn resolve_through_relay(
+        &self,
+        relay: &Socks5UdpRelay,
+        hostname: &str,
+    ) -> crate::Result<SocketAddr> {
+        if let Ok(addr) = hostname.parse() {
+            return Ok(addr);
+        }
+        let (host, port) = hostname
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .ok_or_else(|| {
+                Error::lookup(
+                    hostname,
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "expected host:port"),
+                )
+            })?;
+
+        // The address only decides what to "want", which pings don't have.
+        let (tid, message) = self.dht.create_request(Request::Ping, self.dht.listen_addr);
+        let mut buf = Vec::new();
+        bprotocol::serialize_message(
+            &mut buf,
+            message.transaction_id,
+            message.version,
+            message.ip,
+            message.kind,
+        )?;
+        let mut wrapped = Vec::new();
+        Socks5UdpRelay::encapsulate(RelayDst::Host(host, port), &buf, &mut wrapped)
+            .map_err(|err| Error::lookup(hostname, err))?;
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.dht.inflight_by_hostname.insert(tid, tx);
+        let res = async {
+            self.socket
+                .send_to(&wrapped, relay.relay_addr())
+                .await
+                .map_err(Error::Send)?;
+            match tokio::time::timeout(RESPONSE_TIMEOUT, rx).await {
+                Ok(Ok(addr)) => Ok(addr),
+                Ok(Err(_)) => Err(Error::DhtDead),
+                Err(_) => Err(Error::ResponseTimeout(RESPONSE_TIMEOUT)),
+            }
+        }
+        .await;
+        self.dht.inflight_by_hostname.remove(&tid);
+        res
+    }
+
     async fn bootstrap_hostname(&self, hostname: &str) -> crate::Result<()> {
-        let addrs = tokio::net::lookup_host(hostname)
-            .await
-            .map_err(|err| Error::lookup(hostname, err))?
-            .collect::<Vec<_>>();
+        let addrs = match self.socks5.as_ref() {
+            Some(relay) => vec![self.resolve_through_relay(relay, hostname).await?],
+            None => tokio::net::lookup_host(hostname)
+                .await
+                .map_err(|err| Error::lookup(hostname, err))?
+                .collect::<Vec<_>>(),
+        };
         let v4 = RecursiveRequest::find_node_for_routing_table(
             self.dht.clone(),
             self.dht.id,
@@ -1091,6 +1323,14 @@ impl DhtWorker {
         }
     }
 
+    async fn peer_store_gc(&self) -> crate::Result<()> {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            self.dht.peer_store.garbage_collect_peers();
+        }
+    }
+
     async fn pinger(&self, is_v4: bool) -> crate::Result<()> {
         let table = if is_v4 {
             &self.dht.routing_table_v4
@@ -1153,6 +1393,7 @@ impl DhtWorker {
     ) -> crate::Result<()> {
         let writer = async {
             let mut buf = Vec::new();
+            let mut wrapped = Vec::new();
             while let Some(WorkerSendRequest {
                 our_tid,
                 message,
@@ -1171,7 +1412,14 @@ impl DhtWorker {
                     message.kind,
                 )
                 .unwrap();
-                if let Err(e) = socket.send_to(&buf, addr).await {
+                let res = match self.socks5.as_ref() {
+                    Some(relay) => match Socks5UdpRelay::encapsulate(addr, &buf, &mut wrapped) {
+                        Ok(()) => socket.send_to(&wrapped, relay.relay_addr()).await,
+                        Err(e) => Err(e),
+                    },
+                    None => socket.send_to(&buf, addr).await,
+                };
+                if let Err(e) = res {
                     debug!("error sending to {addr}: {e:#}");
                     if let Some(tid) = our_tid {
                         self.on_send_error(tid, addr, Error::Send(e));
@@ -1184,7 +1432,21 @@ impl DhtWorker {
             let mut buf = vec![0u8; 16384];
             loop {
                 let (size, addr) = socket.recv_from(&mut buf).await.map_err(Error::Recv)?;
-                match bprotocol::deserialize_message::<ByteBufOwned>(&buf[..size]) {
+                let (data, addr) = match self.socks5.as_ref() {
+                    Some(relay) if !relay.is_relay(addr) => {
+                        debug!(?addr, "ignoring datagram from outside the SOCKS5 relay");
+                        continue;
+                    }
+                    Some(_) => match Socks5UdpRelay::decapsulate(&buf[..size]) {
+                        Ok((addr, data)) => (data, addr),
+                        Err(e) => {
+                            debug!("error unwrapping datagram from the SOCKS5 relay: {e:#}");
+                            continue;
+                        }
+                    },
+                    None => (&buf[..size], addr),
+                };
+                match bprotocol::deserialize_message::<ByteBufOwned>(data) {
                     Ok(msg) => match output_tx.send((msg, addr)).await {
                         Ok(_) => {}
                         Err(_) => return Err(Error::DhtDead),
@@ -1244,6 +1506,11 @@ impl DhtWorker {
         tokio::pin!(pinger_v6);
         tokio::pin!(bucket_refresher_v6);
 
+        let peer_store_gc = self
+            .peer_store_gc()
+            .instrument(debug_span!("peer_store_gc"));
+        tokio::pin!(peer_store_gc);
+
         loop {
             tokio::select! {
                 err = &mut framer => {
@@ -1265,6 +1532,9 @@ impl DhtWorker {
                 err = &mut bucket_refresher_v6 => {
                     return Error::task_finished(&"bucket_refresher_v6", err);
                 },
+                err = &mut peer_store_gc => {
+                    return Error::task_finished(&"peer_store_gc", err);
+                },
                 err = &mut response_reader => {
                     return Error::task_finished(&"response_reader", err);
                 }
@@ -1283,6 +1553,8 @@ pub struct DhtConfig<'a> {
     pub peer_store: Option<PeerStore>,
     pub cancellation_token: Option<CancellationToken>,
     pub bind_device: Option<&'a BindDevice>,
+    // Send all traffic through a SOCKS5 proxy's UDP relay.
+    pub socks5_udp: Option<Socks5UdpRelay>,
 }
 
 impl DhtState {
@@ -1321,6 +1593,7 @@ impl DhtState {
                 .unwrap_or_else(|| crate::DHT_BOOTSTRAP.iter().map(|v| v.to_string()).collect());
 
             let token = config.cancellation_token.take().unwrap_or_default();
+            let socks5 = config.socks5_udp.take();
 
             let (in_tx, in_rx) = unbounded_channel();
             let state = Arc::new(Self::new_internal(
@@ -1340,7 +1613,11 @@ impl DhtState {
                 {
                     let state = state.clone();
                     async move {
-                        let worker = DhtWorker { socket, dht: state };
+                        let worker = DhtWorker {
+                            socket,
+                            socks5,
+                            dht: state,
+                        };
                         worker.start(in_rx, &bootstrap_addrs).await
                     }
                 },
@@ -1370,11 +1647,207 @@ impl DhtState {
         f(&self.routing_table_v4.read(), &self.routing_table_v6.read())
     }
 
+    /// Store an immutable item (BEP 44). `value` must be bencoded. Returns the target it's stored under.
+    pub async fn put_immutable(self: &Arc<Self>, value: Bytes) -> crate::Result<Id20> {
+        let item = Item::Immutable(value);
+        let target = item.target();
+        self.put_item(item, None).await?;
+        Ok(target)
+    }
+
+    /// Look up an immutable item by its target, i.e. the SHA-1 of its bencoded value.
+    pub async fn get_immutable(self: &Arc<Self>, target: Id20) -> crate::Result<Option<Bytes>> {
+        let lookup = self.lookup_items(target).await?;
+        Ok(lookup.items.into_iter().find_map(|item| match item {
+            Item::Immutable(v) => Some(v),
+            Item::Mutable(_) => None,
+        }))
+    }
+
+    /// Sign and store a mutable item (BEP 44). `value` must be bencoded.
+    /// If `cas` is set, nodes only store it if their current seq matches.
+    pub async fn put_mutable(
+        self: &Arc<Self>,
+        keypair: &Keypair,
+        salt: Bytes,
+        seq: i64,
+        value: Bytes,
+        cas: Option<i64>,
+    ) -> crate::Result<Id20> {
+        let item = MutableItem::new(keypair, salt, seq, value)?;
+        let target = item.target();
+        self.put_item(Item::Mutable(item), cas).await?;
+        Ok(target)
+    }
+
+    /// Store a mutable item that was already signed, see [`MutableItem::from_signed`].
+    pub async fn put_signed_mutable(
+        self: &Arc<Self>,
+        item: MutableItem,
+        cas: Option<i64>,
+    ) -> crate::Result<Id20> {
+        let target = item.target();
+        self.put_item(Item::Mutable(item), cas).await?;
+        Ok(target)
+    }
+
+    /// Look up the mutable item with the highest seq for the public key and salt.
+    pub async fn get_mutable(
+        self: &Arc<Self>,
+        public_key: [u8; crate::ed25519::PUBLIC_KEY_LEN],
+        salt: Bytes,
+    ) -> crate::Result<Option<MutableItem>> {
+        let target = bep44::mutable_target(&public_key, &salt);
+        let lookup = self.lookup_items(target).await?;
+        Ok(lookup
+            .items
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Mutable(m) => Some(MutableItem {
+                    salt: salt.clone(),
+                    ..m
+                }),
+                Item::Immutable(_) => None,
+            })
+            .max_by_key(|m| m.seq))
+    }
+
+    async fn put_item(self: &Arc<Self>, item: Item, cas: Option<i64>) -> crate::Result<usize> {
+        let target = item.target();
+        let lookup = self.lookup_items(target).await?;
+        let mut puts = lookup
+            .nodes
+            .into_iter()
+            .take(ITEM_LOOKUP_K)
+            .map(|(_, addr, token)| {
+                let request = Request::Put {
+                    token,
+                    item: item.clone(),
+                    cas,
+                };
+                self.request(request, addr).map(move |r| (addr, r))
+            })
+            .collect::<FuturesUnordered<_>>();
+        let mut stored = 0;
+        while let Some((addr, result)) = puts.next().await {
+            match result {
+                Ok(ResponseOrError::Response(_)) => stored += 1,
+                Ok(ResponseOrError::Error(e)) => debug!(?target, %addr, "put rejected: {e:?}"),
+                Err(e) => debug!(?target, %addr, "put failed: {e:#}"),
+            }
+        }
+        debug!(?target, stored, "put item");
+        if stored == 0 {
+            return Err(Error::PutFailed);
+        }
+        Ok(stored)
+    }
+
+    // Iterative "get" lookup towards the target. Returns the closest nodes that responded,
+    // with their write tokens, and all valid items they returned.
+    async fn lookup_items(self: &Arc<Self>, target: Id20) -> crate::Result<ItemLookup> {
+        let now = now();
+        let mut candidates: Vec<(Id20, SocketAddr)> = self.with_routing_tables(|v4, v6| {
+            v4.sorted_by_distance_from(target, now)
+                .into_iter()
+                .take(ITEM_LOOKUP_K)
+                .chain(
+                    v6.sorted_by_distance_from(target, now)
+                        .into_iter()
+                        .take(ITEM_LOOKUP_K),
+                )
+                .map(|n| (n.id(), n.addr()))
+                .collect()
+        });
+        let mut queried = std::collections::HashSet::new();
+        let mut lookup = ItemLookup::default();
+        let mut inflight = FuturesUnordered::new();
+
+        loop {
+            candidates.sort_by_key(|(id, _)| id.distance(&target));
+            while inflight.len() < ITEM_LOOKUP_ALPHA && queried.len() < ITEM_LOOKUP_MAX_QUERIES {
+                let Some((id, addr)) = candidates
+                    .iter()
+                    .take(ITEM_LOOKUP_K)
+                    .find(|(_, addr)| !queried.contains(addr))
+                    .copied()
+                else {
+                    break;
+                };
+                queried.insert(addr);
+                inflight.push(
+                    self.request(Request::Get { target, seq: None }, addr)
+                        .map(move |r| (id, addr, r)),
+                );
+            }
+
+            let Some((id, addr, result)) = inflight.next().await else {
+                break;
+            };
+            let response = match result {
+                Ok(ResponseOrError::Response(r)) => r,
+                Ok(ResponseOrError::Error(e)) => {
+                    debug!(%addr, "error response to get: {e:?}");
+                    candidates.retain(|(_, a)| *a != addr);
+                    continue;
+                }
+                Err(e) => {
+                    trace!(%addr, "get failed: {e:#}");
+                    candidates.retain(|(_, a)| *a != addr);
+                    continue;
+                }
+            };
+
+            if let Some(item) = Item::from_response(&target, &response) {
+                lookup.items.push(item);
+            }
+            if let Some(token) = response.token {
+                lookup.nodes.push((id, addr, token));
+            }
+            let nodes = response
+                .nodes
+                .iter()
+                .flat_map(|n| n.iter().map(|n| n.as_socketaddr()))
+                .chain(
+                    response
+                        .nodes6
+                        .iter()
+                        .flat_map(|n| n.iter().map(|n| n.as_socketaddr())),
+                )
+                .filter(|node| addr.is_ipv4() == node.addr.is_ipv4());
+            for node in nodes {
+                if !candidates.iter().any(|(_, a)| *a == node.addr) {
+                    candidates.push((node.id, node.addr));
+                }
+            }
+        }
+
+        lookup.nodes.sort_by_key(|(id, _, _)| id.distance(&target));
+        if lookup.nodes.is_empty() && lookup.items.is_empty() {
+            return Err(Error::NoSuccessfulLookups {
+                errors: queried.len(),
+            });
+        }
+        Ok(lookup)
+    }
+
     // pub fn clone_routing_table(&self) -> RoutingTable {
     //     self.routing_table.read().clone()
     // }
 }
 
+// BEP 44 lookups: how many closest nodes to store on, how many requests in flight, and
+// how many nodes to query at most.
+const ITEM_LOOKUP_K: usize = 8;
+const ITEM_LOOKUP_ALPHA: usize = 4;
+const ITEM_LOOKUP_MAX_QUERIES: usize = 64;
+
+#[derive(Default)]
+struct ItemLookup {
+    nodes: Vec<(Id20, SocketAddr, ByteBufOwned)>,
+    items: Vec<Item>,
+}
+
 trait FromSocketAddr: Sized {
     fn from_socket_addr(addr: SocketAddr) -> Option<Self>;
 }
@@ -0,0 +1,104 @@
+// Ed25519 signatures (RFC 8032) for BEP 44 mutable items, a thin wrapper around ed25519-dalek.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+pub const PUBLIC_KEY_LEN: usize = ed25519_dalek::PUBLIC_KEY_LENGTH;
+pub const SIGNATURE_LEN: usize = ed25519_dalek::SIGNATURE_LENGTH;
+
+pub struct Keypair {
+    signing: SigningKey,
+    public: [u8; PUBLIC_KEY_LEN],
+}
+
+impl Keypair {
+    pub fn generate() -> Self {
+        Self::from_secret(rand::random())
+    }
+
+    /// Create the keypair from a 32 byte secret key (seed).
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        let signing = SigningKey::from_bytes(&secret);
+        let public = signing.verifying_key().to_bytes();
+        Self { signing, public }
+    }
+
+    pub fn secret(&self) -> &[u8; 32] {
+        self.signing.as_bytes()
+    }
+
+    pub fn public_key(&self) -> &[u8; PUBLIC_KEY_LEN] {
+        &self.public
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> [u8; SIGNATURE_LEN] {
+        self.signing.sign(msg).to_bytes()
+    }
+}
+
+pub fn verify(public: &[u8; PUBLIC_KEY_LEN], msg: &[u8], sig: &[u8; SIGNATURE_LEN]) -> bool {
+    let Ok(public) = VerifyingKey::from_bytes(public) else {
+        return false;
+    };
+    public.verify(msg, &Signature::from_bytes(sig)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Keypair, verify};
+
+    fn hex32(s: &str) -> [u8; 32] {
+        hex(s).try_into().unwrap()
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_rfc8032_vectors() {
+        for (secret, public, msg, sig) in [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                "",
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            ),
+            (
+                "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+                "72",
+                "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+            ),
+            (
+                "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+                "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+                "af82",
+                "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+            ),
+        ] {
+            let keypair = Keypair::from_secret(hex32(secret));
+            assert_eq!(keypair.public_key(), &hex32(public));
+            let msg = hex(msg);
+            let sig: [u8; 64] = hex(sig).try_into().unwrap();
+            assert_eq!(keypair.sign(&msg), sig);
+            assert!(verify(keypair.public_key(), &msg, &sig));
+
+            let mut bad = sig;
+            bad[0] ^= 1;
+            assert!(!verify(keypair.public_key(), &msg, &bad));
+            assert!(!verify(keypair.public_key(), b"other", &sig));
+        }
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let keypair = Keypair::generate();
+        let sig = keypair.sign(b"hello");
+        assert!(verify(keypair.public_key(), b"hello", &sig));
+        let other = Keypair::generate();
+        assert!(!verify(other.public_key(), b"hello", &sig));
+    }
+}
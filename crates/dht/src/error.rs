@@ -55,6 +55,12 @@ pub enum Error {
 
     #[error("bencode serialize error: {0:#}")]
     Serialize(#[source] Box<SerializeError>),
+
+    #[error("{0}")]
+    Bep44(crate::bep44::PutError),
+
+    #[error("no node stored the item")]
+    PutFailed,
 }
 
 impl From<SerializeError> for Error {
@@ -1,5 +1,8 @@
+mod bep42;
+mod bep44;
 mod bprotocol;
 mod dht;
+mod ed25519;
 mod error;
 mod peer_store;
 mod persistence;
@@ -9,6 +12,11 @@ mod utils;
 use std::sync::Arc;
 use std::time::Duration;
 
+pub use bep44::{
+    MAX_SALT_LEN, MAX_VALUE_LEN, MutableItem, PutError, immutable_target, mutable_target,
+    signature_buf,
+};
+pub use ed25519::{Keypair, PUBLIC_KEY_LEN, SIGNATURE_LEN};
 pub use error::{Error, Result};
 
 pub use crate::dht::DhtStats;
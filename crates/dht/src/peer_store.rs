@@ -1,4 +1,6 @@
-use std::{collections::VecDeque, net::SocketAddr, str::FromStr, sync::atomic::AtomicU32};
+use std::{
+    collections::VecDeque, net::SocketAddr, str::FromStr, sync::atomic::AtomicU32, time::Duration,
+};
 
 use bencode::ByteBufOwned;
 use chrono::{DateTime, Utc};
@@ -13,12 +15,26 @@ use tracing::trace;
 
 use crate::bprotocol::{AnnouncePeer, Want};
 
+// BEP 5 recommends accepting tokens for up to 10 minutes.
+const TOKEN_TTL: Duration = Duration::from_secs(10 * 60);
+// Peers are expected to re-announce every 15-30 minutes.
+const PEER_TTL: Duration = Duration::from_secs(30 * 60);
+// Keep get_peers responses within a single UDP packet.
+const MAX_PEERS_IN_RESPONSE: usize = 50;
+
 #[derive(Serialize, Deserialize)]
 struct StoredToken {
     token: [u8; 4],
     #[serde(serialize_with = "crate::utils::serialize_id20")]
     node_id: Id20,
     addr: SocketAddr,
+    // default for backwards compat
+    #[serde(default = "Utc::now")]
+    time: DateTime<Utc>,
+}
+
+fn is_expired(time: DateTime<Utc>, now: DateTime<Utc>, ttl: Duration) -> bool {
+    (now - time).to_std().is_ok_and(|age| age > ttl)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -118,17 +134,34 @@ impl PeerStore {
         let mut token = [0u8; 4];
         rand::rng().fill_bytes(&mut token);
         let mut tokens = self.tokens.write();
+        let now = Utc::now();
+        while tokens
+            .front()
+            .is_some_and(|t| is_expired(t.time, now, TOKEN_TTL))
+            || tokens.len() >= self.max_remembered_tokens as usize
+        {
+            tokens.pop_front();
+        }
         tokens.push_back(StoredToken {
             token,
             addr,
             node_id,
+            time: now,
         });
-        if tokens.len() > self.max_remembered_tokens as usize {
-            tokens.pop_front();
-        }
         token
     }
 
+    /// Whether we gave this token to this node in the last TOKEN_TTL.
+    pub fn is_valid_token(&self, token: &[u8], node_id: Id20, addr: SocketAddr) -> bool {
+        let now = Utc::now();
+        self.tokens.read().iter().any(|t| {
+            t.token[..] == token[..]
+                && t.addr == addr
+                && t.node_id == node_id
+                && !is_expired(t.time, now, TOKEN_TTL)
+        })
+    }
+
     pub fn store_peer(&self, announce: &AnnouncePeer<ByteBufOwned>, mut addr: SocketAddr) -> bool {
         // If the info_hash in announce is too far away from us, don't store it.
         // If the token doesn't match, don't store it.
@@ -138,9 +171,7 @@ impl PeerStore {
             trace!("peer store: info_hash too far to store");
             return false;
         }
-        if !self.tokens.read().iter().any(|t| {
-            t.token[..] == announce.token.as_ref()[..] && t.addr == addr && t.node_id == announce.id
-        }) {
+        if !self.is_valid_token(announce.token.as_ref(), announce.id, addr) {
             trace!("peer store: can't find this token / addr combination");
             return false;
         }
@@ -154,15 +185,19 @@ impl PeerStore {
         let peers_len = self.peers_len.load(std::sync::atomic::Ordering::SeqCst);
         match peers_entry {
             Entry::Occupied(mut occ) => {
-                if let Some(s) = occ.get_mut().iter_mut().find(|s| s.addr == addr) {
+                let peers = occ.get_mut();
+                if let Some(pos) = peers.iter().position(|s| s.addr == addr) {
+                    // Keep the list ordered by announce time.
+                    let mut s = peers.remove(pos);
                     s.time = Utc::now();
+                    peers.push(s);
                     return true;
                 }
                 if peers_len >= self.max_remembered_peers {
                     trace!("peer store: out of capacity");
                     return false;
                 }
-                occ.get_mut().push(StoredPeer {
+                peers.push(StoredPeer {
                     addr,
                     time: Utc::now(),
                 });
@@ -185,9 +220,13 @@ impl PeerStore {
     }
 
     pub fn get_for_info_hash(&self, info_hash: Id20, want: Want) -> Vec<CompactSocketAddr> {
+        let now = Utc::now();
         if let Some(stored_peers) = self.peers.get(&info_hash) {
+            // The most recently announced ones are the most likely to be alive.
             return stored_peers
                 .iter()
+                .rev()
+                .filter(|p| !is_expired(p.time, now, PEER_TTL))
                 .filter(|p| {
                     matches!(
                         (p.addr, want),
@@ -195,14 +234,78 @@ impl PeerStore {
                             | (SocketAddr::V4(..), Want::V4 | Want::Both)
                     )
                 })
+                .take(MAX_PEERS_IN_RESPONSE)
                 .map(|p| p.addr.into())
                 .collect();
         }
         Vec::new()
     }
 
-    #[allow(dead_code)]
+    /// Forget peers that haven't re-announced in a while.
     pub fn garbage_collect_peers(&self) {
-        todo!()
+        let now = Utc::now();
+        let mut removed = 0;
+        self.peers.retain(|_, peers| {
+            let len = peers.len();
+            peers.retain(|p| !is_expired(p.time, now, PEER_TTL));
+            removed += len - peers.len();
+            !peers.is_empty()
+        });
+        if removed > 0 {
+            trace!(removed, "peer store: removed expired peers");
+            self.peers_len.fetch_sub(
+                u32::try_from(removed).unwrap_or(u32::MAX),
+                std::sync::atomic::Ordering::SeqCst,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use bencode::ByteBufOwned;
+    use chrono::Utc;
+    use librqbit_core::hash_id::Id20;
+
+    use super::{PEER_TTL, PeerStore, TOKEN_TTL};
+    use crate::bprotocol::{AnnouncePeer, Want};
+
+    #[test]
+    fn test_store_and_expire() {
+        let self_id = Id20::new([0; 20]);
+        let store = PeerStore::new(self_id);
+        let node_id = Id20::new([1; 20]);
+        let addr: SocketAddr = "1.2.3.4:1000".parse().unwrap();
+        let info_hash = Id20::new([0; 20]);
+
+        let announce = |token: &[u8]| AnnouncePeer {
+            id: node_id,
+            implied_port: 0,
+            info_hash,
+            port: 2000,
+            token: ByteBufOwned::from(token.to_vec()),
+        };
+
+        assert!(!store.store_peer(&announce(b"nope"), addr));
+        let token = store.gen_token_for(node_id, addr);
+        assert!(!store.is_valid_token(&token, node_id, "1.2.3.5:1000".parse().unwrap()));
+        assert!(store.store_peer(&announce(&token), addr));
+        assert_eq!(
+            store.get_for_info_hash(info_hash, Want::V4),
+            vec!["1.2.3.4:2000".parse::<SocketAddr>().unwrap().into()]
+        );
+        assert!(store.get_for_info_hash(info_hash, Want::V6).is_empty());
+
+        // Tokens and peers expire.
+        let long_ago = Utc::now() - TOKEN_TTL.max(PEER_TTL) - PEER_TTL;
+        store.tokens.write()[0].time = long_ago;
+        assert!(!store.is_valid_token(&token, node_id, addr));
+        store.peers.get_mut(&info_hash).unwrap()[0].time = long_ago;
+        assert!(store.get_for_info_hash(info_hash, Want::V4).is_empty());
+        store.garbage_collect_peers();
+        assert!(store.peers.is_empty());
+        assert_eq!(store.peers_len.load(std::sync::atomic::Ordering::SeqCst), 0);
     }
 }
@@ -4,18 +4,20 @@ use anyhow::Context;
 use futures::FutureExt;
 use futures::future::BoxFuture;
 use librqbit_core::directories::get_configuration_directory;
+use librqbit_core::socks5_udp::Socks5UdpRelay;
 use librqbit_core::spawn_utils::spawn_with_cancel;
 use librqbit_dualstack_sockets::BindDevice;
 use serde::{Deserialize, Serialize};
 use std::fs::OpenOptions;
 use std::io::{BufReader, BufWriter};
-use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio_util::sync::CancellationToken;
 
 use tracing::{debug_span, error, info, trace, warn};
 
+use crate::bep42;
 use crate::peer_store::PeerStore;
 use crate::routing_table::RoutingTable;
 use crate::{Dht, DhtConfig, DhtState};
@@ -26,6 +28,8 @@ pub struct PersistentDhtConfig {
     pub config_filename: Option<PathBuf>,
     pub port: Option<u16>,
     pub ipv4_only: bool,
+    // Send all traffic through a SOCKS5 proxy's UDP relay.
+    pub socks5_udp: Option<Socks5UdpRelay>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -35,6 +39,9 @@ struct DhtSerialize<Table, PeerStore> {
     // option for backwards compat
     table_v6: Option<Table>,
     peer_store: Option<PeerStore>,
+    // Used to pick a BEP 42 compliant id on the next start.
+    #[serde(default)]
+    external_ip: Option<IpAddr>,
 }
 
 pub struct PersistentDht {
@@ -59,6 +66,7 @@ fn dump_dht(dht: &Dht, filename: &Path, tempfile_name: &Path) -> anyhow::Result<
                 table: v4,
                 table_v6: Some(v6),
                 peer_store: Some(&dht.peer_store),
+                external_ip: dht.external_ip(),
             },
         )
     }) {
@@ -146,9 +154,19 @@ impl PersistentDht {
                     }
                 },
             };
-            let (mut listen_addr, routing_table, peer_store) = de
-                .map(|de| (Some(de.addr), Some(de.table), de.peer_store))
-                .unwrap_or((None, None, None));
+            let (mut listen_addr, mut routing_table, mut peer_store, external_ip) = de
+                .map(|de| (Some(de.addr), Some(de.table), de.peer_store, de.external_ip))
+                .unwrap_or((None, None, None, None));
+
+            if let (Some(table), Some(ip)) = (routing_table.as_mut(), external_ip)
+                && !bep42::is_node_id_secure(&table.id(), ip)
+            {
+                let id = bep42::generate_node_id(ip);
+                info!(?id, %ip, "switching to a BEP 42 compliant DHT id");
+                *table = table.with_id(id);
+                // It only stores announces close to the old id.
+                peer_store = None;
+            }
 
             if let Some(port) = config.port {
                 if let Some(ref mut addr) = listen_addr {
@@ -166,6 +184,7 @@ impl PersistentDht {
                 peer_store,
                 cancellation_token,
                 bind_device,
+                socks5_udp: config.socks5_udp.take(),
                 ..Default::default()
             };
             let dht = DhtState::with_config(dht_config).await?;
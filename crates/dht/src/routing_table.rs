@@ -292,6 +292,25 @@ impl BucketTree {
                 return InsertResult::ReplacedBad(new_node);
             }
 
+            // BEP 42: prefer nodes whose ID matches their IP over ones that haven't proven
+            // themselves yet, if there's no room for both.
+            let can_grow = self.size < self.max_size
+                && (nodes.nodes.len() < 8
+                    || (*self_id >= leaf.start && *self_id <= leaf.end_inclusive));
+            if !can_grow
+                && new_node.is_secure()
+                && let Some(insecure_node) = nodes
+                    .nodes
+                    .iter_mut()
+                    .find(|r| !r.is_secure() && !matches!(r.status(now), NodeStatus::Good))
+            {
+                std::mem::swap(insecure_node, &mut new_node);
+                nodes.nodes.sort_by_key(|n| n.id);
+                debug!("replaced node with insecure id {:?}", new_node);
+                nodes.last_refreshed = now;
+                return InsertResult::ReplacedBad(new_node);
+            }
+
             // if max size reached, don't bother
             if self.size == self.max_size {
                 trace!(
@@ -390,6 +409,7 @@ impl Serialize for RoutingTableNode {
         s.serialize_field("id", &self.id.as_string())?;
         s.serialize_field("addr", &self.addr)?;
         s.serialize_field("status", &self.status(Instant::now()))?;
+        s.serialize_field("secure", &self.is_secure())?;
         if let Some(l) = self.last_request {
             s.serialize_field("last_request_ago", &l.elapsed())?;
         }
@@ -419,6 +439,10 @@ impl RoutingTableNode {
     pub fn addr(&self) -> SocketAddr {
         self.addr
     }
+    /// Whether the node's ID is valid for its IP (BEP 42).
+    pub fn is_secure(&self) -> bool {
+        crate::bep42::is_node_id_secure(&self.id, self.addr.ip())
+    }
     pub fn status(&self, now: Instant) -> NodeStatus {
         match (self.last_request, self.last_response, self.last_query) {
             // Nodes become bad when they fail to respond to multiple queries in a row.
@@ -489,6 +513,14 @@ impl RoutingTable {
     pub fn id(&self) -> Id20 {
         self.id
     }
+    /// A new table with a different own id, containing as many of the same nodes as fit.
+    pub fn with_id(&self, id: Id20) -> Self {
+        let mut table = Self::new(id, Some(self.buckets.max_size));
+        for node in self.iter() {
+            table.add_node(node.id, node.addr);
+        }
+        table
+    }
     pub fn len(&self) -> usize {
         self.size
     }
@@ -520,13 +552,13 @@ impl RoutingTable {
 
     pub fn add_node(&mut self, id: Id20, addr: SocketAddr) -> InsertResult {
         let res = self.buckets.add_node(&self.id, id, addr);
-        let replaced = match &res {
+        let added = match &res {
             InsertResult::WasExisting => false,
-            InsertResult::ReplacedBad(..) => true,
+            InsertResult::ReplacedBad(..) => false,
             InsertResult::Added => true,
             InsertResult::Ignored => false,
         };
-        if replaced {
+        if added {
             self.size += 1;
         }
         res
@@ -572,7 +604,7 @@ impl RoutingTable {
 mod tests {
     use std::{
         io::Cursor,
-        net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+        net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
         str::FromStr,
         time::Instant,
     };
@@ -582,7 +614,7 @@ mod tests {
 
     use crate::routing_table::compute_split_start_end;
 
-    use super::{RoutingTable, generate_random_id};
+    use super::{InsertResult, RoutingTable, generate_random_id};
 
     #[test]
     fn compute_split_start_end_root() {
@@ -694,6 +726,44 @@ mod tests {
         let _: RoutingTable = serde_json::from_reader(Cursor::new(v)).unwrap();
     }
 
+    #[test]
+    fn test_prefers_secure_nodes() {
+        let mut rtable = RoutingTable::new(random_id_20(), Some(8));
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+        let insecure = (0..8)
+            .map(|_| {
+                loop {
+                    let id = random_id_20();
+                    if !crate::bep42::is_node_id_secure(&id, ip) {
+                        break id;
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        for (port, id) in (1..).zip(&insecure) {
+            assert!(matches!(
+                rtable.add_node(*id, (ip, port).into()),
+                InsertResult::Added
+            ));
+        }
+        assert!(matches!(
+            rtable.add_node(random_id_20(), generate_socket_addr()),
+            InsertResult::Ignored
+        ));
+
+        let secure = crate::bep42::generate_node_id(ip);
+        assert!(matches!(
+            rtable.add_node(secure, (ip, 100).into()),
+            InsertResult::ReplacedBad(_)
+        ));
+        assert_eq!(rtable.len(), 8);
+        assert!(rtable.iter().any(|n| n.id() == secure && n.is_secure()));
+
+        let moved = rtable.with_id(random_id_20());
+        assert_eq!(moved.len(), 8);
+        assert!(moved.iter().any(|n| n.id() == secure));
+    }
+
     #[test]
     fn test_generate_random_id() {
         let start = Id20::from_str("3000000000000000000000000000000000000000").unwrap();
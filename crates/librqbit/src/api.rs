@@ -1,8 +1,12 @@
-use std::{collections::HashSet, marker::PhantomData, net::SocketAddr, str::FromStr, sync::Arc};
+use std::{
+    collections::HashSet, marker::PhantomData, net::SocketAddr, path::PathBuf, str::FromStr,
+    sync::Arc,
+};
 
 use anyhow::Context;
 use buffers::ByteBufOwned;
-use dht::{DhtStats, Id20};
+use bytes::Bytes;
+use dht::{DhtStats, Id20, MutableItem, PUBLIC_KEY_LEN, SIGNATURE_LEN};
 use http::StatusCode;
 use librqbit_core::torrent_metainfo::{FileDetailsAttrs, ValidatedTorrentMetaV1Info};
 use serde::{Deserialize, Serialize};
@@ -14,7 +18,7 @@ use crate::{
     session::{
         AddTorrent, AddTorrentOptions, AddTorrentResponse, ListOnlyResponse, Session, TorrentId,
     },
-    session_stats::snapshot::SessionStatsSnapshot,
+    session_stats::{ConnectivityReport, snapshot::SessionStatsSnapshot},
     torrent_state::{
         FileStream, ManagedTorrentHandle,
         peer::stats::snapshot::{PeerStatsFilter, PeerStatsSnapshot},
@@ -29,7 +33,7 @@ use futures::Stream;
 #[cfg(feature = "tracing-subscriber-utils")]
 use tokio_stream::wrappers::{BroadcastStream, errors::BroadcastStreamRecvError};
 
-pub use crate::torrent_state::stats::{LiveStats, TorrentStats};
+pub use crate::torrent_state::stats::{LiveStats, TorrentStats, TrackerSwarmStats};
 
 pub type Result<T> = std::result::Result<T, ApiError>;
 
@@ -217,17 +221,15 @@ impl Api {
                         id: Some(id),
                         info_hash: mgr.shared().info_hash.as_string(),
                         name: mgr.name(),
-                        output_folder: mgr
-                            .shared()
-                            .options
-                            .output_folder
-                            .to_string_lossy()
-                            .into_owned(),
+                        output_folder: mgr.shared().output_folder().to_string_lossy().into_owned(),
                         total_pieces,
 
                         // These will be filled in /details and /stats endpoints
                         files: None,
                         stats: None,
+                        comment: None,
+                        created_by: None,
+                        creation_date: None,
                     };
                     if opts.with_stats {
                         r.stats = Some(mgr.stats());
@@ -245,25 +247,38 @@ impl Api {
         let only_files = handle.only_files();
         let output_folder = handle
             .shared()
-            .options
-            .output_folder
+            .output_folder()
             .to_string_lossy()
             .into_owned()
             .to_string();
-        make_torrent_details(
+        let metadata = handle.metadata.load();
+        let mut details = make_torrent_details(
             Some(handle.id()),
             &info_hash,
-            handle.metadata.load().as_ref().map(|r| &r.info),
+            metadata.as_ref().map(|r| &r.info),
             handle.name().as_deref(),
             only_files.as_deref(),
             output_folder,
-        )
+        )?;
+        if let Some(m) = metadata.as_ref() {
+            details.comment = m.comment().map(|s| s.to_owned());
+            details.created_by = m.created_by().map(|s| s.to_owned());
+            details.creation_date = m
+                .creation_date()
+                .and_then(|d| d.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs());
+        }
+        Ok(details)
     }
 
     pub fn api_session_stats(&self) -> SessionStatsSnapshot {
         self.session().stats_snapshot()
     }
 
+    pub async fn api_connectivity(&self) -> ConnectivityReport {
+        self.session().check_incoming_connectivity().await
+    }
+
     pub fn torrent_file_mime_type(
         &self,
         idx: TorrentIdOrHash,
@@ -347,6 +362,73 @@ impl Api {
         Ok(Default::default())
     }
 
+    pub async fn api_torrent_action_set_sequential(
+        &self,
+        idx: TorrentIdOrHash,
+        sequential: bool,
+    ) -> Result<EmptyJsonResponse> {
+        let handle = self.mgr_handle(idx)?;
+        self.session.set_sequential(&handle, sequential).await;
+        Ok(Default::default())
+    }
+
+    pub async fn api_torrent_action_set_super_seeding(
+        &self,
+        idx: TorrentIdOrHash,
+        super_seeding: bool,
+    ) -> Result<EmptyJsonResponse> {
+        let handle = self.mgr_handle(idx)?;
+        self.session.set_super_seeding(&handle, super_seeding).await;
+        Ok(Default::default())
+    }
+
+    pub fn api_torrent_action_recheck(&self, idx: TorrentIdOrHash) -> Result<EmptyJsonResponse> {
+        self.mgr_handle(idx)?
+            .force_recheck()
+            .with_status(StatusCode::BAD_REQUEST)?;
+        Ok(Default::default())
+    }
+
+    pub async fn api_torrent_action_move_storage(
+        &self,
+        idx: TorrentIdOrHash,
+        output_folder: PathBuf,
+    ) -> Result<EmptyJsonResponse> {
+        let handle = self.mgr_handle(idx)?;
+        self.session()
+            .move_storage(&handle, output_folder)
+            .await
+            .with_status(StatusCode::BAD_REQUEST)?;
+        Ok(Default::default())
+    }
+
+    pub async fn api_torrent_action_rename_file(
+        &self,
+        idx: TorrentIdOrHash,
+        file_index: usize,
+        path: PathBuf,
+    ) -> Result<EmptyJsonResponse> {
+        let handle = self.mgr_handle(idx)?;
+        self.session()
+            .rename_file(&handle, file_index, path)
+            .await
+            .with_status(StatusCode::BAD_REQUEST)?;
+        Ok(Default::default())
+    }
+
+    pub async fn api_torrent_action_rename_folder(
+        &self,
+        idx: TorrentIdOrHash,
+        name: &str,
+    ) -> Result<EmptyJsonResponse> {
+        let handle = self.mgr_handle(idx)?;
+        self.session()
+            .rename_output_folder(&handle, name)
+            .await
+            .with_status(StatusCode::BAD_REQUEST)?;
+        Ok(Default::default())
+    }
+
     pub fn api_set_rust_log(&self, new_value: String) -> Result<EmptyJsonResponse> {
         let tx = self
             .rust_log_reload_tx
@@ -394,8 +476,7 @@ impl Api {
                     handle.only_files().as_deref(),
                     handle
                         .shared()
-                        .options
-                        .output_folder
+                        .output_folder()
                         .to_string_lossy()
                         .into_owned(),
                 )
@@ -406,8 +487,7 @@ impl Api {
                     seen_peers: None,
                     output_folder: handle
                         .shared()
-                        .options
-                        .output_folder
+                        .output_folder()
                         .to_string_lossy()
                         .into_owned(),
                 }
@@ -442,8 +522,7 @@ impl Api {
                     handle.only_files().as_deref(),
                     handle
                         .shared()
-                        .options
-                        .output_folder
+                        .output_folder()
                         .to_string_lossy()
                         .into_owned(),
                 )
@@ -454,8 +533,7 @@ impl Api {
                     seen_peers: None,
                     output_folder: handle
                         .shared()
-                        .options
-                        .output_folder
+                        .output_folder()
                         .to_string_lossy()
                         .into_owned(),
                 }
@@ -487,6 +565,63 @@ impl Api {
         }))
     }
 
+    pub async fn api_dht_put_immutable(&self, value: Bytes) -> Result<DhtPutResponse> {
+        let dht = self.session.get_dht().ok_or(ApiError::dht_disabled())?;
+        let target = dht.put_immutable(value).await.map_err(dht_item_error)?;
+        Ok(DhtPutResponse {
+            target: target.as_string(),
+            public_key: None,
+        })
+    }
+
+    pub async fn api_dht_get_immutable(&self, target: Id20) -> Result<DhtImmutableItem> {
+        let dht = self.session.get_dht().ok_or(ApiError::dht_disabled())?;
+        let value = dht
+            .get_immutable(target)
+            .await
+            .map_err(dht_item_error)?
+            .with_status_error(StatusCode::NOT_FOUND, "item not found")?;
+        Ok(DhtImmutableItem {
+            target: target.as_string(),
+            value: hex::encode(value),
+        })
+    }
+
+    /// Store a mutable item signed by the caller. The secret key never reaches the server.
+    pub async fn api_dht_put_mutable(
+        &self,
+        public_key: [u8; PUBLIC_KEY_LEN],
+        signature: [u8; SIGNATURE_LEN],
+        salt: Bytes,
+        seq: i64,
+        value: Bytes,
+        cas: Option<i64>,
+    ) -> Result<DhtPutResponse> {
+        let dht = self.session.get_dht().ok_or(ApiError::dht_disabled())?;
+        let item = MutableItem::from_signed(public_key, signature, salt, seq, value)
+            .map_err(dht_item_error)?;
+        let target = dht
+            .put_signed_mutable(item, cas)
+            .await
+            .map_err(dht_item_error)?;
+        Ok(DhtPutResponse {
+            target: target.as_string(),
+            public_key: Some(hex::encode(public_key)),
+        })
+    }
+
+    pub async fn api_dht_get_mutable(
+        &self,
+        public_key: [u8; 32],
+        salt: Bytes,
+    ) -> Result<MutableItem> {
+        let dht = self.session.get_dht().ok_or(ApiError::dht_disabled())?;
+        dht.get_mutable(public_key, salt)
+            .await
+            .map_err(dht_item_error)?
+            .with_status_error(StatusCode::NOT_FOUND, "item not found")
+    }
+
     pub fn api_stats_v0(&self, idx: TorrentIdOrHash) -> Result<LiveStats> {
         let mgr = self.mgr_handle(idx)?;
         let live = mgr.live().context("torrent not live")?;
@@ -498,6 +633,10 @@ impl Api {
         Ok(mgr.stats())
     }
 
+    pub fn api_torrent_magnet_link(&self, idx: TorrentIdOrHash) -> Result<String> {
+        Ok(self.mgr_handle(idx)?.magnet_link().to_string())
+    }
+
     pub fn api_dump_haves(&self, idx: TorrentIdOrHash) -> Result<(BF, u32)> {
         let mgr = self.mgr_handle(idx)?;
         Ok(mgr.with_chunk_tracker(|chunks| {
@@ -530,6 +669,29 @@ pub struct TorrentDetailsResponseFile {
 #[derive(Default, Serialize)]
 pub struct EmptyJsonResponse {}
 
+// Invalid items are the caller's fault, everything else (e.g. no nodes reachable) is ours.
+fn dht_item_error(e: dht::Error) -> ApiError {
+    let status = match e {
+        dht::Error::Bep44(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    ApiError::from((status, anyhow::Error::from(e)))
+}
+
+#[derive(Serialize)]
+pub struct DhtPutResponse {
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DhtImmutableItem {
+    pub target: String,
+    /// Hex of the bencoded value.
+    pub value: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct TorrentDetailsResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -545,6 +707,15 @@ pub struct TorrentDetailsResponse {
     pub files: Option<Vec<TorrentDetailsResponseFile>>,
     #[serde(skip_serializing_if = "Option::is_none", skip_deserializing)]
     pub stats: Option<TorrentStats>,
+
+    // From the .torrent file, filled in the /details endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    /// Seconds since UNIX epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creation_date: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -593,6 +764,9 @@ fn make_torrent_details(
         output_folder,
         total_pieces,
         stats: None,
+        comment: None,
+        created_by: None,
+        creation_date: None,
     })
 }
 
@@ -0,0 +1,180 @@
+//! Time-of-day rate limits for the whole session, e.g. throttled during the day and unlimited
+//! at night.
+//!
+//! A [`BandwidthScheduler`] checks the local time periodically and applies the limits of the
+//! first matching [`BandwidthWindow`] to [`Session::ratelimits`]. Outside of all windows, the
+//! limits the session had when the scheduler was created are restored.
+//!
+//! Limits are only changed when the active window changes, so they can still be adjusted by
+//! hand in between.
+
+use std::{
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use chrono::NaiveTime;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::{debug_span, info};
+
+use crate::{Session, limits::LimitsConfig};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthWindow {
+    /// Inclusive.
+    pub start: NaiveTime,
+    /// Exclusive. If it's before "start", the window wraps around midnight. If it's the same
+    /// as "start", the window is the whole day.
+    pub end: NaiveTime,
+    pub limits: LimitsConfig,
+}
+
+impl BandwidthWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start == self.end || (self.start <= time && time < self.end)
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthSchedule {
+    /// If windows overlap, the first one wins.
+    pub windows: Vec<BandwidthWindow>,
+}
+
+impl BandwidthSchedule {
+    /// Index of the window active at the given time.
+    pub fn active_window(&self, time: NaiveTime) -> Option<usize> {
+        self.windows.iter().position(|w| w.contains(time))
+    }
+}
+
+pub struct BandwidthScheduler {
+    session: Weak<Session>,
+    schedule: BandwidthSchedule,
+    default_limits: LimitsConfig,
+    // None before the first tick, Some(None) outside of all windows.
+    active: Mutex<Option<Option<usize>>>,
+}
+
+impl BandwidthScheduler {
+    pub fn new(session: &Arc<Session>, schedule: BandwidthSchedule) -> Arc<Self> {
+        Arc::new(Self {
+            session: Arc::downgrade(session),
+            schedule,
+            default_limits: session.ratelimits.get_config(),
+            active: Mutex::new(None),
+        })
+    }
+
+    /// The window applied on the last tick, if any.
+    pub fn active_window(&self) -> Option<BandwidthWindow> {
+        let idx = (*self.active.lock()).flatten()?;
+        self.schedule.windows.get(idx).copied()
+    }
+
+    /// The limits the scheduler applied on the last tick.
+    pub fn active_limits(&self) -> LimitsConfig {
+        self.active_window()
+            .map(|w| w.limits)
+            .unwrap_or(self.default_limits)
+    }
+
+    /// Apply the limits for the current local time. Called periodically by
+    /// [`BandwidthScheduler::spawn`].
+    pub fn tick(&self) {
+        self.tick_at(chrono::Local::now().time())
+    }
+
+    pub fn tick_at(&self, time: NaiveTime) {
+        let Some(session) = self.session.upgrade() else {
+            return;
+        };
+        let idx = self.schedule.active_window(time);
+        {
+            let mut active = self.active.lock();
+            if *active == Some(idx) {
+                return;
+            }
+            *active = Some(idx);
+        }
+        let limits = self.active_limits();
+        info!(window = ?idx, ?limits, "bandwidth schedule: applying limits");
+        session.ratelimits.set_download_bps(limits.download_bps);
+        session.ratelimits.set_upload_bps(limits.upload_bps);
+    }
+
+    /// Run [`BandwidthScheduler::tick`] every `interval`. Stops with the session or when the
+    /// scheduler is dropped.
+    pub fn spawn(self: &Arc<Self>, interval: Duration) {
+        let Some(session) = self.session.upgrade() else {
+            return;
+        };
+        let scheduler = Arc::downgrade(self);
+        session.spawn(
+            debug_span!(parent: session.rs(), "bandwidth_scheduler"),
+            "bandwidth_scheduler",
+            async move {
+                let mut interval = tokio::time::interval(interval);
+                loop {
+                    interval.tick().await;
+                    let Some(scheduler) = scheduler.upgrade() else {
+                        return Ok(());
+                    };
+                    scheduler.tick();
+                }
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::{BandwidthSchedule, BandwidthWindow};
+
+    fn t(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn window(start: NaiveTime, end: NaiveTime) -> BandwidthWindow {
+        BandwidthWindow {
+            start,
+            end,
+            limits: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_window_contains() {
+        let day = window(t(8, 0), t(23, 0));
+        assert!(day.contains(t(8, 0)));
+        assert!(day.contains(t(22, 59)));
+        assert!(!day.contains(t(23, 0)));
+        assert!(!day.contains(t(3, 0)));
+
+        let night = window(t(23, 0), t(8, 0));
+        assert!(night.contains(t(23, 0)));
+        assert!(night.contains(t(0, 0)));
+        assert!(night.contains(t(7, 59)));
+        assert!(!night.contains(t(8, 0)));
+        assert!(!night.contains(t(12, 0)));
+
+        assert!(window(t(5, 0), t(5, 0)).contains(t(17, 0)));
+    }
+
+    #[test]
+    fn test_first_window_wins() {
+        let schedule = BandwidthSchedule {
+            windows: vec![window(t(9, 0), t(12, 0)), window(t(8, 0), t(23, 0))],
+        };
+        assert_eq!(schedule.active_window(t(10, 0)), Some(0));
+        assert_eq!(schedule.active_window(t(8, 30)), Some(1));
+        assert_eq!(schedule.active_window(t(23, 30)), None);
+    }
+}
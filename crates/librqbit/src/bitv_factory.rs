@@ -1,14 +1,81 @@
-use crate::{api::TorrentIdOrHash, bitv::BitV, type_aliases::BF};
+use std::{path::Path, time::UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{api::TorrentIdOrHash, bitv::BitV, file_info::FileInfo, type_aliases::BF};
+
+/// Size and modification time of the torrent's files, taken when they were known to match the
+/// stored bitfield. If the files look the same on the next start, the bitfield is used without
+/// hashing anything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilesSnapshot {
+    // One per file in the torrent, None for padding and files that don't exist.
+    files: Vec<Option<FileSnapshot>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileSnapshot {
+    len: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl FilesSnapshot {
+    pub(crate) fn take<'a>(
+        output_folder: &Path,
+        file_infos: impl IntoIterator<Item = &'a FileInfo>,
+    ) -> Self {
+        let files = file_infos
+            .into_iter()
+            .map(|fi| {
+                if fi.attrs.padding {
+                    return None;
+                }
+                let m = std::fs::metadata(output_folder.join(&fi.relative_filename)).ok()?;
+                let modified = m.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+                Some(FileSnapshot {
+                    len: m.len(),
+                    modified_secs: modified.as_secs(),
+                    modified_nanos: modified.subsec_nanos(),
+                })
+            })
+            .collect();
+        Self { files }
+    }
+}
 
 #[async_trait::async_trait]
 pub trait BitVFactory: Send + Sync {
     async fn load(&self, id: TorrentIdOrHash) -> anyhow::Result<Option<Box<dyn BitV>>>;
+    // Should also clear the files snapshot.
     async fn clear(&self, id: TorrentIdOrHash) -> anyhow::Result<()>;
     async fn store_initial_check(
         &self,
         id: TorrentIdOrHash,
         b: BF,
     ) -> anyhow::Result<Box<dyn BitV>>;
+
+    /// The snapshot stored with store_files_snapshot(). Without one, the loaded bitfield is
+    /// validated by hashing a sample of the pieces.
+    async fn load_files_snapshot(
+        &self,
+        _id: TorrentIdOrHash,
+    ) -> anyhow::Result<Option<FilesSnapshot>> {
+        Ok(None)
+    }
+
+    async fn store_files_snapshot(
+        &self,
+        _id: TorrentIdOrHash,
+        _snapshot: &FilesSnapshot,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called when the torrent goes live, as the files are about to change.
+    async fn clear_files_snapshot(&self, _id: TorrentIdOrHash) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 pub struct NonPersistentBitVFactory {}
@@ -1,7 +1,11 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 
 use anyhow::Context;
 use buffers::ByteBuf;
+use itertools::Either;
 use librqbit_core::lengths::{ChunkInfo, Lengths, ValidPieceIndex};
 use peer_binary_protocol::Piece;
 use tracing::{debug, trace};
@@ -128,20 +132,50 @@ fn compute_queued_pieces(have_pieces: &BS, selected_pieces: &BS) -> anyhow::Resu
     ))
 }
 
+// Byte ranges wanted within a file, keyed by file index. Files without an entry are wanted
+// in full. The ranges are sorted, non-empty and don't overlap or touch, see merge_ranges().
+pub(crate) type WantedRanges = HashMap<usize, Vec<Range<u64>>>;
+
+// Sort the ranges and merge the ones that overlap or are adjacent.
+pub(crate) fn merge_ranges(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for r in ranges.into_iter().filter(|r| !r.is_empty()) {
+        match merged.last_mut() {
+            Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+            _ => merged.push(r),
+        }
+    }
+    merged
+}
+
 pub(crate) fn compute_selected_pieces(
     lengths: &Lengths,
     only_files_is_empty_or_contains: impl Fn(usize) -> bool,
     file_infos: &FileInfos,
+    wanted_ranges: &WantedRanges,
 ) -> BF {
     let mut bf = BF::from_boxed_slice(vec![0u8; lengths.piece_bitfield_bytes()].into_boxed_slice());
-    for (_, fi) in file_infos
+    for (id, fi) in file_infos
         .iter()
         .enumerate()
         .filter(|(_, fi)| !fi.attrs.padding)
         .filter(|(id, _)| only_files_is_empty_or_contains(*id))
     {
-        if let Some(r) = bf.get_mut(fi.piece_range_usize()) {
-            r.fill(true);
+        let Some(ranges) = wanted_ranges.get(&id) else {
+            if let Some(r) = bf.get_mut(fi.piece_range_usize()) {
+                r.fill(true);
+            }
+            continue;
+        };
+        // Pieces only partially covered by a range are still downloaded in full, as they can
+        // only be verified as a whole.
+        for r in ranges.iter().filter(|r| !r.is_empty()) {
+            let pieces =
+                lengths.iter_pieces_within_offset(fi.offset_in_torrent + r.start, r.end - r.start);
+            if let Some(r) = bf.get_mut(pieces.start as usize..pieces.end as usize) {
+                r.fill(true);
+            }
         }
     }
     bf
@@ -233,21 +267,41 @@ impl ChunkTracker {
         hns
     }
 
+    // With "sequential", the pieces of each file are in order. Otherwise the first and the last
+    // piece of each file come first.
     pub(crate) fn iter_queued_pieces<'a>(
         &'a self,
         file_priorities: &'a FilePriorities,
         file_infos: &'a FileInfos,
+        sequential: bool,
     ) -> impl Iterator<Item = ValidPieceIndex> + 'a {
         file_priorities
             .iter()
             .filter_map(|p| Some((*p, file_infos.get(*p)?)))
             .filter(|(id, f)| self.per_file_bytes[*id] != f.len)
-            .flat_map(|(_id, f)| f.iter_piece_priorities())
+            .flat_map(move |(_id, f)| {
+                if sequential {
+                    Either::Left(f.piece_range_usize())
+                } else {
+                    Either::Right(f.iter_piece_priorities())
+                }
+            })
             .filter(|id| self.queue_pieces[*id])
             .filter_map(|id| id.try_into().ok())
             .filter_map(|id| self.lengths.validate_piece_index(id))
     }
 
+    pub(crate) fn queued_pieces_count(&self) -> usize {
+        self.queue_pieces.count_ones()
+    }
+
+    pub(crate) fn is_chunk_downloaded(&self, chunk: &ChunkInfo) -> bool {
+        self.chunk_status
+            .get(self.lengths.chunk_range(chunk.piece_index))
+            .and_then(|r| r.get(chunk.chunk_index as usize).map(|b| *b))
+            .unwrap_or(false)
+    }
+
     pub(crate) fn is_piece_have(&self, id: ValidPieceIndex) -> bool {
         self.have.as_slice()[id.get() as usize]
     }
@@ -281,6 +335,42 @@ impl ChunkTracker {
         }
     }
 
+    // A piece checked on disk outside of downloading (see ManagedTorrent::verify_piece) is
+    // complete. Per-file bytes are updated separately, same as for downloaded pieces.
+    pub fn mark_piece_verified(&mut self, idx: ValidPieceIndex) {
+        self.mark_piece_downloaded(idx);
+        self.queue_pieces.set(idx.get() as usize, false);
+        if let Some(s) = self.chunk_status.get_mut(self.lengths.chunk_range(idx)) {
+            s.fill(true);
+        }
+    }
+
+    // A piece we had failed the check on disk. Forget it, so that it's downloaded again if selected.
+    pub fn mark_piece_not_have(&mut self, idx: ValidPieceIndex, file_infos: &FileInfos) {
+        let id = idx.get() as usize;
+        if !self.have.as_slice()[id] {
+            return;
+        }
+        debug!("marking piece={} as not downloaded", idx);
+        self.have.as_slice_mut().set(id, false);
+        let len = self.lengths.piece_length(idx) as u64;
+        self.hns.have_bytes -= len;
+        if self.selected[id] {
+            self.hns.needed_bytes += len;
+            self.queue_pieces.set(id, true);
+        }
+        if let Some(s) = self.chunk_status.get_mut(self.lengths.chunk_range(idx)) {
+            s.fill(false);
+        }
+        for (slot, fi) in self.per_file_bytes.iter_mut().zip(file_infos.iter()) {
+            if fi.piece_range.contains(&idx.get()) {
+                *slot -=
+                    self.lengths
+                        .size_of_piece_in_file(idx.get(), fi.offset_in_torrent, fi.len);
+            }
+        }
+    }
+
     pub fn is_chunk_ready_to_upload(&self, chunk: &ChunkInfo) -> bool {
         self.have
             .as_slice()
@@ -324,11 +414,13 @@ impl ChunkTracker {
         &mut self,
         file_infos: &FileInfos,
         new_only_files: &HashSet<usize>,
+        wanted_ranges: &WantedRanges,
     ) -> anyhow::Result<HaveNeededSelected> {
         let selected = compute_selected_pieces(
             &self.lengths,
             |idx| new_only_files.contains(&idx),
             file_infos,
+            wanted_ranges,
         );
         let prev_selected = std::mem::replace(&mut self.selected, selected);
 
@@ -412,7 +504,10 @@ mod tests {
         bitv::BitV, chunk_tracker::HaveNeededSelected, file_info::FileInfo, type_aliases::BF,
     };
 
-    use super::{ChunkTracker, compute_chunk_have_status};
+    use super::{
+        ChunkTracker, WantedRanges, compute_chunk_have_status, compute_selected_pieces,
+        merge_ranges,
+    };
 
     #[test]
     fn test_compute_chunk_status() {
@@ -512,6 +607,48 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_iter_queued_pieces_file_priorities() {
+        let piece_len = CHUNK_SIZE;
+        let l = Lengths::new(piece_len as u64 * 3, piece_len).unwrap();
+        // File 1 shares piece 1 with file 0.
+        let files = vec![
+            FileInfo {
+                relative_filename: "0".into(),
+                offset_in_torrent: 0,
+                piece_range: 0..2,
+                len: piece_len as u64 + 1,
+                attrs: Default::default(),
+            },
+            FileInfo {
+                relative_filename: "1".into(),
+                offset_in_torrent: piece_len as u64 + 1,
+                piece_range: 1..3,
+                len: piece_len as u64 * 2 - 1,
+                attrs: Default::default(),
+            },
+        ];
+        let bf_len = l.piece_bitfield_bytes();
+        let mut selected = BF::from_boxed_slice(vec![0u8; bf_len].into_boxed_slice());
+        selected.get_mut(0..3).unwrap().fill(true);
+        let ct = ChunkTracker::new(
+            BF::from_boxed_slice(vec![0u8; bf_len].into_boxed_slice()).into_dyn(),
+            selected,
+            l,
+            &files,
+        )
+        .unwrap();
+
+        let order = |pri: Vec<usize>| {
+            ct.iter_queued_pieces(&pri, &files, false)
+                .map(|p| p.get())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(vec![0, 1]), vec![0, 1, 1, 2]);
+        // With file 1 first, the shared piece comes before the pieces only in file 0.
+        assert_eq!(order(vec![1, 0]), vec![1, 2, 0, 1]);
+    }
+
     #[test]
     fn test_update_only_files() {
         let piece_len = CHUNK_SIZE * 2 + 1;
@@ -570,8 +707,12 @@ mod tests {
 
         // Select all file, no changes.
         assert_eq!(
-            ct.update_only_files(&all_files, &HashSet::from_iter([0, 1, 2, 3]))
-                .unwrap(),
+            ct.update_only_files(
+                &all_files,
+                &HashSet::from_iter([0, 1, 2, 3]),
+                &Default::default()
+            )
+            .unwrap(),
             HaveNeededSelected {
                 have_bytes: 0,
                 selected_bytes: total_len,
@@ -584,7 +725,7 @@ mod tests {
         // Select only the first file.
         println!("Select only the first file.");
         assert_eq!(
-            ct.update_only_files(&all_files, &HashSet::from_iter([0]))
+            ct.update_only_files(&all_files, &HashSet::from_iter([0]), &Default::default())
                 .unwrap(),
             HaveNeededSelected {
                 have_bytes: 0,
@@ -598,7 +739,7 @@ mod tests {
 
         // Select only the second file.
         assert_eq!(
-            ct.update_only_files(&all_files, &HashSet::from_iter([1]))
+            ct.update_only_files(&all_files, &HashSet::from_iter([1]), &Default::default())
                 .unwrap(),
             HaveNeededSelected {
                 have_bytes: 0,
@@ -612,7 +753,7 @@ mod tests {
 
         // Select only the third file (zero sized one!).
         assert_eq!(
-            ct.update_only_files(&all_files, &HashSet::from_iter([2]))
+            ct.update_only_files(&all_files, &HashSet::from_iter([2]), &Default::default())
                 .unwrap(),
             HaveNeededSelected {
                 have_bytes: 0,
@@ -626,7 +767,7 @@ mod tests {
 
         // Select only the fourth file.
         assert_eq!(
-            ct.update_only_files(&all_files, &HashSet::from_iter([3]))
+            ct.update_only_files(&all_files, &HashSet::from_iter([3]), &Default::default())
                 .unwrap(),
             HaveNeededSelected {
                 have_bytes: 0,
@@ -640,7 +781,7 @@ mod tests {
 
         // Select first and last file
         assert_eq!(
-            ct.update_only_files(&all_files, &HashSet::from_iter([0, 3]))
+            ct.update_only_files(&all_files, &HashSet::from_iter([0, 3]), &Default::default())
                 .unwrap(),
             HaveNeededSelected {
                 have_bytes: 0,
@@ -654,8 +795,12 @@ mod tests {
 
         // Select all files
         assert_eq!(
-            ct.update_only_files(&all_files, &HashSet::from_iter([0, 1, 2, 3]))
-                .unwrap(),
+            ct.update_only_files(
+                &all_files,
+                &HashSet::from_iter([0, 1, 2, 3]),
+                &Default::default()
+            )
+            .unwrap(),
             HaveNeededSelected {
                 have_bytes: 0,
                 selected_bytes: total_len,
@@ -666,4 +811,56 @@ mod tests {
         assert!(ct.queue_pieces[1]);
         assert!(ct.queue_pieces[2]);
     }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_merge_ranges() {
+        assert_eq!(merge_ranges(vec![]), vec![]);
+        assert_eq!(
+            merge_ranges(vec![10..20, 0..5, 15..30, 5..6, 40..40, 50..60]),
+            vec![0..6, 10..30, 50..60]
+        );
+        assert_eq!(merge_ranges(vec![0..100, 10..20]), vec![0..100]);
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_compute_selected_pieces_wanted_ranges() {
+        let piece_len = CHUNK_SIZE;
+        let l = Lengths::new(piece_len as u64 * 4, piece_len).unwrap();
+        let files = vec![
+            FileInfo {
+                relative_filename: "0".into(),
+                offset_in_torrent: 0,
+                piece_range: 0..1,
+                len: 10,
+                attrs: Default::default(),
+            },
+            FileInfo {
+                relative_filename: "1".into(),
+                offset_in_torrent: 10,
+                piece_range: 0..4,
+                len: piece_len as u64 * 4 - 10,
+                attrs: Default::default(),
+            },
+        ];
+        let pl = piece_len as u64;
+        let selected = |only: &[usize], wanted: &WantedRanges| {
+            compute_selected_pieces(&l, |idx| only.contains(&idx), &files, wanted)
+                .iter_ones()
+                .collect::<Vec<_>>()
+        };
+
+        // A range ending right at a piece boundary doesn't select the next piece, a range
+        // crossing it selects both.
+        let wanted = WantedRanges::from_iter([(1, vec![pl - 10..2 * pl - 10])]);
+        assert_eq!(selected(&[1], &wanted), vec![1]);
+        let wanted = WantedRanges::from_iter([(1, vec![pl - 10..2 * pl - 9, 3 * pl..3 * pl + 1])]);
+        assert_eq!(selected(&[1], &wanted), vec![1, 2, 3]);
+
+        // The piece shared with a fully selected file stays selected.
+        assert_eq!(selected(&[0, 1], &wanted), vec![0, 1, 2, 3]);
+        // Ranges of skipped files are ignored.
+        assert_eq!(selected(&[0], &wanted), vec![0]);
+    }
 }
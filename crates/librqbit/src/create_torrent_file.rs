@@ -1,7 +1,9 @@
 use std::borrow::Cow;
 use std::ffi::OsStr;
-use std::io::{BufWriter, Read};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use anyhow::Context;
 use bencode::{WithRawBytes, bencode_serialize_to_writer};
@@ -18,12 +20,26 @@ use crate::spawn_utils::BlockingSpawner;
 pub struct CreateTorrentOptions<'a> {
     pub name: Option<&'a str>,
     pub trackers: Vec<String>,
+    /// Must be a power of two, and at least 16 KiB. Picked from the total size if not set.
     pub piece_length: Option<u32>,
+    /// Web seed URLs (BEP-19), written as "url-list".
+    pub web_seeds: Vec<String>,
+    /// Set the private flag (BEP 27), so that peers are only found through the trackers.
+    pub private: bool,
+    pub comment: Option<&'a str>,
+}
+
+struct InputFile<'a> {
+    path: Cow<'a, Path>,
+    len: u64,
+    // Offset in the concatenation of all files.
+    offset: u64,
 }
 
 fn walk_dir_find_paths(dir: &Path, out: &mut Vec<Cow<'_, Path>>) -> anyhow::Result<()> {
     out.extend(
         walkdir::WalkDir::new(dir)
+            .sort_by_file_name()
             .into_iter()
             .filter_map(|e| e.ok())
             .filter(|e| e.file_type().is_file())
@@ -47,15 +63,114 @@ fn compute_info_hash(t: &TorrentMetaV1Info<ByteBufOwned>) -> anyhow::Result<(Id2
     Ok((hash, bytes))
 }
 
-fn choose_piece_length(_input_files: &[Cow<'_, Path>]) -> u32 {
-    // TODO: make this smarter or smth
-    2 * 1024 * 1024
+const MIN_PIECE_LENGTH: u32 = 16 * 1024;
+const MAX_PIECE_LENGTH: u32 = 16 * 1024 * 1024;
+
+// Aim for around 1500 pieces. Fewer pieces spread slower through the swarm, more pieces make the
+// .torrent file bigger.
+fn choose_piece_length(total_length: u64) -> u32 {
+    const TARGET_PIECES: u64 = 1500;
+    (total_length / TARGET_PIECES)
+        .next_power_of_two()
+        .clamp(MIN_PIECE_LENGTH.into(), MAX_PIECE_LENGTH.into()) as u32
 }
 
 fn osstr_to_bytes(o: &OsStr) -> Vec<u8> {
     o.to_str().unwrap().to_owned().into_bytes()
 }
 
+const READ_SIZE: usize = 65536;
+const MAX_HASHING_THREADS: usize = 8;
+
+// Hash the piece that starts at "offset" in the concatenation of all files. "open" caches the
+// last opened file, as consecutive pieces usually come from the same one.
+fn hash_piece(
+    files: &[InputFile<'_>],
+    offset: u64,
+    len: u64,
+    open: &mut Option<(usize, File)>,
+    buf: &mut [u8],
+) -> anyhow::Result<[u8; 20]> {
+    let mut sha = sha1w::Sha1::new();
+    let end = offset + len;
+    let mut pos = offset;
+    let mut idx = files.partition_point(|f| f.offset + f.len <= pos);
+    while pos < end {
+        let file = files
+            .get(idx)
+            .context("internal error, piece is out of bounds")?;
+        let file_end = file.offset + file.len;
+        if file_end <= pos {
+            // Empty file.
+            idx += 1;
+            continue;
+        }
+        if open.as_ref().is_none_or(|(i, _)| *i != idx) {
+            let fd =
+                File::open(&file.path).with_context(|| format!("error opening {:?}", file.path))?;
+            *open = Some((idx, fd));
+        }
+        let (_, fd) = open.as_mut().context("internal error, no open file")?;
+        fd.seek(SeekFrom::Start(pos - file.offset))?;
+
+        let mut remaining = end.min(file_end) - pos;
+        while remaining > 0 {
+            let chunk_len = remaining.min(buf.len() as u64) as usize;
+            let chunk = &mut buf[..chunk_len];
+            fd.read_exact(chunk)
+                .with_context(|| format!("error reading {:?}, did it change?", file.path))?;
+            sha.update(chunk);
+            remaining -= chunk.len() as u64;
+        }
+        pos = end.min(file_end);
+        idx += 1;
+    }
+    Ok(sha.finish())
+}
+
+// Pieces are independent, so they're hashed by threads of the spawner, each taking the next piece
+// that nobody took yet.
+async fn compute_piece_hashes(
+    files: &[InputFile<'_>],
+    piece_length: u32,
+    spawner: &BlockingSpawner,
+) -> anyhow::Result<Vec<u8>> {
+    let total_length = files.last().map(|f| f.offset + f.len).unwrap_or(0);
+    let piece_length = u64::from(piece_length);
+    let num_pieces = total_length.div_ceil(piece_length) as usize;
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .clamp(1, MAX_HASHING_THREADS)
+        .min(num_pieces);
+
+    let next_piece = AtomicUsize::new(0);
+    let worker = || -> anyhow::Result<Vec<(usize, [u8; 20])>> {
+        let mut open = None;
+        let mut buf = vec![0u8; READ_SIZE];
+        let mut hashed = Vec::new();
+        loop {
+            let piece = next_piece.fetch_add(1, Ordering::Relaxed);
+            if piece >= num_pieces {
+                return Ok(hashed);
+            }
+            let offset = piece as u64 * piece_length;
+            let len = piece_length.min(total_length - offset);
+            hashed.push((piece, hash_piece(files, offset, len, &mut open, &mut buf)?));
+        }
+    };
+    let hashed = spawner
+        .block_in_place_concurrently(threads, worker)
+        .await
+        .into_iter()
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut piece_hashes = vec![0u8; num_pieces * 20];
+    for (piece, hash) in hashed.into_iter().flatten() {
+        piece_hashes[piece * 20..(piece + 1) * 20].copy_from_slice(&hash);
+    }
+    Ok(piece_hashes)
+}
+
 struct CreateTorrentRawResult {
     info: TorrentMetaV1Info<ByteBufOwned>,
     output_folder: PathBuf,
@@ -79,10 +194,10 @@ async fn create_torrent_raw<'a>(
     };
     let output_folder: PathBuf;
 
-    let mut input_files: Vec<Cow<'a, Path>> = Default::default();
+    let mut input_paths: Vec<Cow<'a, Path>> = Default::default();
     if is_dir {
         output_folder = path.to_owned();
-        walk_dir_find_paths(path, &mut input_files)
+        walk_dir_find_paths(path, &mut input_paths)
             .with_context(|| format!("error walking {path:?}"))?;
     } else {
         output_folder = path
@@ -90,79 +205,66 @@ async fn create_torrent_raw<'a>(
             .parent()
             .context("single file has no parent")?
             .to_path_buf();
-        input_files.push(Cow::Borrowed(path));
+        input_paths.push(Cow::Borrowed(path));
     }
 
-    let piece_length = options
-        .piece_length
-        .unwrap_or_else(|| choose_piece_length(&input_files));
+    let mut input_files = Vec::with_capacity(input_paths.len());
+    let mut total_length = 0;
+    for path in input_paths {
+        let len = std::fs::metadata(&path)
+            .with_context(|| format!("error reading metadata of {path:?}"))?
+            .len();
+        input_files.push(InputFile {
+            path,
+            len,
+            offset: total_length,
+        });
+        total_length += len;
+    }
 
-    // Calculate hashes etc.
-    const READ_SIZE: u32 = 8192; // todo: twea
-    let mut read_buf = vec![0; READ_SIZE as usize];
+    let piece_length = match options.piece_length {
+        Some(l) if !l.is_power_of_two() => {
+            anyhow::bail!("piece length should be a power of two, got {l}")
+        }
+        Some(l) if l < MIN_PIECE_LENGTH => {
+            anyhow::bail!("piece length should be at least {MIN_PIECE_LENGTH}, got {l}")
+        }
+        Some(l) => l,
+        None => choose_piece_length(total_length),
+    };
 
-    let _permit = spawner.semaphore().acquire_owned().await?;
+    let piece_hashes = compute_piece_hashes(&input_files, piece_length, spawner).await?;
 
-    let mut length = 0;
-    let mut remaining_piece_length = piece_length;
-    let mut piece_checksum = sha1w::Sha1::new();
-    let mut piece_hashes = Vec::<u8>::new();
     let mut output_files: Vec<TorrentMetaV1File<ByteBufOwned>> = Vec::new();
-
-    'outer: for file in input_files {
-        let filename = &*file;
-        length = 0;
-        let mut fd = std::io::BufReader::new(
-            std::fs::File::open(&file).with_context(|| format!("error opening {filename:?}"))?,
-        );
-
-        loop {
-            let max_bytes_to_read = remaining_piece_length.min(READ_SIZE) as usize;
-            // NOTE: we can't use the semaphore as Sha1 isn't Send at least on OSX.
-            let size = spawner
-                .block_in_place(|| fd.read(&mut read_buf[..max_bytes_to_read]))
-                .with_context(|| format!("error reading {filename:?}"))?;
-
-            // EOF: swap file
-            if size == 0 {
-                let filename = filename
-                    .strip_prefix(path)
-                    .context("internal error, can't strip prefix")?;
-                let path = filename
+    if !single_file_mode {
+        for file in input_files.iter() {
+            let filename = file
+                .path
+                .strip_prefix(path)
+                .context("internal error, can't strip prefix")?;
+            output_files.push(TorrentMetaV1File {
+                length: file.len,
+                path: filename
                     .components()
                     .map(|c| osstr_to_bytes(c.as_os_str()).into())
-                    .collect();
-                output_files.push(TorrentMetaV1File {
-                    length,
-                    path,
-                    attr: None,
-                    sha1: None,
-                    symlink_path: None,
-                });
-                continue 'outer;
-            }
-
-            length += size as u64;
-            piece_checksum.update(&read_buf[..size]);
-
-            remaining_piece_length -= TryInto::<u32>::try_into(size)?;
-            if remaining_piece_length == 0 {
-                remaining_piece_length = piece_length;
-                piece_hashes.extend_from_slice(&piece_checksum.finish());
-                piece_checksum = sha1w::Sha1::new();
-            }
+                    .collect(),
+                attr: None,
+                sha1: None,
+                symlink_path: None,
+            });
         }
     }
 
-    if remaining_piece_length > 0 && length > 0 {
-        piece_hashes.extend_from_slice(&piece_checksum.finish());
-    }
     Ok(CreateTorrentRawResult {
         info: TorrentMetaV1Info {
             name: Some(name),
             pieces: piece_hashes.into(),
             piece_length,
-            length: if single_file_mode { Some(length) } else { None },
+            length: if single_file_mode {
+                Some(total_length)
+            } else {
+                None
+            },
             md5sum: None,
             files: if single_file_mode {
                 None
@@ -172,7 +274,8 @@ async fn create_torrent_raw<'a>(
             attr: None,
             sha1: None,
             symlink_path: None,
-            private: false,
+            private: options.private,
+            meta_version: None,
         },
         output_folder,
     })
@@ -199,7 +302,9 @@ impl CreateTorrentResult {
             .iter_announce()
             .map(|i| std::str::from_utf8(i.as_ref()).unwrap().to_owned())
             .collect();
-        Magnet::from_id20(self.info_hash(), trackers, None)
+        let mut magnet = Magnet::from_id20(self.info_hash(), trackers, None);
+        magnet.name = self.meta.info.data.name().map(|n| n.into_owned());
+        magnet
     }
 
     pub fn as_bytes(&self) -> anyhow::Result<Bytes> {
@@ -214,28 +319,41 @@ pub async fn create_torrent<'a>(
     options: CreateTorrentOptions<'a>,
     spawner: &BlockingSpawner,
 ) -> anyhow::Result<CreateTorrentResult> {
-    let trackers = options
+    let trackers: Vec<ByteBufOwned> = options
         .trackers
         .iter()
         .map(|t| ByteBufOwned::from(t.as_bytes()))
         .collect();
+    let url_list = options
+        .web_seeds
+        .iter()
+        .map(|u| ByteBufOwned::from(u.as_bytes()))
+        .collect();
+    let comment = options.comment.map(|c| ByteBufOwned::from(c.as_bytes()));
+    let creation_date = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs() as usize);
     let res = create_torrent_raw(path, options, spawner).await?;
     let (info_hash, bytes) = compute_info_hash(&res.info).context("error computing info hash")?;
     Ok(CreateTorrentResult {
         meta: TorrentMetaV1Owned {
-            announce: None,
+            // For clients that don't support announce-list.
+            announce: trackers.first().cloned(),
             announce_list: vec![trackers],
             info: WithRawBytes {
                 data: res.info,
                 raw_bytes: ByteBufOwned(bytes),
             },
-            comment: None,
-            created_by: None,
+            comment,
+            created_by: Some(crate::client_name_and_version().as_bytes().into()),
             encoding: Some(b"utf-8"[..].into()),
             publisher: None,
             publisher_url: None,
-            creation_date: None,
+            creation_date,
+            url_list,
             info_hash,
+            info_hash_v2: None,
         },
         output_folder: res.output_folder,
     })
@@ -245,7 +363,11 @@ pub async fn create_torrent<'a>(
 mod tests {
     use librqbit_core::torrent_metainfo::torrent_from_bytes;
 
-    use crate::{create_torrent, spawn_utils::BlockingSpawner};
+    use sha1w::ISha1;
+
+    use crate::{CreateTorrentOptions, create_torrent, spawn_utils::BlockingSpawner};
+
+    use super::choose_piece_length;
 
     #[tokio::test]
     async fn test_create_torrent() {
@@ -265,4 +387,70 @@ mod tests {
         let deserialized = torrent_from_bytes(&bytes).unwrap();
         assert_eq!(torrent.info_hash(), deserialized.info_hash);
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_create_torrent_piece_hashes() {
+        use crate::tests::test_util;
+
+        let dir = test_util::create_default_random_dir_with_torrents(
+            3,
+            100 * 1000,
+            Some("rqbit_test_create_torrent_piece_hashes"),
+        );
+        let torrent = create_torrent(
+            dir.path(),
+            CreateTorrentOptions {
+                piece_length: Some(16384),
+                private: true,
+                comment: Some("test"),
+                trackers: vec!["http://tracker.example/announce".into()],
+                ..Default::default()
+            },
+            &BlockingSpawner::new(4),
+        )
+        .await
+        .unwrap();
+
+        // Pieces span file boundaries, files are in name order.
+        let mut data = Vec::new();
+        for f in 0..3 {
+            data.extend(std::fs::read(dir.path().join(format!("{f}.data"))).unwrap());
+        }
+        let expected: Vec<u8> = data
+            .chunks(16384)
+            .flat_map(|chunk| {
+                let mut sha = sha1w::Sha1::new();
+                sha.update(chunk);
+                sha.finish()
+            })
+            .collect();
+        let info = &torrent.as_info().info.data;
+        assert_eq!(info.pieces.as_ref(), &expected[..]);
+        assert!(info.private);
+
+        let deserialized = torrent_from_bytes(&torrent.as_bytes().unwrap()).unwrap();
+        assert_eq!(deserialized.comment.unwrap().as_ref(), b"test");
+        assert_eq!(
+            deserialized.announce.unwrap().as_ref(),
+            b"http://tracker.example/announce"
+        );
+
+        let too_small = CreateTorrentOptions {
+            piece_length: Some(8192),
+            ..Default::default()
+        };
+        assert!(
+            create_torrent(dir.path(), too_small, &BlockingSpawner::new(1))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_choose_piece_length() {
+        assert_eq!(choose_piece_length(0), 16384);
+        assert_eq!(choose_piece_length(1000 * 1000), 16384);
+        assert_eq!(choose_piece_length(1024 * 1024 * 1024), 1024 * 1024);
+        assert_eq!(choose_piece_length(1 << 50), 16 * 1024 * 1024);
+    }
 }
@@ -7,7 +7,9 @@ use librqbit_core::torrent_metainfo::TorrentMetaV1Info;
 use tracing::{Instrument, debug, debug_span};
 
 use crate::{
-    peer_connection::PeerConnectionOptions, peer_info_reader, spawn_utils::BlockingSpawner,
+    peer_connection::PeerConnectionOptions,
+    peer_info_reader::{self, MetadataFetchProgress},
+    spawn_utils::BlockingSpawner,
     stream_connect::StreamConnector,
 };
 use librqbit_core::hash_id::Id20;
@@ -34,6 +36,7 @@ pub async fn read_metainfo_from_peer_receiver<A: Stream<Item = SocketAddr> + Unp
     addrs_stream: A,
     peer_connection_options: Option<PeerConnectionOptions>,
     connector: Arc<StreamConnector>,
+    progress: Arc<MetadataFetchProgress>,
 ) -> ReadMetainfoResult<A> {
     let mut seen = HashSet::<SocketAddr>::new();
     let mut addrs = addrs_stream;
@@ -43,6 +46,7 @@ pub async fn read_metainfo_from_peer_receiver<A: Stream<Item = SocketAddr> + Unp
     let read_info_guarded = |addr| {
         let semaphore = &semaphore;
         let connector = connector.clone();
+        let progress = progress.clone();
         async move {
             let token = semaphore.acquire().await?;
             let ret = peer_info_reader::read_metainfo_from_peer(
@@ -54,10 +58,14 @@ pub async fn read_metainfo_from_peer_receiver<A: Stream<Item = SocketAddr> + Unp
                 // ok not to use a shared one.
                 BlockingSpawner::new(1),
                 connector,
+                progress.clone(),
             )
             .instrument(debug_span!("read_metainfo_from_peer", ?addr))
             .await
             .with_context(|| format!("error reading metainfo from {addr}"));
+            if ret.is_err() {
+                progress.on_peer_failed(addr);
+            }
             drop(token);
             ret
         }
@@ -142,6 +150,7 @@ mod tests {
             peer_rx,
             None,
             Arc::new(StreamConnector::new(Default::default()).await.unwrap()),
+            Default::default(),
         )
         .await
         {
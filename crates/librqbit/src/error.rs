@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use governor::InsufficientCapacity;
 use peer_binary_protocol::MessageDeserializeError;
 use tokio::sync::AcquireError;
@@ -123,3 +125,17 @@ pub enum Error {
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
+
+/// Reasons a torrent stopped that can be fixed by the user, as opposed to other fatal errors.
+/// Find them in the torrent's error with [`anyhow::Error::downcast_ref`].
+#[derive(thiserror::Error, Debug)]
+pub enum TorrentError {
+    /// The files or the output folder were removed while the torrent was running.
+    /// See [`ManagedTorrent::recover_storage`](crate::ManagedTorrent::recover_storage).
+    #[error("torrent files are missing, {0:?} doesn't exist")]
+    StorageMissing(PathBuf),
+    /// Writing to disk failed as it's out of space. The torrent gets paused instead of
+    /// stopping with an error, see [`ManagedTorrent::resume_after_disk_full`](crate::ManagedTorrent::resume_after_disk_full).
+    #[error("no space left on device")]
+    DiskFull,
+}
@@ -1,6 +1,11 @@
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
 
+use anyhow::{Context, bail};
 use librqbit_core::torrent_metainfo::FileDetailsAttrs;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub struct FileInfo {
@@ -11,6 +16,141 @@ pub struct FileInfo {
     pub len: u64,
 }
 
+/// Download priority of a single file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilePriority {
+    /// Don't download the file, same as excluding it from "only_files".
+    Skip,
+    /// Pieces of the file are requested after the pieces of normal files.
+    Low,
+    #[default]
+    Normal,
+    /// Pieces of the file are requested before the pieces of normal files.
+    High,
+}
+
+/// Maps path components of a file in the torrent to a path relative to the output folder.
+pub type FileNameMapper = Box<dyn Fn(&[String]) -> PathBuf + Send + Sync>;
+
+/// Maps the index and path components of a file in the torrent to where it's stored.
+/// Relative paths are inside the output folder.
+pub type PathResolver = Box<dyn Fn(usize, &[String]) -> PathBuf + Send + Sync>;
+
+/// How the paths of torrent files inside the output folder are chosen.
+#[derive(Default)]
+pub enum FileNamingStrategy {
+    /// Keep the directory structure of the torrent.
+    #[default]
+    Original,
+    /// Put all files directly into the output folder. If names collide, a numeric suffix
+    /// is appended, e.g. "name (1).ext".
+    Flatten,
+    /// Map the path components of each file in the torrent to a path relative to the
+    /// output folder.
+    Custom(FileNameMapper),
+    /// Like [`FileNamingStrategy::Custom`], but also gets the file index, and may return
+    /// absolute paths to store some files elsewhere, e.g. on another drive. Files outside the
+    /// output folder stay in place on [`ManagedTorrent::move_storage`](crate::ManagedTorrent::move_storage).
+    Resolve(PathResolver),
+}
+
+// A non-empty relative path that stays inside the output folder.
+fn is_valid_relative_path(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_))) && !path.as_os_str().is_empty()
+}
+
+fn components(path: &Path) -> Vec<String> {
+    path.iter()
+        .map(|c| c.to_string_lossy().into_owned())
+        .collect()
+}
+
+impl FileNamingStrategy {
+    pub(crate) fn apply(
+        &self,
+        file_infos: &mut [FileInfo],
+        output_folder: &Path,
+    ) -> anyhow::Result<()> {
+        // Full paths, so that a relative path can collide with an absolute one.
+        let mut seen = HashSet::new();
+        for (idx, fi) in file_infos
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, fi)| !fi.attrs.padding)
+        {
+            let path = match self {
+                FileNamingStrategy::Original => return Ok(()),
+                FileNamingStrategy::Flatten => {
+                    let name = Path::new(fi.relative_filename.file_name().unwrap_or_default());
+                    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+                    let ext = name.extension().map(|e| e.to_string_lossy());
+                    let mut path = name.to_owned();
+                    let mut suffix = 0;
+                    while seen.contains(&output_folder.join(&path)) {
+                        suffix += 1;
+                        path = match &ext {
+                            Some(ext) => format!("{stem} ({suffix}).{ext}"),
+                            None => format!("{stem} ({suffix})"),
+                        }
+                        .into();
+                    }
+                    path
+                }
+                FileNamingStrategy::Custom(f) => {
+                    let path = f(&components(&fi.relative_filename));
+                    if !is_valid_relative_path(&path) {
+                        bail!("invalid path {path:?} for {:?}", fi.relative_filename);
+                    }
+                    path
+                }
+                FileNamingStrategy::Resolve(f) => {
+                    let path = f(idx, &components(&fi.relative_filename));
+                    let valid = path.components().all(|c| match c {
+                        Component::Normal(_) => true,
+                        Component::Prefix(_) | Component::RootDir => path.is_absolute(),
+                        Component::CurDir | Component::ParentDir => false,
+                    });
+                    if !valid || path.file_name().is_none() {
+                        bail!("invalid path {path:?} for {:?}", fi.relative_filename);
+                    }
+                    path
+                }
+            };
+            if !seen.insert(output_folder.join(&path)) {
+                bail!("duplicate path {path:?} for {:?}", fi.relative_filename);
+            }
+            fi.relative_filename = path;
+        }
+        Ok(())
+    }
+}
+
+/// Override the paths of single files by file index, after [`FileNamingStrategy`] was applied.
+/// The paths are relative to the output folder.
+pub(crate) fn apply_renamed_files(
+    file_infos: &mut [FileInfo],
+    renamed_files: &BTreeMap<usize, PathBuf>,
+) -> anyhow::Result<()> {
+    for (&idx, path) in renamed_files {
+        let fi = file_infos
+            .get_mut(idx)
+            .filter(|fi| !fi.attrs.padding)
+            .with_context(|| format!("invalid file index {idx}"))?;
+        if !is_valid_relative_path(path) {
+            bail!("invalid path {path:?} for {:?}", fi.relative_filename);
+        }
+        fi.relative_filename = path.clone();
+    }
+    let mut seen = HashSet::new();
+    for fi in file_infos.iter().filter(|fi| !fi.attrs.padding) {
+        if !seen.insert(&fi.relative_filename) {
+            bail!("duplicate path {:?}", fi.relative_filename);
+        }
+    }
+    Ok(())
+}
+
 // Iterate file pieces in the following order: first, last, everything else from start to end.
 fn iter_piece_priorities(range: std::ops::Range<usize>) -> impl Iterator<Item = usize> {
     // First and last of each file first, then the rest of pieces in that file.
@@ -37,7 +177,113 @@ impl FileInfo {
 
 #[cfg(test)]
 mod tests {
-    use super::iter_piece_priorities;
+    use std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    };
+
+    use librqbit_core::torrent_metainfo::FileDetailsAttrs;
+
+    use super::{FileInfo, FileNamingStrategy, apply_renamed_files, iter_piece_priorities};
+
+    fn file_infos(names: &[&str]) -> Vec<FileInfo> {
+        names
+            .iter()
+            .map(|n| FileInfo {
+                relative_filename: n.into(),
+                offset_in_torrent: 0,
+                piece_range: 0..0,
+                attrs: FileDetailsAttrs::default(),
+                len: 0,
+            })
+            .collect()
+    }
+
+    fn names(fi: &[FileInfo]) -> Vec<PathBuf> {
+        fi.iter().map(|f| f.relative_filename.clone()).collect()
+    }
+
+    #[test]
+    fn test_file_naming_flatten() {
+        let mut fi = file_infos(&["a/x.mkv", "b/x.mkv", "b/c/x.mkv", "b/README"]);
+        FileNamingStrategy::Flatten
+            .apply(&mut fi, Path::new("/out"))
+            .unwrap();
+        assert_eq!(
+            names(&fi),
+            ["x.mkv", "x (1).mkv", "x (2).mkv", "README"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_file_naming_custom() {
+        let mut fi = file_infos(&["a/x.mkv", "a/y.mkv"]);
+        FileNamingStrategy::Custom(Box::new(|c| PathBuf::from("media").join(c.join("-"))))
+            .apply(&mut fi, Path::new("/out"))
+            .unwrap();
+        assert_eq!(
+            names(&fi),
+            ["media/a-x.mkv", "media/a-y.mkv"].map(PathBuf::from)
+        );
+
+        let mut fi = file_infos(&["a/x.mkv"]);
+        assert!(
+            FileNamingStrategy::Custom(Box::new(|_| PathBuf::from("../x.mkv")))
+                .apply(&mut fi, Path::new("/out"))
+                .is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_naming_resolve() {
+        let resolver = || {
+            FileNamingStrategy::Resolve(Box::new(|idx, c| match idx {
+                0 => PathBuf::from("/hdd").join(c.join("/")),
+                _ => c.iter().collect(),
+            }))
+        };
+        let mut fi = file_infos(&["a/x.mkv", "a/y.txt"]);
+        resolver().apply(&mut fi, Path::new("/out")).unwrap();
+        assert_eq!(names(&fi), ["/hdd/a/x.mkv", "a/y.txt"].map(PathBuf::from));
+
+        // Both end up at /hdd/a/x.mkv.
+        let mut fi = file_infos(&["a/x.mkv", "a/x.mkv"]);
+        assert!(resolver().apply(&mut fi, Path::new("/hdd")).is_err());
+
+        for bad in ["/hdd/../x.mkv", "", "/"] {
+            let mut fi = file_infos(&["a/x.mkv"]);
+            assert!(
+                FileNamingStrategy::Resolve(Box::new(move |_, _| PathBuf::from(bad)))
+                    .apply(&mut fi, Path::new("/out"))
+                    .is_err(),
+                "{bad:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_renamed_files() {
+        let renamed = |r: &[(usize, &str)]| -> BTreeMap<usize, PathBuf> {
+            r.iter().map(|(i, p)| (*i, PathBuf::from(p))).collect()
+        };
+        let mut fi = file_infos(&["a/x.mkv", "a/y.txt"]);
+        apply_renamed_files(&mut fi, &renamed(&[(0, "Movie (2001).mkv")])).unwrap();
+        assert_eq!(
+            names(&fi),
+            ["Movie (2001).mkv", "a/y.txt"].map(PathBuf::from)
+        );
+
+        for bad in [
+            renamed(&[(2, "z")]),
+            renamed(&[(0, "../x.mkv")]),
+            renamed(&[(0, "")]),
+            renamed(&[(0, "a/y.txt")]),
+        ] {
+            let mut fi = file_infos(&["a/x.mkv", "a/y.txt"]);
+            assert!(apply_renamed_files(&mut fi, &bad).is_err(), "{bad:?}");
+        }
+    }
 
     #[test]
     fn test_iter_piece_priorities() {
@@ -1,6 +1,6 @@
 use std::{
     marker::PhantomData,
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
 };
 
 use anyhow::Context;
@@ -8,23 +8,29 @@ use buffers::{ByteBuf, ByteBufOwned};
 use librqbit_core::{
     lengths::{ChunkInfo, ValidPieceIndex},
     torrent_metainfo::ValidatedTorrentMetaV1Info,
+    v2::{MerkleHasher, V2Metadata},
 };
+use parking_lot::Mutex;
 use peer_binary_protocol::{DoubleBufHelper, Piece};
 use sha1w::{ISha1, Sha1};
 use tracing::{debug, trace, warn};
 
 use crate::{
     file_info::FileInfo,
+    spawn_utils::BlockingSpawner,
     storage::TorrentStorage,
     type_aliases::{BF, FileInfos, PeerHandle},
 };
 
+// "merkle" is also updated with the data of non-padding files, to check the v2 hash of pieces of
+// hybrid torrents from the same reads.
 pub fn update_hash_from_file<Sha1: ISha1>(
     file_id: usize,
     file_info: &FileInfo,
     mut pos: u64,
     files: &dyn TorrentStorage,
     hash: &mut Sha1,
+    mut merkle: Option<&mut MerkleHasher>,
     buf: &mut [u8],
     mut bytes_to_read: usize,
 ) -> anyhow::Result<()> {
@@ -39,6 +45,9 @@ pub fn update_hash_from_file<Sha1: ISha1>(
                 .with_context(|| {
                     format!("failed reading chunk of size {chunk}, read so far {read}")
                 })?;
+            if let Some(merkle) = merkle.as_deref_mut() {
+                merkle.update(&buf[..chunk]);
+            }
         }
         bytes_to_read -= chunk;
         read += chunk;
@@ -52,6 +61,7 @@ pub(crate) struct FileOps<'a> {
     torrent: &'a ValidatedTorrentMetaV1Info<ByteBufOwned>,
     files: &'a dyn TorrentStorage,
     file_infos: &'a FileInfos,
+    v2: Option<&'a V2Metadata>,
     phantom_data: PhantomData<Sha1>,
 }
 
@@ -65,114 +75,162 @@ impl<'a> FileOps<'a> {
             torrent,
             files,
             file_infos,
+            v2: None,
             phantom_data: PhantomData,
         }
     }
 
+    // Also check pieces of hybrid torrents against their v2 merkle hashes.
+    pub fn with_v2(mut self, v2: Option<&'a V2Metadata>) -> Self {
+        self.v2 = v2;
+        self
+    }
+
     // Returns the bitvector with pieces we have.
-    pub fn initial_check(&self, progress: &AtomicU64) -> anyhow::Result<BF> {
-        let mut have_pieces =
-            BF::from_boxed_slice(vec![0u8; self.torrent.lengths().piece_bitfield_bytes()].into());
-        let mut piece_files = Vec::<usize>::new();
-
-        #[derive(Debug)]
-        struct CurrentFile<'a> {
-            index: usize,
-            fi: &'a FileInfo,
-            processed_bytes: u64,
-            is_broken: bool,
-        }
-        impl CurrentFile<'_> {
-            fn remaining(&self) -> u64 {
-                self.fi.len - self.processed_bytes
-            }
-            fn mark_processed_bytes(&mut self, bytes: u64) {
-                self.processed_bytes += bytes
-            }
-        }
-        let mut file_iterator = self
+    //
+    // Up to "concurrency" pieces are hashed at once, on threads of the spawner. Pieces are handed
+    // out to the workers in order, so the files are still read roughly sequentially.
+    pub async fn initial_check(
+        &self,
+        progress: &AtomicU64,
+        spawner: &BlockingSpawner,
+        concurrency: usize,
+    ) -> anyhow::Result<BF> {
+        let lengths = self.torrent.lengths();
+        let have_pieces = Mutex::new(BF::from_boxed_slice(
+            vec![0u8; lengths.piece_bitfield_bytes()].into(),
+        ));
+        let next_piece = AtomicU32::new(0);
+        // Once a file fails to read, don't read the rest of it.
+        let broken_files = self
             .file_infos
             .iter()
-            .enumerate()
-            .map(|(idx, fi)| CurrentFile {
-                index: idx,
-                fi,
-                processed_bytes: 0,
-                is_broken: false,
-            });
-
-        let mut current_file = file_iterator.next().context("empty input file list")?;
-
-        let mut read_buffer = vec![0u8; 65536];
-
-        for piece_info in self.torrent.lengths().iter_piece_infos() {
-            piece_files.clear();
-            let mut computed_hash = Sha1::new();
-            let mut piece_remaining = piece_info.len as usize;
-            let mut some_files_broken = false;
-            progress.fetch_add(piece_info.len as u64, Ordering::Relaxed);
-
-            while piece_remaining > 0 {
-                let mut to_read_in_file: usize =
-                    std::cmp::min(current_file.remaining(), piece_remaining as u64).try_into()?;
+            .map(|_| AtomicBool::new(false))
+            .collect::<Vec<_>>();
 
-                // Keep changing the current file to next until we find a file that has greater than 0 length.
-                while to_read_in_file == 0 {
-                    current_file = file_iterator.next().context("broken torrent metadata")?;
-
-                    to_read_in_file =
-                        std::cmp::min(current_file.remaining(), piece_remaining as u64)
-                            .try_into()?;
+        let worker = || -> anyhow::Result<()> {
+            let mut read_buffer = vec![0u8; 65536];
+            while let Some(piece) =
+                lengths.validate_piece_index(next_piece.fetch_add(1, Ordering::Relaxed))
+            {
+                let have = self.initial_check_piece(piece, &broken_files, &mut read_buffer)?;
+                progress.fetch_add(lengths.piece_length(piece) as u64, Ordering::Relaxed);
+                if have {
+                    have_pieces.lock().set(piece.get() as usize, true);
                 }
+            }
+            Ok(())
+        };
 
-                piece_files.push(current_file.index);
+        let concurrency = concurrency.clamp(1, lengths.total_pieces() as usize);
+        spawner
+            .block_in_place_concurrently(concurrency, worker)
+            .await
+            .into_iter()
+            .collect::<anyhow::Result<()>>()?;
 
-                let pos = current_file.processed_bytes;
-                piece_remaining -= to_read_in_file;
-                current_file.mark_processed_bytes(to_read_in_file as u64);
+        Ok(have_pieces.into_inner())
+    }
 
-                if current_file.is_broken {
-                    // no need to read.
-                    continue;
-                }
+    // Returns false if the piece doesn't match its hash or any of its files can't be read.
+    fn initial_check_piece(
+        &self,
+        piece: ValidPieceIndex,
+        broken_files: &[AtomicBool],
+        read_buffer: &mut [u8],
+    ) -> anyhow::Result<bool> {
+        let lengths = self.torrent.lengths();
+        let mut pos = lengths.piece_offset(piece);
+        let mut piece_remaining = lengths.piece_length(piece) as u64;
+        let first_file = self
+            .file_infos
+            .partition_point(|fi| fi.offset_in_torrent + fi.len <= pos);
+        let mut computed_hash = Sha1::new();
+        let mut merkle = self.new_merkle_hasher();
 
-                if let Err(err) = update_hash_from_file(
-                    current_file.index,
-                    current_file.fi,
-                    pos,
-                    self.files,
-                    &mut computed_hash,
-                    &mut read_buffer,
-                    to_read_in_file,
-                ) {
-                    debug!(
-                        "error reading from file {} ({:?}) at {}: {:#}",
-                        current_file.index, current_file.fi.relative_filename, pos, &err
-                    );
-                    current_file.is_broken = true;
-                    some_files_broken = true;
-                }
+        for (file_idx, fi) in self.file_infos.iter().enumerate().skip(first_file) {
+            if piece_remaining == 0 {
+                break;
             }
-
-            if some_files_broken {
-                trace!(
-                    "piece {} had errors, marking as needed",
-                    piece_info.piece_index
-                );
+            let offset_in_file = pos - fi.offset_in_torrent;
+            let to_read_in_file = (fi.len - offset_in_file).min(piece_remaining);
+            if to_read_in_file == 0 {
                 continue;
             }
-
-            if self
-                .torrent
-                .info()
-                .compare_hash(piece_info.piece_index.get(), computed_hash.finish())
-                .context("bug: either torrent info broken or we have a bug - piece index invalid")?
-            {
-                have_pieces.set(piece_info.piece_index.get() as usize, true);
+            if broken_files[file_idx].load(Ordering::Relaxed) {
+                return Ok(false);
             }
+            if let Err(err) = update_hash_from_file(
+                file_idx,
+                fi,
+                offset_in_file,
+                self.files,
+                &mut computed_hash,
+                merkle.as_mut(),
+                read_buffer,
+                to_read_in_file.try_into()?,
+            ) {
+                debug!(
+                    "error reading from file {} ({:?}) at {}: {:#}",
+                    file_idx, fi.relative_filename, offset_in_file, &err
+                );
+                broken_files[file_idx].store(true, Ordering::Relaxed);
+                trace!("piece {} had errors, marking as needed", piece);
+                return Ok(false);
+            }
+            pos += to_read_in_file;
+            piece_remaining -= to_read_in_file;
+        }
+        if piece_remaining > 0 {
+            anyhow::bail!("broken torrent metadata");
         }
 
-        Ok(have_pieces)
+        let have = self
+            .torrent
+            .info()
+            .compare_hash(piece.get(), computed_hash.finish())
+            .context("bug: either torrent info broken or we have a bug - piece index invalid")?;
+        Ok(have && self.check_piece_v2(piece, merkle)?)
+    }
+
+    fn new_merkle_hasher(&self) -> Option<MerkleHasher> {
+        self.v2.map(|_| MerkleHasher::default())
+    }
+
+    // Returns true if the torrent has no v2 metadata. "merkle" was updated with the piece's data.
+    //
+    // In hybrid torrents files are padded to piece boundaries, so all the data of a piece besides
+    // padding comes from one file.
+    fn check_piece_v2(
+        &self,
+        piece: ValidPieceIndex,
+        merkle: Option<MerkleHasher>,
+    ) -> anyhow::Result<bool> {
+        let (Some(v2), Some(merkle)) = (self.v2, merkle) else {
+            return Ok(true);
+        };
+        let pos = self.torrent.lengths().piece_offset(piece);
+        let Some((file_idx, fi)) = self.file_infos.iter().enumerate().find(|(_, fi)| {
+            !fi.attrs.padding && fi.offset_in_torrent <= pos && pos < fi.offset_in_torrent + fi.len
+        }) else {
+            return Ok(true);
+        };
+        // v2 files are the same as v1 ones, without the padding.
+        let v2_file_idx = self.file_infos[..file_idx]
+            .iter()
+            .filter(|fi| !fi.attrs.padding)
+            .count();
+        let piece_in_file = ((pos - fi.offset_in_torrent) / v2.piece_length as u64).try_into()?;
+        let ok = v2
+            .check_piece(v2_file_idx, piece_in_file, merkle)
+            .unwrap_or(false);
+        if !ok {
+            warn!(
+                piece = piece.get(),
+                file_idx, "the piece's v2 merkle hash does not match"
+            );
+        }
+        Ok(ok)
     }
 
     pub fn check_piece(&self, piece_index: ValidPieceIndex) -> anyhow::Result<bool> {
@@ -181,6 +239,7 @@ impl<'a> FileOps<'a> {
         }
 
         let mut h = Sha1::new();
+        let mut merkle = self.new_merkle_hasher();
         let piece_length = self.torrent.lengths().piece_length(piece_index);
         let mut absolute_offset = self.torrent.lengths().piece_offset(piece_index);
         let mut buf = vec![0u8; std::cmp::min(65536, piece_length as usize)];
@@ -207,6 +266,7 @@ impl<'a> FileOps<'a> {
                 absolute_offset,
                 self.files,
                 &mut h,
+                merkle.as_mut(),
                 &mut buf,
                 to_read_in_file,
             )
@@ -233,7 +293,7 @@ impl<'a> FileOps<'a> {
         {
             Some(true) => {
                 trace!("piece={} hash matches", piece_index);
-                Ok(true)
+                self.check_piece_v2(piece_index, merkle)
             }
             Some(false) => {
                 let piece_length = self.torrent.lengths().piece_length(piece_index);
@@ -258,12 +318,34 @@ impl<'a> FileOps<'a> {
         chunk_info: &ChunkInfo,
         result_buf: &mut [u8],
     ) -> anyhow::Result<()> {
-        if result_buf.len() < chunk_info.size as usize {
-            anyhow::bail!("read_chunk(): not enough capacity in the provided buffer")
-        }
-        let mut absolute_offset = self.torrent.lengths().chunk_absolute_offset(chunk_info);
-        let mut buf = result_buf;
+        let buf = result_buf
+            .get_mut(..chunk_info.size as usize)
+            .context("read_chunk(): not enough capacity in the provided buffer")?;
+        trace!(
+            "piece={}, handle={}, reading chunk {:?}",
+            chunk_info.piece_index, who_sent, &chunk_info
+        );
+        self.read_at(
+            self.torrent.lengths().chunk_absolute_offset(chunk_info),
+            buf,
+        )
+    }
+
+    /// Read the whole piece. The buffer must be at least the piece length.
+    pub fn read_piece(
+        &self,
+        piece_index: ValidPieceIndex,
+        result_buf: &mut [u8],
+    ) -> anyhow::Result<()> {
+        let lengths = self.torrent.lengths();
+        let buf = result_buf
+            .get_mut(..lengths.piece_length(piece_index) as usize)
+            .context("read_piece(): not enough capacity in the provided buffer")?;
+        self.read_at(lengths.piece_offset(piece_index), buf)
+    }
 
+    // Fill the buffer with torrent data starting at the absolute offset, possibly spanning files.
+    fn read_at(&self, mut absolute_offset: u64, mut buf: &mut [u8]) -> anyhow::Result<()> {
         for (file_idx, file_info) in self.file_infos.iter().enumerate() {
             let file_len = file_info.len;
             if absolute_offset > file_len {
@@ -274,8 +356,8 @@ impl<'a> FileOps<'a> {
             let to_read_in_file = std::cmp::min(file_remaining_len, buf.len() as u64).try_into()?;
 
             trace!(
-                "piece={}, handle={}, file_idx={}, seeking to {}. To read chunk: {:?}",
-                chunk_info.piece_index, who_sent, file_idx, absolute_offset, &chunk_info
+                "file_idx={}, seeking to {}, reading {} bytes",
+                file_idx, absolute_offset, to_read_in_file
             );
             if file_info.attrs.padding {
                 buf[..to_read_in_file].fill(0);
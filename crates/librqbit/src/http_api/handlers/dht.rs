@@ -1,7 +1,15 @@
-use axum::{extract::State, response::IntoResponse};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use bytes::Bytes;
+use dht::Id20;
+use http::StatusCode;
+use librqbit_core::hash_id::Id32;
+use serde::Deserialize;
 
 use super::ApiState;
-use crate::api::Result;
+use crate::{api::Result, api_error::WithStatusError};
 
 pub async fn h_dht_stats(State(state): State<ApiState>) -> Result<impl IntoResponse> {
     state.api.api_dht_stats().map(axum::Json)
@@ -10,3 +18,80 @@ pub async fn h_dht_stats(State(state): State<ApiState>) -> Result<impl IntoRespo
 pub async fn h_dht_table(State(state): State<ApiState>) -> Result<impl IntoResponse + 'static> {
     state.api.api_dht_table().map(axum::Json)
 }
+
+fn decode_hex(s: Option<&str>) -> Result<Bytes> {
+    let Some(s) = s else {
+        return Ok(Bytes::new());
+    };
+    hex::decode(s)
+        .map(Bytes::from)
+        .with_status_error(StatusCode::BAD_REQUEST, "invalid hex")
+}
+
+pub async fn h_dht_put_immutable(
+    State(state): State<ApiState>,
+    body: Bytes,
+) -> Result<impl IntoResponse> {
+    state.api.api_dht_put_immutable(body).await.map(axum::Json)
+}
+
+pub async fn h_dht_get_immutable(
+    State(state): State<ApiState>,
+    Path(target): Path<Id20>,
+) -> Result<impl IntoResponse> {
+    state
+        .api
+        .api_dht_get_immutable(target)
+        .await
+        .map(axum::Json)
+}
+
+#[derive(Deserialize)]
+pub struct PutMutableRequest {
+    public_key: Id32,
+    // Hex of the ed25519 signature of dht::signature_buf(salt, seq, value).
+    signature: String,
+    #[serde(default)]
+    salt: Option<String>,
+    seq: i64,
+    // Hex of the bencoded value.
+    value: String,
+    #[serde(default)]
+    cas: Option<i64>,
+}
+
+pub async fn h_dht_put_mutable(
+    State(state): State<ApiState>,
+    axum::Json(req): axum::Json<PutMutableRequest>,
+) -> Result<impl IntoResponse> {
+    let salt = decode_hex(req.salt.as_deref())?;
+    let value = decode_hex(Some(&req.value))?;
+    let signature = decode_hex(Some(&req.signature))?
+        .as_ref()
+        .try_into()
+        .with_status_error(StatusCode::BAD_REQUEST, "signature must be 64 bytes")?;
+    state
+        .api
+        .api_dht_put_mutable(req.public_key.0, signature, salt, req.seq, value, req.cas)
+        .await
+        .map(axum::Json)
+}
+
+#[derive(Deserialize)]
+pub struct GetMutableParams {
+    #[serde(default)]
+    salt: Option<String>,
+}
+
+pub async fn h_dht_get_mutable(
+    State(state): State<ApiState>,
+    Path(public_key): Path<Id32>,
+    Query(params): Query<GetMutableParams>,
+) -> Result<impl IntoResponse> {
+    let salt = decode_hex(params.salt.as_deref())?;
+    state
+        .api
+        .api_dht_get_mutable(public_key.0, salt)
+        .await
+        .map(axum::Json)
+}
@@ -41,15 +41,19 @@ async fn h_api_root(parts: Parts) -> impl IntoResponse {
             "GET /": "list all available APIs",
             "GET /dht/stats": "DHT stats",
             "GET /dht/table": "DHT routing table",
+            "GET /dht/immutable/{target}": "Get a BEP 44 immutable item from the DHT",
+            "GET /dht/mutable/{public_key}": "Get a BEP 44 mutable item from the DHT. Pass ?salt=<hex> if it was stored with a salt",
             "GET /torrents": "List torrents",
             "GET /torrents/playlist": "Generate M3U8 playlist for all files in all torrents",
             "GET /stats": "Global session stats",
+            "GET /connectivity": "Guess if peers from the internet can connect to us",
             "GET /metrics": "Prometheus metrics",
             "GET /stream_logs": "Continuously stream logs",
             "GET /web/": "Web UI",
             "GET /torrents/playlist": "Playlist for supported players",
             "GET /torrents/{id_or_infohash}": "Torrent details",
             "GET /torrents/{id_or_infohash}/metadata": "Download the corresponding torrent file",
+            "GET /torrents/{id_or_infohash}/magnet": "Magnet link with the name and all known trackers",
             "GET /torrents/{id_or_infohash}/haves": "The bitfield of have pieces",
             "GET /torrents/{id_or_infohash}/playlist": "Generate M3U8 playlist for this torrent",
             "GET /torrents/{id_or_infohash}/stats/v1": "Torrent stats",
@@ -66,6 +70,14 @@ async fn h_api_root(parts: Parts) -> impl IntoResponse {
             "POST /torrents/{id_or_infohash}/delete": "Forget about the torrent, remove the files",
             "POST /torrents/{id_or_infohash}/add_peers": "Add peers (newline-delimited)",
             "POST /torrents/{id_or_infohash}/update_only_files": "Change the selection of files to download. You need to POST json of the following form {\"only_files\": [0, 1, 2]}",
+            "POST /torrents/{id_or_infohash}/sequential": "Download pieces in order. You need to POST json of the following form {\"sequential\": true}",
+            "POST /torrents/{id_or_infohash}/super_seeding": "Offer peers one piece at a time (BEP 16), for the initial seed. You need to POST json of the following form {\"super_seeding\": true}",
+            "POST /torrents/{id_or_infohash}/recheck": "Re-verify all pieces on disk",
+            "POST /torrents/{id_or_infohash}/move_storage": "Move the torrent files to another folder. You need to POST json of the following form {\"output_folder\": \"/new/path\"}",
+            "POST /torrents/{id_or_infohash}/rename_file": "Store a file at another path inside the output folder. You need to POST json of the following form {\"file_index\": 0, \"path\": \"new/name.mkv\"}",
+            "POST /torrents/{id_or_infohash}/rename_folder": "Rename the folder the torrent files are in. You need to POST json of the following form {\"name\": \"new name\"}",
+            "POST /dht/immutable": "Store the POSTed bencoded value in the DHT as a BEP 44 immutable item",
+            "POST /dht/mutable": "Store a BEP 44 mutable item signed by the caller. You need to POST json of the following form {\"public_key\": \"<hex>\", \"signature\": \"<hex of the ed25519 signature of e.g. 4:salt6:foobar3:seqi1e1:v12:Hello World!>\", \"seq\": 1, \"value\": \"<hex of bencoded value>\", \"salt\": \"<hex, optional>\", \"cas\": 0}",
             "POST /rust_log": "Set RUST_LOG to this post launch (for debugging)",
         },
         "server": "rqbit",
@@ -82,11 +94,15 @@ pub fn make_api_router(state: ApiState) -> Router {
         .route("/rust_log", post(logging::h_set_rust_log))
         .route("/dht/stats", get(dht::h_dht_stats))
         .route("/dht/table", get(dht::h_dht_table))
+        .route("/dht/immutable/{target}", get(dht::h_dht_get_immutable))
+        .route("/dht/mutable/{public_key}", get(dht::h_dht_get_mutable))
         .route("/stats", get(torrents::h_session_stats))
+        .route("/connectivity", get(torrents::h_connectivity))
         .route("/torrents", get(torrents::h_torrents_list))
         .route("/torrents/{id}", get(torrents::h_torrent_details))
         .route("/torrents/{id}/haves", get(torrents::h_torrent_haves))
         .route("/torrents/{id}/metadata", get(torrents::h_metadata))
+        .route("/torrents/{id}/magnet", get(torrents::h_magnet_link))
         .route("/torrents/{id}/stats", get(torrents::h_torrent_stats_v0))
         .route("/torrents/{id}/stats/v1", get(torrents::h_torrent_stats_v1))
         .route("/torrents/{id}/peer_stats", get(torrents::h_peer_stats))
@@ -110,6 +126,8 @@ pub fn make_api_router(state: ApiState) -> Router {
     if !state.opts.read_only {
         api_router = api_router
             .route("/torrents", post(torrents::h_torrents_post))
+            .route("/dht/immutable", post(dht::h_dht_put_immutable))
+            .route("/dht/mutable", post(dht::h_dht_put_mutable))
             .route(
                 "/torrents/limits",
                 post(configure::h_update_session_ratelimits),
@@ -134,6 +152,30 @@ pub fn make_api_router(state: ApiState) -> Router {
                 "/torrents/{id}/update_only_files",
                 post(torrents::h_torrent_action_update_only_files),
             )
+            .route(
+                "/torrents/{id}/sequential",
+                post(torrents::h_torrent_action_set_sequential),
+            )
+            .route(
+                "/torrents/{id}/super_seeding",
+                post(torrents::h_torrent_action_set_super_seeding),
+            )
+            .route(
+                "/torrents/{id}/recheck",
+                post(torrents::h_torrent_action_recheck),
+            )
+            .route(
+                "/torrents/{id}/move_storage",
+                post(torrents::h_torrent_action_move_storage),
+            )
+            .route(
+                "/torrents/{id}/rename_file",
+                post(torrents::h_torrent_action_rename_file),
+            )
+            .route(
+                "/torrents/{id}/rename_folder",
+                post(torrents::h_torrent_action_rename_folder),
+            )
             .route("/torrents/{id}/add_peers", post(torrents::h_add_peers))
             .route("/torrents/create", post(torrents::h_create_torrent));
     }
@@ -12,10 +12,7 @@ use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
 use tracing::{debug, trace};
 
 use super::ApiState;
-use crate::{
-    WithStatus,
-    api::{Result, TorrentIdOrHash},
-};
+use crate::api::{Result, TorrentIdOrHash};
 
 #[derive(Deserialize)]
 pub struct StreamPathParams {
@@ -25,6 +22,56 @@ pub struct StreamPathParams {
     _filename: Option<Arc<str>>,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+enum RangeRequest {
+    // No (parseable) range, serve the whole file.
+    Full,
+    // Half-open [start, end) byte range.
+    Partial(u64, u64),
+    NotSatisfiable,
+}
+
+// Parses the "Range" header value. Only the first range is honored if multiple are requested.
+fn parse_range(value: Option<&str>, len: u64) -> RangeRequest {
+    let range = match value
+        .and_then(|v| v.trim().strip_prefix("bytes="))
+        .and_then(|v| v.split(',').next())
+        .and_then(|v| v.trim().split_once('-'))
+    {
+        Some(r) => r,
+        None => return RangeRequest::Full,
+    };
+
+    match range {
+        // bytes=-500 means the last 500 bytes.
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => RangeRequest::NotSatisfiable,
+            Ok(suffix) if len > 0 => RangeRequest::Partial(len.saturating_sub(suffix), len),
+            Ok(_) => RangeRequest::NotSatisfiable,
+            Err(_) => RangeRequest::Full,
+        },
+        (start, end) => {
+            let start = match start.parse::<u64>() {
+                Ok(s) => s,
+                Err(_) => return RangeRequest::Full,
+            };
+            let end = if end.is_empty() {
+                len
+            } else {
+                match end.parse::<u64>() {
+                    // The last byte position past EOF is clamped to the file length.
+                    Ok(end) => end.saturating_add(1).min(len),
+                    Err(_) => return RangeRequest::Full,
+                }
+            };
+            if start >= len || end <= start {
+                return RangeRequest::NotSatisfiable;
+            }
+            RangeRequest::Partial(start, end)
+        }
+    }
+}
+
 pub async fn h_torrent_stream_file(
     State(state): State<ApiState>,
     Path(StreamPathParams { id, file_id, .. }): Path<StreamPathParams>,
@@ -66,60 +113,85 @@ pub async fn h_torrent_stream_file(
     let range_header = headers.get(http::header::RANGE);
     debug!(torrent_id=%id, file_id=file_id, range=?range_header, "request for HTTP stream");
 
-    let range = range_header
-        .and_then(|v| v.to_str().ok())
-        .and_then(|v| v.strip_prefix("bytes="))
-        .and_then(|v| v.split_once('-'))
-        .and_then(|(start, end)| {
-            let start = start.parse::<u64>().ok()?;
-            let end = if end.is_empty() {
-                None
-            } else {
-                Some(end.parse::<u64>().ok()?.saturating_add(1))
-            };
-            Some((start, end))
-        });
-
-    let stream: Box<dyn AsyncRead + Send + Unpin> = if let Some((start, end)) = range {
-        status = StatusCode::PARTIAL_CONTENT;
-
-        if start >= stream.len() || end.is_some_and(|end| end <= start || end > stream.len()) {
-            return Err(anyhow::anyhow!("bad range"))
-                .with_status(StatusCode::RANGE_NOT_SATISFIABLE);
+    let range = parse_range(range_header.and_then(|v| v.to_str().ok()), stream.len());
+
+    // The stream itself waits for the requested pieces and prioritizes them, so the range
+    // doesn't need to be downloaded yet.
+    let stream: Box<dyn AsyncRead + Send + Unpin> = match range {
+        RangeRequest::NotSatisfiable => {
+            // Tell the client the actual length, so that it can retry with a valid range.
+            status = StatusCode::RANGE_NOT_SATISFIABLE;
+            output_headers.insert(
+                http::header::CONTENT_RANGE,
+                HeaderValue::from_maybe_shared(Bytes::from(format!("bytes */{}", stream.len())))
+                    .unwrap(),
+            );
+            output_headers.insert(http::header::CONTENT_LENGTH, HeaderValue::from_static("0"));
+            Box::new(tokio::io::empty())
+        }
+        RangeRequest::Partial(start, end) => {
+            status = StatusCode::PARTIAL_CONTENT;
+
+            stream
+                .seek(SeekFrom::Start(start))
+                .await
+                .context("error seeking")?;
+
+            let to_take = end - start;
+
+            output_headers.insert(
+                http::header::CONTENT_LENGTH,
+                HeaderValue::from_maybe_shared(Bytes::from(to_take.to_string())).unwrap(),
+            );
+            output_headers.insert(
+                http::header::CONTENT_RANGE,
+                HeaderValue::from_maybe_shared(Bytes::from(format!(
+                    "bytes {}-{}/{}",
+                    start,
+                    end.saturating_sub(1),
+                    stream.len()
+                )))
+                .unwrap(),
+            );
+            Box::new(stream.take(to_take))
+        }
+        RangeRequest::Full => {
+            output_headers.insert(
+                http::header::CONTENT_LENGTH,
+                HeaderValue::from_maybe_shared(Bytes::from(stream.len().to_string())).unwrap(),
+            );
+            Box::new(stream)
         }
-
-        let end = end.unwrap_or(stream.len());
-
-        stream
-            .seek(SeekFrom::Start(start))
-            .await
-            .context("error seeking")?;
-
-        let to_take = end - start;
-
-        output_headers.insert(
-            http::header::CONTENT_LENGTH,
-            HeaderValue::from_maybe_shared(Bytes::from(to_take.to_string())).unwrap(),
-        );
-        output_headers.insert(
-            http::header::CONTENT_RANGE,
-            HeaderValue::from_maybe_shared(Bytes::from(format!(
-                "bytes {}-{}/{}",
-                start,
-                end.saturating_sub(1),
-                stream.len()
-            )))
-            .unwrap(),
-        );
-        Box::new(stream.take(to_take))
-    } else {
-        output_headers.insert(
-            http::header::CONTENT_LENGTH,
-            HeaderValue::from_maybe_shared(Bytes::from(stream.len().to_string())).unwrap(),
-        );
-        Box::new(stream)
     };
 
     let s = tokio_util::io::ReaderStream::with_capacity(stream, 65536);
     Ok((status, (output_headers, axum::body::Body::from_stream(s))))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{RangeRequest, parse_range};
+
+    #[test]
+    fn test_parse_range() {
+        use RangeRequest::*;
+        assert_eq!(parse_range(None, 1000), Full);
+        assert_eq!(parse_range(Some("garbage"), 1000), Full);
+        assert_eq!(parse_range(Some("bytes=0-99"), 1000), Partial(0, 100));
+        assert_eq!(parse_range(Some("bytes=500-"), 1000), Partial(500, 1000));
+        assert_eq!(parse_range(Some("bytes=-100"), 1000), Partial(900, 1000));
+        assert_eq!(parse_range(Some("bytes=-5000"), 1000), Partial(0, 1000));
+        assert_eq!(
+            parse_range(Some("bytes=900-5000"), 1000),
+            Partial(900, 1000)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=0-9, 20-29"), 1000),
+            Partial(0, 10),
+            "only the first range is served"
+        );
+        assert_eq!(parse_range(Some("bytes=1000-"), 1000), NotSatisfiable);
+        assert_eq!(parse_range(Some("bytes=10-5"), 1000), NotSatisfiable);
+        assert_eq!(parse_range(Some("bytes=-0"), 1000), NotSatisfiable);
+    }
+}
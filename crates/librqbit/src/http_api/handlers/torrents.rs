@@ -1,4 +1,4 @@
-use std::{net::SocketAddr, str::FromStr};
+use std::{net::SocketAddr, path::PathBuf, str::FromStr};
 
 use anyhow::Context;
 use axum::{
@@ -248,10 +248,107 @@ pub async fn h_torrent_action_update_only_files(
         .map(axum::Json)
 }
 
+#[derive(Deserialize)]
+pub struct SetSequentialRequest {
+    sequential: bool,
+}
+
+pub async fn h_torrent_action_set_sequential(
+    State(state): State<ApiState>,
+    Path(idx): Path<TorrentIdOrHash>,
+    axum::Json(req): axum::Json<SetSequentialRequest>,
+) -> Result<impl IntoResponse> {
+    state
+        .api
+        .api_torrent_action_set_sequential(idx, req.sequential)
+        .await
+        .map(axum::Json)
+}
+
+#[derive(Deserialize)]
+pub struct SetSuperSeedingRequest {
+    super_seeding: bool,
+}
+
+pub async fn h_torrent_action_set_super_seeding(
+    State(state): State<ApiState>,
+    Path(idx): Path<TorrentIdOrHash>,
+    axum::Json(req): axum::Json<SetSuperSeedingRequest>,
+) -> Result<impl IntoResponse> {
+    state
+        .api
+        .api_torrent_action_set_super_seeding(idx, req.super_seeding)
+        .await
+        .map(axum::Json)
+}
+
+pub async fn h_torrent_action_recheck(
+    State(state): State<ApiState>,
+    Path(idx): Path<TorrentIdOrHash>,
+) -> Result<impl IntoResponse> {
+    state.api.api_torrent_action_recheck(idx).map(axum::Json)
+}
+
+#[derive(Deserialize)]
+pub struct MoveStorageRequest {
+    output_folder: PathBuf,
+}
+
+pub async fn h_torrent_action_move_storage(
+    State(state): State<ApiState>,
+    Path(idx): Path<TorrentIdOrHash>,
+    axum::Json(req): axum::Json<MoveStorageRequest>,
+) -> Result<impl IntoResponse> {
+    state
+        .api
+        .api_torrent_action_move_storage(idx, req.output_folder)
+        .await
+        .map(axum::Json)
+}
+
+#[derive(Deserialize)]
+pub struct RenameFileRequest {
+    file_index: usize,
+    path: PathBuf,
+}
+
+pub async fn h_torrent_action_rename_file(
+    State(state): State<ApiState>,
+    Path(idx): Path<TorrentIdOrHash>,
+    axum::Json(req): axum::Json<RenameFileRequest>,
+) -> Result<impl IntoResponse> {
+    state
+        .api
+        .api_torrent_action_rename_file(idx, req.file_index, req.path)
+        .await
+        .map(axum::Json)
+}
+
+#[derive(Deserialize)]
+pub struct RenameFolderRequest {
+    name: String,
+}
+
+pub async fn h_torrent_action_rename_folder(
+    State(state): State<ApiState>,
+    Path(idx): Path<TorrentIdOrHash>,
+    axum::Json(req): axum::Json<RenameFolderRequest>,
+) -> Result<impl IntoResponse> {
+    state
+        .api
+        .api_torrent_action_rename_folder(idx, &req.name)
+        .await
+        .map(axum::Json)
+}
+
 pub async fn h_session_stats(State(state): State<ApiState>) -> impl IntoResponse {
     axum::Json(state.api.api_session_stats())
 }
 
+pub async fn h_connectivity(State(state): State<ApiState>) -> impl IntoResponse {
+    axum::Json(state.api.api_connectivity().await)
+}
+
 pub async fn h_peer_stats_prometheus(
     State(state): State<ApiState>,
     Path(idx): Path<TorrentIdOrHash>,
@@ -288,6 +385,13 @@ pub async fn h_peer_stats_prometheus(
     Ok(buf)
 }
 
+pub async fn h_magnet_link(
+    State(state): State<ApiState>,
+    Path(idx): Path<TorrentIdOrHash>,
+) -> Result<impl IntoResponse> {
+    state.api.api_torrent_magnet_link(idx)
+}
+
 pub async fn h_metadata(
     State(state): State<ApiState>,
     Path(idx): Path<TorrentIdOrHash>,
@@ -360,6 +464,10 @@ pub struct HttpCreateTorrentOptions {
     #[serde(default)]
     trackers: Vec<String>,
     name: Option<String>,
+    piece_length: Option<u32>,
+    #[serde(default)]
+    private: bool,
+    comment: Option<String>,
 }
 
 pub async fn h_create_torrent(
@@ -383,7 +491,10 @@ pub async fn h_create_torrent(
     let create_opts = CreateTorrentOptions {
         name: opts.name.as_deref(),
         trackers: opts.trackers,
-        piece_length: None,
+        piece_length: opts.piece_length,
+        web_seeds: Vec::new(),
+        private: opts.private,
+        comment: opts.comment.as_deref(),
     };
 
     let (torrent, handle) = state
@@ -87,10 +87,25 @@ impl HttpApi {
     /// If read_only is passed, no state-modifying methods will be exposed.
     #[inline(never)]
     pub fn make_http_api_and_run(
-        #[allow(unused_mut)] mut self,
+        self,
         listener: TcpListener,
         upnp_router: Option<Router>,
     ) -> BoxFuture<'static, anyhow::Result<()>> {
+        let app = self
+            .into_router(upnp_router)
+            .into_make_service_with_connect_info::<librqbit_dualstack_sockets::WrappedSocketAddr>();
+
+        async move {
+            axum::serve(listener, app)
+                .await
+                .context("error running HTTP API")
+        }
+        .boxed()
+    }
+
+    /// The router with all the API endpoints, to mount into your own axum server instead of
+    /// running a separate one with [`Self::make_http_api_and_run`].
+    pub fn into_router(#[allow(unused_mut)] mut self, upnp_router: Option<Router>) -> Router {
         #[cfg(feature = "prometheus")]
         let mut prometheus_handle = self.opts.prometheus_handle.take();
 
@@ -115,6 +130,7 @@ impl HttpApi {
                 get(move || async move {
                     let mut metrics = handle.render();
                     session.stats_snapshot().as_prometheus(&mut metrics);
+                    session.torrent_metrics_prometheus(&mut metrics);
                     metrics
                 }),
             );
@@ -166,45 +182,35 @@ impl HttpApi {
             main_router = main_router.nest("/upnp", upnp_router);
         }
 
-        let app = main_router
-            .layer(cors_layer)
-            .layer(
-                tower_http::trace::TraceLayer::new_for_http()
-                    .make_span_with(|req: &Request| {
-                        let method = req.method();
-                        let uri = req.uri();
-                        if let Some(ConnectInfo(addr)) = req
-                            .extensions()
-                            .get::<ConnectInfo<librqbit_dualstack_sockets::WrappedSocketAddr>>()
-                        {
-                            debug_span!("request", %method, %uri, addr=%addr.0)
-                        } else {
-                            debug_span!("request", %method, %uri)
-                        }
-                    })
-                    .on_request(|req: &Request, _: &Span| {
-                        if req.uri().path().starts_with("/upnp") {
-                            debug!(headers=?req.headers())
-                        }
-                    })
-                    .on_response(DefaultOnResponse::new().include_headers(true))
-                    .on_failure({
-                        let mut default = DefaultOnFailure::new();
-                        move |failure_class, latency, span: &Span| match failure_class {
-                            tower_http::classify::ServerErrorsFailureClass::StatusCode(
-                                StatusCode::NOT_IMPLEMENTED,
-                            ) => {}
-                            _ => default.on_failure(failure_class, latency, span),
-                        }
-                    }),
-            )
-            .into_make_service_with_connect_info::<librqbit_dualstack_sockets::WrappedSocketAddr>();
-
-        async move {
-            axum::serve(listener, app)
-                .await
-                .context("error running HTTP API")
-        }
-        .boxed()
+        main_router.layer(cors_layer).layer(
+            tower_http::trace::TraceLayer::new_for_http()
+                .make_span_with(|req: &Request| {
+                    let method = req.method();
+                    let uri = req.uri();
+                    if let Some(ConnectInfo(addr)) = req
+                        .extensions()
+                        .get::<ConnectInfo<librqbit_dualstack_sockets::WrappedSocketAddr>>()
+                    {
+                        debug_span!("request", %method, %uri, addr=%addr.0)
+                    } else {
+                        debug_span!("request", %method, %uri)
+                    }
+                })
+                .on_request(|req: &Request, _: &Span| {
+                    if req.uri().path().starts_with("/upnp") {
+                        debug!(headers=?req.headers())
+                    }
+                })
+                .on_response(DefaultOnResponse::new().include_headers(true))
+                .on_failure({
+                    let mut default = DefaultOnFailure::new();
+                    move |failure_class, latency, span: &Span| match failure_class {
+                        tower_http::classify::ServerErrorsFailureClass::StatusCode(
+                            StatusCode::NOT_IMPLEMENTED,
+                        ) => {}
+                        _ => default.on_failure(failure_class, latency, span),
+                    }
+                }),
+        )
     }
 }
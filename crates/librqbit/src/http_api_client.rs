@@ -12,6 +12,7 @@ use crate::{
 pub struct HttpApiClient {
     client: reqwest::Client,
     base_url: reqwest::Url,
+    basic_auth: Option<(String, String)>,
 }
 
 async fn check_response(r: reqwest::Response) -> anyhow::Result<reqwest::Response> {
@@ -65,9 +66,28 @@ impl HttpApiClient {
         Ok(Self {
             base_url: reqwest::Url::parse(url)?,
             client: reqwest::ClientBuilder::new().build()?,
+            basic_auth: None,
         })
     }
 
+    /// Send these credentials with every request, for servers with HTTP basic auth enabled.
+    pub fn with_basic_auth(mut self, username: String, password: String) -> Self {
+        self.basic_auth = Some((username, password));
+        self
+    }
+
+    fn request(
+        &self,
+        method: reqwest::Method,
+        url: impl reqwest::IntoUrl,
+    ) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.basic_auth {
+            Some((username, password)) => request.basic_auth(username, Some(password)),
+            None => request,
+        }
+    }
+
     pub fn base_url(&self) -> &reqwest::Url {
         &self.base_url
     }
@@ -75,7 +95,10 @@ impl HttpApiClient {
     #[inline(never)]
     pub fn validate_rqbit_server(&self) -> BoxFuture<'_, anyhow::Result<()>> {
         async move {
-            let response = self.client.get(self.base_url.clone()).send().await?;
+            let response = self
+                .request(reqwest::Method::GET, self.base_url.clone())
+                .send()
+                .await?;
             let root: ApiRoot = json_response(response).await?;
             if root.server == "rqbit" {
                 return Ok(());
@@ -99,14 +122,14 @@ impl HttpApiClient {
                 output_folder: opts.output_folder,
                 sub_folder: opts.sub_folder,
                 list_only: Some(opts.list_only),
+                metadata_only: Some(opts.metadata_only),
                 initial_peers: opts.initial_peers.map(InitialPeers),
                 ..Default::default()
             };
             let qs = serde_urlencoded::to_string(&params).unwrap();
             let url = format!("{}torrents?{}", &self.base_url, qs);
             let response = check_response(
-                self.client
-                    .post(&url)
+                self.request(reqwest::Method::POST, &url)
                     .body(torrent.into_bytes())
                     .send()
                     .await?,
@@ -116,4 +139,19 @@ impl HttpApiClient {
         }
         .boxed()
     }
+
+    /// Remove a torrent from the server. With "delete_files", its files are deleted too.
+    pub fn delete_torrent<'a>(
+        &'a self,
+        id_or_infohash: &'a str,
+        delete_files: bool,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            let action = if delete_files { "delete" } else { "forget" };
+            let url = format!("{}torrents/{}/{}", &self.base_url, id_or_infohash, action);
+            check_response(self.request(reqwest::Method::POST, &url).send().await?).await?;
+            Ok(())
+        }
+        .boxed()
+    }
 }
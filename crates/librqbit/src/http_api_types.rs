@@ -23,6 +23,7 @@ pub struct TorrentAddQueryParams {
     // Will force interpreting the content as a URL.
     pub is_url: Option<bool>,
     pub list_only: Option<bool>,
+    pub metadata_only: Option<bool>,
 }
 
 impl Serialize for OnlyFiles {
@@ -100,6 +101,7 @@ impl TorrentAddQueryParams {
             output_folder: self.output_folder,
             sub_folder: self.sub_folder,
             list_only: self.list_only.unwrap_or(false),
+            metadata_only: self.metadata_only.unwrap_or(false),
             initial_peers: self.initial_peers.map(|i| i.0),
             peer_opts: Some(PeerConnectionOptions {
                 connect_timeout: self.peer_connect_timeout.map(Duration::from_secs),
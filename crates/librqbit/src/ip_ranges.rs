@@ -136,6 +136,20 @@ impl IpRanges {
         Ok(Self::new(v4, v6))
     }
 
+    /// Build from CIDR ranges like "10.0.0.0/8" or "2001:db8::/32". A plain IP is a single address.
+    pub fn from_cidrs<S: AsRef<str>>(cidrs: impl IntoIterator<Item = S>) -> Result<Self> {
+        let mut v4 = Vec::new();
+        let mut v6 = Vec::new();
+        for cidr in cidrs {
+            let cidr = cidr.as_ref();
+            match parse_cidr(cidr).with_context(|| format!("invalid CIDR range {cidr:?}"))? {
+                IpRange::V4(r) => v4.push(r),
+                IpRange::V6(r) => v6.push(r),
+            }
+        }
+        Ok(Self::new(v4, v6))
+    }
+
     pub fn has(&self, ip: IpAddr) -> bool {
         match ip {
             IpAddr::V4(a) => self.v4.t.query_point(a).next().is_some(),
@@ -177,6 +191,40 @@ fn parse_ip_range(line: &str) -> Option<IpRange> {
     }
 }
 
+fn parse_cidr(cidr: &str) -> Result<IpRange> {
+    let cidr = cidr.trim();
+    let (ip, prefix) = match cidr.split_once('/') {
+        Some((ip, prefix)) => (ip, Some(prefix.parse::<u32>().context("invalid prefix")?)),
+        None => (cidr, None),
+    };
+    match IpAddr::from_str(ip).context("invalid IP address")? {
+        IpAddr::V4(ip) => {
+            let prefix = prefix.unwrap_or(32);
+            if prefix > 32 {
+                anyhow::bail!("prefix too long");
+            }
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            let start = ip.to_bits() & mask;
+            let end = (start | !mask).saturating_add(1);
+            Ok(IpRange::V4(
+                Ipv4Addr::from_bits(start)..Ipv4Addr::from_bits(end),
+            ))
+        }
+        IpAddr::V6(ip) => {
+            let prefix = prefix.unwrap_or(128);
+            if prefix > 128 {
+                anyhow::bail!("prefix too long");
+            }
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            let start = ip.to_bits() & mask;
+            let end = (start | !mask).saturating_add(1);
+            Ok(IpRange::V6(
+                Ipv6Addr::from_bits(start)..Ipv6Addr::from_bits(end),
+            ))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
@@ -269,6 +317,20 @@ mod tests {
         assert!(!list.has("2001:db9::1".parse().unwrap()));
     }
 
+    #[test]
+    fn test_from_cidrs() {
+        let list = IpRanges::from_cidrs(["10.0.0.0/8", "192.168.1.5", "2001:db8::/32"]).unwrap();
+        assert!(list.has("10.1.2.3".parse().unwrap()));
+        assert!(!list.has("11.0.0.0".parse().unwrap()));
+        assert!(list.has("192.168.1.5".parse().unwrap()));
+        assert!(!list.has("192.168.1.6".parse().unwrap()));
+        assert!(list.has("2001:db8:1::1".parse().unwrap()));
+        assert!(!list.has("2001:db9::1".parse().unwrap()));
+
+        assert!(IpRanges::from_cidrs(["10.0.0.0/33"]).is_err());
+        assert!(IpRanges::from_cidrs(["not an ip"]).is_err());
+    }
+
     #[ignore]
     #[tokio::test]
     async fn test_list_real_url() {
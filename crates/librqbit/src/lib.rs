@@ -43,6 +43,7 @@ mod stat_gen;
 
 pub mod api;
 mod api_error;
+pub mod bandwidth_schedule;
 mod bitv;
 mod bitv_factory;
 mod chunk_tracker;
@@ -61,7 +62,9 @@ mod ip_ranges;
 pub mod limits;
 mod listen;
 mod merge_streams;
+mod mse;
 mod peer_connection;
+pub mod peer_filter;
 mod peer_info_reader;
 mod piece_tracker;
 mod read_buf;
@@ -72,6 +75,7 @@ pub mod spawn_utils;
 
 pub mod storage;
 mod stream_connect;
+pub mod torrent_queue;
 mod torrent_state;
 #[cfg(feature = "tracing-subscriber-utils")]
 pub mod tracing_subscriber_config_utils;
@@ -82,23 +86,31 @@ mod vectored_traits;
 #[cfg(feature = "watch")]
 pub mod watch;
 
-pub use error::{Error, Result};
+pub use error::{Error, Result, TorrentError};
 
 pub use api::Api;
 pub use api_error::{ApiError, WithStatus, WithStatusError};
 pub use create_torrent_file::{CreateTorrentOptions, CreateTorrentResult, create_torrent};
 pub use dht;
+pub use file_info::{FileNamingStrategy, FilePriority};
 pub use librqbit_core::spawn_utils::spawn as librqbit_spawn;
 pub use listen::{ListenerMode, ListenerOptions};
+pub use mse::EncryptionPolicy;
 pub use peer_connection::PeerConnectionOptions;
+pub use peer_filter::{CidrPeerFilter, PeerFilter};
 pub use session::{
-    AddTorrent, AddTorrentOptions, AddTorrentResponse, ListOnlyResponse, SUPPORTED_SCHEMES,
-    Session, SessionOptions, SessionPersistenceConfig,
+    AddTorrent, AddTorrentOptions, AddTorrentResponse, DeletedFiles, ListOnlyResponse,
+    SUPPORTED_SCHEMES, Session, SessionOptions, SessionPersistenceConfig,
 };
+pub use session_stats::ConnectivityReport;
 pub use stream_connect::ConnectionOptions;
+pub use torrent_state::events::TorrentEvent;
+pub use torrent_state::live::read_cache::ReadCacheStats;
+pub use torrent_state::metrics::TorrentMetrics;
+pub use torrent_state::peer::stats::snapshot::{ConnectedPeerStats, InflightRequest};
 pub use torrent_state::{
-    ManagedTorrent, ManagedTorrentShared, ManagedTorrentState, TorrentMetadata, TorrentStats,
-    TorrentStatsState,
+    FileReader, ManagedTorrent, ManagedTorrentShared, ManagedTorrentState, ManagedTorrentStateKind,
+    OnCompleteCallback, StopReason, TorrentMetadata, TorrentStats, TorrentStatsState,
 };
 pub use type_aliases::FileInfos;
 
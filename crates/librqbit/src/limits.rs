@@ -12,6 +12,7 @@ pub struct LimitsConfig {
     pub download_bps: Option<NonZeroU32>,
 }
 
+#[derive(Default)]
 struct Limit {
     limiter: ArcSwapOption<RateLimiter>,
     current_bps: std::sync::atomic::AtomicU32,
@@ -39,6 +40,14 @@ impl Limit {
         Ok(())
     }
 
+    #[cfg(test)]
+    fn try_acquire(&self, size: NonZeroU32) -> bool {
+        match self.limiter.load().as_ref() {
+            Some(rl) => matches!(rl.check_n(size), Ok(Ok(()))),
+            None => true,
+        }
+    }
+
     fn set(&self, limit: Option<NonZeroU32>) {
         use std::sync::atomic::Ordering;
         let new = Self::new_inner(limit);
@@ -53,6 +62,7 @@ impl Limit {
     }
 }
 
+#[derive(Default)]
 pub struct Limits {
     down: Limit,
     up: Limit,
@@ -74,6 +84,12 @@ impl Limits {
         self.down.acquire(len).await
     }
 
+    // Take "len" download tokens if they are available right away, without waiting.
+    #[cfg(test)]
+    pub(crate) fn try_acquire_download(&self, len: NonZeroU32) -> bool {
+        self.down.try_acquire(len)
+    }
+
     pub fn set_upload_bps(&self, bps: Option<NonZeroU32>) {
         self.up.set(bps);
     }
@@ -0,0 +1,78 @@
+// Diffie-Hellman key exchange over the 768-bit MSE prime (G = 2).
+
+use std::sync::LazyLock;
+
+use num_bigint::BigUint;
+use rand::RngCore;
+
+pub const KEY_LEN: usize = 96;
+const PRIVATE_KEY_LEN: usize = 20;
+
+static PRIME: LazyLock<BigUint> = LazyLock::new(|| {
+    BigUint::parse_bytes(
+        b"FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245E485B576625E7EC6F44C42E9A63A36210000000000090563",
+        16,
+    )
+    .unwrap()
+});
+
+pub struct PrivateKey(BigUint);
+
+/// Generate a random private key and the matching public key.
+pub fn generate() -> (PrivateKey, [u8; KEY_LEN]) {
+    let mut private = [0u8; PRIVATE_KEY_LEN];
+    rand::rng().fill_bytes(&mut private);
+    let private = BigUint::from_bytes_be(&private);
+    let public = to_key_bytes(&BigUint::from(2u32).modpow(&private, &PRIME));
+    (PrivateKey(private), public)
+}
+
+/// Compute the shared secret from the other side's public key. None if the key is invalid.
+pub fn shared_secret(private: &PrivateKey, public: &[u8; KEY_LEN]) -> Option<[u8; KEY_LEN]> {
+    let public = BigUint::from_bytes_be(public);
+    if public < BigUint::from(2u32) || public >= &*PRIME - 1u32 {
+        return None;
+    }
+    Some(to_key_bytes(&public.modpow(&private.0, &PRIME)))
+}
+
+// Big-endian, padded with leading zeroes.
+fn to_key_bytes(n: &BigUint) -> [u8; KEY_LEN] {
+    let bytes = n.to_bytes_be();
+    let mut key = [0u8; KEY_LEN];
+    key[KEY_LEN - bytes.len()..].copy_from_slice(&bytes);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigUint;
+
+    use super::{KEY_LEN, PRIME, generate, shared_secret, to_key_bytes};
+
+    #[test]
+    fn test_prime() {
+        assert_eq!(PRIME.bits(), 768);
+        assert_eq!(to_key_bytes(&PRIME)[KEY_LEN - 3..], [0x09, 0x05, 0x63]);
+        assert_eq!(
+            to_key_bytes(&BigUint::from(2u32))[..KEY_LEN - 1],
+            [0; KEY_LEN - 1]
+        );
+    }
+
+    #[test]
+    fn test_key_exchange() {
+        let (a_private, a_public) = generate();
+        let (b_private, b_public) = generate();
+        assert_ne!(a_public, b_public);
+        let a = shared_secret(&a_private, &b_public).unwrap();
+        let b = shared_secret(&b_private, &a_public).unwrap();
+        assert_eq!(a, b);
+
+        assert!(shared_secret(&a_private, &[0; KEY_LEN]).is_none());
+        assert!(shared_secret(&a_private, &[0xff; KEY_LEN]).is_none());
+        let mut one = [0; KEY_LEN];
+        one[KEY_LEN - 1] = 1;
+        assert!(shared_secret(&a_private, &one).is_none());
+    }
+}
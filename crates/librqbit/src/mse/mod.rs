@@ -0,0 +1,570 @@
+//! Message Stream Encryption (MSE/PE): the handshake that obfuscates peer connections with RC4
+//! so that they can't be recognized by the plaintext BitTorrent handshake.
+//!
+//! See <https://wiki.vuze.com/w/Message_Stream_Encryption>.
+
+mod dh;
+
+use std::{io::IoSliceMut, pin::Pin, task::Poll};
+
+use anyhow::{Context, bail};
+use librqbit_core::hash_id::Id20;
+use rand::Rng;
+use rc4::{KeyInit, StreamCipher, consts::U20};
+use serde::{Deserialize, Serialize};
+use sha1w::{ISha1, Sha1};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    type_aliases::{BoxAsyncReadVectored, BoxAsyncWrite},
+    vectored_traits::AsyncReadVectored,
+};
+
+use dh::KEY_LEN;
+
+// MSE keys are SHA-1 hashes.
+type Rc4 = rc4::Rc4<U20>;
+
+/// Whether peer connections are encrypted with MSE.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncryptionPolicy {
+    /// Plaintext connections only.
+    #[default]
+    Disabled,
+    /// Encrypt outgoing connections, falling back to plaintext for peers that don't support
+    /// it. Incoming connections may be either.
+    Prefer,
+    /// Only encrypted connections, both outgoing and incoming.
+    Require,
+}
+
+impl EncryptionPolicy {
+    fn crypto_provide(self) -> u32 {
+        match self {
+            EncryptionPolicy::Require => CRYPTO_RC4,
+            _ => CRYPTO_RC4 | CRYPTO_PLAINTEXT,
+        }
+    }
+
+    fn crypto_select(self, provide: u32) -> Option<u32> {
+        if provide & CRYPTO_RC4 != 0 {
+            Some(CRYPTO_RC4)
+        } else if provide & CRYPTO_PLAINTEXT != 0 && self != EncryptionPolicy::Require {
+            Some(CRYPTO_PLAINTEXT)
+        } else {
+            None
+        }
+    }
+}
+
+const CRYPTO_PLAINTEXT: u32 = 0x01;
+const CRYPTO_RC4: u32 = 0x02;
+const VC: [u8; 8] = [0; 8];
+const MAX_PAD_LEN: usize = 512;
+const BT_HANDSHAKE_PREFIX: &[u8; 20] = b"\x13BitTorrent protocol";
+// The most we encrypt per write.
+const MAX_WRITE: usize = 65536;
+
+fn hash(parts: &[&[u8]]) -> [u8; 20] {
+    let mut h = Sha1::new();
+    for part in parts {
+        h.update(part);
+    }
+    h.finish()
+}
+
+fn xor(a: [u8; 20], b: [u8; 20]) -> [u8; 20] {
+    std::array::from_fn(|i| a[i] ^ b[i])
+}
+
+// RC4 keyed for one direction. The first 1024 bytes of the keystream are discarded.
+fn cipher(name: &[u8], secret: &[u8; KEY_LEN], skey: &Id20) -> Rc4 {
+    let mut rc4 = Rc4::new(&hash(&[name, secret, &skey.0]).into());
+    rc4.apply_keystream(&mut [0; 1024]);
+    rc4
+}
+
+// The public key followed by random padding.
+fn public_key_message(public: &[u8; KEY_LEN]) -> Vec<u8> {
+    let mut rng = rand::rng();
+    let mut msg = public.to_vec();
+    msg.resize(KEY_LEN + rng.random_range(0..=MAX_PAD_LEN), 0);
+    rng.fill(&mut msg[KEY_LEN..]);
+    msg
+}
+
+// Reads the handshake from the stream, keeping what was read past it.
+struct HandshakeReader<'a> {
+    read: &'a mut BoxAsyncReadVectored,
+    buf: Vec<u8>,
+}
+
+impl HandshakeReader<'_> {
+    async fn read_more(&mut self) -> anyhow::Result<()> {
+        let mut tmp = [0u8; 1024];
+        let len = self
+            .read
+            .read(&mut tmp)
+            .await
+            .context("error reading MSE handshake")?;
+        if len == 0 {
+            bail!("peer disconnected during MSE handshake");
+        }
+        self.buf.extend_from_slice(&tmp[..len]);
+        Ok(())
+    }
+
+    async fn take(&mut self, len: usize) -> anyhow::Result<Vec<u8>> {
+        while self.buf.len() < len {
+            self.read_more().await?;
+        }
+        Ok(self.buf.drain(..len).collect())
+    }
+
+    async fn take_decrypted(&mut self, len: usize, rc4: &mut Rc4) -> anyhow::Result<Vec<u8>> {
+        let mut buf = self.take(len).await?;
+        rc4.apply_keystream(&mut buf);
+        Ok(buf)
+    }
+
+    // Skip up to max_skip bytes of padding until right after the pattern.
+    async fn sync(&mut self, pattern: &[u8], max_skip: usize) -> anyhow::Result<()> {
+        loop {
+            if let Some(pos) = self
+                .buf
+                .windows(pattern.len())
+                .take(max_skip + 1)
+                .position(|w| w == pattern)
+            {
+                self.buf.drain(..pos + pattern.len());
+                return Ok(());
+            }
+            if self.buf.len() >= max_skip + pattern.len() {
+                bail!("couldn't find the MSE synchronization point");
+            }
+            self.read_more().await?;
+        }
+    }
+}
+
+fn read_u16(b: &[u8]) -> usize {
+    usize::from(u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn wrap(
+    read: BoxAsyncReadVectored,
+    write: BoxAsyncWrite,
+    mut prefix: Vec<u8>,
+    ciphers: Option<(Rc4, Rc4)>,
+) -> (BoxAsyncReadVectored, BoxAsyncWrite) {
+    let (read_cipher, write_cipher) = match ciphers {
+        Some((mut dec, enc)) => {
+            dec.apply_keystream(&mut prefix);
+            (Some(dec), Some(enc))
+        }
+        None => (None, None),
+    };
+    (
+        Box::new(MseRead {
+            inner: read,
+            prefix,
+            prefix_pos: 0,
+            cipher: read_cipher,
+        }),
+        Box::new(MseWrite {
+            inner: write,
+            cipher: write_cipher,
+            buf: Vec::new(),
+            written: 0,
+        }),
+    )
+}
+
+/// Run the MSE handshake on an outgoing connection.
+pub(crate) async fn initiate(
+    mut read: BoxAsyncReadVectored,
+    mut write: BoxAsyncWrite,
+    info_hash: Id20,
+    policy: EncryptionPolicy,
+) -> anyhow::Result<(BoxAsyncReadVectored, BoxAsyncWrite)> {
+    let (private, public) = dh::generate();
+    write
+        .write_all(&public_key_message(&public))
+        .await
+        .context("error writing MSE handshake")?;
+
+    let mut r = HandshakeReader {
+        read: &mut read,
+        buf: Vec::new(),
+    };
+    let their_public = r.take(KEY_LEN).await?;
+    let secret = dh::shared_secret(&private, their_public.as_slice().try_into()?)
+        .context("peer sent an invalid MSE public key")?;
+    let mut enc = cipher(b"keyA", &secret, &info_hash);
+    let mut dec = cipher(b"keyB", &secret, &info_hash);
+
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&hash(&[b"req1", &secret]));
+    msg.extend_from_slice(&xor(
+        hash(&[b"req2", &info_hash.0]),
+        hash(&[b"req3", &secret]),
+    ));
+    let encrypted_start = msg.len();
+    msg.extend_from_slice(&VC);
+    msg.extend_from_slice(&policy.crypto_provide().to_be_bytes());
+    // No padding and no initial payload, the BitTorrent handshake is sent afterwards.
+    msg.extend_from_slice(&0u16.to_be_bytes());
+    msg.extend_from_slice(&0u16.to_be_bytes());
+    enc.apply_keystream(&mut msg[encrypted_start..]);
+    write
+        .write_all(&msg)
+        .await
+        .context("error writing MSE handshake")?;
+
+    let mut vc = VC;
+    dec.apply_keystream(&mut vc);
+    r.sync(&vc, MAX_PAD_LEN).await?;
+    let b = r.take_decrypted(6, &mut dec).await?;
+    let crypto_select = read_u32(&b);
+    let pad_len = read_u16(&b[4..]);
+    if pad_len > MAX_PAD_LEN {
+        bail!("MSE padding too long: {pad_len}");
+    }
+    r.take_decrypted(pad_len, &mut dec).await?;
+
+    let prefix = std::mem::take(&mut r.buf);
+    let ciphers = match crypto_select {
+        CRYPTO_RC4 => Some((dec, enc)),
+        CRYPTO_PLAINTEXT if policy != EncryptionPolicy::Require => None,
+        other => bail!("peer selected unsupported MSE crypto method {other:#x}"),
+    };
+    Ok(wrap(read, write, prefix, ciphers))
+}
+
+/// Handle the start of an incoming connection, that may be either a plaintext BitTorrent
+/// handshake or an MSE one. `info_hashes` are the torrents the peer may connect for.
+pub(crate) async fn accept(
+    mut read: BoxAsyncReadVectored,
+    mut write: BoxAsyncWrite,
+    policy: EncryptionPolicy,
+    info_hashes: impl FnOnce() -> Vec<Id20>,
+) -> anyhow::Result<(BoxAsyncReadVectored, BoxAsyncWrite)> {
+    let mut r = HandshakeReader {
+        read: &mut read,
+        buf: Vec::new(),
+    };
+    while r.buf.len() < BT_HANDSHAKE_PREFIX.len() {
+        r.read_more().await?;
+    }
+    if r.buf.starts_with(BT_HANDSHAKE_PREFIX) {
+        if policy == EncryptionPolicy::Require {
+            bail!("plaintext connection while encryption is required");
+        }
+        let prefix = std::mem::take(&mut r.buf);
+        return Ok(wrap(read, write, prefix, None));
+    }
+
+    let their_public = r.take(KEY_LEN).await?;
+    let (private, public) = dh::generate();
+    write
+        .write_all(&public_key_message(&public))
+        .await
+        .context("error writing MSE handshake")?;
+    let secret = dh::shared_secret(&private, their_public.as_slice().try_into()?)
+        .context("peer sent an invalid MSE public key")?;
+
+    r.sync(&hash(&[b"req1", &secret]), MAX_PAD_LEN).await?;
+    let skey_hash = xor(
+        r.take(20).await?.as_slice().try_into()?,
+        hash(&[b"req3", &secret]),
+    );
+    let info_hash = info_hashes()
+        .into_iter()
+        .find(|ih| hash(&[b"req2", &ih.0]) == skey_hash)
+        .context("no torrent matches the MSE handshake")?;
+    let mut dec = cipher(b"keyA", &secret, &info_hash);
+    let mut enc = cipher(b"keyB", &secret, &info_hash);
+
+    let b = r.take_decrypted(14, &mut dec).await?;
+    if b[..8] != VC {
+        bail!("bad MSE verification constant");
+    }
+    let crypto_provide = read_u32(&b[8..]);
+    let pad_len = read_u16(&b[12..]);
+    if pad_len > MAX_PAD_LEN {
+        bail!("MSE padding too long: {pad_len}");
+    }
+    r.take_decrypted(pad_len, &mut dec).await?;
+    let ia_len = read_u16(&r.take_decrypted(2, &mut dec).await?);
+    let mut prefix = r.take_decrypted(ia_len, &mut dec).await?;
+
+    let crypto_select = policy
+        .crypto_select(crypto_provide)
+        .with_context(|| format!("no supported MSE crypto method in {crypto_provide:#x}"))?;
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&VC);
+    msg.extend_from_slice(&crypto_select.to_be_bytes());
+    msg.extend_from_slice(&0u16.to_be_bytes());
+    enc.apply_keystream(&mut msg);
+    write
+        .write_all(&msg)
+        .await
+        .context("error writing MSE handshake")?;
+
+    // The initial payload is always encrypted, what follows only with RC4 selected.
+    let rest = std::mem::take(&mut r.buf);
+    Ok(if crypto_select == CRYPTO_RC4 {
+        let (read, write) = wrap(read, write, rest, Some((dec, enc)));
+        prefix_reader(read, prefix, write)
+    } else {
+        prefix.extend_from_slice(&rest);
+        wrap(read, write, prefix, None)
+    })
+}
+
+fn prefix_reader(
+    read: BoxAsyncReadVectored,
+    prefix: Vec<u8>,
+    write: BoxAsyncWrite,
+) -> (BoxAsyncReadVectored, BoxAsyncWrite) {
+    if prefix.is_empty() {
+        return (read, write);
+    }
+    (
+        Box::new(MseRead {
+            inner: read,
+            prefix,
+            prefix_pos: 0,
+            cipher: None,
+        }),
+        write,
+    )
+}
+
+// Yields the bytes already read during the handshake first, then decrypts what's read from
+// the connection.
+struct MseRead {
+    inner: BoxAsyncReadVectored,
+    prefix: Vec<u8>,
+    prefix_pos: usize,
+    cipher: Option<Rc4>,
+}
+
+impl MseRead {
+    fn take_prefix(&mut self, buf: &mut [u8]) -> usize {
+        let rest = &self.prefix[self.prefix_pos..];
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.prefix_pos += len;
+        len
+    }
+}
+
+impl AsyncRead for MseRead {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if this.prefix_pos < this.prefix.len() {
+            let len = this.take_prefix(buf.initialize_unfilled());
+            buf.advance(len);
+            return Poll::Ready(Ok(()));
+        }
+        let start = buf.filled().len();
+        std::task::ready!(Pin::new(&mut this.inner).poll_read(cx, buf)?);
+        if let Some(cipher) = this.cipher.as_mut() {
+            cipher.apply_keystream(&mut buf.filled_mut()[start..]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncReadVectored for MseRead {
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        vec: &mut [IoSliceMut<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        if this.prefix_pos < this.prefix.len() {
+            let mut len = 0;
+            for slice in vec.iter_mut() {
+                len += this.take_prefix(slice);
+            }
+            return Poll::Ready(Ok(len));
+        }
+        let len = std::task::ready!(Pin::new(&mut this.inner).poll_read_vectored(cx, vec)?);
+        if let Some(cipher) = this.cipher.as_mut() {
+            let mut remaining = len;
+            for slice in vec.iter_mut() {
+                let n = remaining.min(slice.len());
+                cipher.apply_keystream(&mut slice[..n]);
+                remaining -= n;
+            }
+        }
+        Poll::Ready(Ok(len))
+    }
+}
+
+struct MseWrite {
+    inner: BoxAsyncWrite,
+    cipher: Option<Rc4>,
+    // Encrypted bytes and how many of them the inner writer took. The cipher already moved past
+    // them, so they must be sent as they are before anything else is encrypted.
+    buf: Vec<u8>,
+    written: usize,
+}
+
+impl AsyncWrite for MseWrite {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let Some(cipher) = this.cipher.as_mut() else {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        };
+        if this.buf.is_empty() {
+            let len = buf.len().min(MAX_WRITE);
+            this.buf.extend_from_slice(&buf[..len]);
+            cipher.apply_keystream(&mut this.buf);
+        }
+        // After Pending, the write is retried with the same data. So it's only reported as
+        // written once all of it was passed on.
+        while this.written < this.buf.len() {
+            let n = std::task::ready!(
+                Pin::new(&mut this.inner).poll_write(cx, &this.buf[this.written..])?
+            );
+            if n == 0 {
+                return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+            }
+            this.written += n;
+        }
+        let len = this.buf.len();
+        debug_assert!(len <= buf.len());
+        this.buf.clear();
+        this.written = 0;
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use librqbit_core::hash_id::Id20;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{BT_HANDSHAKE_PREFIX, EncryptionPolicy, accept, initiate};
+    use crate::{
+        type_aliases::{BoxAsyncReadVectored, BoxAsyncWrite},
+        vectored_traits::AsyncReadVectoredIntoCompat,
+    };
+
+    type Conn = (BoxAsyncReadVectored, BoxAsyncWrite);
+
+    fn pipe() -> (Conn, Conn) {
+        let (a, b) = tokio::io::duplex(4096);
+        let (ar, aw) = tokio::io::split(a);
+        let (br, bw) = tokio::io::split(b);
+        (
+            (Box::new(ar.into_vectored_compat()), Box::new(aw)),
+            (Box::new(br.into_vectored_compat()), Box::new(bw)),
+        )
+    }
+
+    async fn roundtrip(a: Conn, b: Conn) {
+        let ((mut ar, mut aw), (mut br, mut bw)) = (a, b);
+        let data = (0..100_000u32)
+            .map(|i| i.to_le_bytes()[0] ^ i.to_le_bytes()[1])
+            .collect::<Vec<u8>>();
+        let send = async {
+            aw.write_all(&data).await.unwrap();
+            bw.write_all(&data[..100]).await.unwrap();
+        };
+        let recv = async {
+            let mut buf = vec![0u8; data.len()];
+            br.read_exact(&mut buf).await.unwrap();
+            assert!(buf == data);
+            let mut buf = vec![0u8; 100];
+            ar.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, data[..100]);
+        };
+        tokio::join!(send, recv);
+    }
+
+    async fn handshake(
+        initiator: EncryptionPolicy,
+        responder: EncryptionPolicy,
+        info_hash: Id20,
+    ) -> anyhow::Result<(Conn, Conn)> {
+        let ((ar, aw), (br, bw)) = pipe();
+        let (a, b) = tokio::join!(
+            initiate(ar, aw, Id20::new([1; 20]), initiator),
+            accept(br, bw, responder, || vec![Id20::new([2; 20]), info_hash])
+        );
+        Ok((a?, b?))
+    }
+
+    #[tokio::test]
+    async fn test_mse_handshake() {
+        for (initiator, responder) in [
+            (EncryptionPolicy::Prefer, EncryptionPolicy::Prefer),
+            (EncryptionPolicy::Prefer, EncryptionPolicy::Require),
+            (EncryptionPolicy::Require, EncryptionPolicy::Prefer),
+        ] {
+            let (a, b) = handshake(initiator, responder, Id20::new([1; 20]))
+                .await
+                .unwrap();
+            roundtrip(a, b).await;
+        }
+        assert!(
+            handshake(
+                EncryptionPolicy::Prefer,
+                EncryptionPolicy::Prefer,
+                Id20::new([3; 20])
+            )
+            .await
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mse_accept_plaintext() {
+        for policy in [EncryptionPolicy::Prefer, EncryptionPolicy::Require] {
+            let ((_, mut aw), (br, bw)) = pipe();
+            let mut handshake = BT_HANDSHAKE_PREFIX.to_vec();
+            handshake.extend_from_slice(&[7; 48]);
+            aw.write_all(&handshake).await.unwrap();
+            match accept(br, bw, policy, Vec::new).await {
+                Ok((mut br, _)) => {
+                    assert_eq!(policy, EncryptionPolicy::Prefer);
+                    let mut buf = vec![0u8; handshake.len()];
+                    br.read_exact(&mut buf).await.unwrap();
+                    assert_eq!(buf, handshake);
+                }
+                Err(_) => assert_eq!(policy, EncryptionPolicy::Require),
+            }
+        }
+    }
+}
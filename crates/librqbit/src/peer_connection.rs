@@ -4,7 +4,10 @@ use std::{
     time::{Duration, Instant},
 };
 
-use crate::{Error, Result, session::CheckedIncomingConnection, stream_connect::ConnectionKind};
+use crate::{
+    EncryptionPolicy, Error, Result, mse, session::CheckedIncomingConnection,
+    stream_connect::ConnectionKind,
+};
 use buffers::{ByteBuf, ByteBufOwned};
 use futures::TryFutureExt;
 use librqbit_core::{
@@ -36,6 +39,9 @@ use crate::{
 pub trait PeerConnectionHandler {
     fn on_connected(&self, _connection_time: Duration) {}
     fn should_send_bitfield(&self) -> bool;
+    fn should_send_initial_unchoke(&self) -> bool {
+        true
+    }
     fn serialize_bitfield_message_to_buf(&self, buf: &mut [u8]) -> anyhow::Result<usize>;
     fn on_handshake(&self, handshake: Handshake, ckind: ConnectionKind) -> anyhow::Result<()>;
     fn on_extended_handshake(
@@ -109,6 +115,7 @@ pub(crate) async fn with_timeout<T>(
 
 struct ManagePeerArgs {
     handshake_supports_extended: bool,
+    handshake_supports_fast: bool,
     read_buf: ReadBuf,
     write_buf: Box<[u8; MAX_MSG_LEN]>,
     read: BoxAsyncReadVectored,
@@ -180,6 +187,7 @@ impl<H: PeerConnectionHandler> PeerConnection<H> {
         .await?;
 
         let handshake_supports_extended = handshake.supports_extended();
+        let handshake_supports_fast = handshake.supports_fast();
 
         self.handler
             .on_handshake(handshake, incoming.kind)
@@ -187,6 +195,7 @@ impl<H: PeerConnectionHandler> PeerConnection<H> {
 
         self.manage_peer(ManagePeerArgs {
             handshake_supports_extended,
+            handshake_supports_fast,
             read_buf: incoming.read_buf,
             write_buf,
             read: incoming.reader,
@@ -214,13 +223,38 @@ impl<H: PeerConnectionHandler> PeerConnection<H> {
             .unwrap_or_else(|| Duration::from_secs(10));
 
         let now = Instant::now();
-        let (ckind, mut read, mut write) = with_timeout(
+        let (mut ckind, read, write) = with_timeout(
             "connecting",
             connect_timeout,
             self.connector.connect(self.addr),
         )
         .await?;
 
+        let (mut read, mut write) = match self.connector.encryption() {
+            EncryptionPolicy::Disabled => (read, write),
+            policy => match with_timeout(
+                "MSE handshake",
+                rwtimeout,
+                mse::initiate(read, write, self.info_hash, policy).map_err(Error::Anyhow),
+            )
+            .await
+            {
+                Ok(rw) => rw,
+                Err(e) if policy == EncryptionPolicy::Prefer => {
+                    debug!("MSE handshake failed, reconnecting without encryption: {e:#}");
+                    let (kind, read, write) = with_timeout(
+                        "connecting",
+                        connect_timeout,
+                        self.connector.connect(self.addr),
+                    )
+                    .await?;
+                    ckind = kind;
+                    (read, write)
+                }
+                Err(e) => return Err(e),
+            },
+        };
+
         async move {
             self.handler.on_connected(now.elapsed());
 
@@ -239,6 +273,7 @@ impl<H: PeerConnectionHandler> PeerConnection<H> {
             let mut read_buf = ReadBuf::new();
             let h = read_buf.read_handshake(&mut read, rwtimeout).await?;
             let handshake_supports_extended = h.supports_extended();
+            let handshake_supports_fast = h.supports_fast();
             trace!(
                 peer_id=?h.peer_id,
                 decoded_id=?try_decode_peer_id(h.peer_id),
@@ -256,6 +291,7 @@ impl<H: PeerConnectionHandler> PeerConnection<H> {
 
             self.manage_peer(ManagePeerArgs {
                 handshake_supports_extended,
+                handshake_supports_fast,
                 read_buf,
                 write_buf,
                 read,
@@ -272,6 +308,7 @@ impl<H: PeerConnectionHandler> PeerConnection<H> {
     async fn manage_peer(&self, args: ManagePeerArgs) -> Result<()> {
         let ManagePeerArgs {
             handshake_supports_extended,
+            handshake_supports_fast,
             mut read_buf,
             mut write_buf,
             mut read,
@@ -327,16 +364,28 @@ impl<H: PeerConnectionHandler> PeerConnection<H> {
                 )
                 .await?;
                 trace!("sent bitfield");
+            } else if handshake_supports_fast {
+                // With the fast extension one of bitfield, have all or have none must be sent.
+                let len = Message::HaveNone.serialize(&mut *write_buf, &Default::default)?;
+                with_timeout(
+                    "writing have none",
+                    rwtimeout,
+                    write.write_all(&write_buf[..len]).map_err(Error::Write),
+                )
+                .await?;
+                trace!("sent have none");
             }
 
-            let len = Message::Unchoke.serialize(&mut *write_buf, &Default::default)?;
-            with_timeout(
-                "writing",
-                rwtimeout,
-                write.write_all(&write_buf[..len]).map_err(Error::Write),
-            )
-            .await?;
-            trace!("sent unchoke");
+            if self.handler.should_send_initial_unchoke() {
+                let len = Message::Unchoke.serialize(&mut *write_buf, &Default::default)?;
+                with_timeout(
+                    "writing",
+                    rwtimeout,
+                    write.write_all(&write_buf[..len]).map_err(Error::Write),
+                )
+                .await?;
+                trace!("sent unchoke");
+            }
 
             let mut broadcast_closed = false;
 
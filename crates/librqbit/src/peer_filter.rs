@@ -0,0 +1,81 @@
+use std::net::SocketAddr;
+
+use crate::ip_ranges::IpRanges;
+
+/// Decides which peers a torrent may talk to. Consulted for peers discovered through
+/// any source (trackers, DHT, PEX, initial peers) and for incoming connections.
+pub trait PeerFilter: Send + Sync {
+    fn allow(&self, addr: &SocketAddr) -> bool;
+}
+
+/// A [`PeerFilter`] built from lists of CIDR ranges.
+pub struct CidrPeerFilter {
+    block: IpRanges,
+    allow: Option<IpRanges>,
+}
+
+impl CidrPeerFilter {
+    /// Peers in `block` are rejected. If `allow` is set, only peers in it are accepted.
+    ///
+    /// Ranges look like "10.0.0.0/8", "2001:db8::/32" or a single IP.
+    pub fn new<S: AsRef<str>>(
+        block: impl IntoIterator<Item = S>,
+        allow: Option<impl IntoIterator<Item = S>>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            block: IpRanges::from_cidrs(block)?,
+            allow: allow.map(IpRanges::from_cidrs).transpose()?,
+        })
+    }
+}
+
+impl PeerFilter for CidrPeerFilter {
+    fn allow(&self, addr: &SocketAddr) -> bool {
+        let ip = addr.ip().to_canonical();
+        !self.block.has(ip) && self.allow.as_ref().is_none_or(|a| a.has(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CidrPeerFilter, PeerFilter};
+
+    fn allowed(f: &CidrPeerFilter, ip: &str) -> bool {
+        f.allow(&(ip.parse::<std::net::IpAddr>().unwrap(), 6881).into())
+    }
+
+    #[test]
+    fn test_cidr_block() {
+        let f = CidrPeerFilter::new(["10.0.0.0/8", "2001:db8::/32"], None::<[&str; 0]>).unwrap();
+        assert!(!allowed(&f, "10.1.2.3"));
+        assert!(allowed(&f, "11.0.0.1"));
+        assert!(!allowed(&f, "2001:db8::1"));
+        assert!(allowed(&f, "2001:db9::1"));
+        // IPv4-mapped addresses are matched as IPv4.
+        assert!(!allowed(&f, "::ffff:10.1.2.3"));
+        assert!(allowed(&f, "::ffff:11.0.0.1"));
+    }
+
+    #[test]
+    fn test_cidr_allow() {
+        let f = CidrPeerFilter::new(
+            ["192.168.1.5", "fd00::1"],
+            Some(["192.168.0.0/16", "fd00::/8"]),
+        )
+        .unwrap();
+        assert!(allowed(&f, "192.168.2.1"));
+        assert!(!allowed(&f, "8.8.8.8"));
+        assert!(allowed(&f, "fd12::1"));
+        assert!(!allowed(&f, "2001:db8::1"));
+        assert!(allowed(&f, "::ffff:192.168.2.1"));
+        // Blocking wins over allowing.
+        assert!(!allowed(&f, "192.168.1.5"));
+        assert!(!allowed(&f, "fd00::1"));
+    }
+
+    #[test]
+    fn test_cidr_invalid() {
+        assert!(CidrPeerFilter::new(["10.0.0.0/33"], None::<[&str; 0]>).is_err());
+        assert!(CidrPeerFilter::new(["10.0.0.0/8"], Some(["fd00::/129"])).is_err());
+    }
+}
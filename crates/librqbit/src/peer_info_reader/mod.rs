@@ -1,4 +1,4 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 
 use bencode::from_bytes;
 use buffers::{ByteBuf, ByteBufOwned};
@@ -37,6 +37,7 @@ pub(crate) async fn read_metainfo_from_peer(
     peer_connection_options: Option<PeerConnectionOptions>,
     spawner: BlockingSpawner,
     connector: Arc<StreamConnector>,
+    progress: Arc<MetadataFetchProgress>,
 ) -> anyhow::Result<TorrentAndInfoBytes> {
     let (result_tx, result_rx) = tokio::sync::oneshot::channel::<
         Result<(TorrentMetaV1Info<ByteBufOwned>, ByteBufOwned), bencode::DeserializeError>,
@@ -48,6 +49,7 @@ pub(crate) async fn read_metainfo_from_peer(
         writer_tx,
         result_tx: Mutex::new(Some(result_tx)),
         locked: RwLock::new(None),
+        progress,
     };
     let connection = PeerConnection::new(
         addr,
@@ -72,6 +74,67 @@ pub(crate) async fn read_metainfo_from_peer(
     }
 }
 
+struct PeerFetchProgress {
+    metadata_size: u32,
+    received_bytes: u32,
+}
+
+// Progress of fetching the metadata of one torrent from all the peers asked for it.
+//
+// Every peer downloads the whole metadata on its own, so the progress is that of the peer
+// furthest along. Peers may disagree on the metadata size, and only one size can be right: only
+// peers reporting the size most peers agree on are counted.
+#[derive(Default)]
+pub(crate) struct MetadataFetchProgress {
+    peers: Mutex<HashMap<SocketAddr, PeerFetchProgress>>,
+}
+
+impl MetadataFetchProgress {
+    fn on_metadata_size(&self, addr: SocketAddr, metadata_size: u32) {
+        self.peers.lock().insert(
+            addr,
+            PeerFetchProgress {
+                metadata_size,
+                received_bytes: 0,
+            },
+        );
+    }
+
+    fn on_piece_received(&self, addr: SocketAddr, len: u32) {
+        if let Some(p) = self.peers.lock().get_mut(&addr) {
+            p.received_bytes += len;
+        }
+    }
+
+    pub(crate) fn on_peer_failed(&self, addr: SocketAddr) {
+        self.peers.lock().remove(&addr);
+    }
+
+    /// Received and total metadata bytes. Both are 0 until a peer tells the metadata size.
+    pub(crate) fn get(&self) -> (u64, u64) {
+        let peers = self.peers.lock();
+        let mut votes = HashMap::<u32, usize>::new();
+        for p in peers.values() {
+            *votes.entry(p.metadata_size).or_default() += 1;
+        }
+        // On a tie, prefer the smaller size so that the result doesn't depend on map order.
+        let Some(size) = votes
+            .into_iter()
+            .max_by_key(|(size, count)| (*count, std::cmp::Reverse(*size)))
+            .map(|(size, _)| size)
+        else {
+            return (0, 0);
+        };
+        let received = peers
+            .values()
+            .filter(|p| p.metadata_size == size)
+            .map(|p| p.received_bytes)
+            .max()
+            .unwrap_or_default();
+        (received.into(), size.into())
+    }
+}
+
 #[derive(Default)]
 struct HandlerLocked {
     metadata_size: u32,
@@ -157,6 +220,7 @@ struct Handler {
         >,
     >,
     locked: RwLock<Option<HandlerLocked>>,
+    progress: Arc<MetadataFetchProgress>,
 }
 
 impl PeerConnectionHandler for Handler {
@@ -187,6 +251,8 @@ impl PeerConnectionHandler for Handler {
                 .as_mut()
                 .unwrap()
                 .record_piece(&utdata, &self.info_hash)?;
+            self.progress
+                .on_piece_received(self.addr, utdata.len().try_into()?);
             if piece_ready {
                 let buf = Bytes::from(self.locked.write().take().unwrap().buffer);
                 let info = from_bytes::<TorrentMetaV1Info<ByteBuf>>(&buf)
@@ -241,6 +307,7 @@ impl PeerConnectionHandler for Handler {
         let total_pieces = inner.total_pieces;
 
         self.locked.write().replace(inner);
+        self.progress.on_metadata_size(self.addr, metadata_size);
 
         for i in 0..total_pieces {
             self.writer_tx
@@ -255,3 +322,32 @@ impl PeerConnectionHandler for Handler {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use super::MetadataFetchProgress;
+
+    #[test]
+    fn test_metadata_fetch_progress() {
+        let addr = |port| SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let progress = MetadataFetchProgress::default();
+        assert_eq!(progress.get(), (0, 0));
+
+        progress.on_metadata_size(addr(1), 40000);
+        progress.on_piece_received(addr(1), 16384);
+        assert_eq!(progress.get(), (16384, 40000));
+
+        // Two peers disagree with the first one, so it's not counted anymore.
+        progress.on_metadata_size(addr(2), 50000);
+        progress.on_metadata_size(addr(3), 50000);
+        progress.on_piece_received(addr(3), 16384);
+        progress.on_piece_received(addr(3), 16384);
+        assert_eq!(progress.get(), (32768, 50000));
+
+        progress.on_peer_failed(addr(2));
+        progress.on_peer_failed(addr(3));
+        assert_eq!(progress.get(), (16384, 40000));
+    }
+}
@@ -8,6 +8,10 @@
 //! - QUEUED (available to download)
 //! - IN_FLIGHT (currently being downloaded)
 //! - NOT_NEEDED (not selected for download)
+//!
+//! In endgame mode (only a few needed pieces left) an in-flight piece may be requested
+//! from several peers at once. It still has a single owner, the other peers are tracked
+//! as [`InflightPiece::endgame_peers`].
 
 use std::{
     collections::{HashMap, HashSet},
@@ -15,7 +19,7 @@ use std::{
 };
 
 use buffers::ByteBuf;
-use librqbit_core::lengths::ValidPieceIndex;
+use librqbit_core::lengths::{ChunkInfo, ValidPieceIndex};
 use peer_binary_protocol::Piece;
 
 use crate::{
@@ -24,11 +28,34 @@ use crate::{
     type_aliases::{FileInfos, FilePriorities, PeerHandle},
 };
 
+/// Max number of extra peers an in-flight piece gets requested from in endgame mode.
+/// Each of them is a duplicate request, so this bounds the wasted bandwidth.
+const MAX_ENDGAME_PEERS_PER_PIECE: usize = 3;
+
 /// Tracks a piece currently being downloaded.
 #[derive(Debug, Clone)]
 pub struct InflightPiece {
     pub peer: PeerHandle,
     pub started: Instant,
+    /// Peers that were additionally asked for this piece in endgame mode.
+    pub endgame_peers: Vec<PeerHandle>,
+    /// Peers that delivered chunks of this piece, e.g. before it was stolen.
+    /// They get the blame if the piece fails the hash check.
+    pub contributors: Vec<PeerHandle>,
+}
+
+impl InflightPiece {
+    /// Returns true if the peer is either the owner or an endgame peer of this piece.
+    pub fn is_downloaded_by(&self, peer: PeerHandle) -> bool {
+        self.peer == peer || self.endgame_peers.contains(&peer)
+    }
+
+    /// All peers the piece was requested from, except the given one.
+    pub fn other_peers(&self, peer: PeerHandle) -> impl Iterator<Item = PeerHandle> + '_ {
+        std::iter::once(self.peer)
+            .chain(self.endgame_peers.iter().copied())
+            .filter(move |p| *p != peer)
+    }
 }
 
 /// Result of attempting to acquire a piece.
@@ -41,6 +68,9 @@ pub enum AcquireResult {
         piece: ValidPieceIndex,
         from_peer: PeerHandle,
     },
+    /// Endgame mode: an in-flight piece owned by another peer was also assigned to this peer.
+    /// The caller should request its missing chunks.
+    Endgame(ValidPieceIndex),
     /// No pieces are available for this peer.
     NoneAvailable,
 }
@@ -62,10 +92,14 @@ where
     pub file_priorities: &'a FilePriorities,
     /// File metadata for iterating pieces.
     pub file_infos: &'a FileInfos,
+    /// Request the pieces of each file in order.
+    pub sequential: bool,
     /// Returns true if the peer has the given piece.
     pub peer_has_piece: P,
     /// Returns true if the piece can be stolen (e.g., not locked for writing).
     pub can_steal: S,
+    /// Enter endgame mode when this many needed pieces or less are left. 0 disables endgame.
+    pub endgame_threshold: usize,
 }
 
 /// Coordinates piece download state.
@@ -117,6 +151,7 @@ impl PieceTracker {
     /// 1. Try to steal a piece from a peer that's 10x slower
     /// 2. Try to reserve a piece from the queue (priority pieces first)
     /// 3. Try to steal a piece from a peer that's 3x slower
+    /// 4. In endgame mode, join the download of a piece in-flight from another peer
     ///
     /// If `Stolen` is returned, the caller MUST call `peers.on_steal()` to notify
     /// the old peer and update counters.
@@ -146,7 +181,7 @@ impl PieceTracker {
         // Note: iter_queued_pieces only returns pieces in queue_pieces (not in-flight)
         let queued: Vec<_> = self
             .chunks
-            .iter_queued_pieces(req.file_priorities, req.file_infos)
+            .iter_queued_pieces(req.file_priorities, req.file_infos, req.sequential)
             .collect();
 
         for piece in queued {
@@ -160,6 +195,11 @@ impl PieceTracker {
             return result;
         }
 
+        // 4. Endgame
+        if let Some(result) = self.try_endgame(&req) {
+            return result;
+        }
+
         AcquireResult::NoneAvailable
     }
 
@@ -171,6 +211,8 @@ impl PieceTracker {
             InflightPiece {
                 peer,
                 started: Instant::now(),
+                endgame_peers: Vec::new(),
+                contributors: Vec::new(),
             },
         );
         AcquireResult::Reserved(piece)
@@ -210,6 +252,7 @@ impl PieceTracker {
         let info = self.inflight.get_mut(&piece)?;
         info.peer = req.peer;
         info.started = Instant::now();
+        info.endgame_peers.retain(|p| *p != req.peer);
 
         Some(AcquireResult::Stolen {
             piece,
@@ -217,16 +260,44 @@ impl PieceTracker {
         })
     }
 
+    /// Returns true if few enough needed pieces are left to enter endgame mode.
+    fn is_endgame(&self, threshold: usize) -> bool {
+        threshold > 0 && self.chunks.queued_pieces_count() + self.inflight.len() <= threshold
+    }
+
+    /// Assign an in-flight piece of another peer to the requesting peer too.
+    ///
+    /// Prefers the pieces with the least endgame peers, then the ones in-flight the longest.
+    fn try_endgame<I, P, S>(&mut self, req: &AcquireRequest<I, P, S>) -> Option<AcquireResult>
+    where
+        I: Iterator<Item = ValidPieceIndex>,
+        P: Fn(ValidPieceIndex) -> bool,
+        S: Fn(ValidPieceIndex) -> bool,
+    {
+        if !self.is_endgame(req.endgame_threshold) {
+            return None;
+        }
+
+        let (piece, info) = self
+            .inflight
+            .iter_mut()
+            .filter(|(_, info)| !info.is_downloaded_by(req.peer))
+            .filter(|(_, info)| info.endgame_peers.len() < MAX_ENDGAME_PEERS_PER_PIECE)
+            .filter(|(p, _)| (req.peer_has_piece)(**p))
+            .min_by_key(|(_, info)| (info.endgame_peers.len(), info.started))?;
+        info.endgame_peers.push(req.peer);
+        Some(AcquireResult::Endgame(*piece))
+    }
+
     // === PIECE COMPLETION ===
 
     /// Remove piece from inflight tracking (e.g., after all chunks received).
     ///
-    /// Returns download duration if piece was in-flight.
+    /// Returns the in-flight info (download start, contributors) if the piece was in-flight.
     /// Note: Does NOT mark the piece as downloaded - caller should do hash check
     /// and then call `mark_piece_hash_ok` or `mark_piece_hash_failed`.
-    pub fn take_inflight(&mut self, piece: ValidPieceIndex) -> Option<Duration> {
-        let inflight = self.inflight.remove(&piece)?;
-        Some(inflight.started.elapsed())
+    pub fn take_inflight(&mut self, piece: ValidPieceIndex) -> Option<InflightPiece> {
+        self.inflight.remove(&piece)
     }
 
     /// Mark piece as downloaded after successful hash verification.
@@ -239,11 +310,30 @@ impl PieceTracker {
         self.chunks.mark_piece_broken_if_not_have(piece);
     }
 
+    /// Mark a piece that wasn't downloaded, but passed the hash check on disk, as downloaded.
+    pub fn mark_piece_verified(&mut self, piece: ValidPieceIndex) {
+        self.chunks.mark_piece_verified(piece);
+    }
+
+    /// Mark a downloaded piece that failed the hash check on disk as not downloaded - requeues
+    /// the piece if it's selected.
+    pub fn mark_piece_not_have(&mut self, piece: ValidPieceIndex, file_infos: &FileInfos) {
+        self.chunks.mark_piece_not_have(piece, file_infos);
+    }
+
     /// Release all pieces owned by a peer (on peer death).
     ///
-    /// Moves all pieces owned by the peer from IN_FLIGHT back to QUEUED.
+    /// Moves all pieces owned by the peer from IN_FLIGHT back to QUEUED, unless
+    /// another peer is downloading them in endgame mode, in which case it becomes the owner.
     /// Returns the number of pieces released.
     pub fn release_pieces_owned_by(&mut self, peer: PeerHandle) -> usize {
+        for info in self.inflight.values_mut() {
+            info.endgame_peers.retain(|p| *p != peer);
+            if info.peer == peer && !info.endgame_peers.is_empty() {
+                info.peer = info.endgame_peers.remove(0);
+            }
+        }
+
         // Collect pieces to release (can't modify while iterating)
         let pieces_to_release: Vec<_> = self
             .inflight
@@ -260,6 +350,27 @@ impl PieceTracker {
         count
     }
 
+    /// Release a single piece from a peer, e.g. when the peer rejected a request for it.
+    ///
+    /// Same as [`Self::release_pieces_owned_by`], but only for this piece. Returns true if the
+    /// piece went back to the queue.
+    pub fn release_piece(&mut self, peer: PeerHandle, piece: ValidPieceIndex) -> bool {
+        let Some(info) = self.inflight.get_mut(&piece) else {
+            return false;
+        };
+        info.endgame_peers.retain(|p| *p != peer);
+        if info.peer != peer {
+            return false;
+        }
+        if !info.endgame_peers.is_empty() {
+            info.peer = info.endgame_peers.remove(0);
+            return false;
+        }
+        self.inflight.remove(&piece);
+        self.chunks.mark_piece_broken_if_not_have(piece);
+        true
+    }
+
     // === QUERIES ===
 
     /// Get the inflight info for a piece, if it's currently being downloaded.
@@ -267,8 +378,14 @@ impl PieceTracker {
         self.inflight.get(&piece)
     }
 
+    /// Check if the chunk is still missing and its piece is being downloaded by the peer.
+    pub fn is_chunk_needed_from(&self, peer: PeerHandle, chunk: &ChunkInfo) -> bool {
+        self.get_inflight(chunk.piece_index)
+            .is_some_and(|info| info.is_downloaded_by(peer))
+            && !self.chunks.is_chunk_downloaded(chunk)
+    }
+
     /// Check if a piece is currently in-flight.
-    #[allow(dead_code)]
     pub fn is_inflight(&self, piece: ValidPieceIndex) -> bool {
         self.inflight.contains_key(&piece)
     }
@@ -281,12 +398,27 @@ impl PieceTracker {
 
     // === PASS-THROUGH METHODS ===
 
-    /// Mark a chunk as downloaded. Returns the result indicating if the piece is complete.
+    /// Mark a chunk received from a peer as downloaded. Returns the result indicating if
+    /// the piece is complete.
     pub fn mark_chunk_downloaded(
         &mut self,
         piece: &Piece<ByteBuf<'_>>,
+        from: PeerHandle,
     ) -> Option<ChunkMarkingResult> {
-        self.chunks.mark_chunk_downloaded(piece)
+        let result = self.chunks.mark_chunk_downloaded(piece);
+        if matches!(
+            result,
+            Some(ChunkMarkingResult::Completed | ChunkMarkingResult::NotCompleted)
+        ) && let Some(inflight) = self
+            .chunks
+            .get_lengths()
+            .validate_piece_index(piece.index)
+            .and_then(|idx| self.inflight.get_mut(&idx))
+            && !inflight.contributors.contains(&from)
+        {
+            inflight.contributors.push(from);
+        }
+        result
     }
 
     /// Update which files are selected for download.
@@ -294,8 +426,10 @@ impl PieceTracker {
         &mut self,
         file_infos: &FileInfos,
         new_only_files: &HashSet<usize>,
+        wanted_ranges: &crate::chunk_tracker::WantedRanges,
     ) -> anyhow::Result<crate::chunk_tracker::HaveNeededSelected> {
-        self.chunks.update_only_files(file_infos, new_only_files)
+        self.chunks
+            .update_only_files(file_infos, new_only_files, wanted_ranges)
     }
 
     /// Update per-file have bytes when a piece completes. Returns remaining bytes for the file.
@@ -394,8 +528,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true, // Peer has all pieces
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
 
         // Should reserve piece 0 (first in queue)
@@ -409,6 +545,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_reserve_sequential() {
+        let file_infos = make_test_file_infos(5);
+        let file_priorities = make_default_file_priorities(&file_infos);
+        let reserve_three = |sequential: bool| {
+            let mut tracker = PieceTracker::new(make_test_chunk_tracker(5));
+            (0..3)
+                .map(|_| {
+                    match tracker.acquire_piece(AcquireRequest {
+                        peer: peer(1),
+                        peer_avg_time: None,
+                        priority_pieces: std::iter::empty(),
+                        file_priorities: &file_priorities,
+                        file_infos: &file_infos,
+                        sequential,
+                        peer_has_piece: |_| true,
+                        can_steal: |_| true,
+                        endgame_threshold: 0,
+                    }) {
+                        AcquireResult::Reserved(piece) => piece.get(),
+                        r => panic!("Expected Reserved, got {:?}", r),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // By default the last piece of the file is requested right after the first one.
+        assert_eq!(reserve_three(false), vec![0, 4, 1]);
+        assert_eq!(reserve_three(true), vec![0, 1, 2]);
+    }
+
     #[test]
     fn test_reserve_filters_by_peer_has_piece() {
         let chunks = make_test_chunk_tracker(5);
@@ -427,8 +594,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |p| p.get() >= 2,
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
 
         match result {
@@ -455,8 +624,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
 
         let piece = match result {
@@ -465,8 +636,8 @@ mod tests {
         };
 
         // Complete the piece (take_inflight + hash check + mark_piece_hash_ok)
-        let duration = tracker.take_inflight(piece);
-        assert!(duration.is_some());
+        let inflight = tracker.take_inflight(piece);
+        assert!(inflight.is_some());
         assert!(!tracker.is_inflight(piece));
         // Simulate successful hash check
         tracker.mark_piece_hash_ok(piece);
@@ -488,8 +659,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
 
         let piece = match result {
@@ -500,8 +673,8 @@ mod tests {
         assert!(tracker.is_inflight(piece));
 
         // Fail the piece (take_inflight + hash check fails + mark_piece_hash_failed)
-        let duration = tracker.take_inflight(piece);
-        assert!(duration.is_some());
+        let inflight = tracker.take_inflight(piece);
+        assert!(inflight.is_some());
         // Simulate failed hash check
         tracker.mark_piece_hash_failed(piece);
 
@@ -516,8 +689,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |p| p == piece, // Only has the failed piece
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
 
         match result2 {
@@ -545,8 +720,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         }) {
             AcquireResult::Reserved(p) => p,
             _ => panic!("Expected Reserved"),
@@ -557,8 +734,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         }) {
             AcquireResult::Reserved(p) => p,
             _ => panic!("Expected Reserved"),
@@ -571,8 +750,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         }) {
             AcquireResult::Reserved(p) => p,
             _ => panic!("Expected Reserved"),
@@ -595,6 +776,44 @@ mod tests {
         assert!(!tracker.is_inflight(piece_a2));
     }
 
+    #[test]
+    fn test_release_piece() {
+        let chunks = make_test_chunk_tracker(2);
+        let mut tracker = PieceTracker::new(chunks);
+
+        let file_infos = make_test_file_infos(2);
+        let file_priorities = make_default_file_priorities(&file_infos);
+
+        let acquire =
+            |tracker: &mut PieceTracker, peer| match tracker.acquire_piece(AcquireRequest {
+                peer,
+                peer_avg_time: None,
+                priority_pieces: std::iter::empty(),
+                file_priorities: &file_priorities,
+                file_infos: &file_infos,
+                sequential: false,
+                peer_has_piece: |_| true,
+                can_steal: |_| false,
+                endgame_threshold: 0,
+            }) {
+                AcquireResult::Reserved(p) => p,
+                r => panic!("Expected Reserved, got {r:?}"),
+            };
+
+        let a = acquire(&mut tracker, peer(1));
+        let b = acquire(&mut tracker, peer(1));
+
+        // Only the owner can release a piece.
+        assert!(!tracker.release_piece(peer(2), a));
+        assert!(tracker.release_piece(peer(1), a));
+        assert!(!tracker.release_piece(peer(1), a));
+        assert!(!tracker.is_inflight(a));
+        assert!(tracker.is_inflight(b));
+
+        // The released piece is back in the queue.
+        assert_eq!(acquire(&mut tracker, peer(2)), a);
+    }
+
     #[test]
     fn test_into_chunks_requeues_inflight() {
         let chunks = make_test_chunk_tracker(5);
@@ -610,8 +829,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
         tracker.acquire_piece(AcquireRequest {
             peer: peer(1),
@@ -619,8 +840,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
 
         assert_eq!(tracker.inflight_count(), 2);
@@ -636,8 +859,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
 
         // Should get piece 0 again (was requeued)
@@ -674,8 +899,10 @@ mod tests {
             priority_pieces: priority.into_iter(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
 
         // Should get piece 3 (first priority piece)
@@ -700,8 +927,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| false, // Peer has nothing
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
 
         match result {
@@ -748,8 +977,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         }) {
             AcquireResult::Reserved(p) => {
                 assert_eq!(p.get(), 0);
@@ -764,8 +995,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |_| true,
             can_steal: |_| true,
+            endgame_threshold: 0,
         }) {
             AcquireResult::Reserved(p) => {
                 assert_eq!(p.get(), 4);
@@ -786,8 +1019,10 @@ mod tests {
             priority_pieces: std::iter::empty(),
             file_priorities: &file_priorities,
             file_infos: &file_infos,
+            sequential: false,
             peer_has_piece: |p| p.get() == 4, // Peer B only has piece 4
             can_steal: |_| true,
+            endgame_threshold: 0,
         });
 
         // Should steal piece 4 (which peer B has), NOT piece 0 (which peer B doesn't have)
@@ -801,4 +1036,102 @@ mod tests {
             _ => panic!("Expected Stolen, got {:?}", result),
         }
     }
+
+    #[test]
+    fn test_endgame() {
+        let chunks = make_test_chunk_tracker(2);
+        let mut tracker = PieceTracker::new(chunks);
+
+        let file_infos = make_test_file_infos(2);
+        let file_priorities = make_default_file_priorities(&file_infos);
+
+        let mut acquire = |peer, endgame_threshold| {
+            tracker.acquire_piece(AcquireRequest {
+                peer,
+                peer_avg_time: None,
+                priority_pieces: std::iter::empty(),
+                file_priorities: &file_priorities,
+                file_infos: &file_infos,
+                sequential: false,
+                peer_has_piece: |_| true,
+                can_steal: |_| true,
+                endgame_threshold,
+            })
+        };
+
+        // Peer A reserves both pieces.
+        assert!(matches!(acquire(peer(1), 2), AcquireResult::Reserved(_)));
+        assert!(matches!(acquire(peer(1), 2), AcquireResult::Reserved(_)));
+
+        // Endgame disabled or too many pieces left.
+        assert!(matches!(acquire(peer(2), 0), AcquireResult::NoneAvailable));
+        assert!(matches!(acquire(peer(2), 1), AcquireResult::NoneAvailable));
+
+        // Peer B and C join different pieces, then C joins the remaining one.
+        let b = match acquire(peer(2), 2) {
+            AcquireResult::Endgame(p) => p,
+            r => panic!("Expected Endgame, got {r:?}"),
+        };
+        let c = match acquire(peer(3), 2) {
+            AcquireResult::Endgame(p) => p,
+            r => panic!("Expected Endgame, got {r:?}"),
+        };
+        assert_ne!(b, c);
+        assert!(matches!(acquire(peer(3), 2), AcquireResult::Endgame(p) if p == b));
+        assert!(matches!(acquire(peer(3), 2), AcquireResult::NoneAvailable));
+        // Owners don't join their own pieces.
+        assert!(matches!(acquire(peer(1), 2), AcquireResult::NoneAvailable));
+
+        let info = tracker.get_inflight(b).unwrap();
+        assert!(info.is_downloaded_by(peer(1)));
+        assert_eq!(
+            info.other_peers(peer(2)).collect::<Vec<_>>(),
+            vec![peer(1), peer(3)]
+        );
+
+        // Owner dies, endgame peers take over instead of requeueing.
+        assert_eq!(tracker.release_pieces_owned_by(peer(1)), 0);
+        assert_eq!(tracker.get_inflight(b).unwrap().peer, peer(2));
+        assert_eq!(tracker.get_inflight(c).unwrap().peer, peer(3));
+        assert_eq!(tracker.inflight_count(), 2);
+    }
+
+    #[test]
+    fn test_contributors() {
+        let chunks = make_test_chunk_tracker(1);
+        let mut tracker = PieceTracker::new(chunks);
+        let file_infos = make_test_file_infos(1);
+        let file_priorities = make_default_file_priorities(&file_infos);
+
+        let piece = match tracker.acquire_piece(AcquireRequest {
+            peer: peer(1),
+            peer_avg_time: None,
+            priority_pieces: std::iter::empty(),
+            file_priorities: &file_priorities,
+            file_infos: &file_infos,
+            sequential: false,
+            peer_has_piece: |_| true,
+            can_steal: |_| true,
+            endgame_threshold: 0,
+        }) {
+            AcquireResult::Reserved(p) => p,
+            r => panic!("Expected Reserved, got {r:?}"),
+        };
+
+        let data = vec![0u8; 16384];
+        let chunk = Piece::from_data(piece.get(), 0, &data);
+        assert!(matches!(
+            tracker.mark_chunk_downloaded(&chunk, peer(2)),
+            Some(ChunkMarkingResult::Completed)
+        ));
+        // Duplicates aren't counted.
+        assert!(matches!(
+            tracker.mark_chunk_downloaded(&chunk, peer(3)),
+            Some(ChunkMarkingResult::PreviouslyCompleted)
+        ));
+        assert_eq!(
+            tracker.take_inflight(piece).unwrap().contributors,
+            vec![peer(2)]
+        );
+    }
 }
@@ -1,14 +1,14 @@
 use std::{
     borrow::Cow,
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet, hash_map::Entry},
     io::Read,
-    net::SocketAddr,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
     path::{Component, Path, PathBuf},
     sync::{
         Arc,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{
@@ -19,26 +19,36 @@ use crate::{
     create_torrent,
     create_torrent_file::CreateTorrentResult,
     dht_utils::{ReadMetainfoResult, read_metainfo_from_peer_receiver},
+    file_info::{FileNamingStrategy, FilePriority, apply_renamed_files},
     ip_ranges::IpRanges,
     limits::{Limits, LimitsConfig},
     listen::{Accept, ListenerOptions},
     merge_streams::merge_streams,
+    mse::{self, EncryptionPolicy},
     peer_connection::PeerConnectionOptions,
+    peer_filter::PeerFilter,
+    peer_info_reader::MetadataFetchProgress,
     read_buf::ReadBuf,
-    session_persistence::{SessionPersistenceStore, json::JsonSessionPersistenceStore},
-    session_stats::SessionStats,
+    session_persistence::{
+        SessionPersistenceStore, json::JsonSessionPersistenceStore, resume_file::ResumeFile,
+    },
+    session_stats::{SessionStats, is_local_ip},
     spawn_utils::BlockingSpawner,
     storage::{
-        BoxStorageFactory, StorageFactoryExt, TorrentStorage, filesystem::FilesystemStorageFactory,
+        Allocation, BoxStorageFactory, StorageFactoryExt, TorrentStorage,
+        filesystem::FilesystemStorageFactory,
     },
     stream_connect::{
         ConnectionKind, ConnectionOptions, SocksProxyConfig, StreamConnector, StreamConnectorArgs,
     },
     torrent_state::{
         ManagedTorrentHandle, ManagedTorrentLocked, ManagedTorrentOptions, ManagedTorrentState,
-        TorrentMetadata, TorrentStateLive, initializing::TorrentStateInitializing,
+        ManagedTorrentStateKind, OnCompleteCallback, StopReason, TorrentMetadata, TorrentStateLive,
+        TorrentStats, TorrentStatsState,
+        events::{SESSION_EVENTS_CAPACITY, TorrentEvent, TorrentEvents},
+        initializing::TorrentStateInitializing,
     },
-    type_aliases::{BoxAsyncReadVectored, BoxAsyncWrite, PeerStream},
+    type_aliases::{BF, BoxAsyncReadVectored, BoxAsyncWrite, PeerStream},
 };
 use anyhow::{Context, bail};
 use arc_swap::ArcSwapOption;
@@ -58,24 +68,31 @@ use librqbit_core::{
     crate_version,
     directories::get_configuration_directory,
     magnet::Magnet,
-    peer_id::generate_azereus_style,
+    peer_id::{generate_azereus_style, generate_peer_id},
     spawn_utils::spawn_with_cancel,
     torrent_metainfo::{TorrentMetaV1Owned, ValidatedTorrentMetaV1Info},
 };
+use librqbit_dualstack_sockets::{BindOpts, TcpListener};
 use librqbit_lsd::{LocalServiceDiscovery, LocalServiceDiscoveryOptions};
 use librqbit_utp::BindDevice;
 use parking_lot::RwLock;
 use peer_binary_protocol::Handshake;
 use serde::{Deserialize, Serialize};
-use tokio::sync::Notify;
+use tokio::sync::{Notify, broadcast::error::RecvError};
 use tokio_util::sync::{CancellationToken, DropGuard};
 use tracing::{Instrument, debug, debug_span, error, info, trace, warn};
-use tracker_comms::{TrackerComms, UdpTrackerClient};
+use tracker_comms::{
+    AnnounceIpv6, DEFAULT_ANNOUNCE_TIMEOUT, TrackerComms, TrackerCommsOptions, UdpTrackerClient,
+    global_ipv6_addr, is_global_unicast,
+};
 
 pub const SUPPORTED_SCHEMES: [&str; 3] = ["http:", "https:", "magnet:"];
 
 pub type TorrentId = usize;
 
+const DEFAULT_ENDGAME_THRESHOLD: usize = 8;
+const DEFAULT_HASH_FAIL_BAN_THRESHOLD: u32 = 3;
+
 struct ParsedTorrentFile {
     meta: TorrentMetaV1Owned,
     torrent_bytes: Bytes,
@@ -114,10 +131,17 @@ pub struct Session {
     // Network
     peer_id: Id20,
     announce_port: Option<u16>,
+    // Sent to HTTP trackers, so that they learn it even if we announce over IPv4.
+    announce_ipv6: AnnounceIpv6,
     listen_addr: Option<SocketAddr>,
+    // Also forward the ports of torrents with their own listener, see AddTorrentOptions::listen_port.
+    upnp_port_forwarding: bool,
+    bind_device: Option<BindDevice>,
     dht: Option<Dht>,
     pub(crate) connector: Arc<StreamConnector>,
-    reqwest_client: reqwest::Client,
+    pub(crate) reqwest_client: reqwest::Client,
+    // Same as reqwest_client, but with SessionOptions::tracker_user_agent.
+    tracker_reqwest_client: reqwest::Client,
     udp_tracker_client: UdpTrackerClient,
     disable_trackers: bool,
 
@@ -143,6 +167,8 @@ pub struct Session {
 
     // Monitoring / tracing / logging
     pub(crate) stats: Arc<SessionStats>,
+    events_tx: tokio::sync::broadcast::Sender<(TorrentId, TorrentEvent)>,
+    metadata_fetches: RwLock<HashMap<Id20, Arc<MetadataFetchProgress>>>,
     root_span: Option<tracing::Span>,
 
     // Feature flags
@@ -150,6 +176,9 @@ pub struct Session {
     _disable_upload: bool,
     pub ipv4_only: bool,
     pub peer_limit: Option<usize>,
+    pub max_half_open: Option<usize>,
+    tracker_announce_jitter: Option<Duration>,
+    idle_pause_timeout: Option<Duration>,
 }
 
 async fn torrent_from_url(
@@ -244,8 +273,25 @@ pub struct AddTorrentOptions {
     /// Start in paused state.
     #[serde(default)]
     pub paused: bool,
+    /// Together with "paused", don't check existing files until the torrent is first unpaused.
+    /// Useful when adding lots of torrents at once.
+    #[serde(default)]
+    pub defer_initial_check: bool,
     /// A regex to only download files matching it.
     pub only_files_regex: Option<String>,
+    /// Per-file priorities, one for each file in the torrent. Files with
+    /// FilePriority::Skip are excluded, same as with "only_files".
+    pub file_priorities: Option<Vec<FilePriority>>,
+    /// Download pieces in order instead of the default strategy, e.g. to play media while it's
+    /// downloading. High priority files still come first. Can be changed later with
+    /// [`ManagedTorrent::set_sequential`](crate::ManagedTorrent::set_sequential).
+    #[serde(default)]
+    pub sequential: bool,
+    /// Start in super-seeding mode (BEP 16), for the initial upload of new content. Can be
+    /// changed later with
+    /// [`ManagedTorrent::set_super_seeding`](crate::ManagedTorrent::set_super_seeding).
+    #[serde(default)]
+    pub super_seeding: bool,
     /// An explicit list of file IDs to download.
     /// To see the file indices, run with "list_only".
     pub only_files: Option<Vec<usize>>,
@@ -256,9 +302,18 @@ pub struct AddTorrentOptions {
     /// be enabled in order to resume/seed the torrent.
     #[serde(default)]
     pub overwrite: bool,
+    /// How to allocate disk space for the output files. Sparse by default.
+    #[serde(default)]
+    pub allocation: Allocation,
     /// Only list the files in the torrent without starting it.
     #[serde(default)]
     pub list_only: bool,
+    /// Resolve the metadata (e.g. of a magnet link) and add the torrent paused, without
+    /// creating or checking any files in the output folder. Useful for previewing the file list.
+    ///
+    /// Unpausing the torrent later initializes it as usual.
+    #[serde(default)]
+    pub metadata_only: bool,
     /// The output folder for the torrent. If not set, the session's default one will be used.
     pub output_folder: Option<String>,
     /// Sub-folder within session's default output folder. Will error if "output_folder" if also set.
@@ -269,10 +324,26 @@ pub struct AddTorrentOptions {
 
     /// Force a refresh interval for polling trackers.
     pub force_tracker_interval: Option<Duration>,
+    /// Timeout for HTTP tracker announces. Defaults to 15 seconds.
+    pub tracker_announce_timeout: Option<Duration>,
+    /// Delay the first tracker announce by a random duration up to this, and add up to this much
+    /// to the following ones. Defaults to the session's `tracker_announce_jitter`.
+    pub tracker_announce_jitter: Option<Duration>,
+    /// Pause the torrent once it had no connected peers and made no download progress for this
+    /// long, with [`StopReason::Idle`](crate::StopReason::Idle). Finished torrents aren't paused.
+    /// Defaults to the session's `idle_pause_timeout`. Zero disables it for this torrent.
+    pub idle_pause_timeout: Option<Duration>,
 
     #[serde(default)]
     pub disable_trackers: bool,
 
+    /// Find peers through the DHT. Defaults to true. Always off for private torrents.
+    pub enable_dht: Option<bool>,
+    /// Exchange peers with connected peers (PEX). Defaults to true. Always off for private torrents.
+    pub enable_pex: Option<bool>,
+    /// Find peers on the local network (LSD). Defaults to true. Always off for private torrents.
+    pub enable_lsd: Option<bool>,
+
     #[serde(default)]
     pub ratelimits: LimitsConfig,
 
@@ -282,14 +353,98 @@ pub struct AddTorrentOptions {
     /// Max concurrent connected peers.
     pub peer_limit: Option<usize>,
 
+    /// Max concurrent outgoing connections that haven't completed the handshake yet.
+    pub max_half_open: Option<usize>,
+
+    /// Keep up to this many bytes of recently uploaded pieces in memory, so that
+    /// peers requesting the same pieces don't cause repeated disk reads. Disabled if not set.
+    pub read_cache_bytes: Option<usize>,
+
+    /// When this many needed pieces or less are left, request them from all peers that have them
+    /// at once, cancelling the duplicate requests as chunks arrive ("endgame mode").
+    /// Defaults to 8. Set to 0 to disable.
+    pub endgame_threshold: Option<usize>,
+
+    /// Ban a peer after it contributed data to this many pieces that failed the hash check.
+    /// Defaults to 3. Set to 0 to never ban.
+    pub hash_fail_ban_threshold: Option<u32>,
+
+    /// How many pieces to hash at once when checking existing files. Defaults to the number
+    /// of CPUs. The hashing threads count towards [`SessionOptions::runtime_worker_threads`].
+    pub hashing_concurrency: Option<usize>,
+
+    /// Upload to at most this many peers at a time: the ones we download from the fastest, or
+    /// upload to the fastest when seeding. One more peer is unchoked at random every 30 seconds
+    /// to find better ones. If not set, all peers are unchoked.
+    pub unchoke_slots: Option<usize>,
+
+    /// Stop requesting chunks from peers while more than this many downloaded bytes wait to be
+    /// written to disk, so that memory doesn't grow when the disk is slower than the network.
+    /// Not limited if not set.
+    pub max_pending_write_bytes: Option<u64>,
+
+    /// Also accept incoming connections on this TCP port, and announce it instead of the
+    /// session's port for this torrent. If it's taken, a random port is used instead, see
+    /// [`ManagedTorrent::listen_port`].
+    pub listen_port: Option<u16>,
+
     /// This is used to restore the session from serialized state.
     pub preferred_id: Option<usize>,
 
     #[serde(skip)]
     pub storage_factory: Option<BoxStorageFactory>,
 
-    // Custom trackers
+    /// How files are laid out on disk. The paths it chose are persisted, so a torrent
+    /// restored from the session looks for files at the same paths.
+    #[serde(skip)]
+    pub file_naming: FileNamingStrategy,
+
+    /// Paths of single files relative to the output folder by file index, applied after
+    /// "file_naming", see [`ManagedTorrent::rename_file`](crate::ManagedTorrent::rename_file).
+    #[serde(default)]
+    pub renamed_files: BTreeMap<usize, PathBuf>,
+
+    /// Once finished, pause the torrent after uploading this many times the selected bytes.
+    pub seed_ratio_limit: Option<f64>,
+
+    /// Shown instead of the name from the metadata, see
+    /// [`ManagedTorrent::set_display_name`](crate::ManagedTorrent::set_display_name).
+    pub display_name: Option<String>,
+
+    /// Restrict which peers this torrent connects to and accepts connections from.
+    #[serde(skip)]
+    pub peer_filter: Option<Arc<dyn PeerFilter>>,
+
+    /// Called once when the torrent finishes downloading, including when it turns out to be
+    /// complete on the initial check. Not called if "previously_completed" is set.
+    #[serde(skip)]
+    pub on_complete: Option<OnCompleteCallback>,
+
+    /// The torrent finished downloading before, e.g. in a previous session, so "on_complete"
+    /// isn't called for it. Restored from the session's persisted state.
+    #[serde(skip)]
+    pub previously_completed: bool,
+
+    /// Extra trackers, each announced to as its own tier after the torrent's own ones.
     pub trackers: Option<Vec<String>>,
+
+    /// Extra tracker tiers (BEP-12), announced to after the torrent's own tiers and before
+    /// "trackers". Within a tier, trackers are tried one at a time until one responds.
+    pub tracker_tiers: Option<Vec<Vec<String>>>,
+
+    /// Web seed URLs (BEP-19), in addition to the ones in the torrent's "url-list".
+    /// Only http and https URLs are used.
+    pub web_seeds: Option<Vec<String>>,
+
+    /// Pieces downloaded before, e.g. in a previous session. Used as fastresume data if the
+    /// session doesn't have any persisted for this torrent. It's spot-checked against the files
+    /// like any fastresume data, and if that fails all files are checked.
+    #[serde(skip)]
+    pub resume_bitfield: Option<Vec<u8>>,
+
+    /// Bytes uploaded in previous sessions. Counted in stats and towards the seed ratio limit.
+    #[serde(skip)]
+    pub uploaded_bytes: u64,
 }
 
 pub struct ListOnlyResponse {
@@ -359,6 +514,20 @@ impl<'a> AddTorrent<'a> {
         Self::TorrentFileBytes(bytes.into())
     }
 
+    /// Add a torrent from its bencoded info dictionary alone, e.g. one received from peers.
+    ///
+    /// The bytes are used as is rather than re-encoded, so the info hash is computed from
+    /// exactly these bytes.
+    pub fn from_info_bytes(info_bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut torrent = Vec::with_capacity(info_bytes.len() + 8);
+        torrent.extend_from_slice(b"d4:info");
+        torrent.extend_from_slice(info_bytes);
+        torrent.push(b'e');
+        librqbit_core::torrent_metainfo::torrent_from_bytes(&torrent)
+            .context("error decoding info dictionary")?;
+        Ok(Self::TorrentFileBytes(torrent.into()))
+    }
+
     // Don't call this from HTTP API.
     #[inline(never)]
     pub fn from_local_filename(filename: &str) -> anyhow::Result<Self> {
@@ -419,6 +588,23 @@ pub struct SessionOptions {
     /// The peer ID to use. If not specified, a random one will be generated.
     pub peer_id: Option<Id20>,
 
+    /// Generate the peer ID with this client prefix instead of rqbit's, e.g. `*b"-qB4650-"` for
+    /// private trackers that only allow some clients. The rest of the ID is random. Must be
+    /// printable ASCII. Ignored if `peer_id` is set.
+    pub peer_id_prefix: Option<[u8; 8]>,
+
+    /// The User-Agent header for HTTP tracker announces. None is sent if not set.
+    pub tracker_user_agent: Option<String>,
+
+    /// Delay the first tracker announce of each torrent by a random duration up to this, and
+    /// add up to this much to the following ones, so that trackers don't get a burst of
+    /// announces when many torrents start at once. No jitter if not set.
+    pub tracker_announce_jitter: Option<Duration>,
+
+    /// Pause torrents that had no connected peers and made no download progress for this long.
+    /// Finished torrents aren't paused. Never paused if not set or zero.
+    pub idle_pause_timeout: Option<Duration>,
+
     /// Options for listening on TCP and/or uTP for incoming connections.
     pub listen: Option<ListenerOptions>,
     /// Options for connecting to peers (for outgiong connections).
@@ -449,6 +635,9 @@ pub struct SessionOptions {
     /// Default peer limit per torrent.
     pub peer_limit: Option<usize>,
 
+    /// Default limit of half-open outgoing connections per torrent.
+    pub max_half_open: Option<usize>,
+
     #[cfg(feature = "disable-upload")]
     pub disable_upload: bool,
 
@@ -457,27 +646,60 @@ pub struct SessionOptions {
 
     /// Force IPv4 only.
     pub ipv4_only: bool,
+
+    /// Whether to encrypt peer connections with MSE, both outgoing and incoming.
+    pub encryption: EncryptionPolicy,
 }
 
-fn torrent_file_from_info_bytes(info_bytes: &[u8], trackers: &[url::Url]) -> anyhow::Result<Bytes> {
+pub(crate) fn torrent_file_from_info_bytes(
+    info_bytes: &[u8],
+    tracker_tiers: &[Vec<url::Url>],
+) -> anyhow::Result<Bytes> {
     #[derive(Serialize)]
     struct Tmp<'a> {
         announce: &'a str,
         #[serde(rename = "announce-list")]
-        announce_list: &'a [&'a [url::Url]],
+        announce_list: &'a [Vec<url::Url>],
         info: bencode::raw_value::RawValue<&'a [u8]>,
     }
 
     let mut w = Vec::new();
     let v = Tmp {
         info: bencode::raw_value::RawValue(info_bytes),
-        announce: trackers.first().map(|s| s.as_str()).unwrap_or(""),
-        announce_list: &[trackers],
+        announce: tracker_tiers
+            .iter()
+            .flatten()
+            .next()
+            .map(|s| s.as_str())
+            .unwrap_or(""),
+        announce_list: tracker_tiers,
     };
     bencode_serialize_to_writer(&v, &mut w)?;
     Ok(w.into())
 }
 
+// Append the extra trackers from the options to the torrent's own tiers, dropping duplicates
+// and invalid URLs.
+fn merge_tracker_tiers(
+    mut tiers: Vec<Vec<String>>,
+    opts: &AddTorrentOptions,
+) -> Vec<Vec<url::Url>> {
+    tiers.extend(opts.tracker_tiers.iter().flatten().cloned());
+    tiers.extend(opts.trackers.iter().flatten().map(|t| vec![t.clone()]));
+
+    let mut seen = HashSet::new();
+    tiers
+        .into_iter()
+        .map(|tier| {
+            tier.iter()
+                .filter_map(|t| url::Url::parse(t).ok())
+                .filter(|t| seen.insert(t.clone()))
+                .collect::<Vec<_>>()
+        })
+        .filter(|tier| !tier.is_empty())
+        .collect()
+}
+
 pub(crate) struct CheckedIncomingConnection {
     pub kind: ConnectionKind,
     pub addr: SocketAddr,
@@ -490,10 +712,46 @@ pub(crate) struct CheckedIncomingConnection {
 struct InternalAddResult {
     info_hash: Id20,
     metadata: Option<TorrentMetadata>,
-    trackers: Vec<url::Url>,
+    // Tracker tiers, see AddTorrentOptions::tracker_tiers.
+    trackers: Vec<Vec<url::Url>>,
+    web_seeds: Vec<url::Url>,
     name: Option<String>,
 }
 
+// Peer sources of a torrent besides trackers and initial peers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PeerDiscovery {
+    private: bool,
+    dht: bool,
+    pex: bool,
+    lsd: bool,
+}
+
+impl PeerDiscovery {
+    // Everything is on unless disabled in the options. Private torrents must only use their
+    // trackers (BEP-27), so for them it's all off regardless.
+    fn new(opts: &AddTorrentOptions, private: bool, info_hash: Id20) -> Self {
+        let resolve = |name: &str, enabled: Option<bool>| {
+            if !private {
+                return enabled.unwrap_or(true);
+            }
+            if enabled == Some(true) {
+                warn!(
+                    ?info_hash,
+                    "can't use {name} for a private torrent, disabling it"
+                );
+            }
+            false
+        };
+        Self {
+            private,
+            dht: resolve("DHT", opts.enable_dht),
+            pex: resolve("PEX", opts.enable_pex),
+            lsd: resolve("LSD", opts.enable_lsd),
+        }
+    }
+}
+
 impl Session {
     /// Create a new session with default options.
     /// The passed in folder will be used as a default unless overridden per torrent.
@@ -507,6 +765,11 @@ impl Session {
         &self.cancellation_token
     }
 
+    /// Subscribe to events of all torrents in the session.
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<(TorrentId, TorrentEvent)> {
+        self.events_tx.subscribe()
+    }
+
     /// Create a new session with options.
     #[inline(never)]
     pub fn new_with_opts(
@@ -514,9 +777,19 @@ impl Session {
         mut opts: SessionOptions,
     ) -> BoxFuture<'static, anyhow::Result<Arc<Self>>> {
         async move {
-            let peer_id = opts
-                .peer_id
-                .unwrap_or_else(|| generate_azereus_style(*b"rQ", crate_version!()));
+            let peer_id = match (opts.peer_id, opts.peer_id_prefix) {
+                (Some(peer_id), _) => peer_id,
+                (None, Some(prefix)) => {
+                    if !prefix.iter().all(|b| b.is_ascii_graphic()) {
+                        bail!(
+                            "peer_id_prefix should be printable ASCII, got {:?}",
+                            String::from_utf8_lossy(&prefix)
+                        );
+                    }
+                    generate_peer_id(&prefix)
+                }
+                (None, None) => generate_azereus_style(*b"rQ", crate_version!()),
+            };
             let token = opts.cancellation_token.take().unwrap_or_default();
 
             #[cfg(feature = "disable-upload")]
@@ -547,20 +820,50 @@ impl Session {
                 None
             };
 
+            let proxy_url = opts.connect.as_ref().and_then(|s| s.proxy_url.as_ref());
+            let proxy_config = match proxy_url {
+                Some(pu) => Some(
+                    SocksProxyConfig::parse(pu)
+                        .with_context(|| format!("error parsing proxy url {pu}"))?,
+                ),
+                None => None,
+            };
+
+            // UDP only goes through the proxy with UDP ASSOCIATE.
+            let proxy_udp = match proxy_config.as_ref() {
+                Some(pc) if opts.connect.as_ref().is_some_and(|c| c.proxy_udp) => Some(pc),
+                _ => None,
+            };
+            if proxy_config.is_some() && proxy_udp.is_none() && !opts.disable_dht {
+                warn!("DHT doesn't work through the SOCKS5 proxy without UDP, disabling it");
+                opts.disable_dht = true;
+            }
+            // LSD multicasts our port to the LAN directly, which would bypass the proxy.
+            if proxy_config.is_some() && !opts.disable_local_service_discovery {
+                warn!("local service discovery would bypass the SOCKS5 proxy, disabling it");
+                opts.disable_local_service_discovery = true;
+            }
+
             let dht = if opts.disable_dht {
                 None
             } else {
+                let socks5_udp = match proxy_udp {
+                    Some(pc) => Some(pc.udp_associate().await?),
+                    None => None,
+                };
                 let dht = if opts.disable_dht_persistence {
                     DhtBuilder::with_config(DhtConfig {
                         bootstrap_addrs: opts.dht_bootstrap_addrs.clone(),
                         cancellation_token: Some(token.child_token()),
                         bind_device: bind_device.as_ref(),
+                        socks5_udp,
                         ..Default::default()
                     })
                     .await
                     .context("error initializing DHT")?
                 } else {
-                    let pdht_config = opts.dht_config.take().unwrap_or_default();
+                    let mut pdht_config = opts.dht_config.take().unwrap_or_default();
+                    pdht_config.socks5_udp = socks5_udp;
                     PersistentDht::create(
                         Some(pdht_config),
                         Some(token.clone()),
@@ -630,18 +933,9 @@ impl Session {
                 .await
                 .context("error initializing session persistence store")?;
 
-            let proxy_url = opts.connect.as_ref().and_then(|s| s.proxy_url.as_ref());
-            let proxy_config = match proxy_url {
-                Some(pu) => Some(
-                    SocksProxyConfig::parse(pu)
-                        .with_context(|| format!("error parsing proxy url {pu}"))?,
-                ),
-                None => None,
-            };
-
-            let reqwest_client = {
-                let builder = if let Some(proxy_url) = proxy_url {
-                    let proxy = reqwest::Proxy::all(proxy_url)
+            let reqwest_client_builder = || -> anyhow::Result<reqwest::ClientBuilder> {
+                Ok(if let Some(proxy_config) = proxy_config.as_ref() {
+                    let proxy = reqwest::Proxy::all(proxy_config.reqwest_proxy_url())
                         .context("error creating socks5 proxy for HTTP")?;
                     reqwest::Client::builder().proxy(proxy)
                 } else {
@@ -652,9 +946,17 @@ impl Session {
                         b = b.interface(bd);
                     }
                     b
-                };
-
-                builder.build().context("error building HTTP(S) client")?
+                })
+            };
+            let reqwest_client = reqwest_client_builder()?
+                .build()
+                .context("error building HTTP(S) client")?;
+            let tracker_reqwest_client = match opts.tracker_user_agent.as_ref() {
+                Some(user_agent) => reqwest_client_builder()?
+                    .user_agent(user_agent)
+                    .build()
+                    .context("error building HTTP(S) client for trackers")?,
+                None => reqwest_client.clone(),
             };
 
             let stream_connector = Arc::new(
@@ -664,11 +966,34 @@ impl Session {
                     utp_socket: listen_result.as_ref().and_then(|l| l.utp_socket.clone()),
                     bind_device: bind_device.clone(),
                     ipv4_only: opts.ipv4_only,
+                    encryption: opts.encryption,
                 })
                 .await
                 .context("error creating stream connector")?,
             );
 
+            // Don't tell trackers our address when going through a proxy.
+            // When listening on all addresses, the global one is looked up on every announce,
+            // as it may change while we run.
+            let announce_ipv6 = match listen_result.as_ref().map(|l| l.addr.ip()) {
+                _ if stream_connector.is_proxied() => AnnounceIpv6::None,
+                Some(IpAddr::V6(ip)) if ip.is_unspecified() => AnnounceIpv6::Global,
+                Some(IpAddr::V6(ip)) if is_global_unicast(&ip) => AnnounceIpv6::Fixed(ip),
+                _ => AnnounceIpv6::None,
+            };
+            match announce_ipv6 {
+                AnnounceIpv6::None => {}
+                AnnounceIpv6::Fixed(ip) => {
+                    debug!(%ip, "will announce IPv6 address to HTTP trackers")
+                }
+                AnnounceIpv6::Global => {
+                    debug!(
+                        current = ?global_ipv6_addr(),
+                        "will announce the global IPv6 address to HTTP trackers"
+                    )
+                }
+            }
+
             let blocklist = if let Some(blocklist_url) = opts.blocklist_url {
                 info!(url = blocklist_url, "loading p2p blocklist");
                 let bl = IpRanges::load_from_url(&blocklist_url)
@@ -691,9 +1016,18 @@ impl Session {
                 None
             };
 
-            let udp_tracker_client = UdpTrackerClient::new(token.clone(), bind_device.as_ref())
-                .await
-                .context("error creating UDP tracker client")?;
+            let udp_tracker_client = match proxy_udp {
+                Some(pc) => {
+                    UdpTrackerClient::new_with_socks5_relay(
+                        token.clone(),
+                        bind_device.as_ref(),
+                        pc.udp_associate().await?,
+                    )
+                    .await
+                }
+                None => UdpTrackerClient::new(token.clone(), bind_device.as_ref()).await,
+            }
+            .context("error creating UDP tracker client")?;
 
             let lsd = {
                 if opts.disable_local_service_discovery {
@@ -720,15 +1054,23 @@ impl Session {
                 output_folder: default_output_folder,
                 next_id: AtomicUsize::new(0),
                 db: RwLock::new(Default::default()),
+                metadata_fetches: Default::default(),
                 _cancellation_token_drop_guard: token.clone().drop_guard(),
                 cancellation_token: token,
                 announce_port: listen_result.as_ref().and_then(|l| l.announce_port),
+                announce_ipv6,
                 listen_addr: listen_result.as_ref().map(|l| l.addr),
+                upnp_port_forwarding: listen_result
+                    .as_ref()
+                    .is_some_and(|l| l.enable_upnp_port_forwarding),
+                bind_device: bind_device.clone(),
                 default_storage_factory: opts.default_storage_factory,
                 reqwest_client,
+                tracker_reqwest_client,
                 connector: stream_connector,
                 root_span: opts.root_span,
                 stats: Arc::new(SessionStats::new()),
+                events_tx: tokio::sync::broadcast::Sender::new(SESSION_EVENTS_CAPACITY),
                 concurrent_initialize_semaphore: Arc::new(tokio::sync::Semaphore::new(
                     opts.concurrent_init_limit.unwrap_or(3),
                 )),
@@ -738,6 +1080,9 @@ impl Session {
                 trackers: opts.trackers,
                 disable_trackers: opts.disable_trackers,
                 peer_limit: opts.peer_limit,
+                max_half_open: opts.max_half_open,
+                tracker_announce_jitter: opts.tracker_announce_jitter,
+                idle_pause_timeout: opts.idle_pause_timeout,
 
                 #[cfg(feature = "disable-upload")]
                 _disable_upload: opts.disable_upload,
@@ -753,7 +1098,7 @@ impl Session {
                         "tcp_listen",
                         {
                             let this = session.clone();
-                            async move { this.task_listener(tcp).await }
+                            async move { this.task_listener(tcp, None).await }
                         },
                     );
                 }
@@ -763,7 +1108,7 @@ impl Session {
                         "utp_listen",
                         {
                             let this = session.clone();
-                            async move { this.task_listener(utp).await }
+                            async move { this.task_listener(utp, None).await }
                         },
                     );
                 }
@@ -823,11 +1168,13 @@ impl Session {
         .boxed()
     }
 
+    // With "only_torrent", connections for other torrents are rejected.
     async fn check_incoming_connection(
         self: Arc<Self>,
+        only_torrent: Option<TorrentId>,
         addr: SocketAddr,
         kind: ConnectionKind,
-        mut reader: BoxAsyncReadVectored,
+        reader: BoxAsyncReadVectored,
         writer: BoxAsyncWrite,
     ) -> anyhow::Result<(Arc<TorrentStateLive>, CheckedIncomingConnection)> {
         let rwtimeout = self
@@ -851,6 +1198,30 @@ impl Session {
             bail!("Incoming ip {incoming_ip} is not in allowlist");
         }
 
+        let (mut reader, writer) = match self.connector.encryption() {
+            EncryptionPolicy::Disabled => (reader, writer),
+            policy => tokio::time::timeout(
+                rwtimeout,
+                mse::accept(reader, writer, policy, || {
+                    self.db
+                        .read()
+                        .torrents
+                        .iter()
+                        .filter(|(id, _)| only_torrent.is_none_or(|only| **id == only))
+                        .flat_map(|(_, t)| {
+                            [
+                                Some(t.info_hash()),
+                                t.info_hash_v2().map(|h| h.truncate_for_dht()),
+                            ]
+                        })
+                        .flatten()
+                        .collect()
+                }),
+            )
+            .await
+            .context("timeout during MSE handshake")??,
+        };
+
         let mut read_buf = ReadBuf::new();
         let h = read_buf
             .read_handshake(&mut reader, rwtimeout)
@@ -861,13 +1232,21 @@ impl Session {
         if h.peer_id == self.peer_id {
             bail!("seems like we are connecting to ourselves, ignoring");
         }
+        if !is_local_ip(incoming_ip) {
+            *self.stats.last_remote_incoming.lock() = Some(Instant::now());
+        }
 
         let (id, torrent) = self
             .db
             .read()
             .torrents
             .iter()
-            .find(|(_, t)| t.info_hash() == h.info_hash)
+            .filter(|(id, _)| only_torrent.is_none_or(|only| **id == only))
+            .find(|(_, t)| {
+                // Peers from the v2 swarm of hybrid torrents use the truncated v2 info hash.
+                t.info_hash() == h.info_hash
+                    || t.info_hash_v2().map(|v2| v2.truncate_for_dht()) == Some(h.info_hash)
+            })
             .map(|(id, t)| (*id, t.clone()))
             .with_context(|| format!("didn't find a matching torrent {:?}", h.info_hash))?;
 
@@ -889,7 +1268,11 @@ impl Session {
         ))
     }
 
-    async fn task_listener<A: Accept>(self: Arc<Self>, l: A) -> anyhow::Result<()> {
+    async fn task_listener<A: Accept>(
+        self: Arc<Self>,
+        l: A,
+        only_torrent: Option<TorrentId>,
+    ) -> anyhow::Result<()> {
         let mut futs = FuturesUnordered::new();
         let session = Arc::downgrade(&self);
         drop(self);
@@ -903,7 +1286,7 @@ impl Session {
                             let session = session.upgrade().context("session is dead")?;
                             let span = debug_span!(parent: session.rs(), "incoming", addr=%addr);
                             futs.push(
-                                session.check_incoming_connection(addr, A::KIND, Box::new(read), Box::new(write))
+                                session.check_incoming_connection(only_torrent, addr, A::KIND, Box::new(read), Box::new(write))
                                     .map_err(|e| {
                                         debug!("error checking incoming connection: {e:#}");
                                         e
@@ -930,6 +1313,79 @@ impl Session {
         }
     }
 
+    // Listen on the port requested for a torrent, falling back to a random one if it's taken.
+    fn bind_torrent_listener(&self, port: u16) -> anyhow::Result<TcpListener> {
+        let ip = self
+            .listen_addr
+            .map(|a| a.ip())
+            .unwrap_or(IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+        let bind = |port: u16| {
+            TcpListener::bind_tcp(
+                (ip, port).into(),
+                BindOpts {
+                    request_dualstack: ip.is_ipv6(),
+                    reuseport: false,
+                    device: self.bind_device.as_ref(),
+                },
+            )
+        };
+        let listener = match bind(port) {
+            Ok(l) => l,
+            Err(e) if port != 0 => {
+                warn!(
+                    port,
+                    "error listening on requested port, using a random one: {e:#}"
+                );
+                bind(0).context("error starting TCP listener")?
+            }
+            Err(e) => return Err(e).context("error starting TCP listener"),
+        };
+        info!(
+            "Listening on TCP {:?} for incoming peer connections of the torrent",
+            listener.bind_addr()
+        );
+        Ok(listener)
+    }
+
+    // Accept connections on the torrent's own listener until the torrent is removed. Only
+    // connections for this torrent are accepted.
+    fn spawn_torrent_listener(self: &Arc<Self>, t: &ManagedTorrentHandle, tcp: TcpListener) {
+        let mut events = t.subscribe_events();
+        let session = self.clone();
+        let id = t.id();
+        let upnp_port = self.upnp_port_forwarding.then(|| tcp.bind_addr().port());
+        let bind_device = self.bind_device.clone();
+        self.spawn(
+            debug_span!(parent: t.shared().span.clone(), "tcp_listen", addr = ?tcp.bind_addr()),
+            format!("[{}]tcp_listen", t.id()),
+            async move {
+                let removed = async {
+                    loop {
+                        if let Ok(TorrentEvent::Removed) | Err(RecvError::Closed) =
+                            events.recv().await
+                        {
+                            return;
+                        }
+                    }
+                };
+                let upnp = async {
+                    if let Some(port) = upnp_port {
+                        info!(port, "starting UPnP port forwarder for the torrent");
+                        if let Err(e) = Self::task_upnp_port_forwarder(port, bind_device).await {
+                            warn!(port, "error forwarding the torrent's port with UPnP: {e:#}");
+                        }
+                    }
+                    std::future::pending::<()>().await
+                };
+                tokio::select! {
+                    r = session.task_listener(tcp, Some(id)) => r,
+                    _ = removed => Ok(()),
+                    _ = upnp => Ok(()),
+                }
+            },
+        );
+    }
+
     async fn task_upnp_port_forwarder(
         port: u16,
         bind_device: Option<BindDevice>,
@@ -983,6 +1439,9 @@ impl Session {
             .cloned()
             .collect::<Vec<_>>();
         for torrent in torrents {
+            // E.g. the uploaded bytes changed since it was last stored. Before pausing, so that
+            // it's not stored as paused.
+            self.try_update_persistence_metadata(&torrent).await;
             if let Err(e) = torrent.pause() {
                 debug!("error pausing torrent: {e:#}");
             }
@@ -992,6 +1451,71 @@ impl Session {
         tokio::time::sleep(Duration::from_secs(1)).await;
     }
 
+    /// Write the resume state of all torrents to a single file, see
+    /// [`ResumeFile`](crate::session_persistence::resume_file::ResumeFile). Returns how many
+    /// torrents were written.
+    ///
+    /// If `pause_all` is set, live torrents are paused first, e.g. before shutting down. They are
+    /// still written as live, so they start again when the file is loaded.
+    pub async fn save_resume_file(&self, path: &Path, pause_all: bool) -> anyhow::Result<usize> {
+        let mut file = if pause_all {
+            let torrents = self
+                .db
+                .read()
+                .torrents
+                .values()
+                .cloned()
+                .collect::<Vec<_>>();
+            let mut live = HashSet::new();
+            for torrent in torrents {
+                if torrent.state_kind() != ManagedTorrentStateKind::Live {
+                    continue;
+                }
+                match torrent.pause() {
+                    Ok(()) => {
+                        live.insert(torrent.info_hash());
+                    }
+                    Err(e) => debug!("error pausing torrent: {e:#}"),
+                }
+            }
+            let mut file = ResumeFile::from_session(self);
+            for t in file.torrents.iter_mut() {
+                if live.contains(&t.info_hash) {
+                    t.paused = false;
+                }
+            }
+            file
+        } else {
+            ResumeFile::from_session(self)
+        };
+        file.torrents.sort_by_key(|t| t.info_hash);
+        file.write(path).await?;
+        Ok(file.torrents.len())
+    }
+
+    /// Add all torrents from a file written by [`Session::save_resume_file`]. Torrents that can't
+    /// be added are skipped with a warning.
+    pub async fn load_resume_file(
+        self: &Arc<Self>,
+        path: &Path,
+    ) -> anyhow::Result<Vec<ManagedTorrentHandle>> {
+        let file = ResumeFile::read(path).await?;
+        let mut handles = Vec::with_capacity(file.torrents.len());
+        for torrent in file.torrents {
+            let info_hash = torrent.info_hash;
+            let added = async {
+                let (add, opts) = torrent.into_add_torrent()?;
+                self.add_torrent(add, Some(opts)).await
+            }
+            .await;
+            match added {
+                Ok(r) => handles.extend(r.into_handle()),
+                Err(e) => warn!(?info_hash, "error adding torrent from resume file: {e:#}"),
+            }
+        }
+        Ok(handles)
+    }
+
     /// Run a callback given the currently managed torrents.
     pub fn with_torrents<R>(
         &self,
@@ -1022,13 +1546,32 @@ impl Session {
                             opts.only_files = Some(so);
                         }
                     }
+                    if !magnet.peers.is_empty() {
+                        let initial_peers = opts.initial_peers.get_or_insert_default();
+                        for peer in magnet.peers {
+                            if !initial_peers.contains(&peer) {
+                                initial_peers.push(peer);
+                            }
+                        }
+                    }
 
                     InternalAddResult {
                         info_hash,
-                        trackers: magnet
-                            .trackers
-                            .into_iter()
-                            .filter_map(|t| url::Url::parse(&t).ok())
+                        // Magnet links don't have tiers, so all trackers are announced to.
+                        trackers: merge_tracker_tiers(
+                            magnet.trackers.into_iter().map(|t| vec![t]).collect(),
+                            &opts,
+                        ),
+                        web_seeds: magnet
+                            .web_seeds
+                            .iter()
+                            .filter_map(|u| match url::Url::parse(u) {
+                                Ok(u) => Some(u),
+                                Err(e) => {
+                                    warn!(url = u, "ignoring invalid web seed in magnet: {e:#}");
+                                    None
+                                }
+                            })
                             .collect(),
                         metadata: None,
                         name: magnet.name,
@@ -1052,21 +1595,35 @@ impl Session {
                         }
                     };
 
-                    let mut trackers = torrent
+                    let mut tiers = torrent
                         .meta
-                        .iter_announce()
-                        .unique()
-                        .filter_map(|tracker| match std::str::from_utf8(tracker.as_ref()) {
-                            Ok(url) => Some(url.to_owned()),
+                        .iter_announce_tiers()
+                        .map(|tier| {
+                            tier.iter()
+                                .filter_map(|tracker| match std::str::from_utf8(tracker.as_ref()) {
+                                    Ok(url) => Some(url.to_owned()),
+                                    Err(_) => {
+                                        warn!("cannot parse tracker url as utf-8, ignoring");
+                                        None
+                                    }
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>();
+                    let trackers = merge_tracker_tiers(tiers, &opts);
+
+                    let web_seeds = torrent
+                        .meta
+                        .url_list
+                        .iter()
+                        .filter_map(|url| match std::str::from_utf8(url.as_ref()) {
+                            Ok(url) => url::Url::parse(url).ok(),
                             Err(_) => {
-                                warn!("cannot parse tracker url as utf-8, ignoring");
+                                warn!("cannot parse web seed url as utf-8, ignoring");
                                 None
                             }
                         })
-                        .collect::<Vec<_>>();
-                    if let Some(custom_trackers) = opts.trackers.clone() {
-                        trackers.extend(custom_trackers);
-                    }
+                        .collect();
 
                     InternalAddResult {
                         info_hash: torrent.meta.info_hash,
@@ -1075,10 +1632,8 @@ impl Session {
                             torrent.torrent_bytes,
                             torrent.meta.info.raw_bytes.0,
                         )?),
-                        trackers: trackers
-                            .iter()
-                            .filter_map(|t| url::Url::parse(t).ok())
-                            .collect(),
+                        trackers,
+                        web_seeds,
                         name: None,
                     }
                 }
@@ -1090,6 +1645,10 @@ impl Session {
         .boxed()
     }
 
+    pub(crate) fn default_output_folder(&self) -> &Path {
+        &self.output_folder
+    }
+
     fn get_default_subfolder_for_torrent(
         &self,
         info: &ValidatedTorrentMetaV1Info<ByteBufOwned>,
@@ -1142,19 +1701,52 @@ impl Session {
             info_hash,
             metadata,
             trackers,
+            mut web_seeds,
             name,
         } = add_res;
 
+        for url in opts.web_seeds.iter().flatten() {
+            let url = url::Url::parse(url).with_context(|| format!("invalid web seed {url:?}"))?;
+            if !web_seeds.contains(&url) {
+                web_seeds.push(url);
+            }
+        }
+        web_seeds.retain(|u| matches!(u.scheme(), "http" | "https"));
+
+        if opts.metadata_only {
+            opts.paused = true;
+        }
+
         let private = metadata.as_ref().is_some_and(|m| m.info.info().private);
+        let discovery = PeerDiscovery::new(&opts, private, info_hash);
+
+        // Bound before announcing, so that the announces have the torrent's port.
+        let torrent_listener = match opts.listen_port {
+            Some(port) if !opts.list_only => Some(self.bind_torrent_listener(port)?),
+            _ => None,
+        };
+        let listen_port = torrent_listener.as_ref().map(|l| l.bind_addr().port());
 
         let make_peer_rx = || {
             self.make_peer_rx(
                 info_hash,
+                None,
                 trackers.clone(),
                 !opts.paused && !opts.list_only,
-                opts.force_tracker_interval,
+                listen_port.or(self.announce_port),
+                TrackerCommsOptions {
+                    force_interval: opts.force_tracker_interval,
+                    announce_timeout: opts
+                        .tracker_announce_timeout
+                        .unwrap_or(DEFAULT_ANNOUNCE_TIMEOUT),
+                    announce_jitter: opts
+                        .tracker_announce_jitter
+                        .or(self.tracker_announce_jitter)
+                        .unwrap_or_default(),
+                    ..Default::default()
+                },
                 opts.initial_peers.clone().unwrap_or_default(),
-                private,
+                discovery,
             )
         };
 
@@ -1194,13 +1786,44 @@ impl Session {
 
         trace!("Torrent metadata: {:#?}", &metadata.info.info());
 
-        let only_files = compute_only_files(
+        // For magnets, privacy is only known now.
+        let discovery = PeerDiscovery::new(&opts, metadata.info.info().private, info_hash);
+
+        let mut only_files = compute_only_files(
             &metadata.info,
             opts.only_files,
             opts.only_files_regex,
             opts.list_only,
         )?;
 
+        let mut file_priority_overrides = HashMap::new();
+        if let Some(priorities) = opts.file_priorities.take() {
+            if priorities.len() != metadata.file_infos.len() {
+                bail!(
+                    "expected {} file priorities, got {}",
+                    metadata.file_infos.len(),
+                    priorities.len()
+                );
+            }
+            only_files = Some(
+                priorities
+                    .iter()
+                    .enumerate()
+                    .filter(|(id, p)| {
+                        **p != FilePriority::Skip
+                            && only_files.as_ref().is_none_or(|o| o.contains(id))
+                    })
+                    .map(|(id, _)| id)
+                    .collect(),
+            );
+            file_priority_overrides = priorities
+                .iter()
+                .copied()
+                .enumerate()
+                .filter(|(_, p)| matches!(p, FilePriority::High | FilePriority::Low))
+                .collect();
+        }
+
         let output_folder = match (opts.output_folder, opts.sub_folder) {
             (None, None) => self.output_folder.join(
                 self.get_default_subfolder_for_torrent(&metadata.info, name.as_deref())?
@@ -1213,6 +1836,26 @@ impl Session {
             (None, Some(s)) => self.output_folder.join(s),
         };
 
+        let mut metadata = metadata;
+        let original_paths = metadata
+            .file_infos
+            .iter()
+            .map(|fi| fi.relative_filename.clone())
+            .collect_vec();
+        opts.file_naming
+            .apply(&mut metadata.file_infos, &output_folder)
+            .context("error applying file naming strategy")?;
+        let named_files = metadata
+            .file_infos
+            .iter()
+            .zip(original_paths)
+            .enumerate()
+            .filter(|(_, (fi, original))| fi.relative_filename != *original)
+            .map(|(idx, (fi, _))| (idx, fi.relative_filename.clone()))
+            .collect();
+        apply_renamed_files(&mut metadata.file_infos, &opts.renamed_files)
+            .context("error applying renamed files")?;
+
         if opts.list_only {
             return Ok(AddTorrentResponse::ListOnly(ListOnlyResponse {
                 info_hash,
@@ -1260,40 +1903,98 @@ impl Session {
                 id,
                 span,
                 info_hash,
-                trackers: trackers.into_iter().collect(),
+                trackers: RwLock::new(trackers.into_iter().collect()),
+                web_seeds,
                 spawner: self.spawner.clone(),
                 peer_id: self.peer_id,
                 storage_factory,
                 options: ManagedTorrentOptions {
                     force_tracker_interval: opts.force_tracker_interval,
-                    peer_connect_timeout: peer_opts.connect_timeout,
-                    peer_read_write_timeout: peer_opts.read_write_timeout,
+                    tracker_announce_timeout: opts.tracker_announce_timeout,
+                    tracker_announce_jitter: opts
+                        .tracker_announce_jitter
+                        .or(self.tracker_announce_jitter),
+                    idle_pause_timeout: opts
+                        .idle_pause_timeout
+                        .or(self.idle_pause_timeout)
+                        .filter(|t| !t.is_zero()),
+                    peer_connect_timeout: RwLock::new(peer_opts.connect_timeout),
+                    peer_read_write_timeout: RwLock::new(peer_opts.read_write_timeout),
                     allow_overwrite: opts.overwrite,
-                    output_folder,
-                    ratelimits: opts.ratelimits,
+                    output_folder: RwLock::new(output_folder),
+                    ratelimits: Limits::new(opts.ratelimits),
                     initial_peers: opts.initial_peers.clone().unwrap_or_default(),
                     peer_limit: opts.peer_limit.or(self.peer_limit),
+                    max_half_open: opts.max_half_open.or(self.max_half_open),
+                    read_cache_bytes: opts.read_cache_bytes,
+                    allocation: opts.allocation,
+                    endgame_threshold: opts.endgame_threshold.unwrap_or(DEFAULT_ENDGAME_THRESHOLD),
+                    hash_fail_ban_threshold: opts
+                        .hash_fail_ban_threshold
+                        .unwrap_or(DEFAULT_HASH_FAIL_BAN_THRESHOLD),
+                    hashing_concurrency: opts.hashing_concurrency,
+                    unchoke_slots: opts.unchoke_slots,
+                    max_pending_write_bytes: opts.max_pending_write_bytes,
+                    listen_port,
+                    named_files,
+                    peer_filter: opts.peer_filter.take(),
+                    enable_dht: discovery.dht,
+                    enable_pex: discovery.pex,
+                    enable_lsd: discovery.lsd,
                     #[cfg(feature = "disable-upload")]
                     _disable_upload: self._disable_upload,
                 },
                 connector: self.connector.clone(),
                 session: Arc::downgrade(self),
                 magnet_name: name,
+                on_complete: opts.on_complete.take(),
+                on_complete_fired: AtomicBool::new(opts.previously_completed),
+                files_snapshot_generation: AtomicU64::new(0),
+                files_snapshot_lock: Default::default(),
+                events: TorrentEvents::new(id, self.events_tx.clone()),
+                allocation_used: RwLock::new(None),
+                wanted_ranges: Default::default(),
+                total_uploaded_bytes: AtomicU64::new(opts.uploaded_bytes),
+                total_downloaded_bytes: Default::default(),
+                tracker_swarm_stats: Default::default(),
             });
 
-            let initializing = Arc::new(TorrentStateInitializing::new(
-                minfo.clone(),
-                metadata.clone(),
-                only_files.clone(),
+            let storage = if opts.metadata_only {
+                minfo.storage_factory.create(&minfo, &metadata)?
+            } else {
                 self.spawner
-                    .block_in_place(|| minfo.storage_factory.create_and_init(&minfo, &metadata))?,
-                false,
-            ));
+                    .block_in_place(|| minfo.storage_factory.create_and_init(&minfo, &metadata))?
+            };
+            let initializing = Arc::new(
+                TorrentStateInitializing::new(
+                    minfo.clone(),
+                    metadata.clone(),
+                    only_files.clone(),
+                    storage,
+                    false,
+                )
+                .with_resume_bitfield(
+                    opts.resume_bitfield
+                        .take()
+                        .map(|b| BF::from_boxed_slice(b.into_boxed_slice())),
+                ),
+            );
             let handle = Arc::new(ManagedTorrent {
                 locked: RwLock::new(ManagedTorrentLocked {
                     paused: opts.paused,
                     state: ManagedTorrentState::Initializing(initializing),
                     only_files,
+                    seed_ratio_limit: opts.seed_ratio_limit,
+                    last_stop_reason: None,
+                    initial_check_deferred: opts.paused
+                        && (opts.defer_initial_check || opts.metadata_only),
+                    storage_init_deferred: opts.metadata_only,
+                    moving_storage: false,
+                    renamed_files: opts.renamed_files,
+                    file_priority_overrides,
+                    sequential: opts.sequential,
+                    super_seeding: opts.super_seeding,
+                    display_name: opts.display_name,
                 }),
                 state_change_notify: Notify::new(),
                 shared: minfo,
@@ -1311,11 +2012,21 @@ impl Session {
             return Err(e);
         }
 
+        if let Some(listener) = torrent_listener {
+            self.spawn_torrent_listener(&managed_torrent, listener);
+        }
+
         let _e = managed_torrent.shared.span.clone().entered();
 
-        managed_torrent
-            .start(peer_rx, opts.paused)
-            .context("error starting torrent")?;
+        if opts.metadata_only {
+            debug!("metadata only, not initializing storage until the torrent is unpaused");
+        } else if opts.paused && opts.defer_initial_check {
+            debug!("deferring initial check until the torrent is unpaused");
+        } else {
+            managed_torrent
+                .start(peer_rx, opts.paused)
+                .context("error starting torrent")?;
+        }
 
         if let Some(name) = metadata.info.name() {
             info!(?name, "added torrent");
@@ -1338,6 +2049,14 @@ impl Session {
     }
 
     pub async fn delete(&self, id: TorrentIdOrHash, delete_files: bool) -> anyhow::Result<()> {
+        self.delete_torrent(id, delete_files).await.map(|_| ())
+    }
+
+    pub(crate) async fn delete_torrent(
+        &self,
+        id: TorrentIdOrHash,
+        delete_files: bool,
+    ) -> anyhow::Result<DeletedFiles> {
         let id = match id {
             TorrentIdOrHash::Id(id) => id,
             TorrentIdOrHash::Hash(h) => self
@@ -1389,6 +2108,7 @@ impl Session {
                     .storage_factory
                     .create(removed.shared(), &metadata)
             });
+        removed.shared.events.emit(TorrentEvent::Removed);
 
         if let Some(p) = self.persistence.as_ref() {
             if let Err(e) = p.delete(id).await {
@@ -1401,18 +2121,23 @@ impl Session {
             }
         }
 
+        let mut deleted = DeletedFiles::default();
         match (storage, delete_files) {
             (Err(e), true) => return Err(e).context("torrent deleted, but could not delete files"),
             (Ok(storage), true) => {
                 debug!("will delete files");
-                remove_files_and_dirs(&metadata.file_infos, &storage);
-                if removed.shared().options.output_folder != self.output_folder
+                deleted = remove_files_and_dirs(
+                    &metadata.file_infos,
+                    &storage,
+                    &removed.shared().output_folder(),
+                );
+                if removed.shared().output_folder() != self.output_folder
                     && let Err(e) = storage.remove_directory_if_empty(Path::new(""))
                 {
                     warn!(
                         ?id,
                         "error removing {:?}: {e:#}",
-                        removed.shared().options.output_folder
+                        removed.shared().output_folder()
                     )
                 }
             }
@@ -1422,7 +2147,7 @@ impl Session {
         };
 
         info!(id, "deleted torrent");
-        Ok(())
+        Ok(deleted)
     }
 
     pub fn make_peer_rx_managed_torrent(
@@ -1431,40 +2156,87 @@ impl Session {
         announce: bool,
     ) -> Option<PeerStream> {
         let is_private = t.with_metadata(|m| m.info.info().private).unwrap_or(false);
+        let options = &t.shared().options;
         self.make_peer_rx(
             t.info_hash(),
-            t.shared().trackers.iter().cloned().collect(),
+            t.info_hash_v2().map(|h| h.truncate_for_dht()),
+            t.shared().tracker_tiers(),
             announce,
-            t.shared().options.force_tracker_interval,
-            t.shared().options.initial_peers.clone(),
-            is_private,
+            options.listen_port.or(self.announce_port),
+            options.tracker_comms_options(),
+            options.initial_peers.clone(),
+            PeerDiscovery {
+                private: is_private,
+                dht: options.enable_dht,
+                pex: options.enable_pex,
+                lsd: options.enable_lsd,
+            },
+        )
+    }
+
+    // Get a peer stream only from the given tier of trackers, e.g. the ones added to a live torrent.
+    pub(crate) fn make_tracker_rx_managed_torrent(
+        self: &Arc<Self>,
+        t: &Arc<ManagedTorrent>,
+        tiers: Vec<Vec<url::Url>>,
+    ) -> Option<PeerStream> {
+        let is_private = t.with_metadata(|m| m.info.info().private).unwrap_or(false);
+        if self.disable_trackers || is_private {
+            return None;
+        }
+        TrackerComms::start(
+            t.info_hash(),
+            self.peer_id,
+            tiers,
+            Box::new(PeerRxTorrentInfo {
+                info_hash: t.info_hash(),
+                v2_swarm: false,
+                session: self.clone(),
+            }),
+            TrackerCommsOptions {
+                disable_udp: self.connector.is_proxied() && !self.udp_tracker_client.is_proxied(),
+                announce_ipv6: self.announce_ipv6,
+                ..t.shared().options.tracker_comms_options()
+            },
+            t.shared()
+                .options
+                .listen_port
+                .or(self.announce_port)
+                .unwrap_or(4240),
+            self.tracker_reqwest_client.clone(),
+            self.udp_tracker_client.clone(),
         )
     }
 
     // Get a peer stream from both DHT and trackers.
+    #[allow(clippy::too_many_arguments)]
     fn make_peer_rx(
         self: &Arc<Self>,
         info_hash: Id20,
-        mut trackers: Vec<url::Url>,
+        info_hash_v2: Option<Id20>,
+        mut trackers: Vec<Vec<url::Url>>,
         announce: bool,
-        force_tracker_interval: Option<Duration>,
+        announce_port: Option<u16>,
+        tracker_opts: TrackerCommsOptions,
         initial_peers: Vec<SocketAddr>,
-        is_private: bool,
+        discovery: PeerDiscovery,
     ) -> Option<PeerStream> {
-        let dht_rx = if is_private {
-            None
-        } else {
-            self.dht.as_ref().map(|dht| {
-                dht.get_peers(info_hash, if announce { self.announce_port } else { None })
-            })
+        let is_private = discovery.private;
+        let dht_port = if announce { announce_port } else { None };
+        let dht_rx = match self.dht.as_ref() {
+            Some(dht) if discovery.dht => merge_two_optional_streams(
+                Some(dht.get_peers(info_hash, dht_port)),
+                info_hash_v2.map(|h| dht.get_peers(h, dht_port)),
+            ),
+            _ => None,
         };
 
-        let lsd_rx = if is_private {
+        let lsd_rx = if !discovery.lsd {
             None
         } else {
-            self.lsd.as_ref().map(|lsd| {
-                lsd.announce(info_hash, if announce { self.announce_port } else { None })
-            })
+            self.lsd
+                .as_ref()
+                .map(|lsd| lsd.announce(info_hash, if announce { announce_port } else { None }))
         };
 
         if self.disable_trackers {
@@ -1474,26 +2246,38 @@ impl Session {
         if is_private && trackers.len() > 1 {
             warn!(
                 ?info_hash,
-                "private trackers are not fully implemented, so using only the first tracker"
+                "private trackers are not fully implemented, so using only the first tier"
             );
             trackers.truncate(1);
-        } else if !self.disable_trackers {
-            trackers.extend(self.trackers.iter().cloned());
+        } else if !self.disable_trackers && !self.trackers.is_empty() {
+            trackers.extend(self.trackers.iter().map(|t| vec![t.clone()]));
         }
 
-        let tracker_rx_stats = PeerRxTorrentInfo {
-            info_hash,
-            session: self.clone(),
+        let start_trackers = |announce_hash: Id20, trackers: Vec<Vec<url::Url>>| {
+            TrackerComms::start(
+                announce_hash,
+                self.peer_id,
+                trackers,
+                Box::new(PeerRxTorrentInfo {
+                    info_hash,
+                    v2_swarm: announce_hash != info_hash,
+                    session: self.clone(),
+                }),
+                TrackerCommsOptions {
+                    // UDP only goes through the proxy with UDP ASSOCIATE.
+                    disable_udp: self.connector.is_proxied()
+                        && !self.udp_tracker_client.is_proxied(),
+                    announce_ipv6: self.announce_ipv6,
+                    ..tracker_opts
+                },
+                announce_port.unwrap_or(4240),
+                self.tracker_reqwest_client.clone(),
+                self.udp_tracker_client.clone(),
+            )
         };
-        let tracker_rx = TrackerComms::start(
-            info_hash,
-            self.peer_id,
-            trackers.into_iter().collect(),
-            Box::new(tracker_rx_stats),
-            force_tracker_interval,
-            self.announce_port().unwrap_or(4240),
-            self.reqwest_client.clone(),
-            self.udp_tracker_client.clone(),
+        let tracker_rx = merge_two_optional_streams(
+            info_hash_v2.and_then(|h| start_trackers(h, trackers.clone())),
+            start_trackers(info_hash, trackers),
         );
 
         let initial_peers_rx = if initial_peers.is_empty() {
@@ -1510,7 +2294,7 @@ impl Session {
         )
     }
 
-    async fn try_update_persistence_metadata(&self, handle: &ManagedTorrentHandle) {
+    pub(crate) async fn try_update_persistence_metadata(&self, handle: &ManagedTorrentHandle) {
         if let Some(p) = self.persistence.as_ref()
             && let Err(e) = p.update_metadata(handle.id(), handle).await
         {
@@ -1520,6 +2304,7 @@ impl Session {
 
     pub async fn pause(&self, handle: &ManagedTorrentHandle) -> anyhow::Result<()> {
         handle.pause()?;
+        handle.set_last_stop_reason(StopReason::User);
         self.try_update_persistence_metadata(handle).await;
         Ok(())
     }
@@ -1531,6 +2316,94 @@ impl Session {
         Ok(())
     }
 
+    pub async fn add_trackers(
+        self: &Arc<Self>,
+        handle: &ManagedTorrentHandle,
+        trackers: Vec<url::Url>,
+    ) -> anyhow::Result<usize> {
+        let added = handle.add_trackers(trackers)?;
+        if added > 0 {
+            self.try_update_persistence_metadata(handle).await;
+        }
+        Ok(added)
+    }
+
+    /// Move the files of a torrent to a new output folder, see [`ManagedTorrent::move_storage`].
+    pub async fn move_storage(
+        self: &Arc<Self>,
+        handle: &ManagedTorrentHandle,
+        new_output_folder: PathBuf,
+    ) -> anyhow::Result<()> {
+        handle.move_storage(new_output_folder).await?;
+        self.try_update_persistence_metadata(handle).await;
+        Ok(())
+    }
+
+    /// Rename the folder the torrent files are in, see [`ManagedTorrent::rename_output_folder`].
+    pub async fn rename_output_folder(
+        self: &Arc<Self>,
+        handle: &ManagedTorrentHandle,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        handle.rename_output_folder(name).await?;
+        self.try_update_persistence_metadata(handle).await;
+        Ok(())
+    }
+
+    /// Store a file at a different path, see [`ManagedTorrent::rename_file`].
+    pub async fn rename_file(
+        self: &Arc<Self>,
+        handle: &ManagedTorrentHandle,
+        file_index: usize,
+        new_path: PathBuf,
+    ) -> anyhow::Result<()> {
+        handle.rename_file(file_index, new_path).await?;
+        self.try_update_persistence_metadata(handle).await;
+        Ok(())
+    }
+
+    /// Set the priority of one file. FilePriority::Skip excludes the file from "only_files".
+    pub async fn set_file_priority(
+        self: &Arc<Self>,
+        handle: &ManagedTorrentHandle,
+        file_index: usize,
+        prio: FilePriority,
+    ) -> anyhow::Result<()> {
+        handle.set_file_priority(file_index, prio)?;
+        self.try_update_persistence_metadata(handle).await;
+        Ok(())
+    }
+
+    /// Same as [`ManagedTorrent::set_sequential`], also persisted.
+    pub async fn set_sequential(&self, handle: &ManagedTorrentHandle, sequential: bool) {
+        handle.set_sequential(sequential);
+        self.try_update_persistence_metadata(handle).await;
+    }
+
+    /// Same as [`ManagedTorrent::set_super_seeding`], also persisted.
+    pub async fn set_super_seeding(&self, handle: &ManagedTorrentHandle, super_seeding: bool) {
+        handle.set_super_seeding(super_seeding);
+        self.try_update_persistence_metadata(handle).await;
+    }
+
+    /// Same as [`ManagedTorrent::set_rate_limits`], also persisted.
+    pub async fn set_rate_limits(&self, handle: &ManagedTorrentHandle, limits: LimitsConfig) {
+        handle.set_rate_limits(limits);
+        self.try_update_persistence_metadata(handle).await;
+    }
+
+    /// Same as [`ManagedTorrent::set_seed_ratio_limit`], also persisted.
+    pub async fn set_seed_ratio_limit(&self, handle: &ManagedTorrentHandle, ratio: Option<f64>) {
+        handle.set_seed_ratio_limit(ratio);
+        self.try_update_persistence_metadata(handle).await;
+    }
+
+    /// Same as [`ManagedTorrent::set_display_name`], also persisted.
+    pub async fn set_display_name(&self, handle: &ManagedTorrentHandle, name: Option<String>) {
+        handle.set_display_name(name);
+        self.try_update_persistence_metadata(handle).await;
+    }
+
     pub async fn update_only_files(
         self: &Arc<Self>,
         handle: &ManagedTorrentHandle,
@@ -1541,6 +2414,21 @@ impl Session {
         Ok(())
     }
 
+    /// Select or deselect all files under a folder of a multi-file torrent.
+    /// Returns how many files changed selection.
+    pub async fn set_folder_wanted(
+        self: &Arc<Self>,
+        handle: &ManagedTorrentHandle,
+        path_prefix: &str,
+        wanted: bool,
+    ) -> anyhow::Result<usize> {
+        let changed = handle.set_folder_wanted(path_prefix, wanted)?;
+        if changed > 0 {
+            self.try_update_persistence_metadata(handle).await;
+        }
+        Ok(changed)
+    }
+
     pub fn listen_addr(&self) -> Option<SocketAddr> {
         self.listen_addr
     }
@@ -1553,9 +2441,31 @@ impl Session {
         self: &Arc<Self>,
         info_hash: Id20,
         peer_rx: PeerStream,
-        trackers: &[url::Url],
+        trackers: &[Vec<url::Url>],
         peer_opts: Option<PeerConnectionOptions>,
     ) -> anyhow::Result<ResolveMagnetResult> {
+        // Only removes the entry if it's still the one this resolve registered.
+        struct RemoveOnDrop<'a>(&'a Session, Id20, Arc<MetadataFetchProgress>);
+        impl Drop for RemoveOnDrop<'_> {
+            fn drop(&mut self) {
+                let mut fetches = self.0.metadata_fetches.write();
+                if let Entry::Occupied(e) = fetches.entry(self.1)
+                    && Arc::ptr_eq(e.get(), &self.2)
+                {
+                    e.remove();
+                }
+            }
+        }
+
+        // If the same magnet is being resolved concurrently, the first one keeps reporting
+        // its progress.
+        let progress = Arc::new(MetadataFetchProgress::default());
+        self.metadata_fetches
+            .write()
+            .entry(info_hash)
+            .or_insert_with(|| progress.clone());
+        let _remove = RemoveOnDrop(self, info_hash, progress.clone());
+
         match read_metainfo_from_peer_receiver(
             self.peer_id,
             info_hash,
@@ -1563,6 +2473,7 @@ impl Session {
             peer_rx,
             Some(self.merge_peer_opts(peer_opts)),
             self.connector.clone(),
+            progress,
         )
         .await
         {
@@ -1596,6 +2507,28 @@ impl Session {
         }
     }
 
+    /// Stats of a magnet link that is being added and is still waiting for its metadata.
+    /// The state is [`TorrentStatsState::FetchingMetadata`],
+    /// and progress and total bytes are those of the metadata.
+    ///
+    /// Returns None if the metadata of this info hash isn't being fetched.
+    pub fn metadata_fetch_stats(&self, info_hash: Id20) -> Option<TorrentStats> {
+        let (progress_bytes, total_bytes) = self.metadata_fetches.read().get(&info_hash)?.get();
+        Some(TorrentStats {
+            state: TorrentStatsState::FetchingMetadata,
+            display_name: info_hash.as_string(),
+            file_progress: Vec::new(),
+            error: None,
+            storage_missing: false,
+            progress_bytes,
+            uploaded_bytes: 0,
+            downloaded_bytes: 0,
+            total_bytes,
+            finished: false,
+            live: None,
+        })
+    }
+
     pub async fn create_and_serve_torrent(
         self: &Arc<Self>,
         path: &Path,
@@ -1644,17 +2577,43 @@ pub(crate) struct ResolveMagnetResult {
     pub seen_peers: Vec<SocketAddr>,
 }
 
-fn remove_files_and_dirs(infos: &FileInfos, files: &dyn TorrentStorage) {
+/// Files deleted from disk by [`ManagedTorrent::remove`](crate::ManagedTorrent::remove).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DeletedFiles {
+    pub files: usize,
+    /// Lengths of the deleted files. Partially downloaded files are counted with their full
+    /// length if they were preallocated or sparse.
+    pub bytes: u64,
+}
+
+fn remove_files_and_dirs(
+    infos: &FileInfos,
+    files: &dyn TorrentStorage,
+    output_folder: &Path,
+) -> DeletedFiles {
+    let mut deleted = DeletedFiles::default();
     let mut all_dirs = HashSet::new();
     for (id, fi) in infos.iter().enumerate() {
         if fi.attrs.padding {
             continue;
         }
         let mut fname = &*fi.relative_filename;
+        // Placed outside the output folder by FileNamingStrategy::Resolve. Not ours to delete.
+        if fname.is_absolute() && !fname.starts_with(output_folder) {
+            warn!(?fi.relative_filename, "not deleting a file outside of {output_folder:?}");
+            continue;
+        }
+        // Unknown for non-filesystem storages.
+        let len = std::fs::metadata(output_folder.join(fname)).map_or(0, |m| m.len());
         if let Err(e) = files.remove_file(id, fname) {
             warn!(?fi.relative_filename, error=?e, "could not delete file");
         } else {
-            debug!(?fi.relative_filename, "deleted the file")
+            debug!(?fi.relative_filename, "deleted the file");
+            deleted.files += 1;
+            deleted.bytes += len;
+        }
+        if fname.is_absolute() {
+            continue;
         }
         while let Some(parent) = fname.parent() {
             if parent != Path::new("") {
@@ -1676,25 +2635,70 @@ fn remove_files_and_dirs(infos: &FileInfos, files: &dyn TorrentStorage) {
             debug!("removed {dir:?}")
         }
     }
+    deleted
 }
 
 // Ad adapter for converting stats into the format that tracker_comms accepts.
 struct PeerRxTorrentInfo {
     info_hash: Id20,
+    // Announcing the truncated v2 info hash of a hybrid torrent. The swarm stats are only kept
+    // for the v1 swarm.
+    v2_swarm: bool,
     session: Arc<Session>,
 }
 
-impl tracker_comms::TorrentStatsProvider for PeerRxTorrentInfo {
-    fn get(&self) -> tracker_comms::TrackerCommsStats {
-        let mt = self.session.with_torrents(|torrents| {
+impl PeerRxTorrentInfo {
+    fn find_torrent(&self) -> Option<ManagedTorrentHandle> {
+        self.session.with_torrents(|torrents| {
             for (_, mt) in torrents {
                 if mt.info_hash() == self.info_hash {
                     return Some(mt.clone());
                 }
             }
             None
-        });
-        let mt = match mt {
+        })
+    }
+}
+
+impl tracker_comms::TorrentStatsProvider for PeerRxTorrentInfo {
+    fn on_swarm_stats(&self, tracker: &url::Url, stats: tracker_comms::SwarmStats) {
+        if self.v2_swarm {
+            return;
+        }
+        if let Some(mt) = self.find_torrent() {
+            mt.shared
+                .tracker_swarm_stats
+                .write()
+                .insert(tracker.clone(), stats);
+        }
+    }
+
+    fn wait_until_completed(&self) -> BoxFuture<'static, ()> {
+        let Some(mt) = self.find_torrent() else {
+            return futures::future::pending().boxed();
+        };
+        let mut rx = mt.subscribe_events();
+        async move {
+            loop {
+                match rx.recv().await {
+                    Ok(TorrentEvent::Completed) => return,
+                    Ok(_) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+                        if mt.stats().finished {
+                            return;
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                        futures::future::pending::<()>().await
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn get(&self) -> tracker_comms::TrackerCommsStats {
+        let mt = match self.find_torrent() {
             Some(mt) => mt,
             None => {
                 trace!(info_hash=?self.info_hash, "can't find torrent in the session, using default stats");
@@ -1711,6 +2715,8 @@ impl tracker_comms::TorrentStatsProvider for PeerRxTorrentInfo {
             total_bytes: stats.total_bytes,
             uploaded_bytes: stats.uploaded_bytes,
             torrent_state: match stats.state {
+                TS::FetchingMetadata => S::Initializing,
+                TS::Queued => S::Paused,
                 TS::Initializing => S::Initializing,
                 TS::Live => S::Live,
                 TS::Paused => S::Paused,
@@ -1726,14 +2732,77 @@ mod tests {
     use itertools::Itertools;
     use librqbit_core::torrent_metainfo::{TorrentMetaV1, torrent_from_bytes};
 
-    use super::torrent_file_from_info_bytes;
+    use librqbit_core::Id20;
+
+    use super::{
+        AddTorrent, AddTorrentOptions, PeerDiscovery, merge_tracker_tiers,
+        torrent_file_from_info_bytes,
+    };
+
+    #[test]
+    fn test_peer_discovery() {
+        let info_hash = Id20::default();
+        let opts = AddTorrentOptions {
+            enable_dht: Some(true),
+            enable_pex: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(
+            PeerDiscovery::new(&opts, false, info_hash),
+            PeerDiscovery {
+                private: false,
+                dht: true,
+                pex: false,
+                lsd: true,
+            }
+        );
+        assert_eq!(
+            PeerDiscovery::new(&opts, true, info_hash),
+            PeerDiscovery {
+                private: true,
+                dht: false,
+                pex: false,
+                lsd: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_merge_tracker_tiers() {
+        let opts = AddTorrentOptions {
+            tracker_tiers: Some(vec![vec!["http://a/".into(), "http://b/".into()]]),
+            trackers: Some(vec![
+                "http://a/".into(),
+                "http://c/".into(),
+                "udp://d:1".into(),
+            ]),
+            ..Default::default()
+        };
+        let tiers = merge_tracker_tiers(vec![vec!["http://own/".into(), "bad".into()]], &opts);
+        assert_eq!(
+            tiers
+                .iter()
+                .map(|tier| tier.iter().map(|t| t.as_str()).collect_vec())
+                .collect_vec(),
+            [
+                vec!["http://own/"],
+                vec!["http://a/", "http://b/"],
+                vec!["http://c/"],
+                vec!["udp://d:1"],
+            ]
+        );
+    }
 
     #[test]
     fn test_torrent_file_from_info_and_bytes() {
-        fn get_trackers(info: &TorrentMetaV1<ByteBuf>) -> Vec<url::Url> {
-            info.iter_announce()
-                .filter_map(|t| std::str::from_utf8(t.as_ref()).ok().map(|t| t.to_owned()))
-                .filter_map(|t| t.parse().ok())
+        fn get_trackers(info: &TorrentMetaV1<ByteBuf>) -> Vec<Vec<url::Url>> {
+            info.iter_announce_tiers()
+                .map(|tier| {
+                    tier.iter()
+                        .filter_map(|t| std::str::from_utf8(t.as_ref()).ok())
+                        .filter_map(|t| t.parse().ok())
+                        .collect_vec()
+                })
                 .collect_vec()
         }
 
@@ -1749,4 +2818,24 @@ mod tests {
         assert_eq!(parsed.info, generated_parsed.info);
         assert_eq!(parsed_trackers, get_trackers(&generated_parsed));
     }
+
+    #[test]
+    fn test_add_torrent_from_info_bytes() {
+        let orig_full_torrent =
+            include_bytes!("../resources/ubuntu-21.04-desktop-amd64.iso.torrent");
+        let parsed = torrent_from_bytes(&orig_full_torrent[..]).unwrap();
+
+        let add = AddTorrent::from_info_bytes(parsed.info.raw_bytes.as_ref()).unwrap();
+        let bytes = add.into_bytes();
+        let generated = torrent_from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.info_hash, generated.info_hash);
+        assert_eq!(parsed.info, generated.info);
+        assert!(generated.iter_announce().next().is_none());
+
+        // Not an info dictionary, or trailing garbage.
+        assert!(AddTorrent::from_info_bytes(b"i42e").is_err());
+        let mut trailing = parsed.info.raw_bytes.as_ref().to_vec();
+        trailing.extend_from_slice(b"i42e");
+        assert!(AddTorrent::from_info_bytes(&trailing).is_err());
+    }
 }
@@ -3,7 +3,7 @@ use std::{any::TypeId, collections::HashMap, path::PathBuf};
 use crate::{
     api::TorrentIdOrHash,
     bitv::{BitV, DiskBackedBitV},
-    bitv_factory::BitVFactory,
+    bitv_factory::{BitVFactory, FilesSnapshot},
     session::TorrentId,
     spawn_utils::BlockingSpawner,
     storage::filesystem::FilesystemStorageFactory,
@@ -19,7 +19,7 @@ use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing::{debug, trace, warn};
 
-use super::{SerializedTorrent, SessionPersistenceStore};
+use super::{PersistedTorrentOptions, SerializedTorrent, SessionPersistenceStore};
 
 #[derive(Serialize, Deserialize, Default)]
 struct SerializedSessionDatabase {
@@ -121,6 +121,10 @@ impl JsonSessionPersistenceStore {
         self.output_folder.join(format!("{info_hash:?}.bitv"))
     }
 
+    fn files_snapshot_filename(&self, info_hash: &Id20) -> PathBuf {
+        self.output_folder.join(format!("{info_hash:?}.files.json"))
+    }
+
     async fn update_db(
         &self,
         id: TorrentId,
@@ -138,7 +142,7 @@ impl JsonSessionPersistenceStore {
         let st = SerializedTorrent {
             trackers: torrent
                 .shared()
-                .trackers
+                .trackers()
                 .iter()
                 .map(|u| u.to_string())
                 .collect(),
@@ -147,7 +151,8 @@ impl JsonSessionPersistenceStore {
             torrent_bytes: Default::default(),
             only_files: torrent.only_files().clone(),
             is_paused: torrent.is_paused(),
-            output_folder: torrent.shared().options.output_folder.clone(),
+            output_folder: torrent.shared().output_folder(),
+            options: PersistedTorrentOptions::from_handle(torrent),
         };
 
         let torrent_bytes = torrent
@@ -203,6 +208,7 @@ impl BitVFactory for JsonSessionPersistenceStore {
     }
 
     async fn clear(&self, id: TorrentIdOrHash) -> anyhow::Result<()> {
+        self.clear_files_snapshot(id).await?;
         let h = self.to_hash(id).await?;
         let filename = self.bitv_filename(&h);
         tokio::fs::remove_file(&filename)
@@ -237,6 +243,52 @@ impl BitVFactory for JsonSessionPersistenceStore {
             .with_context(|| format!("error constructing MmapBitV from file {filename:?}"))?
             .into_dyn())
     }
+
+    async fn load_files_snapshot(
+        &self,
+        id: TorrentIdOrHash,
+    ) -> anyhow::Result<Option<FilesSnapshot>> {
+        let h = self.to_hash(id).await?;
+        let filename = self.files_snapshot_filename(&h);
+        let buf = match tokio::fs::read(&filename).await {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("error reading {filename:?}")),
+        };
+        serde_json::from_slice(&buf)
+            .with_context(|| format!("error deserializing {filename:?}"))
+            .map(Some)
+    }
+
+    async fn store_files_snapshot(
+        &self,
+        id: TorrentIdOrHash,
+        snapshot: &FilesSnapshot,
+    ) -> anyhow::Result<()> {
+        let h = self.to_hash(id).await?;
+        let filename = self.files_snapshot_filename(&h);
+        let tmp_filename = format!("{}.tmp", filename.to_str().context("bug")?);
+        let buf = serde_json::to_vec(snapshot).context("error serializing files snapshot")?;
+        tokio::fs::write(&tmp_filename, buf)
+            .await
+            .with_context(|| format!("error writing {tmp_filename:?}"))?;
+        tokio::fs::rename(&tmp_filename, &filename)
+            .await
+            .with_context(|| format!("error renaming {tmp_filename:?} to {filename:?}"))?;
+        trace!(?filename, "stored files snapshot");
+        Ok(())
+    }
+
+    async fn clear_files_snapshot(&self, id: TorrentIdOrHash) -> anyhow::Result<()> {
+        let h = self.to_hash(id).await?;
+        let filename = self.files_snapshot_filename(&h);
+        match tokio::fs::remove_file(&filename).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("error removing {filename:?}"))
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 #[async_trait]
@@ -265,6 +317,7 @@ impl SessionPersistenceStore for JsonSessionPersistenceStore {
             for tf in [
                 self.torrent_bytes_filename(&t.info_hash),
                 self.bitv_filename(&t.info_hash),
+                self.files_snapshot_filename(&t.info_hash),
             ] {
                 if let Err(e) = tokio::fs::remove_file(&tf).await {
                     warn!(error=?e, filename=?tf, "error removing");
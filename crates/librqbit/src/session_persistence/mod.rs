@@ -1,8 +1,13 @@
 pub mod json;
 #[cfg(feature = "postgres")]
 pub mod postgres;
+pub mod resume_file;
 
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+    sync::atomic::Ordering,
+};
 
 use anyhow::Context;
 use async_trait::async_trait;
@@ -13,10 +18,113 @@ use librqbit_core::magnet::Magnet;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
-    AddTorrent, AddTorrentOptions, bitv_factory::BitVFactory, session::TorrentId,
+    AddTorrent, AddTorrentOptions,
+    bitv_factory::BitVFactory,
+    file_info::{FileNamingStrategy, FilePriority},
+    limits::LimitsConfig,
+    session::TorrentId,
     torrent_state::ManagedTorrentHandle,
 };
 
+/// Per-torrent settings that can be changed after adding the torrent, kept across restarts.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct PersistedTorrentOptions {
+    pub display_name: Option<String>,
+    /// One per file. None if the metadata wasn't resolved, then only "only_files" is used.
+    pub file_priorities: Option<Vec<FilePriority>>,
+    pub sequential: bool,
+    pub super_seeding: bool,
+    pub ratelimits: LimitsConfig,
+    pub seed_ratio_limit: Option<f64>,
+    /// Paths chosen by the [`FileNamingStrategy`] the torrent was added with, by file index.
+    /// Only the files whose path differs from the metadata.
+    pub named_files: BTreeMap<usize, PathBuf>,
+    /// Files stored at a different path than in the metadata, by file index.
+    pub renamed_files: BTreeMap<usize, PathBuf>,
+    /// All trackers including the torrent's own ones, grouped in tiers.
+    pub tracker_tiers: Vec<Vec<String>>,
+    /// Peer discovery that was turned off. None means the default (on).
+    pub enable_dht: Option<bool>,
+    pub enable_pex: Option<bool>,
+    pub enable_lsd: Option<bool>,
+    /// Bytes uploaded so far, including in previous sessions.
+    pub uploaded_bytes: u64,
+    /// The torrent finished downloading at some point, so "on_complete" isn't called again.
+    pub completed: bool,
+    /// All web seeds, including the ones from the magnet link or the options.
+    pub web_seeds: Vec<String>,
+    /// The port of the torrent's own listener, asked for again so that the swarm can still
+    /// reach us on it.
+    pub listen_port: Option<u16>,
+}
+
+impl PersistedTorrentOptions {
+    pub fn from_handle(handle: &ManagedTorrentHandle) -> Self {
+        let options = &handle.shared().options;
+        let stats = handle.stats();
+        Self {
+            display_name: handle.locked.read().display_name.clone(),
+            file_priorities: Some(handle.file_priorities()).filter(|p| !p.is_empty()),
+            sequential: handle.is_sequential(),
+            super_seeding: handle.is_super_seeding(),
+            ratelimits: handle.rate_limits(),
+            seed_ratio_limit: handle.seed_ratio_limit(),
+            named_files: options.named_files.clone(),
+            renamed_files: handle.renamed_files(),
+            tracker_tiers: handle
+                .shared()
+                .tracker_tiers()
+                .into_iter()
+                .map(|tier| tier.into_iter().map(|t| t.to_string()).collect())
+                .collect(),
+            enable_dht: (!options.enable_dht).then_some(false),
+            enable_pex: (!options.enable_pex).then_some(false),
+            enable_lsd: (!options.enable_lsd).then_some(false),
+            uploaded_bytes: stats.uploaded_bytes,
+            completed: stats.finished || handle.shared().on_complete_fired.load(Ordering::Relaxed),
+            web_seeds: handle
+                .shared()
+                .web_seeds
+                .iter()
+                .map(|u| u.to_string())
+                .collect(),
+            listen_port: options.listen_port,
+        }
+    }
+
+    pub fn apply(self, opts: &mut AddTorrentOptions) {
+        opts.display_name = self.display_name;
+        opts.file_priorities = self.file_priorities;
+        opts.sequential = self.sequential;
+        opts.super_seeding = self.super_seeding;
+        opts.ratelimits = self.ratelimits;
+        opts.seed_ratio_limit = self.seed_ratio_limit;
+        if !self.named_files.is_empty() {
+            let named_files = self.named_files;
+            opts.file_naming = FileNamingStrategy::Resolve(Box::new(move |idx, components| {
+                named_files
+                    .get(&idx)
+                    .cloned()
+                    .unwrap_or_else(|| components.iter().collect())
+            }));
+        }
+        opts.renamed_files = self.renamed_files;
+        if !self.tracker_tiers.is_empty() {
+            opts.tracker_tiers = Some(self.tracker_tiers);
+        }
+        opts.enable_dht = self.enable_dht;
+        opts.enable_pex = self.enable_pex;
+        opts.enable_lsd = self.enable_lsd;
+        opts.uploaded_bytes = self.uploaded_bytes;
+        opts.previously_completed = self.completed;
+        if !self.web_seeds.is_empty() {
+            opts.web_seeds = Some(self.web_seeds);
+        }
+        opts.listen_port = self.listen_port;
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SerializedTorrent {
     #[serde(
@@ -30,6 +138,8 @@ pub struct SerializedTorrent {
     output_folder: PathBuf,
     only_files: Option<Vec<usize>>,
     is_paused: bool,
+    #[serde(default)]
+    options: PersistedTorrentOptions,
 }
 
 impl SerializedTorrent {
@@ -37,19 +147,27 @@ impl SerializedTorrent {
         &self.info_hash
     }
     pub fn into_add_torrent(self) -> anyhow::Result<(AddTorrent<'static>, AddTorrentOptions)> {
-        let add_torrent = if !self.torrent_bytes.is_empty() {
-            AddTorrent::TorrentFileBytes(self.torrent_bytes)
+        // The flat list is only used for torrents stored before the tiers were, as it would
+        // put every tracker in its own tier.
+        let trackers: Vec<String> = if self.options.tracker_tiers.is_empty() {
+            self.trackers.into_iter().collect()
         } else {
-            let magnet = Magnet::from_id20(
-                self.info_hash,
-                self.trackers.into_iter().collect(),
-                self.only_files.clone(),
+            Vec::new()
+        };
+        let (add_torrent, trackers) = if !self.torrent_bytes.is_empty() {
+            // Trackers the torrent file doesn't have, e.g. added at runtime, are appended to
+            // its own ones.
+            (
+                AddTorrent::TorrentFileBytes(self.torrent_bytes),
+                Some(trackers).filter(|t| !t.is_empty()),
             )
-            .to_string();
-            AddTorrent::from_url(magnet)
+        } else {
+            let magnet =
+                Magnet::from_id20(self.info_hash, trackers, self.only_files.clone()).to_string();
+            (AddTorrent::from_url(magnet), None)
         };
 
-        let opts = AddTorrentOptions {
+        let mut opts = AddTorrentOptions {
             paused: self.is_paused,
             output_folder: Some(
                 self.output_folder
@@ -59,8 +177,10 @@ impl SerializedTorrent {
             ),
             only_files: self.only_files,
             overwrite: true,
+            trackers,
             ..Default::default()
         };
+        self.options.apply(&mut opts);
 
         Ok((add_torrent, opts))
     }
@@ -96,3 +216,64 @@ where
 {
     Id20::deserialize(deserializer)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use librqbit_core::Id20;
+
+    use super::{PersistedTorrentOptions, SerializedTorrent};
+    use crate::AddTorrent;
+
+    fn torrent(torrent_bytes: &'static [u8], tracker_tiers: Vec<Vec<String>>) -> SerializedTorrent {
+        SerializedTorrent {
+            info_hash: Id20::new([1; 20]),
+            torrent_bytes: torrent_bytes.into(),
+            trackers: ["http://added/announce".to_owned()].into(),
+            output_folder: PathBuf::from("/tmp/out"),
+            only_files: None,
+            is_paused: false,
+            options: PersistedTorrentOptions {
+                tracker_tiers,
+                uploaded_bytes: 42,
+                listen_port: Some(6881),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_into_add_torrent_torrent_file() {
+        // Stored before the tiers were, the flat list is added to the file's own trackers.
+        let (add, opts) = torrent(b"d4:infod4:name1:aee", Vec::new())
+            .into_add_torrent()
+            .unwrap();
+        assert!(matches!(add, AddTorrent::TorrentFileBytes(..)));
+        assert_eq!(
+            opts.trackers,
+            Some(vec!["http://added/announce".to_owned()])
+        );
+        assert_eq!(opts.uploaded_bytes, 42);
+
+        let tiers = vec![vec!["http://tier/announce".to_owned()]];
+        let (_, opts) = torrent(b"d4:infod4:name1:aee", tiers.clone())
+            .into_add_torrent()
+            .unwrap();
+        assert_eq!(opts.trackers, None);
+        assert_eq!(opts.tracker_tiers, Some(tiers));
+        assert_eq!(opts.uploaded_bytes, 42);
+    }
+
+    #[test]
+    fn test_into_add_torrent_magnet() {
+        let (add, opts) = torrent(b"", Vec::new()).into_add_torrent().unwrap();
+        let AddTorrent::Url(url) = add else {
+            panic!("expected a magnet link");
+        };
+        assert!(url.contains("added"), "{url}");
+        assert_eq!(opts.trackers, None);
+        assert_eq!(opts.uploaded_bytes, 42);
+        assert_eq!(opts.listen_port, Some(6881));
+    }
+}
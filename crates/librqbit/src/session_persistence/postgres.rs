@@ -8,9 +8,9 @@ use anyhow::Context;
 use futures::{StreamExt, stream::BoxStream};
 use librqbit_core::{Id20, spawn_utils::spawn};
 use sqlx::{Pool, Postgres};
-use tracing::debug_span;
+use tracing::{debug_span, warn};
 
-use super::{SerializedTorrent, SessionPersistenceStore};
+use super::{PersistedTorrentOptions, SerializedTorrent, SessionPersistenceStore};
 
 #[derive(Debug)]
 pub struct PostgresSessionStorage {
@@ -26,10 +26,24 @@ struct TorrentsTableRecord {
     output_folder: String,
     only_files: Option<Vec<i32>>,
     is_paused: bool,
+    // JSON of PersistedTorrentOptions.
+    options: Option<String>,
 }
 
 impl TorrentsTableRecord {
     fn into_serialized_torrent(self) -> Option<(TorrentId, SerializedTorrent)> {
+        // Don't lose the torrent over its options, but don't hide that they were reset either.
+        let options = match self.options.as_deref().map(serde_json::from_str) {
+            Some(Ok(options)) => options,
+            Some(Err(e)) => {
+                warn!(
+                    id = self.id,
+                    "error parsing torrent options, using defaults: {e:#}"
+                );
+                Default::default()
+            }
+            None => Default::default(),
+        };
         Some((
             self.id as TorrentId,
             SerializedTorrent {
@@ -41,11 +55,17 @@ impl TorrentsTableRecord {
                     .only_files
                     .map(|v| v.into_iter().map(|v| v as usize).collect()),
                 is_paused: self.is_paused,
+                options,
             },
         ))
     }
 }
 
+fn serialize_options(torrent: &ManagedTorrentHandle) -> anyhow::Result<String> {
+    serde_json::to_string(&PersistedTorrentOptions::from_handle(torrent))
+        .context("error serializing torrent options")
+}
+
 impl PostgresSessionStorage {
     pub async fn new(connection_string: &str) -> anyhow::Result<Self> {
         use sqlx::postgres::PgPoolOptions;
@@ -80,6 +100,7 @@ impl PostgresSessionStorage {
         );
 
         exec!("ALTER TABLE torrents ADD COLUMN IF NOT EXISTS have_bitfield BYTEA");
+        exec!("ALTER TABLE torrents ADD COLUMN IF NOT EXISTS options TEXT");
 
         Ok(Self { pool })
     }
@@ -102,8 +123,8 @@ impl SessionPersistenceStore for PostgresSessionStorage {
             .as_ref()
             .map(|i| i.torrent_bytes.clone())
             .unwrap_or_default();
-        let q = "INSERT INTO torrents (id, info_hash, torrent_bytes, trackers, output_folder, only_files, is_paused)
-        VALUES($1, $2, $3, $4, $5, $6, $7)
+        let q = "INSERT INTO torrents (id, info_hash, torrent_bytes, trackers, output_folder, only_files, is_paused, options)
+        VALUES($1, $2, $3, $4, $5, $6, $7, $8)
         ON CONFLICT(id) DO NOTHING";
         sqlx::query(q)
             .bind::<i32>(id.try_into()?)
@@ -112,7 +133,7 @@ impl SessionPersistenceStore for PostgresSessionStorage {
             .bind(
                 torrent
                     .shared()
-                    .trackers
+                    .trackers()
                     .iter()
                     .map(|t| t.to_string())
                     .collect::<Vec<_>>(),
@@ -120,8 +141,7 @@ impl SessionPersistenceStore for PostgresSessionStorage {
             .bind(
                 torrent
                     .shared()
-                    .options
-                    .output_folder
+                    .output_folder()
                     .to_str()
                     .context("output_folder")?
                     .to_owned(),
@@ -132,6 +152,7 @@ impl SessionPersistenceStore for PostgresSessionStorage {
                     .collect::<Vec<i32>>()
             }))
             .bind(torrent.is_paused())
+            .bind(serialize_options(torrent)?)
             .execute(&self.pool)
             .await
             .context("error executing INSERT INTO torrents")?;
@@ -163,17 +184,36 @@ impl SessionPersistenceStore for PostgresSessionStorage {
         id: TorrentId,
         torrent: &ManagedTorrentHandle,
     ) -> anyhow::Result<()> {
-        sqlx::query("UPDATE torrents SET only_files = $1, is_paused = $2 WHERE id = $3")
-            .bind(torrent.only_files().map(|v| {
-                v.into_iter()
-                    .filter_map(|f| f.try_into().ok())
-                    .collect::<Vec<i32>>()
-            }))
-            .bind(torrent.is_paused())
-            .bind::<i32>(id.try_into()?)
-            .execute(&self.pool)
-            .await
-            .context("error executing UPDATE torrents")?;
+        sqlx::query(
+            "UPDATE torrents SET only_files = $1, is_paused = $2, trackers = $3, output_folder = $4, options = $5 WHERE id = $6",
+        )
+        .bind(torrent.only_files().map(|v| {
+            v.into_iter()
+                .filter_map(|f| f.try_into().ok())
+                .collect::<Vec<i32>>()
+        }))
+        .bind(torrent.is_paused())
+        .bind(
+            torrent
+                .shared()
+                .trackers()
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            torrent
+                .shared()
+                .output_folder()
+                .to_str()
+                .context("output_folder")?
+                .to_owned(),
+        )
+        .bind(serialize_options(torrent)?)
+        .bind::<i32>(id.try_into()?)
+        .execute(&self.pool)
+        .await
+        .context("error executing UPDATE torrents")?;
         Ok(())
     }
 
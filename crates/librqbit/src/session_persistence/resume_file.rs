@@ -0,0 +1,275 @@
+//! A single file with the resume state of all torrents in the session, for a clean shutdown
+//! and a fast restart without a persistence store. See [`Session::save_resume_file`] and
+//! [`Session::load_resume_file`].
+//!
+//! The file is JSON with a "version" field. Files written by newer versions are read on a
+//! best-effort basis, and torrents that can't be read are skipped with a warning.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use base64::Engine;
+use librqbit_core::{Id20, magnet::Magnet};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::warn;
+
+use crate::{AddTorrent, AddTorrentOptions, Session, torrent_state::ManagedTorrentHandle};
+
+use super::{PersistedTorrentOptions, deserialize_info_hash, serialize_info_hash};
+
+pub const RESUME_FILE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct ResumeFile {
+    pub version: u32,
+    pub torrents: Vec<ResumeTorrent>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ResumeTorrent {
+    #[serde(
+        serialize_with = "serialize_info_hash",
+        deserialize_with = "deserialize_info_hash"
+    )]
+    pub info_hash: Id20,
+    /// The .torrent file. If empty, the torrent is added as a magnet link.
+    #[serde(with = "base64_bytes", default)]
+    pub torrent_bytes: Vec<u8>,
+    pub output_folder: PathBuf,
+    pub only_files: Option<Vec<usize>>,
+    /// Pieces we have. If empty, the files are checked when the torrent is loaded.
+    #[serde(with = "base64_bytes", default)]
+    pub bitfield: Vec<u8>,
+    #[serde(default)]
+    pub tracker_tiers: Vec<Vec<String>>,
+    pub paused: bool,
+    #[serde(default)]
+    pub uploaded_bytes: u64,
+    #[serde(default)]
+    pub options: PersistedTorrentOptions,
+}
+
+impl ResumeTorrent {
+    pub fn from_handle(handle: &ManagedTorrentHandle) -> Self {
+        let torrent_bytes = handle
+            .metadata
+            .load()
+            .as_ref()
+            .map(|m| m.torrent_bytes.to_vec())
+            .unwrap_or_default();
+        Self {
+            info_hash: handle.info_hash(),
+            torrent_bytes,
+            output_folder: handle.shared().output_folder(),
+            only_files: handle.only_files(),
+            bitfield: handle
+                .with_chunk_tracker(|ct| ct.get_have_pieces().as_bytes().to_vec())
+                .unwrap_or_default(),
+            tracker_tiers: handle
+                .shared()
+                .tracker_tiers()
+                .into_iter()
+                .map(|tier| tier.into_iter().map(|u| u.to_string()).collect())
+                .collect(),
+            paused: handle.is_paused(),
+            uploaded_bytes: handle.stats().uploaded_bytes,
+            options: PersistedTorrentOptions::from_handle(handle),
+        }
+    }
+
+    pub fn into_add_torrent(self) -> anyhow::Result<(AddTorrent<'static>, AddTorrentOptions)> {
+        let add_torrent = if !self.torrent_bytes.is_empty() {
+            AddTorrent::TorrentFileBytes(self.torrent_bytes.into())
+        } else {
+            // The trackers are passed in tiers with the options below.
+            let magnet =
+                Magnet::from_id20(self.info_hash, Vec::new(), self.only_files.clone()).to_string();
+            AddTorrent::from_url(magnet)
+        };
+
+        let mut opts = AddTorrentOptions {
+            paused: self.paused,
+            output_folder: Some(
+                self.output_folder
+                    .to_str()
+                    .context("broken path")?
+                    .to_owned(),
+            ),
+            only_files: self.only_files,
+            overwrite: true,
+            tracker_tiers: Some(self.tracker_tiers),
+            resume_bitfield: Some(self.bitfield).filter(|b| !b.is_empty()),
+            ..Default::default()
+        };
+        self.options.apply(&mut opts);
+        // Older resume files only have it here.
+        opts.uploaded_bytes = self.uploaded_bytes;
+
+        Ok((add_torrent, opts))
+    }
+}
+
+impl ResumeFile {
+    pub fn from_session(session: &Session) -> Self {
+        Self {
+            version: RESUME_FILE_VERSION,
+            torrents: session.with_torrents(|torrents| {
+                torrents
+                    .map(|(_, handle)| ResumeTorrent::from_handle(handle))
+                    .collect()
+            }),
+        }
+    }
+
+    pub fn from_json(buf: &[u8]) -> anyhow::Result<Self> {
+        let mut value: serde_json::Value =
+            serde_json::from_slice(buf).context("error deserializing resume file")?;
+        let version = value
+            .get("version")
+            .and_then(|v| v.as_u64())
+            .context("resume file has no version")?;
+        if version > RESUME_FILE_VERSION as u64 {
+            warn!(
+                version,
+                supported = RESUME_FILE_VERSION,
+                "resume file is from a newer version, reading what's possible"
+            );
+        } else if version < RESUME_FILE_VERSION as u64 {
+            // Migrations from older versions go here.
+            bail!("unsupported resume file version {version}");
+        }
+
+        let torrents = match value.get_mut("torrents").map(|t| t.take()) {
+            Some(serde_json::Value::Array(torrents)) => torrents,
+            _ => bail!("resume file has no torrents"),
+        };
+        let torrents = torrents
+            .into_iter()
+            .enumerate()
+            .filter_map(
+                |(idx, t)| match serde_json::from_value::<ResumeTorrent>(t) {
+                    Ok(t) => Some(t),
+                    Err(e) => {
+                        warn!(
+                            idx,
+                            "skipping torrent that can't be read from resume file: {e:#}"
+                        );
+                        None
+                    }
+                },
+            )
+            .collect();
+        Ok(Self {
+            version: RESUME_FILE_VERSION,
+            torrents,
+        })
+    }
+
+    pub async fn read(path: &Path) -> anyhow::Result<Self> {
+        let buf = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("error reading {path:?}"))?;
+        Self::from_json(&buf)
+    }
+
+    pub async fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let buf = serde_json::to_vec(self).context("error serializing resume file")?;
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, &buf)
+            .await
+            .with_context(|| format!("error writing {tmp:?}"))?;
+        tokio::fs::rename(&tmp, path)
+            .await
+            .with_context(|| format!("error renaming {tmp:?} to {path:?}"))
+    }
+}
+
+mod base64_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(b: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        base64::engine::general_purpose::STANDARD
+            .encode(b)
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        base64::engine::general_purpose::STANDARD
+            .decode(s)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use librqbit_core::Id20;
+
+    use super::{PersistedTorrentOptions, RESUME_FILE_VERSION, ResumeFile, ResumeTorrent};
+
+    fn torrent() -> ResumeTorrent {
+        ResumeTorrent {
+            info_hash: Id20::new([1; 20]),
+            torrent_bytes: b"d4:infod4:name1:aee".to_vec(),
+            output_folder: PathBuf::from("/tmp/out"),
+            only_files: Some(vec![0, 2]),
+            bitfield: vec![0b1010_0000],
+            tracker_tiers: vec![vec!["http://tracker/announce".into()]],
+            paused: true,
+            uploaded_bytes: 42,
+            options: PersistedTorrentOptions {
+                display_name: Some("name".into()),
+                sequential: true,
+                renamed_files: [(0, PathBuf::from("renamed"))].into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_resume_file_roundtrip() {
+        let file = ResumeFile {
+            version: RESUME_FILE_VERSION,
+            torrents: vec![torrent()],
+        };
+        let json = serde_json::to_vec(&file).unwrap();
+        let read = ResumeFile::from_json(&json).unwrap();
+        assert_eq!(read.torrents.len(), 1);
+        let t = &read.torrents[0];
+        assert_eq!(t.info_hash, Id20::new([1; 20]));
+        assert_eq!(t.torrent_bytes, torrent().torrent_bytes);
+        assert_eq!(t.bitfield, vec![0b1010_0000]);
+        assert_eq!(t.only_files, Some(vec![0, 2]));
+        assert_eq!(t.uploaded_bytes, 42);
+        assert!(t.paused);
+        assert_eq!(t.options, torrent().options);
+    }
+
+    #[test]
+    fn test_resume_file_versions() {
+        let t = serde_json::to_value(torrent()).unwrap();
+
+        // Newer versions are read as far as possible, broken torrents are skipped.
+        let json = serde_json::json!({
+            "version": RESUME_FILE_VERSION + 1,
+            "torrents": [t, {"info_hash": "broken"}],
+            "something_new": true,
+        });
+        let read = ResumeFile::from_json(json.to_string().as_bytes()).unwrap();
+        assert_eq!(read.torrents.len(), 1);
+
+        // Torrents written before options were persisted.
+        let mut old = t.clone();
+        old.as_object_mut().unwrap().remove("options");
+        let json = serde_json::json!({"version": RESUME_FILE_VERSION, "torrents": [old]});
+        let read = ResumeFile::from_json(json.to_string().as_bytes()).unwrap();
+        assert_eq!(read.torrents[0].options, Default::default());
+
+        let json = serde_json::json!({"version": 0, "torrents": [t]});
+        assert!(ResumeFile::from_json(json.to_string().as_bytes()).is_err());
+        let json = serde_json::json!({"torrents": [t]});
+        assert!(ResumeFile::from_json(json.to_string().as_bytes()).is_err());
+    }
+}
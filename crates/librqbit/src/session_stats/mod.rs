@@ -1,14 +1,20 @@
 use std::{
+    net::IpAddr,
     sync::{Arc, atomic::Ordering},
     time::{Duration, Instant},
 };
 
 use anyhow::Context;
 use librqbit_core::speed_estimator::SpeedEstimator;
+use parking_lot::Mutex;
+use serde::Serialize;
 use snapshot::SessionStatsSnapshot;
 use tracing::debug_span;
 
-use crate::{Session, torrent_state::peers::stats::AggregatePeerStatsAtomic};
+use crate::{
+    Session,
+    torrent_state::{metrics::TorrentMetrics, peers::stats::AggregatePeerStatsAtomic},
+};
 
 pub mod snapshot;
 
@@ -25,6 +31,8 @@ pub struct SessionStats {
     pub down_speed_estimator: SpeedEstimator,
     pub up_speed_estimator: SpeedEstimator,
     pub startup_time: Instant,
+    // When a peer from outside the local network last connected to us.
+    pub(crate) last_remote_incoming: Mutex<Option<Instant>>,
 }
 
 impl SessionStats {
@@ -35,6 +43,7 @@ impl SessionStats {
             down_speed_estimator: SpeedEstimator::new(5),
             up_speed_estimator: SpeedEstimator::new(5),
             startup_time: Instant::now(),
+            last_remote_incoming: Mutex::new(None),
         }
     }
 }
@@ -74,4 +83,99 @@ impl Session {
     pub fn stats_snapshot(&self) -> SessionStatsSnapshot {
         SessionStatsSnapshot::from((&*self.stats, self.connector.stats().snapshot()))
     }
+
+    pub fn torrent_metrics(&self) -> Vec<TorrentMetrics> {
+        self.with_torrents(|torrents| torrents.map(|(_, t)| t.metrics_snapshot()).collect())
+    }
+
+    /// Append per-torrent metrics and their totals in the Prometheus text format.
+    pub fn torrent_metrics_prometheus(&self, out: &mut String) {
+        crate::torrent_state::metrics::write_prometheus(&self.torrent_metrics(), out)
+    }
+
+    /// Guess whether peers from the internet can connect to us, e.g. if the listen port
+    /// is forwarded through NAT. Useful to diagnose why there are no incoming connections.
+    ///
+    /// It's based on the incoming connections seen recently, so it's only meaningful
+    /// after the session has been running for a while with some live torrents. It's async so
+    /// that active probes (e.g. asking a tracker) can be added later without an API change.
+    pub async fn check_incoming_connectivity(&self) -> ConnectivityReport {
+        let (inbound_peers, outbound_peers) = self.with_torrents(|torrents| {
+            let mut inbound = Vec::new();
+            let mut outbound = 0;
+            for live in torrents.filter_map(|(_, t)| t.live()) {
+                inbound.extend(live.live_inbound_peer_addrs());
+                outbound += live.live_outbound_peer_count();
+            }
+            (inbound, outbound)
+        });
+
+        let since_last_remote_incoming =
+            self.stats.last_remote_incoming.lock().map(|t| t.elapsed());
+        let listen_port = self
+            .listen_addr()
+            .map(|_| self.announce_port().unwrap_or_default());
+        let likely_reachable = listen_port.is_some()
+            && (since_last_remote_incoming.is_some_and(|d| d < REACHABILITY_WINDOW)
+                || inbound_peers.iter().any(|a| !is_local_ip(a.ip())));
+        ConnectivityReport {
+            listen_port,
+            inbound_peers: inbound_peers.len(),
+            outbound_peers,
+            seconds_since_last_remote_incoming: since_last_remote_incoming.map(|d| d.as_secs()),
+            likely_reachable,
+        }
+    }
+}
+
+// An incoming connection from the internet within this time means we are reachable.
+const REACHABILITY_WINDOW: Duration = Duration::from_secs(3600);
+
+/// See [`Session::check_incoming_connectivity`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectivityReport {
+    /// The port announced to peers. None if not listening.
+    pub listen_port: Option<u16>,
+    /// Connected peers that connected to us, across all torrents.
+    pub inbound_peers: usize,
+    /// Connected peers that we connected to, across all torrents.
+    pub outbound_peers: usize,
+    /// When a peer from outside the local network last connected to us.
+    pub seconds_since_last_remote_incoming: Option<u64>,
+    pub likely_reachable: bool,
+}
+
+// Loopback, private and link-local addresses. Connections from them don't prove that we are
+// reachable from the internet.
+pub(crate) fn is_local_ip(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(a) => a.is_loopback() || a.is_private() || a.is_link_local(),
+        IpAddr::V6(a) => a.is_loopback() || a.is_unicast_link_local() || a.is_unique_local(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::is_local_ip;
+
+    #[test]
+    fn test_is_local_ip() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "192.168.0.10",
+            "169.254.1.1",
+            "::1",
+            "fe80::1",
+            "fd00::1",
+            "::ffff:192.168.0.10",
+        ] {
+            assert!(is_local_ip(ip.parse::<IpAddr>().unwrap()), "{ip}");
+        }
+        for ip in ["8.8.8.8", "2001:4860:4860::8888", "::ffff:8.8.8.8"] {
+            assert!(!is_local_ip(ip.parse::<IpAddr>().unwrap()), "{ip}");
+        }
+    }
 }
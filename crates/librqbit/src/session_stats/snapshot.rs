@@ -92,9 +92,16 @@ impl SessionStatsSnapshot {
         )
         .unwrap();
         m!(gauge, rqbit_peers_dead, self.peers.dead);
+        m!(gauge, rqbit_peers_reconnecting, self.peers.reconnecting);
         m!(gauge, rqbit_peers_not_needed, self.peers.not_needed);
         m!(gauge, rqbit_peers_queued, self.peers.queued);
         m!(gauge, rqbit_peers_queued, self.peers.seen);
         m!(gauge, rqbit_peers_steals, self.peers.steals);
+        m!(
+            counter,
+            rqbit_peers_endgame_duplicate_requests,
+            self.peers.endgame_duplicate_requests
+        );
+        m!(counter, rqbit_peers_banned, self.peers.banned);
     }
 }
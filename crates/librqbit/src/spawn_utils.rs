@@ -52,6 +52,41 @@ impl BlockingSpawner {
         f()
     }
 
+    /// like "block_in_place_with_semaphore" but runs "f" on up to "concurrency" threads at once,
+    /// each holding a permit. Waits for one permit only, and takes the others if they are free.
+    pub async fn block_in_place_concurrently<F: Fn() -> R + Sync, R: Send>(
+        &self,
+        concurrency: usize,
+        f: F,
+    ) -> Vec<R> {
+        let mut permits = vec![
+            self.concurrent_block_in_place_semaphore
+                .acquire()
+                .await
+                .unwrap(),
+        ];
+        while permits.len() < concurrency {
+            match self.concurrent_block_in_place_semaphore.try_acquire() {
+                Ok(permit) => permits.push(permit),
+                Err(_) => break,
+            }
+        }
+        let run = || {
+            std::thread::scope(|s| {
+                let threads = (1..permits.len()).map(|_| s.spawn(&f)).collect::<Vec<_>>();
+                let mut results = vec![f()];
+                for t in threads {
+                    results.push(t.join().unwrap_or_else(|e| std::panic::resume_unwind(e)));
+                }
+                results
+            })
+        };
+        if self.allow_block_in_place {
+            return tokio::task::block_in_place(run);
+        }
+        run()
+    }
+
     pub fn semaphore(&self) -> Arc<Semaphore> {
         self.concurrent_block_in_place_semaphore.clone()
     }
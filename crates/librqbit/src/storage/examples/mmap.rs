@@ -5,6 +5,7 @@ use parking_lot::RwLock;
 use crate::{
     FileInfos, ManagedTorrentShared,
     storage::{StorageFactory, StorageFactoryExt, TorrentStorage},
+    torrent_state::TorrentMetadata,
 };
 
 #[derive(Default, Clone)]
@@ -18,14 +19,18 @@ pub struct MmapStorage {
 impl StorageFactory for MmapStorageFactory {
     type Storage = MmapStorage;
 
-    fn create(&self, info: &ManagedTorrentShared) -> anyhow::Result<Self::Storage> {
+    fn create(
+        &self,
+        _shared: &ManagedTorrentShared,
+        metadata: &TorrentMetadata,
+    ) -> anyhow::Result<Self::Storage> {
         Ok(MmapStorage {
             mmap: RwLock::new(
                 MmapOptions::new()
-                    .len(info.lengths.total_length().try_into()?)
+                    .len(metadata.lengths().total_length().try_into()?)
                     .map_anon()?,
             ),
-            file_infos: info.file_infos.clone(),
+            file_infos: metadata.file_infos.clone(),
         })
     }
 
@@ -63,15 +68,15 @@ impl TorrentStorage for MmapStorage {
         anyhow::bail!("not implemented")
     }
 
-    fn init(&mut self, _meta: &ManagedTorrentShared) -> anyhow::Result<()> {
+    fn init(
+        &mut self,
+        _shared: &ManagedTorrentShared,
+        _metadata: &TorrentMetadata,
+    ) -> anyhow::Result<()> {
         Ok(())
     }
 
     fn remove_directory_if_empty(&self, _path: &std::path::Path) -> anyhow::Result<()> {
         Ok(())
     }
-
-    fn on_piece_completed(&self, _file_id: usize, _offset: u64) -> anyhow::Result<()> {
-        Ok(())
-    }
 }
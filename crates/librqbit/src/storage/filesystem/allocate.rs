@@ -0,0 +1,62 @@
+use std::fs::File;
+
+use crate::storage::Allocation;
+
+/// Allocate disk blocks for the first "len" bytes of the file.
+///
+/// Returns Allocation::Sparse if that isn't supported by the platform or the filesystem.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub fn try_allocate_full(f: &File, len: u64) -> Allocation {
+    if len == 0 {
+        return Allocation::Full;
+    }
+    let len = match i64::try_from(len) {
+        Ok(len) => len,
+        Err(_) => return Allocation::Sparse,
+    };
+    match nix::fcntl::posix_fallocate(f, 0, len) {
+        Ok(()) => Allocation::Full,
+        Err(e) => {
+            tracing::debug!("posix_fallocate failed: {e:#}");
+            Allocation::Sparse
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+pub fn try_allocate_full(_f: &File, _len: u64) -> Allocation {
+    Allocation::Sparse
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::storage::Allocation;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_try_allocate_full() {
+        use std::os::unix::fs::MetadataExt;
+
+        let td = TempDir::with_prefix("test_try_allocate_full").unwrap();
+        let f = std::fs::File::create(td.path().join("file")).unwrap();
+        let len = 1024 * 1024;
+        f.set_len(len).unwrap();
+
+        assert_eq!(super::try_allocate_full(&f, len), Allocation::Full);
+        let meta = f.metadata().unwrap();
+        assert_eq!(meta.len(), len);
+        assert!(meta.blocks() * 512 >= len);
+    }
+
+    #[test]
+    fn test_try_allocate_full_empty() {
+        let td = TempDir::with_prefix("test_try_allocate_full_empty").unwrap();
+        let f = std::fs::File::create(td.path().join("file")).unwrap();
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+        assert_eq!(super::try_allocate_full(&f, 0), Allocation::Full);
+        #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+        assert_eq!(super::try_allocate_full(&f, 0), Allocation::Sparse);
+    }
+}
@@ -12,7 +12,7 @@ use crate::{
     torrent_state::{ManagedTorrentShared, TorrentMetadata},
 };
 
-use crate::storage::{StorageFactory, TorrentStorage};
+use crate::storage::{Allocation, StorageFactory, TorrentStorage};
 
 use super::opened_file::OpenedFile;
 
@@ -28,7 +28,7 @@ impl StorageFactory for FilesystemStorageFactory {
         _metadata: &TorrentMetadata,
     ) -> anyhow::Result<FilesystemStorage> {
         Ok(FilesystemStorage {
-            output_folder: shared.options.output_folder.clone(),
+            output_folder: shared.output_folder(),
             opened_files: Default::default(),
         })
     }
@@ -97,6 +97,26 @@ impl TorrentStorage for FilesystemStorage {
         Ok(f.lock_read()?.set_len(len)?)
     }
 
+    fn allocate_file(
+        &self,
+        file_id: usize,
+        len: u64,
+        allocation: Allocation,
+    ) -> anyhow::Result<Allocation> {
+        match allocation {
+            Allocation::Sparse => {
+                self.ensure_file_length(file_id, len)?;
+                Ok(Allocation::Sparse)
+            }
+            Allocation::Full => {
+                let f = self.opened_files.get(file_id).context("no such file")?;
+                let f = f.lock_read()?;
+                f.set_len(len)?;
+                Ok(super::allocate::try_allocate_full(&f, len))
+            }
+        }
+    }
+
     fn take(&self) -> anyhow::Result<Box<dyn TorrentStorage>> {
         Ok(Box::new(Self {
             opened_files: self
@@ -125,6 +145,24 @@ impl TorrentStorage for FilesystemStorage {
         &mut self,
         shared: &ManagedTorrentShared,
         metadata: &TorrentMetadata,
+    ) -> anyhow::Result<()> {
+        self.open_files(metadata, shared.options.allow_overwrite)
+    }
+
+    fn reopen(
+        &mut self,
+        _shared: &ManagedTorrentShared,
+        metadata: &TorrentMetadata,
+    ) -> anyhow::Result<()> {
+        self.open_files(metadata, true)
+    }
+}
+
+impl FilesystemStorage {
+    fn open_files(
+        &mut self,
+        metadata: &TorrentMetadata,
+        allow_overwrite: bool,
     ) -> anyhow::Result<()> {
         let mut files = Vec::<OpenedFile>::new();
         for file_details in metadata.file_infos.iter() {
@@ -137,7 +175,7 @@ impl TorrentStorage for FilesystemStorage {
                 continue;
             };
             std::fs::create_dir_all(full_path.parent().context("bug: no parent")?)?;
-            let f = if shared.options.allow_overwrite {
+            let f = if allow_overwrite {
                 OpenOptions::new()
                     .create(true)
                     .truncate(false)
@@ -6,7 +6,7 @@ use parking_lot::RwLock;
 
 use crate::torrent_state::{ManagedTorrentShared, TorrentMetadata};
 
-use crate::storage::{StorageFactory, StorageFactoryExt, TorrentStorage};
+use crate::storage::{Allocation, StorageFactory, StorageFactoryExt, TorrentStorage};
 
 use super::{FilesystemStorage, FilesystemStorageFactory};
 
@@ -86,6 +86,15 @@ impl TorrentStorage for MmapFilesystemStorage {
         self.fs.ensure_file_length(file_id, len)
     }
 
+    fn allocate_file(
+        &self,
+        file_id: usize,
+        len: u64,
+        allocation: Allocation,
+    ) -> anyhow::Result<Allocation> {
+        self.fs.allocate_file(file_id, len, allocation)
+    }
+
     fn take(&self) -> anyhow::Result<Box<dyn TorrentStorage>> {
         Ok(Box::new(Self {
             opened_mmaps: self
@@ -107,6 +116,21 @@ impl TorrentStorage for MmapFilesystemStorage {
         metadata: &TorrentMetadata,
     ) -> anyhow::Result<()> {
         self.fs.init(shared, metadata)?;
+        self.map_files(metadata)
+    }
+
+    fn reopen(
+        &mut self,
+        shared: &ManagedTorrentShared,
+        metadata: &TorrentMetadata,
+    ) -> anyhow::Result<()> {
+        self.fs.reopen(shared, metadata)?;
+        self.map_files(metadata)
+    }
+}
+
+impl MmapFilesystemStorage {
+    fn map_files(&mut self, metadata: &TorrentMetadata) -> anyhow::Result<()> {
         let mut mmaps = Vec::new();
         for (idx, file) in self.fs.opened_files.iter().enumerate() {
             let fg = file.lock_write()?;
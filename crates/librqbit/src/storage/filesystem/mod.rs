@@ -1,3 +1,4 @@
+mod allocate;
 mod fs;
 mod mmap;
 mod opened_file;
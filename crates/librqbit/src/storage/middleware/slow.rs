@@ -16,7 +16,7 @@ use parking_lot::Mutex;
 
 use crate::{
     ManagedTorrentShared,
-    storage::{StorageFactory, StorageFactoryExt, TorrentStorage},
+    storage::{Allocation, StorageFactory, StorageFactoryExt, TorrentStorage},
     torrent_state::TorrentMetadata,
 };
 
@@ -113,6 +113,15 @@ impl<U: TorrentStorage> TorrentStorage for SlowStorage<U> {
         self.underlying.ensure_file_length(file_id, length)
     }
 
+    fn allocate_file(
+        &self,
+        file_id: usize,
+        length: u64,
+        allocation: Allocation,
+    ) -> anyhow::Result<Allocation> {
+        self.underlying.allocate_file(file_id, length, allocation)
+    }
+
     fn take(&self) -> anyhow::Result<Box<dyn TorrentStorage>> {
         anyhow::bail!("not implemented")
     }
@@ -128,4 +137,12 @@ impl<U: TorrentStorage> TorrentStorage for SlowStorage<U> {
     ) -> anyhow::Result<()> {
         self.underlying.init(shared, metadata)
     }
+
+    fn reopen(
+        &mut self,
+        shared: &ManagedTorrentShared,
+        metadata: &TorrentMetadata,
+    ) -> anyhow::Result<()> {
+        self.underlying.reopen(shared, metadata)
+    }
 }
@@ -4,7 +4,7 @@ A storage middleware that logs the time underlying storage operations took.
 
 use crate::{
     ManagedTorrentShared,
-    storage::{StorageFactory, StorageFactoryExt, TorrentStorage},
+    storage::{Allocation, StorageFactory, StorageFactoryExt, TorrentStorage},
     torrent_state::TorrentMetadata,
 };
 
@@ -98,6 +98,15 @@ impl<U: TorrentStorage> TorrentStorage for TimingStorage<U> {
         self.underlying.ensure_file_length(file_id, length)
     }
 
+    fn allocate_file(
+        &self,
+        file_id: usize,
+        length: u64,
+        allocation: Allocation,
+    ) -> anyhow::Result<Allocation> {
+        self.underlying.allocate_file(file_id, length, allocation)
+    }
+
     fn take(&self) -> anyhow::Result<Box<dyn TorrentStorage>> {
         Ok(Box::new(TimingStorage {
             underlying: self.underlying.take()?,
@@ -116,4 +125,12 @@ impl<U: TorrentStorage> TorrentStorage for TimingStorage<U> {
     ) -> anyhow::Result<()> {
         self.underlying.init(shared, metadata)
     }
+
+    fn reopen(
+        &mut self,
+        shared: &ManagedTorrentShared,
+        metadata: &TorrentMetadata,
+    ) -> anyhow::Result<()> {
+        self.underlying.reopen(shared, metadata)
+    }
 }
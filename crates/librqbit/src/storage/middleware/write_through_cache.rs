@@ -14,7 +14,7 @@ use parking_lot::RwLock;
 
 use crate::{
     FileInfos, ManagedTorrentShared,
-    storage::{StorageFactory, StorageFactoryExt, TorrentStorage},
+    storage::{Allocation, StorageFactory, StorageFactoryExt, TorrentStorage},
     torrent_state::TorrentMetadata,
 };
 
@@ -111,6 +111,15 @@ impl<U: TorrentStorage> TorrentStorage for WriteThroughCacheStorage<U> {
         self.underlying.ensure_file_length(file_id, length)
     }
 
+    fn allocate_file(
+        &self,
+        file_id: usize,
+        length: u64,
+        allocation: Allocation,
+    ) -> anyhow::Result<Allocation> {
+        self.underlying.allocate_file(file_id, length, allocation)
+    }
+
     fn take(&self) -> anyhow::Result<Box<dyn TorrentStorage>> {
         let replacement_cache = LruCache::new(NonZeroUsize::new(1).context("unreachable")?);
         let lru = std::mem::replace(&mut *self.lru.write(), replacement_cache);
@@ -133,4 +142,12 @@ impl<U: TorrentStorage> TorrentStorage for WriteThroughCacheStorage<U> {
     ) -> anyhow::Result<()> {
         self.underlying.init(shared, metadata)
     }
+
+    fn reopen(
+        &mut self,
+        shared: &ManagedTorrentShared,
+        metadata: &TorrentMetadata,
+    ) -> anyhow::Result<()> {
+        self.underlying.reopen(shared, metadata)
+    }
 }
@@ -37,9 +37,21 @@ use std::{
 };
 
 use librqbit_core::lengths::ValidPieceIndex;
+use serde::{Deserialize, Serialize};
 
 use crate::torrent_state::{ManagedTorrentShared, TorrentMetadata};
 
+/// How to allocate disk space for the output files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Allocation {
+    /// Only set the file length, the blocks are allocated as pieces get written.
+    #[default]
+    Sparse,
+    /// Allocate all blocks upfront (fallocate). Avoids fragmentation on some filesystems.
+    Full,
+}
+
 pub trait StorageFactory: Send + Sync + Any {
     type Storage: TorrentStorage;
 
@@ -125,6 +137,16 @@ pub trait TorrentStorage: Send + Sync {
         metadata: &TorrentMetadata,
     ) -> anyhow::Result<()>;
 
+    /// Open the files that a previous init() created, e.g. after they were moved to a new
+    /// output folder. Unlike init() this must not fail if the files already exist.
+    fn reopen(
+        &mut self,
+        shared: &ManagedTorrentShared,
+        metadata: &TorrentMetadata,
+    ) -> anyhow::Result<()> {
+        self.init(shared, metadata)
+    }
+
     /// Given a file_id (which you can get more info from in init_storage() through torrent info)
     /// read buf.len() bytes into buf at offset.
     fn pread_exact(&self, file_id: usize, offset: u64, buf: &mut [u8]) -> anyhow::Result<()>;
@@ -159,6 +181,19 @@ pub trait TorrentStorage: Send + Sync {
     /// E.g. for filesystem backend ensure that the file has a certain length, and grow/shrink as needed.
     fn ensure_file_length(&self, file_id: usize, length: u64) -> anyhow::Result<()>;
 
+    /// Same as ensure_file_length(), but also allocate the space according to the requested strategy.
+    /// Returns the strategy that was actually used, as e.g. full allocation might not be supported.
+    fn allocate_file(
+        &self,
+        file_id: usize,
+        length: u64,
+        allocation: Allocation,
+    ) -> anyhow::Result<Allocation> {
+        let _ = allocation;
+        self.ensure_file_length(file_id, length)?;
+        Ok(Allocation::Sparse)
+    }
+
     /// Replace the current storage with a dummy, and return a new one that should be used instead.
     /// This is used to make the underlying object useless when e.g. pausing the torrent.
     fn take(&self) -> anyhow::Result<Box<dyn TorrentStorage>>;
@@ -187,6 +222,15 @@ impl<U: TorrentStorage + ?Sized> TorrentStorage for Box<U> {
         (**self).ensure_file_length(file_id, length)
     }
 
+    fn allocate_file(
+        &self,
+        file_id: usize,
+        length: u64,
+        allocation: Allocation,
+    ) -> anyhow::Result<Allocation> {
+        (**self).allocate_file(file_id, length, allocation)
+    }
+
     fn take(&self) -> anyhow::Result<Box<dyn TorrentStorage>> {
         (**self).take()
     }
@@ -203,6 +247,14 @@ impl<U: TorrentStorage + ?Sized> TorrentStorage for Box<U> {
         (**self).init(shared, metadata)
     }
 
+    fn reopen(
+        &mut self,
+        shared: &ManagedTorrentShared,
+        metadata: &TorrentMetadata,
+    ) -> anyhow::Result<()> {
+        (**self).reopen(shared, metadata)
+    }
+
     fn on_piece_completed(&self, piece_id: ValidPieceIndex) -> anyhow::Result<()> {
         (**self).on_piece_completed(piece_id)
     }
@@ -30,6 +30,11 @@ async fn test_e2e_download_utp() {
     _test_e2e_download_timeout_and_cleanups(ListenerMode::UtpOnly).await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_download_tcp_and_utp() {
+    _test_e2e_download_timeout_and_cleanups(ListenerMode::TcpAndUtp).await
+}
+
 async fn _test_e2e_download_timeout_and_cleanups(mode: ListenerMode) {
     let timeout = std::env::var("E2E_TIMEOUT")
         .ok()
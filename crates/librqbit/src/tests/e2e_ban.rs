@@ -0,0 +1,79 @@
+use std::{io::Write, time::Duration};
+
+use anyhow::{Context, bail};
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder, wait_until,
+    },
+};
+
+async fn e2e_ban() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 65536, Some("test_e2e_ban"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    // The seeder already checked its files, so it serves whatever is on disk now.
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(files.path().join("0.data"))?
+        .write_all(&[0u8; 65536])?;
+
+    let client_dir = TempDir::with_prefix("test_e2e_ban_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                hash_fail_ban_threshold: Some(1),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .context("expected a torrent handle")?;
+
+    // The first bad piece gets the seeder banned and disconnected.
+    wait_until(
+        || {
+            let live = handle.live().context("client torrent isn't live")?;
+            let peers = live.stats_snapshot().peer_stats;
+            if peers.banned != 1 || peers.live != 0 {
+                bail!("peers: {peers:?}");
+            }
+            Ok(())
+        },
+        Duration::from_secs(5),
+    )
+    .await?;
+
+    // It isn't connected to again, however it comes up.
+    let live = handle.live().context("client torrent isn't live")?;
+    assert!(!live.add_peer_if_not_seen(seeder.addr)?);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let peers = live.stats_snapshot().peer_stats;
+    assert_eq!(peers.live, 0);
+    assert_eq!(peers.connecting, 0);
+    assert_eq!(handle.stats().progress_bytes, 0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_ban() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_ban()).await?
+}
@@ -0,0 +1,59 @@
+use std::num::NonZeroU32;
+
+use chrono::NaiveTime;
+
+use crate::{
+    Session,
+    bandwidth_schedule::{BandwidthSchedule, BandwidthScheduler, BandwidthWindow},
+    limits::LimitsConfig,
+};
+
+#[tokio::test]
+async fn test_e2e_bandwidth_schedule() -> anyhow::Result<()> {
+    let dir = tempfile::TempDir::with_prefix("test_e2e_bandwidth_schedule")?;
+    let default_limits = LimitsConfig {
+        upload_bps: NonZeroU32::new(5_000_000),
+        download_bps: None,
+    };
+    let session = Session::new_with_opts(
+        dir.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ratelimits: default_limits,
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let t = |h| NaiveTime::from_hms_opt(h, 0, 0).unwrap();
+    let day_limits = LimitsConfig {
+        upload_bps: NonZeroU32::new(100_000),
+        download_bps: NonZeroU32::new(1_000_000),
+    };
+    let scheduler = BandwidthScheduler::new(
+        &session,
+        BandwidthSchedule {
+            windows: vec![BandwidthWindow {
+                start: t(8),
+                end: t(23),
+                limits: day_limits,
+            }],
+        },
+    );
+
+    scheduler.tick_at(t(12));
+    assert_eq!(session.ratelimits.get_config(), day_limits);
+    assert_eq!(scheduler.active_limits(), day_limits);
+
+    // Changed by hand, kept until the window changes.
+    session.ratelimits.set_download_bps(None);
+    scheduler.tick_at(t(13));
+    assert_eq!(session.ratelimits.get_download_bps(), None);
+
+    scheduler.tick_at(t(2));
+    assert_eq!(session.ratelimits.get_config(), default_limits);
+    assert_eq!(scheduler.active_window(), None);
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::{num::NonZeroU32, time::Duration};
+
+use anyhow::Context;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+async fn e2e_choking() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 131072, Some("test_e2e_choking"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let seeder = start_test_seeder(
+        files.path(),
+        &torrent,
+        AddTorrentOptions {
+            unchoke_slots: Some(1),
+            // Slow enough for the client to still be connected when we check below.
+            ratelimits: crate::limits::LimitsConfig {
+                upload_bps: NonZeroU32::new(32768),
+                download_bps: None,
+            },
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    // The seeder doesn't unchoke everyone on connect, but the interested client gets a
+    // free slot right away.
+    let client_dir = TempDir::with_prefix("test_e2e_choking_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let client_handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    client_handle.wait_for_piece(0).await?;
+
+    let live = seeder.handle.live().context("server torrent isn't live")?;
+    let unchoked = live.unchoked_peers();
+    assert_eq!(unchoked.len(), 1, "{unchoked:?}");
+    let peers = live.per_peer_stats_snapshot(Default::default()).peers;
+    assert!(
+        peers
+            .get(&unchoked[0].to_string())
+            .context("unchoked peer not in peer stats")?
+            .unchoked
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_choking() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_choking()).await?
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use tokio::{io::AsyncReadExt, time::timeout};
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    storage::{StorageFactoryExt, examples::inmemory::InMemoryExampleStorageFactory},
+    tests::test_util::{create_test_client_session, setup_test_logging, start_test_seeder},
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+// Download into a non-filesystem storage and read the data back through it.
+async fn e2e_custom_storage() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(2, 8192, Some("test_e2e_custom_storage"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            name: None,
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    // The output folder must not be touched.
+    let client_dir = files.path().join("client");
+    let client_session = create_test_client_session(&client_dir).await?;
+    let client_handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                storage_factory: Some(InMemoryExampleStorageFactory::default().boxed()),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    client_handle.wait_until_completed().await?;
+
+    for file_id in 0..2 {
+        let mut buf = Vec::new();
+        client_handle
+            .clone()
+            .stream(file_id)
+            .await?
+            .read_to_end(&mut buf)
+            .await?;
+        let expected = std::fs::read(files.path().join(format!("{file_id}.data")))?;
+        assert!(buf == expected, "file {file_id} contents differ");
+    }
+    assert!(!client_dir.join("0.data").exists());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_custom_storage() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_custom_storage()).await?
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, Session, SessionOptions,
+    TorrentStatsState, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{create_default_random_dir_with_torrents, setup_test_logging},
+};
+
+async fn e2e_defer_initial_check() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files =
+        create_default_random_dir_with_torrents(2, 65536, Some("test_e2e_defer_initial_check"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let session = Session::new_with_opts(
+        files.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                paused: true,
+                defer_initial_check: true,
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+
+    // The files are all there, but nothing looks at them yet.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let stats = handle.stats();
+    assert!(
+        matches!(stats.state, TorrentStatsState::Queued),
+        "{stats:?}"
+    );
+    assert_eq!(stats.progress_bytes, 0);
+    assert!(!stats.finished);
+
+    // The check runs on the first unpause.
+    session.unpause(&handle).await?;
+    handle.wait_until_completed().await?;
+    let stats = handle.stats();
+    assert!(matches!(stats.state, TorrentStatsState::Live), "{stats:?}");
+    assert_eq!(stats.progress_bytes, 131072);
+    assert!(stats.finished);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_defer_initial_check() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_defer_initial_check()).await?
+}
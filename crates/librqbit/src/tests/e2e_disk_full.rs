@@ -0,0 +1,180 @@
+use std::{
+    io::IoSlice,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, ManagedTorrentShared, StopReason,
+    TorrentStatsState, create_torrent,
+    spawn_utils::BlockingSpawner,
+    storage::{
+        Allocation, StorageFactory, StorageFactoryExt, TorrentStorage,
+        filesystem::FilesystemStorageFactory,
+    },
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+    torrent_state::TorrentMetadata,
+};
+
+// Filesystem storage where writes fail with ENOSPC while "full" is set.
+#[derive(Clone)]
+struct DiskFullStorageFactory {
+    full: Arc<AtomicBool>,
+}
+
+impl StorageFactory for DiskFullStorageFactory {
+    type Storage = DiskFullStorage;
+
+    fn create(
+        &self,
+        shared: &ManagedTorrentShared,
+        metadata: &TorrentMetadata,
+    ) -> anyhow::Result<Self::Storage> {
+        Ok(DiskFullStorage {
+            underlying: Box::new(FilesystemStorageFactory::default().create(shared, metadata)?),
+            full: self.full.clone(),
+        })
+    }
+
+    fn clone_box(&self) -> crate::storage::BoxStorageFactory {
+        self.clone().boxed()
+    }
+}
+
+struct DiskFullStorage {
+    underlying: Box<dyn TorrentStorage>,
+    full: Arc<AtomicBool>,
+}
+
+impl DiskFullStorage {
+    fn check_full(&self) -> anyhow::Result<()> {
+        if self.full.load(Ordering::Relaxed) {
+            return Err(std::io::Error::from(std::io::ErrorKind::StorageFull))
+                .context("error writing to file");
+        }
+        Ok(())
+    }
+}
+
+impl TorrentStorage for DiskFullStorage {
+    fn init(
+        &mut self,
+        shared: &ManagedTorrentShared,
+        metadata: &TorrentMetadata,
+    ) -> anyhow::Result<()> {
+        self.underlying.init(shared, metadata)
+    }
+
+    fn pread_exact(&self, file_id: usize, offset: u64, buf: &mut [u8]) -> anyhow::Result<()> {
+        self.underlying.pread_exact(file_id, offset, buf)
+    }
+
+    fn pwrite_all(&self, file_id: usize, offset: u64, buf: &[u8]) -> anyhow::Result<()> {
+        self.check_full()?;
+        self.underlying.pwrite_all(file_id, offset, buf)
+    }
+
+    fn pwrite_all_vectored(
+        &self,
+        file_id: usize,
+        offset: u64,
+        bufs: [IoSlice<'_>; 2],
+    ) -> anyhow::Result<usize> {
+        self.check_full()?;
+        self.underlying.pwrite_all_vectored(file_id, offset, bufs)
+    }
+
+    fn remove_file(&self, file_id: usize, filename: &Path) -> anyhow::Result<()> {
+        self.underlying.remove_file(file_id, filename)
+    }
+
+    fn remove_directory_if_empty(&self, path: &Path) -> anyhow::Result<()> {
+        self.underlying.remove_directory_if_empty(path)
+    }
+
+    fn ensure_file_length(&self, file_id: usize, length: u64) -> anyhow::Result<()> {
+        self.underlying.ensure_file_length(file_id, length)
+    }
+
+    fn allocate_file(
+        &self,
+        file_id: usize,
+        length: u64,
+        allocation: Allocation,
+    ) -> anyhow::Result<Allocation> {
+        self.underlying.allocate_file(file_id, length, allocation)
+    }
+
+    fn take(&self) -> anyhow::Result<Box<dyn TorrentStorage>> {
+        Ok(Box::new(Self {
+            underlying: self.underlying.take()?,
+            full: self.full.clone(),
+        }))
+    }
+}
+
+async fn e2e_disk_full() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 131072, Some("test_e2e_disk_full"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+    assert!(seeder.handle.resume_after_disk_full().is_err());
+
+    let full = Arc::new(AtomicBool::new(true));
+    let client_dir = TempDir::with_prefix("test_e2e_disk_full_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let client_handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                storage_factory: Some(DiskFullStorageFactory { full: full.clone() }.boxed()),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+
+    // The first write fails, the torrent gets paused instead of erroring out.
+    while client_handle.last_stop_reason() != Some(StopReason::DiskFull) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let stats = client_handle.stats();
+    assert!(
+        matches!(stats.state, TorrentStatsState::Paused),
+        "{stats:?}"
+    );
+    assert!(stats.error.is_none());
+
+    full.store(false, Ordering::Relaxed);
+    client_handle.resume_after_disk_full()?;
+    // Completion means all pieces, including the ones written before, passed the hash check.
+    client_handle.wait_until_completed().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_disk_full() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_disk_full()).await?
+}
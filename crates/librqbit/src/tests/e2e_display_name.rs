@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, Session, create_torrent, spawn_utils::BlockingSpawner,
+    tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_display_name() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 1000, Some("test_e2e_display_name"));
+    let torrent =
+        create_torrent(files.path(), Default::default(), &BlockingSpawner::new(1)).await?;
+
+    let session = Session::new_with_opts(
+        files.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                paused: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+
+    let name = handle.name().unwrap();
+    assert_eq!(handle.display_name(), name);
+
+    handle.set_display_name(Some("my label".to_owned()));
+    assert_eq!(handle.display_name(), "my label");
+    assert_eq!(handle.stats().display_name, "my label");
+    // The name from the metadata stays the same.
+    assert_eq!(handle.name().unwrap(), name);
+
+    handle.set_display_name(None);
+    assert_eq!(handle.stats().display_name, name);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_display_name() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_display_name()).await?
+}
@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, ManagedTorrentState, StopReason, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+async fn e2e_download_prefix() -> anyhow::Result<()> {
+    setup_test_logging();
+    // 2 files of 8 pieces each.
+    let files = create_default_random_dir_with_torrents(2, 131072, Some("test_download_prefix"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    let client_dir = TempDir::with_prefix("test_download_prefix_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                paused: true,
+                only_files: Some(vec![0]),
+                initial_peers: Some(vec![seeder.addr]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+
+    let have = |range: std::ops::Range<usize>| {
+        handle
+            .with_chunk_tracker(|ct| ct.get_have_pieces().as_slice()[range].to_bitvec())
+            .unwrap()
+    };
+
+    // File 1 isn't selected, so only the pieces of its first 48000 bytes are downloaded.
+    let prefix = handle.download_prefix(1, 48000, true)?;
+    client_session.unpause(&handle).await?;
+    prefix.await?;
+    assert!(handle.is_paused());
+    assert_eq!(
+        handle.last_stop_reason(),
+        Some(StopReason::PrefixDownloaded)
+    );
+    assert!(have(8..11).all());
+    assert!(!have(15..16).any());
+
+    // Prefixes longer than the file cover the whole file.
+    let prefix = handle.download_prefix(1, u64::MAX, false)?;
+    client_session.unpause(&handle).await?;
+    prefix.await?;
+    assert!(have(8..16).all());
+    assert!(handle.with_state(|s| matches!(s, ManagedTorrentState::Live(_))));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_download_prefix() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_download_prefix()).await?
+}
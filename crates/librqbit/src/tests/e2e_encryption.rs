@@ -0,0 +1,106 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use anyhow::Context;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, EncryptionPolicy, Session, SessionOptions,
+    create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{create_default_random_dir_with_torrents, setup_test_logging},
+};
+
+// Download the torrent from a seeder with one encryption policy into a client with another.
+async fn download(server: EncryptionPolicy, client: EncryptionPolicy) -> anyhow::Result<()> {
+    let files = create_default_random_dir_with_torrents(2, 8192, Some("test_e2e_encryption"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let server_session = Session::new_with_opts(
+        files.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: Some(crate::listen::ListenerOptions {
+                listen_addr: (Ipv4Addr::LOCALHOST, 0).into(),
+                ..Default::default()
+            }),
+            encryption: server,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating server session")?;
+    server_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap()
+        .wait_until_completed()
+        .await?;
+    let peer = server_session
+        .listen_addr()
+        .context("expected listen_addr to be set")?;
+
+    let client_dir = TempDir::with_prefix("test_e2e_encryption_client")?;
+    let client_session = Session::new_with_opts(
+        client_dir.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            encryption: client,
+            ..Default::default()
+        },
+    )
+    .await?;
+    client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![peer]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap()
+        .wait_until_completed()
+        .await?;
+
+    let downloaded = client_dir.path().join(files.path().file_name().unwrap());
+    for name in ["0.data", "1.data"] {
+        assert_eq!(
+            std::fs::read(files.path().join(name))?,
+            std::fs::read(downloaded.join(name))?,
+        );
+    }
+    Ok(())
+}
+
+async fn e2e_encryption() -> anyhow::Result<()> {
+    setup_test_logging();
+    download(EncryptionPolicy::Require, EncryptionPolicy::Require).await?;
+    // Falls back to plaintext for peers that don't support encryption.
+    download(EncryptionPolicy::Disabled, EncryptionPolicy::Prefer).await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_encryption() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_encryption()).await?
+}
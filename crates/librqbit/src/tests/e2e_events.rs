@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, ManagedTorrentStateKind, TorrentEvent,
+    create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+async fn e2e_events() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 65536, Some("test_e2e_events"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    let client_dir = TempDir::with_prefix("test_e2e_events_client")?;
+    let session = create_test_client_session(client_dir.path()).await?;
+    let mut session_events = session.subscribe_events();
+
+    // Start paused so that no events are emitted before subscribing.
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                paused: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .context("expected a torrent handle")?;
+    handle.wait_until_initialized().await?;
+    let mut events = handle.subscribe_events();
+    session.unpause(&handle).await?;
+
+    let mut went_live = false;
+    let mut connected = false;
+    let mut pieces = Vec::new();
+    loop {
+        match events.recv().await? {
+            TorrentEvent::StateChanged(ManagedTorrentStateKind::Live) => went_live = true,
+            TorrentEvent::PeerConnected(addr) => connected |= addr == seeder.addr,
+            TorrentEvent::PieceCompleted(id) => pieces.push(id),
+            TorrentEvent::Completed => break,
+            _ => {}
+        }
+    }
+    assert!(went_live);
+    assert!(connected);
+    pieces.sort();
+    assert_eq!(pieces, [0, 1, 2, 3]);
+
+    // The session-wide channel got all of the above too, tagged with the torrent id, and the
+    // removal after them.
+    session.delete(handle.id().into(), false).await?;
+    let mut session_pieces = Vec::new();
+    let mut completed = false;
+    loop {
+        let (id, event) = session_events.recv().await?;
+        assert_eq!(id, handle.id());
+        match event {
+            TorrentEvent::PieceCompleted(id) => session_pieces.push(id),
+            TorrentEvent::Completed => completed = true,
+            TorrentEvent::Removed => break,
+            _ => {}
+        }
+    }
+    assert!(completed);
+    session_pieces.sort();
+    assert_eq!(session_pieces, pieces);
+    while !matches!(events.recv().await?, TorrentEvent::Removed) {}
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_events() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_events()).await?
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use librqbit_core::{magnet::Magnet, torrent_metainfo::torrent_from_bytes};
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+// A torrent added from a magnet link only has the info dict it got from peers. The exported
+// file is built from it and the magnet's trackers.
+async fn e2e_export_torrent_file() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files =
+        create_default_random_dir_with_torrents(2, 32768, Some("test_e2e_export_torrent_file"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    let tracker = "http://127.0.0.1:1/announce";
+    let magnet = Magnet::from_id20(torrent.info_hash(), vec![tracker.to_owned()], None).to_string();
+    let client_dir = TempDir::with_prefix("test_e2e_export_torrent_file_client")?;
+    let session = create_test_client_session(client_dir.path()).await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_url(&magnet),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .context("expected a torrent handle")?;
+    handle.wait_until_completed().await?;
+
+    let exported = handle.export_torrent_file()?;
+    let exported = torrent_from_bytes(&exported).context("error parsing exported torrent")?;
+    let original_bytes = torrent.as_bytes()?;
+    let original = torrent_from_bytes(&original_bytes)?;
+    assert_eq!(exported.info_hash, torrent.info_hash());
+    assert_eq!(
+        exported.info.raw_bytes.as_ref(),
+        original.info.raw_bytes.as_ref()
+    );
+    let announce = exported.announce.context("no announce URL")?;
+    assert_eq!(announce.as_ref(), tracker.as_bytes());
+
+    // And it can be added like any other torrent file.
+    let other_dir = TempDir::with_prefix("test_e2e_export_torrent_file_other")?;
+    let other = create_test_client_session(other_dir.path()).await?;
+    let list = other
+        .add_torrent(
+            AddTorrent::from_bytes(handle.export_torrent_file()?),
+            Some(AddTorrentOptions {
+                list_only: true,
+                ..Default::default()
+            }),
+        )
+        .await?;
+    let crate::AddTorrentResponse::ListOnly(list) = list else {
+        anyhow::bail!("expected a list-only response");
+    };
+    assert_eq!(list.info_hash, torrent.info_hash());
+    assert_eq!(list.info.iter_file_lengths().sum::<u64>(), 65536);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_export_torrent_file() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_export_torrent_file()).await?
+}
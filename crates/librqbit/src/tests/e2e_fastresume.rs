@@ -0,0 +1,177 @@
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, Session, SessionPersistenceConfig, create_torrent,
+    spawn_utils::BlockingSpawner, tests::test_util::setup_test_logging,
+    torrent_state::ManagedTorrentHandle,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn session(output: &Path, persistence: &Path) -> anyhow::Result<Arc<Session>> {
+    Session::new_with_opts(
+        output.into(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: Some(SessionPersistenceConfig::Json {
+                folder: Some(persistence.into()),
+            }),
+            fastresume: true,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")
+}
+
+// The torrent restored from persistence, after its initial check.
+async fn restored(session: &Session) -> anyhow::Result<ManagedTorrentHandle> {
+    let handle = session
+        .with_torrents(|torrents| torrents.next().map(|(_, t)| t.clone()))
+        .context("torrent wasn't restored")?;
+    handle.wait_until_initialized().await?;
+    Ok(handle)
+}
+
+// Overwrite the first piece without changing the file's size or modification time.
+fn corrupt_first_piece_keep_mtime(path: &Path) -> anyhow::Result<()> {
+    let mut f = File::options().write(true).open(path)?;
+    let modified = f.metadata()?.modified()?;
+    f.seek(SeekFrom::Start(0))?;
+    f.write_all(&[0u8; 16384])?;
+    f.set_modified(modified)?;
+    Ok(())
+}
+
+async fn e2e_fastresume() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 131072, Some("test_e2e_fastresume"));
+    let persistence = tempfile::TempDir::with_prefix("test_e2e_fastresume_persistence")?;
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let data = files.path().join("0.data");
+
+    let s = session(files.path(), persistence.path()).await?;
+    let handle = s
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                paused: true,
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+    assert!(handle.stats().finished);
+    drop(handle);
+    s.stop().await;
+    drop(s);
+
+    // The files look unchanged, so the bitfield is trusted without hashing. Sampling would
+    // have caught the corrupted first piece.
+    corrupt_first_piece_keep_mtime(&data)?;
+    let s = session(files.path(), persistence.path()).await?;
+    let handle = restored(&s).await?;
+    assert!(handle.stats().finished);
+    drop(handle);
+    s.stop().await;
+    drop(s);
+
+    // Touching the file makes the snapshot stale, so everything is hashed again.
+    File::options()
+        .write(true)
+        .open(&data)?
+        .set_modified(SystemTime::now() + Duration::from_secs(1))?;
+    let s = session(files.path(), persistence.path()).await?;
+    let handle = restored(&s).await?;
+    let stats = handle.stats();
+    assert!(!stats.finished);
+    assert_eq!(stats.progress_bytes, 131072 - 16384);
+    s.stop().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_fastresume() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_fastresume()).await?
+}
+
+// Wait for the stored files snapshot to exist or not.
+async fn wait_for_snapshot(path: &Path, exists: bool) {
+    while path.exists() != exists {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+async fn e2e_fastresume_snapshot_cleared_while_live() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 16384, Some("test_e2e_fastresume_live"));
+    let persistence = tempfile::TempDir::with_prefix("test_e2e_fastresume_live_persistence")?;
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let s = session(files.path(), persistence.path()).await?;
+    let handle = s
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                paused: true,
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+    let snapshot = persistence
+        .path()
+        .join(format!("{:?}.files.json", handle.info_hash()));
+    assert!(snapshot.exists());
+
+    // The files might change while live, so the snapshot is gone until the torrent is paused.
+    s.unpause(&handle).await?;
+    wait_for_snapshot(&snapshot, false).await;
+    s.pause(&handle).await?;
+    wait_for_snapshot(&snapshot, true).await;
+    s.stop().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_fastresume_snapshot_cleared_while_live() -> anyhow::Result<()> {
+    timeout(
+        Duration::from_secs(10),
+        e2e_fastresume_snapshot_cleared_while_live(),
+    )
+    .await?
+}
@@ -0,0 +1,76 @@
+use std::{io::SeekFrom, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    time::timeout,
+};
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, Session, create_torrent, spawn_utils::BlockingSpawner,
+    tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_file_reader() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(3, 10000, Some("test_e2e_file_reader"));
+    // Files don't start or end on piece boundaries.
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let session = Session::new_with_opts(
+        files.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                only_files: Some(vec![0, 1]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+
+    let expected = std::fs::read(files.path().join("1.data"))?;
+    let mut reader = handle.clone().file_reader(1).await?;
+    assert_eq!(reader.len(), 10000);
+
+    let mut buf = Vec::new();
+    reader.seek(SeekFrom::Start(5000)).await?;
+    reader.read_to_end(&mut buf).await?;
+    assert!(buf == expected[5000..], "contents differ after seek");
+
+    let mut buf = vec![0u8; 100];
+    reader.seek(SeekFrom::End(-100)).await?;
+    reader.read_exact(&mut buf).await?;
+    assert!(buf == expected[9900..], "contents differ at end");
+
+    assert!(handle.clone().file_reader(2).await.is_err());
+    assert!(handle.clone().file_reader(3).await.is_err());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_file_reader() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_file_reader()).await?
+}
@@ -0,0 +1,95 @@
+use std::{
+    net::Ipv4Addr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use tempfile::TempDir;
+use tokio::{net::TcpListener, time::timeout};
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        wait_until,
+    },
+};
+
+async fn e2e_half_open() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 16384, Some("test_e2e_half_open"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    // Peers that accept connections, but never complete the handshake.
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let mut peers = Vec::new();
+    let mut servers = Vec::new();
+    for _ in 0..3 {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        peers.push(listener.local_addr()?);
+        let accepted = accepted.clone();
+        servers.push(tokio::spawn(async move {
+            let mut conns = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::Relaxed);
+                conns.push(conn);
+            }
+        }));
+    }
+
+    let client_dir = TempDir::with_prefix("test_e2e_half_open_client")?;
+    let session = create_test_client_session(client_dir.path()).await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(peers),
+                max_half_open: Some(1),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .context("expected a torrent handle")?;
+
+    // One connection is stuck in the handshake, the other peers wait for it.
+    wait_until(
+        || {
+            let live = handle.live().context("torrent isn't live")?;
+            let peers = live.stats_snapshot().peer_stats;
+            if peers.connecting != 1 || peers.queued != 2 {
+                bail!("peers: {peers:?}");
+            }
+            Ok(())
+        },
+        Duration::from_secs(5),
+    )
+    .await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let live = handle.live().context("torrent isn't live")?;
+    assert_eq!(live.stats_snapshot().peer_stats.connecting, 1);
+    assert_eq!(accepted.load(Ordering::Relaxed), 1);
+
+    for server in servers {
+        server.abort();
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_half_open() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_half_open()).await?
+}
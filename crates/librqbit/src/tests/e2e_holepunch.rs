@@ -0,0 +1,192 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use librqbit_core::Id20;
+use peer_binary_protocol::{
+    Handshake, Message,
+    extended::{
+        ExtendedMessage, PeerExtendedMessageIds,
+        handshake::ExtendedHandshake,
+        ut_holepunch::{UT_HOLEPUNCH_ERR_NOT_CONNECTED, UtHolepunch, UtHolepunchMsgType},
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, Session, SessionOptions, create_torrent,
+    listen::ListenerOptions,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        TestPeerMetadata, create_default_random_dir_with_torrents, setup_test_logging,
+    },
+};
+
+// A peer speaking just enough of the protocol to exchange ut_holepunch messages.
+struct RawPeer {
+    conn: TcpStream,
+    // The extended message ids of the session.
+    ids: PeerExtendedMessageIds,
+}
+
+impl RawPeer {
+    async fn connect(addr: SocketAddr, info_hash: Id20) -> anyhow::Result<Self> {
+        let mut conn = TcpStream::connect(addr).await?;
+        let mut buf = vec![0u8; 68];
+        let len = Handshake::new(info_hash, TestPeerMetadata::good().as_peer_id())
+            .serialize_unchecked_len(&mut buf);
+        conn.write_all(&buf[..len]).await?;
+        conn.read_exact(&mut buf).await?;
+        Handshake::deserialize(&buf)?;
+
+        let mut peer = Self {
+            conn,
+            ids: Default::default(),
+        };
+        peer.send(Message::Extended(ExtendedMessage::Handshake(
+            ExtendedHandshake::new(),
+        )))
+        .await?;
+        for _ in 0..4 {
+            let msg = peer.read_message().await?;
+            if let (Message::Extended(ExtendedMessage::Handshake(hs)), _) =
+                Message::deserialize(&msg, &[])?
+            {
+                peer.ids = hs.m;
+                return Ok(peer);
+            }
+        }
+        bail!("no extended handshake received")
+    }
+
+    async fn send(&mut self, msg: Message<'_>) -> anyhow::Result<()> {
+        let mut buf = vec![0u8; 1024];
+        let ids = self.ids;
+        let len = msg.serialize(&mut buf, &|| ids)?;
+        self.conn.write_all(&buf[..len]).await?;
+        Ok(())
+    }
+
+    async fn send_holepunch(&mut self, msg: UtHolepunch) -> anyhow::Result<()> {
+        self.send(Message::Extended(ExtendedMessage::UtHolepunch(msg)))
+            .await
+    }
+
+    async fn read_message(&mut self) -> anyhow::Result<Vec<u8>> {
+        let msg_len = self.conn.read_u32().await? as usize;
+        let mut msg = vec![0u8; 4 + msg_len];
+        msg[..4].copy_from_slice(&(msg_len as u32).to_be_bytes());
+        self.conn.read_exact(&mut msg[4..]).await?;
+        Ok(msg)
+    }
+
+    async fn read_holepunch(&mut self) -> anyhow::Result<UtHolepunch> {
+        loop {
+            let msg = self.read_message().await?;
+            if let (Message::Extended(ExtendedMessage::UtHolepunch(msg)), _) =
+                Message::deserialize(&msg, &[])?
+            {
+                return Ok(msg);
+            }
+        }
+    }
+}
+
+async fn e2e_holepunch() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 16384, Some("test_e2e_holepunch"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let session = Session::new_with_opts(
+        files.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: Some(ListenerOptions {
+                listen_addr: (Ipv4Addr::LOCALHOST, 0).into(),
+                ..Default::default()
+            }),
+            disable_local_service_discovery: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")?;
+    let addr = session.listen_addr().context("expected a listen address")?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+    let info_hash = handle.info_hash();
+
+    let mut x = RawPeer::connect(addr, info_hash).await?;
+    let mut y = RawPeer::connect(addr, info_hash).await?;
+    let x_addr = x.conn.local_addr()?;
+    let y_addr = y.conn.local_addr()?;
+
+    // The session relays the rendezvous to both sides. It might not have processed Y's extended
+    // handshake yet, so retry for a bit.
+    let mut attempts = 0;
+    loop {
+        x.send_holepunch(UtHolepunch::rendezvous(y_addr)).await?;
+        let reply = x.read_holepunch().await?;
+        if reply.msg_type == UtHolepunchMsgType::Connect {
+            assert_eq!(reply.addr, y_addr);
+            break;
+        }
+        attempts += 1;
+        if attempts == 20 {
+            bail!("rendezvous failed: {reply:?}");
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(y.read_holepunch().await?, UtHolepunch::connect(x_addr));
+
+    // Peers the session isn't connected to can't be introduced.
+    let unknown: SocketAddr = (Ipv4Addr::LOCALHOST, 1).into();
+    x.send_holepunch(UtHolepunch::rendezvous(unknown)).await?;
+    assert_eq!(
+        x.read_holepunch().await?,
+        UtHolepunch::error(unknown, UT_HOLEPUNCH_ERR_NOT_CONNECTED)
+    );
+
+    // The session didn't ask for a rendezvous, so it doesn't connect anywhere it's told to.
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    x.send_holepunch(UtHolepunch::connect(listener.local_addr()?))
+        .await?;
+    assert!(
+        timeout(Duration::from_secs(1), listener.accept())
+            .await
+            .is_err(),
+        "session connected on an unsolicited holepunch connect"
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_holepunch() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_holepunch()).await?
+}
@@ -0,0 +1,86 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, Session, api::Api, create_torrent, http_api::HttpApi, spawn_utils::BlockingSpawner,
+    tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+// The API mounted into an embedder's own axum server.
+async fn e2e_http_api_router() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 1024, Some("test_http_api_router"));
+    let torrent =
+        create_torrent(files.path(), Default::default(), &BlockingSpawner::new(1)).await?;
+
+    let session = Session::new_with_opts(
+        files.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+
+    let router = HttpApi::new(Api::new(session.clone(), None, None), None).into_router(None);
+    let app = axum::Router::new().nest("/rqbit", router);
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let base = format!("http://{}/rqbit", listener.local_addr()?);
+    let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let client = reqwest::Client::new();
+    let list: serde_json::Value = client
+        .get(format!("{base}/torrents"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(
+        list["torrents"][0]["info_hash"].as_str(),
+        Some(handle.info_hash().as_string().as_str()),
+        "{list}"
+    );
+
+    client
+        .post(format!("{base}/torrents/{}/pause", handle.id()))
+        .send()
+        .await?
+        .error_for_status()?;
+    assert!(handle.is_paused());
+
+    let stats: serde_json::Value = client
+        .get(format!("{base}/torrents/{}/stats/v1", handle.id()))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(stats["state"].as_str(), Some("paused"), "{stats}");
+
+    server.abort();
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_http_api_router() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_http_api_router()).await?
+}
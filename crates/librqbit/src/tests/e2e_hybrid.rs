@@ -0,0 +1,227 @@
+use std::{collections::HashMap, net::Ipv4Addr, time::Duration};
+
+use anyhow::Context;
+use bencode::{BencodeValue, ByteBufOwned, bencode_serialize_to_writer};
+use librqbit_core::{
+    Id32,
+    v2::{MERKLE_BLOCK_SIZE, MerkleHasher, piece_layer_root},
+};
+use peer_binary_protocol::Handshake;
+use sha1w::{ISha1, Sha1};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::{
+    AddTorrent, Session,
+    listen::ListenerOptions,
+    tests::test_util::{TestPeerMetadata, setup_test_logging},
+};
+
+const PIECE_LENGTH: usize = MERKLE_BLOCK_SIZE as usize;
+const PIECE_LENGTH_U32: u32 = MERKLE_BLOCK_SIZE;
+
+type Value = BencodeValue<ByteBufOwned>;
+
+fn dict<'a>(items: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+    Value::Dict(
+        items
+            .into_iter()
+            .map(|(k, v)| (ByteBufOwned::from(k.as_bytes()), v))
+            .collect(),
+    )
+}
+
+fn bytes(b: &[u8]) -> Value {
+    Value::Bytes(ByteBufOwned::from(b))
+}
+
+// A single-file hybrid torrent. The v1 and v2 parts are computed from their own name and data.
+fn hybrid_torrent(name: &str, v1_data: &[u8], v2_name: &str, v2_data: &[u8]) -> Vec<u8> {
+    let pieces = v1_data
+        .chunks(PIECE_LENGTH)
+        .flat_map(|piece| {
+            let mut h = Sha1::new();
+            h.update(piece);
+            h.finish()
+        })
+        .collect::<Vec<u8>>();
+    let layer = v2_data
+        .chunks(PIECE_LENGTH)
+        .map(|piece| {
+            let mut h = MerkleHasher::default();
+            h.update(piece);
+            h.finish(PIECE_LENGTH / MERKLE_BLOCK_SIZE as usize)
+        })
+        .collect::<Vec<Id32>>();
+    let root = piece_layer_root(&layer, PIECE_LENGTH_U32);
+
+    let info = dict([
+        ("name", bytes(name.as_bytes())),
+        ("length", Value::Integer(v1_data.len() as i64)),
+        ("piece length", Value::Integer(PIECE_LENGTH as i64)),
+        ("pieces", bytes(&pieces)),
+        ("meta version", Value::Integer(2)),
+        (
+            "file tree",
+            dict([(
+                v2_name,
+                dict([(
+                    "",
+                    dict([
+                        ("length", Value::Integer(v2_data.len() as i64)),
+                        ("pieces root", bytes(&root.0)),
+                    ]),
+                )]),
+            )]),
+        ),
+    ]);
+    let piece_layers = Value::Dict(HashMap::from([(
+        ByteBufOwned::from(&root.0[..]),
+        bytes(&layer.iter().flat_map(|h| h.0).collect::<Vec<u8>>()),
+    )]));
+    let mut buf = Vec::new();
+    bencode_serialize_to_writer(
+        dict([("info", info), ("piece layers", piece_layers)]),
+        &mut buf,
+    )
+    .unwrap();
+    buf
+}
+
+async fn e2e_hybrid() -> anyhow::Result<()> {
+    setup_test_logging();
+    let dir = tempfile::TempDir::with_prefix("test_e2e_hybrid")?;
+    let data = (0..PIECE_LENGTH * 2 + 100)
+        .map(|i| (i / 3).to_le_bytes()[0])
+        .collect::<Vec<u8>>();
+    std::fs::write(dir.path().join("a"), &data)?;
+    // Same as the data on disk except for the last piece.
+    let mut other = data.clone();
+    *other.last_mut().unwrap() ^= 1;
+
+    let session = Session::new_with_opts(
+        dir.path().into(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")?;
+
+    let add = |torrent: Vec<u8>| {
+        let session = session.clone();
+        let output_folder = dir.path().to_str().unwrap().to_owned();
+        async move {
+            let handle = session
+                .add_torrent(
+                    AddTorrent::from_bytes(torrent),
+                    Some(crate::AddTorrentOptions {
+                        paused: true,
+                        overwrite: true,
+                        output_folder: Some(output_folder),
+                        ..Default::default()
+                    }),
+                )
+                .await?
+                .into_handle()
+                .context("expected a handle")?;
+            handle.wait_until_initialized().await?;
+            anyhow::Ok(handle)
+        }
+    };
+
+    let handle = add(hybrid_torrent("a", &data, "a", &data)).await?;
+    assert!(handle.info_hash_v2().is_some());
+    assert!(handle.stats().finished);
+
+    // The v1 hashes match the data, but the last piece doesn't match its v2 merkle hash.
+    let handle = add(hybrid_torrent("a", &data, "a", &other)).await?;
+    let stats = handle.stats();
+    assert!(!stats.finished);
+    assert_eq!(stats.progress_bytes, PIECE_LENGTH as u64 * 2);
+
+    // Hybrid torrents whose v1 and v2 parts describe different files are rejected.
+    for broken in [
+        hybrid_torrent("a", &data, "b", &data),
+        hybrid_torrent("a", &data, "a", &data[..PIECE_LENGTH * 2]),
+    ] {
+        let err = session
+            .add_torrent(AddTorrent::from_bytes(broken), None)
+            .await
+            .err()
+            .context("expected an error")?;
+        assert!(format!("{err:#}").contains("v1 and v2 parts"), "{err:#}");
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_hybrid() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_hybrid()).await?
+}
+
+// Peers from the v2 swarm connect with the truncated v2 info hash and get it back.
+async fn e2e_hybrid_v2_swarm() -> anyhow::Result<()> {
+    setup_test_logging();
+    let dir = tempfile::TempDir::with_prefix("test_e2e_hybrid_v2_swarm")?;
+    let data = (0..PIECE_LENGTH * 2 + 100)
+        .map(|i| (i / 3).to_le_bytes()[0])
+        .collect::<Vec<u8>>();
+    std::fs::write(dir.path().join("a"), &data)?;
+
+    let session = Session::new_with_opts(
+        dir.path().into(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: Some(ListenerOptions {
+                listen_addr: (Ipv4Addr::LOCALHOST, 0).into(),
+                ..Default::default()
+            }),
+            disable_local_service_discovery: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")?;
+    let addr = session.listen_addr().context("expected a listen address")?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(hybrid_torrent("a", &data, "a", &data)),
+            Some(crate::AddTorrentOptions {
+                overwrite: true,
+                output_folder: Some(dir.path().to_str().unwrap().to_owned()),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .context("expected a handle")?;
+    handle.wait_until_completed().await?;
+    let info_hash = handle
+        .info_hash_v2()
+        .context("expected a v2 info hash")?
+        .truncate_for_dht();
+    assert_ne!(info_hash, handle.info_hash());
+
+    let mut conn = TcpStream::connect(addr).await?;
+    let mut buf = vec![0u8; 68];
+    let len = Handshake::new(info_hash, TestPeerMetadata::good().as_peer_id())
+        .serialize_unchecked_len(&mut buf);
+    conn.write_all(&buf[..len]).await?;
+    conn.read_exact(&mut buf).await?;
+    let (handshake, _) = Handshake::deserialize(&buf)?;
+    assert_eq!(handshake.info_hash, info_hash);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_hybrid_v2_swarm() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_hybrid_v2_swarm()).await?
+}
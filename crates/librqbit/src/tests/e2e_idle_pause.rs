@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::{sync::broadcast, time::timeout};
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, ManagedTorrentStateKind, Session,
+    SessionOptions, StopReason, TorrentEvent, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{create_default_random_dir_with_torrents, setup_test_logging},
+};
+
+async fn wait_idle(events: &mut broadcast::Receiver<TorrentEvent>) -> anyhow::Result<()> {
+    loop {
+        match events.recv().await? {
+            TorrentEvent::Idle => return Ok(()),
+            TorrentEvent::StateChanged(_) => {}
+            e => anyhow::bail!("unexpected event {e:?}"),
+        }
+    }
+}
+
+async fn e2e_idle_pause() -> anyhow::Result<()> {
+    setup_test_logging();
+    let mut torrents = Vec::new();
+    let mut dirs = Vec::new();
+    for _ in 0..3 {
+        let files = create_default_random_dir_with_torrents(1, 8192, Some("test_e2e_idle_pause"));
+        torrents.push(
+            create_torrent(
+                files.path(),
+                CreateTorrentOptions {
+                    piece_length: Some(16384),
+                    ..Default::default()
+                },
+                &BlockingSpawner::new(1),
+            )
+            .await?,
+        );
+        dirs.push(files);
+    }
+
+    let out = TempDir::with_prefix("test_e2e_idle_pause_out")?;
+    let session = Session::new_with_opts(
+        out.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            idle_pause_timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    // Finished torrents keep seeding.
+    let seeding = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrents[0].as_bytes()?),
+            Some(AddTorrentOptions {
+                output_folder: Some(dirs[0].path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    seeding.wait_until_completed().await?;
+
+    // No peers to download from.
+    let idle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrents[1].as_bytes()?),
+            Some(AddTorrentOptions {
+                output_folder: Some(out.path().to_str().unwrap().to_owned()),
+                paused: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    idle.wait_until_initialized().await?;
+    let mut events = idle.subscribe_events();
+
+    // A zero timeout turns idle pausing off for the torrent.
+    let never_idle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrents[2].as_bytes()?),
+            Some(AddTorrentOptions {
+                output_folder: Some(out.path().to_str().unwrap().to_owned()),
+                idle_pause_timeout: Some(Duration::ZERO),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    never_idle.wait_until_initialized().await?;
+    session.unpause(&idle).await?;
+
+    wait_idle(&mut events).await?;
+    assert_eq!(idle.state_kind(), ManagedTorrentStateKind::Paused);
+    assert_eq!(idle.last_stop_reason(), Some(StopReason::Idle));
+    assert_eq!(seeding.state_kind(), ManagedTorrentStateKind::Live);
+
+    // Starting again resets the idle time.
+    session.unpause(&idle).await?;
+    assert_eq!(idle.state_kind(), ManagedTorrentStateKind::Live);
+    wait_idle(&mut events).await?;
+    assert_eq!(idle.state_kind(), ManagedTorrentStateKind::Paused);
+    assert_eq!(seeding.state_kind(), ManagedTorrentStateKind::Live);
+    assert_eq!(never_idle.state_kind(), ManagedTorrentStateKind::Live);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_idle_pause() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_idle_pause()).await?
+}
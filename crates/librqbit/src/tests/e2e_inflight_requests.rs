@@ -0,0 +1,112 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    time::timeout,
+};
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, Session, create_torrent,
+    spawn_utils::BlockingSpawner, tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_inflight_requests() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 100_000, Some("test_inflight_requests"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let info_hash = torrent.info_hash();
+    let total_pieces = torrent.as_info().info.data.pieces.as_ref().len() / 20;
+
+    // A peer that claims to have everything, unchokes us and then never answers.
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let peer_addr = listener.local_addr()?;
+    let peer = tokio::spawn(async move {
+        let (mut conn, _) = listener.accept().await?;
+        let mut handshake = [0u8; 68];
+        conn.read_exact(&mut handshake).await?;
+
+        let mut msg = Vec::new();
+        msg.push(19);
+        msg.extend_from_slice(b"BitTorrent protocol");
+        msg.extend_from_slice(&[0u8; 8]);
+        msg.extend_from_slice(&info_hash.0);
+        msg.extend_from_slice(&[1u8; 20]);
+        let bitfield_len = total_pieces.div_ceil(8);
+        msg.extend_from_slice(&(u32::try_from(bitfield_len)? + 1).to_be_bytes());
+        msg.push(5);
+        let mut bitfield = vec![0u8; bitfield_len];
+        for piece in 0..total_pieces {
+            bitfield[piece / 8] |= 0x80 >> (piece % 8);
+        }
+        msg.extend_from_slice(&bitfield);
+        msg.extend_from_slice(&[0, 0, 0, 1, 1]);
+        conn.write_all(&msg).await?;
+
+        let mut buf = [0u8; 1024];
+        while conn.read(&mut buf).await? > 0 {}
+        anyhow::Ok(())
+    });
+
+    let client_dir = tempfile::TempDir::with_prefix("test_inflight_requests_client")?;
+    let session = Session::new_with_opts(
+        client_dir.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![peer_addr]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+
+    let requests = loop {
+        let requests = handle
+            .live()
+            .map(|l| l.inflight_requests())
+            .unwrap_or_default();
+        if !requests.is_empty() {
+            break requests;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    for r in requests.iter() {
+        assert_eq!(r.peer, peer_addr);
+        assert!((r.piece as usize) < total_pieces);
+        assert!(r.len > 0 && r.len <= 16384);
+    }
+    assert!(
+        requests
+            .windows(2)
+            .all(|w| w[0].requested_at <= w[1].requested_at)
+    );
+
+    peer.abort();
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_inflight_requests() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_inflight_requests()).await?
+}
@@ -0,0 +1,74 @@
+use std::{io::Write, time::Duration};
+
+use anyhow::Context;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, Session, SessionOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{create_default_random_dir_with_torrents, setup_test_logging},
+};
+
+async fn e2e_initial_check_concurrent() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(
+        3,
+        40000,
+        Some("test_e2e_initial_check_concurrent"),
+    );
+    // Pieces span file boundaries.
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    // Corrupt piece 2, which spans the first two files.
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(files.path().join("1.data"))?
+        .write_all(&[0u8; 16])?;
+
+    let session = Session::new_with_opts(
+        files.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            runtime_worker_threads: Some(4),
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")?;
+
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                paused: true,
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                hashing_concurrency: Some(4),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+
+    let stats = handle.stats();
+    assert_eq!(stats.progress_bytes, 120000 - 16384);
+    assert_eq!(stats.file_progress, [40000 - 7232, 40000 - 9152, 40000]);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_initial_check_concurrent() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_initial_check_concurrent()).await?
+}
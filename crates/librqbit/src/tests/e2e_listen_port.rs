@@ -0,0 +1,139 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::Context;
+use peer_binary_protocol::Handshake;
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, Session, SessionOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        TestPeerMetadata, create_default_random_dir_with_torrents, setup_test_logging,
+    },
+};
+
+async fn e2e_listen_port() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 8192, Some("test_e2e_listen_port"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    // The session itself doesn't listen, the torrent does.
+    let server_session = Session::new_with_opts(
+        files.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            peer_id: Some(TestPeerMetadata::good().as_peer_id()),
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating server session")?;
+
+    // The requested port is taken, so a random one is used instead.
+    let taken = std::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    let taken_port = taken.local_addr()?.port();
+    let server_handle = server_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                listen_port: Some(taken_port),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    server_handle.wait_until_completed().await?;
+    let port = server_handle
+        .listen_port()
+        .context("expected the torrent to have a listen port")?;
+    assert_ne!(port, taken_port);
+    assert_ne!(port, 0);
+
+    // The torrent's port doesn't take connections for the session's other torrents.
+    let other_torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(32768),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let other_handle = server_session
+        .add_torrent(
+            AddTorrent::from_bytes(other_torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    other_handle.wait_until_completed().await?;
+    let mut conn = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).await?;
+    let mut buf = vec![0u8; 68];
+    let len = Handshake::new(
+        other_handle.info_hash(),
+        TestPeerMetadata::good().as_peer_id(),
+    )
+    .serialize_unchecked_len(&mut buf);
+    conn.write_all(&buf[..len]).await?;
+    assert!(
+        conn.read_exact(&mut buf).await.is_err(),
+        "connection for another torrent was accepted"
+    );
+
+    let client_dir = TempDir::with_prefix("test_e2e_listen_port_client")?;
+    let client_session = Session::new_with_opts(
+        client_dir.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            peer_id: Some(TestPeerMetadata::good().as_peer_id()),
+            ..Default::default()
+        },
+    )
+    .await?;
+    let client_handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![SocketAddr::from((Ipv4Addr::LOCALHOST, port))]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    client_handle.wait_until_completed().await?;
+    drop(taken);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_listen_port() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_listen_port()).await?
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, ManagedTorrentStateKind, Session, TorrentStatsState,
+    create_torrent, spawn_utils::BlockingSpawner, tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_metadata_only() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(2, 8192, Some("test_e2e_metadata_only"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            name: None,
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let session = Session::new_with_opts(
+        files.path().into(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")?;
+
+    let output_folder = files.path().join("out");
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                metadata_only: true,
+                output_folder: Some(output_folder.to_str().unwrap().to_owned()),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+
+    assert!(handle.is_paused());
+    assert!(matches!(handle.stats().state, TorrentStatsState::Queued));
+    let file_count = handle.with_metadata(|m| m.file_infos.len())?;
+    assert_eq!(file_count, 2);
+    assert!(!output_folder.exists());
+
+    session.unpause(&handle).await?;
+    handle.wait_until_initialized().await?;
+    assert_eq!(handle.state_kind(), ManagedTorrentStateKind::Live);
+    assert!(output_folder.join("0.data").exists());
+    assert!(output_folder.join("1.data").exists());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_metadata_only() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_metadata_only()).await?
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+async fn e2e_metrics() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 65536, Some("test_e2e_metrics"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    let client_dir = TempDir::with_prefix("test_e2e_metrics_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+    client_session.pause(&handle).await?;
+
+    let downloaded = handle.stats().downloaded_bytes;
+    assert!(downloaded >= 65536, "downloaded {downloaded}");
+    let metrics = handle.metrics_snapshot();
+    assert_eq!(metrics.downloaded_bytes, downloaded);
+    assert_eq!((metrics.have_pieces, metrics.total_pieces), (4, 4));
+    assert_eq!(metrics.live_peers, 0);
+
+    let mut prometheus = String::new();
+    client_session.torrent_metrics_prometheus(&mut prometheus);
+    assert!(
+        prometheus.contains(&format!(
+            "rqbit_torrent_downloaded_bytes{{info_hash=\"{}\"}} {downloaded}",
+            handle.info_hash().as_string()
+        )),
+        "{prometheus}"
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_metrics() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_metrics()).await?
+}
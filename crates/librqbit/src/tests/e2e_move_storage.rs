@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, ManagedTorrentStateKind, Session, create_torrent,
+    spawn_utils::BlockingSpawner, tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_move_storage() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(2, 8192, Some("test_e2e_move_storage"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            name: None,
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let original = std::fs::read(files.path().join("0.data"))?;
+
+    let session = Session::new_with_opts(
+        files.path().into(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")?;
+
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+    assert!(handle.stats().finished);
+
+    // Live torrents are paused for the move, and resumed without re-checking.
+    let live_folder = files.path().join("moved_live");
+    session.move_storage(&handle, live_folder.clone()).await?;
+    assert_eq!(handle.shared().output_folder(), live_folder);
+    assert_eq!(handle.state_kind(), ManagedTorrentStateKind::Live);
+    assert!(!files.path().join("0.data").exists());
+    assert_eq!(std::fs::read(live_folder.join("0.data"))?, original);
+
+    let new_folder = files.path().join("moved");
+    session.pause(&handle).await?;
+    session.move_storage(&handle, new_folder.clone()).await?;
+    assert_eq!(handle.shared().output_folder(), new_folder);
+    assert_eq!(handle.state_kind(), ManagedTorrentStateKind::Paused);
+    assert!(!live_folder.join("0.data").exists());
+    assert!(!live_folder.join("1.data").exists());
+    assert_eq!(std::fs::read(new_folder.join("0.data"))?, original);
+
+    // The moved files are used from now on.
+    handle.force_recheck()?;
+    handle.wait_until_initialized().await?;
+    assert_eq!(handle.state_kind(), ManagedTorrentStateKind::Paused);
+    assert!(handle.stats().finished);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_move_storage() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_move_storage()).await?
+}
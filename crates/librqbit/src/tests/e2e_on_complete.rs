@@ -0,0 +1,182 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, OnCompleteCallback, Session,
+    SessionOptions, SessionPersistenceConfig, create_torrent,
+    session_persistence::{SessionPersistenceStore, json::JsonSessionPersistenceStore},
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder, wait_until,
+    },
+};
+
+// Counts the calls, and checks the torrent is finished by the time the callback runs.
+fn counting_callback(calls: &Arc<AtomicUsize>) -> OnCompleteCallback {
+    let calls = calls.clone();
+    Box::new(move |t| {
+        assert!(
+            t.stats().finished,
+            "on_complete called on unfinished torrent"
+        );
+        calls.fetch_add(1, Ordering::Relaxed);
+    })
+}
+
+async fn wait_for_calls(calls: &AtomicUsize) -> anyhow::Result<()> {
+    wait_until(
+        || match calls.load(Ordering::Relaxed) {
+            0 => bail!("on_complete wasn't called"),
+            _ => Ok(()),
+        },
+        Duration::from_secs(5),
+    )
+    .await
+}
+
+async fn e2e_on_complete() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(2, 65536, Some("test_e2e_on_complete"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    // The seeder's files are complete on the initial check, which counts as completion.
+    let seeder_calls = Arc::new(AtomicUsize::new(0));
+    let seeder = start_test_seeder(
+        files.path(),
+        &torrent,
+        AddTorrentOptions {
+            on_complete: Some(counting_callback(&seeder_calls)),
+            ..Default::default()
+        },
+    )
+    .await?;
+    wait_for_calls(&seeder_calls).await?;
+
+    // The client fires it when the download finishes.
+    let client_dir = TempDir::with_prefix("test_e2e_on_complete_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let client_calls = Arc::new(AtomicUsize::new(0));
+    let handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                on_complete: Some(counting_callback(&client_calls)),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .context("expected a torrent handle")?;
+    handle.wait_until_completed().await?;
+    wait_for_calls(&client_calls).await?;
+
+    // It only ever fires once per torrent, even when completion is seen again.
+    client_session.pause(&handle).await?;
+    client_session.unpause(&handle).await?;
+    handle.wait_until_completed().await?;
+    seeder.session.pause(&seeder.handle).await?;
+    seeder.handle.force_recheck()?;
+    seeder.handle.wait_until_initialized().await?;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(client_calls.load(Ordering::Relaxed), 1);
+    assert_eq!(seeder_calls.load(Ordering::Relaxed), 1);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_on_complete() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_on_complete()).await?
+}
+
+// Without fastresume, a restarted torrent is fully checked again. It was complete before, so
+// that doesn't count as completion.
+async fn e2e_on_complete_restart() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files =
+        create_default_random_dir_with_torrents(1, 65536, Some("test_e2e_on_complete_restart"));
+    let persistence = TempDir::with_prefix("test_e2e_on_complete_restart_persistence")?;
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let session = Session::new_with_opts(
+        files.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            fastresume: false,
+            persistence: Some(SessionPersistenceConfig::Json {
+                folder: Some(persistence.path().into()),
+            }),
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let calls = Arc::new(AtomicUsize::new(0));
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                on_complete: Some(counting_callback(&calls)),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .context("expected a torrent handle")?;
+    wait_for_calls(&calls).await?;
+    let id = handle.id();
+    drop(handle);
+    session.stop().await;
+    drop(session);
+
+    // Add it again from what the session persisted, with the callback set again.
+    let store =
+        JsonSessionPersistenceStore::new(persistence.path().into(), BlockingSpawner::new(1))
+            .await?;
+    let (add, mut opts) = store.get(id).await?.into_add_torrent()?;
+    assert!(opts.previously_completed);
+    opts.on_complete = Some(counting_callback(&calls));
+    let session = create_test_client_session(files.path()).await?;
+    let handle = session
+        .add_torrent(add, Some(opts))
+        .await?
+        .into_handle()
+        .context("expected a torrent handle")?;
+    handle.wait_until_completed().await?;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_on_complete_restart() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_on_complete_restart()).await?
+}
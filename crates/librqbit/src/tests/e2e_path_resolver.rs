@@ -0,0 +1,95 @@
+use std::{path::PathBuf, time::Duration};
+
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, FileNamingStrategy, Session, create_torrent,
+    session_persistence::PersistedTorrentOptions, spawn_utils::BlockingSpawner,
+    tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_path_resolver() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(3, 10000, Some("test_e2e_path_resolver"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    // File 1 lives in another folder, the rest stay in the output folder.
+    let other = tempfile::TempDir::with_prefix("test_e2e_path_resolver_other")?;
+    let moved = other.path().join("moved.data");
+    std::fs::rename(files.path().join("1.data"), &moved)?;
+
+    let session = Session::new_with_opts(
+        files.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                file_naming: FileNamingStrategy::Resolve(Box::new({
+                    let moved = moved.clone();
+                    move |idx, components| match idx {
+                        1 => moved.clone(),
+                        _ => components.iter().collect::<PathBuf>(),
+                    }
+                })),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+
+    // All files are found on the initial check.
+    handle.wait_until_completed().await?;
+    assert!(!files.path().join("1.data").exists());
+
+    // The chosen paths are persisted, and the torrent finds the files there when restored.
+    let persisted = PersistedTorrentOptions::from_handle(&handle);
+    assert_eq!(persisted.named_files, [(1, moved.clone())].into());
+    session.delete(handle.id().into(), false).await?;
+    let mut opts = crate::AddTorrentOptions {
+        output_folder: Some(files.path().to_str().unwrap().to_owned()),
+        overwrite: true,
+        ..Default::default()
+    };
+    persisted.apply(&mut opts);
+    let handle = session
+        .add_torrent(AddTorrent::from_bytes(torrent.as_bytes()?), Some(opts))
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+
+    // Moving the storage leaves the file outside the output folder alone.
+    let new_folder = tempfile::TempDir::with_prefix("test_e2e_path_resolver_new")?;
+    session.pause(&handle).await?;
+    handle.move_storage(new_folder.path().to_owned()).await?;
+    assert!(new_folder.path().join("0.data").exists());
+    assert!(new_folder.path().join("2.data").exists());
+    assert!(moved.exists());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_path_resolver() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_path_resolver()).await?
+}
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+async fn e2e_peer_counts() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 65536, Some("test_e2e_peer_counts"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    let client_dir = TempDir::with_prefix("test_e2e_peer_counts_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+
+    // The seeder was the only peer, and it was connected to.
+    let live = handle.stats().live.context("client torrent isn't live")?;
+    assert_eq!(live.known_peers, 1);
+    assert_eq!(live.connecting_peers, 0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_peer_counts() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_peer_counts()).await?
+}
@@ -0,0 +1,169 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use librqbit_core::Id20;
+use peer_binary_protocol::{Handshake, Message, extended::ExtendedMessage};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, Session, SessionOptions,
+    SessionPersistenceConfig, create_torrent,
+    listen::ListenerOptions,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        TestPeerMetadata, create_default_random_dir_with_torrents, setup_test_logging,
+    },
+};
+
+// Connect to the session like a peer would and tell if its extended handshake offers PEX.
+async fn offers_pex(addr: SocketAddr, info_hash: Id20) -> anyhow::Result<bool> {
+    let mut conn = TcpStream::connect(addr).await?;
+    let mut buf = vec![0u8; 68];
+    let len = Handshake::new(info_hash, TestPeerMetadata::good().as_peer_id())
+        .serialize_unchecked_len(&mut buf);
+    conn.write_all(&buf[..len]).await?;
+    conn.read_exact(&mut buf).await?;
+    let (handshake, _) = Handshake::deserialize(&buf)?;
+    assert_eq!(handshake.info_hash, info_hash);
+    assert!(handshake.supports_extended());
+
+    // The extended handshake comes right after the handshake, maybe after the bitfield.
+    for _ in 0..4 {
+        let msg_len = conn.read_u32().await? as usize;
+        let mut msg = vec![0u8; 4 + msg_len];
+        msg[..4].copy_from_slice(&(msg_len as u32).to_be_bytes());
+        conn.read_exact(&mut msg[4..]).await?;
+        if let (Message::Extended(ExtendedMessage::Handshake(hs)), _) =
+            Message::deserialize(&msg, &[])?
+        {
+            return Ok(hs.m.ut_pex.is_some());
+        }
+    }
+    bail!("no extended handshake received")
+}
+
+async fn e2e_pex_disabled() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 8192, Some("test_e2e_pex_disabled"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let session = Session::new_with_opts(
+        files.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: Some(ListenerOptions {
+                listen_addr: (Ipv4Addr::LOCALHOST, 0).into(),
+                ..Default::default()
+            }),
+            disable_local_service_discovery: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")?;
+    let addr = session.listen_addr().context("expected a listen address")?;
+
+    for enable_pex in [Some(false), None] {
+        let handle = session
+            .add_torrent(
+                AddTorrent::from_bytes(torrent.as_bytes()?),
+                Some(AddTorrentOptions {
+                    output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                    overwrite: true,
+                    enable_pex,
+                    ..Default::default()
+                }),
+            )
+            .await?
+            .into_handle()
+            .unwrap();
+        handle.wait_until_completed().await?;
+        assert_eq!(
+            offers_pex(addr, handle.info_hash()).await?,
+            enable_pex.is_none()
+        );
+        session.delete(handle.id().into(), false).await?;
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_pex_disabled() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_pex_disabled()).await?
+}
+
+async fn persistent_session(output: &Path, persistence: &Path) -> anyhow::Result<Arc<Session>> {
+    Session::new_with_opts(
+        output.into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: Some(SessionPersistenceConfig::Json {
+                folder: Some(persistence.into()),
+            }),
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")
+}
+
+async fn e2e_peer_discovery_restore() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files =
+        create_default_random_dir_with_torrents(1, 8192, Some("test_e2e_peer_discovery_restore"));
+    let persistence =
+        tempfile::TempDir::with_prefix("test_e2e_peer_discovery_restore_persistence")?;
+    let torrent =
+        create_torrent(files.path(), Default::default(), &BlockingSpawner::new(1)).await?;
+
+    let s = persistent_session(files.path(), persistence.path()).await?;
+    s.add_torrent(
+        AddTorrent::from_bytes(torrent.as_bytes()?),
+        Some(AddTorrentOptions {
+            paused: true,
+            output_folder: Some(files.path().to_str().unwrap().to_owned()),
+            overwrite: true,
+            enable_pex: Some(false),
+            enable_lsd: Some(false),
+            ..Default::default()
+        }),
+    )
+    .await?;
+    s.stop().await;
+    drop(s);
+
+    let s = persistent_session(files.path(), persistence.path()).await?;
+    let handle = s
+        .with_torrents(|torrents| torrents.next().map(|(_, t)| t.clone()))
+        .context("torrent wasn't restored")?;
+    let options = &handle.shared().options;
+    assert!(options.enable_dht);
+    assert!(!options.enable_pex);
+    assert!(!options.enable_lsd);
+    s.stop().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_peer_discovery_restore() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_peer_discovery_restore()).await?
+}
@@ -0,0 +1,92 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    time::timeout,
+};
+
+use crate::{
+    AddTorrent, AddTorrentOptions, Session, SessionOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{create_default_random_dir_with_torrents, setup_test_logging},
+};
+
+async fn e2e_peer_id_prefix() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 1024, Some("test_peer_id_prefix"));
+    let torrent =
+        create_torrent(files.path(), Default::default(), &BlockingSpawner::new(1)).await?;
+
+    // A tracker that records the first announce.
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let tracker = format!("http://{}/announce", listener.local_addr()?);
+    let announce = tokio::spawn(async move {
+        let (mut conn, _) = listener.accept().await?;
+        let mut req = Vec::new();
+        while !req.ends_with(b"\r\n\r\n") {
+            let mut b = [0u8; 1];
+            if conn.read(&mut b).await? == 0 {
+                break;
+            }
+            req.push(b[0]);
+        }
+        let body = b"d8:intervali60e5:peers0:e";
+        conn.write_all(
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .as_bytes(),
+        )
+        .await?;
+        conn.write_all(body).await?;
+        Ok::<_, anyhow::Error>(String::from_utf8(req)?)
+    });
+
+    let dir = TempDir::with_prefix("test_peer_id_prefix_client")?;
+    let session = Session::new_with_opts(
+        dir.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            peer_id_prefix: Some(*b"-qB4650-"),
+            tracker_user_agent: Some("qBittorrent/4.6.5".into()),
+            ..Default::default()
+        },
+    )
+    .await?;
+    session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                trackers: Some(vec![tracker]),
+                ..Default::default()
+            }),
+        )
+        .await?;
+
+    let req = announce.await??.to_lowercase();
+    assert!(req.contains("peer_id=-qb4650-"), "{req}");
+    assert!(req.contains("user-agent: qbittorrent/4.6.5\r\n"), "{req}");
+
+    // Prefixes that don't look like a client signature are rejected.
+    let res = Session::new_with_opts(
+        dir.path().into(),
+        SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            peer_id_prefix: Some(*b"-qB4\n50-"),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(res.is_err());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_peer_id_prefix() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_peer_id_prefix()).await?
+}
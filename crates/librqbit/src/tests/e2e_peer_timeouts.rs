@@ -0,0 +1,96 @@
+use std::{
+    net::Ipv4Addr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use tempfile::TempDir;
+use tokio::{net::TcpListener, time::timeout};
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        wait_until,
+    },
+};
+
+async fn e2e_peer_timeouts() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 16384, Some("test_e2e_peer_timeouts"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    // A peer that accepts connections, but never sends a handshake.
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let peer = listener.local_addr()?;
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let server = tokio::spawn({
+        let accepted = accepted.clone();
+        async move {
+            let mut conns = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::Relaxed);
+                conns.push(conn);
+            }
+        }
+    });
+
+    let client_dir = TempDir::with_prefix("test_e2e_peer_timeouts_client")?;
+    let session = create_test_client_session(client_dir.path()).await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![peer]),
+                paused: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .context("expected a torrent handle")?;
+    handle.wait_until_initialized().await?;
+
+    let short = Duration::from_millis(300);
+    handle.set_peer_connect_timeout(short);
+    handle.set_peer_read_write_timeout(short);
+    let opts = handle.shared().options.peer_connection_options();
+    assert_eq!(opts.connect_timeout, Some(short));
+    assert_eq!(opts.read_write_timeout, Some(short));
+
+    // With the default of 10 seconds, the connection would still be waiting for the handshake.
+    session.unpause(&handle).await?;
+    wait_until(
+        || {
+            let live = handle.live().context("torrent isn't live")?;
+            let peers = live.stats_snapshot().peer_stats;
+            if accepted.load(Ordering::Relaxed) == 0 || peers.connecting != 0 || peers.live != 0 {
+                bail!("peers: {peers:?}");
+            }
+            Ok(())
+        },
+        Duration::from_secs(3),
+    )
+    .await?;
+
+    server.abort();
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_peer_timeouts() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_peer_timeouts()).await?
+}
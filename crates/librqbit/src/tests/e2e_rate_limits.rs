@@ -0,0 +1,75 @@
+use std::{
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
+
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, create_torrent,
+    limits::LimitsConfig,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+async fn e2e_rate_limits() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 30000, Some("test_e2e_rate_limits"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    let client_dir = TempDir::with_prefix("test_e2e_rate_limits_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let upload_only = LimitsConfig {
+        upload_bps: NonZeroU32::new(1 << 20),
+        download_bps: None,
+    };
+    let handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                ratelimits: upload_only,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    assert_eq!(handle.rate_limits(), upload_only);
+
+    // Changed at runtime. The first 16KiB pass right away, the other ~14KB take about a second.
+    let limits = LimitsConfig {
+        upload_bps: None,
+        download_bps: NonZeroU32::new(16384),
+    };
+    handle.set_rate_limits(limits);
+    let started = Instant::now();
+    handle.wait_until_completed().await?;
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(500), "{elapsed:?}");
+
+    // Kept across pause and resume.
+    client_session.pause(&handle).await?;
+    client_session.unpause(&handle).await?;
+    assert_eq!(handle.rate_limits(), limits);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_rate_limits() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_rate_limits()).await?
+}
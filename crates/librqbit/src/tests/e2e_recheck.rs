@@ -0,0 +1,99 @@
+use std::{io::Write, time::Duration};
+
+use anyhow::Context;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, ManagedTorrentStateKind, Session, TorrentEvent,
+    create_torrent, spawn_utils::BlockingSpawner, tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_recheck() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 131072, Some("test_e2e_recheck"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            name: None,
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let session = Session::new_with_opts(
+        files.path().into(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")?;
+
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                paused: true,
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+    assert!(handle.stats().finished);
+
+    // Corrupt the first piece.
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(files.path().join("0.data"))?
+        .write_all(&[0u8; 16384])?;
+
+    let mut events = handle.subscribe_events();
+    handle.force_recheck()?;
+    handle.wait_until_initialized().await?;
+
+    let mut kinds = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if let TorrentEvent::StateChanged(kind) = event {
+            kinds.push(kind);
+        }
+    }
+    assert_eq!(
+        kinds,
+        [
+            ManagedTorrentStateKind::Initializing,
+            ManagedTorrentStateKind::Paused
+        ]
+    );
+
+    assert_eq!(handle.state_kind(), ManagedTorrentStateKind::Paused);
+    let stats = handle.stats();
+    assert!(!stats.finished);
+    assert_eq!(stats.progress_bytes, 131072 - 16384);
+
+    // Torrents in error state can be rechecked too.
+    handle
+        .stop_with_error(anyhow::anyhow!("simulated write error"))
+        .await;
+    assert_eq!(handle.state_kind(), ManagedTorrentStateKind::Error);
+    handle.force_recheck()?;
+    handle.wait_until_initialized().await?;
+    assert_eq!(handle.state_kind(), ManagedTorrentStateKind::Paused);
+    assert_eq!(handle.stats().progress_bytes, 131072 - 16384);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_recheck() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_recheck()).await?
+}
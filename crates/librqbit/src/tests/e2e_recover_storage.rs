@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, Session, TorrentStatsState, create_torrent,
+    spawn_utils::BlockingSpawner, tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_recover_storage() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(2, 10000, Some("test_e2e_recover_storage"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let session = Session::new_with_opts(
+        files.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+    assert!(handle.recover_storage().is_err());
+
+    // Any fatal error while the files are gone is reported as missing storage.
+    std::fs::remove_dir_all(files.path())?;
+    handle
+        .stop_with_error(anyhow::anyhow!("simulated write error"))
+        .await;
+    assert!(handle.is_storage_missing());
+    let stats = handle.stats();
+    assert!(matches!(stats.state, TorrentStatsState::Error));
+    assert!(stats.storage_missing);
+
+    handle.recover_storage()?;
+    handle.wait_until_initialized().await?;
+    let stats = handle.stats();
+    assert!(matches!(stats.state, TorrentStatsState::Live));
+    assert!(!stats.storage_missing);
+    assert_eq!(stats.progress_bytes, 0);
+    for f in 0..2 {
+        assert!(files.path().join(format!("{f}.data")).exists());
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_recover_storage() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_recover_storage()).await?
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, DeletedFiles, Session, create_torrent,
+    spawn_utils::BlockingSpawner, tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_remove() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(2, 10000, Some("test_e2e_remove"));
+    std::fs::create_dir(files.path().join("sub"))?;
+    std::fs::write(files.path().join("sub").join("2.data"), vec![1u8; 5000])?;
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    // Not part of the torrent, must survive.
+    std::fs::write(files.path().join("sibling.txt"), b"hello")?;
+
+    let session = Session::new_with_opts(
+        files.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+
+    let deleted = handle.remove(true).await?;
+    assert_eq!(
+        deleted,
+        DeletedFiles {
+            files: 3,
+            bytes: 25000
+        }
+    );
+    assert!(session.get(handle.id().into()).is_none());
+    assert!(!files.path().join("0.data").exists());
+    assert!(!files.path().join("sub").exists());
+    assert!(files.path().join("sibling.txt").exists());
+
+    assert!(handle.remove(false).await.is_err());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_remove() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_remove()).await?
+}
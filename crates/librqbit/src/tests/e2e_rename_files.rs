@@ -0,0 +1,109 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Context;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, ManagedTorrentStateKind, Session,
+    create_torrent, spawn_utils::BlockingSpawner, tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_rename_files() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(2, 8192, Some("test_e2e_rename_files"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let original = std::fs::read(files.path().join("0.data"))?;
+
+    let session = Session::new_with_opts(
+        files.path().parent().unwrap().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")?;
+
+    let add_opts = || AddTorrentOptions {
+        output_folder: Some(files.path().to_str().unwrap().to_owned()),
+        overwrite: true,
+        ..Default::default()
+    };
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(add_opts()),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+    assert!(handle.stats().finished);
+
+    // A live torrent is paused for the rename and resumed afterwards.
+    let new_path = PathBuf::from("sub").join("renamed.data");
+    session.rename_file(&handle, 0, new_path.clone()).await?;
+    assert_eq!(handle.state_kind(), ManagedTorrentStateKind::Live);
+    assert!(!files.path().join("0.data").exists());
+    assert_eq!(std::fs::read(files.path().join(&new_path))?, original);
+    assert_eq!(handle.renamed_files(), [(0, new_path.clone())].into());
+
+    // Paths that are taken or outside the output folder are refused.
+    for bad in ["1.data", "../0.data"] {
+        assert!(session.rename_file(&handle, 0, bad.into()).await.is_err());
+    }
+
+    // The renamed file is used from now on.
+    handle.force_recheck()?;
+    handle.wait_until_initialized().await?;
+    assert!(handle.stats().finished);
+
+    let new_name = format!(
+        "{}_renamed",
+        files.path().file_name().unwrap().to_str().unwrap()
+    );
+    let new_folder = files.path().with_file_name(&new_name);
+    session.rename_output_folder(&handle, &new_name).await?;
+    assert_eq!(handle.shared().output_folder(), new_folder);
+    assert!(!files.path().exists());
+    assert!(new_folder.join(&new_path).exists());
+    assert!(session.rename_output_folder(&handle, "a/b").await.is_err());
+
+    // The renames are passed on when the torrent is added again, e.g. on session restore.
+    let renamed_files = handle.renamed_files();
+    session.delete(handle.id().into(), false).await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                output_folder: Some(new_folder.to_str().unwrap().to_owned()),
+                renamed_files,
+                ..add_opts()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+    assert!(handle.stats().finished);
+
+    std::fs::remove_dir_all(&new_folder)?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_rename_files() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_rename_files()).await?
+}
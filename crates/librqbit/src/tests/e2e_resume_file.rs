@@ -0,0 +1,118 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tempfile::TempDir;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, Session, create_torrent,
+    session_persistence::resume_file::{ResumeFile, ResumeTorrent},
+    spawn_utils::BlockingSpawner,
+    tests::test_util::setup_test_logging,
+    torrent_state::ManagedTorrentHandle,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn session(dir: &Path) -> anyhow::Result<Arc<Session>> {
+    Session::new_with_opts(
+        dir.to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+// Load a single-torrent resume file into a new session.
+async fn load(path: &Path) -> anyhow::Result<(TempDir, Arc<Session>, ManagedTorrentHandle)> {
+    let dir = TempDir::with_prefix("test_resume_file_session")?;
+    let session = session(dir.path()).await?;
+    let mut handles = session.load_resume_file(path).await?;
+    assert_eq!(handles.len(), 1);
+    let handle = handles.pop().unwrap();
+    handle.wait_until_initialized().await?;
+    Ok((dir, session, handle))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_resume_file() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(2, 10000, Some("test_resume_file"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let total_bytes = 20000;
+
+    let dir = TempDir::with_prefix("test_resume_file_dir")?;
+    let session = session(dir.path()).await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+
+    // Pausing the session keeps the torrent live in the file.
+    let path = dir.path().join("resume.json");
+    assert_eq!(session.save_resume_file(&path, true).await?, 1);
+    assert!(handle.is_paused());
+    let file = ResumeFile::read(&path).await?;
+    let saved = file.torrents[0].clone();
+    assert!(!saved.paused);
+    assert_eq!(saved.output_folder, files.path());
+
+    let (_dir, _session, restored) = load(&path).await?;
+    assert!(!restored.is_paused());
+    assert_eq!(restored.info_hash(), handle.info_hash());
+    assert_eq!(restored.stats().progress_bytes, total_bytes);
+
+    let rewrite = |f: &dyn Fn(&mut ResumeTorrent)| {
+        let mut t = saved.clone();
+        t.paused = true;
+        f(&mut t);
+        ResumeFile {
+            version: file.version,
+            torrents: vec![t],
+        }
+    };
+
+    // The saved bitfield is used instead of checking the files: here it claims nothing.
+    rewrite(&|t| {
+        t.bitfield.fill(0);
+        t.uploaded_bytes = 1000;
+    })
+    .write(&path)
+    .await?;
+    let (_dir, _session, restored) = load(&path).await?;
+    assert!(restored.is_paused());
+    assert_eq!(restored.stats().progress_bytes, 0);
+    assert_eq!(restored.stats().uploaded_bytes, 1000);
+
+    // Files went missing, so the bitfield doesn't match and all files are checked.
+    let empty = TempDir::with_prefix("test_resume_file_moved")?;
+    let moved: PathBuf = empty.path().to_owned();
+    rewrite(&|t| t.output_folder = moved.clone())
+        .write(&path)
+        .await?;
+    let (_dir, _session, restored) = load(&path).await?;
+    assert_eq!(restored.stats().progress_bytes, 0);
+    Ok(())
+}
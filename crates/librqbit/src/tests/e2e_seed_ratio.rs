@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use anyhow::{Context, bail};
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, ManagedTorrentStateKind, StopReason,
+    create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder, wait_until,
+    },
+};
+
+async fn e2e_seed_ratio() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 65536, Some("test_e2e_seed_ratio"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let seeder = start_test_seeder(
+        files.path(),
+        &torrent,
+        AddTorrentOptions {
+            seed_ratio_limit: Some(0.5),
+            ..Default::default()
+        },
+    )
+    .await?;
+    assert_eq!(seeder.handle.last_stop_reason(), None);
+
+    // Uploading the whole torrent once takes the seeder over the limit.
+    let client_dir = TempDir::with_prefix("test_e2e_seed_ratio_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .context("expected a torrent handle")?
+        .wait_until_completed()
+        .await?;
+    wait_until(
+        || match seeder.handle.last_stop_reason() {
+            Some(StopReason::SeedRatioReached) => Ok(()),
+            r => bail!("last_stop_reason = {r:?}"),
+        },
+        Duration::from_secs(5),
+    )
+    .await?;
+    assert_eq!(seeder.handle.state_kind(), ManagedTorrentStateKind::Paused);
+
+    // Unpausing clears the stop reason. The limit is lifted first, otherwise the seeder would
+    // get paused again right away.
+    seeder
+        .session
+        .set_seed_ratio_limit(&seeder.handle, None)
+        .await;
+    seeder.session.unpause(&seeder.handle).await?;
+    assert_eq!(seeder.handle.last_stop_reason(), None);
+    seeder.handle.wait_until_completed().await?;
+    assert_eq!(seeder.handle.state_kind(), ManagedTorrentStateKind::Live);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_seed_ratio() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_seed_ratio()).await?
+}
@@ -0,0 +1,161 @@
+use std::{num::NonZeroU32, path::Path, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, Session, SessionPersistenceConfig, create_torrent,
+    file_info::FilePriority, limits::LimitsConfig, spawn_utils::BlockingSpawner,
+    tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn session(output: &Path, persistence: &Path) -> anyhow::Result<Arc<Session>> {
+    Session::new_with_opts(
+        output.into(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: Some(SessionPersistenceConfig::Json {
+                folder: Some(persistence.into()),
+            }),
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating session")
+}
+
+async fn e2e_session_restore() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(2, 65536, Some("test_e2e_session_restore"));
+    let persistence = tempfile::TempDir::with_prefix("test_e2e_session_restore_persistence")?;
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let limits = LimitsConfig {
+        upload_bps: NonZeroU32::new(1000),
+        download_bps: NonZeroU32::new(2000),
+    };
+
+    let s = session(files.path(), persistence.path()).await?;
+    let handle = s
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                paused: true,
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                web_seeds: Some(vec!["http://127.0.0.1:1/files/".into()]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+    s.set_file_priority(&handle, 0, FilePriority::High).await?;
+    s.set_file_priority(&handle, 1, FilePriority::Skip).await?;
+    s.set_sequential(&handle, true).await;
+    s.set_rate_limits(&handle, limits).await;
+    s.set_seed_ratio_limit(&handle, Some(1.5)).await;
+    s.set_display_name(&handle, Some("renamed".into())).await;
+    drop(handle);
+    s.stop().await;
+    drop(s);
+
+    let s = session(files.path(), persistence.path()).await?;
+    let handle = s
+        .with_torrents(|torrents| torrents.next().map(|(_, t)| t.clone()))
+        .context("torrent wasn't restored")?;
+    handle.wait_until_initialized().await?;
+    assert!(handle.is_paused());
+    assert_eq!(handle.only_files(), Some(vec![0]));
+    assert_eq!(
+        handle.file_priorities(),
+        [FilePriority::High, FilePriority::Skip]
+    );
+    assert!(handle.is_sequential());
+    assert_eq!(handle.rate_limits(), limits);
+    assert_eq!(handle.seed_ratio_limit(), Some(1.5));
+    assert_eq!(handle.display_name(), "renamed");
+    assert_eq!(
+        handle.shared().web_seeds,
+        ["http://127.0.0.1:1/files/".parse::<url::Url>()?]
+    );
+    s.stop().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_session_restore() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_session_restore()).await?
+}
+
+async fn e2e_session_restore_added_trackers() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(
+        1,
+        16384,
+        Some("test_e2e_session_restore_added_trackers"),
+    );
+    let persistence =
+        tempfile::TempDir::with_prefix("test_e2e_session_restore_added_trackers_persistence")?;
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            trackers: vec!["http://127.0.0.1:1/own".into()],
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let added: url::Url = "http://127.0.0.1:1/added".parse()?;
+
+    let s = session(files.path(), persistence.path()).await?;
+    let handle = s
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+    assert_eq!(s.add_trackers(&handle, vec![added.clone()]).await?, 1);
+    drop(handle);
+    s.stop().await;
+    drop(s);
+
+    let s = session(files.path(), persistence.path()).await?;
+    let handle = s
+        .with_torrents(|torrents| torrents.next().map(|(_, t)| t.clone()))
+        .context("torrent wasn't restored")?;
+    assert_eq!(
+        handle.shared().tracker_tiers(),
+        [vec!["http://127.0.0.1:1/own".parse()?], vec![added]]
+    );
+    s.stop().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_session_restore_added_trackers() -> anyhow::Result<()> {
+    timeout(
+        Duration::from_secs(10),
+        e2e_session_restore_added_trackers(),
+    )
+    .await?
+}
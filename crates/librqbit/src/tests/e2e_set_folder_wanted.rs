@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, FilePriority, Session, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{create_new_file_with_random_content, setup_test_logging},
+};
+
+async fn e2e_set_folder_wanted() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = tempfile::TempDir::with_prefix("test_e2e_set_folder_wanted")?;
+    for name in ["a/0.data", "a/sub/1.data", "ab/2.data", "3.data"] {
+        let path = files.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        create_new_file_with_random_content(&path, 48000);
+    }
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let session = Session::new_with_opts(
+        files.path().join("out"),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                paused: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+
+    let folder_of = |idx: usize| {
+        handle.metadata.load().as_ref().unwrap().file_infos[idx]
+            .relative_filename
+            .clone()
+    };
+    let skipped = || {
+        handle
+            .file_priorities()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, p)| *p == FilePriority::Skip)
+            .map(|(idx, _)| folder_of(idx))
+            .collect::<Vec<_>>()
+    };
+
+    // "a" must not match "ab".
+    assert_eq!(session.set_folder_wanted(&handle, "a/", false).await?, 2);
+    let mut s = skipped();
+    s.sort();
+    assert_eq!(
+        s,
+        ["a/0.data", "a/sub/1.data"].map(std::path::PathBuf::from)
+    );
+    // Pieces shared with selected files are still needed.
+    let total_bytes = handle.stats().total_bytes;
+    assert!((96000..192000).contains(&total_bytes), "{total_bytes}");
+
+    // Already deselected.
+    assert_eq!(session.set_folder_wanted(&handle, "a/sub", false).await?, 0);
+
+    assert_eq!(session.set_folder_wanted(&handle, "a/sub", true).await?, 1);
+    assert_eq!(skipped(), ["a/0.data"].map(std::path::PathBuf::from));
+    assert!(handle.stats().total_bytes > total_bytes);
+
+    assert_eq!(session.set_folder_wanted(&handle, "", true).await?, 1);
+    assert!(skipped().is_empty());
+    assert_eq!(handle.stats().total_bytes, 192000);
+
+    assert!(session.set_folder_wanted(&handle, "b", true).await.is_err());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_set_folder_wanted() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_set_folder_wanted()).await?
+}
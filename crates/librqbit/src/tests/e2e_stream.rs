@@ -15,12 +15,12 @@ use super::test_util::create_default_random_dir_with_torrents;
 
 async fn e2e_stream() -> anyhow::Result<()> {
     setup_test_logging();
-    let files = create_default_random_dir_with_torrents(1, 8192, Some("test_e2e_stream"));
+    let files = create_default_random_dir_with_torrents(1, 131072, Some("test_e2e_stream"));
     let torrent = create_torrent(
         files.path(),
         CreateTorrentOptions {
             name: None,
-            piece_length: Some(1024),
+            piece_length: Some(16384),
             ..Default::default()
         },
         &BlockingSpawner::new(1),
@@ -105,7 +105,7 @@ async fn e2e_stream() -> anyhow::Result<()> {
     info!("client torrent initialized, starting stream");
 
     let mut stream = client_handle.stream(0).await?;
-    let mut buf = Vec::<u8>::with_capacity(8192);
+    let mut buf = Vec::<u8>::with_capacity(131072);
     stream.read_to_end(&mut buf).await?;
 
     if buf != orig_content {
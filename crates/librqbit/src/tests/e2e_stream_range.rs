@@ -0,0 +1,87 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use http::StatusCode;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, Session, api::Api, create_torrent, http_api::HttpApi, spawn_utils::BlockingSpawner,
+    tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+// Byte ranges of the HTTP streaming endpoint, as seen by a client.
+async fn e2e_stream_range() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 1024, Some("test_e2e_stream_range"));
+    let torrent =
+        create_torrent(files.path(), Default::default(), &BlockingSpawner::new(1)).await?;
+
+    let session = Session::new_with_opts(
+        files.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+
+    let app = HttpApi::new(Api::new(session.clone(), None, None), None).into_router(None);
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let url = format!(
+        "http://{}/torrents/{}/stream/0",
+        listener.local_addr()?,
+        handle.id()
+    );
+    let server = tokio::spawn(async move { axum::serve(listener, app).await });
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&url)
+        .header("Range", "bytes=1000-")
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers()["Content-Range"].to_str()?,
+        "bytes 1000-1023/1024"
+    );
+    let expected = std::fs::read(files.path().join("0.data"))?;
+    assert_eq!(response.bytes().await?, expected[1000..]);
+
+    // Past EOF, the client is told the actual length.
+    let response = client
+        .get(&url)
+        .header("Range", "bytes=2000-")
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        response.headers()["Content-Range"].to_str()?,
+        "bytes */1024"
+    );
+    assert!(response.bytes().await?.is_empty());
+
+    server.abort();
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_stream_range() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_stream_range()).await?
+}
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, ManagedTorrentStateKind, Session, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+    },
+    torrent_queue::{TorrentQueue, TorrentQueueOptions},
+    torrent_state::ManagedTorrentHandle,
+};
+
+async fn add_unfinished_torrents(
+    session: &std::sync::Arc<Session>,
+    count: usize,
+) -> anyhow::Result<(Vec<ManagedTorrentHandle>, Vec<tempfile::TempDir>)> {
+    let mut handles = Vec::new();
+    let mut dirs = Vec::new();
+    for _ in 0..count {
+        let files = create_default_random_dir_with_torrents(1, 65536, Some("test_e2e_queue"));
+        let torrent = create_torrent(
+            files.path(),
+            CreateTorrentOptions {
+                name: None,
+                piece_length: Some(16384),
+                ..Default::default()
+            },
+            &BlockingSpawner::new(1),
+        )
+        .await?;
+        // Nobody seeds these, so they never finish.
+        let handle = session
+            .add_torrent(
+                AddTorrent::from_bytes(torrent.as_bytes()?),
+                Some(crate::AddTorrentOptions {
+                    metadata_only: true,
+                    output_folder: Some(files.path().join("out").to_str().unwrap().to_owned()),
+                    ..Default::default()
+                }),
+            )
+            .await?
+            .into_handle()
+            .unwrap();
+        handles.push(handle);
+        dirs.push(files);
+    }
+    Ok((handles, dirs))
+}
+
+async fn live(handles: &[ManagedTorrentHandle]) -> anyhow::Result<Vec<bool>> {
+    let mut result = Vec::new();
+    for h in handles {
+        if !h.is_paused() {
+            h.wait_until_initialized().await?;
+        }
+        result.push(h.state_kind() == ManagedTorrentStateKind::Live);
+    }
+    Ok(result)
+}
+
+async fn e2e_torrent_queue() -> anyhow::Result<()> {
+    setup_test_logging();
+    let session = create_test_client_session(&std::env::temp_dir()).await?;
+    let (handles, _dirs) = add_unfinished_torrents(&session, 3).await?;
+
+    let queue = TorrentQueue::new(
+        &session,
+        TorrentQueueOptions {
+            max_active: 1,
+            pause_completed: false,
+            stall_timeout: None,
+        },
+    );
+    for h in handles.iter() {
+        queue.push(h.clone());
+    }
+
+    queue.tick().await;
+    assert_eq!(live(&handles).await?, [true, false, false]);
+
+    queue.set_max_active(2);
+    queue.tick().await;
+    assert_eq!(live(&handles).await?, [true, true, false]);
+
+    // Paused by the user, so the queue doesn't take it over.
+    session.pause(&handles[0]).await?;
+    queue.tick().await;
+    assert_eq!(live(&handles).await?, [false, true, true]);
+
+    queue.set_max_active(1);
+    queue.tick().await;
+    assert_eq!(live(&handles).await?, [false, true, false]);
+    Ok(())
+}
+
+async fn e2e_torrent_queue_rotates_stalled() -> anyhow::Result<()> {
+    setup_test_logging();
+    let session = create_test_client_session(&std::env::temp_dir()).await?;
+    let (handles, _dirs) = add_unfinished_torrents(&session, 2).await?;
+
+    let queue = TorrentQueue::new(
+        &session,
+        TorrentQueueOptions {
+            max_active: 1,
+            pause_completed: false,
+            stall_timeout: Some(Duration::ZERO),
+        },
+    );
+    for h in handles.iter() {
+        queue.push(h.clone());
+    }
+    let ids = handles.iter().map(|h| h.id()).collect::<Vec<_>>();
+
+    queue.tick().await;
+    assert_eq!(live(&handles).await?, [true, false]);
+
+    // No pieces arrive, so the first one gets rotated out.
+    queue.tick().await;
+    assert_eq!(live(&handles).await?, [false, true]);
+    assert_eq!(queue.ids(), [ids[1], ids[0]]);
+
+    session.delete(ids[1].into(), false).await?;
+    queue.tick().await;
+    assert_eq!(queue.ids(), [ids[0]]);
+    assert_eq!(live(&handles[..1]).await?, [true]);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_torrent_queue() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_torrent_queue()).await?
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_torrent_queue_rotates_stalled() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_torrent_queue_rotates_stalled()).await?
+}
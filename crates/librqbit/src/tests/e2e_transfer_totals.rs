@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+// Cumulative byte counters survive pausing and resuming.
+async fn e2e_transfer_totals() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 65536, Some("test_e2e_transfer_totals"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    let client_dir = TempDir::with_prefix("test_e2e_transfer_totals_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let client_handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    client_handle.wait_until_completed().await?;
+
+    let downloaded = client_handle.stats().downloaded_bytes;
+    assert!(downloaded >= 65536, "downloaded {downloaded}");
+    let uploaded = seeder.handle.stats().uploaded_bytes;
+    assert!(uploaded >= 65536, "uploaded {uploaded}");
+
+    client_session.pause(&client_handle).await?;
+    seeder.session.pause(&seeder.handle).await?;
+    assert_eq!(client_handle.stats().downloaded_bytes, downloaded);
+    assert_eq!(seeder.handle.stats().uploaded_bytes, uploaded);
+
+    seeder.session.unpause(&seeder.handle).await?;
+    seeder.handle.wait_until_initialized().await?;
+    assert_eq!(seeder.handle.stats().uploaded_bytes, uploaded);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_transfer_totals() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_transfer_totals()).await?
+}
@@ -0,0 +1,75 @@
+use std::{collections::HashSet, time::Duration};
+
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, ManagedTorrentStateKind, TorrentEvent,
+    create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+async fn e2e_update_only_files_live() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files =
+        create_default_random_dir_with_torrents(3, 16384, Some("test_e2e_update_only_files_live"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    let client_dir = TempDir::with_prefix("test_e2e_update_only_files_live_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                only_files: Some(vec![0]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+    let file_progress = || handle.stats().file_progress;
+    assert_eq!(file_progress(), vec![16384, 0, 0]);
+
+    // Selecting another file resumes downloading without restarting the torrent.
+    handle.update_only_files(&HashSet::from([0, 1]))?;
+    assert!(!handle.stats().finished);
+    handle.wait_until_completed().await?;
+    assert_eq!(file_progress(), vec![16384, 16384, 0]);
+    assert_eq!(handle.state_kind(), ManagedTorrentStateKind::Live);
+
+    // With nothing to download from, deselecting the only missing file finishes the torrent.
+    seeder.session.pause(&seeder.handle).await?;
+    handle.update_only_files(&HashSet::from([0, 1, 2]))?;
+    assert!(!handle.stats().finished);
+    let mut events = handle.subscribe_events();
+    handle.update_only_files(&HashSet::from([0, 1]))?;
+    assert!(handle.stats().finished);
+    handle.wait_until_completed().await?;
+    loop {
+        if let TorrentEvent::Completed = events.recv().await? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_update_only_files_live() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_update_only_files_live()).await?
+}
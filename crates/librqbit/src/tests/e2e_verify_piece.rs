@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, Session, create_torrent, spawn_utils::BlockingSpawner,
+    tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+async fn e2e_verify_piece() -> anyhow::Result<()> {
+    setup_test_logging();
+    // 3 pieces, the last one is short.
+    let files = create_default_random_dir_with_torrents(1, 40000, Some("test_e2e_verify_piece"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let session = Session::new_with_opts(
+        files.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                output_folder: Some(files.path().to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+    assert!(handle.verify_piece(3).await.is_err());
+
+    let path = files.path().join("0.data");
+    let original = std::fs::read(&path)?;
+    let corrupt = |byte: u8| {
+        let mut data = original.clone();
+        data[20000] = byte;
+        std::fs::write(&path, data)
+    };
+
+    // Live: a corrupted piece is forgotten.
+    corrupt(!original[20000])?;
+    assert!(handle.verify_piece(0).await?);
+    assert!(handle.verify_piece(2).await?);
+    assert!(!handle.verify_piece(1).await?);
+    let stats = handle.stats();
+    assert!(!stats.finished);
+    assert_eq!(stats.progress_bytes, 40000 - 16384);
+
+    // Paused: the repaired piece is marked as downloaded again.
+    session.pause(&handle).await?;
+    corrupt(original[20000])?;
+    assert!(handle.verify_piece(1).await?);
+    let stats = handle.stats();
+    assert!(stats.finished);
+    assert_eq!(stats.progress_bytes, 40000);
+    assert_eq!(stats.file_progress, vec![40000]);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_verify_piece() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_verify_piece()).await?
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, CreateTorrentOptions,
+    api::TorrentIdOrHash,
+    create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+async fn e2e_wait_for_piece() -> anyhow::Result<()> {
+    setup_test_logging();
+    // 2 files of 8 pieces each.
+    let files = create_default_random_dir_with_torrents(2, 131072, Some("test_wait_for_piece"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    let client_dir = TempDir::with_prefix("test_wait_for_piece_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(crate::AddTorrentOptions {
+                only_files: Some(vec![0]),
+                initial_peers: Some(vec![seeder.addr]),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+
+    let have = |range: std::ops::Range<usize>| {
+        handle
+            .with_chunk_tracker(|ct| ct.get_have_pieces().as_slice()[range].to_bitvec())
+            .unwrap()
+    };
+
+    // File 1 isn't selected, but the awaited pieces are downloaded anyway.
+    handle.wait_for_piece(12).await?;
+    assert!(have(12..13).all());
+    handle.wait_for_file(1).await?;
+    assert!(have(8..16).all());
+    handle.wait_for_file(0).await?;
+    assert!(have(0..8).all());
+    assert!(handle.wait_for_piece(16).await.is_err());
+    assert!(handle.wait_for_file(2).await.is_err());
+
+    // Waits fail when the torrent is removed.
+    let lonely_dir = TempDir::with_prefix("test_wait_for_piece_lonely")?;
+    let lonely_session = create_test_client_session(lonely_dir.path()).await?;
+    let lonely = lonely_session
+        .add_torrent(AddTorrent::from_bytes(torrent.as_bytes()?), None)
+        .await?
+        .into_handle()
+        .unwrap();
+    lonely.wait_until_initialized().await?;
+    let wait = tokio::spawn({
+        let lonely = lonely.clone();
+        async move { lonely.wait_for_piece(0).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    lonely_session
+        .delete(TorrentIdOrHash::Id(lonely.id()), false)
+        .await?;
+    assert!(wait.await?.is_err());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_wait_for_piece() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_wait_for_piece()).await?
+}
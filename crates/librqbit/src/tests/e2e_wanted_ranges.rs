@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, FilePriority, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+#[allow(clippy::single_range_in_vec_init)]
+async fn e2e_wanted_ranges() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(1, 160000, Some("test_e2e_wanted_ranges"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    let client_dir = TempDir::with_prefix("test_e2e_wanted_ranges_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                paused: true,
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_initialized().await?;
+
+    assert!(handle.set_wanted_ranges(0, vec![0..160001]).is_err());
+    assert!(handle.set_wanted_ranges(0, vec![5..5]).is_err());
+    assert!(handle.set_wanted_ranges(1, vec![0..1]).is_err());
+
+    // Merged into 0..16384 and 16384..17600, i.e. pieces 0 and 1.
+    handle.set_wanted_ranges(0, vec![0..16384, 1600..17600, 16384..16400])?;
+    assert_eq!(handle.stats().total_bytes, 32768);
+
+    // Skipping the file takes precedence over its ranges.
+    client_session
+        .set_file_priority(&handle, 0, FilePriority::Skip)
+        .await?;
+    assert_eq!(handle.stats().total_bytes, 0);
+    client_session
+        .set_file_priority(&handle, 0, FilePriority::Normal)
+        .await?;
+
+    // The last piece is short.
+    handle.set_wanted_ranges(0, vec![1600..17600, 159999..160000])?;
+    assert_eq!(handle.stats().total_bytes, 32768 + 160000 - 9 * 16384);
+
+    client_session.unpause(&handle).await?;
+    handle.wait_until_completed().await?;
+
+    let original = std::fs::read(files.path().join("0.data"))?;
+    let downloaded = std::fs::read(client_dir.path().join("0.data"))?;
+    assert_eq!(&downloaded[..32768], &original[..32768]);
+    assert_eq!(&downloaded[9 * 16384..], &original[9 * 16384..]);
+    assert_eq!(handle.stats().progress_bytes, 32768 + 160000 - 9 * 16384);
+
+    // Wanting the whole file again resumes downloading the rest.
+    handle.set_wanted_ranges(0, vec![])?;
+    assert!(!handle.stats().finished);
+    handle.wait_until_completed().await?;
+    assert_eq!(std::fs::read(client_dir.path().join("0.data"))?, original);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_wanted_ranges() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_wanted_ranges()).await?
+}
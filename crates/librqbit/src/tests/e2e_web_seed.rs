@@ -0,0 +1,154 @@
+use std::{net::Ipv4Addr, num::NonZeroU32, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use crate::{
+    AddTorrent, CreateTorrentOptions, Session, create_torrent, limits::LimitsConfig,
+    spawn_utils::BlockingSpawner, tests::test_util::setup_test_logging,
+};
+
+use super::test_util::create_default_random_dir_with_torrents;
+
+// A minimal HTTP server that serves files from "root" under "/<name>/", and supports
+// single "Range: bytes=start-end" requests.
+async fn serve_one(mut conn: TcpStream, name: &str, root: &std::path::Path) -> anyhow::Result<()> {
+    let mut req = Vec::new();
+    while !req.ends_with(b"\r\n\r\n") {
+        let mut b = [0u8; 1];
+        if conn.read(&mut b).await? == 0 {
+            return Ok(());
+        }
+        req.push(b[0]);
+    }
+    let req = String::from_utf8(req)?;
+    let path = req
+        .split_whitespace()
+        .nth(1)
+        .context("bad request line")?
+        .to_owned();
+    let range = req
+        .lines()
+        .find_map(|l| {
+            l.strip_prefix("range: bytes=")
+                .or(l.strip_prefix("Range: bytes="))
+        })
+        .and_then(|r| r.split_once('-'))
+        .map(|(s, e)| Ok::<_, anyhow::Error>((s.parse::<usize>()?, e.parse::<usize>()?)))
+        .transpose()?;
+
+    let data = urlencoding::decode(&path)?
+        .strip_prefix(&format!("/{name}/"))
+        .and_then(|p| std::fs::read(root.join(p)).ok());
+    let response = match (data, range) {
+        (Some(data), Some((start, end))) if end < data.len() => {
+            let mut r = format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{end}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                data.len(),
+                end - start + 1
+            )
+            .into_bytes();
+            r.extend_from_slice(&data[start..=end]);
+            r
+        }
+        _ => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec(),
+    };
+    conn.write_all(&response).await?;
+    Ok(())
+}
+
+async fn e2e_web_seed(ratelimits: LimitsConfig) -> anyhow::Result<Arc<Session>> {
+    setup_test_logging();
+    let files = create_default_random_dir_with_torrents(3, 10000, Some("test_e2e_web_seed"));
+    let name = files
+        .path()
+        .file_name()
+        .context("no file name")?
+        .to_str()
+        .context("non-utf8 name")?
+        .to_owned();
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let addr = listener.local_addr()?;
+    let root = Arc::new(files.path().to_owned());
+    let server = tokio::spawn({
+        let name = name.clone();
+        async move {
+            while let Ok((conn, _)) = listener.accept().await {
+                let name = name.clone();
+                let root = root.clone();
+                tokio::spawn(async move { serve_one(conn, &name, &root).await });
+            }
+        }
+    });
+
+    // The web seed comes from the torrent's "url-list". Pieces span file boundaries.
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            web_seeds: vec![format!("http://{addr}/")],
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    // No DHT, trackers or peers, so the web seed is the only source.
+    let client_dir = tempfile::TempDir::with_prefix("test_e2e_web_seed_client")?;
+    let session = Session::new_with_opts(
+        client_dir.path().to_owned(),
+        crate::SessionOptions {
+            disable_dht: true,
+            persistence: None,
+            listen: None,
+            ratelimits,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let handle = session
+        .add_torrent(AddTorrent::from_bytes(torrent.as_bytes()?), None)
+        .await?
+        .into_handle()
+        .unwrap();
+    handle.wait_until_completed().await?;
+
+    for f in 0..3 {
+        let name = format!("{f}.data");
+        let got = std::fs::read(client_dir.path().join(handle.name().unwrap()).join(&name))?;
+        let expected = std::fs::read(files.path().join(&name))?;
+        assert!(got == expected, "file {name} contents differ");
+    }
+    assert_eq!(handle.stats().downloaded_bytes, 30000);
+
+    server.abort();
+    Ok(session)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_web_seed() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_web_seed(Default::default())).await??;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_web_seed_session_ratelimit() -> anyhow::Result<()> {
+    // The burst is exactly the torrent size, so the download doesn't wait, but takes up all of
+    // it. Refilling it takes a second.
+    let bps = NonZeroU32::new(30000).unwrap();
+    let ratelimits = LimitsConfig {
+        download_bps: Some(bps),
+        upload_bps: None,
+    };
+    let session = timeout(Duration::from_secs(10), e2e_web_seed(ratelimits)).await??;
+    assert!(
+        !session.ratelimits.try_acquire_download(bps),
+        "web seed downloads didn't take session download tokens"
+    );
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentOptions, create_torrent,
+    spawn_utils::BlockingSpawner,
+    tests::test_util::{
+        create_default_random_dir_with_torrents, create_test_client_session, setup_test_logging,
+        start_test_seeder,
+    },
+};
+
+async fn e2e_write_backpressure() -> anyhow::Result<()> {
+    setup_test_logging();
+    let files =
+        create_default_random_dir_with_torrents(1, 131072, Some("test_e2e_write_backpressure"));
+    let torrent = create_torrent(
+        files.path(),
+        CreateTorrentOptions {
+            piece_length: Some(16384),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await?;
+
+    let seeder = start_test_seeder(files.path(), &torrent, Default::default()).await?;
+
+    // Any chunk waiting to be written fills the queue, so requests wait for every write.
+    let client_dir = TempDir::with_prefix("test_e2e_write_backpressure_client")?;
+    let client_session = create_test_client_session(client_dir.path()).await?;
+    let client_handle = client_session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                initial_peers: Some(vec![seeder.addr]),
+                max_pending_write_bytes: Some(1),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_handle()
+        .unwrap();
+    client_handle.wait_until_completed().await?;
+
+    // The last write is still accounted for for a moment after the torrent completes.
+    let live = client_handle.live().context("client torrent isn't live")?;
+    live.wait_until_writes_drained().await;
+    let stats = live.stats_snapshot();
+    assert_eq!(stats.pending_writes, 0);
+    assert_eq!(stats.pending_write_bytes, 0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_e2e_write_backpressure() -> anyhow::Result<()> {
+    timeout(Duration::from_secs(10), e2e_write_backpressure()).await?
+}
@@ -1,4 +1,55 @@
 mod e2e;
 mod e2e_another_local_client;
+mod e2e_ban;
+mod e2e_bandwidth_schedule;
+mod e2e_choking;
+#[cfg(feature = "storage_examples")]
+mod e2e_custom_storage;
+mod e2e_defer_initial_check;
+mod e2e_disk_full;
+mod e2e_display_name;
+mod e2e_download_prefix;
+mod e2e_encryption;
+mod e2e_events;
+mod e2e_export_torrent_file;
+mod e2e_fastresume;
+mod e2e_file_reader;
+mod e2e_half_open;
+mod e2e_holepunch;
+#[cfg(feature = "http-api")]
+mod e2e_http_api_router;
+mod e2e_hybrid;
+mod e2e_idle_pause;
+mod e2e_inflight_requests;
+mod e2e_initial_check;
+mod e2e_listen_port;
+mod e2e_metadata_only;
+mod e2e_metrics;
+mod e2e_move_storage;
+mod e2e_on_complete;
+mod e2e_path_resolver;
+mod e2e_peer_counts;
+mod e2e_peer_discovery;
+mod e2e_peer_id_prefix;
+mod e2e_peer_timeouts;
+mod e2e_rate_limits;
+mod e2e_recheck;
+mod e2e_recover_storage;
+mod e2e_remove;
+mod e2e_rename_files;
+mod e2e_resume_file;
+mod e2e_seed_ratio;
+mod e2e_session_restore;
+mod e2e_set_folder_wanted;
 mod e2e_stream;
+#[cfg(feature = "http-api")]
+mod e2e_stream_range;
+mod e2e_torrent_queue;
+mod e2e_transfer_totals;
+mod e2e_update_only_files_live;
+mod e2e_verify_piece;
+mod e2e_wait_for_piece;
+mod e2e_wanted_ranges;
+mod e2e_web_seed;
+mod e2e_write_backpressure;
 pub mod test_util;
@@ -1,17 +1,23 @@
 use std::{
     io::Write,
+    net::{Ipv4Addr, SocketAddr},
     path::Path,
     sync::{Arc, Weak},
     time::Duration,
 };
 
-use anyhow::bail;
+use anyhow::{Context, bail};
 use librqbit_core::{Id20, crate_version, peer_id::generate_azereus_style};
 use parking_lot::RwLock;
 use rand::{Rng, RngCore, SeedableRng, rng};
 use tempfile::TempDir;
 use tracing::{info, trace};
 
+use crate::{
+    AddTorrent, AddTorrentOptions, CreateTorrentResult, ListenerOptions, Session, SessionOptions,
+    torrent_state::ManagedTorrentHandle,
+};
+
 pub fn setup_test_logging() {
     if std::env::var("RUST_LOG").is_err() {
         unsafe { std::env::set_var("RUST_LOG", "info") };
@@ -216,3 +222,72 @@ pub async fn wait_until_i_am_the_last_task() -> anyhow::Result<()> {
     )
     .await
 }
+
+/// A session seeding a torrent created from "dir" on a random localhost port, for tests that
+/// download from it.
+pub struct TestSeeder {
+    pub session: Arc<Session>,
+    pub handle: ManagedTorrentHandle,
+    pub addr: SocketAddr,
+}
+
+/// Start seeding "torrent" from "dir". The output folder is set from "dir", other options are
+/// used as given.
+pub async fn start_test_seeder(
+    dir: &Path,
+    torrent: &CreateTorrentResult,
+    opts: AddTorrentOptions,
+) -> anyhow::Result<TestSeeder> {
+    let session = Session::new_with_opts(
+        dir.into(),
+        SessionOptions {
+            disable_dht: true,
+            peer_id: Some(TestPeerMetadata::good().as_peer_id()),
+            persistence: None,
+            listen: Some(ListenerOptions {
+                listen_addr: (Ipv4Addr::LOCALHOST, 0).into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating seeder session")?;
+    let handle = session
+        .add_torrent(
+            AddTorrent::from_bytes(torrent.as_bytes()?),
+            Some(AddTorrentOptions {
+                output_folder: Some(dir.to_str().unwrap().to_owned()),
+                overwrite: true,
+                ..opts
+            }),
+        )
+        .await?
+        .into_handle()
+        .context("expected a torrent handle")?;
+    handle.wait_until_completed().await?;
+    let addr = session
+        .listen_addr()
+        .context("expected listen_addr to be set")?;
+    Ok(TestSeeder {
+        session,
+        handle,
+        addr,
+    })
+}
+
+/// A session downloading into "dir", not listening for connections.
+pub async fn create_test_client_session(dir: &Path) -> anyhow::Result<Arc<Session>> {
+    Session::new_with_opts(
+        dir.into(),
+        SessionOptions {
+            disable_dht: true,
+            peer_id: Some(TestPeerMetadata::good().as_peer_id()),
+            persistence: None,
+            listen: None,
+            ..Default::default()
+        },
+    )
+    .await
+    .context("error creating client session")
+}
@@ -0,0 +1,362 @@
+//! A scheduling layer over the session that keeps at most N torrents downloading at once.
+//!
+//! Torrents pushed into a [`TorrentQueue`] are started and paused by it in queue order.
+//! Finished torrents don't count as active. A torrent that stops making progress is moved
+//! to the back of the queue to let the next one try.
+//!
+//! Torrents paused by the user through [`Session::pause`], or after downloading a prefix with
+//! [`ManagedTorrent::download_prefix`](crate::ManagedTorrent::download_prefix), are left alone
+//! until they are unpaused again.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, debug_span, warn};
+
+use crate::{
+    Session, StopReason, TorrentEvent, TorrentStatsState, session::TorrentId,
+    torrent_state::ManagedTorrentHandle,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct TorrentQueueOptions {
+    /// How many unfinished torrents may be live at the same time.
+    pub max_active: usize,
+    /// Pause torrents once they finish. If not set, they keep seeding outside of the queue.
+    pub pause_completed: bool,
+    /// Move a live torrent to the back of the queue if it didn't download anything for this
+    /// long and other torrents are waiting.
+    pub stall_timeout: Option<Duration>,
+}
+
+impl Default for TorrentQueueOptions {
+    fn default() -> Self {
+        Self {
+            max_active: 3,
+            pause_completed: false,
+            stall_timeout: Some(Duration::from_secs(300)),
+        }
+    }
+}
+
+struct QueueEntry {
+    handle: ManagedTorrentHandle,
+    last_progress_bytes: u64,
+    last_progress_at: Instant,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EntryKind {
+    // Live or initializing, and not finished.
+    Active { stalled: bool },
+    // Paused and not finished, the queue may start it.
+    Waiting,
+    // Finished and still live.
+    Seeding,
+    // Paused by the user, finished and paused, or errored. The queue doesn't touch these.
+    Ignored,
+}
+
+impl QueueEntry {
+    fn kind(&mut self, now: Instant, stall_timeout: Option<Duration>) -> EntryKind {
+        let stats = self.handle.stats();
+        let kind = match stats.state {
+            TorrentStatsState::Live if stats.finished => EntryKind::Seeding,
+            TorrentStatsState::Live | TorrentStatsState::Initializing => {
+                EntryKind::Active { stalled: false }
+            }
+            TorrentStatsState::Paused | TorrentStatsState::Queued
+                if !stats.finished
+                    && !matches!(
+                        self.handle.last_stop_reason(),
+                        Some(
+                            StopReason::User | StopReason::PrefixDownloaded | StopReason::DiskFull
+                        )
+                    ) =>
+            {
+                EntryKind::Waiting
+            }
+            _ => EntryKind::Ignored,
+        };
+
+        // Only time progress while active, so that a torrent starts with a clean slate.
+        if !matches!(kind, EntryKind::Active { .. })
+            || stats.progress_bytes != self.last_progress_bytes
+        {
+            self.last_progress_bytes = stats.progress_bytes;
+            self.last_progress_at = now;
+            return kind;
+        }
+        let stalled = matches!(stats.state, TorrentStatsState::Live)
+            && stall_timeout.is_some_and(|t| now.duration_since(self.last_progress_at) >= t);
+        EntryKind::Active { stalled }
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Eq)]
+struct Plan {
+    pause: Vec<usize>,
+    start: Vec<usize>,
+    move_to_back: Vec<usize>,
+}
+
+// Decide what to do given the entries in queue order.
+fn plan(kinds: &[EntryKind], max_active: usize, pause_completed: bool) -> Plan {
+    let mut plan = Plan::default();
+    let mut active = Vec::new();
+    let mut waiting = VecDeque::new();
+    for (idx, kind) in kinds.iter().enumerate() {
+        match kind {
+            EntryKind::Active { .. } => active.push(idx),
+            EntryKind::Waiting => waiting.push_back(idx),
+            EntryKind::Seeding if pause_completed => plan.pause.push(idx),
+            EntryKind::Seeding | EntryKind::Ignored => {}
+        }
+    }
+
+    // Too many active, e.g. max_active was lowered. Pause the ones last in the queue.
+    while active.len() > max_active {
+        plan.pause.extend(active.pop());
+    }
+
+    // Rotate stalled torrents out if there's someone to replace them.
+    active.retain(|idx| {
+        let stalled = matches!(kinds[*idx], EntryKind::Active { stalled: true });
+        if stalled && !waiting.is_empty() {
+            plan.pause.push(*idx);
+            plan.move_to_back.push(*idx);
+            return false;
+        }
+        true
+    });
+
+    while active.len() < max_active {
+        let Some(idx) = waiting.pop_front() else {
+            break;
+        };
+        active.push(idx);
+        plan.start.push(idx);
+    }
+
+    plan
+}
+
+struct QueueState {
+    max_active: usize,
+    entries: Vec<QueueEntry>,
+}
+
+pub struct TorrentQueue {
+    session: Weak<Session>,
+    opts: TorrentQueueOptions,
+    state: Mutex<QueueState>,
+}
+
+impl TorrentQueue {
+    pub fn new(session: &Arc<Session>, opts: TorrentQueueOptions) -> Arc<Self> {
+        Arc::new(Self {
+            session: Arc::downgrade(session),
+            opts,
+            state: Mutex::new(QueueState {
+                max_active: opts.max_active,
+                entries: Vec::new(),
+            }),
+        })
+    }
+
+    /// Add a torrent to the back of the queue. It's started on the next tick if there's room.
+    pub fn push(&self, handle: ManagedTorrentHandle) {
+        let mut g = self.state.lock();
+        if g.entries.iter().any(|e| e.handle.id() == handle.id()) {
+            return;
+        }
+        g.entries.push(QueueEntry {
+            handle,
+            last_progress_bytes: 0,
+            last_progress_at: Instant::now(),
+        });
+    }
+
+    /// Stop managing a torrent. Its state is left as is.
+    pub fn remove(&self, id: TorrentId) -> bool {
+        let mut g = self.state.lock();
+        let len = g.entries.len();
+        g.entries.retain(|e| e.handle.id() != id);
+        g.entries.len() != len
+    }
+
+    pub fn set_max_active(&self, max_active: usize) {
+        self.state.lock().max_active = max_active;
+    }
+
+    pub fn max_active(&self) -> usize {
+        self.state.lock().max_active
+    }
+
+    /// Torrent ids in queue order.
+    pub fn ids(&self) -> Vec<TorrentId> {
+        self.state
+            .lock()
+            .entries
+            .iter()
+            .map(|e| e.handle.id())
+            .collect()
+    }
+
+    /// Start and pause torrents to match the limits. Called periodically by [`TorrentQueue::spawn`].
+    pub async fn tick(&self) {
+        let Some(session) = self.session.upgrade() else {
+            return;
+        };
+
+        let (to_pause, to_start) = {
+            let mut g = self.state.lock();
+            // Forget torrents that were deleted from the session.
+            g.entries.retain(|e| {
+                session
+                    .get(e.handle.id().into())
+                    .is_some_and(|h| Arc::ptr_eq(&h, &e.handle))
+            });
+
+            let now = Instant::now();
+            let kinds = g
+                .entries
+                .iter_mut()
+                .map(|e| e.kind(now, self.opts.stall_timeout))
+                .collect::<Vec<_>>();
+            let plan = plan(&kinds, g.max_active, self.opts.pause_completed);
+            let handle = |idx: &usize| g.entries[*idx].handle.clone();
+            let to_pause = plan.pause.iter().map(handle).collect::<Vec<_>>();
+            let to_start = plan.start.iter().map(handle).collect::<Vec<_>>();
+
+            // Indices are ascending, so remove from the end to keep them valid.
+            let mut moved = Vec::new();
+            for idx in plan.move_to_back.iter().rev() {
+                moved.push(g.entries.remove(*idx));
+            }
+            g.entries.extend(moved.into_iter().rev());
+            (to_pause, to_start)
+        };
+
+        for handle in to_pause {
+            debug!(id = handle.id(), "queue: pausing");
+            if let Err(e) = handle.pause() {
+                warn!(id = handle.id(), "queue: error pausing torrent: {e:#}");
+                continue;
+            }
+            handle.set_last_stop_reason(StopReason::Queued);
+            session.try_update_persistence_metadata(&handle).await;
+        }
+
+        for handle in to_start {
+            debug!(id = handle.id(), "queue: starting");
+            if let Err(e) = session.unpause(&handle).await {
+                warn!(id = handle.id(), "queue: error starting torrent: {e:#}");
+            }
+        }
+    }
+
+    /// Run [`TorrentQueue::tick`] every `interval`, and also right away when a torrent in the
+    /// session completes, changes state or gets deleted. Stops with the session or when the
+    /// queue is dropped.
+    pub fn spawn(self: &Arc<Self>, interval: Duration) {
+        let Some(session) = self.session.upgrade() else {
+            return;
+        };
+        let queue = Arc::downgrade(self);
+        let mut events = session.subscribe_events();
+        session.spawn(
+            debug_span!(parent: session.rs(), "torrent_queue"),
+            "torrent_queue",
+            async move {
+                let mut interval = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {},
+                        event = events.recv() => match event {
+                            Ok((
+                                _,
+                                TorrentEvent::Completed
+                                | TorrentEvent::StateChanged(_)
+                                | TorrentEvent::Removed,
+                            )) => {}
+                            Ok(_) => continue,
+                            Err(RecvError::Lagged(_)) => {}
+                            Err(RecvError::Closed) => return Ok(()),
+                        },
+                    }
+                    let Some(queue) = queue.upgrade() else {
+                        return Ok(());
+                    };
+                    queue.tick().await;
+                }
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EntryKind, Plan, plan};
+
+    const ACTIVE: EntryKind = EntryKind::Active { stalled: false };
+    const STALLED: EntryKind = EntryKind::Active { stalled: true };
+    const WAITING: EntryKind = EntryKind::Waiting;
+    const SEEDING: EntryKind = EntryKind::Seeding;
+    const IGNORED: EntryKind = EntryKind::Ignored;
+
+    #[test]
+    fn test_plan_starts_in_order() {
+        assert_eq!(
+            plan(&[WAITING, IGNORED, SEEDING, WAITING, WAITING], 2, false),
+            Plan {
+                start: vec![0, 3],
+                ..Default::default()
+            }
+        );
+        assert_eq!(plan(&[ACTIVE, WAITING, ACTIVE], 2, false), Plan::default());
+    }
+
+    #[test]
+    fn test_plan_pauses_excess() {
+        assert_eq!(
+            plan(&[ACTIVE, ACTIVE, WAITING, ACTIVE], 1, false),
+            Plan {
+                pause: vec![3, 1],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_plan_pause_completed() {
+        assert_eq!(
+            plan(&[SEEDING, WAITING], 1, true),
+            Plan {
+                pause: vec![0],
+                start: vec![1],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_plan_rotates_stalled() {
+        assert_eq!(
+            plan(&[STALLED, ACTIVE, WAITING], 2, false),
+            Plan {
+                pause: vec![0],
+                start: vec![2],
+                move_to_back: vec![0],
+            }
+        );
+
+        // Nobody to replace it with.
+        assert_eq!(plan(&[STALLED, ACTIVE], 2, false), Plan::default());
+    }
+}
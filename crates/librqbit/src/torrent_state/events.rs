@@ -0,0 +1,64 @@
+use std::net::SocketAddr;
+
+use tokio::sync::broadcast;
+
+use crate::session::TorrentId;
+
+use super::ManagedTorrentStateKind;
+
+const TORRENT_EVENTS_CAPACITY: usize = 256;
+pub(crate) const SESSION_EVENTS_CAPACITY: usize = 4096;
+
+/// Events emitted by a torrent.
+///
+/// Events are delivered through tokio broadcast channels. A receiver that falls behind by
+/// more than the channel capacity gets [`broadcast::error::RecvError::Lagged`] and misses
+/// the oldest events, so consumers shouldn't block while processing them.
+#[derive(Clone, Debug)]
+pub enum TorrentEvent {
+    StateChanged(ManagedTorrentStateKind),
+    PieceCompleted(u32),
+    PeerConnected(SocketAddr),
+    PeerDisconnected(SocketAddr),
+    /// All selected files were downloaded.
+    Completed,
+    /// The prefix of the file with this index requested through
+    /// [`ManagedTorrent::download_prefix`](crate::ManagedTorrent::download_prefix) was downloaded.
+    PrefixCompleted(usize),
+    Error(String),
+    /// The torrent was paused as the disk is full, see [`StopReason::DiskFull`](crate::StopReason::DiskFull).
+    DiskFull,
+    /// The torrent was paused as it had no peers and made no progress, see
+    /// [`StopReason::Idle`](crate::StopReason::Idle).
+    Idle,
+    /// The torrent was deleted from the session. No more events follow.
+    Removed,
+}
+
+pub(crate) struct TorrentEvents {
+    id: TorrentId,
+    tx: broadcast::Sender<TorrentEvent>,
+    session_tx: broadcast::Sender<(TorrentId, TorrentEvent)>,
+}
+
+impl TorrentEvents {
+    pub fn new(id: TorrentId, session_tx: broadcast::Sender<(TorrentId, TorrentEvent)>) -> Self {
+        Self {
+            id,
+            tx: broadcast::Sender::new(TORRENT_EVENTS_CAPACITY),
+            session_tx,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TorrentEvent> {
+        self.tx.subscribe()
+    }
+
+    pub fn emit(&self, event: TorrentEvent) {
+        // Errors only mean there are no subscribers.
+        if self.session_tx.receiver_count() > 0 {
+            let _ = self.session_tx.send((self.id, event.clone()));
+        }
+        let _ = self.tx.send(event);
+    }
+}
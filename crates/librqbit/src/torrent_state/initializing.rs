@@ -1,7 +1,7 @@
 use std::{
     sync::{
         Arc,
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
     },
     time::Instant,
 };
@@ -9,14 +9,15 @@ use std::{
 use anyhow::Context;
 
 use itertools::Itertools;
+use parking_lot::Mutex;
 use rand::Rng;
 use size_format::SizeFormatterBinary as SF;
-use tracing::{info, trace, warn};
+use tracing::{debug, info, trace, warn};
 
 use crate::{
     api::TorrentIdOrHash,
     bitv::BitV,
-    bitv_factory::BitVFactory,
+    bitv_factory::{BitVFactory, FilesSnapshot},
     chunk_tracker::{ChunkTracker, compute_selected_pieces},
     file_ops::FileOps,
     type_aliases::{BF, FileStorage},
@@ -31,6 +32,10 @@ pub struct TorrentStateInitializing {
     pub(crate) only_files: Option<Vec<usize>>,
     pub(crate) checked_bytes: AtomicU64,
     previously_errored: bool,
+    // Set if fastresume data wasn't used, i.e. all pieces were hashed.
+    did_full_check: AtomicBool,
+    // Used as fastresume data if the session has none, see AddTorrentOptions::resume_bitfield.
+    resume_bitfield: Mutex<Option<BF>>,
 }
 
 impl TorrentStateInitializing {
@@ -48,18 +53,37 @@ impl TorrentStateInitializing {
             files,
             checked_bytes: AtomicU64::new(0),
             previously_errored,
+            did_full_check: AtomicBool::new(false),
+            resume_bitfield: Mutex::new(None),
         }
     }
 
+    pub(crate) fn with_resume_bitfield(self, bitfield: Option<BF>) -> Self {
+        *self.resume_bitfield.lock() = bitfield;
+        self
+    }
+
     pub fn get_checked_bytes(&self) -> u64 {
         self.checked_bytes
             .load(std::sync::atomic::Ordering::Relaxed)
     }
 
+    pub(crate) fn did_full_check(&self) -> bool {
+        self.did_full_check.load(Ordering::Relaxed)
+    }
+
+    async fn files_snapshot(&self) -> Option<FilesSnapshot> {
+        self.shared
+            .spawner
+            .block_in_place_with_semaphore(|| self.shared.files_snapshot(&self.metadata))
+            .await
+    }
+
     async fn validate_fastresume(
         &self,
         bitv_factory: &dyn BitVFactory,
         have_pieces: Option<Box<dyn BitV>>,
+        files_unchanged: bool,
     ) -> Option<Box<dyn BitV>> {
         let hp = have_pieces?;
         let actual = hp.as_bytes().len();
@@ -73,6 +97,11 @@ impl TorrentStateInitializing {
             return None;
         }
 
+        if files_unchanged {
+            debug!(id = ?self.shared.id, "files didn't change since the bitfield was stored, skipping validation");
+            return Some(hp);
+        }
+
         let is_broken = self
             .shared
             .spawner
@@ -81,7 +110,8 @@ impl TorrentStateInitializing {
                     &self.metadata.info,
                     &self.files,
                     &self.metadata.file_infos,
-                );
+                )
+                .with_v2(self.metadata.v2.as_ref());
 
                 use rand::seq::SliceRandom;
 
@@ -168,32 +198,81 @@ impl TorrentStateInitializing {
             .context("session is dead")?
             .bitv_factory
             .clone();
+        let mut from_resume_bitfield = false;
+        let mut files_unchanged = false;
         let have_pieces = if self.previously_errored {
             if let Err(e) = bitv_factory.clear(id).await {
                 warn!(id=?self.shared.id, info_hash = ?self.shared.info_hash, error=?e, "error clearing bitfield");
             }
             None
         } else {
-            bitv_factory
+            match bitv_factory
                 .load(id)
                 .await
                 .context("error loading have_pieces")?
+            {
+                Some(h) => {
+                    let stored = match bitv_factory.load_files_snapshot(id).await {
+                        Ok(s) => s,
+                        Err(e) => {
+                            warn!(id=?self.shared.id, info_hash = ?self.shared.info_hash, "error loading files snapshot: {e:#}");
+                            None
+                        }
+                    };
+                    match (stored, self.files_snapshot().await) {
+                        (Some(stored), Some(current)) if stored != current => {
+                            warn!(
+                                id = ?self.shared.id,
+                                info_hash = ?self.shared.info_hash,
+                                "files changed since the bitfield was stored, will do full check"
+                            );
+                            if let Err(e) = bitv_factory.clear(id).await {
+                                warn!(id=?self.shared.id, info_hash = ?self.shared.info_hash, "error clearing bitfield: {e:#}");
+                            }
+                            None
+                        }
+                        (Some(_), Some(_)) => {
+                            files_unchanged = true;
+                            Some(h)
+                        }
+                        _ => Some(h),
+                    }
+                }
+                None => {
+                    let resume = self.resume_bitfield.lock().take();
+                    from_resume_bitfield = resume.is_some();
+                    resume.map(|b| b.into_dyn())
+                }
+            }
         };
 
-        let have_pieces = self.validate_fastresume(&*bitv_factory, have_pieces).await;
+        let mut have_pieces = self
+            .validate_fastresume(&*bitv_factory, have_pieces, files_unchanged)
+            .await;
+        if from_resume_bitfield && let Some(h) = have_pieces.take() {
+            // Store it so that further progress is persisted the same way as after a full check.
+            have_pieces = Some(
+                bitv_factory
+                    .store_initial_check(id, BF::from_boxed_slice(h.as_bytes().into()))
+                    .await
+                    .context("error storing resume bitfield")?,
+            );
+        }
 
         let have_pieces = match have_pieces {
             Some(h) => h,
             None => {
                 info!("Doing initial checksum validation, this might take a while...");
-                let have_pieces = self
-                    .shared
-                    .spawner
-                    .block_in_place_with_semaphore(|| {
-                        FileOps::new(&self.metadata.info, &self.files, &self.metadata.file_infos)
-                            .initial_check(&self.checked_bytes)
-                    })
-                    .await?;
+                self.did_full_check.store(true, Ordering::Relaxed);
+                let have_pieces =
+                    FileOps::new(&self.metadata.info, &self.files, &self.metadata.file_infos)
+                        .with_v2(self.metadata.v2.as_ref())
+                        .initial_check(
+                            &self.checked_bytes,
+                            &self.shared.spawner,
+                            self.shared.options.hashing_concurrency(),
+                        )
+                        .await?;
                 bitv_factory
                     .store_initial_check(id, have_pieces)
                     .await
@@ -210,6 +289,7 @@ impl TorrentStateInitializing {
                     .unwrap_or(true)
             },
             &self.metadata.file_infos,
+            &self.shared.wanted_ranges.read(),
         );
 
         let chunk_tracker = ChunkTracker::new(
@@ -231,9 +311,12 @@ impl TorrentStateInitializing {
         );
 
         // Ensure file lengths are correct, and reopen read-only.
-        self.shared
+        let allocation = self.shared.options.allocation;
+        let allocation_used = self
+            .shared
             .spawner
             .block_in_place_with_semaphore(|| {
+                let mut allocation_used = allocation;
                 for (idx, fi) in self.metadata.file_infos.iter().enumerate() {
                     if self
                         .only_files
@@ -245,25 +328,47 @@ impl TorrentStateInitializing {
                         if fi.attrs.padding {
                             continue;
                         }
-                        if let Err(err) = self.files.ensure_file_length(idx, fi.len) {
-                            warn!(
-                                id=?self.shared.id, info_hash = ?self.shared.info_hash,
-                                "Error setting length for file {:?} to {}: {:#?}",
-                                fi.relative_filename, fi.len, err
-                            );
-                        } else {
-                            trace!(
-                                "Set length for file {:?} to {} in {:?}",
-                                fi.relative_filename,
-                                SF::new(fi.len),
-                                now.elapsed()
-                            );
+                        match self.files.allocate_file(idx, fi.len, allocation) {
+                            Err(err) => {
+                                warn!(
+                                    id=?self.shared.id, info_hash = ?self.shared.info_hash,
+                                    "Error setting length for file {:?} to {}: {:#?}",
+                                    fi.relative_filename, fi.len, err
+                                );
+                            }
+                            Ok(used) => {
+                                if used != allocation {
+                                    allocation_used = used;
+                                }
+                                trace!(
+                                    "Set length for file {:?} to {} ({:?}) in {:?}",
+                                    fi.relative_filename,
+                                    SF::new(fi.len),
+                                    used,
+                                    now.elapsed()
+                                );
+                            }
                         }
                     }
                 }
-                Ok::<_, anyhow::Error>(())
+                Ok::<_, anyhow::Error>(allocation_used)
             })
             .await?;
+        if allocation_used != allocation {
+            warn!(
+                id=?self.shared.id, info_hash = ?self.shared.info_hash,
+                "{:?} allocation is not supported, used {:?} allocation instead",
+                allocation, allocation_used
+            );
+        }
+        *self.shared.allocation_used.write() = Some(allocation_used);
+
+        // The bitfield matches the files now, unless they're written to later.
+        if let Some(snapshot) = self.files_snapshot().await
+            && let Err(e) = bitv_factory.store_files_snapshot(id, &snapshot).await
+        {
+            warn!(id=?self.shared.id, info_hash = ?self.shared.info_hash, "error storing files snapshot: {e:#}");
+        }
 
         let paused = TorrentStatePaused {
             shared: self.shared.clone(),
@@ -0,0 +1,66 @@
+// The allowed fast set (BEP 6): pieces a peer may request from us even while choked. It's derived
+// from the peer's IP and the info hash, so a peer can't get a different set by reconnecting.
+
+use std::net::IpAddr;
+
+use librqbit_core::hash_id::Id20;
+use sha1w::{ISha1, Sha1};
+
+pub(crate) const ALLOWED_FAST_SET_SIZE: u32 = 10;
+
+// BEP 6 only defines the set for IPv4 peers, it's empty for others.
+pub(crate) fn allowed_fast_set(k: u32, total_pieces: u32, ip: IpAddr, info_hash: Id20) -> Vec<u32> {
+    let IpAddr::V4(ip) = ip.to_canonical() else {
+        return Vec::new();
+    };
+    let k = k.min(total_pieces) as usize;
+    let mut set = Vec::with_capacity(k);
+
+    let mut x = [0u8; 20];
+    let mut sha = Sha1::new();
+    sha.update(&(u32::from(ip) & 0xffffff00).to_be_bytes());
+    sha.update(&info_hash.0);
+    x.copy_from_slice(&sha.finish());
+
+    while set.len() < k {
+        for chunk in x.chunks_exact(4) {
+            if set.len() >= k {
+                break;
+            }
+            let y = u32::from_be_bytes(chunk.try_into().unwrap());
+            let index = y % total_pieces;
+            if !set.contains(&index) {
+                set.push(index);
+            }
+        }
+        if set.len() < k {
+            let mut sha = Sha1::new();
+            sha.update(&x);
+            x = sha.finish();
+        }
+    }
+    set
+}
+
+#[cfg(test)]
+mod tests {
+    use librqbit_core::hash_id::Id20;
+
+    use super::allowed_fast_set;
+
+    #[test]
+    fn test_allowed_fast_set_bep6_example() {
+        let ip = "80.4.4.200".parse().unwrap();
+        let info_hash = Id20::new([0xaa; 20]);
+        assert_eq!(
+            allowed_fast_set(7, 1313, ip, info_hash),
+            vec![1059, 431, 808, 1217, 287, 376, 1188]
+        );
+        assert_eq!(
+            allowed_fast_set(9, 1313, ip, info_hash),
+            vec![1059, 431, 808, 1217, 287, 376, 1188, 353, 508]
+        );
+        assert!(allowed_fast_set(7, 1313, "::1".parse().unwrap(), info_hash).is_empty());
+        assert_eq!(allowed_fast_set(7, 2, ip, info_hash).len(), 2);
+    }
+}
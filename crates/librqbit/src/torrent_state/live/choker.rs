@@ -0,0 +1,183 @@
+// The standard BitTorrent choking algorithm.
+//
+// Every CHOKE_INTERVAL the interested peers that gave us the most data since the last round
+// (or took the most when we are seeding) get the unchoke slots. On top of that one random
+// interested peer is unchoked "optimistically", and it's rotated every OPTIMISTIC_ROUNDS rounds.
+// This lets new peers prove they are fast.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use rand::seq::IndexedRandom;
+
+use crate::type_aliases::PeerHandle;
+
+pub(crate) const CHOKE_INTERVAL: Duration = Duration::from_secs(10);
+const OPTIMISTIC_ROUNDS: u32 = 3;
+
+#[derive(Default)]
+struct ChokerState {
+    unchoked: HashSet<PeerHandle>,
+    optimistic: Option<PeerHandle>,
+    rounds: u32,
+    // Bytes transferred with each peer as of the last round.
+    last_bytes: HashMap<PeerHandle, u64>,
+}
+
+pub(crate) struct Choker {
+    slots: usize,
+    state: Mutex<ChokerState>,
+}
+
+// Which peers to unchoke and choke after a round.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ChokeChanges {
+    pub unchoke: Vec<PeerHandle>,
+    pub choke: Vec<PeerHandle>,
+}
+
+impl Choker {
+    pub fn new(slots: usize) -> Self {
+        Self {
+            slots,
+            state: Default::default(),
+        }
+    }
+
+    pub fn is_unchoked(&self, peer: PeerHandle) -> bool {
+        self.state.lock().unchoked.contains(&peer)
+    }
+
+    pub fn unchoked(&self) -> Vec<PeerHandle> {
+        self.state.lock().unchoked.iter().copied().collect()
+    }
+
+    pub fn optimistic(&self) -> Option<PeerHandle> {
+        self.state.lock().optimistic
+    }
+
+    // Unchoke a newly interested peer right away if a slot is free, so it doesn't wait
+    // for the next round. Returns true if the peer needs to be sent an unchoke.
+    pub fn on_peer_interested(&self, peer: PeerHandle) -> bool {
+        let mut g = self.state.lock();
+        if g.unchoked.contains(&peer) || g.unchoked.len() > self.slots {
+            return false;
+        }
+        g.unchoked.insert(peer)
+    }
+
+    pub fn on_peer_dropped(&self, peer: PeerHandle) {
+        let mut g = self.state.lock();
+        g.unchoked.remove(&peer);
+        g.last_bytes.remove(&peer);
+        if g.optimistic == Some(peer) {
+            g.optimistic = None;
+        }
+    }
+
+    // Run one round. "interested" has the interested live peers with the total bytes
+    // downloaded from (or uploaded to when seeding) each of them.
+    pub fn round(&self, interested: &[(PeerHandle, u64)]) -> ChokeChanges {
+        let mut g = self.state.lock();
+        let g = &mut *g;
+
+        let mut by_rate: Vec<(PeerHandle, u64)> = interested
+            .iter()
+            .map(|(peer, bytes)| {
+                let last = g.last_bytes.get(peer).copied().unwrap_or_default();
+                (*peer, bytes.saturating_sub(last))
+            })
+            .collect();
+        g.last_bytes = interested.iter().copied().collect();
+        by_rate.sort_by_key(|(_, rate)| std::cmp::Reverse(*rate));
+
+        let mut unchoked: HashSet<PeerHandle> = by_rate
+            .iter()
+            .take(self.slots)
+            .map(|(peer, _)| *peer)
+            .collect();
+
+        let rotate = g.rounds.is_multiple_of(OPTIMISTIC_ROUNDS);
+        g.rounds = g.rounds.wrapping_add(1);
+        let current = g
+            .optimistic
+            .filter(|p| !unchoked.contains(p) && by_rate.iter().any(|(peer, _)| peer == p));
+        g.optimistic = match current {
+            Some(p) if !rotate => Some(p),
+            _ => {
+                let rest: Vec<PeerHandle> = by_rate
+                    .iter()
+                    .map(|(peer, _)| *peer)
+                    .filter(|p| !unchoked.contains(p))
+                    .collect();
+                rest.choose(&mut rand::rng()).copied()
+            }
+        };
+        unchoked.extend(g.optimistic);
+
+        let changes = ChokeChanges {
+            unchoke: unchoked.difference(&g.unchoked).copied().collect(),
+            choke: g.unchoked.difference(&unchoked).copied().collect(),
+        };
+        g.unchoked = unchoked;
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::Choker;
+
+    fn peer(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_choker_round() {
+        let choker = Choker::new(2);
+
+        // 4 peers, the 2 fastest get the slots and one of the others the optimistic unchoke.
+        let changes = choker.round(&[(peer(1), 100), (peer(2), 400), (peer(3), 300), (peer(4), 0)]);
+        assert_eq!(changes.unchoke.len(), 3);
+        assert!(changes.choke.is_empty());
+        assert!(choker.is_unchoked(peer(2)));
+        assert!(choker.is_unchoked(peer(3)));
+        let optimistic = choker.optimistic().unwrap();
+        assert!([peer(1), peer(4)].contains(&optimistic));
+
+        // Rates are counted since the last round, so peer 4 is now the fastest and peer 3
+        // the slowest.
+        let changes = choker.round(&[
+            (peer(1), 150),
+            (peer(2), 450),
+            (peer(3), 300),
+            (peer(4), 500),
+        ]);
+        assert!(choker.is_unchoked(peer(4)));
+        assert!(choker.is_unchoked(peer(2)) || choker.is_unchoked(peer(1)));
+        assert!(changes.choke.iter().all(|p| *p != peer(4)));
+        assert_eq!(choker.unchoked().len(), 3);
+
+        // Peers that are gone or not interested anymore get choked.
+        let changes = choker.round(&[(peer(1), 150)]);
+        assert_eq!(choker.unchoked(), vec![peer(1)]);
+        assert!(changes.choke.contains(&peer(4)));
+    }
+
+    #[test]
+    fn test_choker_on_peer_interested() {
+        let choker = Choker::new(1);
+        assert!(choker.on_peer_interested(peer(1)));
+        assert!(!choker.on_peer_interested(peer(1)));
+        assert!(choker.on_peer_interested(peer(2)));
+        // 1 slot + the optimistic one are taken.
+        assert!(!choker.on_peer_interested(peer(3)));
+        choker.on_peer_dropped(peer(1));
+        assert!(choker.on_peer_interested(peer(3)));
+    }
+}
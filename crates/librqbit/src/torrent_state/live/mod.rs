@@ -22,6 +22,11 @@
 //   and are supposed to finish quickly (apart from writing to disk, which is accounted for as "spawn_blocking").
 // - "peer_chunk_requester" - this continuously sends requests for chunks to the peer.
 //   it may steal chunks/pieces from other peers.
+//   When only a few pieces are left ("endgame"), it may also request pieces that other peers are
+//   already downloading. Whoever delivers a chunk first wins, the duplicate requests are cancelled.
+//
+// Each web seed (BEP-19) has a "web_seed" task. While peers are slow, it reserves pieces like a peer
+// would and fetches them over HTTP, see web_seed.rs.
 //
 // ## Peer lifecycle
 // State transitions:
@@ -30,7 +35,8 @@
 // - ANY STATE -> dead (on error)
 // - ANY STATE -> not_needed (when we don't need to talk to the peer anymore)
 //
-// When the peer dies, it's rescheduled with exponential backoff.
+// When the peer dies, it's rescheduled with exponential backoff. Peers that had many of the
+// pieces we need are retried sooner a few times first.
 //
 // > NOTE: deadlock notice:
 // > peers and stateLocked are behind 2 different locks.
@@ -39,13 +45,18 @@
 // > so don't lock them both at the same time at all, or at the worst lock them in the
 // > same order (peers one first, then the global one).
 
+mod allowed_fast;
+mod choker;
 pub mod peer;
 pub mod peers;
+pub(crate) mod read_cache;
 pub mod stats;
+mod super_seeder;
+mod web_seed;
 
 use std::{
     borrow::Cow,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     net::{IpAddr, SocketAddr},
     num::NonZeroU32,
     sync::{
@@ -72,6 +83,11 @@ use peer_binary_protocol::{
     extended::{
         self, ExtendedMessage,
         handshake::ExtendedHandshake,
+        ut_holepunch::{
+            PEX_FLAG_SUPPORTS_HOLEPUNCH, UT_HOLEPUNCH_ERR_NO_SUCH_PEER,
+            UT_HOLEPUNCH_ERR_NO_SUPPORT, UT_HOLEPUNCH_ERR_NOT_CONNECTED, UtHolepunch,
+            UtHolepunchMsgType,
+        },
         ut_metadata::{UtMetadata, UtMetadataData},
         ut_pex::UtPex,
     },
@@ -84,40 +100,84 @@ use tokio_util::sync::CancellationToken;
 use tracing::{Instrument, debug, debug_span, error, info, trace, warn};
 
 use crate::{
-    Error,
+    Error, TorrentError,
     chunk_tracker::{ChunkMarkingResult, ChunkTracker, HaveNeededSelected},
+    file_info::FilePriority,
     file_ops::FileOps,
-    limits::Limits,
-    peer_connection::{
-        PeerConnection, PeerConnectionHandler, PeerConnectionOptions, WriterRequest,
-    },
+    peer_connection::{PeerConnection, PeerConnectionHandler, WriterRequest},
     piece_tracker::{AcquireRequest, AcquireResult, PieceTracker},
     session::CheckedIncomingConnection,
     session_stats::SessionStats,
     stream_connect::ConnectionKind,
     torrent_state::{peer::Peer, utils::atomic_inc},
-    type_aliases::{BF, FilePriorities, FileStorage, PeerHandle},
+    type_aliases::{BF, FileInfos, FilePriorities, FileStorage, PeerHandle},
 };
 
 use self::{
+    allowed_fast::{ALLOWED_FAST_SET_SIZE, allowed_fast_set},
+    choker::{CHOKE_INTERVAL, Choker},
     peer::{
         PeerRx, PeerState, PeerTx,
         stats::{
             atomic::PeerCountersAtomic as AtomicPeerCounters,
-            snapshot::{PeerStatsFilter, PeerStatsSnapshot},
+            snapshot::{
+                ConnectedPeerStats, InflightRequest, PeerStats, PeerStatsFilter, PeerStatsSnapshot,
+            },
         },
     },
     peers::PeerStates,
+    read_cache::{PieceReadCache, ReadCacheStats},
     stats::{atomic::AtomicStats, snapshot::StatsSnapshot},
+    super_seeder::SuperSeeder,
 };
 
 use super::{
     ManagedTorrentShared, TorrentMetadata,
+    events::TorrentEvent,
+    is_disk_full_error,
     paused::TorrentStatePaused,
     streaming::TorrentStreams,
     utils::{TimedExistence, timeit},
 };
 
+// The order in which files' pieces are requested: high priority files first and low priority
+// ones last, within the same priority by filename, cause many torrents have random sort order.
+// In sequential mode, files of the same priority are in torrent order instead, so that pieces
+// are requested in order.
+//
+// A piece shared by two files is requested together with the first of them, so it gets the
+// higher priority of the two.
+fn compute_file_priorities(
+    file_infos: &FileInfos,
+    file_priority_overrides: &HashMap<usize, FilePriority>,
+    sequential: bool,
+) -> FilePriorities {
+    let prio = |id: &usize| {
+        std::cmp::Reverse(
+            file_priority_overrides
+                .get(id)
+                .copied()
+                .unwrap_or(FilePriority::Normal),
+        )
+    };
+    let mut pri = (0..file_infos.len()).collect::<Vec<usize>>();
+    if sequential {
+        pri.sort_by_key(prio);
+        return pri;
+    }
+    pri.sort_unstable_by_key(|id| {
+        (
+            prio(id),
+            file_infos.get(*id).map(|fi| fi.relative_filename.as_path()),
+        )
+    });
+    pri
+}
+
+const SPEED_ESTIMATOR_TICK: Duration = Duration::from_millis(100);
+// 10 seconds worth of ticks.
+const ROLLING_SPEED_WINDOW_TICKS: usize = 100;
+
 fn make_piece_bitfield(lengths: &Lengths) -> BF {
     BF::from_boxed_slice(vec![0; lengths.piece_bitfield_bytes()].into_boxed_slice())
 }
@@ -129,6 +189,8 @@ pub(crate) struct TorrentStateLocked {
 
     // The sorted file list in which order to download them.
     file_priorities: FilePriorities,
+    // Download the pieces of each file in order, see ManagedTorrent::set_sequential().
+    sequential: bool,
 
     // If this is None, then it was already used
     fatal_errors_tx: Option<tokio::sync::oneshot::Sender<anyhow::Error>>,
@@ -172,10 +234,17 @@ impl TorrentStateLocked {
 
 const FLUSH_BITV_EVERY_BYTES: u64 = 16 * 1024 * 1024;
 
+// Request permits added for each allowed fast piece received while choked.
+const ALLOWED_FAST_REQUEST_PERMITS: usize = 4;
+// At most this many are added per peer, however many pieces it allows.
+const MAX_ALLOWED_FAST_REQUEST_PERMITS: usize =
+    ALLOWED_FAST_REQUEST_PERMITS * ALLOWED_FAST_SET_SIZE as usize;
+
 pub enum AddIncomingPeerResult {
     Added,
     AlreadyActive,
     ConcurrencyLimitReached,
+    Filtered,
 }
 
 pub struct TorrentStateLive {
@@ -193,15 +262,22 @@ pub struct TorrentStateLive {
 
     // Limits how many active (occupying network resources) peers there are at a moment in time.
     peer_semaphore: Arc<Semaphore>,
+    // Limits outgoing connections that are still connecting / handshaking.
+    half_open_semaphore: Arc<Semaphore>,
 
     // The queue for peer manager to connect to them.
     peer_queue_tx: UnboundedSender<SocketAddr>,
 
     finished_notify: Notify,
-    new_pieces_notify: Notify,
+    pub(crate) new_pieces_notify: Notify,
 
     down_speed_estimator: SpeedEstimator,
     up_speed_estimator: SpeedEstimator,
+    // Same as above, but averaged over a longer window for a steadier speed and ETA.
+    down_speed_rolling_estimator: SpeedEstimator,
+    up_speed_rolling_estimator: SpeedEstimator,
+    // Download speed from peers only, without web seeds.
+    peer_down_speed_estimator: SpeedEstimator,
     cancellation_token: CancellationToken,
 
     session_stats: Arc<SessionStats>,
@@ -213,7 +289,38 @@ pub struct TorrentStateLive {
         tokio::sync::mpsc::UnboundedSender<WriterRequest>,
         ChunkInfo,
     )>,
-    ratelimits: Limits,
+
+    // Whole pieces recently read for uploading. None if disabled.
+    read_cache: Option<PieceReadCache>,
+
+    // None if all peers are unchoked.
+    choker: Option<Choker>,
+
+    // None unless super-seeding, see ManagedTorrent::set_super_seeding().
+    super_seeder: parking_lot::Mutex<Option<SuperSeeder>>,
+
+    // Notified when received chunks were written to disk, see max_pending_write_bytes.
+    writes_drained_notify: Notify,
+}
+
+// Counts a received chunk in pending_writes and pending_write_bytes until it's written to disk.
+struct PendingWrite<'a> {
+    state: &'a TorrentStateLive,
+    bytes: u64,
+}
+
+impl Drop for PendingWrite<'_> {
+    fn drop(&mut self) {
+        self.state
+            .stats
+            .pending_write_bytes
+            .fetch_sub(self.bytes, Ordering::Relaxed);
+        self.state
+            .stats
+            .pending_writes
+            .fetch_sub(1, Ordering::Relaxed);
+        self.state.writes_drained_notify.notify_waiters();
+    }
 }
 
 impl TorrentStateLive {
@@ -221,6 +328,9 @@ impl TorrentStateLive {
         paused: TorrentStatePaused,
         fatal_errors_tx: tokio::sync::oneshot::Sender<anyhow::Error>,
         cancellation_token: CancellationToken,
+        file_priority_overrides: &HashMap<usize, FilePriority>,
+        sequential: bool,
+        super_seeding: bool,
     ) -> anyhow::Result<Arc<Self>> {
         let (peer_queue_tx, peer_queue_rx) = unbounded_channel();
         let session = paused
@@ -235,19 +345,11 @@ impl TorrentStateLive {
         let have_bytes = paused.chunk_tracker.get_hns().have_bytes;
         let lengths = *paused.chunk_tracker.get_lengths();
 
-        // TODO: make it configurable
-        let file_priorities = {
-            let mut pri = (0..paused.metadata.file_infos.len()).collect::<Vec<usize>>();
-            // sort by filename, cause many torrents have random sort order.
-            pri.sort_unstable_by_key(|id| {
-                paused
-                    .metadata
-                    .file_infos
-                    .get(*id)
-                    .map(|fi| fi.relative_filename.as_path())
-            });
-            pri
-        };
+        let file_priorities = compute_file_priorities(
+            &paused.metadata.file_infos,
+            file_priority_overrides,
+            sequential,
+        );
 
         let (have_broadcast_tx, _) = tokio::sync::broadcast::channel(128);
 
@@ -255,7 +357,6 @@ impl TorrentStateLive {
             tokio::sync::mpsc::UnboundedSender<WriterRequest>,
             ChunkInfo,
         )>();
-        let ratelimits = Limits::new(paused.shared.options.ratelimits);
 
         let state = Arc::new(TorrentStateLive {
             shared: paused.shared.clone(),
@@ -265,10 +366,12 @@ impl TorrentStateLive {
                 stats: Default::default(),
                 states: Default::default(),
                 live_outgoing_peers: Default::default(),
+                banned: Default::default(),
             },
             _locked: RwLock::new(TorrentStateLocked {
                 pieces: Some(PieceTracker::new(paused.chunk_tracker)),
                 file_priorities,
+                sequential,
                 fatal_errors_tx: Some(fatal_errors_tx),
                 unflushed_bitv_bytes: 0,
             }),
@@ -281,11 +384,17 @@ impl TorrentStateLive {
             peer_semaphore: Arc::new(Semaphore::new(
                 paused.shared.options.peer_limit.unwrap_or(128),
             )),
+            half_open_semaphore: Arc::new(Semaphore::new(
+                paused.shared.options.max_half_open.unwrap_or(32),
+            )),
             new_pieces_notify: Notify::new(),
             peer_queue_tx,
             finished_notify: Notify::new(),
             down_speed_estimator,
             up_speed_estimator,
+            down_speed_rolling_estimator: SpeedEstimator::new(ROLLING_SPEED_WINDOW_TICKS),
+            up_speed_rolling_estimator: SpeedEstimator::new(ROLLING_SPEED_WINDOW_TICKS),
+            peer_down_speed_estimator: SpeedEstimator::default(),
             cancellation_token,
             have_broadcast_tx,
             session_stats,
@@ -294,7 +403,17 @@ impl TorrentStateLive {
                 .map(|_| RwLock::new(()))
                 .collect(),
             ratelimit_upload_tx,
-            ratelimits,
+            read_cache: paused
+                .shared
+                .options
+                .read_cache_bytes
+                .filter(|b| *b > 0)
+                .map(PieceReadCache::new),
+            choker: paused.shared.options.unchoke_slots.map(Choker::new),
+            super_seeder: parking_lot::Mutex::new(
+                super_seeding.then(|| SuperSeeder::new(lengths.total_pieces())),
+            ),
+            writes_drained_notify: Notify::new(),
         });
 
         state.spawn(
@@ -321,7 +440,24 @@ impl TorrentStateLive {
                         state
                             .up_speed_estimator
                             .add_snapshot(stats.uploaded_bytes, None, now);
-                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        state.down_speed_rolling_estimator.add_snapshot(
+                            fetched,
+                            Some(remaining),
+                            now,
+                        );
+                        state.up_speed_rolling_estimator.add_snapshot(
+                            stats.uploaded_bytes,
+                            None,
+                            now,
+                        );
+                        state.peer_down_speed_estimator.add_snapshot(
+                            fetched.saturating_sub(
+                                state.stats.web_seed_fetched_bytes.load(Ordering::Relaxed),
+                            ),
+                            None,
+                            now,
+                        );
+                        tokio::time::sleep(SPEED_ESTIMATOR_TICK).await;
                     }
                 }
             },
@@ -338,6 +474,24 @@ impl TorrentStateLive {
             format!("[{}]upload_scheduler", state.shared.id),
             state.clone().task_upload_scheduler(ratelimit_upload_rx),
         );
+
+        if state.choker.is_some() {
+            state.spawn(
+                debug_span!(parent: state.shared.span.clone(), "choker"),
+                format!("[{}]choker", state.shared.id),
+                state.clone().task_choker(),
+            );
+        }
+
+        for (idx, url) in state.shared.web_seeds.iter().enumerate() {
+            state.spawn(
+                debug_span!(parent: state.shared.span.clone(), "web_seed", %url),
+                format!("[{}]web_seed", state.shared.id),
+                state
+                    .clone()
+                    .task_web_seed(idx, url.clone(), session.reqwest_client.clone()),
+            );
+        }
         Ok(state)
     }
 
@@ -359,11 +513,29 @@ impl TorrentStateLive {
         &self.up_speed_estimator
     }
 
+    /// Download speed averaged over the last 10 seconds.
+    pub fn down_speed_rolling_estimator(&self) -> &SpeedEstimator {
+        &self.down_speed_rolling_estimator
+    }
+
+    /// Upload speed averaged over the last 10 seconds.
+    pub fn up_speed_rolling_estimator(&self) -> &SpeedEstimator {
+        &self.up_speed_rolling_estimator
+    }
+
     pub(crate) fn add_incoming_peer(
         self: &Arc<Self>,
         checked_peer: CheckedIncomingConnection,
     ) -> anyhow::Result<AddIncomingPeerResult> {
         use dashmap::mapref::entry::Entry;
+        if !self.is_peer_allowed(&checked_peer.addr) {
+            debug!(addr = %checked_peer.addr, "incoming peer rejected by the peer filter");
+            return Ok(AddIncomingPeerResult::Filtered);
+        }
+        if self.peers.is_banned(&checked_peer.addr) {
+            debug!(addr = %checked_peer.addr, "incoming peer is banned");
+            return Ok(AddIncomingPeerResult::Filtered);
+        }
         let (tx, rx) = unbounded_channel();
         let permit = match self.peer_semaphore.clone().try_acquire_owned() {
             Ok(permit) => permit,
@@ -444,7 +616,7 @@ impl TorrentStateLive {
                 _ = tx.closed() => {
                     continue;
                 }
-                res = self.ratelimits.prepare_for_upload(NonZeroU32::new(ci.size).unwrap()) => {
+                res = self.shared.options.ratelimits.prepare_for_upload(NonZeroU32::new(ci.size).unwrap()) => {
                     res?;
                 }
             };
@@ -463,6 +635,45 @@ impl TorrentStateLive {
         Ok(())
     }
 
+    async fn task_choker(self: Arc<Self>) -> crate::Result<()> {
+        let Some(choker) = self.choker.as_ref() else {
+            return Ok(());
+        };
+        let mut interval = tokio::time::interval(CHOKE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let seeding = self.is_finished();
+            let interested: Vec<(PeerHandle, u64)> = self
+                .peers
+                .states
+                .iter()
+                .filter(|e| e.value().get_live().is_some_and(|l| l.peer_interested))
+                .map(|e| {
+                    let counters = &e.value().stats.counters;
+                    let bytes = if seeding {
+                        counters.uploaded_bytes.load(Ordering::Relaxed)
+                    } else {
+                        counters.fetched_bytes.load(Ordering::Relaxed)
+                    };
+                    (*e.key(), bytes)
+                })
+                .collect();
+            let changes = choker.round(&interested);
+            for (peers, unchoke) in [(changes.unchoke, true), (changes.choke, false)] {
+                for peer in peers {
+                    let msg = if unchoke {
+                        Message::Unchoke
+                    } else {
+                        Message::Choke
+                    };
+                    self.peers.with_live(peer, |live| {
+                        let _ = live.tx.send(WriterRequest::Message(msg));
+                    });
+                }
+            }
+        }
+    }
+
     async fn task_manage_incoming_peer(
         self: Arc<Self>,
         checked_peer: CheckedIncomingConnection,
@@ -476,23 +687,29 @@ impl TorrentStateLive {
             incoming: true,
             on_bitfield_notify: Default::default(),
             unchoke_notify: Default::default(),
-            _locked: RwLock::new(PeerHandlerLocked { i_am_choked: true }),
+            _locked: RwLock::new(PeerHandlerLocked::new()),
             requests_sem: Semaphore::new(0),
             state: self.clone(),
             tx,
             counters,
             first_message_received: AtomicBool::new(false),
+            supports_fast: AtomicBool::new(false),
             cancel_token: self.cancellation_token.child_token(),
+            half_open_permit: Default::default(),
         };
         let _token_guard = handler.cancel_token.clone().drop_guard();
-        let options = PeerConnectionOptions {
-            connect_timeout: self.shared.options.peer_connect_timeout,
-            read_write_timeout: self.shared.options.peer_read_write_timeout,
-            ..Default::default()
+        let options = self.shared.options.peer_connection_options();
+        // Peers from the v2 swarm of a hybrid torrent know it by the truncated v2 info hash, and
+        // expect the same one back.
+        let info_hash = match self.metadata.info_hash_v2() {
+            Some(v2) if v2.truncate_for_dht() == checked_peer.handshake.info_hash => {
+                checked_peer.handshake.info_hash
+            }
+            _ => self.shared.info_hash,
         };
         let peer_connection = PeerConnection::new(
             checked_peer.addr,
-            self.shared.info_hash,
+            info_hash,
             self.shared.peer_id,
             &handler,
             Some(options),
@@ -528,6 +745,7 @@ impl TorrentStateLive {
         self: Arc<Self>,
         addr: SocketAddr,
         permit: OwnedSemaphorePermit,
+        half_open_permit: OwnedSemaphorePermit,
     ) -> crate::Result<()> {
         let state = self;
         let (rx, tx) = state.peers.mark_peer_connecting(addr)?;
@@ -541,21 +759,19 @@ impl TorrentStateLive {
             incoming: false,
             on_bitfield_notify: Default::default(),
             unchoke_notify: Default::default(),
-            _locked: RwLock::new(PeerHandlerLocked { i_am_choked: true }),
+            _locked: RwLock::new(PeerHandlerLocked::new()),
             requests_sem: Semaphore::new(0),
             state: state.clone(),
             tx,
             counters,
             first_message_received: AtomicBool::new(false),
+            supports_fast: AtomicBool::new(false),
             cancel_token: state.cancellation_token.child_token(),
+            half_open_permit: parking_lot::Mutex::new(Some(half_open_permit)),
         };
         let _token_guard = handler.cancel_token.clone().drop_guard();
 
-        let options = PeerConnectionOptions {
-            connect_timeout: state.shared.options.peer_connect_timeout,
-            read_write_timeout: state.shared.options.peer_read_write_timeout,
-            ..Default::default()
-        };
+        let options = state.shared.options.peer_connection_options();
         let peer_connection = PeerConnection::new(
             addr,
             state.shared.info_hash,
@@ -592,7 +808,13 @@ impl TorrentStateLive {
             }
             Err(e) => {
                 debug!("error managing peer: {:#}", e);
+                // The half-open permit is released on handshake, so if it's still there we never
+                // reached the peer.
+                let never_connected = handler.half_open_permit.lock().is_some();
                 handler.on_peer_died(Some(e))?;
+                if never_connected {
+                    state.try_holepunch(addr);
+                }
             }
         }
         drop(permit);
@@ -652,11 +874,17 @@ impl TorrentStateLive {
                 continue;
             }
 
+            // Queued peers wait here until both a connection slot and a half-open slot free up.
             let permit = state.peer_semaphore.clone().acquire_owned().await?;
+            let half_open_permit = state.half_open_semaphore.clone().acquire_owned().await?;
             state.spawn(
                 debug_span!(parent: state.shared.span.clone(), "manage_peer", peer = ?addr),
                 format!("[{}][addr={addr}]manage_peer", state.shared.id),
-                aframe!(state.clone().task_manage_outgoing_peer(addr, permit)),
+                aframe!(
+                    state
+                        .clone()
+                        .task_manage_outgoing_peer(addr, permit, half_open_permit)
+                ),
             );
         }
     }
@@ -676,6 +904,7 @@ impl TorrentStateLive {
     }
     pub(crate) fn file_ops(&self) -> FileOps<'_> {
         FileOps::new(&self.metadata.info, &*self.files, &self.metadata.file_infos)
+            .with_v2(self.metadata.v2.as_ref())
     }
 
     pub(crate) fn lock_read(
@@ -695,6 +924,7 @@ impl TorrentStateLive {
         self.peers.with_peer_mut(handle, "set_peer_live", |p| {
             p.connecting_to_live(h.peer_id, &self.peers, connection_kind);
         });
+        self.shared.events.emit(TorrentEvent::PeerConnected(handle));
     }
 
     pub fn get_uploaded_bytes(&self) -> u64 {
@@ -717,11 +947,57 @@ impl TorrentStateLive {
             .map(|c| *c.get_hns())
     }
 
+    // Check the piece on disk and update whether we have it. Returns true if it passed.
+    pub(crate) async fn verify_piece(&self, id: ValidPieceIndex) -> anyhow::Result<bool> {
+        let is_inflight = self.lock_read("verify_piece").get_pieces()?.is_inflight(id);
+        if is_inflight {
+            bail!("piece {id} is being downloaded");
+        }
+        let ok = self
+            .shared
+            .spawner
+            .block_in_place_with_semaphore(|| self.file_ops().check_piece(id))
+            .await?;
+
+        let mut g = self.lock_write("verify_piece");
+        let pieces = g.get_pieces_mut()?;
+        if pieces.is_inflight(id) {
+            bail!("piece {id} is being downloaded");
+        }
+        let have = pieces.chunks().is_piece_have(id);
+        if ok && !have {
+            pieces.mark_piece_verified(id);
+            drop(g);
+            self.on_piece_completed(id)?;
+            self.transmit_haves(id);
+        } else if !ok && have {
+            pieces.mark_piece_not_have(id, &self.metadata.file_infos);
+            pieces.flush_have_pieces(true)?;
+        }
+        Ok(ok)
+    }
+
     fn transmit_haves(&self, index: ValidPieceIndex) {
         let _ = self.have_broadcast_tx.send(index);
     }
 
+    fn is_peer_allowed(&self, addr: &SocketAddr) -> bool {
+        self.shared
+            .options
+            .peer_filter
+            .as_ref()
+            .is_none_or(|f| f.allow(addr))
+    }
+
     pub(crate) fn add_peer_if_not_seen(&self, addr: SocketAddr) -> crate::Result<bool> {
+        if !self.is_peer_allowed(&addr) {
+            debug!(?addr, "peer rejected by the peer filter");
+            return Ok(false);
+        }
+        if self.peers.is_banned(&addr) {
+            debug!(?addr, "peer is banned");
+            return Ok(false);
+        }
         match self.peers.add_if_not_seen(addr) {
             Some(handle) => handle,
             None => return Ok(false),
@@ -734,6 +1010,64 @@ impl TorrentStateLive {
         Ok(true)
     }
 
+    // Connect to the peer right away, even if it's waiting for its reconnect backoff.
+    fn connect_now(&self, addr: SocketAddr) -> crate::Result<()> {
+        if self.add_peer_if_not_seen(addr)?
+            || !self.is_peer_allowed(&addr)
+            || self.peers.is_banned(&addr)
+        {
+            return Ok(());
+        }
+        let requeue = self
+            .peers
+            .with_peer_mut(addr, "connect_now", |peer| {
+                if matches!(peer.get_state(), PeerState::Dead) {
+                    peer.set_state(PeerState::Queued, &self.peers);
+                    true
+                } else {
+                    false
+                }
+            })
+            .unwrap_or(false);
+        if requeue {
+            self.peer_queue_tx
+                .send(addr)
+                .ok()
+                .ok_or(Error::TorrentIsNotLive)?;
+        }
+        Ok(())
+    }
+
+    // We couldn't connect to the peer. If we learned about it from a peer that supports
+    // ut_holepunch, ask that peer to introduce us (BEP 55).
+    fn try_holepunch(&self, addr: SocketAddr) {
+        let Some(relay) = self
+            .peers
+            .with_peer_mut(addr, "try_holepunch", |peer| peer.holepunch_relay.take())
+            .flatten()
+        else {
+            return;
+        };
+        let sent = self
+            .peers
+            .with_live(relay, |live| {
+                live.supports_holepunch
+                    && live
+                        .tx
+                        .send(WriterRequest::Message(Message::Extended(
+                            ExtendedMessage::UtHolepunch(UtHolepunch::rendezvous(addr)),
+                        )))
+                        .is_ok()
+            })
+            .unwrap_or(false);
+        if sent {
+            debug!(?addr, ?relay, "asked relay to holepunch");
+            self.peers.with_peer_mut(addr, "try_holepunch", |peer| {
+                peer.holepunch_requested_at = Some(Instant::now())
+            });
+        }
+    }
+
     pub fn stats_snapshot(&self) -> StatsSnapshot {
         use Ordering::*;
         let downloaded_bytes = self.stats.downloaded_and_checked_bytes.load(Relaxed);
@@ -743,6 +1077,8 @@ impl TorrentStateLive {
             fetched_bytes: self.stats.fetched_bytes.load(Relaxed),
             uploaded_bytes: self.stats.uploaded_bytes.load(Relaxed),
             total_piece_download_ms: self.stats.total_piece_download_ms.load(Relaxed),
+            pending_writes: self.stats.pending_writes.load(Relaxed),
+            pending_write_bytes: self.stats.pending_write_bytes.load(Relaxed),
             peer_stats: self.peers.stats(),
         }
     }
@@ -754,11 +1090,149 @@ impl TorrentStateLive {
                 .states
                 .iter()
                 .filter(|e| filter.state.matches(e.value().get_state()))
-                .map(|e| (e.key().to_string(), e.value().into()))
+                .map(|e| {
+                    let mut stats = PeerStats::from(e.value());
+                    stats.unchoked =
+                        e.value().get_live().is_some() && self.is_peer_unchoked(*e.key());
+                    (e.key().to_string(), stats)
+                })
+                .collect(),
+        }
+    }
+
+    /// Piece read cache counters. None if the cache is disabled.
+    pub fn read_cache_stats(&self) -> Option<ReadCacheStats> {
+        self.read_cache.as_ref().map(|c| c.stats())
+    }
+
+    /// Stats of all currently connected peers.
+    pub fn connected_peer_stats(&self) -> Vec<ConnectedPeerStats> {
+        let total_pieces = self.lengths.total_pieces() as usize;
+        self.peers
+            .states
+            .iter()
+            .filter_map(|e| {
+                let peer = e.value();
+                let live = peer.get_live()?;
+                Some(ConnectedPeerStats {
+                    addr: peer.addr,
+                    peer_id: live.peer_id,
+                    bytes_down: peer.stats.counters.fetched_bytes.load(Ordering::Relaxed),
+                    bytes_up: peer.stats.counters.uploaded_bytes.load(Ordering::Relaxed),
+                    have_pieces: live
+                        .bitfield
+                        .get(..total_pieces)
+                        .map(|s| s.count_ones().try_into().unwrap_or(u32::MAX))
+                        .unwrap_or_default(),
+                    interested: live.peer_interested,
+                    choked: live.i_am_choked,
+                    unchoked: self.is_peer_unchoked(peer.addr),
+                })
+            })
+            .collect()
+    }
+
+    /// Live peers we currently let download from us. With
+    /// [`AddTorrentOptions::unchoke_slots`](crate::AddTorrentOptions::unchoke_slots) set, these are
+    /// picked by the choking algorithm, otherwise all live peers are unchoked.
+    pub fn unchoked_peers(&self) -> Vec<PeerHandle> {
+        match &self.choker {
+            Some(choker) => choker.unchoked(),
+            None => self
+                .peers
+                .states
+                .iter()
+                .filter(|e| e.value().get_live().is_some())
+                .map(|e| *e.key())
                 .collect(),
         }
     }
 
+    /// The peer unchoked by the choking algorithm to discover faster peers, if any.
+    pub fn optimistic_unchoke(&self) -> Option<PeerHandle> {
+        self.choker.as_ref()?.optimistic()
+    }
+
+    fn pending_write(&self, bytes: u64) -> PendingWrite<'_> {
+        self.stats.pending_writes.fetch_add(1, Ordering::Relaxed);
+        self.stats
+            .pending_write_bytes
+            .fetch_add(bytes, Ordering::Relaxed);
+        PendingWrite { state: self, bytes }
+    }
+
+    /// Wait until all the chunks received so far are written to disk.
+    pub async fn wait_until_writes_drained(&self) {
+        loop {
+            // Grab the token before checking, so that a write finishing in between isn't missed.
+            let notified = self.writes_drained_notify.notified();
+            if self.stats.pending_writes.load(Ordering::Relaxed) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    // Too many received chunks are waiting to be written to disk, so we should stop requesting more.
+    fn is_write_queue_full(&self) -> bool {
+        self.shared
+            .options
+            .max_pending_write_bytes
+            .is_some_and(|max| self.stats.pending_write_bytes.load(Ordering::Relaxed) > max)
+    }
+
+    fn is_peer_unchoked(&self, peer: PeerHandle) -> bool {
+        self.choker.as_ref().is_none_or(|c| c.is_unchoked(peer))
+    }
+
+    /// All block requests sent to live peers that weren't answered yet, oldest first. Useful
+    /// to see what a stalled download is waiting for.
+    pub fn inflight_requests(&self) -> Vec<InflightRequest> {
+        let mut requests = Vec::new();
+        for e in self.peers.states.iter() {
+            let Some(live) = e.value().get_live() else {
+                continue;
+            };
+            requests.extend(live.inflight_requests.iter().map(|(chunk, requested_at)| {
+                InflightRequest {
+                    piece: chunk.piece_index.get(),
+                    begin: chunk.offset,
+                    len: chunk.size,
+                    peer: *e.key(),
+                    requested_at: *requested_at,
+                }
+            }));
+        }
+        requests.sort_unstable_by_key(|r| r.requested_at);
+        requests
+    }
+
+    /// Addresses of live peers that connected to us.
+    pub(crate) fn live_inbound_peer_addrs(&self) -> Vec<SocketAddr> {
+        self.peers
+            .states
+            .iter()
+            .filter(|e| e.value().outgoing_address.is_none() && e.value().get_live().is_some())
+            .map(|e| e.value().addr)
+            .collect()
+    }
+
+    pub(crate) fn live_outbound_peer_count(&self) -> usize {
+        self.peers.live_outgoing_peers.read().len()
+    }
+
+    /// For each piece, the number of connected peers that have it (capped at u16::MAX).
+    pub fn piece_availability(&self) -> Vec<u16> {
+        let total_pieces = self.lengths.total_pieces() as usize;
+        let mut availability = vec![0u16; total_pieces];
+        for e in self.peers.states.iter() {
+            if let Some(live) = e.value().get_live() {
+                add_piece_availability(&mut availability, &live.bitfield);
+            }
+        }
+        availability
+    }
+
     pub async fn wait_until_completed(&self) {
         if self.is_finished() {
             return;
@@ -806,13 +1280,113 @@ impl TorrentStateLive {
     pub(crate) fn update_only_files(&self, only_files: &HashSet<usize>) -> anyhow::Result<()> {
         let mut g = self.lock_write("update_only_files");
         let pt = g.get_pieces_mut()?;
-        let hns = pt.update_only_files(&self.metadata.file_infos, only_files)?;
-        if !hns.finished() {
+        let was_finished = pt.chunks().is_finished();
+        let hns = pt.update_only_files(
+            &self.metadata.file_infos,
+            only_files,
+            &self.shared.wanted_ranges.read(),
+        )?;
+        if hns.finished() {
+            self.on_finished(g, !was_finished);
+        } else {
+            drop(g);
             self.reconnect_all_not_needed_peers();
+            self.new_pieces_notify.notify_waiters();
         }
         Ok(())
     }
 
+    pub(crate) fn update_download_order(
+        &self,
+        file_priority_overrides: &HashMap<usize, FilePriority>,
+        sequential: bool,
+    ) {
+        let file_priorities = compute_file_priorities(
+            &self.metadata.file_infos,
+            file_priority_overrides,
+            sequential,
+        );
+        let mut g = self.lock_write("update_download_order");
+        g.file_priorities = file_priorities;
+        g.sequential = sequential;
+        drop(g);
+        self.new_pieces_notify.notify_waiters();
+    }
+
+    pub(crate) fn set_super_seeding(&self, super_seeding: bool) {
+        let mut ss = self.super_seeder.lock();
+        if super_seeding {
+            // Peers that are already connected have seen our bitfield, so only new ones are
+            // affected.
+            ss.get_or_insert_with(|| SuperSeeder::new(self.lengths.total_pieces()));
+            return;
+        }
+        let Some(prev) = ss.take() else {
+            return;
+        };
+        drop(ss);
+        // Peers that were connected while super-seeding only know about the pieces they were
+        // offered, tell them about the rest.
+        let Ok(we_have) = self
+            .lock_read("set_super_seeding")
+            .get_chunks()
+            .map(|c| BF::from_bitslice(c.get_have_pieces().as_slice()))
+        else {
+            return;
+        };
+        for peer in prev.peers() {
+            self.peers.with_live(peer, |live| {
+                for idx in we_have.iter_ones() {
+                    if !live.bitfield.get(idx).is_some_and(|b| *b) {
+                        let _ = live
+                            .tx
+                            .send(WriterRequest::Message(Message::Have(idx as u32)));
+                    }
+                }
+            });
+        }
+    }
+
+    // Super-seeding is only in effect once we have everything there is to upload.
+    fn is_super_seeding(&self) -> bool {
+        !self.shared.options.disable_upload()
+            && self.super_seeder.lock().is_some()
+            && self.is_finished()
+    }
+
+    // Offer the peer a piece it doesn't have, unless its current offer is still valid.
+    fn super_seed_offer(&self, peer: PeerHandle) {
+        if !self.is_super_seeding() {
+            return;
+        }
+        self.peers.with_live(peer, |live| {
+            let g = self.lock_read("super_seed_offer");
+            let Ok(chunks) = g.get_chunks() else {
+                return;
+            };
+            let mut ss = self.super_seeder.lock();
+            let Some(ss) = ss.as_mut() else {
+                return;
+            };
+            if let Some(piece) =
+                ss.next_offer(peer, chunks.get_have_pieces().as_slice(), &live.bitfield)
+            {
+                trace!(?peer, piece, "super-seeding: offering piece");
+                let _ = live.tx.send(WriterRequest::Message(Message::Have(piece)));
+            }
+        });
+    }
+
+    fn super_seed_on_have(&self, from: PeerHandle, piece: u32) {
+        let spread_to = match self.super_seeder.lock().as_mut() {
+            Some(ss) => ss.on_have(from, piece),
+            None => return,
+        };
+        for peer in spread_to {
+            self.super_seed_offer(peer);
+        }
+    }
+
     // If we have all selected pieces but not necessarily all pieces.
     pub(crate) fn is_finished(&self) -> bool {
         self.get_hns().map(|h| h.finished()).unwrap_or_default()
@@ -826,6 +1400,11 @@ impl TorrentStateLive {
         self.streams
             .streamed_file_ids()
             .any(|file_id| !chunks.is_file_finished(&self.metadata.file_infos[file_id]))
+            || self
+                .streams
+                .iter_waited_pieces(&self.lengths)
+                .chain(self.streams.iter_prefix_pieces(&self.lengths))
+                .any(|piece| !chunks.is_piece_have(piece))
     }
 
     // We might have the torrent "finished" i.e. no selected files. But if someone is streaming files despite
@@ -841,6 +1420,9 @@ impl TorrentStateLive {
         if let Err(e) = self.files.on_piece_completed(id) {
             debug!(?id, "file storage errored in on_piece_completed(): {e:#}");
         }
+        self.shared
+            .events
+            .emit(TorrentEvent::PieceCompleted(id.get()));
         let mut g = self.lock_write("on_piece_completed");
         let locked = &mut **g;
         let pieces = locked.get_pieces_mut()?;
@@ -867,23 +1449,245 @@ impl TorrentStateLive {
 
         let chunks = locked.get_chunks()?;
         if chunks.is_finished() {
-            if chunks.get_selected_pieces()[id.get_usize()] {
-                locked.try_flush_bitv(&self.shared, false);
-                info!(id=self.shared.id, info_hash=?self.shared.info_hash, "torrent finished downloading");
-            }
-            self.finished_notify.notify_waiters();
-
-            if !self.has_active_streams_unfinished_files(locked) {
-                // prevent deadlocks.
-                drop(g);
-                // There is not point being connected to peers that have all the torrent, when
-                // we don't need anything from them, and they don't need anything from us.
-                self.disconnect_all_peers_that_have_full_torrent();
-            }
+            let just_finished = chunks.get_selected_pieces()[id.get_usize()];
+            self.on_finished(g, just_finished);
         }
         Ok(())
     }
 
+    // Called with all selected pieces downloaded. "just_finished" is set when the torrent wasn't
+    // finished before, i.e. when the last selected piece completed or the selection changed.
+    fn on_finished(
+        &self,
+        mut g: TimedExistence<RwLockWriteGuard<'_, TorrentStateLocked>>,
+        just_finished: bool,
+    ) {
+        if just_finished {
+            g.try_flush_bitv(&self.shared, false);
+            self.shared.spawn_store_files_snapshot(&self.metadata);
+            info!(id=self.shared.id, info_hash=?self.shared.info_hash, "torrent finished downloading");
+            self.shared.events.emit(TorrentEvent::Completed);
+        }
+        self.finished_notify.notify_waiters();
+
+        if !self.has_active_streams_unfinished_files(&g) {
+            // prevent deadlocks.
+            drop(g);
+            // There is not point being connected to peers that have all the torrent, when
+            // we don't need anything from them, and they don't need anything from us.
+            self.disconnect_all_peers_that_have_full_torrent();
+        }
+    }
+
+    /// Write a received chunk, and if it completes the piece, check its hash.
+    ///
+    /// Returns false if the piece failed the hash check.
+    fn write_chunk_and_check(
+        &self,
+        addr: PeerHandle,
+        counters: &AtomicPeerCounters,
+        piece: &Piece<ByteBuf<'_>>,
+        chunk_info: &ChunkInfo,
+    ) -> anyhow::Result<bool> {
+        let index = piece.index;
+
+        // If someone stole the piece by now, ignore it.
+        // However if they didn't, don't let them steal it while we are writing.
+        // So that by the time we are done writing AND if it was the last piece,
+        // we can actually checksum etc.
+        // Otherwise it might get into some weird state.
+        let ppl_guard = {
+            let g = self.lock_read("check_steal");
+
+            let ppl = self
+                .per_piece_locks
+                .get(piece.index as usize)
+                .map(|l| l.read());
+
+            let pieces = g.get_pieces()?;
+            match pieces.get_inflight(chunk_info.piece_index) {
+                Some(inflight) if inflight.is_downloaded_by(addr) => {
+                    if pieces.chunks().is_chunk_downloaded(chunk_info) {
+                        debug!(
+                            ?chunk_info,
+                            "endgame: chunk was already downloaded, ignoring"
+                        );
+                        return Ok(true);
+                    }
+                }
+                Some(inflight) => {
+                    debug!(
+                        "in-flight piece {} was stolen by {}, ignoring",
+                        chunk_info.piece_index, inflight.peer
+                    );
+                    return Ok(true);
+                }
+                None => {
+                    debug!(
+                        "in-flight piece {} not found. it was probably completed by someone else",
+                        chunk_info.piece_index
+                    );
+                    return Ok(true);
+                }
+            };
+
+            ppl
+        };
+
+        // While we hold per piece lock, noone can steal it.
+        // So we can proceed writing knowing that the piece is ours now and will still be by the time
+        // the write is finished.
+        //
+
+        if !cfg!(feature = "_disable_disk_write_net_benchmark") {
+            match self.file_ops().write_chunk(addr, piece, chunk_info) {
+                Ok(()) => {}
+                Err(e) if is_disk_full_error(&e) => {
+                    warn!(
+                        id = self.shared.id,
+                        info_hash = ?self.shared.info_hash,
+                        "disk is full, pausing: {e:#}"
+                    );
+                    return self
+                        .on_fatal_error(e.context(TorrentError::DiskFull))
+                        .map(|_| true);
+                }
+                Err(e) => {
+                    error!(
+                        id = self.shared.id,
+                        info_hash = ?self.shared.info_hash,
+                        "FATAL: error writing chunk to disk: {e:#}"
+                    );
+                    return self.on_fatal_error(e).map(|_| true);
+                }
+            };
+        }
+
+        let (completed, duplicate_requests_to) = {
+            let mut g = self.lock_write("mark_chunk_downloaded");
+            // Endgame: the chunk was also requested from these peers.
+            let duplicate_requests_to: Vec<PeerHandle> = g
+                .get_pieces()?
+                .get_inflight(chunk_info.piece_index)
+                .map(|inflight| inflight.other_peers(addr).collect())
+                .unwrap_or_default();
+            let chunk_marking_result = g.get_pieces_mut()?.mark_chunk_downloaded(piece, addr);
+            trace!(?piece, chunk_marking_result=?chunk_marking_result);
+
+            let completed = match chunk_marking_result {
+                Some(ChunkMarkingResult::Completed) => {
+                    trace!("piece={} done, will write and checksum", piece.index);
+                    // Remove from inflight to prevent others from stealing it during hash check.
+                    g.get_pieces_mut()?.take_inflight(chunk_info.piece_index)
+                }
+                Some(ChunkMarkingResult::PreviouslyCompleted) => {
+                    // TODO: we might need to send cancellations here.
+                    debug!("piece={} was done by someone else, ignoring", piece.index);
+                    return Ok(true);
+                }
+                Some(ChunkMarkingResult::NotCompleted) => None,
+                None => {
+                    anyhow::bail!(
+                        "bogus data received: {:?}, cannot map this to a chunk, dropping peer",
+                        piece
+                    );
+                }
+            };
+            (completed, duplicate_requests_to)
+        };
+
+        // Peers locks must not be taken while holding the state lock, so cancelling
+        // after it's released.
+        for other in duplicate_requests_to {
+            self.peers.cancel_request(other, chunk_info);
+        }
+
+        // We don't care about per piece lock anymore, as it's removed from inflight pieces.
+        // It shouldn't impact perf anyway, but dropping just in case.
+        drop(ppl_guard);
+
+        let completed = match completed {
+            Some(c) => c,
+            None => return Ok(true),
+        };
+        let full_piece_download_time = completed.started.elapsed();
+
+        match self
+            .file_ops()
+            .check_piece(chunk_info.piece_index)
+            .with_context(|| format!("error checking piece={index}"))?
+        {
+            true => {
+                {
+                    let mut g = self.lock_write("mark_piece_downloaded");
+                    g.get_pieces_mut()?
+                        .mark_piece_hash_ok(chunk_info.piece_index);
+                }
+
+                // Global piece counters.
+                let piece_len = self.lengths.piece_length(chunk_info.piece_index) as u64;
+                self.stats
+                    .downloaded_and_checked_bytes
+                    // This counter is used to compute "is_finished", so using
+                    // stronger ordering.
+                    .fetch_add(piece_len, Ordering::Release);
+                self.stats
+                    .downloaded_and_checked_pieces
+                    // This counter is used to compute "is_finished", so using
+                    // stronger ordering.
+                    .fetch_add(1, Ordering::Release);
+                self.stats
+                    .have_bytes
+                    .fetch_add(piece_len, Ordering::Relaxed);
+                #[allow(clippy::cast_possible_truncation)]
+                self.stats.total_piece_download_ms.fetch_add(
+                    full_piece_download_time.as_millis() as u64,
+                    Ordering::Relaxed,
+                );
+
+                // Per-peer piece counters.
+                counters.on_piece_completed(piece_len, full_piece_download_time);
+                self.peers.reset_peer_backoff(addr);
+
+                trace!(piece = index, "successfully downloaded and verified");
+
+                self.on_piece_completed(chunk_info.piece_index)?;
+
+                self.transmit_haves(chunk_info.piece_index);
+            }
+            false => {
+                warn!(
+                    id = self.shared.id,
+                    info_hash = ?self.shared.info_hash,
+                    contributors = ?completed.contributors,
+                    "checksum for piece={} did not validate", index
+                );
+                self.lock_write("mark_piece_broken")
+                    .get_pieces_mut()?
+                    .mark_piece_hash_failed(chunk_info.piece_index);
+                self.new_pieces_notify.notify_waiters();
+
+                // We can't tell which chunk was bad, so everyone who sent one is blamed.
+                let threshold = self.shared.options.hash_fail_ban_threshold;
+                let mut i_am_banned = false;
+                for peer in completed.contributors {
+                    if self.peers.on_hash_failed(peer, threshold) {
+                        warn!(
+                            ?peer,
+                            "banning peer for sending pieces that fail the hash check"
+                        );
+                        i_am_banned |= peer == addr;
+                    }
+                }
+                if i_am_banned {
+                    anyhow::bail!("i am probably a bogus peer and got banned. dying.")
+                }
+                return Ok(false);
+            }
+        };
+        Ok(true)
+    }
+
     fn disconnect_all_peers_that_have_full_torrent(&self) {
         for mut pe in self.peers.states.iter_mut() {
             if let PeerState::Live(l) = pe.value().get_state()
@@ -920,8 +1724,6 @@ impl TorrentStateLive {
         // As per BEP 11 recommended interval is min 60 seconds
         const PEX_MESSAGE_INTERVAL: Duration = Duration::from_secs(60);
 
-        let mut connected = Vec::with_capacity(MAX_SENT_PEERS);
-        let mut dropped = Vec::with_capacity(MAX_SENT_PEERS);
         let mut peer_view_of_live_peers = HashSet::new();
 
         // Wait 10 seconds before sending the first message to assure that peer will stay with us
@@ -937,48 +1739,29 @@ impl TorrentStateLive {
                 return Ok(());
             }
 
-            {
-                let live_peers = self.peers.live_outgoing_peers.read();
-                connected.clear();
-                dropped.clear();
-
-                connected.extend(
-                    live_peers
-                        .difference(&peer_view_of_live_peers)
-                        .take(MAX_SENT_PEERS)
-                        .copied(),
-                );
-                dropped.extend(
-                    peer_view_of_live_peers
-                        .difference(&live_peers)
-                        .take(MAX_SENT_PEERS)
-                        .copied(),
-                );
-            }
+            let (connected, dropped) = pex_deltas(
+                this_peer_addr,
+                &self.peers.live_outgoing_peers.read(),
+                &peer_view_of_live_peers,
+                MAX_SENT_PEERS,
+            );
 
             trace!(connected_len = connected.len(), dropped_len = dropped.len());
 
-            let peer_ip_non_local = match this_peer_addr.ip() {
-                IpAddr::V4(a) => !a.is_loopback() && !a.is_private(),
-                IpAddr::V6(a) => !a.is_loopback() && !a.is_unique_local(),
-            };
-
-            let other_ip_is_local = |addr: &IpAddr| match addr {
-                IpAddr::V4(a) => a.is_loopback() || a.is_private(),
-                IpAddr::V6(a) => {
-                    a.is_loopback() || a.is_unicast_link_local() || a.is_unique_local()
-                }
-            };
-
-            let filter = |addr: &SocketAddr| !(peer_ip_non_local && other_ip_is_local(&addr.ip()));
-
-            // BEP 11 - Dont send closed if they are now in live
-            // it's assured by mutual exclusion of two  above sets  if in sent_peers_live, it cannot be in addrs_live_to_sent,
-            // and addrs_closed_to_sent are only filtered addresses from sent_peers_live
-
             if !connected.is_empty() || !dropped.is_empty() {
-                let pex_msg = extended::ut_pex::UtPex::from_addrs(
-                    connected.iter().copied(),
+                // Let the peer know which of them it can ask us to holepunch to (BEP 55).
+                let connected_peers = connected
+                    .iter()
+                    .map(|&addr| extended::ut_pex::PexPeerInfo {
+                        flags: match self.peers.with_live(addr, |l| l.supports_holepunch) {
+                            Some(true) => PEX_FLAG_SUPPORTS_HOLEPUNCH,
+                            _ => 0,
+                        },
+                        addr,
+                    })
+                    .collect::<Vec<_>>();
+                let pex_msg = extended::ut_pex::UtPex::from_peers(
+                    connected_peers.into_iter(),
                     dropped.iter().copied(),
                 );
                 if tx.send(WriterRequest::UtPex(pex_msg)).is_err() {
@@ -988,14 +1771,62 @@ impl TorrentStateLive {
                 for addr in &dropped {
                     peer_view_of_live_peers.remove(addr);
                 }
-                peer_view_of_live_peers.extend(connected.iter().filter(|a| filter(a)).copied());
+                peer_view_of_live_peers.extend(connected);
             }
         }
     }
 }
 
+// The live peers to announce to a peer as connected and dropped in the next PEX message, given
+// the ones it was already told about. The peer itself isn't sent, and neither are local
+// addresses if the peer isn't local.
+//
+// BEP 11 - Dont send closed if they are now in live. It's assured by the two sets being
+// mutually exclusive.
+fn pex_deltas(
+    this_peer_addr: SocketAddr,
+    live_peers: &HashSet<SocketAddr>,
+    peer_view_of_live_peers: &HashSet<SocketAddr>,
+    max_sent_peers: usize,
+) -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+    let is_local = |addr: &IpAddr| match addr {
+        IpAddr::V4(a) => a.is_loopback() || a.is_private(),
+        IpAddr::V6(a) => a.is_loopback() || a.is_unicast_link_local() || a.is_unique_local(),
+    };
+    let peer_ip_non_local = !is_local(&this_peer_addr.ip());
+    let connected = live_peers
+        .difference(peer_view_of_live_peers)
+        .filter(|a| **a != this_peer_addr && !(peer_ip_non_local && is_local(&a.ip())))
+        .take(max_sent_peers)
+        .copied()
+        .collect();
+    let dropped = peer_view_of_live_peers
+        .difference(live_peers)
+        .take(max_sent_peers)
+        .copied()
+        .collect();
+    (connected, dropped)
+}
+
 struct PeerHandlerLocked {
     pub i_am_choked: bool,
+    // Pieces the peer allows us to request while choked (fast extension).
+    pub allowed_fast: HashSet<ValidPieceIndex>,
+    // Request permits added for "allowed_fast" so far, see MAX_ALLOWED_FAST_REQUEST_PERMITS.
+    pub allowed_fast_permits: usize,
+    // Pieces we allowed the peer to request while choked (fast extension).
+    pub sent_allowed_fast: HashSet<u32>,
+}
+
+impl PeerHandlerLocked {
+    fn new() -> Self {
+        Self {
+            i_am_choked: true,
+            allowed_fast: Default::default(),
+            allowed_fast_permits: 0,
+            sent_allowed_fast: Default::default(),
+        }
+    }
 }
 
 // All peer state that would never be used by other actors should pe put here.
@@ -1026,7 +1857,13 @@ struct PeerHandler {
 
     first_message_received: AtomicBool,
 
+    // Both sides support the fast extension (BEP 6), set on handshake.
+    supports_fast: AtomicBool,
+
     cancel_token: CancellationToken,
+
+    // Held by outgoing connections until the handshake completes.
+    half_open_permit: parking_lot::Mutex<Option<OwnedSemaphorePermit>>,
 }
 
 impl PeerConnectionHandler for &'_ PeerHandler {
@@ -1043,12 +1880,26 @@ impl PeerConnectionHandler for &'_ PeerHandler {
     async fn on_received_message(&self, message: Message<'_>) -> anyhow::Result<()> {
         // The first message must be "bitfield", but if it's not sent,
         // assume the bitfield is all zeroes and was sent.
-        if !matches!(&message, Message::Bitfield(..))
-            && !self.first_message_received.swap(true, Ordering::Relaxed)
+        if !matches!(
+            &message,
+            Message::Bitfield(..) | Message::HaveAll | Message::HaveNone
+        ) && !self.first_message_received.swap(true, Ordering::Relaxed)
         {
             self.on_bitfield_notify.notify_waiters();
         }
 
+        if matches!(
+            &message,
+            Message::HaveAll
+                | Message::HaveNone
+                | Message::SuggestPiece(..)
+                | Message::RejectRequest(..)
+                | Message::AllowedFast(..)
+        ) && !self.supports_fast()
+        {
+            bail!("peer sent {message:?}, but the fast extension wasn't negotiated");
+        }
+
         match message {
             Message::Request(request) => {
                 self.on_download_request(request)
@@ -1068,8 +1919,22 @@ impl PeerConnectionHandler for &'_ PeerHandler {
                 trace!("keepalive received");
             }
             Message::Have(h) => self.on_have(h),
+            Message::HaveAll => {
+                let mut bf = make_piece_bitfield(&self.state.lengths);
+                bf[..self.state.lengths.total_pieces() as usize].fill(true);
+                self.set_bitfield(bf);
+            }
+            Message::HaveNone => self.set_bitfield(make_piece_bitfield(&self.state.lengths)),
+            Message::SuggestPiece(index) => {
+                trace!(index, "received suggest_piece, ignoring");
+            }
+            Message::RejectRequest(request) => self
+                .on_reject_request(request)
+                .context("on_reject_request")?,
+            Message::AllowedFast(index) => self.on_allowed_fast(index),
             Message::NotInterested => {
-                trace!("received \"not interested\", but we don't process it yet")
+                trace!("peer is not interested");
+                self.state.peers.mark_peer_interested(self.addr, false);
             }
             Message::Cancel(_) => {
                 trace!("received \"cancel\", but we don't process it yet")
@@ -1099,10 +1964,22 @@ impl PeerConnectionHandler for &'_ PeerHandler {
                         "received noncompliant PEX message from {}, ignoring",
                         self.addr
                     );
-                } else {
+                } else if self.state.shared.options.enable_pex {
                     self.on_pex_message(pex);
                 }
             }
+            Message::Extended(ExtendedMessage::UtHolepunch(msg)) => {
+                if self.state.metadata.info.info().private {
+                    warn!(
+                        id = self.state.shared.id,
+                        info_hash = ?self.state.shared.info_hash,
+                        "received noncompliant ut_holepunch message from {}, ignoring",
+                        self.addr
+                    );
+                } else {
+                    self.on_holepunch(msg).context("on_holepunch")?;
+                }
+            }
             message => {
                 warn!(
                     id = self.state.shared.id,
@@ -1116,14 +1993,29 @@ impl PeerConnectionHandler for &'_ PeerHandler {
 
     fn serialize_bitfield_message_to_buf(&self, buf: &mut [u8]) -> anyhow::Result<usize> {
         let g = self.state.lock_read("serialize_bitfield_message_to_buf");
-        let msg = Message::Bitfield(ByteBuf(g.get_chunks()?.get_have_pieces().as_bytes()));
+        let have = g.get_chunks()?.get_have_pieces();
+        let have_all = have
+            .as_slice()
+            .get(..self.state.lengths.total_pieces() as usize)
+            .is_some_and(|s| s.all());
+        let msg = if have_all && self.supports_fast() {
+            Message::HaveAll
+        } else {
+            Message::Bitfield(ByteBuf(have.as_bytes()))
+        };
         let len = msg.serialize(buf, &Default::default)?;
         trace!("sending: {:?}, length={}", &msg, len);
         Ok(len)
     }
 
     fn on_handshake(&self, handshake: Handshake, ckind: ConnectionKind) -> anyhow::Result<()> {
+        self.half_open_permit.lock().take();
+        self.supports_fast
+            .store(handshake.supports_fast(), Ordering::Relaxed);
         self.state.set_peer_live(self.addr, handshake, ckind);
+        // Queued now, sent right after the handshake instead of the bitfield.
+        self.state.super_seed_offer(self.addr);
+        self.send_allowed_fast();
         Ok(())
     }
 
@@ -1135,6 +2027,10 @@ impl PeerConnectionHandler for &'_ PeerHandler {
             .stats
             .uploaded_bytes
             .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.state
+            .shared
+            .total_uploaded_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
         self.state
             .session_stats
             .counters
@@ -1143,11 +2039,30 @@ impl PeerConnectionHandler for &'_ PeerHandler {
     }
 
     fn read_chunk(&self, chunk: &ChunkInfo, buf: &mut [u8]) -> anyhow::Result<()> {
-        self.state.file_ops().read_chunk(self.addr, chunk, buf)
+        match &self.state.read_cache {
+            Some(cache) => cache.read_chunk(
+                chunk,
+                self.state.lengths.piece_length(chunk.piece_index),
+                buf,
+                |piece_buf| {
+                    self.state
+                        .file_ops()
+                        .read_piece(chunk.piece_index, piece_buf)
+                },
+            ),
+            None => self.state.file_ops().read_chunk(self.addr, chunk, buf),
+        }
     }
 
     fn on_extended_handshake(&self, hs: &ExtendedHandshake<ByteBuf>) -> anyhow::Result<()> {
-        if !self.state.metadata.info.info().private && hs.m.ut_pex.is_some() {
+        if hs.m.ut_holepunch.is_some() {
+            self.state
+                .peers
+                .with_live_mut(self.addr, "supports_holepunch", |live| {
+                    live.supports_holepunch = true
+                });
+        }
+        if self.state.shared.options.enable_pex && hs.m.ut_pex.is_some() {
             spawn_with_cancel(
                 debug_span!(
                     parent: self.state.shared.span.clone(),
@@ -1180,8 +2095,12 @@ impl PeerConnectionHandler for &'_ PeerHandler {
         Ok(())
     }
 
+    fn should_send_initial_unchoke(&self) -> bool {
+        self.state.choker.is_none()
+    }
+
     fn should_send_bitfield(&self) -> bool {
-        if self.state.torrent().options.disable_upload() {
+        if self.state.torrent().options.disable_upload() || self.state.is_super_seeding() {
             return false;
         }
 
@@ -1189,7 +2108,7 @@ impl PeerConnectionHandler for &'_ PeerHandler {
     }
 
     fn should_transmit_have(&self, id: ValidPieceIndex) -> bool {
-        if self.state.shared.options.disable_upload() {
+        if self.state.shared.options.disable_upload() || self.state.is_super_seeding() {
             return false;
         }
         let have = self
@@ -1212,11 +2131,46 @@ impl PeerConnectionHandler for &'_ PeerHandler {
         {
             handshake.metadata_size = Some(len);
         }
+        if !self.state.shared.options.enable_pex {
+            handshake.m.ut_pex = None;
+        }
+        if self.state.metadata.info.info().private {
+            handshake.m.ut_holepunch = None;
+        }
 
         Ok(())
     }
 }
 
+// Whether the peer has at least half of the selected pieces we don't have yet. Such peers are
+// reconnected sooner if they drop.
+// Count the pieces a peer has. Bitfields shorter than the number of pieces are ignored, and so
+// are the spare bits after the last piece.
+fn add_piece_availability(availability: &mut [u16], bitfield: &BF) {
+    let Some(bf) = bitfield.get(..availability.len()) else {
+        return;
+    };
+    for piece in bf.iter_ones() {
+        availability[piece] = availability[piece].saturating_add(1);
+    }
+}
+
+fn has_many_needed_pieces(chunks: &ChunkTracker, bitfield: &BF) -> bool {
+    let have = chunks.get_have_pieces().as_slice();
+    let mut needed = 0;
+    let mut peer_has = 0;
+    for idx in chunks.get_selected_pieces().iter_ones() {
+        if have.get(idx).is_some_and(|b| *b) {
+            continue;
+        }
+        needed += 1;
+        if bitfield.get(idx).is_some_and(|b| *b) {
+            peer_has += 1;
+        }
+    }
+    peer_has > 0 && peer_has * 2 >= needed
+}
+
 impl PeerHandler {
     fn on_peer_died(self, error: Option<crate::Error>) -> crate::Result<()> {
         let peers = &self.state.peers;
@@ -1234,11 +2188,23 @@ impl PeerHandler {
             }
         };
         let prev = pe.value_mut().take_state(peers);
+        let mut valuable = false;
+        if let Some(choker) = &self.state.choker {
+            choker.on_peer_dropped(handle);
+        }
+        if let Some(ss) = self.state.super_seeder.lock().as_mut() {
+            ss.on_peer_dropped(handle);
+        }
 
         match prev {
             PeerState::Connecting(_) => {}
             PeerState::Live(live) => {
+                self.state
+                    .shared
+                    .events
+                    .emit(TorrentEvent::PeerDisconnected(handle));
                 let mut g = self.state.lock_write("mark_chunk_requests_canceled");
+                valuable = has_many_needed_pieces(g.get_chunks()?, &live.bitfield);
 
                 // Release all pieces owned by this peer (fixes the bug where pieces
                 // could be in both queue_pieces AND inflight_pieces after peer death)
@@ -1252,7 +2218,7 @@ impl PeerHandler {
 
                 // Also handle any chunk-level inflight requests
                 let had_inflight = !live.inflight_requests.is_empty();
-                for req in live.inflight_requests {
+                for req in live.inflight_requests.into_keys() {
                     trace!(
                         "peer dead, marking chunk request cancelled, index={}, chunk={}",
                         req.piece_index.get(),
@@ -1283,6 +2249,14 @@ impl PeerHandler {
             }
         };
 
+        if peers.is_banned(&handle) {
+            debug!("peer is banned, forgetting it");
+            // Prevent deadlocks.
+            drop(pe);
+            peers.drop_peer(handle);
+            return Ok(());
+        }
+
         let _error = match error {
             Some(e) => e,
             None => {
@@ -1311,12 +2285,13 @@ impl PeerHandler {
             return Ok(());
         }
 
-        let backoff = pe.value_mut().stats.backoff.next();
+        let backoff = pe.value_mut().stats.next_reconnect_delay(valuable);
 
         // Prevent deadlocks.
         drop(pe);
 
         if let Some(dur) = backoff {
+            debug!(valuable, "will reconnect in {dur:?}");
             if cfg!(feature = "_disable_reconnect_test") {
                 return Ok(());
             }
@@ -1330,8 +2305,17 @@ impl PeerHandler {
                 format!("[{}][addr={}]wait_for_peer", self.state.shared.id, handle),
                 async move {
                     trace!("waiting to reconnect again");
-                    tokio::time::sleep(dur).await;
+                    {
+                        let _reconnecting = self.state.peers.reconnecting_guard();
+                        tokio::time::sleep(dur).await;
+                    }
                     trace!("finished waiting");
+                    // It could have been banned by IP through another connection meanwhile.
+                    if self.state.peers.is_banned(&handle) {
+                        debug!("peer is banned, not reconnecting");
+                        self.state.peers.drop_peer(handle);
+                        return Ok(());
+                    }
                     let should_requeue = self
                         .state
                         .peers
@@ -1374,10 +2358,11 @@ impl PeerHandler {
         Ok(())
     }
 
-    /// Acquire a piece for this peer: try steal (10x) → reserve → steal (3x).
+    /// Acquire a piece for this peer: try steal (10x) → reserve → steal (3x) → endgame.
     ///
-    /// Returns the piece index to download, or None if no pieces are available.
-    fn acquire_next_piece(&self) -> crate::Result<Option<ValidPieceIndex>> {
+    /// Returns the piece index to download and whether it's also being downloaded by
+    /// other peers (endgame), or None if no pieces are available.
+    fn acquire_next_piece(&self) -> crate::Result<Option<(ValidPieceIndex, bool)>> {
         // Steal info to process after releasing the peer lock
         let mut steal_info: Option<(SocketAddr, ValidPieceIndex)> = None;
 
@@ -1385,10 +2370,13 @@ impl PeerHandler {
             .state
             .peers
             .with_live_mut(self.addr, "acquire_next_piece", |live| {
-                if self.lock_read("i am choked").i_am_choked {
+                let locked = self.lock_read("i am choked");
+                if locked.i_am_choked && locked.allowed_fast.is_empty() {
                     debug!("we are choked, can't acquire piece");
                     return Ok(None);
                 }
+                // While choked only the allowed fast pieces can be requested.
+                let only_allowed_fast = locked.i_am_choked.then_some(&locked.allowed_fast);
                 let mut g = self.state.lock_write("acquire_next_piece");
 
                 let bf = &live.bitfield;
@@ -1396,6 +2384,7 @@ impl PeerHandler {
                 let TorrentStateLocked {
                     pieces,
                     file_priorities,
+                    sequential,
                     ..
                 } = &mut **g;
                 let pieces = pieces.as_mut().ok_or(Error::ChunkTrackerEmpty)?;
@@ -1405,24 +2394,33 @@ impl PeerHandler {
                     priority_pieces: self.state.streams.iter_next_pieces(&self.state.lengths),
                     file_priorities,
                     file_infos: &self.state.metadata.file_infos,
-                    peer_has_piece: |p| bf.get(p.get() as usize).map(|v| *v) == Some(true),
+                    sequential: *sequential,
+                    peer_has_piece: |p| {
+                        bf.get(p.get() as usize).map(|v| *v) == Some(true)
+                            && only_allowed_fast.is_none_or(|s| s.contains(&p))
+                    },
                     can_steal: |p| {
                         self.state.per_piece_locks[p.get_usize()]
                             .try_write()
                             .is_some()
                     },
+                    endgame_threshold: self.state.torrent().options.endgame_threshold,
                 });
 
                 match result {
                     AcquireResult::Reserved(piece) => {
                         trace!("reserved piece {}", piece);
-                        Ok(Some(piece))
+                        Ok(Some((piece, false)))
                     }
                     AcquireResult::Stolen { piece, from_peer } => {
                         debug!("stole piece {} from {}", piece, from_peer);
                         // Store steal info to process after releasing peer lock to avoid deadlock
                         steal_info = Some((from_peer, piece));
-                        Ok(Some(piece))
+                        Ok(Some((piece, false)))
+                    }
+                    AcquireResult::Endgame(piece) => {
+                        debug!("endgame: requesting in-flight piece {}", piece);
+                        Ok(Some((piece, true)))
                     }
                     AcquireResult::NoneAvailable => Ok(None),
                 }
@@ -1443,6 +2441,18 @@ impl PeerHandler {
             anyhow::bail!("upload disabled, but peer requested a piece")
         }
 
+        if !self.state.is_peer_unchoked(self.addr)
+            && !self
+                .lock_read("sent_allowed_fast")
+                .sent_allowed_fast
+                .contains(&request.index)
+        {
+            // The peer might not have received our choke yet.
+            trace!(?request, "ignoring request from a choked peer");
+            self.reject_request(request);
+            return Ok(());
+        }
+
         let piece_index = match self.state.lengths.validate_piece_index(request.index) {
             Some(p) => p,
             None => {
@@ -1473,6 +2483,14 @@ impl PeerHandler {
             .get_chunks()?
             .is_chunk_ready_to_upload(&chunk_info)
         {
+            if self.supports_fast() {
+                debug!(
+                    ?chunk_info,
+                    "rejecting request for a chunk that is not ready to upload"
+                );
+                self.reject_request(request);
+                return Ok(());
+            }
             anyhow::bail!(
                 "got request for a chunk that is not ready to upload. chunk {:?}",
                 &chunk_info
@@ -1516,6 +2534,7 @@ impl PeerHandler {
                     debug!("peer has full torrent");
                 }
             });
+        self.state.super_seed_on_have(self.addr, have);
         self.on_bitfield_notify.notify_waiters();
     }
 
@@ -1528,6 +2547,11 @@ impl PeerHandler {
             );
         }
         let bf = BF::from_boxed_slice(bitfield.0.to_vec().into_boxed_slice());
+        self.set_bitfield(bf);
+        Ok(())
+    }
+
+    fn set_bitfield(&self, bf: BF) {
         if let Some(true) = bf
             .get(..self.state.lengths.total_pieces() as usize)
             .map(|s| s.all())
@@ -1535,7 +2559,118 @@ impl PeerHandler {
             debug!("peer has full torrent");
         }
         self.state.peers.update_bitfield(self.addr, bf);
+        // The piece offered on connect might be one the peer already has.
+        self.state.super_seed_offer(self.addr);
         self.on_bitfield_notify.notify_waiters();
+    }
+
+    fn supports_fast(&self) -> bool {
+        self.supports_fast.load(Ordering::Relaxed)
+    }
+
+    // With the fast extension, requests that won't be served are rejected explicitly. Without it
+    // they are silently dropped.
+    fn reject_request(&self, request: Request) {
+        if self.supports_fast() {
+            let _ = self
+                .tx
+                .send(WriterRequest::Message(Message::RejectRequest(request)));
+        }
+    }
+
+    // Let the peer request a few pieces while choked, so that new peers get something to share
+    // sooner.
+    fn send_allowed_fast(&self) {
+        if !self.supports_fast()
+            || self.state.shared.options.disable_upload()
+            || self.state.is_super_seeding()
+        {
+            return;
+        }
+        let set = allowed_fast_set(
+            ALLOWED_FAST_SET_SIZE,
+            self.state.lengths.total_pieces(),
+            self.addr.ip(),
+            self.state.shared.info_hash,
+        );
+        let Ok(have) = self
+            .state
+            .lock_read("send_allowed_fast")
+            .get_chunks()
+            .map(|c| BF::from_bitslice(c.get_have_pieces().as_slice()))
+        else {
+            return;
+        };
+        let mut g = self.lock_write("send_allowed_fast");
+        for index in set {
+            if !have.get(index as usize).is_some_and(|b| *b) {
+                continue;
+            }
+            g.sent_allowed_fast.insert(index);
+            let _ = self
+                .tx
+                .send(WriterRequest::Message(Message::AllowedFast(index)));
+        }
+    }
+
+    fn on_allowed_fast(&self, index: u32) {
+        let Some(piece) = self.state.lengths.validate_piece_index(index) else {
+            debug!(
+                index,
+                "received allowed_fast for an invalid piece, ignoring"
+            );
+            return;
+        };
+        let mut g = self.lock_write("on_allowed_fast");
+        if !g.allowed_fast.insert(piece) || !g.i_am_choked {
+            return;
+        }
+        // There are no request permits while choked, let a few through for this piece.
+        let permits = ALLOWED_FAST_REQUEST_PERMITS
+            .min(MAX_ALLOWED_FAST_REQUEST_PERMITS - g.allowed_fast_permits);
+        g.allowed_fast_permits += permits;
+        drop(g);
+        trace!(index, permits, "allowed to request piece while choked");
+        self.requests_sem.add_permits(permits);
+        self.unchoke_notify.notify_waiters();
+    }
+
+    fn on_reject_request(&self, request: Request) -> anyhow::Result<()> {
+        let piece_index = self
+            .state
+            .lengths
+            .validate_piece_index(request.index)
+            .with_context(|| format!("peer rejected an invalid piece {}", request.index))?;
+        let chunk_info = self
+            .state
+            .lengths
+            .chunk_info_from_received_data(piece_index, request.begin, request.length)
+            .with_context(|| format!("peer rejected an invalid request {request:?}"))?;
+        let requested = self
+            .state
+            .peers
+            .with_live_mut(self.addr, "inflight_requests.remove", |h| {
+                h.inflight_requests.remove(&chunk_info).is_some()
+            })
+            .context("peer not found")?;
+        if !requested {
+            trace!(
+                ?request,
+                "ignoring reject for a request that isn't in flight"
+            );
+            return Ok(());
+        }
+        trace!(?request, "request rejected");
+        self.requests_sem.add_permits(1);
+        // Let this or other peers download the piece again.
+        if self
+            .state
+            .lock_write("on_reject_request")
+            .get_pieces_mut()?
+            .release_piece(self.addr, piece_index)
+        {
+            self.state.new_pieces_notify.notify_waiters();
+        }
         Ok(())
     }
 
@@ -1559,9 +2694,21 @@ impl PeerHandler {
         .await;
     }
 
+    async fn wait_for_writes_to_drain(&self) {
+        // Every finished write notifies, but the queue might still be full.
+        while self.state.is_write_queue_full() {
+            self.wait_for_any_notify(&self.state.writes_drained_notify, || {
+                !self.state.is_write_queue_full()
+            })
+            .await;
+        }
+    }
+
+    // Also returns while choked if the peer allowed requesting some pieces (fast extension).
     async fn wait_for_unchoke(&self) {
         self.wait_for_any_notify(&self.unchoke_notify, || {
-            !self.lock_read("wait_for_unchoke:i_am_choked").i_am_choked
+            let g = self.lock_read("wait_for_unchoke:i_am_choked");
+            !g.i_am_choked || !g.allowed_fast.is_empty()
         })
         .await;
     }
@@ -1613,7 +2760,11 @@ impl PeerHandler {
                 } else {
                     // TODO: wait for a notification of interest, e.g. update of selected files or new streams or change
                     // in peer interest.
-                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    let _ = aframe!(tokio::time::timeout(
+                        Duration::from_secs(5),
+                        self.state.new_pieces_notify.notified()
+                    ))
+                    .await;
                     continue;
                 }
             }
@@ -1623,7 +2774,7 @@ impl PeerHandler {
 
             // Acquire a piece using the strategy: try steal (10x) → reserve → steal (3x).
             let new_piece_notify = self.state.new_pieces_notify.notified();
-            let next = match self.acquire_next_piece()? {
+            let (next, endgame) = match self.acquire_next_piece()? {
                 Some(next) => next,
                 None => {
                     debug!("no pieces to request");
@@ -1642,6 +2793,17 @@ impl PeerHandler {
             };
 
             for chunk in self.state.lengths.iter_chunk_infos(next) {
+                // Other peers might have already delivered some chunks of an endgame piece.
+                if endgame
+                    && !self
+                        .state
+                        .lock_read("is_chunk_needed_from")
+                        .get_pieces()?
+                        .is_chunk_needed_from(self.addr, &chunk)
+                {
+                    continue;
+                }
+
                 let request = Request {
                     index: next.get(),
                     begin: chunk.offset,
@@ -1652,7 +2814,11 @@ impl PeerHandler {
                     .state
                     .peers
                     .with_live_mut(handle, "add chunk request", |live| {
-                        live.inflight_requests.insert(chunk)
+                        if live.inflight_requests.contains_key(&chunk) {
+                            return false;
+                        }
+                        live.inflight_requests.insert(chunk, Instant::now());
+                        true
                     }) {
                     Some(true) => {}
                     Some(false) => {
@@ -1675,7 +2841,19 @@ impl PeerHandler {
                     None => return Ok(()),
                 };
 
+                if endgame {
+                    self.state.peers.stats.inc_endgame_duplicate_requests();
+                    self.state
+                        .peers
+                        .session_stats
+                        .inc_endgame_duplicate_requests();
+                }
+
+                aframe!(self.wait_for_writes_to_drain()).await;
+
                 self.state
+                    .shared
+                    .options
                     .ratelimits
                     .prepare_for_download(NonZeroU32::new(request.length).unwrap())
                     .await?;
@@ -1712,16 +2890,23 @@ impl PeerHandler {
 
     fn on_i_am_choked(&self) {
         self.lock_write("i_am_choked = true").i_am_choked = true;
+        self.state.peers.mark_i_am_choked(self.addr, true);
     }
 
     fn on_peer_interested(&self) {
         trace!("peer is interested");
         self.state.peers.mark_peer_interested(self.addr, true);
+        if let Some(choker) = &self.state.choker
+            && choker.on_peer_interested(self.addr)
+        {
+            let _ = self.tx.send(WriterRequest::Message(Message::Unchoke));
+        }
     }
 
     fn on_i_am_unchoked(&self) {
         trace!("we are unchoked");
         self.lock_write("i_am_choked = false").i_am_choked = false;
+        self.state.peers.mark_i_am_choked(self.addr, false);
         self.unchoke_notify.notify_waiters();
         // 128 should be more than enough to maintain 100mbps
         // for a single peer that has 100ms ping
@@ -1753,20 +2938,29 @@ impl PeerHandler {
             .fetched_bytes
             .fetch_add(piece.len() as u64, Ordering::Relaxed);
         self.counters.fetched_chunks.fetch_add(1, Ordering::Relaxed);
-
         self.state
+            .shared
+            .total_downloaded_bytes
+            .fetch_add(piece.len() as u64, Ordering::Relaxed);
+
+        let requested = self
+            .state
             .peers
             .with_live_mut(self.addr, "inflight_requests.remove", |h| {
-                if !h.inflight_requests.remove(&chunk_info) {
-                    anyhow::bail!(
-                        "peer sent us a piece we did not ask. Requested pieces: {:?}. Got: {:?}",
-                        &h.inflight_requests,
-                        &piece,
-                    );
-                }
-                Ok(())
+                h.inflight_requests.remove(&chunk_info).is_some()
             })
-            .context("peer not found")??;
+            .context("peer not found")?;
+        if !requested {
+            // In endgame mode we cancel duplicate requests once another peer delivers the chunk,
+            // but the peer might have sent it before receiving the cancellation.
+            let g = self.state.lock_read("check_cancelled_chunk");
+            let chunks = g.get_pieces()?.chunks();
+            if chunks.is_chunk_downloaded(&chunk_info) || chunks.is_piece_have(piece_index) {
+                debug!(?chunk_info, "ignoring a chunk that was already downloaded");
+                return Ok(());
+            }
+            anyhow::bail!("peer sent us a piece we did not ask. Got: {:?}", &piece,);
+        }
 
         // This one is used to calculate download speed.
         self.state
@@ -1779,172 +2973,13 @@ impl PeerHandler {
             .fetched_bytes
             .fetch_add(piece.len() as u64, Ordering::Relaxed);
 
-        fn write_to_disk(
-            state: &TorrentStateLive,
-            addr: PeerHandle,
-            counters: &AtomicPeerCounters,
-            piece: &Piece<ByteBuf<'_>>,
-            chunk_info: &ChunkInfo,
-        ) -> anyhow::Result<()> {
-            let index = piece.index;
-
-            // If someone stole the piece by now, ignore it.
-            // However if they didn't, don't let them steal it while we are writing.
-            // So that by the time we are done writing AND if it was the last piece,
-            // we can actually checksum etc.
-            // Otherwise it might get into some weird state.
-            let ppl_guard = {
-                let g = state.lock_read("check_steal");
-
-                let ppl = state
-                    .per_piece_locks
-                    .get(piece.index as usize)
-                    .map(|l| l.read());
-
-                match g.get_pieces()?.get_inflight(chunk_info.piece_index) {
-                    Some(inflight) if inflight.peer == addr => {}
-                    Some(inflight) => {
-                        debug!(
-                            "in-flight piece {} was stolen by {}, ignoring",
-                            chunk_info.piece_index, inflight.peer
-                        );
-                        return Ok(());
-                    }
-                    None => {
-                        debug!(
-                            "in-flight piece {} not found. it was probably completed by someone else",
-                            chunk_info.piece_index
-                        );
-                        return Ok(());
-                    }
-                };
-
-                ppl
-            };
-
-            // While we hold per piece lock, noone can steal it.
-            // So we can proceed writing knowing that the piece is ours now and will still be by the time
-            // the write is finished.
-            //
-
-            if !cfg!(feature = "_disable_disk_write_net_benchmark") {
-                match state.file_ops().write_chunk(addr, piece, chunk_info) {
-                    Ok(()) => {}
-                    Err(e) => {
-                        error!(
-                            id = state.shared.id,
-                            info_hash = ?state.shared.info_hash,
-                            "FATAL: error writing chunk to disk: {e:#}"
-                        );
-                        return state.on_fatal_error(e);
-                    }
-                };
-            }
-
-            let full_piece_download_time = {
-                let mut g = state.lock_write("mark_chunk_downloaded");
-                let chunk_marking_result = g.get_pieces_mut()?.mark_chunk_downloaded(piece);
-                trace!(?piece, chunk_marking_result=?chunk_marking_result);
-
-                match chunk_marking_result {
-                    Some(ChunkMarkingResult::Completed) => {
-                        trace!("piece={} done, will write and checksum", piece.index);
-                        // Remove from inflight to prevent others from stealing it during hash check.
-                        g.get_pieces_mut()?.take_inflight(chunk_info.piece_index)
-                    }
-                    Some(ChunkMarkingResult::PreviouslyCompleted) => {
-                        // TODO: we might need to send cancellations here.
-                        debug!("piece={} was done by someone else, ignoring", piece.index);
-                        return Ok(());
-                    }
-                    Some(ChunkMarkingResult::NotCompleted) => None,
-                    None => {
-                        anyhow::bail!(
-                            "bogus data received: {:?}, cannot map this to a chunk, dropping peer",
-                            piece
-                        );
-                    }
-                }
-            };
-
-            // We don't care about per piece lock anymore, as it's removed from inflight pieces.
-            // It shouldn't impact perf anyway, but dropping just in case.
-            drop(ppl_guard);
-
-            let full_piece_download_time = match full_piece_download_time {
-                Some(t) => t,
-                None => return Ok(()),
-            };
-
-            match state
-                .file_ops()
-                .check_piece(chunk_info.piece_index)
-                .with_context(|| format!("error checking piece={index}"))?
-            {
-                true => {
-                    {
-                        let mut g = state.lock_write("mark_piece_downloaded");
-                        g.get_pieces_mut()?
-                            .mark_piece_hash_ok(chunk_info.piece_index);
-                    }
-
-                    // Global piece counters.
-                    let piece_len = state.lengths.piece_length(chunk_info.piece_index) as u64;
-                    state
-                        .stats
-                        .downloaded_and_checked_bytes
-                        // This counter is used to compute "is_finished", so using
-                        // stronger ordering.
-                        .fetch_add(piece_len, Ordering::Release);
-                    state
-                        .stats
-                        .downloaded_and_checked_pieces
-                        // This counter is used to compute "is_finished", so using
-                        // stronger ordering.
-                        .fetch_add(1, Ordering::Release);
-                    state
-                        .stats
-                        .have_bytes
-                        .fetch_add(piece_len, Ordering::Relaxed);
-                    #[allow(clippy::cast_possible_truncation)]
-                    state.stats.total_piece_download_ms.fetch_add(
-                        full_piece_download_time.as_millis() as u64,
-                        Ordering::Relaxed,
-                    );
-
-                    // Per-peer piece counters.
-                    counters.on_piece_completed(piece_len, full_piece_download_time);
-                    state.peers.reset_peer_backoff(addr);
-
-                    trace!(piece = index, "successfully downloaded and verified");
-
-                    state.on_piece_completed(chunk_info.piece_index)?;
-
-                    state.transmit_haves(chunk_info.piece_index);
-                }
-                false => {
-                    warn!(
-                        id = state.shared.id,
-                        info_hash = ?state.shared.info_hash,
-                        ?addr,
-                        "checksum for piece={} did not validate. disconnecting peer.", index
-                    );
-                    state
-                        .lock_write("mark_piece_broken")
-                        .get_pieces_mut()?
-                        .mark_piece_hash_failed(chunk_info.piece_index);
-                    state.new_pieces_notify.notify_waiters();
-                    anyhow::bail!("i am probably a bogus peer. dying.")
-                }
-            };
-            Ok(())
-        }
-
+        let _pending_write = self.state.pending_write(piece.len() as u64);
         self.state
             .shared
             .spawner
             .block_in_place_with_semaphore(|| {
-                write_to_disk(&self.state, self.addr, &self.counters, &piece, &chunk_info)
+                self.state
+                    .write_chunk_and_check(self.addr, &self.counters, &piece, &chunk_info)
             })
             .await
             .with_context(|| format!("error processing received chunk {chunk_info:?}"))?;
@@ -1983,22 +3018,121 @@ impl PeerHandler {
     }
 
     fn on_pex_message(&self, msg: UtPex<ByteBuf<'_>>) {
-        msg.dropped_peers()
-            .chain(msg.added_peers())
-            .for_each(|peer| {
+        let sender_supports_holepunch = self
+            .state
+            .peers
+            .with_live(self.addr, |live| live.supports_holepunch)
+            .unwrap_or(false);
+        // Dropped peers are the ones the sender disconnected from, no reason to try them.
+        msg.added_peers().for_each(|peer| {
+            self.state
+                .add_peer_if_not_seen(peer.addr)
+                .map_err(|error| {
+                    warn!(
+                        id = self.state.shared.id,
+                        info_hash = ?self.state.shared.info_hash,
+                        ?peer,
+                        "failed to add peer: {error:#}"
+                    );
+                    error
+                })
+                .ok();
+            if sender_supports_holepunch && peer.flags & PEX_FLAG_SUPPORTS_HOLEPUNCH != 0 {
                 self.state
-                    .add_peer_if_not_seen(peer.addr)
-                    .map_err(|error| {
-                        warn!(
-                            id = self.state.shared.id,
-                            info_hash = ?self.state.shared.info_hash,
-                            ?peer,
-                            "failed to add peer: {error:#}"
-                        );
-                        error
+                    .peers
+                    .with_peer_mut(peer.addr, "holepunch_relay", |p| {
+                        if p.get_live().is_none() {
+                            p.holepunch_relay = Some(self.addr);
+                        }
+                    });
+            }
+        });
+    }
+
+    fn on_holepunch(&self, msg: UtHolepunch) -> anyhow::Result<()> {
+        match msg.msg_type {
+            UtHolepunchMsgType::Rendezvous => self.on_holepunch_rendezvous(msg.addr),
+            UtHolepunchMsgType::Connect => {
+                // Only connect to peers we asked a relay for recently, otherwise anyone could
+                // make us connect anywhere.
+                const HOLEPUNCH_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+                let requested = self
+                    .state
+                    .peers
+                    .with_peer_mut(msg.addr, "on_holepunch", |p| {
+                        p.holepunch_requested_at.take()
                     })
-                    .ok();
-            });
+                    .flatten()
+                    .is_some_and(|t| t.elapsed() < HOLEPUNCH_CONNECT_TIMEOUT);
+                if !requested {
+                    trace!(addr = ?msg.addr, "ignoring unsolicited holepunch connect");
+                    return Ok(());
+                }
+                trace!(addr = ?msg.addr, "received holepunch connect");
+                self.state.connect_now(msg.addr)?;
+                Ok(())
+            }
+            UtHolepunchMsgType::Error => {
+                debug!(addr = ?msg.addr, err_code = msg.err_code, "holepunch rendezvous failed");
+                Ok(())
+            }
+        }
+    }
+
+    // The peer asks us to introduce it to "target", which we're connected to. If we can, both
+    // of them are told to connect to each other.
+    fn on_holepunch_rendezvous(&self, target: SocketAddr) -> anyhow::Result<()> {
+        let peers = &self.state.peers;
+        let requester = peers
+            .with_peer(self.addr, |p| p.outgoing_address)
+            .flatten()
+            .unwrap_or(self.addr);
+        let target_handle = if peers.states.contains_key(&target) {
+            Some(target)
+        } else {
+            peers
+                .states
+                .iter()
+                .find(|e| e.value().outgoing_address == Some(target))
+                .map(|e| *e.key())
+        };
+
+        let res = match target_handle {
+            _ if target.ip().is_unspecified() || target.port() == 0 => {
+                Err(UT_HOLEPUNCH_ERR_NO_SUCH_PEER)
+            }
+            Some(handle) if handle == self.addr => Err(UT_HOLEPUNCH_ERR_NO_SUCH_PEER),
+            Some(handle) => peers
+                .with_live(handle, |live| {
+                    if !live.supports_holepunch {
+                        return Err(UT_HOLEPUNCH_ERR_NO_SUPPORT);
+                    }
+                    live.tx
+                        .send(WriterRequest::Message(Message::Extended(
+                            ExtendedMessage::UtHolepunch(UtHolepunch::connect(requester)),
+                        )))
+                        .map_err(|_| UT_HOLEPUNCH_ERR_NOT_CONNECTED)
+                })
+                .unwrap_or(Err(UT_HOLEPUNCH_ERR_NOT_CONNECTED)),
+            None => Err(UT_HOLEPUNCH_ERR_NOT_CONNECTED),
+        };
+
+        let reply = match res {
+            Ok(()) => {
+                debug!(?requester, ?target, "relaying holepunch");
+                UtHolepunch::connect(target)
+            }
+            Err(err_code) => {
+                debug!(?target, err_code, "can't relay holepunch");
+                UtHolepunch::error(target, err_code)
+            }
+        };
+        self.tx
+            .send(WriterRequest::Message(Message::Extended(
+                ExtendedMessage::UtHolepunch(reply),
+            )))
+            .context("error sending ut_holepunch: channel closed")?;
+        Ok(())
     }
 
     fn lock_read(
@@ -2014,3 +3148,89 @@ impl PeerHandler {
         TimedExistence::new(timeit(reason, || self._locked.write()), reason)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        net::SocketAddr,
+    };
+
+    use crate::file_info::{FileInfo, FilePriority};
+
+    use crate::type_aliases::BF;
+
+    use super::{add_piece_availability, compute_file_priorities, pex_deltas};
+
+    #[test]
+    fn test_compute_file_priorities() {
+        let files = ["d", "c", "b", "a"]
+            .into_iter()
+            .map(|name| FileInfo {
+                relative_filename: name.into(),
+                offset_in_torrent: 0,
+                piece_range: 0..0,
+                len: 0,
+                attrs: Default::default(),
+            })
+            .collect::<Vec<_>>();
+        let overrides = HashMap::from_iter([(0, FilePriority::Low), (2, FilePriority::High)]);
+
+        assert_eq!(
+            compute_file_priorities(&files, &Default::default(), false),
+            vec![3, 2, 1, 0]
+        );
+        assert_eq!(
+            compute_file_priorities(&files, &overrides, false),
+            vec![2, 3, 1, 0]
+        );
+        assert_eq!(
+            compute_file_priorities(&files, &overrides, true),
+            vec![2, 1, 3, 0]
+        );
+    }
+
+    #[test]
+    fn test_piece_availability() {
+        let bf = |bytes: &[u8]| BF::from_boxed_slice(bytes.to_vec().into_boxed_slice());
+        // 10 pieces, so bitfields are 2 bytes.
+        let mut availability = vec![0u16; 10];
+        add_piece_availability(&mut availability, &bf(&[0b1100_0000, 0b0000_0000]));
+        add_piece_availability(&mut availability, &bf(&[0b1000_0001, 0b0100_0000]));
+        // Spare bits after piece 9 don't count.
+        add_piece_availability(&mut availability, &bf(&[0b0000_0001, 0b0011_1111]));
+        // Too short to be valid.
+        add_piece_availability(&mut availability, &bf(&[0b1111_1111]));
+        assert_eq!(availability, [2, 1, 0, 0, 0, 0, 0, 2, 0, 1]);
+
+        let mut availability = vec![u16::MAX, 0];
+        add_piece_availability(&mut availability, &bf(&[0b1100_0000]));
+        assert_eq!(availability, [u16::MAX, 1]);
+    }
+
+    #[test]
+    fn test_pex_deltas() {
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        let set = |addrs: &[&str]| addrs.iter().map(|a| addr(a)).collect::<HashSet<_>>();
+        let sorted = |mut v: Vec<SocketAddr>| {
+            v.sort();
+            v
+        };
+        let live = set(&["1.1.1.1:1", "2.2.2.2:2", "192.168.0.1:3", "3.3.3.3:3"]);
+        let view = set(&["2.2.2.2:2", "4.4.4.4:4"]);
+
+        // Local addresses aren't sent to remote peers, and the peer isn't told about itself.
+        let (connected, dropped) = pex_deltas(addr("3.3.3.3:3"), &live, &view, 50);
+        assert_eq!(sorted(connected), vec![addr("1.1.1.1:1")]);
+        assert_eq!(dropped, vec![addr("4.4.4.4:4")]);
+
+        let (connected, _) = pex_deltas(addr("192.168.0.2:1"), &live, &view, 50);
+        assert_eq!(
+            sorted(connected),
+            vec![addr("1.1.1.1:1"), addr("3.3.3.3:3"), addr("192.168.0.1:3")]
+        );
+
+        let (connected, dropped) = pex_deltas(addr("192.168.0.2:1"), &live, &view, 1);
+        assert_eq!((connected.len(), dropped.len()), (1, 1));
+    }
+}
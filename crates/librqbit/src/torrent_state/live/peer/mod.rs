@@ -1,8 +1,9 @@
 pub mod stats;
 
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::atomic::Ordering;
+use std::time::Instant;
 
 use librqbit_core::hash_id::Id20;
 use librqbit_core::lengths::ChunkInfo;
@@ -16,7 +17,6 @@ use crate::type_aliases::BF;
 
 use super::PeerStates;
 
-pub(crate) type InflightRequest = ChunkInfo;
 pub(crate) type PeerRx = UnboundedReceiver<WriterRequest>;
 pub(crate) type PeerTx = UnboundedSender<WriterRequest>;
 
@@ -26,6 +26,12 @@ pub(crate) struct Peer {
     state: PeerState,
     pub stats: stats::atomic::PeerStats,
     pub outgoing_address: Option<SocketAddr>,
+    // The peer that told us about this one through PEX, if both of them support ut_holepunch. If
+    // we can't connect to this peer, we can ask the relay to introduce us.
+    pub holepunch_relay: Option<SocketAddr>,
+    // When we last asked a relay to introduce us to this peer. Holepunch "connect" messages are
+    // only honoured for peers we asked for.
+    pub holepunch_requested_at: Option<Instant>,
 }
 
 impl Peer {
@@ -45,6 +51,8 @@ impl Peer {
             state,
             stats: Default::default(),
             outgoing_address: None,
+            holepunch_relay: None,
+            holepunch_requested_at: None,
         }
     }
 
@@ -52,6 +60,8 @@ impl Peer {
         Self {
             addr,
             outgoing_address: Some(addr),
+            holepunch_relay: None,
+            holepunch_requested_at: None,
             stats: Default::default(),
             state: Default::default(),
         }
@@ -246,21 +256,26 @@ impl Peer {
 
 #[derive(Debug)]
 pub(crate) struct LivePeerState {
-    #[allow(dead_code)]
-    peer_id: Id20,
+    pub peer_id: Id20,
 
     pub peer_interested: bool,
 
+    // Whether the peer is choking us.
+    pub i_am_choked: bool,
+
     // This is used to track the pieces the peer has.
     pub bitfield: BF,
 
-    // When the peer sends us data this is used to track if we asked for it.
-    pub inflight_requests: HashSet<InflightRequest>,
+    // When the peer sends us data this is used to track if we asked for it, and since when.
+    pub inflight_requests: HashMap<ChunkInfo, Instant>,
 
     // The main channel to send requests to peer.
     pub tx: PeerTx,
 
     pub connection_kind: ConnectionKind,
+
+    // Whether the peer advertised ut_holepunch in its extended handshake.
+    pub supports_holepunch: bool,
 }
 
 impl LivePeerState {
@@ -273,10 +288,12 @@ impl LivePeerState {
         LivePeerState {
             peer_id,
             peer_interested: initial_interested,
+            i_am_choked: true,
             bitfield: BF::default(),
             inflight_requests: Default::default(),
             tx,
             connection_kind,
+            supports_holepunch: false,
         }
     }
 
@@ -23,6 +23,8 @@ pub(crate) struct PeerCountersAtomic {
     pub total_piece_download_ms: AtomicU64,
     pub times_stolen_from_me: AtomicU32,
     pub times_i_stole: AtomicU32,
+    // Pieces this peer contributed to that failed the hash check.
+    pub hash_failed_pieces: AtomicU32,
 }
 
 impl PeerCountersAtomic {
@@ -60,10 +62,15 @@ fn backoff() -> ExponentialBackoff {
         .build()
 }
 
+// Peers that had many pieces we need are first retried this soon, doubling each time.
+const FAST_RECONNECT_MIN_DELAY: Duration = Duration::from_secs(2);
+const MAX_FAST_RECONNECTS: u32 = 5;
+
 #[derive(Debug)]
 pub(crate) struct PeerStats {
     pub counters: Arc<PeerCountersAtomic>,
     pub backoff: ExponentialBackoff,
+    fast_reconnects: u32,
 }
 
 impl Default for PeerStats {
@@ -71,6 +78,7 @@ impl Default for PeerStats {
         Self {
             counters: Arc::new(Default::default()),
             backoff: backoff(),
+            fast_reconnects: 0,
         }
     }
 }
@@ -78,5 +86,46 @@ impl Default for PeerStats {
 impl PeerStats {
     pub fn reset_backoff(&mut self) {
         self.backoff = backoff();
+        self.fast_reconnects = 0;
+    }
+
+    /// How long to wait before reconnecting to the peer after it died. None if it shouldn't
+    /// be retried anymore.
+    ///
+    /// Valuable peers are retried a few times with short delays before falling back to the
+    /// regular backoff.
+    pub fn next_reconnect_delay(&mut self, valuable: bool) -> Option<Duration> {
+        if valuable && self.fast_reconnects < MAX_FAST_RECONNECTS {
+            let delay = FAST_RECONNECT_MIN_DELAY * 2u32.pow(self.fast_reconnects);
+            self.fast_reconnects += 1;
+            return Some(delay);
+        }
+        self.backoff.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::PeerStats;
+
+    #[test]
+    fn test_next_reconnect_delay() {
+        let mut stats = PeerStats::default();
+        let fast: Vec<_> = (0..5)
+            .map(|_| stats.next_reconnect_delay(true).unwrap())
+            .collect();
+        assert_eq!(fast, [2, 4, 8, 16, 32].map(Duration::from_secs).to_vec());
+
+        // Out of fast attempts, so it's the regular backoff (10s min with jitter).
+        assert!(stats.next_reconnect_delay(true).unwrap() >= Duration::from_secs(10));
+
+        stats.reset_backoff();
+        assert_eq!(
+            stats.next_reconnect_delay(true),
+            Some(Duration::from_secs(2))
+        );
+        assert!(stats.next_reconnect_delay(false).unwrap() >= Duration::from_secs(10));
     }
 }
@@ -1,5 +1,6 @@
-use std::{collections::HashMap, sync::atomic::Ordering};
+use std::{collections::HashMap, net::SocketAddr, sync::atomic::Ordering, time::Instant};
 
+use librqbit_core::hash_id::Id20;
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -21,6 +22,8 @@ pub struct PeerCounters {
     pub total_piece_download_ms: u64,
     pub times_stolen_from_me: u32,
     pub times_i_stole: u32,
+    #[serde(default)]
+    pub hash_failed_pieces: u32,
 }
 
 #[derive(Serialize)]
@@ -28,6 +31,8 @@ pub struct PeerStats {
     pub counters: PeerCounters,
     pub state: &'static str,
     pub conn_kind: Option<ConnectionKind>,
+    /// We let the peer download from us.
+    pub unchoked: bool,
 }
 
 impl From<&super::atomic::PeerCountersAtomic> for PeerCounters {
@@ -49,6 +54,7 @@ impl From<&super::atomic::PeerCountersAtomic> for PeerCounters {
             total_piece_download_ms: counters.total_piece_download_ms.load(Ordering::Relaxed),
             times_i_stole: counters.times_i_stole.load(Ordering::Relaxed),
             times_stolen_from_me: counters.times_stolen_from_me.load(Ordering::Relaxed),
+            hash_failed_pieces: counters.hash_failed_pieces.load(Ordering::Relaxed),
         }
     }
 }
@@ -63,6 +69,7 @@ impl From<&Peer> for PeerStats {
                 PeerState::Live(l) => Some(l.connection_kind),
                 _ => None,
             },
+            unchoked: false,
         }
     }
 }
@@ -72,6 +79,32 @@ pub struct PeerStatsSnapshot {
     pub peers: HashMap<String, PeerStats>,
 }
 
+/// A point-in-time view of a connected peer.
+#[derive(Clone, Debug, Serialize)]
+pub struct ConnectedPeerStats {
+    pub addr: SocketAddr,
+    pub peer_id: Id20,
+    pub bytes_down: u64,
+    pub bytes_up: u64,
+    pub have_pieces: u32,
+    /// The peer is interested in our pieces.
+    pub interested: bool,
+    /// The peer is choking us.
+    pub choked: bool,
+    /// We let the peer download from us.
+    pub unchoked: bool,
+}
+
+/// A block request sent to a peer that wasn't answered yet.
+#[derive(Clone, Copy, Debug)]
+pub struct InflightRequest {
+    pub piece: u32,
+    pub begin: u32,
+    pub len: u32,
+    pub peer: SocketAddr,
+    pub requested_at: Instant,
+}
+
 #[derive(Clone, Copy, Default, Deserialize)]
 pub enum PeerStatsFilterState {
     #[serde(rename = "all")]
@@ -1,14 +1,18 @@
-use std::{collections::HashSet, net::SocketAddr, sync::Arc};
+use std::{
+    collections::HashSet,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, atomic::Ordering},
+};
 
 use dashmap::DashMap;
-use librqbit_core::lengths::ValidPieceIndex;
+use librqbit_core::lengths::{ChunkInfo, ValidPieceIndex};
 use parking_lot::RwLock;
 use peer_binary_protocol::{Message, Request};
 
 use crate::{
     Error,
     peer_connection::WriterRequest,
-    torrent_state::utils::{TimedExistence, atomic_inc},
+    torrent_state::utils::{TimedExistence, atomic_dec, atomic_inc},
     type_aliases::{BF, PeerHandle},
 };
 
@@ -25,6 +29,9 @@ pub(crate) struct PeerStates {
     pub live_outgoing_peers: RwLock<HashSet<PeerHandle>>,
     pub stats: AggregatePeerStatsAtomic,
     pub states: DashMap<PeerHandle, Peer>,
+    // Peers that sent too many pieces that failed the hash check. Banned by IP, as they
+    // might come back from another port.
+    pub banned: RwLock<HashSet<IpAddr>>,
 }
 
 impl Drop for PeerStates {
@@ -113,6 +120,12 @@ impl PeerStates {
         })
     }
 
+    pub fn mark_i_am_choked(&self, handle: PeerHandle, is_choked: bool) -> Option<()> {
+        self.with_live_mut(handle, "mark_i_am_choked", |live| {
+            live.i_am_choked = is_choked;
+        })
+    }
+
     pub fn update_bitfield(&self, handle: PeerHandle, bitfield: BF) -> Option<()> {
         self.with_live_mut(handle, "update_bitfield", |live| {
             live.bitfield = bitfield;
@@ -142,6 +155,61 @@ impl PeerStates {
         Some(prev)
     }
 
+    /// Count a dead peer as waiting to be reconnected until the guard is dropped.
+    pub fn reconnecting_guard(&self) -> ReconnectingGuard<'_> {
+        atomic_inc(&self.stats.reconnecting);
+        atomic_inc(&self.session_stats.reconnecting);
+        ReconnectingGuard { peers: self }
+    }
+
+    pub fn is_banned(&self, addr: &SocketAddr) -> bool {
+        self.banned.read().contains(&addr.ip())
+    }
+
+    /// Blame the peer for a piece that failed the hash check. Once it's done that
+    /// `ban_threshold` times (0 means never), it's banned and disconnected.
+    ///
+    /// Returns true if the peer is banned.
+    pub(crate) fn on_hash_failed(&self, handle: PeerHandle, ban_threshold: u32) -> bool {
+        let Some(fails) = self.with_peer(handle, |p| {
+            p.stats
+                .counters
+                .hash_failed_pieces
+                .fetch_add(1, Ordering::Relaxed)
+                + 1
+        }) else {
+            return false;
+        };
+        if ban_threshold == 0 || fails < ban_threshold {
+            return false;
+        }
+        if self.banned.write().insert(handle.ip()) {
+            self.stats.inc_banned();
+            self.session_stats.inc_banned();
+        }
+        self.with_live(handle, |live| {
+            let _ = live.tx.send(WriterRequest::Disconnect(Err(anyhow::anyhow!(
+                "banned: sent {fails} pieces that failed the hash check"
+            ))));
+        });
+        true
+    }
+
+    /// Cancel a chunk request sent to the peer, if it's still in-flight.
+    pub(crate) fn cancel_request(&self, handle: PeerHandle, chunk: &ChunkInfo) {
+        self.with_live_mut(handle, "cancel_request", |live| {
+            if live.inflight_requests.remove(chunk).is_some() {
+                let _ = live
+                    .tx
+                    .send(WriterRequest::Message(Message::Cancel(Request {
+                        index: chunk.piece_index.get(),
+                        begin: chunk.offset,
+                        length: chunk.size,
+                    })));
+            }
+        });
+    }
+
     pub(crate) fn on_steal(
         &self,
         from_peer: SocketAddr,
@@ -159,7 +227,7 @@ impl PeerStates {
 
         self.with_live_mut(from_peer, "send_cancellations", |live| {
             let tx = &live.tx;
-            live.inflight_requests.retain(|req| {
+            live.inflight_requests.retain(|req, _| {
                 if req.piece_index == stolen_idx {
                     let _ = tx.send(WriterRequest::Message(Message::Cancel(Request {
                         index: stolen_idx.get(),
@@ -174,3 +242,14 @@ impl PeerStates {
         });
     }
 }
+
+pub(crate) struct ReconnectingGuard<'a> {
+    peers: &'a PeerStates,
+}
+
+impl Drop for ReconnectingGuard<'_> {
+    fn drop(&mut self) {
+        atomic_dec(&self.peers.stats.reconnecting);
+        atomic_dec(&self.peers.session_stats.reconnecting);
+    }
+}
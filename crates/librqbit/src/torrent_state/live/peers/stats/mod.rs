@@ -18,8 +18,12 @@ gen_stats!(AggregatePeerStatsAtomic AggregatePeerStats, [
     live_socks u32,
     seen u32,
     dead u32,
+    // Dead peers waiting for their backoff to expire to be connected again.
+    reconnecting u32,
     not_needed u32,
-    steals u32
+    steals u32,
+    endgame_duplicate_requests u32,
+    banned u32
 ], []);
 
 impl AggregatePeerStatsAtomic {
@@ -63,4 +67,12 @@ impl AggregatePeerStatsAtomic {
     pub fn inc_steals(&self) {
         atomic_inc(&self.steals);
     }
+
+    pub fn inc_endgame_duplicate_requests(&self) {
+        atomic_inc(&self.endgame_duplicate_requests);
+    }
+
+    pub fn inc_banned(&self) {
+        atomic_inc(&self.banned);
+    }
 }
@@ -0,0 +1,160 @@
+// An LRU cache of whole verified pieces for serving upload requests.
+//
+// Peers usually request all chunks of a piece, and popular pieces get requested by many peers,
+// so keeping recently read pieces in memory saves a lot of disk reads when seeding.
+// Only pieces we have are ever uploaded, and their data doesn't change while the torrent is live,
+// so the cache never needs invalidation.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use librqbit_core::lengths::{ChunkInfo, ValidPieceIndex};
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// Piece read cache counters.
+#[derive(Debug, Default, Clone, Copy, Serialize, PartialEq, Eq)]
+pub struct ReadCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub cached_bytes: u64,
+}
+
+struct Cached {
+    lru: LruCache<ValidPieceIndex, Arc<[u8]>>,
+    bytes: usize,
+}
+
+pub(crate) struct PieceReadCache {
+    max_bytes: usize,
+    cached: Mutex<Cached>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl PieceReadCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            cached: Mutex::new(Cached {
+                lru: LruCache::unbounded(),
+                bytes: 0,
+            }),
+            hits: Default::default(),
+            misses: Default::default(),
+        }
+    }
+
+    // Copy the chunk into buf. On a miss, read_piece is called to read the whole piece from disk.
+    pub fn read_chunk(
+        &self,
+        chunk: &ChunkInfo,
+        piece_length: u32,
+        buf: &mut [u8],
+        read_piece: impl FnOnce(&mut [u8]) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let range = chunk.offset as usize..(chunk.offset + chunk.size) as usize;
+        let buf = buf
+            .get_mut(..chunk.size as usize)
+            .ok_or_else(|| anyhow::anyhow!("not enough capacity in the provided buffer"))?;
+
+        let cached = self.cached.lock().lru.get(&chunk.piece_index).cloned();
+        if let Some(piece) = cached {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            buf.copy_from_slice(&piece[range]);
+            return Ok(());
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let mut piece = vec![0u8; piece_length as usize];
+        read_piece(&mut piece)?;
+        buf.copy_from_slice(&piece[range]);
+        self.insert(chunk.piece_index, piece.into());
+        Ok(())
+    }
+
+    fn insert(&self, index: ValidPieceIndex, piece: Arc<[u8]>) {
+        if piece.len() > self.max_bytes {
+            return;
+        }
+        let mut g = self.cached.lock();
+        g.bytes += piece.len();
+        if let Some(prev) = g.lru.put(index, piece) {
+            // Two peers missed the same piece concurrently.
+            g.bytes -= prev.len();
+        }
+        while g.bytes > self.max_bytes {
+            match g.lru.pop_lru() {
+                Some((_, evicted)) => g.bytes -= evicted.len(),
+                None => break,
+            }
+        }
+    }
+
+    pub fn stats(&self) -> ReadCacheStats {
+        ReadCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            cached_bytes: self.cached.lock().bytes as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use librqbit_core::lengths::Lengths;
+
+    use super::{PieceReadCache, ReadCacheStats};
+
+    fn marker(piece: u32, chunk: usize) -> u8 {
+        u8::try_from(piece as usize * 7 + chunk).unwrap()
+    }
+
+    #[test]
+    fn test_piece_read_cache_lru() {
+        // 4 pieces of 32KiB, 2 chunks each.
+        let lengths = Lengths::new(4 * 32768, 32768).unwrap();
+        let piece = |i| lengths.validate_piece_index(i).unwrap();
+        let chunk = |i, c| lengths.iter_chunk_infos(piece(i)).nth(c).unwrap();
+
+        // Fits 2 pieces.
+        let cache = PieceReadCache::new(2 * 32768 + 1);
+        let mut disk_reads = Vec::new();
+        let mut read = |i: u32, c: usize| {
+            let mut buf = vec![0u8; 16384];
+            cache
+                .read_chunk(&chunk(i, c), 32768, &mut buf, |b| {
+                    disk_reads.push(i);
+                    // Mark every byte with its piece and offset within the piece.
+                    for (off, v) in b.iter_mut().enumerate() {
+                        *v = marker(i, off / 16384);
+                    }
+                    Ok(())
+                })
+                .unwrap();
+            assert!(buf.iter().all(|v| *v == marker(i, c)));
+        };
+
+        read(0, 0);
+        read(0, 1);
+        read(1, 0);
+        read(0, 0);
+        // Evicts 1 as 0 was used more recently.
+        read(2, 1);
+        read(0, 1);
+        read(1, 1);
+
+        assert_eq!(disk_reads, vec![0, 1, 2, 1]);
+        assert_eq!(
+            cache.stats(),
+            ReadCacheStats {
+                hits: 3,
+                misses: 4,
+                cached_bytes: 2 * 32768,
+            }
+        );
+    }
+}
@@ -7,5 +7,10 @@ pub struct AtomicStats {
     pub downloaded_and_checked_pieces: AtomicU64,
     pub uploaded_bytes: AtomicU64,
     pub fetched_bytes: AtomicU64,
+    // The part of fetched_bytes that came from web seeds.
+    pub web_seed_fetched_bytes: AtomicU64,
     pub total_piece_download_ms: AtomicU64,
+    // Received chunks waiting to be written to disk, and their size.
+    pub pending_writes: AtomicU64,
+    pub pending_write_bytes: AtomicU64,
 }
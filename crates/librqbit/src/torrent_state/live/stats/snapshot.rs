@@ -13,6 +13,9 @@ pub struct StatsSnapshot {
 
     pub downloaded_and_checked_pieces: u64,
     pub total_piece_download_ms: u64,
+    /// Chunks received from peers that are not written to disk yet, and their size in bytes.
+    pub pending_writes: u64,
+    pub pending_write_bytes: u64,
     pub peer_stats: AggregatePeerStats,
 }
 
@@ -0,0 +1,119 @@
+// Super-seeding (BEP 16), a mode for the initial seed of a torrent.
+//
+// Instead of the bitfield, each peer is told about a single piece it doesn't have with a "have"
+// message. The peer only learns about another piece once its offered piece was announced by a
+// different peer, i.e. once it has spread. This way the initial seed uploads every piece roughly
+// once, and the swarm does the rest.
+
+use std::collections::HashMap;
+
+use crate::type_aliases::{BS, PeerHandle};
+
+pub(crate) struct SuperSeeder {
+    // The piece each peer was last told about.
+    offered: HashMap<PeerHandle, u32>,
+    // How many times each piece was offered, the least offered pieces are offered first.
+    offer_counts: Vec<u32>,
+}
+
+impl SuperSeeder {
+    pub fn new(total_pieces: u32) -> Self {
+        Self {
+            offered: Default::default(),
+            offer_counts: vec![0; total_pieces as usize],
+        }
+    }
+
+    pub fn offered(&self, peer: PeerHandle) -> Option<u32> {
+        self.offered.get(&peer).copied()
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = PeerHandle> + '_ {
+        self.offered.keys().copied()
+    }
+
+    // Pick the next piece to offer to the peer among the ones we have and the peer doesn't.
+    // Returns None if the current offer is still valid, or if there's nothing to offer.
+    pub fn next_offer(&mut self, peer: PeerHandle, we_have: &BS, peer_has: &BS) -> Option<u32> {
+        let peer_has_piece = |idx: usize| peer_has.get(idx).is_some_and(|b| *b);
+        if let Some(current) = self.offered(peer)
+            && !peer_has_piece(current as usize)
+        {
+            return None;
+        }
+        let (idx, _) = we_have
+            .iter_ones()
+            .filter(|idx| !peer_has_piece(*idx))
+            .filter_map(|idx| Some((idx, *self.offer_counts.get(idx)?)))
+            .min_by_key(|(_, count)| *count)?;
+        self.offer_counts[idx] += 1;
+        let idx = idx as u32;
+        self.offered.insert(peer, idx);
+        Some(idx)
+    }
+
+    // A peer announced having a piece. Returns the other peers that were offered this piece, as
+    // it has spread now, they need to be offered the next one.
+    pub fn on_have(&mut self, from: PeerHandle, piece: u32) -> Vec<PeerHandle> {
+        let peers: Vec<PeerHandle> = self
+            .offered
+            .iter()
+            .filter(|(peer, offered)| **peer != from && **offered == piece)
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in peers.iter() {
+            self.offered.remove(peer);
+        }
+        peers
+    }
+
+    pub fn on_peer_dropped(&mut self, peer: PeerHandle) {
+        self.offered.remove(&peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use bitvec::{bitvec, order::Msb0};
+
+    use super::SuperSeeder;
+
+    fn peer(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_super_seeder() {
+        let mut s = SuperSeeder::new(4);
+        let all = bitvec![u8, Msb0; 1; 4];
+        let empty = bitvec![u8, Msb0; 0; 4];
+
+        // Every peer gets a different piece.
+        assert_eq!(s.next_offer(peer(1), &all, &empty), Some(0));
+        assert_eq!(s.next_offer(peer(2), &all, &empty), Some(1));
+        // The offer stays until it spreads.
+        assert_eq!(s.next_offer(peer(1), &all, &empty), None);
+        assert_eq!(s.offered(peer(1)), Some(0));
+
+        // Peer 1 downloaded its piece, but nobody else has it yet.
+        assert!(s.on_have(peer(1), 0).is_empty());
+
+        // Peer 2 got piece 0 from peer 1, so peer 1 gets a new piece.
+        assert_eq!(s.on_have(peer(2), 0), vec![peer(1)]);
+        let mut peer_1_has = empty.clone();
+        peer_1_has.set(0, true);
+        assert_eq!(s.next_offer(peer(1), &all, &peer_1_has), Some(2));
+
+        // Pieces the peer already has are never offered.
+        let mut peer_3_has = all.clone();
+        peer_3_has.set(3, false);
+        assert_eq!(s.next_offer(peer(3), &all, &peer_3_has), Some(3));
+        assert_eq!(s.next_offer(peer(4), &all, &all), None);
+
+        s.on_peer_dropped(peer(2));
+        assert_eq!(s.offered(peer(2)), None);
+        assert!(s.on_have(peer(5), 1).is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+// Downloading pieces over HTTP from web seeds (BEP-19).
+//
+// A web seed is a plain HTTP server hosting the torrent's files. It's only used as a fallback:
+// while peers are fast enough, the web seed tasks sit idle. Otherwise each task reserves pieces
+// from the PieceTracker under a made-up peer handle, fetches them with HTTP Range requests
+// (one per file the piece spans), and writes and hash-checks them the same way as chunks
+// received from peers.
+
+use std::{
+    net::{Ipv6Addr, SocketAddr},
+    num::NonZeroU32,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use librqbit_core::lengths::ValidPieceIndex;
+use peer_binary_protocol::Piece;
+use reqwest::{StatusCode, header::RANGE};
+use tracing::{debug, trace, warn};
+use url::Url;
+
+use crate::{
+    Error,
+    piece_tracker::{AcquireRequest, AcquireResult},
+    type_aliases::PeerHandle,
+};
+
+use super::{AtomicPeerCounters, TorrentStateLive, TorrentStateLocked};
+
+// Web seeds are used while peers download slower than this.
+const MIN_PEER_DOWNLOAD_SPEED_BPS: u64 = 512 * 1024;
+// How often to re-check if the web seed is needed when idle.
+const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// Web seeds need a PeerHandle to own pieces in the PieceTracker. [::]:N can't be a real
+// peer, so it won't clash with one.
+pub(crate) fn web_seed_handle(idx: usize) -> PeerHandle {
+    let port = u16::try_from(idx + 1).unwrap_or(u16::MAX);
+    SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port)
+}
+
+// Build the URL of each file in the torrent, as laid out in BEP-19.
+//
+// For a single-file torrent, the URL points to the file itself, unless it ends with a slash,
+// in which case the torrent name is appended. For a multi-file torrent, it's the parent
+// directory of the torrent's folder.
+fn file_urls(
+    base: &Url,
+    name: Option<&str>,
+    single_file: bool,
+    files: impl Iterator<Item = Vec<String>>,
+) -> anyhow::Result<Vec<Url>> {
+    if single_file {
+        let mut url = base.clone();
+        if url.path().ends_with('/') {
+            let name = name.context("torrent has no name")?;
+            url.path_segments_mut()
+                .ok()
+                .context("invalid web seed url")?
+                .pop_if_empty()
+                .push(name);
+        }
+        return Ok(vec![url]);
+    }
+
+    let name = name.context("torrent has no name")?;
+    files
+        .map(|components| {
+            let mut url = base.clone();
+            url.path_segments_mut()
+                .ok()
+                .context("invalid web seed url")?
+                .pop_if_empty()
+                .push(name)
+                .extend(components);
+            Ok(url)
+        })
+        .collect()
+}
+
+impl TorrentStateLive {
+    fn web_seed_acquire_piece(&self, handle: PeerHandle) -> crate::Result<Option<ValidPieceIndex>> {
+        let mut g = self.lock_write("web_seed_acquire_piece");
+        let TorrentStateLocked {
+            pieces,
+            file_priorities,
+            sequential,
+            ..
+        } = &mut **g;
+        let pieces = pieces.as_mut().ok_or(Error::ChunkTrackerEmpty)?;
+        // No steals or endgame: the web seed only takes pieces nobody is downloading.
+        let result = pieces.acquire_piece(AcquireRequest {
+            peer: handle,
+            peer_avg_time: None,
+            priority_pieces: self.streams.iter_next_pieces(&self.lengths),
+            file_priorities,
+            file_infos: &self.metadata.file_infos,
+            sequential: *sequential,
+            peer_has_piece: |_| true,
+            can_steal: |_| false,
+            endgame_threshold: 0,
+        });
+        match result {
+            AcquireResult::Reserved(piece) => Ok(Some(piece)),
+            _ => Ok(None),
+        }
+    }
+
+    fn web_seed_release_pieces(&self, handle: PeerHandle) -> crate::Result<()> {
+        self.lock_write("web_seed_release_pieces")
+            .get_pieces_mut()?
+            .release_pieces_owned_by(handle);
+        self.new_pieces_notify.notify_waiters();
+        Ok(())
+    }
+
+    async fn web_seed_fetch_piece(
+        &self,
+        client: &reqwest::Client,
+        urls: &[Url],
+        piece: ValidPieceIndex,
+    ) -> anyhow::Result<Vec<u8>> {
+        let piece_start = self.lengths.piece_offset(piece);
+        let piece_end = piece_start + self.lengths.piece_length(piece) as u64;
+        let mut buf = Vec::with_capacity(self.lengths.piece_length(piece) as usize);
+
+        for (fi, url) in self.metadata.file_infos.iter().zip(urls) {
+            let file_end = fi.offset_in_torrent + fi.len;
+            if file_end <= piece_start || fi.offset_in_torrent >= piece_end {
+                continue;
+            }
+            let start = piece_start.max(fi.offset_in_torrent) - fi.offset_in_torrent;
+            let end = piece_end.min(file_end) - fi.offset_in_torrent;
+            let len = usize::try_from(end - start)?;
+
+            // Padding files aren't hosted by web seeds.
+            if fi.attrs.padding {
+                buf.resize(buf.len() + len, 0);
+                continue;
+            }
+
+            trace!(%url, start, end, "fetching from web seed");
+            let response = client
+                .get(url.clone())
+                .header(RANGE, format!("bytes={}-{}", start, end - 1))
+                .timeout(REQUEST_TIMEOUT)
+                .send()
+                .await
+                .with_context(|| format!("error requesting {url}"))?;
+            match response.status() {
+                StatusCode::PARTIAL_CONTENT => {}
+                // The server ignored the range, which is fine only if we asked for the whole file.
+                StatusCode::OK if start == 0 && end == fi.len => {}
+                s => bail!("{url} responded with {s}"),
+            }
+            let body = response
+                .bytes()
+                .await
+                .with_context(|| format!("error reading response from {url}"))?;
+            if body.len() != len {
+                bail!("{url} sent {} bytes, expected {len}", body.len());
+            }
+            buf.extend_from_slice(&body);
+        }
+        Ok(buf)
+    }
+
+    // Web seeds share the torrent's and the session's download rate limits with peers.
+    async fn web_seed_wait_for_ratelimits(&self, piece: ValidPieceIndex) -> crate::Result<()> {
+        for chunk in self.lengths.iter_chunk_infos(piece) {
+            let Some(len) = NonZeroU32::new(chunk.size) else {
+                continue;
+            };
+            self.shared
+                .options
+                .ratelimits
+                .prepare_for_download(len)
+                .await?;
+            if let Some(session) = self.shared.session.upgrade() {
+                session.ratelimits.prepare_for_download(len).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn web_seed_write_piece(
+        &self,
+        handle: PeerHandle,
+        counters: &AtomicPeerCounters,
+        piece: ValidPieceIndex,
+        data: &[u8],
+    ) -> anyhow::Result<bool> {
+        for chunk in self.lengths.iter_chunk_infos(piece) {
+            let block = &data[chunk.offset as usize..][..chunk.size as usize];
+            let msg = Piece::from_data(piece.get(), chunk.offset, block);
+            if !self.write_chunk_and_check(handle, counters, &msg, &chunk)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn web_seed_needed(&self) -> bool {
+        !self.is_finished() && self.peer_down_speed_estimator.bps() < MIN_PEER_DOWNLOAD_SPEED_BPS
+    }
+
+    pub(crate) async fn task_web_seed(
+        self: Arc<Self>,
+        idx: usize,
+        base: Url,
+        client: reqwest::Client,
+    ) -> crate::Result<()> {
+        let handle = web_seed_handle(idx);
+        let info = &self.metadata.info;
+        let urls = match file_urls(
+            &base,
+            info.name().as_deref(),
+            info.info().files.is_none(),
+            info.iter_file_details().map(|fd| fd.filename.to_vec()),
+        ) {
+            Ok(urls) => urls,
+            Err(e) => {
+                warn!(%base, "can't use web seed: {e:#}");
+                return Ok(());
+            }
+        };
+
+        let counters = AtomicPeerCounters::default();
+        let ban_threshold = self.shared.options.hash_fail_ban_threshold;
+        let mut hash_fails = 0;
+        let mut backoff = Duration::ZERO;
+
+        loop {
+            if !backoff.is_zero() {
+                tokio::time::sleep(backoff).await;
+            }
+            if !self.web_seed_needed() {
+                tokio::time::sleep(IDLE_INTERVAL).await;
+                continue;
+            }
+            let Some(piece) = self.web_seed_acquire_piece(handle)? else {
+                tokio::time::sleep(IDLE_INTERVAL).await;
+                continue;
+            };
+
+            if let Err(e) = self.web_seed_wait_for_ratelimits(piece).await {
+                self.web_seed_release_pieces(handle)?;
+                return Err(e);
+            }
+            let data = match self.web_seed_fetch_piece(&client, &urls, piece).await {
+                Ok(data) => data,
+                Err(e) => {
+                    backoff = (backoff * 2).clamp(IDLE_INTERVAL, MAX_BACKOFF);
+                    debug!(%base, ?backoff, "error fetching piece={piece} from web seed: {e:#}");
+                    self.web_seed_release_pieces(handle)?;
+                    continue;
+                }
+            };
+            backoff = Duration::ZERO;
+
+            let len = data.len() as u64;
+            counters.fetched_bytes.fetch_add(len, Ordering::Relaxed);
+            self.stats.fetched_bytes.fetch_add(len, Ordering::Relaxed);
+            self.stats
+                .web_seed_fetched_bytes
+                .fetch_add(len, Ordering::Relaxed);
+            self.shared
+                .total_downloaded_bytes
+                .fetch_add(len, Ordering::Relaxed);
+            self.session_stats
+                .counters
+                .fetched_bytes
+                .fetch_add(len, Ordering::Relaxed);
+
+            let hash_ok = self
+                .shared
+                .spawner
+                .block_in_place_with_semaphore(|| {
+                    self.web_seed_write_piece(handle, &counters, piece, &data)
+                })
+                .await;
+            match hash_ok {
+                Ok(true) => {}
+                Ok(false) => {
+                    hash_fails += 1;
+                    if ban_threshold > 0 && hash_fails >= ban_threshold {
+                        warn!(%base, "web seed sent {hash_fails} pieces that failed the hash check, not using it anymore");
+                        self.web_seed_release_pieces(handle)?;
+                        return Ok(());
+                    }
+                }
+                Err(e) => {
+                    debug!(%base, "error writing piece={piece} from web seed: {e:#}");
+                    self.web_seed_release_pieces(handle)?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::file_urls;
+
+    fn urls(base: &str, single_file: bool, files: &[&[&str]]) -> Vec<String> {
+        file_urls(
+            &Url::parse(base).unwrap(),
+            Some("my torrent"),
+            single_file,
+            files
+                .iter()
+                .map(|f| f.iter().map(|c| c.to_string()).collect()),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|u| u.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn test_file_urls_single_file() {
+        assert_eq!(
+            urls("http://example.com/files/f.iso", true, &[&["f.iso"]]),
+            ["http://example.com/files/f.iso"]
+        );
+        assert_eq!(
+            urls("http://example.com/files/", true, &[&["f.iso"]]),
+            ["http://example.com/files/my%20torrent"]
+        );
+    }
+
+    #[test]
+    fn test_file_urls_multi_file() {
+        let expected = [
+            "http://example.com/files/my%20torrent/a.txt",
+            "http://example.com/files/my%20torrent/dir/b%3F.txt",
+        ];
+        for base in ["http://example.com/files", "http://example.com/files/"] {
+            assert_eq!(
+                urls(base, false, &[&["a.txt"], &["dir", "b?.txt"]]),
+                expected
+            );
+        }
+    }
+}
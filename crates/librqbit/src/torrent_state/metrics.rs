@@ -0,0 +1,277 @@
+use core::fmt::Write;
+use std::sync::atomic::Ordering;
+
+use librqbit_core::Id20;
+use serde::Serialize;
+
+use super::{ManagedTorrent, stats::TorrentStatsState};
+
+/// A point-in-time view of a torrent for monitoring, see [`write_prometheus`].
+///
+/// Byte counters only grow while the torrent is in the session, including across
+/// pause/resume. The rest are gauges of the current state.
+#[derive(Debug, Clone, Serialize)]
+pub struct TorrentMetrics {
+    pub info_hash: Id20,
+    pub state: TorrentStatsState,
+    pub uploaded_bytes: u64,
+    pub downloaded_bytes: u64,
+    pub progress_bytes: u64,
+    pub total_bytes: u64,
+    pub have_pieces: u32,
+    pub total_pieces: u32,
+    /// Zero unless the torrent is live.
+    pub live_peers: u32,
+    pub download_speed_bps: f64,
+    pub upload_speed_bps: f64,
+}
+
+impl ManagedTorrent {
+    pub fn metrics_snapshot(&self) -> TorrentMetrics {
+        let stats = self.stats();
+        let have_pieces = self
+            .with_chunk_tracker(|ct| ct.get_have_pieces().as_slice().count_ones())
+            .ok()
+            .and_then(|c| u32::try_from(c).ok())
+            .unwrap_or_default();
+        let total_pieces = self
+            .metadata
+            .load()
+            .as_ref()
+            .map(|m| m.lengths().total_pieces())
+            .unwrap_or_default();
+        let live = stats.live.as_ref();
+        TorrentMetrics {
+            info_hash: self.info_hash(),
+            state: stats.state,
+            // Read the counters directly so they don't depend on the state.
+            uploaded_bytes: self.shared.total_uploaded_bytes.load(Ordering::Relaxed),
+            downloaded_bytes: self.shared.total_downloaded_bytes.load(Ordering::Relaxed),
+            progress_bytes: stats.progress_bytes,
+            total_bytes: stats.total_bytes,
+            have_pieces,
+            total_pieces,
+            live_peers: live.map(|l| l.snapshot.peer_stats.live).unwrap_or_default(),
+            download_speed_bps: live.map(|l| l.download_speed_bps).unwrap_or_default(),
+            upload_speed_bps: live.map(|l| l.upload_speed_bps).unwrap_or_default(),
+        }
+    }
+}
+
+const STATES: [TorrentStatsState; 6] = [
+    TorrentStatsState::FetchingMetadata,
+    TorrentStatsState::Queued,
+    TorrentStatsState::Initializing,
+    TorrentStatsState::Live,
+    TorrentStatsState::Paused,
+    TorrentStatsState::Error,
+];
+
+fn state_label(state: TorrentStatsState) -> &'static str {
+    match state {
+        TorrentStatsState::FetchingMetadata => "fetching_metadata",
+        TorrentStatsState::Queued => "queued",
+        TorrentStatsState::Initializing => "initializing",
+        TorrentStatsState::Live => "live",
+        TorrentStatsState::Paused => "paused",
+        TorrentStatsState::Error => "error",
+    }
+}
+
+/// Render per-torrent samples labelled by info hash, and session totals, in the
+/// Prometheus text exposition format.
+pub fn write_prometheus(torrents: &[TorrentMetrics], mut out: &mut String) {
+    out.push('\n');
+
+    macro_rules! m {
+        ($type:ident, $name:ident, $help:literal, $field:ident) => {{
+            writeln!(&mut out, concat!("# HELP ", stringify!($name), " ", $help)).unwrap();
+            writeln!(
+                &mut out,
+                concat!("# TYPE ", stringify!($name), " ", stringify!($type))
+            )
+            .unwrap();
+            for t in torrents {
+                writeln!(
+                    &mut out,
+                    concat!(stringify!($name), "{{info_hash=\"{}\"}} {}"),
+                    t.info_hash.as_string(),
+                    t.$field
+                )
+                .unwrap();
+            }
+        }};
+    }
+
+    m!(
+        counter,
+        rqbit_torrent_uploaded_bytes,
+        "Bytes uploaded to peers.",
+        uploaded_bytes
+    );
+    m!(
+        counter,
+        rqbit_torrent_downloaded_bytes,
+        "Bytes downloaded from peers.",
+        downloaded_bytes
+    );
+    m!(
+        gauge,
+        rqbit_torrent_progress_bytes,
+        "Bytes of the selected files we have.",
+        progress_bytes
+    );
+    m!(
+        gauge,
+        rqbit_torrent_total_bytes,
+        "Bytes of the selected files.",
+        total_bytes
+    );
+    m!(
+        gauge,
+        rqbit_torrent_have_pieces,
+        "Pieces we have.",
+        have_pieces
+    );
+    m!(
+        gauge,
+        rqbit_torrent_total_pieces,
+        "Pieces in the torrent.",
+        total_pieces
+    );
+    m!(
+        gauge,
+        rqbit_torrent_live_peers,
+        "Connected peers.",
+        live_peers
+    );
+    m!(
+        gauge,
+        rqbit_torrent_download_speed_bps,
+        "Download speed averaged over the last 10 seconds.",
+        download_speed_bps
+    );
+    m!(
+        gauge,
+        rqbit_torrent_upload_speed_bps,
+        "Upload speed averaged over the last 10 seconds.",
+        upload_speed_bps
+    );
+
+    writeln!(
+        &mut out,
+        "# HELP rqbit_torrent_state 1 for the current state of the torrent."
+    )
+    .unwrap();
+    writeln!(&mut out, "# TYPE rqbit_torrent_state gauge").unwrap();
+    for t in torrents {
+        writeln!(
+            &mut out,
+            "rqbit_torrent_state{{info_hash=\"{}\",state=\"{}\"}} 1",
+            t.info_hash.as_string(),
+            state_label(t.state)
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        &mut out,
+        "# HELP rqbit_torrents Torrents in the session by state."
+    )
+    .unwrap();
+    writeln!(&mut out, "# TYPE rqbit_torrents gauge").unwrap();
+    for state in STATES {
+        let count = torrents
+            .iter()
+            .filter(|t| std::mem::discriminant(&t.state) == std::mem::discriminant(&state))
+            .count();
+        writeln!(
+            &mut out,
+            "rqbit_torrents{{state=\"{}\"}} {count}",
+            state_label(state)
+        )
+        .unwrap();
+    }
+
+    macro_rules! total {
+        ($name:ident, $help:literal, $value:expr) => {{
+            writeln!(&mut out, concat!("# HELP ", stringify!($name), " ", $help)).unwrap();
+            writeln!(&mut out, concat!("# TYPE ", stringify!($name), " gauge")).unwrap();
+            writeln!(&mut out, concat!(stringify!($name), " {}"), $value).unwrap();
+        }};
+    }
+
+    // Totals are gauges as they go down when torrents are removed. Session-wide
+    // counters are in rqbit_fetched_bytes and rqbit_uploaded_bytes.
+    total!(
+        rqbit_torrents_progress_bytes,
+        "Bytes we have over all torrents.",
+        torrents.iter().map(|t| t.progress_bytes).sum::<u64>()
+    );
+    total!(
+        rqbit_torrents_total_bytes,
+        "Selected bytes over all torrents.",
+        torrents.iter().map(|t| t.total_bytes).sum::<u64>()
+    );
+    total!(
+        rqbit_torrents_live_peers,
+        "Connected peers over all torrents.",
+        torrents.iter().map(|t| t.live_peers).sum::<u32>()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use librqbit_core::Id20;
+
+    use super::{TorrentMetrics, write_prometheus};
+    use crate::torrent_state::stats::TorrentStatsState;
+
+    fn metrics(b: u8, state: TorrentStatsState, live_peers: u32) -> TorrentMetrics {
+        TorrentMetrics {
+            info_hash: Id20::new([b; 20]),
+            state,
+            uploaded_bytes: 10,
+            downloaded_bytes: 20,
+            progress_bytes: 30,
+            total_bytes: 40,
+            have_pieces: 3,
+            total_pieces: 4,
+            live_peers,
+            download_speed_bps: 0.,
+            upload_speed_bps: 0.,
+        }
+    }
+
+    #[test]
+    fn test_write_prometheus() {
+        let mut out = String::new();
+        write_prometheus(
+            &[
+                metrics(1, TorrentStatsState::Live, 5),
+                metrics(2, TorrentStatsState::Paused, 0),
+            ],
+            &mut out,
+        );
+        let h1 = Id20::new([1; 20]).as_string();
+        let h2 = Id20::new([2; 20]).as_string();
+
+        for line in [
+            "# HELP rqbit_torrent_uploaded_bytes Bytes uploaded to peers.".to_owned(),
+            "# TYPE rqbit_torrent_uploaded_bytes counter".to_owned(),
+            format!("rqbit_torrent_uploaded_bytes{{info_hash=\"{h1}\"}} 10"),
+            format!("rqbit_torrent_downloaded_bytes{{info_hash=\"{h2}\"}} 20"),
+            "# TYPE rqbit_torrent_live_peers gauge".to_owned(),
+            format!("rqbit_torrent_live_peers{{info_hash=\"{h1}\"}} 5"),
+            format!("rqbit_torrent_have_pieces{{info_hash=\"{h2}\"}} 3"),
+            format!("rqbit_torrent_state{{info_hash=\"{h1}\",state=\"live\"}} 1"),
+            format!("rqbit_torrent_state{{info_hash=\"{h2}\",state=\"paused\"}} 1"),
+            "rqbit_torrents{state=\"live\"} 1".to_owned(),
+            "rqbit_torrents{state=\"error\"} 0".to_owned(),
+            "rqbit_torrents_total_bytes 80".to_owned(),
+            "rqbit_torrents_live_peers 5".to_owned(),
+        ] {
+            assert!(out.lines().any(|l| l == line), "missing {line:?} in\n{out}");
+        }
+    }
+}
@@ -1,59 +1,94 @@
+pub mod events;
 pub mod initializing;
 pub mod live;
+pub mod metrics;
+mod move_storage;
 pub mod paused;
 pub mod stats;
 mod streaming;
 pub mod utils;
 
+use std::any::TypeId;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::net::SocketAddr;
+use std::ops::Range;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Weak;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 
 use anyhow::Context;
 use anyhow::bail;
 use arc_swap::ArcSwapOption;
+use buffers::ByteBuf;
 use buffers::ByteBufOwned;
 use bytes::Bytes;
 use futures::FutureExt;
 use futures::future::BoxFuture;
 use librqbit_core::hash_id::Id20;
+use librqbit_core::hash_id::Id32;
 use librqbit_core::lengths::Lengths;
+use librqbit_core::magnet::Magnet;
 
 use librqbit_core::spawn_utils::spawn_with_cancel;
+use librqbit_core::torrent_metainfo::TorrentMetaV1Borrowed;
 use librqbit_core::torrent_metainfo::ValidatedTorrentMetaV1Info;
+use librqbit_core::torrent_metainfo::torrent_from_bytes;
 pub use live::*;
 use parking_lot::RwLock;
 
 use tokio::sync::Notify;
+use tokio::sync::broadcast;
 use tokio::time::timeout;
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
 use tracing::debug;
 use tracing::debug_span;
+use tracing::info;
 use tracing::trace;
 use tracing::warn;
+use tracker_comms::DEFAULT_ANNOUNCE_TIMEOUT;
+use tracker_comms::TrackerCommsOptions;
 
 use crate::Session;
-use crate::chunk_tracker::ChunkTracker;
+use crate::TorrentError;
+use crate::api::TorrentIdOrHash;
+use crate::bitv_factory::FilesSnapshot;
+use crate::chunk_tracker::{ChunkTracker, WantedRanges, merge_ranges};
 use crate::file_info::FileInfo;
-use crate::limits::LimitsConfig;
+use crate::file_info::FilePriority;
+use crate::file_info::apply_renamed_files;
+use crate::file_ops::FileOps;
+use crate::limits::{Limits, LimitsConfig};
+use crate::peer_connection::PeerConnectionOptions;
+use crate::peer_filter::PeerFilter;
+use crate::session::DeletedFiles;
 use crate::session::TorrentId;
 use crate::spawn_utils::BlockingSpawner;
-use crate::storage::BoxStorageFactory;
+use crate::storage::filesystem::{FilesystemStorageFactory, MmapFilesystemStorageFactory};
+use crate::storage::{Allocation, BoxStorageFactory, TorrentStorage};
 use crate::stream_connect::StreamConnector;
+use crate::torrent_state::events::{TorrentEvent, TorrentEvents};
+use crate::torrent_state::live::read_cache::ReadCacheStats;
+use crate::torrent_state::peer::stats::snapshot::ConnectedPeerStats;
 use crate::torrent_state::stats::LiveStats;
-use crate::type_aliases::FileInfos;
 use crate::type_aliases::PeerStream;
+use crate::type_aliases::{FileInfos, FileStorage};
+use librqbit_core::v2::V2Metadata;
 
 use initializing::TorrentStateInitializing;
 
 use self::paused::TorrentStatePaused;
 pub use self::stats::{TorrentStats, TorrentStatsState};
-pub use self::streaming::FileStream;
+pub use self::streaming::{FileReader, FileStream};
 
 // State machine transitions.
 //
@@ -74,7 +109,27 @@ pub enum ManagedTorrentState {
     None,
 }
 
+/// The name of the current [`ManagedTorrentState`] without any of its contents.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ManagedTorrentStateKind {
+    Initializing,
+    Paused,
+    Live,
+    Error,
+}
+
 impl ManagedTorrentState {
+    pub fn kind(&self) -> ManagedTorrentStateKind {
+        match self {
+            ManagedTorrentState::Initializing(_) => ManagedTorrentStateKind::Initializing,
+            ManagedTorrentState::Paused(_) => ManagedTorrentStateKind::Paused,
+            ManagedTorrentState::Live(_) => ManagedTorrentStateKind::Live,
+            ManagedTorrentState::Error(_) | ManagedTorrentState::None => {
+                ManagedTorrentStateKind::Error
+            }
+        }
+    }
+
     pub fn name(&self) -> &'static str {
         match self {
             ManagedTorrentState::Initializing(_) => "initializing",
@@ -97,6 +152,26 @@ impl ManagedTorrentState {
     }
 }
 
+/// Why the torrent was last stopped (paused or errored).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StopReason {
+    /// Paused through the session API.
+    User,
+    /// The seed ratio limit was reached.
+    SeedRatioReached,
+    /// Paused by a TorrentQueue to make room for other torrents.
+    Queued,
+    /// Paused after downloading a file prefix, see [`ManagedTorrent::download_prefix`].
+    PrefixDownloaded,
+    /// Paused as the disk is full, see [`ManagedTorrent::resume_after_disk_full`].
+    DiskFull,
+    /// Paused after having no connected peers and making no progress for a while, see
+    /// [`AddTorrentOptions::idle_pause_timeout`](crate::AddTorrentOptions::idle_pause_timeout).
+    Idle,
+    /// Stopped due to a fatal error.
+    Error,
+}
+
 pub(crate) struct ManagedTorrentLocked {
     // The torrent might not be in "paused" state technically,
     // but the intention might be for it to stay paused.
@@ -105,23 +180,103 @@ pub(crate) struct ManagedTorrentLocked {
     pub(crate) paused: bool,
     pub(crate) state: ManagedTorrentState,
     pub(crate) only_files: Option<Vec<usize>>,
+    // Uploaded / selected bytes after which a finished torrent gets paused.
+    pub(crate) seed_ratio_limit: Option<f64>,
+    pub(crate) last_stop_reason: Option<StopReason>,
+    // Added paused without checking the files, the check will run on first start.
+    pub(crate) initial_check_deferred: bool,
+    // Added with "metadata_only": the storage was created, but not initialized,
+    // so nothing was written to the output folder yet.
+    pub(crate) storage_init_deferred: bool,
+    // Files with FilePriority::High or Low, the rest are Normal. Skipped files are the ones
+    // not in only_files.
+    pub(crate) file_priority_overrides: HashMap<usize, FilePriority>,
+    // Download pieces in order, see ManagedTorrent::set_sequential().
+    pub(crate) sequential: bool,
+    // See ManagedTorrent::set_super_seeding().
+    pub(crate) super_seeding: bool,
+    // Set while ManagedTorrent::move_storage(), rename_file() or verify_piece() on a paused
+    // torrent is running, the torrent can't be started meanwhile.
+    pub(crate) moving_storage: bool,
+    // See ManagedTorrent::rename_file().
+    pub(crate) renamed_files: BTreeMap<usize, PathBuf>,
+    // Set by the user, shown instead of the name from the metadata.
+    pub(crate) display_name: Option<String>,
 }
 
 #[derive(Default)]
 pub(crate) struct ManagedTorrentOptions {
     pub force_tracker_interval: Option<Duration>,
-    pub peer_connect_timeout: Option<Duration>,
-    pub peer_read_write_timeout: Option<Duration>,
+    // Defaults to DEFAULT_ANNOUNCE_TIMEOUT.
+    pub tracker_announce_timeout: Option<Duration>,
+    // Random delay of up to this before the first announce and added to the following ones.
+    pub tracker_announce_jitter: Option<Duration>,
+    // Pause the live torrent when it had no peers and no progress for this long. Finished
+    // torrents are exempt.
+    pub idle_pause_timeout: Option<Duration>,
+    // These can be changed while the torrent is running, see ManagedTorrent::set_peer_connect_timeout().
+    pub peer_connect_timeout: RwLock<Option<Duration>>,
+    pub peer_read_write_timeout: RwLock<Option<Duration>>,
     pub allow_overwrite: bool,
-    pub output_folder: PathBuf,
-    pub ratelimits: LimitsConfig,
+    // Changed by ManagedTorrent::move_storage().
+    pub output_folder: RwLock<PathBuf>,
+    // Changed by ManagedTorrent::set_rate_limits(), kept while paused.
+    pub ratelimits: Limits,
     pub initial_peers: Vec<SocketAddr>,
     pub peer_limit: Option<usize>,
+    pub max_half_open: Option<usize>,
+    pub read_cache_bytes: Option<usize>,
+    pub allocation: Allocation,
+    // Enter endgame mode when this many needed pieces or less are left. 0 disables it.
+    pub endgame_threshold: usize,
+    // Ban peers that contributed to this many pieces that failed the hash check. 0 disables it.
+    pub hash_fail_ban_threshold: u32,
+    // How many pieces to hash at once on the initial check. Defaults to the number of CPUs.
+    pub hashing_concurrency: Option<usize>,
+    // Unchoke at most this many peers plus an optimistic one. All peers are unchoked if not set.
+    pub unchoke_slots: Option<usize>,
+    // Stop requesting chunks while more than this many received bytes wait to be written to disk.
+    pub max_pending_write_bytes: Option<u64>,
+    // The port of the torrent's own listener, if it has one. Announced instead of the session's.
+    pub listen_port: Option<u16>,
+    // Paths chosen by AddTorrentOptions::file_naming that differ from the torrent's, by file
+    // index. Kept to persist the layout, as the strategy itself may be a closure.
+    pub named_files: BTreeMap<usize, PathBuf>,
+    pub peer_filter: Option<Arc<dyn PeerFilter>>,
+    // Peer discovery besides trackers. All off for private torrents.
+    pub enable_dht: bool,
+    pub enable_pex: bool,
+    pub enable_lsd: bool,
     #[cfg(feature = "disable-upload")]
     pub _disable_upload: bool,
 }
 
 impl ManagedTorrentOptions {
+    pub fn peer_connection_options(&self) -> PeerConnectionOptions {
+        PeerConnectionOptions {
+            connect_timeout: *self.peer_connect_timeout.read(),
+            read_write_timeout: *self.peer_read_write_timeout.read(),
+            ..Default::default()
+        }
+    }
+
+    pub fn tracker_comms_options(&self) -> TrackerCommsOptions {
+        TrackerCommsOptions {
+            force_interval: self.force_tracker_interval,
+            announce_timeout: self
+                .tracker_announce_timeout
+                .unwrap_or(DEFAULT_ANNOUNCE_TIMEOUT),
+            announce_jitter: self.tracker_announce_jitter.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn hashing_concurrency(&self) -> usize {
+        self.hashing_concurrency.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        })
+    }
+
     #[cfg(feature = "disable-upload")]
     pub fn disable_upload(&self) -> bool {
         self._disable_upload
@@ -134,11 +289,18 @@ impl ManagedTorrentOptions {
 }
 
 // Torrent bencodee "info" + some precomputed fields based on it for frequent access.
+#[derive(Clone)]
 pub struct TorrentMetadata {
     pub info: ValidatedTorrentMetaV1Info<ByteBufOwned>,
     pub torrent_bytes: Bytes,
     pub info_bytes: Bytes,
     pub file_infos: FileInfos,
+    info_hash_v2: Option<Id32>,
+    // The v2 parts of hybrid torrents, used to also check pieces against their merkle hashes.
+    pub(crate) v2: Option<V2Metadata>,
+    comment: Option<String>,
+    created_by: Option<String>,
+    creation_date: Option<SystemTime>,
 }
 
 impl TorrentMetadata {
@@ -160,19 +322,181 @@ impl TorrentMetadata {
             })
             .collect::<anyhow::Result<Vec<FileInfo>>>()?;
 
+        let info_hash_v2 = info
+            .info()
+            .is_hybrid()
+            .then(|| librqbit_core::torrent_metainfo::info_hash_v2(&info_bytes));
+
+        // Piece layers aren't in the "info" dict, so torrents from magnet links don't have them.
+        let v2 = match info_hash_v2.map(|_| V2Metadata::parse(&torrent_bytes)) {
+            Some(Ok(v2)) => {
+                v2.check_hybrid(&info)
+                    .context("v1 and v2 parts of the hybrid torrent differ")?;
+                Some(v2)
+            }
+            Some(Err(e)) => {
+                debug!("not using v2 metadata of the hybrid torrent: {e:#}");
+                None
+            }
+            None => None,
+        };
+
+        // Fields outside of "info" are informational only, so don't fail if they can't be parsed.
+        let (comment, created_by, creation_date) =
+            match bencode::from_bytes::<TorrentMetaV1Borrowed>(&torrent_bytes) {
+                Ok(meta) => {
+                    let text = |b: Option<ByteBuf>| {
+                        b.map(|b| String::from_utf8_lossy(b.as_ref()).trim().to_owned())
+                            .filter(|s| !s.is_empty())
+                    };
+                    (
+                        text(meta.comment),
+                        text(meta.created_by),
+                        meta.creation_date.and_then(|secs| {
+                            SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))
+                        }),
+                    )
+                }
+                Err(e) => {
+                    debug!("error parsing torrent file metadata: {e:#}");
+                    (None, None, None)
+                }
+            };
+
         Ok(Self {
             info,
             torrent_bytes,
             info_bytes,
             file_infos,
+            info_hash_v2,
+            v2,
+            comment,
+            created_by,
+            creation_date,
         })
     }
 
     pub fn lengths(&self) -> &Lengths {
         self.info.lengths()
     }
+
+    /// The v2 (BEP-52) info hash of hybrid torrents. These are downloaded as v1 torrents.
+    pub fn info_hash_v2(&self) -> Option<Id32> {
+        self.info_hash_v2
+    }
+
+    /// The "comment" field of the .torrent file.
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    /// The "created by" field of the .torrent file, usually the program that created it.
+    pub fn created_by(&self) -> Option<&str> {
+        self.created_by.as_deref()
+    }
+
+    /// The "creation date" field of the .torrent file.
+    pub fn creation_date(&self) -> Option<SystemTime> {
+        self.creation_date
+    }
 }
 
+impl ManagedTorrentShared {
+    /// All trackers, regardless of their tier.
+    pub fn trackers(&self) -> HashSet<url::Url> {
+        self.trackers.read().iter().flatten().cloned().collect()
+    }
+
+    /// Trackers grouped in tiers (BEP-12), in the order they are tried.
+    pub fn tracker_tiers(&self) -> Vec<Vec<url::Url>> {
+        self.trackers.read().clone()
+    }
+
+    pub fn output_folder(&self) -> PathBuf {
+        self.options.output_folder.read().clone()
+    }
+
+    pub(crate) fn is_filesystem_storage(&self) -> bool {
+        let factory = &self.storage_factory;
+        factory.is_type_id(TypeId::of::<FilesystemStorageFactory>())
+            || factory.is_type_id(TypeId::of::<MmapFilesystemStorageFactory>())
+    }
+
+    // Only files on the filesystem can be snapshotted.
+    pub(crate) fn files_snapshot(&self, metadata: &TorrentMetadata) -> Option<FilesSnapshot> {
+        if !self.is_filesystem_storage() {
+            return None;
+        }
+        Some(FilesSnapshot::take(
+            &self.output_folder(),
+            metadata.file_infos.iter(),
+        ))
+    }
+
+    // Snapshot the files and persist it in the background. Call when no more writes are
+    // expected, e.g. after pausing or finishing, so that the next start can skip validation.
+    pub(crate) fn spawn_store_files_snapshot(self: &Arc<Self>, metadata: &Arc<TorrentMetadata>) {
+        if self.is_filesystem_storage() {
+            self.spawn_update_files_snapshot(Some(metadata.clone()));
+        }
+    }
+
+    // Forget the stored snapshot in the background, as the files are about to be written to.
+    pub(crate) fn spawn_clear_files_snapshot(self: &Arc<Self>) {
+        if self.is_filesystem_storage() {
+            self.spawn_update_files_snapshot(None);
+        }
+    }
+
+    // Stores a snapshot with metadata, clears it otherwise. If another update is requested
+    // before this one runs, this one is skipped, so the last one wins.
+    fn spawn_update_files_snapshot(self: &Arc<Self>, metadata: Option<Arc<TorrentMetadata>>) {
+        let Some(session) = self.session.upgrade() else {
+            return;
+        };
+        let generation = self
+            .files_snapshot_generation
+            .fetch_add(1, Ordering::Relaxed)
+            + 1;
+        let bitv_factory = session.bitv_factory.clone();
+        let id: TorrentIdOrHash = self.info_hash.into();
+        let shared = self.clone();
+        // Not cancelled with the session, as it's stopped right after pausing all torrents.
+        librqbit_core::spawn_utils::spawn(
+            debug_span!(parent: self.span.clone(), "update_files_snapshot"),
+            "update_files_snapshot",
+            async move {
+                let _lock = shared.files_snapshot_lock.lock().await;
+                if shared.files_snapshot_generation.load(Ordering::Relaxed) != generation {
+                    return Ok(());
+                }
+                match metadata {
+                    Some(metadata) => {
+                        let Some(snapshot) = shared
+                            .spawner
+                            .block_in_place_with_semaphore(|| shared.files_snapshot(&metadata))
+                            .await
+                        else {
+                            return Ok(());
+                        };
+                        bitv_factory
+                            .store_files_snapshot(id, &snapshot)
+                            .await
+                            .context("error storing files snapshot")
+                    }
+                    None => bitv_factory
+                        .clear_files_snapshot(id)
+                        .await
+                        .context("error clearing files snapshot"),
+                }
+            },
+        );
+    }
+}
+
+/// A callback invoked once when the torrent finishes downloading all selected files.
+pub type OnCompleteCallback = Box<dyn Fn(&ManagedTorrent) + Send + Sync>;
+
 /// Common information about torrent shared among all possible states.
 ///
 // The reason it's not inlined into ManagedTorrent is to break the Arc cycle:
@@ -182,7 +506,9 @@ pub struct ManagedTorrentShared {
     pub id: TorrentId,
     pub info_hash: Id20,
     pub(crate) spawner: BlockingSpawner,
-    pub trackers: HashSet<url::Url>,
+    pub(crate) trackers: RwLock<Vec<Vec<url::Url>>>,
+    // HTTP servers hosting the torrent's files (BEP-19).
+    pub(crate) web_seeds: Vec<url::Url>,
     pub peer_id: Id20,
     pub span: tracing::Span,
     pub(crate) options: ManagedTorrentOptions,
@@ -192,10 +518,33 @@ pub struct ManagedTorrentShared {
 
     // "dn" from magnet link
     pub(crate) magnet_name: Option<String>,
+
+    pub(crate) on_complete: Option<OnCompleteCallback>,
+    pub(crate) on_complete_fired: AtomicBool,
+
+    // See spawn_update_files_snapshot().
+    pub(crate) files_snapshot_generation: AtomicU64,
+    pub(crate) files_snapshot_lock: tokio::sync::Mutex<()>,
+
+    pub(crate) events: TorrentEvents,
+
+    // Set on initialization, see ManagedTorrent::allocation_used().
+    pub(crate) allocation_used: RwLock<Option<Allocation>>,
+
+    // Changed by ManagedTorrent::set_wanted_ranges().
+    pub(crate) wanted_ranges: RwLock<WantedRanges>,
+
+    // Cumulative counters that survive pause/resume, unlike the live stats.
+    pub(crate) total_uploaded_bytes: AtomicU64,
+    pub(crate) total_downloaded_bytes: AtomicU64,
+
+    // The last swarm size reported by each tracker, see LiveStats::trackers.
+    pub(crate) tracker_swarm_stats: RwLock<BTreeMap<url::Url, tracker_comms::SwarmStats>>,
 }
 
 pub struct ManagedTorrent {
-    // Static torrent configuration that doesn't change.
+    // Torrent configuration shared with the live state. Some fields, e.g. the trackers and the
+    // output folder, can be changed at runtime.
     pub shared: Arc<ManagedTorrentShared>,
     // Torrent metadata. Maybe be None when the magnet is resolving (not implemented yet)
     pub metadata: ArcSwapOption<TorrentMetadata>,
@@ -236,10 +585,73 @@ impl ManagedTorrent {
         self.shared.info_hash
     }
 
+    /// The v2 info hash of a hybrid torrent, see [`TorrentMetadata::info_hash_v2`].
+    /// None for v1 torrents, and for magnet links that weren't resolved yet.
+    pub fn info_hash_v2(&self) -> Option<Id32> {
+        self.metadata.load().as_ref()?.info_hash_v2()
+    }
+
+    /// The disk allocation strategy that was actually used for the output files. It might differ
+    /// from the requested one if e.g. full allocation isn't supported.
+    /// None if the torrent wasn't initialized yet.
+    pub fn allocation_used(&self) -> Option<Allocation> {
+        *self.shared.allocation_used.read()
+    }
+
+    /// Build a .torrent file from the info dictionary and all currently known tracker tiers,
+    /// e.g. to save a torrent that was added from a magnet link.
+    ///
+    /// The info dictionary is copied byte for byte, so the info hash stays the same.
+    pub fn export_torrent_file(&self) -> anyhow::Result<Vec<u8>> {
+        let info_bytes = self.with_metadata(|m| m.info_bytes.clone())?;
+        let torrent_bytes = crate::session::torrent_file_from_info_bytes(
+            &info_bytes,
+            &self.shared.tracker_tiers(),
+        )?;
+
+        let info_hash = torrent_from_bytes(&torrent_bytes)
+            .context("error parsing exported torrent")?
+            .info_hash;
+        if info_hash != self.info_hash() {
+            bail!(
+                "bug: exported torrent has info hash {info_hash:?}, expected {:?}",
+                self.info_hash()
+            );
+        }
+        Ok(torrent_bytes.into())
+    }
+
+    /// A magnet link with the info hash, name, web seeds and all currently known trackers,
+    /// e.g. to re-share a torrent that was added from a file.
+    pub fn magnet_link(&self) -> Magnet {
+        let trackers = self
+            .shared
+            .tracker_tiers()
+            .into_iter()
+            .flatten()
+            .map(|t| t.to_string())
+            .collect();
+        let mut magnet = Magnet::new(Some(self.info_hash()), self.info_hash_v2(), trackers, None)
+            .expect("v1 info hash is always set");
+        magnet.name = self.name();
+        magnet.web_seeds = self
+            .shared
+            .web_seeds
+            .iter()
+            .map(|u| u.to_string())
+            .collect();
+        magnet
+    }
+
     pub fn only_files(&self) -> Option<Vec<usize>> {
         self.locked.read().only_files.clone()
     }
 
+    /// Cheaply get the current state without building [`TorrentStats`].
+    pub fn state_kind(&self) -> ManagedTorrentStateKind {
+        self.with_state(|s| s.kind())
+    }
+
     pub fn with_state<R>(&self, f: impl FnOnce(&ManagedTorrentState) -> R) -> R {
         f(&self.locked.read().state)
     }
@@ -284,7 +696,11 @@ impl ManagedTorrent {
         self.live()
     }
 
-    fn stop_with_error(&self, error: anyhow::Error) {
+    pub(crate) async fn stop_with_error(&self, error: anyhow::Error) {
+        let error = match self.missing_storage_path().await {
+            Some(path) => error.context(TorrentError::StorageMissing(path)),
+            None => error,
+        };
         let mut g = self.locked.write();
 
         match g.state.take() {
@@ -314,9 +730,74 @@ impl ManagedTorrent {
             _ => {}
         };
 
+        self.shared
+            .events
+            .emit(TorrentEvent::Error(format!("{error:#}")));
+        g.state = ManagedTorrentState::Error(error);
+        g.last_stop_reason = Some(StopReason::Error);
+        self.notify_state_changed(ManagedTorrentStateKind::Error);
+    }
+
+    // Unlike other fatal errors, running out of disk space can be fixed without re-checking
+    // the files, so the torrent is only paused. The pieces written so far are kept.
+    async fn pause_on_disk_full(&self, error: anyhow::Error) {
+        if let Err(e) = self.pause() {
+            warn!(
+                id = self.shared.id,
+                info_hash = ?self.shared.info_hash,
+                "error pausing torrent on full disk, stopping with error: {e:#}"
+            );
+            self.stop_with_error(error).await;
+            return;
+        }
+        self.set_last_stop_reason(StopReason::DiskFull);
+        self.shared.events.emit(TorrentEvent::DiskFull);
+    }
+
+    // The output folder, or the first selected file, that was removed from disk. Only filesystem
+    // storage is checked. The checks run on a blocking thread, as a broken disk or network share
+    // can make them hang.
+    async fn missing_storage_path(&self) -> Option<PathBuf> {
+        if !self.shared.is_filesystem_storage() {
+            return None;
+        }
+        let output_folder = self.shared.output_folder();
+        let metadata = self.metadata.load_full();
+        let only_files = self.only_files();
+        let files = metadata
+            .iter()
+            .flat_map(|m| m.file_infos.iter().enumerate())
+            .filter(|(idx, fi)| {
+                !fi.attrs.padding && only_files.as_ref().is_none_or(|o| o.contains(idx))
+            })
+            .map(|(_, fi)| output_folder.join(&fi.relative_filename))
+            .collect::<Vec<_>>();
+        tokio::task::spawn_blocking(move || {
+            std::iter::once(output_folder)
+                .chain(files)
+                .find(|path| !path.exists())
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    /// True if the torrent stopped with [`TorrentError::StorageMissing`].
+    pub fn is_storage_missing(&self) -> bool {
+        self.with_state(|s| match s {
+            ManagedTorrentState::Error(e) => is_storage_missing_error(e),
+            _ => false,
+        })
+    }
+
+    fn notify_state_changed(&self, kind: ManagedTorrentStateKind) {
         self.state_change_notify.notify_waiters();
+        self.shared.events.emit(TorrentEvent::StateChanged(kind));
+    }
 
-        g.state = ManagedTorrentState::Error(error)
+    /// Subscribe to events of this torrent.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<TorrentEvent> {
+        self.shared.events.subscribe()
     }
 
     /// peer_rx: the peer stream. If start_paused=false, must be set.
@@ -340,6 +821,23 @@ impl ManagedTorrent {
                 ManagedTorrentState::Live(_) => {
                     bail!("torrent is already live");
                 }
+                ManagedTorrentState::Initializing(init) if g.storage_init_deferred => {
+                    let metadata = init.metadata.clone();
+                    let initializing = Arc::new(TorrentStateInitializing::new(
+                        t.shared.clone(),
+                        metadata.clone(),
+                        g.only_files.clone(),
+                        t.shared
+                            .storage_factory
+                            .create_and_init(t.shared(), &metadata)?,
+                        false,
+                    ));
+                    g.state = ManagedTorrentState::Initializing(initializing);
+                    g.storage_init_deferred = false;
+
+                    // Recurse.
+                    _start(t, peer_rx, start_paused, session, Some(g), token)
+                }
                 ManagedTorrentState::Initializing(init) => {
                     let init = init.clone();
                     let t = t.clone();
@@ -360,6 +858,10 @@ impl ManagedTorrent {
 
                             match init.check().await {
                                 Ok(paused) => {
+                                    // Completed from existing files (e.g. seeding). If fastresume
+                                    // said we were done, it's a restart and was reported before.
+                                    let completed_on_check =
+                                        init.did_full_check() && paused.hns().finished();
                                     let mut g = t.locked.write();
                                     if let ManagedTorrentState::Initializing(_) = &g.state {
                                     } else {
@@ -370,13 +872,18 @@ impl ManagedTorrent {
                                     }
 
                                     g.state = ManagedTorrentState::Paused(paused);
-                                    t.state_change_notify.notify_waiters();
-                                    _start(&t, peer_rx, start_paused, session, Some(g), token)
+                                    t.notify_state_changed(g.state.kind());
+                                    let res =
+                                        _start(&t, peer_rx, start_paused, session, Some(g), token);
+                                    if completed_on_check {
+                                        t.on_completed();
+                                    }
+                                    res
                                 }
                                 Err(err) => {
                                     let result = anyhow::anyhow!("{:?}", err);
                                     t.locked.write().state = ManagedTorrentState::Error(err);
-                                    t.state_change_notify.notify_waiters();
+                                    t.notify_state_changed(ManagedTorrentStateKind::Error);
                                     Err(result)
                                 }
                             }
@@ -390,11 +897,26 @@ impl ManagedTorrent {
                     }
                     let paused = g.state.take().assert_paused();
                     let (tx, rx) = tokio::sync::oneshot::channel();
-                    let live = TorrentStateLive::new(paused, tx, token.clone())?;
+                    let live = TorrentStateLive::new(
+                        paused,
+                        tx,
+                        token.clone(),
+                        &g.file_priority_overrides,
+                        g.sequential,
+                        g.super_seeding,
+                    )?;
                     g.state = ManagedTorrentState::Live(live.clone());
-                    t.state_change_notify.notify_waiters();
+                    t.notify_state_changed(g.state.kind());
+                    t.shared.spawn_clear_files_snapshot();
 
                     spawn_fatal_errors_receiver(t, rx, token);
+                    spawn_seed_ratio_watcher(t, &live);
+                    if let Some(idle_timeout) = t.shared.options.idle_pause_timeout {
+                        spawn_idle_watcher(t, &live, idle_timeout);
+                    }
+                    if t.shared.on_complete.is_some() && !live.is_finished() {
+                        spawn_on_complete_waiter(t, &live);
+                    }
                     if let Some(peer_rx) = peer_rx {
                         spawn_peer_adder(&live, peer_rx);
                     }
@@ -412,7 +934,7 @@ impl ManagedTorrent {
                         true,
                     ));
                     g.state = ManagedTorrentState::Initializing(initializing.clone());
-                    t.state_change_notify.notify_waiters();
+                    t.notify_state_changed(g.state.kind());
 
                     // Recurse.
                     _start(t, peer_rx, start_paused, session, Some(g), token)
@@ -427,7 +949,14 @@ impl ManagedTorrent {
             .upgrade()
             .context("session is dead, cannot start torrent")?;
         let mut g = self.locked.write();
+        if g.moving_storage {
+            bail!("torrent storage is being moved, can't start");
+        }
         g.paused = start_paused;
+        if !start_paused {
+            g.last_stop_reason = None;
+        }
+        g.initial_check_deferred = false;
         let cancellation_token = session.cancellation_token().child_token();
 
         _start(
@@ -440,6 +969,150 @@ impl ManagedTorrent {
         )
     }
 
+    // Must be called without holding any locks, as the callback might inspect the torrent.
+    fn on_completed(&self) {
+        if let Some(cb) = self.shared.on_complete.as_ref()
+            && !self.shared.on_complete_fired.swap(true, Ordering::Relaxed)
+        {
+            cb(self)
+        }
+    }
+
+    /// Add trackers to the torrent, each as a new tier. Returns how many of them were not known
+    /// before.
+    ///
+    /// If the torrent is live, the new trackers are announced to right away.
+    pub fn add_trackers(self: &Arc<Self>, new: Vec<url::Url>) -> anyhow::Result<usize> {
+        for url in new.iter() {
+            if !matches!(url.scheme(), "http" | "https" | "udp") {
+                bail!("unsupported tracker URL: {url}");
+            }
+        }
+
+        let added = {
+            let mut tiers = self.shared.trackers.write();
+            let mut known = tiers.iter().flatten().cloned().collect::<HashSet<_>>();
+            let added = new
+                .into_iter()
+                .filter(|url| known.insert(url.clone()))
+                .collect::<Vec<_>>();
+            tiers.extend(added.iter().map(|url| vec![url.clone()]));
+            added
+        };
+        if added.is_empty() {
+            return Ok(0);
+        }
+        let count = added.len();
+
+        let live = self.live();
+        if let Some(live) = live
+            && let Some(session) = self.shared.session.upgrade()
+            && let Some(peer_rx) = session.make_tracker_rx_managed_torrent(
+                self,
+                added.into_iter().map(|url| vec![url]).collect(),
+            )
+        {
+            spawn_peer_adder(&live, peer_rx);
+        }
+        Ok(count)
+    }
+
+    /// Upload read cache counters. None unless the torrent is live with the cache enabled.
+    pub fn read_cache_stats(&self) -> Option<ReadCacheStats> {
+        self.live().and_then(|live| live.read_cache_stats())
+    }
+
+    /// Change the timeout for connecting to peers.
+    ///
+    /// Only affects connections started after this call. Peers that are already connected or
+    /// being connected to keep the timeout they started with.
+    pub fn set_peer_connect_timeout(&self, timeout: Duration) {
+        *self.shared.options.peer_connect_timeout.write() = Some(timeout);
+    }
+
+    /// Change the timeout for reading from and writing to peers.
+    ///
+    /// Same as [`Self::set_peer_connect_timeout`], only connections started after this call
+    /// use the new value.
+    pub fn set_peer_read_write_timeout(&self, timeout: Duration) {
+        *self.shared.options.peer_read_write_timeout.write() = Some(timeout);
+    }
+
+    /// The torrent's own upload and download limits. They apply on top of the session's ones.
+    pub fn rate_limits(&self) -> LimitsConfig {
+        self.shared.options.ratelimits.get_config()
+    }
+
+    /// Change the torrent's own upload and download limits, None removes the limit. Takes
+    /// effect right away, including on a live torrent, and is kept across pause and resume.
+    pub fn set_rate_limits(&self, limits: LimitsConfig) {
+        let ratelimits = &self.shared.options.ratelimits;
+        ratelimits.set_upload_bps(limits.upload_bps);
+        ratelimits.set_download_bps(limits.download_bps);
+    }
+
+    /// Stats of connected peers. Empty unless the torrent is live.
+    pub fn peer_stats(&self) -> Vec<ConnectedPeerStats> {
+        self.live()
+            .map(|live| live.connected_peer_stats())
+            .unwrap_or_default()
+    }
+
+    /// For each piece, the number of connected peers that have it (capped at u16::MAX).
+    /// Needed pieces with 0 can't be downloaded until a peer that has them connects.
+    /// None unless the torrent is live.
+    pub fn piece_availability(&self) -> Option<Vec<u16>> {
+        self.live().map(|live| live.piece_availability())
+    }
+
+    pub fn seed_ratio_limit(&self) -> Option<f64> {
+        self.locked.read().seed_ratio_limit
+    }
+
+    /// Pause the torrent once it's finished and uploaded ratio * selected bytes.
+    /// Takes effect immediately if the torrent is live.
+    pub fn set_seed_ratio_limit(&self, ratio: Option<f64>) {
+        self.locked.write().seed_ratio_limit = ratio;
+    }
+
+    /// The name set with [`ManagedTorrent::set_display_name`], falling back to the name from
+    /// the metadata or magnet link, and then to the info hash.
+    pub fn display_name(&self) -> String {
+        if let Some(name) = self.locked.read().display_name.clone() {
+            return name;
+        }
+        self.name()
+            .unwrap_or_else(|| self.shared.info_hash.as_string())
+    }
+
+    /// Override the name shown for the torrent. This doesn't rename anything on disk.
+    /// None goes back to the name from the metadata.
+    pub fn set_display_name(&self, name: Option<String>) {
+        self.locked.write().display_name = name;
+    }
+
+    /// The TCP port peers can connect to for this torrent: the one it listens on if
+    /// [`AddTorrentOptions::listen_port`](crate::AddTorrentOptions::listen_port) was set, otherwise
+    /// the session's. This is the actual port, which differs from the requested one if that
+    /// was taken.
+    pub fn listen_port(&self) -> Option<u16> {
+        self.shared.options.listen_port.or_else(|| {
+            self.shared
+                .session
+                .upgrade()?
+                .listen_addr()
+                .map(|a| a.port())
+        })
+    }
+
+    pub fn last_stop_reason(&self) -> Option<StopReason> {
+        self.locked.read().last_stop_reason
+    }
+
+    pub(crate) fn set_last_stop_reason(&self, reason: StopReason) {
+        self.locked.write().last_stop_reason = Some(reason);
+    }
+
     pub fn is_paused(&self) -> bool {
         self.locked.read().paused
     }
@@ -450,9 +1123,10 @@ impl ManagedTorrent {
         match &g.state {
             ManagedTorrentState::Live(live) => {
                 let paused = live.pause()?;
+                self.shared.spawn_store_files_snapshot(&paused.metadata);
                 g.state = ManagedTorrentState::Paused(paused);
                 g.paused = true;
-                self.state_change_notify.notify_waiters();
+                self.notify_state_changed(g.state.kind());
                 Ok(())
             }
             ManagedTorrentState::Initializing(_) => {
@@ -468,10 +1142,456 @@ impl ManagedTorrent {
         }
     }
 
+    /// Check one piece on disk against its hash, without re-checking the whole torrent.
+    ///
+    /// The piece is marked as downloaded if it passed, or queued to be downloaded again if it
+    /// didn't. Returns whether it passed. The torrent must be live or paused.
+    pub async fn verify_piece(&self, piece: u32) -> anyhow::Result<bool> {
+        let metadata = self
+            .metadata
+            .load_full()
+            .context("torrent metadata is not resolved")?;
+        let id = metadata
+            .lengths()
+            .validate_piece_index(piece)
+            .with_context(|| format!("invalid piece index {piece}"))?;
+        let live_or_files = {
+            let mut g = self.locked.write();
+            let live_or_files = match &g.state {
+                ManagedTorrentState::Live(live) => Err(live.clone()),
+                // Hash with the lock released, the files are put back when done. Meanwhile the
+                // torrent can't be started or have its storage touched.
+                ManagedTorrentState::Paused(paused) if !g.moving_storage => {
+                    Ok(paused.files.take()?)
+                }
+                ManagedTorrentState::Paused(_) => {
+                    bail!("torrent storage is being moved, can't verify pieces")
+                }
+                s => bail!("can't verify pieces, torrent is {}", s.name()),
+            };
+            if live_or_files.is_ok() {
+                g.moving_storage = true;
+            }
+            live_or_files
+        };
+        let files = match live_or_files {
+            Ok(files) => files,
+            Err(live) => return live.verify_piece(id).await,
+        };
+
+        let ok = self
+            .shared
+            .spawner
+            .block_in_place_with_semaphore(|| {
+                FileOps::new(&metadata.info, &*files, &metadata.file_infos)
+                    .with_v2(metadata.v2.as_ref())
+                    .check_piece(id)
+            })
+            .await;
+
+        let mut g = self.locked.write();
+        g.moving_storage = false;
+        let ManagedTorrentState::Paused(paused) = &mut g.state else {
+            bail!("torrent was removed while verifying piece {id}");
+        };
+        paused.files = files;
+        let ok = ok?;
+        paused.on_piece_verified(id, ok)?;
+        Ok(ok)
+    }
+
+    /// Re-verify all pieces on disk, e.g. if the data is suspected to be corrupted.
+    ///
+    /// The torrent goes back to initializing (progress is reported through the usual
+    /// checked bytes counter), and then returns to live or paused, whichever it was in before.
+    /// A torrent in error state is resumed the same way as when unpausing it.
+    pub fn force_recheck(self: &Arc<Self>) -> anyhow::Result<()> {
+        let session = self
+            .shared
+            .session
+            .upgrade()
+            .context("session is dead, cannot recheck torrent")?;
+
+        // A torrent in error state has no files open, open them before taking the lock.
+        let reopened = if self.state_kind() == ManagedTorrentStateKind::Error {
+            let metadata = self
+                .metadata
+                .load_full()
+                .context("torrent metadata is not resolved")?;
+            let files = self
+                .shared
+                .spawner
+                .block_in_place(|| {
+                    self.shared
+                        .storage_factory
+                        .create_and_init(self.shared(), &metadata)
+                })
+                .context("error opening torrent files")?;
+            Some((metadata, files))
+        } else {
+            None
+        };
+
+        let mut g = self.locked.write();
+        if g.moving_storage {
+            bail!("torrent storage is being moved, can't recheck");
+        }
+        let (metadata, files) = match &g.state {
+            ManagedTorrentState::Live(live) => {
+                let paused = live.pause()?;
+                (paused.metadata, paused.files)
+            }
+            ManagedTorrentState::Paused(_) => {
+                let paused = g.state.take().assert_paused();
+                (paused.metadata, paused.files)
+            }
+            ManagedTorrentState::Error(_) => match reopened {
+                Some(reopened) => reopened,
+                None => bail!("torrent state changed while rechecking, try again"),
+            },
+            ManagedTorrentState::Initializing(_) => bail!("torrent is already initializing"),
+            ManagedTorrentState::None => bail!("bug: torrent is in empty state"),
+        };
+
+        // previously_errored=true ignores and clears the fastresume bitfield, so every piece is hashed.
+        let initializing = Arc::new(TorrentStateInitializing::new(
+            self.shared.clone(),
+            metadata,
+            g.only_files.clone(),
+            files,
+            true,
+        ));
+        g.state = ManagedTorrentState::Initializing(initializing);
+        self.notify_state_changed(g.state.kind());
+
+        let start_paused = g.paused;
+        drop(g);
+
+        let peer_rx = if start_paused {
+            None
+        } else {
+            session.make_peer_rx_managed_torrent(self, true)
+        };
+        self.start(peer_rx, start_paused)
+    }
+
+    /// Recreate the files of a torrent that stopped with [`TorrentError::StorageMissing`].
+    ///
+    /// The files that are still there are kept. Everything is re-verified, so the torrent goes
+    /// back to initializing, and then to live or paused, whichever it was in before.
+    pub fn recover_storage(self: &Arc<Self>) -> anyhow::Result<()> {
+        let session = self
+            .shared
+            .session
+            .upgrade()
+            .context("session is dead, cannot recover torrent storage")?;
+        let metadata = self
+            .metadata
+            .load_full()
+            .context("torrent metadata is not resolved")?;
+        let check_state = |state: &ManagedTorrentState| -> anyhow::Result<()> {
+            match state {
+                ManagedTorrentState::Error(e) if is_storage_missing_error(e) => Ok(()),
+                s => bail!("torrent storage is not missing, torrent is {}", s.name()),
+            }
+        };
+        check_state(&self.locked.read().state)?;
+
+        // Unlike the initial open, this creates missing files even if overwriting isn't allowed,
+        // as the remaining ones are ours. It's done without the lock, so that the torrent can
+        // still be looked at meanwhile.
+        let files = self
+            .shared
+            .spawner
+            .block_in_place(|| self.reopen_storage(&metadata))
+            .context("error recreating torrent files")?;
+
+        // If the state changed in between, the opened files are dropped.
+        let mut g = self.locked.write();
+        check_state(&g.state)?;
+        let initializing = Arc::new(TorrentStateInitializing::new(
+            self.shared.clone(),
+            metadata,
+            g.only_files.clone(),
+            files,
+            true,
+        ));
+        g.state = ManagedTorrentState::Initializing(initializing);
+        self.notify_state_changed(g.state.kind());
+
+        let start_paused = g.paused;
+        drop(g);
+
+        let peer_rx = if start_paused {
+            None
+        } else {
+            session.make_peer_rx_managed_torrent(self, true)
+        };
+        self.start(peer_rx, start_paused)
+    }
+
+    /// Continue a torrent that was paused as the disk was full, once there's free space again.
+    ///
+    /// Same as unpausing it, the pieces downloaded before the disk filled up are kept.
+    pub fn resume_after_disk_full(self: &Arc<Self>) -> anyhow::Result<()> {
+        let session = self
+            .shared
+            .session
+            .upgrade()
+            .context("session is dead, cannot resume torrent")?;
+        {
+            let g = self.locked.read();
+            if !matches!(g.state, ManagedTorrentState::Paused(_))
+                || g.last_stop_reason != Some(StopReason::DiskFull)
+            {
+                bail!("torrent wasn't paused due to a full disk");
+            }
+        }
+        let peer_rx = session.make_peer_rx_managed_torrent(self, true);
+        self.start(peer_rx, false)
+    }
+
+    /// Stop the torrent and remove it from its session, same as [`Session::delete`].
+    ///
+    /// With `delete_files`, also delete the files of the torrent and the directories inside
+    /// the output folder that are left empty. Files outside the output folder are kept.
+    pub async fn remove(&self, delete_files: bool) -> anyhow::Result<DeletedFiles> {
+        let session = self
+            .shared
+            .session
+            .upgrade()
+            .context("session is dead, cannot remove torrent")?;
+        if !session
+            .get(self.id().into())
+            .is_some_and(|t| std::ptr::eq(&*t, self))
+        {
+            bail!("torrent was already removed from the session");
+        }
+        session.delete_torrent(self.id().into(), delete_files).await
+    }
+
+    /// Move the torrent files to a new output folder.
+    ///
+    /// Files are renamed, or copied and removed if renaming isn't possible (e.g. across filesystems).
+    /// If anything fails, the already moved files are moved back to the original folder.
+    ///
+    /// A live torrent is paused for the duration of the move and resumed afterwards. Its pieces
+    /// aren't re-checked.
+    pub async fn move_storage(self: &Arc<Self>, new_output_folder: PathBuf) -> anyhow::Result<()> {
+        if !self.shared.is_filesystem_storage() {
+            bail!("only filesystem storage can be moved");
+        }
+
+        let old_output_folder = self.shared.output_folder();
+        if new_output_folder == old_output_folder {
+            return Ok(());
+        }
+
+        // The session's output folder is shared by all torrents, so it's kept even if empty.
+        let remove_old_root = self
+            .shared
+            .session
+            .upgrade()
+            .is_some_and(|s| s.default_output_folder() != old_output_folder);
+
+        self.with_storage_closed(|metadata| {
+            let moved = move_storage::move_files(
+                &metadata.file_infos,
+                &old_output_folder,
+                &new_output_folder,
+            )?;
+            *self.shared.options.output_folder.write() = new_output_folder.clone();
+            match self.reopen_storage(metadata) {
+                Ok(files) => {
+                    moved.cleanup(remove_old_root);
+                    Ok((metadata.clone(), files))
+                }
+                Err(e) => {
+                    *self.shared.options.output_folder.write() = old_output_folder.clone();
+                    moved.rollback();
+                    Err(e)
+                }
+            }
+        })
+        .await
+        .with_context(|| format!("error moving files to {new_output_folder:?}"))
+    }
+
+    /// Rename the folder the torrent files are in, keeping it next to where it was. For torrents
+    /// added to the session's output folder, that's the root folder named after the torrent.
+    pub async fn rename_output_folder(self: &Arc<Self>, name: &str) -> anyhow::Result<()> {
+        if !matches!(
+            Path::new(name).components().collect::<Vec<_>>()[..],
+            [std::path::Component::Normal(_)]
+        ) {
+            bail!("invalid folder name {name:?}");
+        }
+        let output_folder = self.shared.output_folder();
+        if self
+            .shared
+            .session
+            .upgrade()
+            .is_some_and(|s| s.default_output_folder() == output_folder)
+        {
+            bail!("torrent files are directly in the session's output folder, rename them instead");
+        }
+        let parent = output_folder
+            .parent()
+            .context("output folder has no parent")?;
+        self.move_storage(parent.join(name)).await
+    }
+
+    /// Store a file at a different path than the one in the metadata, e.g. to give it a nicer name.
+    /// The path is relative to the output folder. The file is renamed on disk if it exists.
+    ///
+    /// Like with [`Self::move_storage`], a live torrent is paused meanwhile and resumed afterwards.
+    /// The new path is kept across restarts.
+    pub async fn rename_file(
+        self: &Arc<Self>,
+        file_index: usize,
+        new_path: PathBuf,
+    ) -> anyhow::Result<()> {
+        if !self.shared.is_filesystem_storage() {
+            bail!("only files of filesystem storage can be renamed");
+        }
+
+        let output_folder = self.shared.output_folder();
+        let renamed_files = BTreeMap::from([(file_index, new_path.clone())]);
+
+        self.with_storage_closed(|metadata| {
+            let mut new_metadata = TorrentMetadata::clone(metadata);
+            apply_renamed_files(&mut new_metadata.file_infos, &renamed_files)?;
+            let old_relative = &metadata.file_infos[file_index].relative_filename;
+            let old = output_folder.join(old_relative);
+            let new = output_folder.join(&new_path);
+            let rename = old != new && old.exists();
+            if rename {
+                if new.exists() {
+                    bail!("{new:?} already exists");
+                }
+                move_storage::move_file(&old, &new)?;
+            }
+            match self.reopen_storage(&new_metadata) {
+                Ok(files) => {
+                    if rename && old_relative.is_relative() {
+                        move_storage::remove_empty_dirs(
+                            &output_folder,
+                            std::slice::from_ref(old_relative),
+                        );
+                    }
+                    Ok((Arc::new(new_metadata), files))
+                }
+                Err(e) => {
+                    if rename && let Err(e) = move_storage::move_file(&new, &old) {
+                        warn!("error renaming {new:?} back to {old:?}: {e:#}");
+                    }
+                    Err(e)
+                }
+            }
+        })
+        .await
+        .with_context(|| format!("error renaming file {file_index} to {new_path:?}"))?;
+
+        self.locked
+            .write()
+            .renamed_files
+            .insert(file_index, new_path);
+        Ok(())
+    }
+
+    /// The paths of files that were renamed with [`Self::rename_file`], by file index.
+    pub fn renamed_files(&self) -> BTreeMap<usize, PathBuf> {
+        self.locked.read().renamed_files.clone()
+    }
+
+    // Close the files, let "f" change where they are on disk, and reopen them with the metadata it
+    // returns. If "f" fails, it must leave the files where they were, and they are reopened there.
+    // A live torrent is paused meanwhile and resumed afterwards.
+    async fn with_storage_closed(
+        self: &Arc<Self>,
+        f: impl FnOnce(&Arc<TorrentMetadata>) -> anyhow::Result<(Arc<TorrentMetadata>, FileStorage)>,
+    ) -> anyhow::Result<()> {
+        let session = self
+            .shared
+            .session
+            .upgrade()
+            .context("session is dead, cannot move torrent files")?;
+        let was_live = self.state_kind() == ManagedTorrentStateKind::Live;
+        if was_live {
+            self.pause()?;
+        }
+
+        let metadata = {
+            let mut g = self.locked.write();
+            if g.moving_storage {
+                bail!("torrent files are already being moved");
+            }
+            let paused = match &mut g.state {
+                ManagedTorrentState::Paused(paused) => paused,
+                _ => bail!("torrent must be live or paused to move its files"),
+            };
+            // Close the files before moving them.
+            drop(paused.files.take()?);
+            let metadata = paused.metadata.clone();
+            g.moving_storage = true;
+            metadata
+        };
+
+        let result = self
+            .shared
+            .spawner
+            .block_in_place_with_semaphore(|| f(&metadata))
+            .await;
+
+        let (files, result) = match result {
+            Ok(files) => (Ok(files), Ok(())),
+            Err(e) => (
+                self.shared
+                    .spawner
+                    .block_in_place(|| self.reopen_storage(&metadata))
+                    .map(|files| (metadata, files)),
+                Err(e),
+            ),
+        };
+
+        let mut g = self.locked.write();
+        g.moving_storage = false;
+        if let ManagedTorrentState::Paused(paused) = &mut g.state {
+            match files {
+                Ok((metadata, files)) => {
+                    self.metadata.store(Some(metadata.clone()));
+                    paused.metadata = metadata;
+                    paused.files = files;
+                }
+                Err(e) => {
+                    g.state = ManagedTorrentState::Error(e);
+                    self.notify_state_changed(ManagedTorrentStateKind::Error);
+                }
+            }
+        }
+        let resume = was_live && g.state.kind() == ManagedTorrentStateKind::Paused;
+        drop(g);
+
+        // Resume even if "f" failed, the files are back in their original place then.
+        if resume {
+            let peer_rx = session.make_peer_rx_managed_torrent(self, true);
+            self.start(peer_rx, false)
+                .context("error resuming torrent after moving files")?;
+        }
+        result
+    }
+
+    fn reopen_storage(&self, metadata: &TorrentMetadata) -> anyhow::Result<FileStorage> {
+        let mut storage = self.shared.storage_factory.create(&self.shared, metadata)?;
+        storage.reopen(&self.shared, metadata)?;
+        Ok(storage)
+    }
+
     /// Get stats.
     pub fn stats(&self) -> TorrentStats {
         use stats::TorrentStatsState as S;
         let mut resp = TorrentStats {
+            display_name: self.display_name(),
             total_bytes: self
                 .metadata
                 .load()
@@ -481,14 +1601,20 @@ impl ManagedTorrent {
             file_progress: Vec::new(),
             state: S::Error,
             error: None,
+            storage_missing: false,
             progress_bytes: 0,
-            uploaded_bytes: 0,
+            uploaded_bytes: self.shared.total_uploaded_bytes.load(Ordering::Relaxed),
+            downloaded_bytes: self.shared.total_downloaded_bytes.load(Ordering::Relaxed),
             finished: false,
             live: None,
         };
 
+        let initial_check_deferred = self.locked.read().initial_check_deferred;
         self.with_state(|s| {
             match s {
+                ManagedTorrentState::Initializing(_) if initial_check_deferred => {
+                    resp.state = S::Queued;
+                }
                 ManagedTorrentState::Initializing(i) => {
                     resp.state = S::Initializing;
                     resp.progress_bytes = i.checked_bytes.load(Ordering::Relaxed);
@@ -508,7 +1634,6 @@ impl ManagedTorrent {
                     resp.total_bytes = hns.total();
                     resp.progress_bytes = hns.progress();
                     resp.finished = hns.finished();
-                    resp.uploaded_bytes = l.get_uploaded_bytes();
                     resp.file_progress = l
                         .lock_read("file_progress")
                         .get_chunks()
@@ -519,7 +1644,8 @@ impl ManagedTorrent {
                 }
                 ManagedTorrentState::Error(e) => {
                     resp.state = S::Error;
-                    resp.error = Some(format!("{e:?}"))
+                    resp.error = Some(format!("{e:?}"));
+                    resp.storage_missing = is_storage_missing_error(e);
                 }
                 ManagedTorrentState::None => {
                     resp.state = S::Error;
@@ -579,9 +1705,12 @@ impl ManagedTorrent {
         .boxed()
     }
 
-    // Returns true if needed to unpause torrent.
-    // This is just implementation detail - it's easier to pause/unpause than to tinker with internals.
-    pub(crate) fn update_only_files(&self, only_files: &HashSet<usize>) -> anyhow::Result<()> {
+    /// Change which files are downloaded. Works on paused and live torrents: a live torrent
+    /// starts downloading newly selected files right away, and becomes finished if all the
+    /// remaining selected files are downloaded.
+    ///
+    /// This doesn't update the session persistence, see [`Session::update_only_files`].
+    pub fn update_only_files(&self, only_files: &HashSet<usize>) -> anyhow::Result<()> {
         let metadata = self.metadata.load();
         let metadata = metadata.as_ref().context("torrent is not resolved")?;
         let file_count = metadata.file_infos.len();
@@ -591,30 +1720,259 @@ impl ManagedTorrent {
             }
         }
 
-        // if live, need to update chunk tracker
-        // - if already finished: need to pause, then unpause (to reopen files etc)
-        // if paused, need to update chunk tracker
+        update_only_files_locked(&mut self.locked.write(), only_files)
+    }
+}
+
+// Read-modify-write callers hold the same guard throughout, so that concurrent changes to the
+// selection aren't lost.
+fn update_only_files_locked(
+    g: &mut ManagedTorrentLocked,
+    only_files: &HashSet<usize>,
+) -> anyhow::Result<()> {
+    // if live, need to update chunk tracker
+    // - if already finished: need to pause, then unpause (to reopen files etc)
+    // if paused, need to update chunk tracker
+    match &mut g.state {
+        ManagedTorrentState::Initializing(_) => bail!("can't update initializing torrent"),
+        ManagedTorrentState::Error(_) => {}
+        ManagedTorrentState::None => {}
+        ManagedTorrentState::Paused(p) => {
+            p.update_only_files(only_files)?;
+        }
+        ManagedTorrentState::Live(l) => {
+            l.update_only_files(only_files)?;
+        }
+    };
+
+    g.only_files = Some(only_files.iter().copied().collect());
+    Ok(())
+}
+
+impl ManagedTorrent {
+    /// True if pieces are downloaded in order, see [`ManagedTorrent::set_sequential`].
+    pub fn is_sequential(&self) -> bool {
+        self.locked.read().sequential
+    }
+
+    /// Download pieces in order instead of the default strategy, which requests the first and
+    /// the last piece of each file first, and orders files by name. Files with
+    /// [`FilePriority::High`] still come first. Takes effect right away on a live torrent.
+    pub fn set_sequential(&self, sequential: bool) {
+        let mut g = self.locked.write();
+        if g.sequential == sequential {
+            return;
+        }
+        g.sequential = sequential;
+        if let ManagedTorrentState::Live(live) = &g.state {
+            live.update_download_order(&g.file_priority_overrides, sequential);
+        }
+    }
+
+    /// True if super-seeding, see [`ManagedTorrent::set_super_seeding`].
+    pub fn is_super_seeding(&self) -> bool {
+        self.locked.read().super_seeding
+    }
+
+    /// Super-seeding (BEP 16) is meant for the initial seed of a torrent. Instead of announcing
+    /// all pieces, each peer is offered one piece at a time, and the next one only after the
+    /// previous one was seen at another peer. Only in effect once the torrent is finished, and
+    /// only for peers connecting after it was turned on. Turning it off tells connected peers
+    /// about all pieces.
+    pub fn set_super_seeding(&self, super_seeding: bool) {
+        let mut g = self.locked.write();
+        if g.super_seeding == super_seeding {
+            return;
+        }
+        g.super_seeding = super_seeding;
+        if let ManagedTorrentState::Live(live) = &g.state {
+            live.set_super_seeding(super_seeding);
+        }
+    }
+
+    /// Priorities of all files. Empty if the metadata isn't resolved yet.
+    pub fn file_priorities(&self) -> Vec<FilePriority> {
+        let file_count = self
+            .metadata
+            .load()
+            .as_ref()
+            .map(|m| m.file_infos.len())
+            .unwrap_or_default();
+        let g = self.locked.read();
+        (0..file_count)
+            .map(|id| {
+                if g.only_files.as_ref().is_some_and(|o| !o.contains(&id)) {
+                    FilePriority::Skip
+                } else {
+                    g.file_priority_overrides
+                        .get(&id)
+                        .copied()
+                        .unwrap_or(FilePriority::Normal)
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn set_file_priority(
+        &self,
+        file_index: usize,
+        prio: FilePriority,
+    ) -> anyhow::Result<()> {
+        let file_count = self
+            .metadata
+            .load()
+            .as_ref()
+            .context("torrent is not resolved")?
+            .file_infos
+            .len();
+        if file_index >= file_count {
+            bail!("invalid file index {file_index}");
+        }
 
+        // The selection and the priority are changed under one lock, so that readers never see
+        // them out of sync.
         let mut g = self.locked.write();
-        match &mut g.state {
-            ManagedTorrentState::Initializing(_) => bail!("can't update initializing torrent"),
-            ManagedTorrentState::Error(_) => {}
-            ManagedTorrentState::None => {}
-            ManagedTorrentState::Paused(p) => {
-                p.update_only_files(only_files)?;
+        let mut only_files: HashSet<usize> = match &g.only_files {
+            Some(o) => o.iter().copied().collect(),
+            None => (0..file_count).collect(),
+        };
+        let only_files_changed = if prio == FilePriority::Skip {
+            only_files.remove(&file_index)
+        } else {
+            only_files.insert(file_index)
+        };
+        if only_files_changed {
+            update_only_files_locked(&mut g, &only_files)?;
+        }
+
+        let prev = match prio {
+            FilePriority::High | FilePriority::Low => {
+                g.file_priority_overrides.insert(file_index, prio)
             }
-            ManagedTorrentState::Live(l) => {
-                l.update_only_files(only_files)?;
+            FilePriority::Skip | FilePriority::Normal => {
+                g.file_priority_overrides.remove(&file_index)
             }
         };
+        let order_changed = prev != g.file_priority_overrides.get(&file_index).copied();
+        if order_changed && let ManagedTorrentState::Live(live) = &g.state {
+            live.update_download_order(&g.file_priority_overrides, g.sequential);
+        }
+        Ok(())
+    }
+
+    /// Select or deselect all files in the folder, given as a path inside the torrent,
+    /// e.g. "season 1/extras". Returns how many files changed selection.
+    pub(crate) fn set_folder_wanted(
+        &self,
+        path_prefix: &str,
+        wanted: bool,
+    ) -> anyhow::Result<usize> {
+        let metadata = self.metadata.load();
+        let metadata = metadata.as_ref().context("torrent is not resolved")?;
+        let prefix = Path::new(path_prefix.trim_matches('/'));
+        // Match whole components, so "a" doesn't match "ab/c".
+        let in_folder = metadata
+            .info
+            .iter_file_details()
+            .enumerate()
+            .filter(|(_, fd)| fd.filename.to_pathbuf().starts_with(prefix))
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        if in_folder.is_empty() {
+            bail!("no files under {path_prefix:?}");
+        }
+
+        let file_count = metadata.file_infos.len();
+        let mut g = self.locked.write();
+        let mut only_files: HashSet<usize> = match &g.only_files {
+            Some(o) => o.iter().copied().collect(),
+            None => (0..file_count).collect(),
+        };
+        let changed = in_folder
+            .into_iter()
+            .filter(|idx| {
+                if wanted {
+                    only_files.insert(*idx)
+                } else {
+                    only_files.remove(idx)
+                }
+            })
+            .count();
+        if changed > 0 {
+            update_only_files_locked(&mut g, &only_files)?;
+        }
+        Ok(changed)
+    }
 
-        g.only_files = Some(only_files.iter().copied().collect());
+    /// Only download the given byte ranges of the file, e.g. the parts of a large archive
+    /// that are needed. Overlapping and adjacent ranges are merged. Pieces only partially
+    /// covered by a range are downloaded in full.
+    ///
+    /// An empty list makes the whole file wanted again. Skipped files stay skipped, see
+    /// [`ManagedTorrent::file_priorities`]. The ranges aren't persisted across restarts.
+    pub fn set_wanted_ranges(
+        &self,
+        file_index: usize,
+        ranges: Vec<Range<u64>>,
+    ) -> anyhow::Result<()> {
+        let metadata = self.metadata.load();
+        let metadata = metadata.as_ref().context("torrent is not resolved")?;
+        let file_count = metadata.file_infos.len();
+        let fi = metadata
+            .file_infos
+            .get(file_index)
+            .with_context(|| format!("invalid file index {file_index}"))?;
+        for r in ranges.iter() {
+            if r.start >= r.end || r.end > fi.len {
+                bail!(
+                    "invalid range {r:?} for file {file_index} of length {}",
+                    fi.len
+                );
+            }
+        }
+
+        let only_files: HashSet<usize> = match &self.locked.read().only_files {
+            Some(o) => o.iter().copied().collect(),
+            None => (0..file_count).collect(),
+        };
+        let prev = {
+            let mut wanted = self.shared.wanted_ranges.write();
+            let prev = wanted.clone();
+            if ranges.is_empty() {
+                wanted.remove(&file_index);
+            } else {
+                wanted.insert(file_index, merge_ranges(ranges));
+            }
+            prev
+        };
+        if let Err(e) = self.update_only_files(&only_files) {
+            *self.shared.wanted_ranges.write() = prev;
+            return Err(e);
+        }
         Ok(())
     }
 }
 
 pub type ManagedTorrentHandle = Arc<ManagedTorrent>;
 
+fn is_storage_missing_error(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<TorrentError>(),
+        Some(TorrentError::StorageMissing(_))
+    )
+}
+
+// Either a write that failed with ENOSPC, or an error already tagged as such.
+pub(crate) fn is_disk_full_error(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<TorrentError>(),
+        Some(TorrentError::DiskFull)
+    ) || e
+        .chain()
+        .filter_map(|e| e.downcast_ref::<std::io::Error>())
+        .any(|e| e.kind() == std::io::ErrorKind::StorageFull)
+}
+
 fn spawn_fatal_errors_receiver(
     state: &Arc<ManagedTorrent>,
     rx: tokio::sync::oneshot::Receiver<anyhow::Error>,
@@ -634,7 +1992,14 @@ fn spawn_fatal_errors_receiver(
                 Err(_) => return Ok(()),
             };
             if let Some(state) = state.upgrade() {
-                state.stop_with_error(e);
+                if is_disk_full_error(&e) {
+                    state.pause_on_disk_full(e).await;
+                    if let Some(session) = state.shared.session.upgrade() {
+                        session.try_update_persistence_metadata(&state).await;
+                    }
+                } else {
+                    state.stop_with_error(e).await;
+                }
             } else {
                 warn!(
                     ?id,
@@ -647,6 +2012,140 @@ fn spawn_fatal_errors_receiver(
     );
 }
 
+fn spawn_on_complete_waiter(state: &Arc<ManagedTorrent>, live: &Arc<TorrentStateLive>) {
+    let state = Arc::downgrade(state);
+    live.spawn(
+        debug_span!(parent: live.torrent().span.clone(), "on_complete_waiter"),
+        format!("[{}]on_complete_waiter", live.shared.id),
+        {
+            let live = Arc::downgrade(live);
+            async move {
+                let Some(l) = live.upgrade() else {
+                    return Ok(());
+                };
+                l.wait_until_completed().await;
+                drop(l);
+                if let Some(state) = state.upgrade() {
+                    state.on_completed();
+                }
+                Ok(())
+            }
+        },
+    );
+}
+
+fn spawn_seed_ratio_watcher(state: &Arc<ManagedTorrent>, live: &Arc<TorrentStateLive>) {
+    let state = Arc::downgrade(state);
+    live.spawn(
+        debug_span!(parent: live.torrent().span.clone(), "seed_ratio_watcher"),
+        format!("[{}]seed_ratio_watcher", live.shared.id),
+        {
+            let live = Arc::downgrade(live);
+            async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(1));
+                loop {
+                    interval.tick().await;
+                    let (Some(state), Some(live)) = (state.upgrade(), live.upgrade()) else {
+                        return Ok(());
+                    };
+                    let Some(limit) = state.seed_ratio_limit() else {
+                        continue;
+                    };
+                    let hns = live.get_hns().unwrap_or_default();
+                    if !hns.finished() || hns.total() == 0 {
+                        continue;
+                    }
+                    let uploaded = state.shared.total_uploaded_bytes.load(Ordering::Relaxed);
+                    let ratio = uploaded as f64 / hns.total() as f64;
+                    if ratio < limit {
+                        continue;
+                    }
+                    drop(live);
+
+                    info!(ratio, limit, "seed ratio limit reached, pausing");
+                    pause_from_watcher(state, StopReason::SeedRatioReached);
+                    return Ok(());
+                }
+            }
+        },
+    );
+}
+
+fn spawn_idle_watcher(
+    state: &Arc<ManagedTorrent>,
+    live: &Arc<TorrentStateLive>,
+    idle_timeout: Duration,
+) {
+    let state = Arc::downgrade(state);
+    live.spawn(
+        debug_span!(parent: live.torrent().span.clone(), "idle_watcher"),
+        format!("[{}]idle_watcher", live.shared.id),
+        {
+            let live = Arc::downgrade(live);
+            async move {
+                // The task is spawned on every start, so idle time is counted from then.
+                let mut last_active = Instant::now();
+                let mut last_have_bytes = None;
+                let mut interval = tokio::time::interval(idle_timeout.min(Duration::from_secs(1)));
+                loop {
+                    interval.tick().await;
+                    let Some(live) = live.upgrade() else {
+                        return Ok(());
+                    };
+                    let have_bytes = live.get_approx_have_bytes();
+                    if live.is_finished()
+                        || live.stats_snapshot().peer_stats.live > 0
+                        || last_have_bytes != Some(have_bytes)
+                    {
+                        last_active = Instant::now();
+                        last_have_bytes = Some(have_bytes);
+                        continue;
+                    }
+                    if last_active.elapsed() < idle_timeout {
+                        continue;
+                    }
+                    drop(live);
+                    let Some(state) = state.upgrade() else {
+                        return Ok(());
+                    };
+
+                    info!(?idle_timeout, "no peers and no progress, pausing");
+                    if pause_from_watcher(state.clone(), StopReason::Idle) {
+                        state.shared.events.emit(TorrentEvent::Idle);
+                    }
+                    return Ok(());
+                }
+            }
+        },
+    );
+}
+
+// Pause the torrent from one of the tasks watching the live state. Pausing cancels the
+// calling task at the next await point, so persistence is updated in a session task.
+// Returns false if pausing failed.
+fn pause_from_watcher(state: Arc<ManagedTorrent>, reason: StopReason) -> bool {
+    if let Err(e) = state.pause() {
+        warn!(?reason, "error pausing torrent: {e:#}");
+        return false;
+    }
+    state.set_last_stop_reason(reason);
+    let Some(session) = state.shared.session.upgrade() else {
+        return true;
+    };
+    session.spawn(
+        debug_span!(parent: state.shared.span.clone(), "stop_reason_persist"),
+        "stop_reason_persist",
+        {
+            let session = session.clone();
+            async move {
+                session.try_update_persistence_metadata(&state).await;
+                Ok(())
+            }
+        },
+    );
+    true
+}
+
 fn spawn_peer_adder(live: &Arc<TorrentStateLive>, mut peer_rx: PeerStream) {
     live.spawn(
         debug_span!(parent: live.torrent().span.clone(), "external_peer_adder"),
@@ -686,3 +2185,43 @@ fn spawn_peer_adder(live: &Arc<TorrentStateLive>, mut peer_rx: PeerStream) {
         },
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use bytes::Bytes;
+    use clone_to_owned::CloneToOwned;
+    use librqbit_core::torrent_metainfo::torrent_from_bytes;
+
+    use super::TorrentMetadata;
+
+    #[test]
+    fn test_torrent_metadata_informational_fields() {
+        let bytes = Bytes::from_static(include_bytes!(
+            "../../resources/ubuntu-21.04-desktop-amd64.iso.torrent"
+        ));
+        let torrent = torrent_from_bytes(&bytes).unwrap();
+        let info = torrent
+            .info
+            .data
+            .clone_to_owned(Some(&bytes))
+            .validate()
+            .unwrap();
+        let info_bytes = Bytes::copy_from_slice(torrent.info.raw_bytes.as_ref());
+
+        let m = TorrentMetadata::new(info.clone(), bytes.clone(), info_bytes.clone()).unwrap();
+        assert_eq!(m.comment(), Some("Ubuntu CD releases.ubuntu.com"));
+        assert_eq!(m.created_by(), Some("mktorrent 1.1"));
+        assert_eq!(
+            m.creation_date(),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1619102605))
+        );
+
+        // Unparseable torrent bytes don't prevent creating the metadata.
+        let m = TorrentMetadata::new(info, Bytes::from_static(b"garbage"), info_bytes).unwrap();
+        assert_eq!(m.comment(), None);
+        assert_eq!(m.created_by(), None);
+        assert_eq!(m.creation_date(), None);
+    }
+}
@@ -0,0 +1,188 @@
+// Moving torrent files on disk, see ManagedTorrent::move_storage() and rename_file().
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use tracing::{debug, warn};
+
+use crate::type_aliases::FileInfos;
+
+// The files that were moved, relative to both folders.
+pub(crate) struct MovedFiles {
+    from: PathBuf,
+    to: PathBuf,
+    to_existed: bool,
+    files: Vec<PathBuf>,
+}
+
+pub(crate) fn move_file(src: &Path, dst: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dst.parent().context("bug: no parent")?)
+        .with_context(|| format!("error creating parent directory of {dst:?}"))?;
+    if let Err(e) = std::fs::rename(src, dst) {
+        // E.g. the folders are on different filesystems.
+        debug!(?src, ?dst, "error renaming, will copy: {e:#}");
+        std::fs::copy(src, dst).with_context(|| format!("error copying {src:?} to {dst:?}"))?;
+        if let Err(e) = std::fs::remove_file(src) {
+            let _ = std::fs::remove_file(dst);
+            return Err(e).with_context(|| format!("error removing {src:?}"));
+        }
+    }
+    Ok(())
+}
+
+// Remove the directories inside root that contained the files, if they are empty now.
+pub(crate) fn remove_empty_dirs(root: &Path, files: &[PathBuf]) {
+    let mut dirs = files
+        .iter()
+        .flat_map(|f| f.ancestors().skip(1))
+        .filter(|d| !d.as_os_str().is_empty())
+        .collect::<Vec<_>>();
+    // Deepest first.
+    dirs.sort_by_key(|d| std::cmp::Reverse(d.components().count()));
+    dirs.dedup();
+    for dir in dirs {
+        // Fails if not empty, which is fine.
+        let _ = std::fs::remove_dir(root.join(dir));
+    }
+}
+
+// Move all existing torrent files from one folder to another. If that fails half-way,
+// the already moved files are moved back.
+pub(crate) fn move_files(
+    file_infos: &FileInfos,
+    from: &Path,
+    to: &Path,
+) -> anyhow::Result<MovedFiles> {
+    // Files that weren't selected might have never been created. Files with absolute paths
+    // aren't in the output folder, so they stay where they are.
+    let files = file_infos
+        .iter()
+        .filter(|fi| !fi.attrs.padding && fi.relative_filename.is_relative())
+        .map(|fi| fi.relative_filename.clone())
+        .filter(|f| from.join(f).exists())
+        .collect::<Vec<_>>();
+    for f in files.iter() {
+        let dst = to.join(f);
+        if dst.exists() {
+            bail!("{dst:?} already exists");
+        }
+    }
+
+    let mut moved = MovedFiles {
+        from: from.to_owned(),
+        to: to.to_owned(),
+        to_existed: to.exists(),
+        files: Vec::with_capacity(files.len()),
+    };
+    for f in files {
+        if let Err(e) = move_file(&from.join(&f), &to.join(&f)) {
+            moved.rollback();
+            return Err(e);
+        }
+        moved.files.push(f);
+    }
+
+    if let Some(missing) = moved.files.iter().find(|f| !to.join(f).is_file()) {
+        let missing = to.join(missing);
+        moved.rollback();
+        bail!("{missing:?} doesn't exist after moving");
+    }
+    Ok(moved)
+}
+
+impl MovedFiles {
+    // Move the files back to where they were.
+    pub fn rollback(&self) {
+        for f in self.files.iter().rev() {
+            if let Err(e) = move_file(&self.to.join(f), &self.from.join(f)) {
+                warn!("error moving {f:?} back to {:?}: {e:#}", self.from);
+            }
+        }
+        remove_empty_dirs(&self.to, &self.files);
+        if !self.to_existed {
+            let _ = std::fs::remove_dir(&self.to);
+        }
+    }
+
+    // Remove the directories left empty in the old location.
+    pub fn cleanup(&self, remove_root: bool) {
+        remove_empty_dirs(&self.from, &self.files);
+        if remove_root {
+            let _ = std::fs::remove_dir(&self.from);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use tempfile::TempDir;
+
+    use crate::file_info::FileInfo;
+
+    use super::move_files;
+
+    fn file_info(name: &str) -> FileInfo {
+        FileInfo {
+            relative_filename: name.into(),
+            offset_in_torrent: 0,
+            len: 4,
+            piece_range: 0..1,
+            attrs: Default::default(),
+        }
+    }
+
+    fn write(path: PathBuf) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"data").unwrap();
+    }
+
+    fn exists(root: &Path, name: &str) -> bool {
+        root.join(name).exists()
+    }
+
+    #[test]
+    fn test_move_files() {
+        let td = TempDir::with_prefix("test_move_files").unwrap();
+        let from = td.path().join("from");
+        let to = td.path().join("to");
+        let file_infos = vec![file_info("a/1"), file_info("a/b/2"), file_info("3")];
+        write(from.join("a/1"));
+        write(from.join("a/b/2"));
+
+        let moved = move_files(&file_infos, &from, &to).unwrap();
+        moved.cleanup(true);
+        assert!(exists(&to, "a/1"));
+        assert!(exists(&to, "a/b/2"));
+        assert!(!exists(&to, "3"));
+        assert!(!from.exists());
+        assert_eq!(std::fs::read(to.join("a/b/2")).unwrap(), b"data");
+    }
+
+    #[test]
+    fn test_move_files_rollback() {
+        let td = TempDir::with_prefix("test_move_files_rollback").unwrap();
+        let from = td.path().join("from");
+        let to = td.path().join("to");
+        let file_infos = vec![file_info("1"), file_info("2")];
+        write(from.join("1"));
+        write(from.join("2"));
+
+        // Destination exists, nothing is moved.
+        write(to.join("2"));
+        assert!(move_files(&file_infos, &from, &to).is_err());
+        assert!(exists(&from, "1"));
+        assert!(!exists(&to, "1"));
+
+        std::fs::remove_file(to.join("2")).unwrap();
+        let moved = move_files(&file_infos, &from, &to).unwrap();
+        assert!(exists(&to, "1"));
+        moved.rollback();
+        assert!(exists(&from, "1"));
+        assert!(exists(&from, "2"));
+        assert!(!exists(&to, "1"));
+        // Existed before moving, so it's kept.
+        assert!(to.exists());
+    }
+}
@@ -1,5 +1,7 @@
 use std::{collections::HashSet, sync::Arc};
 
+use librqbit_core::lengths::ValidPieceIndex;
+
 use crate::{
     chunk_tracker::{ChunkTracker, HaveNeededSelected},
     type_aliases::FileStorage,
@@ -17,8 +19,35 @@ pub struct TorrentStatePaused {
 
 impl TorrentStatePaused {
     pub(crate) fn update_only_files(&mut self, only_files: &HashSet<usize>) -> anyhow::Result<()> {
-        self.chunk_tracker
-            .update_only_files(&self.metadata.file_infos, only_files)?;
+        self.chunk_tracker.update_only_files(
+            &self.metadata.file_infos,
+            only_files,
+            &self.shared.wanted_ranges.read(),
+        )?;
+        Ok(())
+    }
+
+    // Update whether we have the piece after it was checked on disk.
+    pub(crate) fn on_piece_verified(
+        &mut self,
+        id: ValidPieceIndex,
+        ok: bool,
+    ) -> anyhow::Result<()> {
+        let ct = &mut self.chunk_tracker;
+        let have = ct.is_piece_have(id);
+        if ok && !have {
+            ct.mark_piece_verified(id);
+            for (idx, fi) in self.metadata.file_infos.iter().enumerate() {
+                if fi.piece_range.contains(&id.get()) {
+                    ct.update_file_have_on_piece_completed(id, idx, fi);
+                }
+            }
+        } else if !ok && have {
+            ct.mark_piece_not_have(id, &self.metadata.file_infos);
+        } else {
+            return Ok(());
+        }
+        ct.get_have_pieces_mut().flush(false)?;
         Ok(())
     }
 
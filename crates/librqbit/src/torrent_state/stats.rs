@@ -12,6 +12,31 @@ pub struct LiveStats {
     pub download_speed: Speed,
     pub upload_speed: Speed,
     pub time_remaining: Option<DurationWithHumanReadable>,
+    /// Download speed averaged over the last 10 seconds.
+    pub download_speed_bps: f64,
+    /// Upload speed averaged over the last 10 seconds.
+    pub upload_speed_bps: f64,
+    /// Remaining bytes over the 10 second average download speed. None if nothing
+    /// is being downloaded or the torrent is finished.
+    pub eta: Option<Duration>,
+    /// Peers we are connected to.
+    pub connected_peers: u32,
+    /// Outgoing connections that weren't established yet.
+    pub connecting_peers: u32,
+    /// Unique peer addresses seen so far from all sources, connected or not. If this is low,
+    /// peer discovery is the bottleneck, otherwise connectivity is.
+    pub known_peers: u32,
+    /// Swarm size reported by each tracker, refreshed on every announce.
+    pub trackers: Vec<TrackerSwarmStats>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TrackerSwarmStats {
+    pub url: String,
+    pub seeders: u32,
+    pub leechers: u32,
+    /// Completed downloads. None if the tracker doesn't support scrape.
+    pub downloaded: Option<u32>,
 }
 
 impl std::fmt::Display for LiveStats {
@@ -30,21 +55,48 @@ impl From<&TorrentStateLive> for LiveStats {
         let snapshot = live.stats_snapshot();
         let down_estimator = live.down_speed_estimator();
         let up_estimator = live.up_speed_estimator();
+        let down_rolling = live.down_speed_rolling_estimator();
+        let up_rolling = live.up_speed_rolling_estimator();
 
         Self {
             average_piece_download_time: snapshot.average_piece_download_time(),
+            connected_peers: snapshot.peer_stats.live,
+            connecting_peers: snapshot.peer_stats.connecting,
+            known_peers: snapshot.peer_stats.seen,
+            trackers: live
+                .torrent()
+                .tracker_swarm_stats
+                .read()
+                .iter()
+                .map(|(url, s)| TrackerSwarmStats {
+                    url: url.to_string(),
+                    seeders: s.seeders,
+                    leechers: s.leechers,
+                    downloaded: s.downloaded,
+                })
+                .collect(),
             snapshot,
             download_speed: down_estimator.mbps().into(),
             upload_speed: up_estimator.mbps().into(),
             time_remaining: down_estimator
                 .time_remaining()
                 .map(DurationWithHumanReadable),
+            download_speed_bps: down_rolling.bps() as f64,
+            upload_speed_bps: up_rolling.bps() as f64,
+            // The estimator reports no time remaining when either speed or remaining bytes is zero.
+            eta: down_rolling.time_remaining(),
         }
     }
 }
 
 #[derive(Clone, Copy, Serialize, Debug)]
 pub enum TorrentStatsState {
+    /// A magnet link waiting for its metadata from peers, see [`Session::metadata_fetch_stats`](crate::Session::metadata_fetch_stats).
+    #[serde(rename = "fetching-metadata")]
+    FetchingMetadata,
+    /// Added paused with the initial check deferred.
+    #[serde(rename = "queued")]
+    Queued,
     #[serde(rename = "initializing")]
     Initializing,
     #[serde(rename = "live")]
@@ -58,6 +110,8 @@ pub enum TorrentStatsState {
 impl std::fmt::Display for TorrentStatsState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            TorrentStatsState::FetchingMetadata => f.write_str("fetching metadata"),
+            TorrentStatsState::Queued => f.write_str("queued"),
             TorrentStatsState::Initializing => f.write_str("initializing"),
             TorrentStatsState::Live => f.write_str("live"),
             TorrentStatsState::Paused => f.write_str("paused"),
@@ -69,10 +123,20 @@ impl std::fmt::Display for TorrentStatsState {
 #[derive(Serialize, Debug)]
 pub struct TorrentStats {
     pub state: TorrentStatsState,
+    /// See [`ManagedTorrent::display_name`](crate::ManagedTorrent::display_name).
+    pub display_name: String,
     pub file_progress: Vec<u64>,
     pub error: Option<String>,
+    /// The error is [`TorrentError::StorageMissing`](crate::TorrentError::StorageMissing),
+    /// so the torrent can be recovered with [`ManagedTorrent::recover_storage`](crate::ManagedTorrent::recover_storage).
+    pub storage_missing: bool,
+    /// Bytes we have, i.e. downloaded and verified.
     pub progress_bytes: u64,
+    /// Uploaded since the torrent was added to the session, across pauses.
     pub uploaded_bytes: u64,
+    /// Received from peers since the torrent was added to the session, across pauses.
+    /// Unlike progress_bytes this includes pieces that failed the hash check and duplicates.
+    pub downloaded_bytes: u64,
     pub total_bytes: u64,
     pub finished: bool,
     pub live: Option<LiveStats>,
@@ -1,6 +1,7 @@
 use std::{
     collections::VecDeque,
     io::SeekFrom,
+    ops::Range,
     sync::{
         Arc,
         atomic::{AtomicUsize, Ordering},
@@ -9,19 +10,20 @@ use std::{
     time::Instant,
 };
 
-use anyhow::Context;
+use anyhow::{Context, bail};
 use dashmap::DashMap;
+use futures::{FutureExt, future::BoxFuture};
 
 use librqbit_core::lengths::{CurrentPiece, Lengths, ValidPieceIndex};
 use tokio::{
     io::{AsyncRead, AsyncSeek},
-    sync::OwnedSemaphorePermit,
+    sync::{OwnedSemaphorePermit, broadcast::error::RecvError},
 };
-use tracing::{debug, trace};
+use tracing::{debug, debug_span, info, trace, warn};
 
 use crate::{ManagedTorrent, file_info::FileInfo, storage::TorrentStorage};
 
-use super::{ManagedTorrentHandle, TorrentMetadata};
+use super::{ManagedTorrentHandle, StopReason, TorrentEvent, TorrentMetadata};
 
 type StreamId = usize;
 
@@ -55,6 +57,10 @@ impl StreamState {
 pub(crate) struct TorrentStreams {
     next_stream_id: AtomicUsize,
     streams: DashMap<StreamId, StreamState>,
+    // Piece ranges requested through ManagedTorrent::download_prefix(), by file id.
+    prefixes: DashMap<usize, Range<u32>>,
+    // Piece ranges awaited through ManagedTorrent::wait_for_piece() and wait_for_file(), by wait id.
+    waits: DashMap<usize, Range<u32>>,
 }
 
 impl TorrentStreams {
@@ -99,6 +105,25 @@ impl TorrentStreams {
         all.shuffle(&mut rand::rng());
 
         Interleave { all: all.into() }
+            .chain(self.iter_waited_pieces(lengths))
+            .chain(self.iter_prefix_pieces(lengths))
+    }
+
+    // Pieces of all requested prefixes, in order. They go after the streams as nobody is
+    // blocked reading them yet.
+    pub(crate) fn iter_prefix_pieces<'a>(
+        &self,
+        lengths: &'a Lengths,
+    ) -> impl Iterator<Item = ValidPieceIndex> + use<'a> {
+        iter_ranges(&self.prefixes, lengths)
+    }
+
+    // Pieces someone is waiting for, in order.
+    pub(crate) fn iter_waited_pieces<'a>(
+        &self,
+        lengths: &'a Lengths,
+    ) -> impl Iterator<Item = ValidPieceIndex> + use<'a> {
+        iter_ranges(&self.waits, lengths)
     }
 
     pub(crate) fn wake_streams_on_piece_completed(
@@ -130,6 +155,30 @@ impl TorrentStreams {
     }
 }
 
+fn iter_ranges<'a>(
+    ranges: &DashMap<usize, Range<u32>>,
+    lengths: &'a Lengths,
+) -> impl Iterator<Item = ValidPieceIndex> + use<'a> {
+    let mut ranges: Vec<_> = ranges.iter().map(|r| r.value().clone()).collect();
+    ranges.sort_by_key(|r| r.start);
+    ranges
+        .into_iter()
+        .flatten()
+        .filter_map(|i| lengths.validate_piece_index(i))
+}
+
+// Stops prioritizing the awaited pieces when the wait is over or cancelled.
+struct WaitGuard {
+    streams: Arc<TorrentStreams>,
+    id: usize,
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        self.streams.waits.remove(&self.id);
+    }
+}
+
 pub struct FileStream {
     torrent: ManagedTorrentHandle,
     metadata: Arc<TorrentMetadata>,
@@ -334,6 +383,168 @@ impl ManagedTorrent {
             .unwrap_or(false)
     }
 
+    /// Download the first `bytes` of the file before anything else, e.g. to preview it. The
+    /// pieces are requested in order, even if the file isn't selected. If `bytes` is more than
+    /// the file length, the whole file is downloaded.
+    ///
+    /// The returned future resolves once all pieces of the prefix are downloaded and verified.
+    /// [`TorrentEvent::PrefixCompleted`] is emitted at the same time. If `pause_when_done` is set,
+    /// the torrent is paused first.
+    ///
+    /// Calling this again for the same file replaces the previous prefix.
+    pub fn download_prefix(
+        self: &Arc<Self>,
+        file_id: usize,
+        bytes: u64,
+        pause_when_done: bool,
+    ) -> anyhow::Result<BoxFuture<'static, anyhow::Result<()>>> {
+        let metadata = self
+            .metadata
+            .load_full()
+            .context("torrent metadata is not resolved")?;
+        let fi = metadata.file_infos.get(file_id).context("invalid file")?;
+        let pieces = prefix_pieces(metadata.lengths(), fi, bytes);
+        let session = self.shared.session.upgrade().context("session is dead")?;
+        let streams = self.streams()?;
+
+        // Subscribe before registering so that no completed pieces are missed.
+        let mut events = self.subscribe_events();
+        streams.prefixes.insert(file_id, pieces.clone());
+        self.maybe_reconnect_needed_peers_for_file(file_id);
+        debug!(file_id, ?pieces, "downloading prefix");
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let torrent = Arc::downgrade(self);
+        session.spawn(
+            debug_span!(parent: self.shared.span.clone(), "prefix_watcher", file_id),
+            "prefix_watcher",
+            {
+                let session = Arc::downgrade(&session);
+                async move {
+                    loop {
+                        let Some(t) = torrent.upgrade() else {
+                            return Ok(());
+                        };
+                        if t.has_pieces(&pieces) {
+                            break;
+                        }
+                        drop(t);
+                        match events.recv().await {
+                            Ok(TorrentEvent::Removed) | Err(RecvError::Closed) => return Ok(()),
+                            Ok(_) | Err(RecvError::Lagged(_)) => {}
+                        }
+                    }
+
+                    let Some(t) = torrent.upgrade() else {
+                        return Ok(());
+                    };
+                    streams.prefixes.remove_if(&file_id, |_, p| *p == pieces);
+                    if pause_when_done && !t.is_paused() {
+                        info!(file_id, "prefix downloaded, pausing");
+                        match t.pause() {
+                            Ok(()) => {
+                                t.set_last_stop_reason(StopReason::PrefixDownloaded);
+                                if let Some(session) = session.upgrade() {
+                                    session.try_update_persistence_metadata(&t).await;
+                                }
+                            }
+                            Err(e) => warn!(file_id, "error pausing after prefix download: {e:#}"),
+                        }
+                    }
+                    t.shared.events.emit(TorrentEvent::PrefixCompleted(file_id));
+                    let _ = tx.send(());
+                    Ok(())
+                }
+            },
+        );
+
+        Ok(async move {
+            rx.await.map_err(|_| {
+                anyhow::anyhow!("torrent was removed before the prefix was downloaded")
+            })
+        }
+        .boxed())
+    }
+
+    fn has_pieces(&self, pieces: &Range<u32>) -> bool {
+        self.with_chunk_tracker(|ct| {
+            ct.get_have_pieces()
+                .as_slice()
+                .get(pieces.start as usize..pieces.end as usize)
+                .is_some_and(|s| s.all())
+        })
+        .unwrap_or(false)
+    }
+
+    /// Wait until the piece is downloaded and verified. The piece is prioritized while waiting,
+    /// even if it's not in a selected file.
+    ///
+    /// Fails if the torrent errors out or is removed before that.
+    pub async fn wait_for_piece(&self, piece: u32) -> anyhow::Result<()> {
+        let metadata = self
+            .metadata
+            .load_full()
+            .context("torrent metadata is not resolved")?;
+        metadata
+            .lengths()
+            .validate_piece_index(piece)
+            .context("invalid piece")?;
+        self.wait_for_pieces(piece..piece + 1).await
+    }
+
+    /// Wait until all pieces of the file are downloaded and verified, see [`Self::wait_for_piece`].
+    pub async fn wait_for_file(&self, file_index: usize) -> anyhow::Result<()> {
+        let metadata = self
+            .metadata
+            .load_full()
+            .context("torrent metadata is not resolved")?;
+        let fi = metadata
+            .file_infos
+            .get(file_index)
+            .context("invalid file")?;
+        self.wait_for_pieces(fi.piece_range.clone()).await
+    }
+
+    async fn wait_for_pieces(&self, pieces: Range<u32>) -> anyhow::Result<()> {
+        // Subscribe before checking so that no completed pieces are missed.
+        let mut events = self.subscribe_events();
+        if self.has_pieces(&pieces) {
+            return Ok(());
+        }
+        self.wait_until_initialized().await?;
+
+        let streams = self.streams()?;
+        let id = streams.next_id();
+        streams.waits.insert(id, pieces.clone());
+        let _guard = WaitGuard { streams, id };
+        self.with_state(|state| {
+            if let crate::ManagedTorrentState::Live(l) = &state {
+                l.reconnect_all_not_needed_peers();
+                l.new_pieces_notify.notify_waiters();
+            }
+        });
+
+        loop {
+            let error = self.with_state(|s| match s {
+                crate::ManagedTorrentState::Error(e) => Some(format!("{e:#}")),
+                _ => None,
+            });
+            if let Some(e) = error {
+                bail!("torrent errored: {e}");
+            }
+            if self.has_pieces(&pieces) {
+                return Ok(());
+            }
+            match events.recv().await {
+                Ok(TorrentEvent::Removed) | Err(RecvError::Closed) => {
+                    bail!("torrent was removed")
+                }
+                Ok(TorrentEvent::Error(e)) => bail!("torrent errored: {e}"),
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+            }
+        }
+    }
+
     pub async fn stream(self: Arc<Self>, file_id: usize) -> anyhow::Result<FileStream> {
         let metadata = self
             .metadata
@@ -376,6 +587,47 @@ impl ManagedTorrent {
     }
 }
 
+// Pieces covering the first "bytes" of the file.
+fn prefix_pieces(lengths: &Lengths, fi: &FileInfo, bytes: u64) -> Range<u32> {
+    let bytes = bytes.min(fi.len);
+    if bytes == 0 {
+        return 0..0;
+    }
+    let piece_len = lengths.default_piece_length() as u64;
+    let start = fi.offset_in_torrent / piece_len;
+    let end = (fi.offset_in_torrent + bytes).div_ceil(piece_len);
+    start.try_into().unwrap()..end.try_into().unwrap()
+}
+
+/// Reader over a single file of the torrent, see [`ManagedTorrent::file_reader`].
+pub type FileReader = FileStream;
+
+impl ManagedTorrent {
+    /// Read a file of the torrent, e.g. to serve it over HTTP. Reads of pieces the torrent
+    /// doesn't have yet wait for them to download, and those pieces are prioritized.
+    ///
+    /// Fails if there's no such file or it's not selected for download.
+    pub async fn file_reader(self: Arc<Self>, file_index: usize) -> anyhow::Result<FileReader> {
+        let files = self
+            .metadata
+            .load()
+            .as_ref()
+            .context("torrent metadata is not resolved")?
+            .file_infos
+            .len();
+        if file_index >= files {
+            bail!("invalid file index {file_index}, the torrent has {files} files");
+        }
+        if self
+            .only_files()
+            .is_some_and(|only_files| !only_files.contains(&file_index))
+        {
+            bail!("file {file_index} is not selected for download");
+        }
+        self.stream(file_index).await
+    }
+}
+
 impl FileStream {
     pub fn position(&self) -> u64 {
         self.position
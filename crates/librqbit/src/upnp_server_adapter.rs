@@ -6,7 +6,10 @@ use std::{
     sync::Arc,
 };
 
-use crate::{ManagedTorrentShared, Session, session::TorrentId, torrent_state::TorrentMetadata};
+use crate::{
+    ManagedTorrentShared, ManagedTorrentStateKind, Session, TorrentEvent, session::TorrentId,
+    torrent_state::TorrentMetadata,
+};
 
 #[derive(Clone)]
 pub struct UpnpServerSessionAdapter {
@@ -17,12 +20,14 @@ use anyhow::Context;
 use buffers::ByteBufOwned;
 use itertools::Itertools;
 use librqbit_core::torrent_metainfo::ValidatedTorrentMetaV1Info;
-use tracing::{debug, trace, warn};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, debug_span, trace, warn};
 use upnp_serve::{
     UpnpServer, UpnpServerOptions,
     services::content_directory::{
         ContentDirectoryBrowseProvider,
         browse::response::{Container, Item, ItemOrContainer},
+        search::SearchCriteria,
     },
 };
 
@@ -346,6 +351,27 @@ impl ContentDirectoryBrowseProvider for UpnpServerSessionAdapter {
     fn browse_metadata(&self, object_id: usize, http_hostname: &str) -> Vec<ItemOrContainer> {
         self.build_impl(object_id, http_hostname, true)
     }
+
+    fn search(
+        &self,
+        container_id: usize,
+        criteria: &SearchCriteria,
+        http_hostname: &str,
+    ) -> anyhow::Result<Vec<ItemOrContainer>> {
+        let mut result = Vec::new();
+        let mut queue = vec![container_id];
+        while let Some(id) = queue.pop() {
+            for child in self.build_impl(id, http_hostname, false) {
+                if let ItemOrContainer::Container(c) = &child {
+                    queue.push(c.id);
+                }
+                if criteria.matches(&child) {
+                    result.push(child);
+                }
+            }
+        }
+        Ok(result)
+    }
 }
 
 impl Session {
@@ -353,8 +379,9 @@ impl Session {
         self: &Arc<Self>,
         friendly_name: String,
         http_listen_port: u16,
+        enable_ipv6: bool,
     ) -> anyhow::Result<UpnpServer> {
-        UpnpServer::new(UpnpServerOptions {
+        let server = UpnpServer::new(UpnpServerOptions {
             friendly_name,
             http_listen_port,
             http_prefix: "/upnp".to_owned(),
@@ -362,9 +389,40 @@ impl Session {
                 session: self.clone(),
             }),
             cancellation_token: self.cancellation_token().child_token(),
+            enable_ipv6,
+            server_string: None,
+            manufacturer: None,
+            model_name: None,
+            model_number: None,
+            model_description: None,
         })
         .await
-        .context("error creating upnp adapter")
+        .context("error creating upnp adapter")?;
+
+        // Let subscribed renderers know when the list of torrents or their files changes.
+        let handle = server.handle();
+        let mut events = self.subscribe_events();
+        self.spawn(
+            debug_span!(parent: self.rs(), "upnp_content_change_notifier"),
+            "upnp_content_change_notifier",
+            async move {
+                loop {
+                    match events.recv().await {
+                        Ok((
+                            _,
+                            TorrentEvent::StateChanged(ManagedTorrentStateKind::Initializing)
+                            | TorrentEvent::Completed
+                            | TorrentEvent::Removed,
+                        ))
+                        | Err(RecvError::Lagged(_)) => handle.notify_content_changed(),
+                        Ok(_) => {}
+                        Err(RecvError::Closed) => return Ok(()),
+                    }
+                }
+            },
+        );
+
+        Ok(server)
     }
 }
 
@@ -417,6 +475,7 @@ mod tests {
                     sha1: None,
                     symlink_path: None,
                     private: false,
+                    meta_version: None,
                 },
                 raw_bytes: Default::default(),
             },
@@ -426,7 +485,9 @@ mod tests {
             publisher: None,
             publisher_url: None,
             creation_date: None,
+            url_list: Vec::new(),
             info_hash: Id20::default(),
+            info_hash_v2: None,
         }
     }
 
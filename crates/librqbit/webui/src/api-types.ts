@@ -26,6 +26,10 @@ export interface TorrentDetails {
   files: Array<TorrentFile>;
   total_pieces?: number;
   output_folder: string;
+  comment?: string;
+  created_by?: string;
+  // Seconds since UNIX epoch.
+  creation_date?: number;
 }
 
 // Interface for torrent list item (from bulk /torrents?with_stats=true endpoint)
@@ -61,7 +65,9 @@ export interface AggregatePeerStats {
   live: number;
   seen: number;
   dead: number;
+  reconnecting: number;
   not_needed: number;
+  banned: number;
 }
 
 export type ConnectionKind = "tcp" | "utp" | "socks";
@@ -79,12 +85,14 @@ export interface PeerCounters {
   total_piece_download_ms: number;
   times_stolen_from_me: number;
   times_i_stole: number;
+  hash_failed_pieces: number;
 }
 
 export interface PeerStats {
   counters: PeerCounters;
   state: string;
   conn_kind: ConnectionKind | null;
+  unchoked: boolean;
 }
 
 export interface PeerStatsSnapshot {
@@ -139,6 +147,8 @@ export interface LiveTorrentStats {
     remaining_bytes: number;
     total_bytes: number;
     total_piece_download_ms: number;
+    pending_writes: number;
+    pending_write_bytes: number;
     peer_stats: AggregatePeerStats;
   };
   average_piece_download_time: {
@@ -157,18 +167,46 @@ export interface LiveTorrentStats {
       secs: number;
     };
   } | null;
+  download_speed_bps: number;
+  upload_speed_bps: number;
+  eta: {
+    secs: number;
+    nanos: number;
+  } | null;
+  connected_peers: number;
+  connecting_peers: number;
+  known_peers: number;
+  trackers: TrackerSwarmStats[];
 }
 
+export interface TrackerSwarmStats {
+  url: string;
+  seeders: number;
+  leechers: number;
+  downloaded: number | null;
+}
+
+export const STATE_QUEUED = "queued";
 export const STATE_INITIALIZING = "initializing";
 export const STATE_PAUSED = "paused";
 export const STATE_LIVE = "live";
 export const STATE_ERROR = "error";
 
 export interface TorrentStats {
-  state: "initializing" | "paused" | "live" | "error";
+  state:
+    | "fetching-metadata"
+    | "queued"
+    | "initializing"
+    | "paused"
+    | "live"
+    | "error";
   error: string | null;
+  display_name: string;
+  storage_missing: boolean;
   file_progress: number[];
   progress_bytes: number;
+  uploaded_bytes: number;
+  downloaded_bytes: number;
   finished: boolean;
   total_bytes: number;
   live: LiveTorrentStats | null;
@@ -197,6 +235,8 @@ export interface AddTorrentOptions {
   only_files?: number[] | null;
   overwrite?: boolean;
   list_only?: boolean;
+  allocation?: "sparse" | "full";
+  metadata_only?: boolean;
   output_folder?: string | null;
   sub_folder?: string | null;
   peer_opts?: PeerConnectionOptions | null;
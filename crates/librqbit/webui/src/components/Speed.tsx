@@ -1,5 +1,6 @@
 import {
   TorrentStats,
+  STATE_QUEUED,
   STATE_INITIALIZING,
   STATE_PAUSED,
   STATE_ERROR,
@@ -12,6 +13,8 @@ export const Speed: React.FC<{ statsResponse: TorrentStats }> = ({
   switch (statsResponse.state) {
     case STATE_PAUSED:
       return <span className="text-secondary">Paused</span>;
+    case STATE_QUEUED:
+      return <span className="text-secondary">Queued</span>;
     case STATE_INITIALIZING:
       return <span className="text-warning">Checking files</span>;
     case STATE_ERROR:
@@ -129,6 +129,9 @@ export const API: RqbitAPI & { getVersion: () => Promise<string> } = {
     if (opts?.list_only) {
       url += "&list_only=true";
     }
+    if (opts?.metadata_only) {
+      url += "&metadata_only=true";
+    }
     if (opts?.only_files != null) {
       url += `&only_files=${opts.only_files.join(",")}`;
     }
@@ -219,6 +219,17 @@ function generateLiveStats(
   const etaSecs =
     downloadBytesPerSec > 0 ? remainingBytes / downloadBytesPerSec : null;
 
+  const peerStats = {
+    queued: Math.floor(rand() * 50),
+    connecting: Math.floor(rand() * 10),
+    live: Math.floor(rand() * 30) + 1,
+    seen: Math.floor(rand() * 200),
+    dead: Math.floor(rand() * 100),
+    reconnecting: Math.floor(rand() * 50),
+    not_needed: Math.floor(rand() * 20),
+    banned: 0,
+  };
+
   return {
     snapshot: {
       have_bytes: progressBytes,
@@ -232,14 +243,9 @@ function generateLiveStats(
       remaining_bytes: remainingBytes,
       total_bytes: totalBytes,
       total_piece_download_ms: Math.floor(rand() * 100000),
-      peer_stats: {
-        queued: Math.floor(rand() * 50),
-        connecting: Math.floor(rand() * 10),
-        live: Math.floor(rand() * 30) + 1,
-        seen: Math.floor(rand() * 200),
-        dead: Math.floor(rand() * 100),
-        not_needed: Math.floor(rand() * 20),
-      },
+      pending_writes: 0,
+      pending_write_bytes: 0,
+      peer_stats: peerStats,
     },
     average_piece_download_time: {
       secs: Math.floor(rand() * 2),
@@ -269,6 +275,13 @@ function generateLiveStats(
             duration: { secs: Math.floor(etaSecs) },
           }
         : null,
+    download_speed_bps: downloadSpeed * 1024 * 1024,
+    upload_speed_bps: uploadSpeed * 1024 * 1024,
+    eta: etaSecs !== null ? { secs: Math.floor(etaSecs), nanos: 0 } : null,
+    connected_peers: peerStats.live,
+    connecting_peers: peerStats.connecting,
+    known_peers: peerStats.seen,
+    trackers: [],
   };
 }
 
@@ -290,6 +303,8 @@ function generateTorrentStats(id: number): TorrentStats {
     error: state === "error" ? "Connection timed out" : null,
     file_progress: fileProgress,
     progress_bytes: progressBytes,
+    uploaded_bytes: Math.floor(rand() * progressBytes * 0.5),
+    downloaded_bytes: progressBytes,
     finished,
     total_bytes: totalBytes,
     live:
@@ -473,9 +488,11 @@ export const MockAPI: RqbitAPI & { getVersion: () => Promise<string> } = {
           total_piece_download_ms: Math.floor(rand() * 50000) + 5000,
           times_stolen_from_me: 0,
           times_i_stole: 0,
+          hash_failed_pieces: 0,
         },
         state: "live",
         conn_kind: peer.connKind,
+        unchoked: true,
       };
     }
 
@@ -501,7 +518,9 @@ export const MockAPI: RqbitAPI & { getVersion: () => Promise<string> } = {
         live: Math.floor(Math.random() * 300) + 50,
         seen: Math.floor(Math.random() * 2000),
         dead: Math.floor(Math.random() * 500),
+        reconnecting: Math.floor(Math.random() * 250),
         not_needed: Math.floor(Math.random() * 200),
+        banned: Math.floor(Math.random() * 3),
       },
       connections: {
         tcp: {
@@ -54,4 +54,8 @@ pub enum Error {
     V2InvalidTorrent,
     #[error("v2 hybrid file list mismatch: {0}")]
     V2HybridFileListMismatch(String),
+    #[error("invalid v2 metadata: {0}")]
+    V2BadMetadata(String),
+    #[error("v2-only torrents are not supported, only v1 and hybrid ones")]
+    V2OnlyNotSupported,
 }
@@ -6,9 +6,12 @@ pub mod hash_id;
 pub mod lengths;
 pub mod magnet;
 pub mod peer_id;
+pub mod socks5_udp;
 pub mod spawn_utils;
 pub mod speed_estimator;
 pub mod torrent_metainfo;
+#[cfg(any(feature = "sha1-ring", feature = "sha1-crypto-hash"))]
+pub mod v2;
 pub use hash_id::{Id20, Id32};
 
 pub use error::Error;
@@ -1,6 +1,7 @@
-use std::str::FromStr;
+use std::{net::SocketAddr, str::FromStr};
 
 use anyhow::Context;
+use tracing::warn;
 
 use crate::hash_id::{Id20, Id32};
 
@@ -10,6 +11,10 @@ pub struct Magnet {
     id32: Option<Id32>,
     pub trackers: Vec<String>,
     pub name: Option<String>,
+    /// Peer address hints from "x.pe" parameters.
+    pub peers: Vec<SocketAddr>,
+    /// BEP 19 web seed URLs from "ws" parameters.
+    pub web_seeds: Vec<String>,
     select_only: Option<Vec<usize>>,
 }
 
@@ -37,6 +42,8 @@ impl Magnet {
             id32: None,
             trackers,
             name: None,
+            peers: Vec::new(),
+            web_seeds: Vec::new(),
             select_only,
         }
     }
@@ -55,6 +62,8 @@ impl Magnet {
             id32,
             trackers,
             name: None,
+            peers: Vec::new(),
+            web_seeds: Vec::new(),
             select_only,
         })
     }
@@ -69,6 +78,8 @@ impl Magnet {
                 id32: None,
                 name: None,
                 trackers: vec![],
+                peers: vec![],
+                web_seeds: vec![],
                 select_only: None,
             });
         }
@@ -81,6 +92,8 @@ impl Magnet {
         let mut id32: Option<Id32> = None;
         let mut name: Option<String> = None;
         let mut trackers = Vec::<String>::new();
+        let mut peers = Vec::<SocketAddr>::new();
+        let mut web_seeds = Vec::<String>::new();
         let mut files = Vec::<usize>::new();
         for (key, value) in url.query_pairs() {
             match key.as_ref() {
@@ -98,10 +111,14 @@ impl Magnet {
                     }
                 }
                 "tr" => trackers.push(value.into()),
-                "dn" => {
-                    if !value.is_empty() {
-                        name = Some(value.into_owned())
-                    }
+                "dn" if !value.is_empty() => name = Some(value.into_owned()),
+                "x.pe" => match parse_peer_hint(&value) {
+                    Some(addr) if !peers.contains(&addr) => peers.push(addr),
+                    Some(_) => {}
+                    None => warn!(peer = %value, "ignoring invalid x.pe peer address in magnet"),
+                },
+                "ws" if !value.is_empty() && !web_seeds.iter().any(|w| w == value.as_ref()) => {
+                    web_seeds.push(value.into_owned())
                 }
                 "so" => {
                     // Process 'so' values, but silently ignore any which fail parsing
@@ -134,6 +151,8 @@ impl Magnet {
                 id32,
                 trackers,
                 name,
+                peers,
+                web_seeds,
                 select_only: if files.is_empty() { None } else { Some(files) },
             }),
             false => {
@@ -143,6 +162,21 @@ impl Magnet {
     }
 }
 
+// Only IP addresses are supported, as resolving hostnames would need async DNS lookups.
+fn parse_peer_hint(value: &str) -> Option<SocketAddr> {
+    let addr = SocketAddr::from_str(value).ok()?;
+    if addr.port() == 0 || addr.ip().is_unspecified() {
+        return None;
+    }
+    Some(addr)
+}
+
+// Percent-encode a query value, so that e.g. trackers with their own query string survive a
+// round trip.
+fn encode_query_value(v: &str) -> String {
+    url::form_urlencoded::byte_serialize(v.as_bytes()).collect()
+}
+
 impl std::fmt::Display for Magnet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "magnet:")?;
@@ -166,9 +200,23 @@ impl std::fmt::Display for Magnet {
             write_ampersand(f)?;
             write!(f, "xt=urn:btmh:1220{}", id32.as_string(),)?;
         }
+        if let Some(name) = self.name.as_deref()
+            && !name.is_empty()
+        {
+            write_ampersand(f)?;
+            write!(f, "dn={}", encode_query_value(name))?;
+        }
         for tracker in self.trackers.iter() {
             write_ampersand(f)?;
-            write!(f, "tr={tracker}")?;
+            write!(f, "tr={}", encode_query_value(tracker))?;
+        }
+        for peer in self.peers.iter() {
+            write_ampersand(f)?;
+            write!(f, "x.pe={peer}")?;
+        }
+        for web_seed in self.web_seeds.iter() {
+            write_ampersand(f)?;
+            write!(f, "ws={}", encode_query_value(web_seed))?;
         }
         if let Some(select_only) = &self.select_only
             && !select_only.is_empty()
@@ -232,4 +280,69 @@ mod tests {
             "magnet:?xt=urn:btih:a621779b5e3d486e127c3efbca9b6f8d135f52e5&so=1,2,3"
         );
     }
+
+    #[test]
+    fn test_magnet_to_string_roundtrip() {
+        let id20 = Id20::from_str("a621779b5e3d486e127c3efbca9b6f8d135f52e5").unwrap();
+        let mut m = Magnet::from_id20(
+            id20,
+            vec!["http://tracker.example/announce?passkey=a&b=c".to_string()],
+            None,
+        );
+        m.name = Some("Some name & more".to_string());
+        let s = m.to_string();
+        assert_eq!(
+            s,
+            "magnet:?xt=urn:btih:a621779b5e3d486e127c3efbca9b6f8d135f52e5\
+             &dn=Some+name+%26+more\
+             &tr=http%3A%2F%2Ftracker.example%2Fannounce%3Fpasskey%3Da%26b%3Dc"
+        );
+        let parsed = Magnet::parse(&s).unwrap();
+        assert_eq!(parsed.name, m.name);
+        assert_eq!(parsed.trackers, m.trackers);
+    }
+
+    #[test]
+    fn test_parse_magnet_peer_hints() {
+        let m = Magnet::parse(
+            "magnet:?xt=urn:btih:a621779b5e3d486e127c3efbca9b6f8d135f52e5\
+             &x.pe=1.2.3.4:6881&x.pe=[::1]:6882&x.pe=1.2.3.4:6881\
+             &x.pe=example.com:6881&x.pe=1.2.3.4&x.pe=0.0.0.0:1&x.pe=1.2.3.4:0",
+        )
+        .unwrap();
+        assert_eq!(
+            m.peers,
+            vec![
+                "1.2.3.4:6881".parse().unwrap(),
+                "[::1]:6882".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            m.to_string(),
+            "magnet:?xt=urn:btih:a621779b5e3d486e127c3efbca9b6f8d135f52e5&x.pe=1.2.3.4:6881&x.pe=[::1]:6882"
+        );
+    }
+
+    #[test]
+    fn test_parse_magnet_web_seeds() {
+        let m = Magnet::parse(
+            "magnet:?xt=urn:btih:a621779b5e3d486e127c3efbca9b6f8d135f52e5\
+             &ws=http%3A%2F%2Fexample.com%2Ffiles%2F&ws=&ws=http%3A%2F%2Fexample.com%2Ffiles%2F\
+             &ws=https%3A%2F%2Fmirror.example.org%2Fa.iso\
+             &ws=https%3A%2F%2Fmirror.example.org%2Fget%3Fa%3D1%26b%3D2",
+        )
+        .unwrap();
+        assert_eq!(
+            m.web_seeds,
+            vec![
+                "http://example.com/files/".to_owned(),
+                "https://mirror.example.org/a.iso".to_owned(),
+                "https://mirror.example.org/get?a=1&b=2".to_owned(),
+            ]
+        );
+        assert_eq!(
+            Magnet::parse(&m.to_string()).unwrap().web_seeds,
+            m.web_seeds
+        );
+    }
 }
@@ -0,0 +1,392 @@
+// UDP through a SOCKS5 proxy with the UDP ASSOCIATE command (RFC 1928).
+//
+// A TCP connection to the proxy sets up a UDP relay, which lives as long as that connection.
+// Datagrams are sent to the relay with a header holding the real destination, and come back from
+// it with a header holding the real source. If the proxy closes the connection, the relay is set
+// up again in the background.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use parking_lot::RwLock;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::{debug, debug_span, warn};
+
+use crate::spawn_utils::spawn_with_cancel;
+
+const SOCKS_VERSION: u8 = 5;
+const AUTH_NONE: u8 = 0x00;
+const AUTH_USERNAME_PASSWORD: u8 = 0x02;
+const AUTH_USERNAME_PASSWORD_VERSION: u8 = 1;
+const CMD_UDP_ASSOCIATE: u8 = 0x03;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+const ASSOCIATE_TIMEOUT: Duration = Duration::from_secs(10);
+const MIN_REASSOCIATE_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_REASSOCIATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where to send a datagram through the relay. Hostnames are resolved by the proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayDst<'a> {
+    Addr(SocketAddr),
+    Host(&'a str, u16),
+}
+
+impl From<SocketAddr> for RelayDst<'_> {
+    fn from(addr: SocketAddr) -> Self {
+        RelayDst::Addr(addr)
+    }
+}
+
+struct ProxyConfig {
+    host: String,
+    port: u16,
+    username_password: Option<(String, String)>,
+}
+
+impl ProxyConfig {
+    async fn associate(&self) -> anyhow::Result<(TcpStream, SocketAddr)> {
+        tokio::time::timeout(ASSOCIATE_TIMEOUT, self.associate_no_timeout())
+            .await
+            .with_context(|| {
+                format!(
+                    "timeout setting up UDP relay at {}:{} after {ASSOCIATE_TIMEOUT:?}",
+                    self.host, self.port
+                )
+            })?
+    }
+
+    async fn associate_no_timeout(&self) -> anyhow::Result<(TcpStream, SocketAddr)> {
+        let (proxy_host, proxy_port) = (self.host.as_str(), self.port);
+        let mut control = TcpStream::connect((proxy_host, proxy_port))
+            .await
+            .with_context(|| format!("error connecting to {proxy_host}:{proxy_port}"))?;
+        let proxy_ip = control.peer_addr()?.ip();
+
+        let method = if self.username_password.is_some() {
+            AUTH_USERNAME_PASSWORD
+        } else {
+            AUTH_NONE
+        };
+        control.write_all(&[SOCKS_VERSION, 1, method]).await?;
+        let mut reply = [0u8; 2];
+        control.read_exact(&mut reply).await?;
+        if reply[0] != SOCKS_VERSION {
+            bail!("unexpected SOCKS version {}", reply[0]);
+        }
+        if reply[1] != method {
+            bail!("proxy doesn't accept the authentication method");
+        }
+
+        if let Some((username, password)) = self.username_password.as_ref() {
+            let mut req = vec![AUTH_USERNAME_PASSWORD_VERSION];
+            for s in [username, password] {
+                let len: u8 = s
+                    .len()
+                    .try_into()
+                    .context("username or password too long")?;
+                req.push(len);
+                req.extend_from_slice(s.as_bytes());
+            }
+            control.write_all(&req).await?;
+            control.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                bail!("proxy rejected the username or password");
+            }
+        }
+
+        // We don't know which address the datagrams will come from, so it's left unspecified.
+        let mut req = vec![SOCKS_VERSION, CMD_UDP_ASSOCIATE, 0, ATYP_IPV4];
+        req.extend_from_slice(&[0; 6]);
+        control.write_all(&req).await?;
+        let mut header = [0u8; 4];
+        control.read_exact(&mut header).await?;
+        if header[1] != 0 {
+            bail!("proxy refused UDP ASSOCIATE, reply code {}", header[1]);
+        }
+        let ip: IpAddr = match header[3] {
+            ATYP_IPV4 => {
+                let mut ip = [0u8; 4];
+                control.read_exact(&mut ip).await?;
+                Ipv4Addr::from(ip).into()
+            }
+            ATYP_IPV6 => {
+                let mut ip = [0u8; 16];
+                control.read_exact(&mut ip).await?;
+                Ipv6Addr::from(ip).into()
+            }
+            t => bail!("unsupported relay address type {t}"),
+        };
+        let port = control.read_u16().await?;
+        // Proxies often reply with an unspecified address, which means their own.
+        let ip = if ip.is_unspecified() { proxy_ip } else { ip };
+
+        Ok((control, SocketAddr::new(ip, port)))
+    }
+
+    // The proxy never sends anything on the control connection, so it's only read to notice when
+    // it's closed. The relay is gone then, so set up a new one.
+    async fn watch(
+        self,
+        mut control: TcpStream,
+        relay_addr: Arc<RwLock<SocketAddr>>,
+    ) -> anyhow::Result<()> {
+        let mut buf = [0u8; 64];
+        loop {
+            match control.read(&mut buf).await {
+                Ok(0) => warn!("SOCKS5 proxy closed the UDP relay control connection"),
+                Ok(_) => continue,
+                Err(e) => warn!("error reading the UDP relay control connection: {e:#}"),
+            }
+
+            let mut interval = MIN_REASSOCIATE_INTERVAL;
+            control = loop {
+                tokio::time::sleep(interval).await;
+                match self.associate().await {
+                    Ok((control, addr)) => {
+                        debug!(relay = ?addr, "set up the SOCKS5 UDP relay again");
+                        *relay_addr.write() = addr;
+                        break control;
+                    }
+                    Err(e) => {
+                        warn!("error setting up the SOCKS5 UDP relay again: {e:#}");
+                        interval = (interval * 2).min(MAX_REASSOCIATE_INTERVAL);
+                    }
+                }
+            };
+        }
+    }
+}
+
+pub struct Socks5UdpRelay {
+    relay_addr: Arc<RwLock<SocketAddr>>,
+    // Stops watching the control connection, which closes it, and the proxy tears the relay down.
+    _watcher: DropGuard,
+}
+
+impl Socks5UdpRelay {
+    pub async fn associate(
+        proxy_host: &str,
+        proxy_port: u16,
+        username_password: Option<(&str, &str)>,
+    ) -> anyhow::Result<Self> {
+        let config = ProxyConfig {
+            host: proxy_host.to_owned(),
+            port: proxy_port,
+            username_password: username_password.map(|(u, p)| (u.to_owned(), p.to_owned())),
+        };
+        let (control, addr) = config.associate().await?;
+        let relay_addr = Arc::new(RwLock::new(addr));
+        let cancel_token = CancellationToken::new();
+        spawn_with_cancel(
+            debug_span!("socks5_udp_relay", proxy_host, proxy_port),
+            "socks5_udp_relay",
+            cancel_token.clone(),
+            config.watch(control, relay_addr.clone()),
+        );
+        Ok(Self {
+            relay_addr,
+            _watcher: cancel_token.drop_guard(),
+        })
+    }
+
+    pub fn relay_addr(&self) -> SocketAddr {
+        *self.relay_addr.read()
+    }
+
+    pub fn is_relay(&self, addr: SocketAddr) -> bool {
+        let relay_addr = self.relay_addr();
+        addr.port() == relay_addr.port()
+            && addr.ip().to_canonical() == relay_addr.ip().to_canonical()
+    }
+
+    /// Wrap a datagram to `dst` to be sent to the relay.
+    pub fn encapsulate<'a>(
+        dst: impl Into<RelayDst<'a>>,
+        payload: &[u8],
+        out: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        out.clear();
+        // Reserved, and the fragment number, which we don't use.
+        out.extend_from_slice(&[0, 0, 0]);
+        let port = match dst.into() {
+            RelayDst::Addr(addr) => {
+                match addr.ip().to_canonical() {
+                    IpAddr::V4(ip) => {
+                        out.push(ATYP_IPV4);
+                        out.extend_from_slice(&ip.octets());
+                    }
+                    IpAddr::V6(ip) => {
+                        out.push(ATYP_IPV6);
+                        out.extend_from_slice(&ip.octets());
+                    }
+                }
+                addr.port()
+            }
+            RelayDst::Host(host, port) => {
+                let len: u8 = host.len().try_into().map_err(|_| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "hostname too long")
+                })?;
+                out.push(ATYP_DOMAIN);
+                out.push(len);
+                out.extend_from_slice(host.as_bytes());
+                port
+            }
+        };
+        out.extend_from_slice(&port.to_be_bytes());
+        out.extend_from_slice(payload);
+        Ok(())
+    }
+
+    /// Unwrap a datagram received from the relay into its real source and payload.
+    pub fn decapsulate(buf: &[u8]) -> anyhow::Result<(SocketAddr, &[u8])> {
+        let (header, rest) = buf.split_first_chunk::<4>().context("datagram too short")?;
+        if header[2] != 0 {
+            bail!("fragmented datagrams aren't supported");
+        }
+        let (ip, rest): (IpAddr, _) = match header[3] {
+            ATYP_IPV4 => {
+                let (ip, rest) = rest
+                    .split_first_chunk::<4>()
+                    .context("datagram too short")?;
+                (Ipv4Addr::from(*ip).into(), rest)
+            }
+            ATYP_IPV6 => {
+                let (ip, rest) = rest
+                    .split_first_chunk::<16>()
+                    .context("datagram too short")?;
+                (Ipv6Addr::from(*ip).into(), rest)
+            }
+            t => bail!("unsupported address type {t}"),
+        };
+        let (port, payload) = rest
+            .split_first_chunk::<2>()
+            .context("datagram too short")?;
+        Ok((SocketAddr::new(ip, u16::from_be_bytes(*port)), payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        time::Duration,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::{RelayDst, Socks5UdpRelay};
+
+    #[test]
+    fn test_encapsulate_decapsulate() {
+        let mut buf = Vec::new();
+        for addr in ["1.2.3.4:6969", "[2001:db8::1]:6881"] {
+            let addr: SocketAddr = addr.parse().unwrap();
+            Socks5UdpRelay::encapsulate(addr, b"hello", &mut buf).unwrap();
+            assert_eq!(
+                Socks5UdpRelay::decapsulate(&buf).unwrap(),
+                (addr, &b"hello"[..])
+            );
+        }
+
+        // IPv4-mapped addresses are sent as IPv4.
+        Socks5UdpRelay::encapsulate(
+            "[::ffff:1.2.3.4]:80".parse::<SocketAddr>().unwrap(),
+            b"",
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(buf, [0, 0, 0, 1, 1, 2, 3, 4, 0, 80]);
+
+        Socks5UdpRelay::encapsulate(RelayDst::Host("a.io", 80), b"x", &mut buf).unwrap();
+        assert_eq!(buf, [0, 0, 0, 3, 4, b'a', b'.', b'i', b'o', 0, 80, b'x']);
+        let long = "a".repeat(256);
+        assert!(Socks5UdpRelay::encapsulate(RelayDst::Host(&long, 80), b"", &mut buf).is_err());
+
+        buf = vec![0, 0, 1, 1, 1, 2, 3, 4, 0, 80];
+        assert!(Socks5UdpRelay::decapsulate(&buf).is_err());
+        assert!(Socks5UdpRelay::decapsulate(&[0, 0, 0, 1, 1, 2]).is_err());
+    }
+
+    // Accept the UDP ASSOCIATE of a client without authentication, and reply with an unspecified
+    // relay address.
+    async fn fake_associate(conn: &mut TcpStream, relay_port: u16) {
+        let mut greeting = [0u8; 3];
+        conn.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, [5, 1, 0]);
+        conn.write_all(&[5, 0]).await.unwrap();
+        let mut req = [0u8; 10];
+        conn.read_exact(&mut req).await.unwrap();
+        assert_eq!(req[..2], [5, 3]);
+        let mut reply = vec![5, 0, 0, 1, 0, 0, 0, 0];
+        reply.extend_from_slice(&relay_port.to_be_bytes());
+        conn.write_all(&reply).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_associate_and_reassociate() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let proxy_port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            // The first relay is torn down right away, the second one stays up.
+            let (mut conn, _) = listener.accept().await.unwrap();
+            fake_associate(&mut conn, 1000).await;
+            drop(conn);
+            let (mut conn, _) = listener.accept().await.unwrap();
+            fake_associate(&mut conn, 1001).await;
+            std::future::pending::<()>().await;
+        });
+
+        let relay = Socks5UdpRelay::associate("127.0.0.1", proxy_port, None)
+            .await
+            .unwrap();
+        let first: SocketAddr = (Ipv4Addr::LOCALHOST, 1000).into();
+        let second: SocketAddr = (Ipv4Addr::LOCALHOST, 1001).into();
+        assert!(relay.relay_addr() == first || relay.relay_addr() == second);
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while relay.relay_addr() != second {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("relay wasn't set up again");
+        assert!(relay.is_relay("[::ffff:127.0.0.1]:1001".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_associate_refused() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let proxy_port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 3];
+            conn.read_exact(&mut buf).await.unwrap();
+            conn.write_all(&[5, 0]).await.unwrap();
+            let mut buf = [0u8; 10];
+            conn.read_exact(&mut buf).await.unwrap();
+            // Command not supported.
+            conn.write_all(&[5, 7, 0, 1, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+        });
+        assert!(
+            Socks5UdpRelay::associate("127.0.0.1", proxy_port, None)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -93,3 +93,38 @@ impl SpeedEstimator {
         self.bytes_per_second.store(bps as u64, Ordering::Relaxed);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::SpeedEstimator;
+
+    #[test]
+    fn test_speed_estimator_window_and_eta() {
+        let e = SpeedEstimator::new(10);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // A burst of 1000 bytes/s, then stalling.
+        for i in 0..10u64 {
+            e.add_snapshot(i * 1000, Some(10000 - i * 1000), at(i));
+        }
+        assert_eq!(e.bps(), 1000);
+        assert_eq!(e.time_remaining(), Some(Duration::from_secs(1)));
+
+        // The window still contains progress, so the average goes down, not to zero.
+        e.add_snapshot(9000, Some(1000), at(10));
+        assert_eq!(e.bps(), 900);
+
+        for i in 11..20 {
+            e.add_snapshot(9000, Some(1000), at(i));
+        }
+        assert_eq!(e.bps(), 0);
+        assert_eq!(e.time_remaining(), None);
+
+        // Finished.
+        e.add_snapshot(10000, Some(0), at(20));
+        assert_eq!(e.time_remaining(), None);
+    }
+}
@@ -8,7 +8,11 @@ use serde_derive::{Deserialize, Serialize};
 use std::{borrow::Cow, collections::HashSet, iter::once, path::PathBuf};
 use tracing::debug;
 
-use crate::{Error, hash_id::Id20, lengths::Lengths};
+use crate::{
+    Error,
+    hash_id::{Id20, Id32},
+    lengths::Lengths,
+};
 
 pub type TorrentMetaV1Borrowed<'a> = TorrentMetaV1<ByteBuf<'a>>;
 pub type TorrentMetaV1Owned = TorrentMetaV1<ByteBufOwned>;
@@ -35,15 +39,29 @@ pub fn torrent_from_bytes<'de>(
     let mut digest = sha1w::Sha1::new();
     digest.update(t.info.raw_bytes.as_ref());
     t.info_hash = Id20::new(digest.finish());
+    if t.info.data.is_hybrid() {
+        t.info_hash_v2 = Some(info_hash_v2(t.info.raw_bytes.as_ref()));
+    }
     Ok(t)
 }
 
+/// The v2 (BEP-52) info hash, i.e. SHA-256 of the bencoded "info" dict.
+#[cfg(any(feature = "sha1-ring", feature = "sha1-crypto-hash"))]
+pub fn info_hash_v2(info_bytes: &[u8]) -> Id32 {
+    use sha1w::ISha256;
+
+    let mut digest = sha1w::Sha256::new();
+    digest.update(info_bytes);
+    Id32::new(digest.finish())
+}
+
 fn is_false(b: &bool) -> bool {
     !*b
 }
 
 /// A parsed .torrent file.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(bound(deserialize = "BufType: serde::Deserialize<'de> + Default"))]
 pub struct TorrentMetaV1<BufType> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub announce: Option<BufType>,
@@ -66,9 +84,58 @@ pub struct TorrentMetaV1<BufType> {
     pub publisher_url: Option<BufType>,
     #[serde(rename = "creation date", skip_serializing_if = "Option::is_none")]
     pub creation_date: Option<usize>,
+    /// Web seed URLs (BEP-19). A single URL is also accepted when deserializing.
+    #[serde(
+        rename = "url-list",
+        default = "Vec::new",
+        deserialize_with = "deserialize_url_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub url_list: Vec<BufType>,
 
     #[serde(skip)]
     pub info_hash: Id20,
+    /// Set for hybrid v1/v2 torrents.
+    #[serde(skip)]
+    pub info_hash_v2: Option<Id32>,
+}
+
+// "url-list" is either a list of strings or a single string.
+fn deserialize_url_list<'de, D, BufType>(de: D) -> Result<Vec<BufType>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    BufType: serde::Deserialize<'de>,
+{
+    struct Visitor<BufType>(std::marker::PhantomData<BufType>);
+
+    impl<'de, BufType> serde::de::Visitor<'de> for Visitor<BufType>
+    where
+        BufType: serde::Deserialize<'de>,
+    {
+        type Value = Vec<BufType>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a URL or a list of URLs")
+        }
+
+        fn visit_borrowed_bytes<E: serde::de::Error>(self, v: &'de [u8]) -> Result<Self::Value, E> {
+            BufType::deserialize(serde::de::value::BorrowedBytesDeserializer::new(v))
+                .map(|b| vec![b])
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> Result<Self::Value, A::Error> {
+            let mut urls = Vec::new();
+            while let Some(url) = seq.next_element()? {
+                urls.push(url);
+            }
+            Ok(urls)
+        }
+    }
+
+    de.deserialize_any(Visitor(std::marker::PhantomData))
 }
 
 impl<BufType> TorrentMetaV1<BufType> {
@@ -78,6 +145,15 @@ impl<BufType> TorrentMetaV1<BufType> {
         }
         itertools::Either::Right(self.announce.iter())
     }
+
+    /// Trackers grouped in tiers (BEP-12). Without "announce-list", it's a single tier with the
+    /// "announce" tracker.
+    pub fn iter_announce_tiers(&self) -> impl Iterator<Item = &[BufType]> {
+        if self.announce_list.iter().flatten().next().is_some() {
+            return itertools::Either::Left(self.announce_list.iter().map(|tier| tier.as_slice()));
+        }
+        itertools::Either::Right(self.announce.as_ref().map(std::slice::from_ref).into_iter())
+    }
 }
 
 /// Main torrent information, shared by .torrent files and magnet link contents.
@@ -85,6 +161,11 @@ impl<BufType> TorrentMetaV1<BufType> {
 pub struct TorrentMetaV1Info<BufType> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<BufType>,
+    // Missing in v2-only torrents, validate() rejects those.
+    #[serde(
+        default,
+        bound(deserialize = "BufType: serde::Deserialize<'de> + Default")
+    )]
     pub pieces: BufType,
     #[serde(rename = "piece length")]
     pub piece_length: u32,
@@ -112,6 +193,14 @@ pub struct TorrentMetaV1Info<BufType> {
 
     #[serde(skip_serializing_if = "is_false", default)]
     pub private: bool,
+
+    // BEP-52. Set to 2 in v2 and hybrid torrents. Only the v1 parts of hybrid torrents are used.
+    #[serde(
+        rename = "meta version",
+        default = "none",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub meta_version: Option<u32>,
 }
 
 #[derive(Clone, Copy)]
@@ -304,7 +393,18 @@ impl<BufType: AsRef<[u8]>> ValidatedTorrentMetaV1Info<BufType> {
 }
 
 impl<BufType: AsRef<[u8]>> TorrentMetaV1Info<BufType> {
+    /// A v2 torrent that also has all the v1 fields (BEP-52), so it can be downloaded as v1.
+    pub fn is_hybrid(&self) -> bool {
+        self.meta_version == Some(2)
+    }
+
     pub fn validate(self) -> crate::Result<ValidatedTorrentMetaV1Info<BufType>> {
+        match self.meta_version {
+            None => {}
+            Some(2) if self.pieces.as_ref().is_empty() => return Err(Error::V2OnlyNotSupported),
+            Some(2) => {}
+            Some(v) => return Err(Error::V2UnsupportedMetaVersion(v)),
+        }
         let lengths = Lengths::from_torrent(&self)?;
         let encoding = self.detect_encoding();
         let validated = ValidatedTorrentMetaV1Info {
@@ -478,6 +578,7 @@ where
             sha1: self.sha1.clone_to_owned(within_buffer),
             symlink_path: self.symlink_path.clone_to_owned(within_buffer),
             private: self.private,
+            meta_version: self.meta_version,
         }
     }
 }
@@ -499,7 +600,9 @@ where
             publisher: self.publisher.clone_to_owned(within_buffer),
             publisher_url: self.publisher_url.clone_to_owned(within_buffer),
             creation_date: self.creation_date,
+            url_list: self.url_list.clone_to_owned(within_buffer),
             info_hash: self.info_hash,
+            info_hash_v2: self.info_hash_v2,
         }
     }
 }
@@ -570,6 +673,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_url_list() {
+        const INFO: &[u8] = b"4:infod12:piece lengthi16384e6:pieces0:e";
+        let parse = |url_list: &[u8]| {
+            let buf = [b"d", INFO, b"8:url-list", url_list, b"e"].concat();
+            from_bytes::<TorrentMetaV1Borrowed>(&buf)
+                .unwrap()
+                .url_list
+                .iter()
+                .map(|u| std::str::from_utf8(u.as_ref()).unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(parse(b"18:http://example.com"), ["http://example.com"]);
+        assert_eq!(
+            parse(b"l18:http://example.com19:http://example2.come"),
+            ["http://example.com", "http://example2.com"]
+        );
+
+        let buf = [b"d", INFO, b"e"].concat();
+        let torrent = from_bytes::<TorrentMetaV1Borrowed>(&buf).unwrap();
+        assert!(torrent.url_list.is_empty());
+    }
+
     #[test]
     #[cfg(any(feature = "sha1-ring", feature = "sha1-crypto-hash"))]
     fn test_private_real_torrent() {
@@ -577,4 +704,50 @@ mod tests {
         let torrent: TorrentMetaV1Borrowed = from_bytes(buf).unwrap();
         assert!(torrent.info.data.private);
     }
+
+    #[test]
+    #[cfg(any(feature = "sha1-ring", feature = "sha1-crypto-hash"))]
+    fn test_meta_version() {
+        // Keys are sorted, "pieces" is optional and comes last.
+        let torrent = |meta_version: &[u8], pieces: &[u8]| {
+            [
+                &b"d4:infod6:lengthi16384e"[..],
+                meta_version,
+                b"4:name1:a12:piece lengthi16384e",
+                pieces,
+                b"ee",
+            ]
+            .concat()
+        };
+        let pieces = [&b"6:pieces20:"[..], &[0u8; 20]].concat();
+
+        let v1 = torrent(b"", &pieces);
+        let t = torrent_from_bytes(&v1).unwrap();
+        assert!(!t.info.data.is_hybrid());
+        assert_eq!(t.info_hash_v2, None);
+        t.info.data.validate().unwrap();
+
+        let hybrid = torrent(b"12:meta versioni2e", &pieces);
+        let t = torrent_from_bytes(&hybrid).unwrap();
+        assert!(t.info.data.is_hybrid());
+        assert_eq!(
+            t.info_hash_v2,
+            Some(super::info_hash_v2(t.info.raw_bytes.as_ref()))
+        );
+        t.info.data.validate().unwrap();
+
+        let v2_only = torrent(b"12:meta versioni2e", b"");
+        let t = torrent_from_bytes(&v2_only).unwrap();
+        assert!(matches!(
+            t.info.data.validate(),
+            Err(crate::Error::V2OnlyNotSupported)
+        ));
+
+        let v3 = torrent(b"12:meta versioni3e", &pieces);
+        let t = torrent_from_bytes(&v3).unwrap();
+        assert!(matches!(
+            t.info.data.validate(),
+            Err(crate::Error::V2UnsupportedMetaVersion(3))
+        ));
+    }
 }
@@ -0,0 +1,609 @@
+//! BitTorrent v2 (BEP-52) metadata: the "file tree", the "piece layers" and merkle hashes.
+//!
+//! Hybrid torrents are downloaded through their v1 parts. The v2 parts are used to make sure
+//! both describe the same files, and to verify pieces against their merkle hashes too.
+
+use std::collections::HashMap;
+
+use bencode::BencodeValue;
+use buffers::{ByteBuf, ByteBufOwned};
+use serde_derive::Deserialize;
+use sha1w::ISha256;
+
+use crate::{Error, hash_id::Id32, torrent_metainfo::ValidatedTorrentMetaV1Info};
+
+/// Leaves of the merkle trees are hashes of blocks of this size.
+pub const MERKLE_BLOCK_SIZE: u32 = 16384;
+
+/// A file from the v2 "file tree".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct V2File {
+    pub path: Vec<String>,
+    pub length: u64,
+    /// Merkle root of the file's blocks. None for empty files.
+    pub pieces_root: Option<Id32>,
+}
+
+/// The validated v2 parts of a torrent.
+#[derive(Debug, Clone)]
+pub struct V2Metadata {
+    pub piece_length: u32,
+    /// In the order of the file tree, which is the same as in the v1 file list of hybrid torrents,
+    /// without the padding files.
+    pub files: Vec<V2File>,
+    // Piece hashes of the files larger than one piece, by their pieces root.
+    piece_layers: HashMap<Id32, Vec<Id32>>,
+}
+
+#[derive(Deserialize)]
+struct RawTorrent<'a> {
+    info: RawInfo,
+    #[serde(rename = "piece layers", default, borrow)]
+    piece_layers: Option<HashMap<ByteBuf<'a>, ByteBuf<'a>>>,
+}
+
+#[derive(Deserialize)]
+struct RawInfo {
+    #[serde(rename = "piece length")]
+    piece_length: u32,
+    #[serde(rename = "meta version", default)]
+    meta_version: Option<u32>,
+    #[serde(rename = "file tree", default)]
+    file_tree: Option<BencodeValue<ByteBufOwned>>,
+}
+
+type Dict = HashMap<ByteBufOwned, BencodeValue<ByteBufOwned>>;
+
+fn key(k: &'static [u8]) -> ByteBufOwned {
+    ByteBufOwned(bytes::Bytes::from_static(k))
+}
+
+fn bad(msg: impl Into<String>) -> Error {
+    Error::V2BadMetadata(msg.into())
+}
+
+impl V2Metadata {
+    /// Parse and validate the v2 parts of a .torrent file. The piece layers are checked against
+    /// the pieces roots of the files.
+    pub fn parse(torrent_bytes: &[u8]) -> crate::Result<Self> {
+        let raw: RawTorrent = bencode::from_bytes(torrent_bytes).map_err(|e| bad(e.to_string()))?;
+        match raw.info.meta_version {
+            None => return Err(Error::V2MissingMetaVersion),
+            Some(2) => {}
+            Some(v) => return Err(Error::V2UnsupportedMetaVersion(v)),
+        }
+        let piece_length = raw.info.piece_length;
+        if piece_length < MERKLE_BLOCK_SIZE || !piece_length.is_power_of_two() {
+            return Err(Error::V2InvalidPieceLength(piece_length));
+        }
+
+        let tree = match raw.info.file_tree.ok_or(Error::V2MissingFileTree)? {
+            BencodeValue::Dict(d) => d,
+            _ => return Err(bad("file tree is not a dictionary")),
+        };
+        if tree.contains_key(&key(b"")) {
+            return Err(Error::V2FileTreeRootIsFile);
+        }
+        let mut files = Vec::new();
+        walk_file_tree(&tree, &mut Vec::new(), &mut files)?;
+        if files.is_empty() {
+            return Err(Error::BadTorrentNoFiles);
+        }
+
+        let mut piece_layers = HashMap::new();
+        for file in files.iter() {
+            let root = match (file.length, file.pieces_root) {
+                (0, None) => continue,
+                (0, Some(_)) => return Err(Error::V2ZeroLengthFileHasPiecesRoot),
+                (len, None) if len <= piece_length as u64 => {
+                    return Err(Error::V2SmallFileMissingPiecesRoot);
+                }
+                (_, None) => return Err(bad(format!("{:?} has no pieces root", file.path))),
+                (_, Some(root)) => root,
+            };
+            let layer = raw
+                .piece_layers
+                .as_ref()
+                .and_then(|l| l.get(&ByteBuf(&root.0[..])));
+            if file.length <= piece_length as u64 {
+                if layer.is_some() {
+                    return Err(Error::V2SmallFileShouldNotHavePieceLayers);
+                }
+                continue;
+            }
+            let layer = match (raw.piece_layers.as_ref(), layer) {
+                (None, _) => return Err(Error::V2MissingPieceLayers),
+                (Some(_), None) => {
+                    return Err(Error::V2MissingPieceLayersEntry(file.path.join("/")));
+                }
+                (Some(_), Some(layer)) => layer.0,
+            };
+            let expected = file.length.div_ceil(piece_length as u64) as usize * 32;
+            if layer.len() != expected {
+                return Err(Error::V2PieceLayersWrongSize {
+                    expected,
+                    actual: layer.len(),
+                });
+            }
+            let hashes = layer
+                .chunks_exact(32)
+                .map(|h| Id32::new(h.try_into().unwrap()))
+                .collect::<Vec<_>>();
+            if piece_layer_root(&hashes, piece_length) != root {
+                return Err(Error::V2PieceLayersRootMismatch);
+            }
+            piece_layers.insert(root, hashes);
+        }
+
+        let layer_count = raw.piece_layers.map(|l| l.len()).unwrap_or_default();
+        if layer_count != piece_layers.len() {
+            return Err(Error::V2PieceLayerCountMismatch {
+                expected: piece_layers.len(),
+                actual: layer_count,
+            });
+        }
+
+        Ok(Self {
+            piece_length,
+            files,
+            piece_layers,
+        })
+    }
+
+    /// Make sure the v1 parts of a hybrid torrent describe the same files.
+    pub fn check_hybrid<BufType: AsRef<[u8]>>(
+        &self,
+        info: &ValidatedTorrentMetaV1Info<BufType>,
+    ) -> crate::Result<()> {
+        if info.info().piece_length != self.piece_length {
+            return Err(Error::V2HybridFileListMismatch(format!(
+                "piece length {} != {}",
+                info.info().piece_length,
+                self.piece_length
+            )));
+        }
+        let mut v1_files = info
+            .iter_file_details_ext()
+            .filter(|fd| !fd.details.attrs().padding);
+        for v2 in self.files.iter() {
+            let v1 = v1_files
+                .next()
+                .ok_or_else(|| Error::V2HybridFileListMismatch(v2.path.join("/")))?;
+            let same_path = v1
+                .details
+                .filename
+                .iter_components_bytes()
+                .eq(v2.path.iter().map(|c| c.as_bytes()));
+            if !same_path || v1.details.len != v2.length {
+                return Err(Error::V2HybridFileListMismatch(v2.path.join("/")));
+            }
+            // Pieces are verified against the merkle hashes of one file, so files must be padded
+            // to piece boundaries.
+            if v1.details.len > 0 && !v1.offset.is_multiple_of(self.piece_length as u64) {
+                return Err(Error::V2HybridFileListMismatch(format!(
+                    "{} doesn't start on a piece boundary",
+                    v2.path.join("/")
+                )));
+            }
+        }
+        if let Some(v1) = v1_files.next() {
+            return Err(Error::V2HybridFileListMismatch(
+                v1.details.filename.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check the merkle hash of a piece of a file, computed from the piece's data with `hasher`.
+    /// Returns None if the file has no such piece.
+    pub fn check_piece(
+        &self,
+        file: usize,
+        piece_in_file: u32,
+        hasher: MerkleHasher,
+    ) -> Option<bool> {
+        let file = self.files.get(file)?;
+        let root = file.pieces_root?;
+        let (expected, width) = if file.length <= self.piece_length as u64 {
+            if piece_in_file != 0 {
+                return None;
+            }
+            // The tree of a small file is only as wide as it needs to be.
+            (root, hasher.block_count().next_power_of_two())
+        } else {
+            let expected = self.piece_layers.get(&root)?.get(piece_in_file as usize)?;
+            (*expected, blocks_per_piece(self.piece_length))
+        };
+        if hasher.block_count() > width {
+            return Some(false);
+        }
+        Some(hasher.finish(width) == expected)
+    }
+}
+
+fn walk_file_tree(
+    dict: &Dict,
+    path: &mut Vec<String>,
+    files: &mut Vec<V2File>,
+) -> crate::Result<()> {
+    // Bencoded dicts are sorted, the file order follows it.
+    let mut entries = dict.iter().collect::<Vec<_>>();
+    entries.sort_unstable_by_key(|(name, _)| *name);
+    for (name, node) in entries {
+        let name = match name.as_ref() {
+            b"" => return Err(bad("empty file name")),
+            b"." => return Err(Error::V2FileTreeDotComponent),
+            b".." => return Err(Error::BadTorrentPathTraversal),
+            n if memchr::memchr2(b'/', b'\\', n).is_some() => {
+                return Err(Error::BadTorrentSeparatorInName);
+            }
+            n => std::str::from_utf8(n).map_err(|_| bad("file name is not UTF-8"))?,
+        };
+        let BencodeValue::Dict(node) = node else {
+            return Err(bad(format!("{name:?} is not a dictionary")));
+        };
+        path.push(name.to_owned());
+        match node.get(&key(b"")) {
+            Some(BencodeValue::Dict(attrs)) => files.push(parse_file(attrs, path)?),
+            Some(_) => return Err(bad(format!("{name:?} has invalid attributes"))),
+            None => walk_file_tree(node, path, files)?,
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+fn parse_file(attrs: &Dict, path: &[String]) -> crate::Result<V2File> {
+    let length = match attrs.get(&key(b"length")) {
+        Some(BencodeValue::Integer(l)) if *l >= 0 => *l as u64,
+        _ => return Err(bad(format!("{path:?} has no valid length"))),
+    };
+    let pieces_root = match attrs.get(&key(b"pieces root")) {
+        None => None,
+        Some(BencodeValue::Bytes(b)) => Some(
+            Id32::from_bytes(b.as_ref())
+                .map_err(|_| bad(format!("{path:?} has invalid pieces root")))?,
+        ),
+        Some(_) => return Err(bad(format!("{path:?} has invalid pieces root"))),
+    };
+    Ok(V2File {
+        path: path.to_vec(),
+        length,
+        pieces_root,
+    })
+}
+
+fn blocks_per_piece(piece_length: u32) -> usize {
+    (piece_length / MERKLE_BLOCK_SIZE) as usize
+}
+
+fn hash_pair(left: &Id32, right: &Id32) -> Id32 {
+    let mut h = sha1w::Sha256::new();
+    h.update(&left.0);
+    h.update(&right.0);
+    Id32::new(h.finish())
+}
+
+/// Merkle root over `leaves`, padded with `pad` to `width` leaves. Width must be a power of two.
+pub fn merkle_root(leaves: &[Id32], width: usize, pad: Id32) -> Id32 {
+    debug_assert!(width.is_power_of_two() && leaves.len() <= width);
+    let mut layer = leaves.to_vec();
+    let mut pad = pad;
+    let mut width = width;
+    while width > 1 {
+        layer = layer
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pad)))
+            .collect();
+        pad = hash_pair(&pad, &pad);
+        width /= 2;
+    }
+    layer.first().copied().unwrap_or(pad)
+}
+
+/// The pieces root of a file from its piece layer. The layer is padded with hashes of pieces
+/// made of zero leaves.
+pub fn piece_layer_root(layer: &[Id32], piece_length: u32) -> Id32 {
+    let pad = merkle_root(&[], blocks_per_piece(piece_length), Id32::new([0; 32]));
+    merkle_root(layer, layer.len().next_power_of_two(), pad)
+}
+
+/// Computes the merkle hash of a piece, or of a whole small file, from its data.
+pub struct MerkleHasher {
+    leaves: Vec<Id32>,
+    block: sha1w::Sha256,
+    block_len: u32,
+}
+
+impl Default for MerkleHasher {
+    fn default() -> Self {
+        Self {
+            leaves: Vec::new(),
+            block: sha1w::Sha256::new(),
+            block_len: 0,
+        }
+    }
+}
+
+impl MerkleHasher {
+    pub fn update(&mut self, mut buf: &[u8]) {
+        while !buf.is_empty() {
+            let take = buf.len().min((MERKLE_BLOCK_SIZE - self.block_len) as usize);
+            self.block.update(&buf[..take]);
+            self.block_len += take as u32;
+            buf = &buf[take..];
+            if self.block_len == MERKLE_BLOCK_SIZE {
+                self.finish_block();
+            }
+        }
+    }
+
+    fn block_count(&self) -> usize {
+        self.leaves.len() + usize::from(self.block_len > 0)
+    }
+
+    fn finish_block(&mut self) {
+        let block = std::mem::replace(&mut self.block, sha1w::Sha256::new());
+        self.leaves.push(Id32::new(block.finish()));
+        self.block_len = 0;
+    }
+
+    /// The root over the blocks hashed so far, padded with zero leaves to `width` leaves.
+    pub fn finish(mut self, width: usize) -> Id32 {
+        if self.block_len > 0 {
+            self.finish_block();
+        }
+        merkle_root(&self.leaves, width, Id32::new([0; 32]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bencode::{BencodeValue, ByteBufOwned, bencode_serialize_to_writer};
+
+    use super::{MERKLE_BLOCK_SIZE, MerkleHasher, V2Metadata, hash_pair, piece_layer_root};
+    use crate::{Error, hash_id::Id32};
+
+    const PIECE_LENGTH: u32 = MERKLE_BLOCK_SIZE * 2;
+
+    type Value = BencodeValue<ByteBufOwned>;
+
+    fn bytes(b: &[u8]) -> Value {
+        Value::Bytes(ByteBufOwned::from(b))
+    }
+
+    fn dict<'a>(items: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+        Value::Dict(
+            items
+                .into_iter()
+                .map(|(k, v)| (ByteBufOwned::from(k.as_bytes()), v))
+                .collect(),
+        )
+    }
+
+    fn hash(data: &[u8], width: usize) -> Id32 {
+        let mut h = MerkleHasher::default();
+        h.update(data);
+        h.finish(width)
+    }
+
+    fn small_file_root(data: &[u8]) -> Id32 {
+        hash(
+            data,
+            data.len()
+                .div_ceil(MERKLE_BLOCK_SIZE as usize)
+                .next_power_of_two(),
+        )
+    }
+
+    fn piece_layer(data: &[u8]) -> Vec<Id32> {
+        data.chunks(PIECE_LENGTH as usize)
+            .map(|piece| hash(piece, 2))
+            .collect()
+    }
+
+    fn file(length: usize, root: Option<Id32>) -> Value {
+        let mut attrs = vec![("length", Value::Integer(length as i64))];
+        if let Some(root) = root {
+            attrs.push(("pieces root", bytes(&root.0)));
+        }
+        dict([("", dict(attrs))])
+    }
+
+    fn torrent(file_tree: Value, piece_layers: Option<Value>) -> Vec<u8> {
+        let info = dict([
+            ("meta version", Value::Integer(2)),
+            ("piece length", Value::Integer(PIECE_LENGTH as i64)),
+            ("file tree", file_tree),
+        ]);
+        let mut t = vec![("info", info)];
+        if let Some(l) = piece_layers {
+            t.push(("piece layers", l));
+        }
+        let mut buf = Vec::new();
+        bencode_serialize_to_writer(dict(t), &mut buf).unwrap();
+        buf
+    }
+
+    // A small file and a file of 3 pieces, the last one partial.
+    fn test_data() -> (Vec<u8>, Vec<u8>) {
+        let small = (0..20000u32).map(|i| i as u8).collect();
+        let large = (0..(PIECE_LENGTH * 2 + 5000))
+            .map(|i| (i / 7) as u8)
+            .collect();
+        (small, large)
+    }
+
+    fn test_torrent(layer: Vec<Id32>) -> Vec<u8> {
+        let (small, large) = test_data();
+        let large_root = piece_layer_root(&piece_layer(&large), PIECE_LENGTH);
+        let layer = layer.iter().flat_map(|h| h.0).collect::<Vec<u8>>();
+        torrent(
+            dict([
+                (
+                    "dir",
+                    dict([("large", file(large.len(), Some(large_root)))]),
+                ),
+                ("empty", file(0, None)),
+                ("small", file(small.len(), Some(small_file_root(&small)))),
+            ]),
+            Some(Value::Dict(HashMap::from([(
+                ByteBufOwned::from(&large_root.0[..]),
+                bytes(&layer),
+            )]))),
+        )
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        let leaves = [Id32::new([1; 32]), Id32::new([2; 32]), Id32::new([3; 32])];
+        let zero = Id32::new([0; 32]);
+        assert_eq!(
+            super::merkle_root(&leaves, 4, zero),
+            hash_pair(
+                &hash_pair(&leaves[0], &leaves[1]),
+                &hash_pair(&leaves[2], &zero)
+            )
+        );
+        assert_eq!(super::merkle_root(&leaves[..1], 1, zero), leaves[0]);
+    }
+
+    #[test]
+    fn test_parse_and_check_pieces() {
+        let (small, large) = test_data();
+        let v2 = V2Metadata::parse(&test_torrent(piece_layer(&large))).unwrap();
+        assert_eq!(
+            v2.files
+                .iter()
+                .map(|f| (f.path.join("/"), f.length))
+                .collect::<Vec<_>>(),
+            [
+                ("dir/large".to_owned(), large.len() as u64),
+                ("empty".to_owned(), 0),
+                ("small".to_owned(), small.len() as u64),
+            ]
+        );
+
+        let check = |file: usize, piece: u32, data: &[u8]| {
+            let mut h = MerkleHasher::default();
+            h.update(data);
+            v2.check_piece(file, piece, h)
+        };
+        for (idx, piece) in large.chunks(PIECE_LENGTH as usize).enumerate() {
+            assert_eq!(check(0, idx as u32, piece), Some(true));
+        }
+        assert_eq!(check(0, 1, &large[..PIECE_LENGTH as usize]), Some(false));
+        assert_eq!(check(0, 3, &large[..100]), None);
+        assert_eq!(check(1, 0, &[]), None);
+        assert_eq!(check(2, 0, &small), Some(true));
+        assert_eq!(check(2, 0, &small[1..]), Some(false));
+    }
+
+    // A hybrid torrent with two small files, with or without padding between them.
+    fn hybrid_torrent(padded: bool) -> Vec<u8> {
+        let data = [0u8; 20000];
+        let root = small_file_root(&data);
+        let v1_file = |name: &str| {
+            dict([
+                ("length", Value::Integer(data.len() as i64)),
+                ("path", Value::List(vec![bytes(name.as_bytes())])),
+            ])
+        };
+        let mut files = vec![v1_file("a")];
+        if padded {
+            let pad = PIECE_LENGTH as usize - data.len();
+            files.push(dict([
+                ("attr", bytes(b"p")),
+                ("length", Value::Integer(pad as i64)),
+                (
+                    "path",
+                    Value::List(vec![bytes(b".pad"), bytes(pad.to_string().as_bytes())]),
+                ),
+            ]));
+        }
+        files.push(v1_file("b"));
+        let info = dict([
+            ("name", bytes(b"t")),
+            ("files", Value::List(files)),
+            ("pieces", bytes(&[0u8; 40])),
+            ("meta version", Value::Integer(2)),
+            ("piece length", Value::Integer(PIECE_LENGTH as i64)),
+            (
+                "file tree",
+                dict([
+                    ("a", file(data.len(), Some(root))),
+                    ("b", file(data.len(), Some(root))),
+                ]),
+            ),
+        ]);
+        let mut buf = Vec::new();
+        bencode_serialize_to_writer(dict([("info", info)]), &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_check_hybrid_piece_boundaries() {
+        for padded in [true, false] {
+            let torrent = hybrid_torrent(padded);
+            let v2 = V2Metadata::parse(&torrent).unwrap();
+            let info = crate::torrent_metainfo::torrent_from_bytes(&torrent)
+                .unwrap()
+                .info
+                .data
+                .validate()
+                .unwrap();
+            let res = v2.check_hybrid(&info);
+            if padded {
+                res.unwrap();
+            } else {
+                assert!(
+                    matches!(&res, Err(Error::V2HybridFileListMismatch(m)) if m.contains("b ")),
+                    "{res:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let (_, large) = test_data();
+        let mut layer = piece_layer(&large);
+        layer[0] = Id32::new([5; 32]);
+        assert!(matches!(
+            V2Metadata::parse(&test_torrent(layer)),
+            Err(Error::V2PieceLayersRootMismatch)
+        ));
+
+        let layer = piece_layer(&large)[..2].to_vec();
+        assert!(matches!(
+            V2Metadata::parse(&test_torrent(layer)),
+            Err(Error::V2PieceLayersWrongSize {
+                expected: 96,
+                actual: 64
+            })
+        ));
+
+        let no_layers = torrent(
+            dict([("large", file(large.len(), Some(Id32::new([1; 32]))))]),
+            None,
+        );
+        assert!(matches!(
+            V2Metadata::parse(&no_layers),
+            Err(Error::V2MissingPieceLayers)
+        ));
+
+        let root_is_file = torrent(file(10, Some(Id32::new([1; 32]))), None);
+        assert!(matches!(
+            V2Metadata::parse(&root_is_file),
+            Err(Error::V2FileTreeRootIsFile)
+        ));
+
+        let traversal = torrent(dict([("..", file(0, None))]), None);
+        assert!(matches!(
+            V2Metadata::parse(&traversal),
+            Err(Error::BadTorrentPathTraversal)
+        ));
+    }
+}
@@ -166,45 +166,53 @@ cookie: {cookie}\r
             return Ok(());
         }
 
-        let announce_port = {
-            let g = self.inner.receivers.read();
-            let announce = return_if_none!(g.get(&bts.hash));
-            let mut addr = addr;
-            addr.set_port(bts.port);
+        let mut addr = addr;
+        addr.set_port(bts.port);
 
-            return_if_none!(announce.tx.send(addr).ok());
-
-            let announce_port = return_if_none!(announce.port);
-
-            let rl = if addr.is_ipv4() {
-                &announce.last_reply_ipv4
-            } else {
-                &announce.last_reply_ipv6
+        // An announce may be for several torrents.
+        for hash in bts.hashes {
+            let Some(announce_port) = self.on_announce(hash, addr) else {
+                continue;
             };
 
-            return_if_none!(rl.check(), trace!(?addr, ?bts, "replying rate-limited"));
+            let mopts = return_if_none!(
+                self.inner.socket.find_mcast_opts_for_replying_to(&addr),
+                debug!(?addr, "couldn't find where to reply")
+            );
 
-            announce_port
-        };
+            let reply = self.gen_announce_msg(hash, announce_port, addr.is_ipv6());
 
-        let mopts = return_if_none!(
-            self.inner.socket.find_mcast_opts_for_replying_to(&addr),
-            debug!(?addr, "couldn't find where to reply")
-        );
+            if let Err(e) = self
+                .inner
+                .socket
+                .send_multicast_msg(reply.as_bytes(), &mopts)
+                .await
+            {
+                trace!(?addr, ?reply, ?mopts, "error sending reply: {e:#}");
+            } else {
+                trace!(?addr, ?reply, ?mopts, "sent reply");
+            }
+        }
+        Ok(())
+    }
 
-        let reply = self.gen_announce_msg(bts.hash, announce_port, addr.is_ipv6());
+    // Pass the peer to the torrent's stream. Returns the port to reply with if we should reply.
+    fn on_announce(&self, hash: Id20, addr: SocketAddr) -> Option<u16> {
+        let g = self.inner.receivers.read();
+        let announce = g.get(&hash)?;
+        announce.tx.send(addr).ok()?;
+        let announce_port = announce.port?;
 
-        if let Err(e) = self
-            .inner
-            .socket
-            .send_multicast_msg(reply.as_bytes(), &mopts)
-            .await
-        {
-            trace!(?addr, ?reply, ?mopts, "error sending reply: {e:#}");
+        let rl = if addr.is_ipv4() {
+            &announce.last_reply_ipv4
         } else {
-            trace!(?addr, ?reply, ?mopts, "sent reply");
+            &announce.last_reply_ipv6
+        };
+        if rl.check().is_none() {
+            trace!(?addr, ?hash, "replying rate-limited");
+            return None;
         }
-        Ok(())
+        Some(announce_port)
     }
 
     async fn task_monitor_recv(self) -> anyhow::Result<()> {
@@ -293,7 +301,7 @@ cookie: {cookie}\r
 
 #[derive(Debug)]
 struct BtSearchAnnounceMessage {
-    hash: Id20,
+    hashes: Vec<Id20>,
     our_cookie: Option<u32>,
     #[allow(unused)]
     host: SocketAddr,
@@ -311,7 +319,7 @@ fn try_parse_bt_search<'a: 'h, 'h>(
         Some("BT-SEARCH") => {
             let mut host = None;
             let mut port = None;
-            let mut infohash = None;
+            let mut hashes = Vec::new();
             let mut our_cookie = None;
 
             for header in req.headers.iter() {
@@ -325,7 +333,8 @@ fn try_parse_bt_search<'a: 'h, 'h>(
                 } else if header.name.eq_ignore_ascii_case("port") {
                     port = Some(atoi::atoi::<u16>(header.value).context("port is not a number")?)
                 } else if header.name.eq_ignore_ascii_case("infohash") {
-                    infohash = Some(
+                    // Repeated for each torrent if the announce is for several.
+                    hashes.push(
                         Id20::from_str(from_utf8(header.value).context("infohash isn't utf-8")?)
                             .context("invalid infohash header")?,
                     );
@@ -334,16 +343,47 @@ fn try_parse_bt_search<'a: 'h, 'h>(
                 }
             }
 
-            match (host, port, infohash) {
-                (Some(host), Some(port), Some(hash)) => Ok(BtSearchAnnounceMessage {
-                    hash,
+            match (host, port) {
+                (Some(host), Some(port)) if !hashes.is_empty() => Ok(BtSearchAnnounceMessage {
+                    hashes,
                     our_cookie,
                     host,
                     port,
                 }),
-                _ => anyhow::bail!("not all of host, man and st are set"),
+                _ => anyhow::bail!("not all of host, port and infohash are set"),
             }
         }
         _ => anyhow::bail!("expecting BT-SEARCH"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use librqbit_core::Id20;
+
+    use super::try_parse_bt_search;
+
+    #[test]
+    fn test_parse_bt_search() {
+        let a = "a621779b5e3d486e127c3efbca9b6f8d135f52e5";
+        let b = "caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa";
+        let msg = format!(
+            "BT-SEARCH * HTTP/1.1\r\nHost: 239.192.152.143:6771\r\nPort: 6881\r\n\
+             Infohash: {a}\r\nInfohash: {b}\r\ncookie: 42\r\n\r\n\r\n"
+        );
+        let mut headers = [httparse::EMPTY_HEADER; 16];
+        let bts = try_parse_bt_search(msg.as_bytes(), &mut headers).unwrap();
+        assert_eq!(
+            bts.hashes,
+            vec![Id20::from_str(a).unwrap(), Id20::from_str(b).unwrap()]
+        );
+        assert_eq!(bts.port, 6881);
+        assert_eq!(bts.our_cookie, Some(42));
+
+        let msg = "BT-SEARCH * HTTP/1.1\r\nHost: 239.192.152.143:6771\r\nPort: 6881\r\n\r\n";
+        let mut headers = [httparse::EMPTY_HEADER; 16];
+        assert!(try_parse_bt_search(msg.as_bytes(), &mut headers).is_err());
+    }
+}
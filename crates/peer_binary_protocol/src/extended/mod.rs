@@ -11,14 +11,16 @@ use ut_pex::UtPex;
 
 use crate::DoubleBufHelper;
 use crate::MSGID_EXTENDED;
+use crate::MY_EXTENDED_UT_HOLEPUNCH;
 use crate::MY_EXTENDED_UT_PEX;
 use crate::SerializeError;
 
-use self::{handshake::ExtendedHandshake, ut_metadata::UtMetadata};
+use self::{handshake::ExtendedHandshake, ut_holepunch::UtHolepunch, ut_metadata::UtMetadata};
 
 use super::MessageDeserializeError;
 
 pub mod handshake;
+pub mod ut_holepunch;
 pub mod ut_metadata;
 pub mod ut_pex;
 
@@ -28,6 +30,7 @@ use super::MY_EXTENDED_UT_METADATA;
 pub struct PeerExtendedMessageIds {
     pub ut_metadata: Option<u8>,
     pub ut_pex: Option<u8>,
+    pub ut_holepunch: Option<u8>,
 }
 
 impl PeerExtendedMessageIds {
@@ -35,6 +38,7 @@ impl PeerExtendedMessageIds {
         Self {
             ut_metadata: Some(MY_EXTENDED_UT_METADATA),
             ut_pex: Some(MY_EXTENDED_UT_PEX),
+            ut_holepunch: Some(MY_EXTENDED_UT_HOLEPUNCH),
         }
     }
 }
@@ -44,6 +48,7 @@ pub enum ExtendedMessage<ByteBuf: ByteBufT> {
     Handshake(ExtendedHandshake<ByteBuf>),
     UtMetadata(UtMetadata<ByteBuf>),
     UtPex(UtPex<ByteBuf>),
+    UtHolepunch(UtHolepunch),
     Dyn(u8, BencodeValue<ByteBuf>),
 }
 
@@ -77,6 +82,13 @@ impl<'a> ExtendedMessage<ByteBuf<'a>> {
                 out.write_u8(emsg_id)?;
                 bencode_serialize_to_writer(m, &mut out)?;
             }
+            ExtendedMessage::UtHolepunch(m) => {
+                let emsg_id = peer_extended_msg_ids()
+                    .ut_holepunch
+                    .ok_or(SerializeError::NeedHolepunch)?;
+                out.write_u8(emsg_id)?;
+                m.serialize(&mut out)?;
+            }
         }
         Ok(out.position() as usize)
     }
@@ -106,6 +118,9 @@ impl<'a> ExtendedMessage<ByteBuf<'a>> {
                 Ok(ExtendedMessage::UtMetadata(UtMetadata::deserialize(buf)?))
             }
             MY_EXTENDED_UT_PEX => Ok(ExtendedMessage::UtPex(from_bytes_contig(&buf)?)),
+            MY_EXTENDED_UT_HOLEPUNCH => {
+                Ok(ExtendedMessage::UtHolepunch(UtHolepunch::deserialize(buf)?))
+            }
             _ => Ok(ExtendedMessage::Dyn(emsg_id, from_bytes_contig(&buf)?)),
         }
     }
@@ -119,6 +134,7 @@ mod tests {
         DoubleBufHelper, MessageDeserializeError,
         extended::{
             ExtendedMessage, PeerExtendedMessageIds,
+            ut_holepunch::{UT_HOLEPUNCH_ERR_NOT_CONNECTED, UtHolepunch},
             ut_metadata::{UtMetadata, UtMetadataData},
         },
     };
@@ -157,6 +173,34 @@ mod tests {
         )));
     }
 
+    #[test]
+    fn test_ut_holepunch_roundtrip() {
+        for msg in [
+            UtHolepunch::rendezvous("1.2.3.4:5678".parse().unwrap()),
+            UtHolepunch::connect("[2001:db8::1]:6881".parse().unwrap()),
+            UtHolepunch::error(
+                "1.2.3.4:5678".parse().unwrap(),
+                UT_HOLEPUNCH_ERR_NOT_CONNECTED,
+            ),
+        ] {
+            let msg = ExtendedMessage::UtHolepunch(msg);
+            let mut buf = [0u8; 100];
+            let sz = msg
+                .serialize(&mut buf, &|| PeerExtendedMessageIds::my())
+                .unwrap();
+            for split_point in 0..sz {
+                let (d0, d1) = buf[..sz].split_at(split_point);
+                let de = ExtendedMessage::deserialize(DoubleBufHelper::new(d0, d1)).unwrap();
+                assert_eq!(msg, de);
+            }
+            let res = ExtendedMessage::deserialize(DoubleBufHelper::new(&buf[..sz + 1], &[]));
+            assert!(
+                matches!(res, Err(MessageDeserializeError::UtHolepunchTrailingBytes)),
+                "expected trailing bytes error, got {res:?}"
+            );
+        }
+    }
+
     #[test]
     fn test_ut_metadata_non_contiguous() {
         let mut buf = [0u8; 100];
@@ -0,0 +1,126 @@
+// ut_holepunch (BEP 55): connect two peers that can't reach each other through a peer both of
+// them are connected to.
+//
+// The initiator sends "rendezvous" with the target's address to the relay. The relay sends
+// "connect" to both the initiator (with the target's address) and the target (with the
+// initiator's address), and both of them connect to each other at the same time.
+
+use std::{
+    io::Write,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use byteorder::{BE, WriteBytesExt};
+
+use crate::{DoubleBufHelper, MSGID_EXTENDED, MessageDeserializeError, MsgIdDebug};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtHolepunchMsgType {
+    Rendezvous,
+    Connect,
+    Error,
+}
+
+impl UtHolepunchMsgType {
+    fn to_u8(self) -> u8 {
+        match self {
+            UtHolepunchMsgType::Rendezvous => 0,
+            UtHolepunchMsgType::Connect => 1,
+            UtHolepunchMsgType::Error => 2,
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(UtHolepunchMsgType::Rendezvous),
+            1 => Some(UtHolepunchMsgType::Connect),
+            2 => Some(UtHolepunchMsgType::Error),
+            _ => None,
+        }
+    }
+}
+
+// Error codes sent by the relay.
+pub const UT_HOLEPUNCH_ERR_NO_SUCH_PEER: u32 = 1;
+pub const UT_HOLEPUNCH_ERR_NOT_CONNECTED: u32 = 2;
+pub const UT_HOLEPUNCH_ERR_NO_SUPPORT: u32 = 3;
+pub const UT_HOLEPUNCH_ERR_NO_SELF: u32 = 4;
+
+// The "added.f" flag in PEX messages for peers that support ut_holepunch.
+pub const PEX_FLAG_SUPPORTS_HOLEPUNCH: u8 = 0x08;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtHolepunch {
+    pub msg_type: UtHolepunchMsgType,
+    pub addr: SocketAddr,
+    // 0 unless msg_type is Error.
+    pub err_code: u32,
+}
+
+impl UtHolepunch {
+    pub fn rendezvous(addr: SocketAddr) -> Self {
+        Self {
+            msg_type: UtHolepunchMsgType::Rendezvous,
+            addr,
+            err_code: 0,
+        }
+    }
+
+    pub fn connect(addr: SocketAddr) -> Self {
+        Self {
+            msg_type: UtHolepunchMsgType::Connect,
+            addr,
+            err_code: 0,
+        }
+    }
+
+    pub fn error(addr: SocketAddr, err_code: u32) -> Self {
+        Self {
+            msg_type: UtHolepunchMsgType::Error,
+            addr,
+            err_code,
+        }
+    }
+
+    pub fn serialize(&self, out: &mut impl Write) -> std::io::Result<()> {
+        out.write_u8(self.msg_type.to_u8())?;
+        match self.addr.ip() {
+            IpAddr::V4(ip) => {
+                out.write_u8(0)?;
+                out.write_all(&ip.octets())?;
+            }
+            IpAddr::V6(ip) => {
+                out.write_u8(1)?;
+                out.write_all(&ip.octets())?;
+            }
+        }
+        out.write_u16::<BE>(self.addr.port())?;
+        out.write_u32::<BE>(self.err_code)?;
+        Ok(())
+    }
+
+    pub fn deserialize(mut buf: DoubleBufHelper<'_>) -> Result<Self, MessageDeserializeError> {
+        let not_enough = |rem: usize| {
+            MessageDeserializeError::NotEnoughData(rem, Some(MsgIdDebug(MSGID_EXTENDED)))
+        };
+        let msg_type = buf.read_u8().ok_or_else(|| not_enough(1))?;
+        let msg_type = UtHolepunchMsgType::from_u8(msg_type)
+            .ok_or(MessageDeserializeError::UtHolepunchTypeUnknown(msg_type))?;
+        let addr_type = buf.read_u8().ok_or_else(|| not_enough(1))?;
+        let ip: IpAddr = match addr_type {
+            0 => Ipv4Addr::from(buf.consume::<4>().map_err(not_enough)?).into(),
+            1 => Ipv6Addr::from(buf.consume::<16>().map_err(not_enough)?).into(),
+            t => return Err(MessageDeserializeError::UtHolepunchAddrTypeUnknown(t)),
+        };
+        let port = u16::from_be_bytes(buf.consume::<2>().map_err(not_enough)?);
+        let err_code = buf.read_u32_be().map_err(not_enough)?;
+        if !buf.is_empty() {
+            return Err(MessageDeserializeError::UtHolepunchTrailingBytes);
+        }
+        Ok(Self {
+            msg_type,
+            addr: SocketAddr::new(ip, port),
+            err_code,
+        })
+    }
+}
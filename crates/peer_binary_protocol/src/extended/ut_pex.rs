@@ -6,6 +6,7 @@ use librqbit_core::compact_ip::{
 };
 use serde_derive::{Deserialize, Serialize};
 
+#[derive(Clone, Copy)]
 pub struct PexPeerInfo {
     pub flags: u8,
     pub addr: SocketAddr,
@@ -130,41 +131,69 @@ impl UtPex<ByteBufOwned> {
         addrs_live: impl Iterator<Item = SocketAddr> + Clone,
         addrs_closed: impl Iterator<Item = SocketAddr> + Clone,
     ) -> Self {
-        fn split(
-            addrs: impl Iterator<Item = SocketAddr> + Clone,
-        ) -> (
+        Self::from_peers(
+            addrs_live.map(|addr| PexPeerInfo { flags: 0, addr }),
+            addrs_closed,
+        )
+    }
+
+    // Same as from_addrs(), but with the flags of the added peers. "added.f" and "added6.f" are
+    // only sent if any of the flags are set.
+    pub fn from_peers(
+        peers_live: impl Iterator<Item = PexPeerInfo> + Clone,
+        addrs_closed: impl Iterator<Item = SocketAddr> + Clone,
+    ) -> Self {
+        type Split = (
             Option<CompactListInBufferOwned<SocketAddrV4>>,
+            Option<CompactListInBufferOwned<Flags>>,
             Option<CompactListInBufferOwned<SocketAddrV6>>,
-        ) {
-            let v4 =
-                CompactListInBufferOwned::new_from_iter(addrs.clone().filter_map(
-                    |addr| match addr {
-                        SocketAddr::V4(a) => Some(a),
-                        _ => None,
-                    },
-                ));
-            let v6 =
-                CompactListInBufferOwned::new_from_iter(addrs.clone().filter_map(
-                    |addr| match addr {
-                        SocketAddr::V6(a) => Some(a),
-                        _ => None,
-                    },
-                ));
-            (
-                if v4.is_empty() { None } else { Some(v4) },
-                if v6.is_empty() { None } else { Some(v6) },
-            )
+            Option<CompactListInBufferOwned<Flags>>,
+        );
+
+        fn split(peers: impl Iterator<Item = PexPeerInfo> + Clone) -> Split {
+            let v4 = peers.clone().filter_map(|p| match p.addr {
+                SocketAddr::V4(a) => Some((a, p.flags)),
+                _ => None,
+            });
+            let v6 = peers.filter_map(|p| match p.addr {
+                SocketAddr::V6(a) => Some((a, p.flags)),
+                _ => None,
+            });
+            fn lists<A: CompactSerialize + CompactSerializeFixedLen>(
+                it: impl Iterator<Item = (A, u8)> + Clone,
+            ) -> (
+                Option<CompactListInBufferOwned<A>>,
+                Option<CompactListInBufferOwned<Flags>>,
+            ) {
+                let addrs = CompactListInBufferOwned::new_from_iter(it.clone().map(|(a, _)| a));
+                if addrs.is_empty() {
+                    return (None, None);
+                }
+                let flags = if it.clone().any(|(_, f)| f != 0) {
+                    Some(CompactListInBufferOwned::new_from_iter(
+                        it.map(|(_, f)| Flags(f)),
+                    ))
+                } else {
+                    None
+                };
+                (Some(addrs), flags)
+            }
+            let (v4, v4_f) = lists(v4);
+            let (v6, v6_f) = lists(v6);
+            (v4, v4_f, v6, v6_f)
         }
 
-        let (added, added6) = split(addrs_live);
-        let (dropped, dropped6) = split(addrs_closed);
+        let (added, added_f, added6, added6_f) = split(peers_live);
+        let (dropped, _, dropped6, _) =
+            split(addrs_closed.map(|addr| PexPeerInfo { flags: 0, addr }));
 
         Self {
             added,
+            added_f,
             added6,
+            added6_f,
             dropped,
             dropped6,
-            ..Default::default()
         }
     }
 }
@@ -234,4 +263,33 @@ mod tests {
         assert_eq!(aa1, addrs2[2].addr);
         assert_eq!(aa2, addrs2[3].addr);
     }
+
+    #[test]
+    fn test_pex_roundtrip_flags() {
+        let a1 = "185.159.157.20:46439".parse::<SocketAddr>().unwrap();
+        let a2 = "151.249.105.134:4240".parse::<SocketAddr>().unwrap();
+        let aa1 = "[5be8:dde9:7f0b:d5a7:bd01:b3be:9c69:573b]:46439"
+            .parse::<SocketAddr>()
+            .unwrap();
+
+        let peers = [
+            PexPeerInfo {
+                flags: 0x08,
+                addr: a1,
+            },
+            PexPeerInfo { flags: 0, addr: a2 },
+            PexPeerInfo {
+                flags: 0,
+                addr: aa1,
+            },
+        ];
+        let pex = UtPex::from_peers(peers.iter().copied(), std::iter::empty());
+        assert!(pex.added_f.is_some());
+        assert!(pex.added6_f.is_none());
+        let mut bytes = Vec::new();
+        bencode_serialize_to_writer(&pex.as_borrowed(), &mut bytes).unwrap();
+        let pex2 = from_bytes::<UtPex<ByteBuf>>(&bytes).unwrap();
+        let added: Vec<_> = pex2.added_peers().map(|p| (p.addr, p.flags)).collect();
+        assert_eq!(added, [(a1, 0x08), (a2, 0), (aa1, 0)]);
+    }
 }
@@ -46,6 +46,12 @@ const MSGID_BITFIELD: MsgId = 5;
 const MSGID_REQUEST: MsgId = 6;
 const MSGID_PIECE: MsgId = 7;
 const MSGID_CANCEL: MsgId = 8;
+// Fast extension (BEP 6).
+const MSGID_SUGGEST_PIECE: MsgId = 0x0D;
+const MSGID_HAVE_ALL: MsgId = 0x0E;
+const MSGID_HAVE_NONE: MsgId = 0x0F;
+const MSGID_REJECT_REQUEST: MsgId = 0x10;
+const MSGID_ALLOWED_FAST: MsgId = 0x11;
 const MSGID_EXTENDED: MsgId = 20;
 
 pub const EXTENDED_UT_METADATA_KEY: &[u8] = b"ut_metadata";
@@ -54,6 +60,9 @@ pub const MY_EXTENDED_UT_METADATA: u8 = 3;
 pub const EXTENDED_UT_PEX_KEY: &[u8] = b"ut_pex";
 pub const MY_EXTENDED_UT_PEX: u8 = 1;
 
+pub const EXTENDED_UT_HOLEPUNCH_KEY: &[u8] = b"ut_holepunch";
+pub const MY_EXTENDED_UT_HOLEPUNCH: u8 = 4;
+
 #[derive(Clone, Copy)]
 pub struct MsgIdDebug(MsgId);
 impl MsgIdDebug {
@@ -68,6 +77,11 @@ impl MsgIdDebug {
             MSGID_REQUEST => "request",
             MSGID_PIECE => "piece",
             MSGID_CANCEL => "cancel",
+            MSGID_SUGGEST_PIECE => "suggest_piece",
+            MSGID_HAVE_ALL => "have_all",
+            MSGID_HAVE_NONE => "have_none",
+            MSGID_REJECT_REQUEST => "reject_request",
+            MSGID_ALLOWED_FAST => "allowed_fast",
             MSGID_EXTENDED => "extended",
             _ => return None,
         };
@@ -119,6 +133,12 @@ pub enum MessageDeserializeError {
         expected_size: u32,
         received_size: u32,
     },
+    #[error("ut_holepunch: unrecognized message type: {0}")]
+    UtHolepunchTypeUnknown(u8),
+    #[error("ut_holepunch: unrecognized address type: {0}")]
+    UtHolepunchAddrTypeUnknown(u8),
+    #[error("ut_holepunch: trailing bytes when decoding")]
+    UtHolepunchTrailingBytes,
     #[error("pstr doesn't match {PSTR_BT1:?}")]
     HandshakePstrWrongContent,
     #[error("pstr should be 19 bytes long but got {0}")]
@@ -229,6 +249,12 @@ pub enum Message<'a> {
     NotInterested,
     Piece(Piece<ByteBuf<'a>>),
     Extended(ExtendedMessage<ByteBuf<'a>>),
+    // Fast extension (BEP 6), only sent if both sides support it, see Handshake::supports_fast().
+    SuggestPiece(u32),
+    HaveAll,
+    HaveNone,
+    RejectRequest(Request),
+    AllowedFast(u32),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -241,6 +267,8 @@ pub enum SerializeError {
     NeedUtMetadata,
     #[error("need peer's handshake to serialize ut_pex, or peer does't support ut_pex")]
     NeedPex,
+    #[error("need peer's handshake to serialize ut_holepunch, or peer does't support ut_holepunch")]
+    NeedHolepunch,
 }
 
 impl From<std::io::Error> for SerializeError {
@@ -271,12 +299,15 @@ impl Message<'_> {
         }
 
         match self {
-            Message::Request(request) | Message::Cancel(request) => {
+            Message::Request(request)
+            | Message::Cancel(request)
+            | Message::RejectRequest(request) => {
                 const TOTAL_LEN: usize = PREAMBLE_LEN + INTEGER_LEN * 3;
                 check_len!(TOTAL_LEN);
                 let msg_id = match self {
                     Message::Request(..) => MSGID_REQUEST,
                     Message::Cancel(..) => MSGID_CANCEL,
+                    Message::RejectRequest(..) => MSGID_REJECT_REQUEST,
                     _ => unsafe { unreachable_unchecked() },
                 };
                 write_preamble!((INTEGER_LEN * 3) as u32, msg_id);
@@ -291,13 +322,20 @@ impl Message<'_> {
                 out[PREAMBLE_LEN..PREAMBLE_LEN + block_len].copy_from_slice(b.as_ref());
                 Ok(total_len)
             }
-            Message::Choke | Message::Unchoke | Message::Interested | Message::NotInterested => {
+            Message::Choke
+            | Message::Unchoke
+            | Message::Interested
+            | Message::NotInterested
+            | Message::HaveAll
+            | Message::HaveNone => {
                 check_len!(PREAMBLE_LEN);
                 let msg_id = match self {
                     Message::Choke => MSGID_CHOKE,
                     Message::Unchoke => MSGID_UNCHOKE,
                     Message::Interested => MSGID_INTERESTED,
                     Message::NotInterested => MSGID_NOT_INTERESTED,
+                    Message::HaveAll => MSGID_HAVE_ALL,
+                    Message::HaveNone => MSGID_HAVE_NONE,
                     _ => unsafe { unreachable_unchecked() },
                 };
                 write_preamble!(0, msg_id);
@@ -317,9 +355,15 @@ impl Message<'_> {
                 out[0..4].copy_from_slice(&0u32.to_be_bytes());
                 Ok(4)
             }
-            Message::Have(v) => {
+            Message::Have(v) | Message::SuggestPiece(v) | Message::AllowedFast(v) => {
                 check_len!(PREAMBLE_LEN + INTEGER_LEN);
-                write_preamble!(INTEGER_LEN as u32, MSGID_HAVE);
+                let msg_id = match self {
+                    Message::Have(..) => MSGID_HAVE,
+                    Message::SuggestPiece(..) => MSGID_SUGGEST_PIECE,
+                    Message::AllowedFast(..) => MSGID_ALLOWED_FAST,
+                    _ => unsafe { unreachable_unchecked() },
+                };
+                write_preamble!(INTEGER_LEN as u32, msg_id);
                 out[5..9].copy_from_slice(&v.to_be_bytes());
                 Ok(9)
             }
@@ -398,10 +442,23 @@ impl Message<'_> {
                 check_msg_len!(0);
                 Ok((Message::NotInterested, total_len))
             }
-            MSGID_HAVE => {
+            MSGID_HAVE_ALL => {
+                check_msg_len!(0);
+                Ok((Message::HaveAll, total_len))
+            }
+            MSGID_HAVE_NONE => {
+                check_msg_len!(0);
+                Ok((Message::HaveNone, total_len))
+            }
+            MSGID_HAVE | MSGID_SUGGEST_PIECE | MSGID_ALLOWED_FAST => {
                 check_msg_len!(4);
-                let have = buf.read_u32_be().unwrap();
-                Ok((Message::Have(have), total_len))
+                let index = buf.read_u32_be().unwrap();
+                let msg = match msg_id {
+                    MSGID_HAVE => Message::Have(index),
+                    MSGID_SUGGEST_PIECE => Message::SuggestPiece(index),
+                    _ => Message::AllowedFast(index),
+                };
+                Ok((msg, total_len))
             }
             MSGID_BITFIELD => {
                 check_msg_len!(min 1);
@@ -411,7 +468,7 @@ impl Message<'_> {
                     .ok_or(MessageDeserializeError::NeedContiguous)?;
                 Ok((Message::Bitfield(ByteBuf::from(data)), total_len))
             }
-            MSGID_REQUEST | MSGID_CANCEL => {
+            MSGID_REQUEST | MSGID_CANCEL | MSGID_REJECT_REQUEST => {
                 check_msg_len!(12);
                 const I32: usize = 4;
                 const I32_3: usize = I32 * 3;
@@ -421,10 +478,10 @@ impl Message<'_> {
                     begin: BE::read_u32(&req[I32..I32 * 2]),
                     length: BE::read_u32(&req[I32 * 2..I32 * 3]),
                 };
-                let req = if msg_id == MSGID_REQUEST {
-                    Message::Request(request)
-                } else {
-                    Message::Cancel(request)
+                let req = match msg_id {
+                    MSGID_REQUEST => Message::Request(request),
+                    MSGID_CANCEL => Message::Cancel(request),
+                    _ => Message::RejectRequest(request),
                 };
                 Ok((req, total_len))
             }
@@ -478,6 +535,8 @@ impl Handshake {
         let mut reserved: u64 = 0;
         // supports extended messaging
         reserved |= 1 << 20;
+        // supports the fast extension
+        reserved |= 1 << 2;
 
         Handshake {
             reserved,
@@ -510,6 +569,11 @@ impl Handshake {
         self.reserved.to_be_bytes()[5] & 0x10 > 0
     }
 
+    // Fast extension (BEP 6). It's only used if both sides support it.
+    pub fn supports_fast(&self) -> bool {
+        self.reserved.to_be_bytes()[7] & 0x04 > 0
+    }
+
     #[must_use]
     pub fn serialize_unchecked_len(&self, buf: &mut [u8]) -> usize {
         debug_assert_eq!(PSTR_BT1.len(), 19);
@@ -575,6 +639,8 @@ mod tests {
         let (de, dlen) = Handshake::deserialize(&buf).unwrap();
         assert_eq!(dlen, len);
         assert_eq!(se, de);
+        assert!(de.supports_extended());
+        assert!(de.supports_fast());
     }
 
     #[test]
@@ -729,6 +795,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_piece_index_messages() {
+        let mut buf = [0u8; 100];
+        for msgid in [MSGID_HAVE, MSGID_SUGGEST_PIECE, MSGID_ALLOWED_FAST] {
+            buf[0..4].copy_from_slice(&5u32.to_be_bytes());
+            buf[4] = msgid;
+            buf[5..9].copy_from_slice(&42u32.to_be_bytes());
+            let (msg, len) = Message::deserialize(&buf, &[]).unwrap();
+            match (msgid, &msg) {
+                (MSGID_HAVE, Message::Have(42))
+                | (MSGID_SUGGEST_PIECE, Message::SuggestPiece(42))
+                | (MSGID_ALLOWED_FAST, Message::AllowedFast(42)) => {}
+                (msgid, msg) => panic!("msgid={msgid}, msg={msg:?}"),
+            }
+            assert_eq!(len, 9);
+            let mut tmp = [0u8; 100];
+            let slen = msg.serialize(&mut tmp, &|| Default::default()).unwrap();
+            assert_eq!(slen, len);
+            assert_eq!(buf[..len], tmp[..len]);
+        }
+    }
+
+    #[test]
+    fn test_reject_request() {
+        let msg = Message::RejectRequest(Request::new(42, 43, 44));
+        let mut buf = [0u8; 100];
+        let len = msg.serialize(&mut buf, &|| Default::default()).unwrap();
+        assert_eq!(len, 17);
+        assert_eq!(buf[4], MSGID_REJECT_REQUEST);
+        let (msg, dlen) = Message::deserialize(&buf[..len], &[]).unwrap();
+        assert_eq!(dlen, len);
+        match msg {
+            Message::RejectRequest(req) => {
+                assert_eq!((req.index, req.begin, req.length), (42, 43, 44));
+            }
+            other => panic!("expected reject_request, got {other:?}"),
+        }
+    }
+
     #[test]
     fn test_bitfield() {
         let mut buf = [0u8; 100];
@@ -774,6 +879,8 @@ mod tests {
             MSGID_UNCHOKE,
             MSGID_INTERESTED,
             MSGID_NOT_INTERESTED,
+            MSGID_HAVE_ALL,
+            MSGID_HAVE_NONE,
         ] {
             buf[0..4].copy_from_slice(&1u32.to_be_bytes());
             buf[4] = msgid;
@@ -784,7 +891,9 @@ mod tests {
                     (MSGID_CHOKE, Message::Choke)
                     | (MSGID_UNCHOKE, Message::Unchoke)
                     | (MSGID_INTERESTED, Message::Interested)
-                    | (MSGID_NOT_INTERESTED, Message::NotInterested) => {}
+                    | (MSGID_NOT_INTERESTED, Message::NotInterested)
+                    | (MSGID_HAVE_ALL, Message::HaveAll)
+                    | (MSGID_HAVE_NONE, Message::HaveNone) => {}
                     (msgid, msg) => panic!("msgid={msgid}, msg={msg:?}"),
                 }
                 assert_eq!(len, 5);
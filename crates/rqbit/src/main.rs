@@ -14,11 +14,13 @@ use clap::{CommandFactory, Parser, ValueEnum};
 use clap_complete::Shell;
 use librqbit::{
     AddTorrent, AddTorrentOptions, AddTorrentResponse, Api, ConnectionOptions,
-    CreateTorrentOptions, ListOnlyResponse, ListenerMode, ListenerOptions, PeerConnectionOptions,
-    Session, SessionOptions, SessionPersistenceConfig, TorrentStatsState,
+    CreateTorrentOptions, EncryptionPolicy, ListOnlyResponse, ListenerMode, ListenerOptions,
+    PeerConnectionOptions, Session, SessionOptions, SessionPersistenceConfig, TorrentStatsState,
     http_api::{HttpApi, HttpApiOptions},
+    http_api_client::HttpApiClient,
     librqbit_spawn,
     limits::LimitsConfig,
+    spawn_utils::BlockingSpawner,
     storage::{
         StorageFactory, StorageFactoryExt,
         filesystem::{FilesystemStorageFactory, MmapFilesystemStorageFactory},
@@ -39,6 +41,13 @@ enum LogLevel {
     Error,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Encryption {
+    Disabled,
+    Prefer,
+    Require,
+}
+
 #[cfg(not(target_os = "windows"))]
 fn parse_umask(value: &str) -> anyhow::Result<libc::mode_t> {
     fn parse_oct_digit(d: u8) -> Option<libc::mode_t> {
@@ -65,6 +74,14 @@ fn parse_umask(value: &str) -> anyhow::Result<libc::mode_t> {
     Ok(output)
 }
 
+fn parse_peer_id_prefix(value: &str) -> anyhow::Result<[u8; 8]> {
+    value
+        .as_bytes()
+        .try_into()
+        .ok()
+        .context("expected 8 characters")
+}
+
 #[derive(Parser)]
 #[command(version, author, about)]
 struct Opts {
@@ -138,6 +155,11 @@ struct Opts {
     #[arg(long = "peer-limit", env = "RQBIT_PEER_LIMIT")]
     peer_limit: Option<usize>,
 
+    /// The maximum number of outgoing connections per torrent that haven't completed
+    /// the handshake yet.
+    #[arg(long = "max-half-open", env = "RQBIT_MAX_HALF_OPEN")]
+    max_half_open: Option<usize>,
+
     /// How many threads to spawn for the executor.
     #[arg(short = 't', long, env = "RQBIT_RUNTIME_WORKER_THREADS")]
     worker_threads: Option<usize>,
@@ -201,10 +223,39 @@ struct Opts {
     #[arg(long = "bind-device", env = "RQBIT_BIND_DEVICE")]
     bind_device_name: Option<String>,
 
+    /// The first 8 bytes of the peer ID, e.g. "-qB4650-", for private trackers that only
+    /// allow some clients.
+    #[arg(long = "peer-id-prefix", value_parser = parse_peer_id_prefix, env = "RQBIT_PEER_ID_PREFIX")]
+    peer_id_prefix: Option<[u8; 8]>,
+
+    /// The User-Agent header to send to HTTP trackers.
+    #[arg(long = "tracker-user-agent", env = "RQBIT_TRACKER_USER_AGENT")]
+    tracker_user_agent: Option<String>,
+
+    /// Delay the first tracker announce of each torrent by a random duration up to this,
+    /// e.g. 30s, and add up to this much to the following ones. Avoids announcing all
+    /// torrents at once on startup.
+    #[arg(long = "tracker-announce-jitter", value_parser = parse_duration::parse, env = "RQBIT_TRACKER_ANNOUNCE_JITTER")]
+    tracker_announce_jitter: Option<Duration>,
+
+    /// Pause torrents that had no connected peers and made no progress for this long,
+    /// e.g. 1h. Finished torrents keep seeding.
+    #[arg(long = "idle-pause-timeout", value_parser = parse_duration::parse, env = "RQBIT_IDLE_PAUSE_TIMEOUT")]
+    idle_pause_timeout: Option<Duration>,
+
     /// Force IPv4 only.
     #[arg(long = "ipv4-only", env = "RQBIT_IPV4_ONLY")]
     ipv4_only: bool,
 
+    /// Encrypt peer connections with MSE. "prefer" falls back to plaintext for peers that
+    /// don't support it, "require" only allows encrypted connections.
+    #[arg(
+        long = "encryption",
+        default_value = "disabled",
+        env = "RQBIT_ENCRYPTION"
+    )]
+    encryption: Encryption,
+
     #[command(subcommand)]
     subcommand: SubCommand,
 
@@ -230,6 +281,11 @@ struct Opts {
     #[arg(long, env = "RQBIT_SOCKS_PROXY_URL")]
     socks_url: Option<String>,
 
+    /// Also send DHT and UDP tracker traffic through the socks5 proxy (UDP ASSOCIATE).
+    /// Without it, DHT and UDP trackers are disabled when a proxy is set.
+    #[arg(long, env = "RQBIT_SOCKS_PROXY_UDP", requires = "socks_url")]
+    socks_udp: bool,
+
     /// How many torrents can be initializing (rehashing) at the same time
     #[arg(long, default_value = "5", env = "RQBIT_CONCURRENT_INIT_LIMIT")]
     concurrent_init_limit: usize,
@@ -357,6 +413,15 @@ struct DownloadOpts {
     #[arg(long)]
     overwrite: bool,
 
+    /// Download pieces in order, e.g. to play media files while they are downloading.
+    #[arg(long)]
+    sequential: bool,
+
+    /// Super-seeding (BEP 16): offer peers one piece at a time once the torrent is complete.
+    /// Useful when being the first to upload new content.
+    #[arg(long)]
+    super_seeding: bool,
+
     /// Exit the program once the torrents complete download.
     #[arg(short = 'e', long)]
     exit_on_finish: bool,
@@ -405,14 +470,59 @@ struct ShareOpts {
     trackers: Vec<url::Url>,
 }
 
+#[derive(Parser)]
+struct CreateOpts {
+    /// The file or directory to create a torrent from
+    path: PathBuf,
+
+    /// Where to write the .torrent file. Defaults to "<name>.torrent" in the current directory.
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
+
+    /// Optional torrent name to use in the torrent file and magnet.
+    #[arg(short = 'n', long)]
+    name: Option<String>,
+
+    /// Piece length in bytes, a power of two and at least 16384. By default it's picked from
+    /// the total size.
+    #[arg(long)]
+    piece_length: Option<u32>,
+
+    /// Mark the torrent private, so that peers are only found through its trackers.
+    #[arg(long)]
+    private: bool,
+
+    /// A comment to put in the torrent file.
+    #[arg(long)]
+    comment: Option<String>,
+
+    /// Tracker URLs (comma separated), written to the torrent file in this order.
+    #[arg(value_delimiter = ',', num_args = 0..32)]
+    trackers: Vec<url::Url>,
+}
+
+#[derive(Parser)]
+struct DeleteOpts {
+    /// The id or info hash of the torrent.
+    id_or_infohash: String,
+
+    /// Also delete the files of the torrent. Otherwise they are kept on disk.
+    #[arg(long)]
+    delete_files: bool,
+}
+
 #[derive(Parser)]
 enum SubCommand {
     /// Start rqbit server with HTTP API.
     Server(ServerOpts),
     /// Create a torrent from a given path and announce it. Stateless.
     Share(ShareOpts),
+    /// Create a .torrent file from a given path and print its magnet link, without sharing it.
+    Create(CreateOpts),
     /// Download a single torrent, stateless.
     Download(DownloadOpts),
+    /// Remove a torrent from a running rqbit server, at --http-api-listen-addr.
+    Delete(DeleteOpts),
     /// Shell completions. eval "$(rqbit completions bash)"
     Completions(CompletionsOpts),
 }
@@ -579,6 +689,11 @@ async fn async_main(mut opts: Opts, cancel: CancellationToken) -> anyhow::Result
         Err(e) => warn!("failed increasing open file limit: {:#}", e),
     };
 
+    // The torrent file only gets the trackers given for it, not the ones rqbit uses itself.
+    if let SubCommand::Create(create_opts) = &opts.subcommand {
+        return create_torrent_file(create_opts).await;
+    }
+
     let trackers = if let Some(f) = &opts.trackers_filename {
         parse_trackers_file(f)
             .await
@@ -588,6 +703,10 @@ async fn async_main(mut opts: Opts, cancel: CancellationToken) -> anyhow::Result
         Default::default()
     };
 
+    if let SubCommand::Delete(delete_opts) = &opts.subcommand {
+        return delete_torrent(delete_opts, opts.http_api_listen_addr).await;
+    }
+
     let listen_mode = match (!opts.disable_tcp_listen, opts.enable_utp_listen) {
         (true, false) => Some(ListenerMode::TcpOnly),
         (false, true) => Some(ListenerMode::UtpOnly),
@@ -614,9 +733,14 @@ async fn async_main(mut opts: Opts, cancel: CancellationToken) -> anyhow::Result
         // This will be overridden by "server start" below if needed.
         persistence: None,
         peer_id: None,
+        peer_id_prefix: opts.peer_id_prefix,
+        tracker_user_agent: opts.tracker_user_agent.take(),
+        tracker_announce_jitter: opts.tracker_announce_jitter,
+        idle_pause_timeout: opts.idle_pause_timeout,
         listen,
         connect: Some(ConnectionOptions {
             proxy_url: opts.socks_url.take(),
+            proxy_udp: opts.socks_udp,
             enable_tcp: !opts.disable_tcp_connect,
             peer_opts: Some(PeerConnectionOptions {
                 connect_timeout: Some(opts.peer_connect_timeout),
@@ -660,21 +784,20 @@ async fn async_main(mut opts: Opts, cancel: CancellationToken) -> anyhow::Result
         disable_trackers: opts.disable_trackers,
         trackers,
         peer_limit: opts.peer_limit,
+        max_half_open: opts.max_half_open,
         runtime_worker_threads: Some(opts.max_blocking_threads as usize),
         ipv4_only: opts.ipv4_only,
+        encryption: match opts.encryption {
+            Encryption::Disabled => EncryptionPolicy::Disabled,
+            Encryption::Prefer => EncryptionPolicy::Prefer,
+            Encryption::Require => EncryptionPolicy::Require,
+        },
     };
 
     #[allow(clippy::needless_update)]
     let mut http_api_opts = HttpApiOptions {
         read_only: true,
-        basic_auth: if let Ok(up) = std::env::var("RQBIT_HTTP_BASIC_AUTH_USERPASS") {
-            let (u, p) = up
-                .split_once(":")
-                .context("basic auth credentials should be in format username:password")?;
-            Some((u.to_owned(), p.to_owned()))
-        } else {
-            None
-        },
+        basic_auth: basic_auth_from_env()?,
         allow_create: opts.http_api_allow_create,
 
         // We need to install prometheus recorder early before we registered any metrics.
@@ -776,6 +899,8 @@ async fn async_main(mut opts: Opts, cancel: CancellationToken) -> anyhow::Result
             let torrent_opts = || AddTorrentOptions {
                 only_files_regex: download_opts.only_files_matching_regex.clone(),
                 overwrite: download_opts.overwrite,
+                sequential: download_opts.sequential,
+                super_seeding: download_opts.super_seeding,
                 list_only: download_opts.list,
                 force_tracker_interval: opts.force_tracker_interval,
                 sub_folder: download_opts.sub_folder.clone(),
@@ -952,8 +1077,97 @@ async fn async_main(mut opts: Opts, cancel: CancellationToken) -> anyhow::Result
 
             http_api_fut.await
         }
-        SubCommand::Completions(_) => unreachable!(),
+        SubCommand::Create(_) | SubCommand::Delete(_) | SubCommand::Completions(_) => {
+            unreachable!()
+        }
+    }
+}
+
+// The credentials the HTTP API is protected with, if any.
+fn basic_auth_from_env() -> anyhow::Result<Option<(String, String)>> {
+    let Ok(up) = std::env::var("RQBIT_HTTP_BASIC_AUTH_USERPASS") else {
+        return Ok(None);
+    };
+    let (u, p) = up
+        .split_once(":")
+        .context("basic auth credentials should be in format username:password")?;
+    Ok(Some((u.to_owned(), p.to_owned())))
+}
+
+async fn delete_torrent(
+    delete_opts: &DeleteOpts,
+    http_api_listen_addr: Option<SocketAddr>,
+) -> anyhow::Result<()> {
+    let mut addr = http_api_listen_addr.unwrap_or((Ipv4Addr::LOCALHOST, 3030).into());
+    // A server listening on all addresses is reachable on loopback.
+    if addr.ip().is_unspecified() {
+        addr.set_ip(match addr.ip() {
+            IpAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            IpAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
     }
+    let mut client = HttpApiClient::new(&format!("http://{addr}/"))?;
+    if let Some((username, password)) = basic_auth_from_env()? {
+        client = client.with_basic_auth(username, password);
+    }
+    client
+        .validate_rqbit_server()
+        .await
+        .with_context(|| format!("error connecting to rqbit server at {addr}"))?;
+    client
+        .delete_torrent(&delete_opts.id_or_infohash, delete_opts.delete_files)
+        .await?;
+    if delete_opts.delete_files {
+        info!(
+            "deleted torrent {} and its files",
+            delete_opts.id_or_infohash
+        );
+    } else {
+        info!(
+            "deleted torrent {}, kept its files",
+            delete_opts.id_or_infohash
+        );
+    }
+    Ok(())
+}
+
+async fn create_torrent_file(create_opts: &CreateOpts) -> anyhow::Result<()> {
+    let trackers = create_opts.trackers.iter().map(|t| t.to_string()).collect();
+    let torrent = librqbit::create_torrent(
+        &create_opts.path,
+        CreateTorrentOptions {
+            name: create_opts.name.as_deref(),
+            trackers,
+            piece_length: create_opts.piece_length,
+            private: create_opts.private,
+            comment: create_opts.comment.as_deref(),
+            ..Default::default()
+        },
+        &BlockingSpawner::new(1),
+    )
+    .await
+    .context("error creating torrent")?;
+
+    let output = match create_opts.output.as_ref() {
+        Some(output) => output.clone(),
+        None => {
+            let name = torrent
+                .as_info()
+                .info
+                .data
+                .name
+                .as_ref()
+                .map(|n| String::from_utf8_lossy(n.as_ref()))
+                .unwrap_or("torrent".into());
+            PathBuf::from(format!("{name}.torrent"))
+        }
+    };
+    tokio::fs::write(&output, torrent.as_bytes()?)
+        .await
+        .with_context(|| format!("error writing {output:?}"))?;
+    info!(path = ?output, info_hash = ?torrent.info_hash(), "created torrent file");
+    println!("{}", torrent.as_magnet());
+    Ok(())
 }
 
 async fn start_http_api(
@@ -1001,6 +1215,8 @@ async fn start_http_api(
                             format!("rqbit@{}", gethostname::gethostname().to_string_lossy())
                         }),
                         listen_addr.port(),
+                        // IPv6 clients can only reach the HTTP API if it listens on IPv6.
+                        listen_addr.is_ipv6() && !opts.ipv4_only,
                     )
                     .await
                     .context("error starting UPNP server")?;
@@ -3,4 +3,4 @@ mod tracker_comms_http;
 mod tracker_comms_udp;
 
 pub use tracker_comms::*;
-pub use tracker_comms_udp::UdpTrackerClient;
+pub use tracker_comms_udp::{MAX_SCRAPE_INFO_HASHES, ScrapeStats, TrackerAddr, UdpTrackerClient};
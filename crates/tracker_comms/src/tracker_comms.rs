@@ -1,41 +1,152 @@
 use std::collections::HashSet;
+use std::net::Ipv6Addr;
 use std::net::SocketAddr;
 use std::net::SocketAddrV4;
 use std::net::SocketAddrV6;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Context;
-use anyhow::bail;
-use backon::ExponentialBuilder;
-use backon::Retryable;
 use futures::FutureExt;
 use futures::StreamExt;
-use futures::future::Either;
+use futures::future::BoxFuture;
 use futures::stream::BoxStream;
 use futures::stream::FuturesUnordered;
+use rand::seq::SliceRandom;
 use tracing::Instrument;
 use tracing::debug;
 use tracing::debug_span;
 use tracing::trace;
 use tracing::trace_span;
+use tracing::warn;
 use url::Url;
 
 use crate::tracker_comms_http;
 use crate::tracker_comms_udp;
+use crate::tracker_comms_udp::ScrapeStats;
+use crate::tracker_comms_udp::TrackerAddr;
 use crate::tracker_comms_udp::UdpTrackerClient;
 use librqbit_core::hash_id::Id20;
+use parking_lot::Mutex;
+
+// The "stopped" announce is sent in the background when the torrent is paused or removed,
+// so don't let it linger for slow or unreachable trackers.
+const STOPPED_ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long to wait before trying a tracker again after it failed. Doubles on every consecutive
+// failure up to the max, and then keeps being retried at the max.
+const MIN_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(600);
+
+pub const DEFAULT_ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The global unicast IPv6 address this host would use to reach the internet, if any.
+///
+/// Nothing is sent: connecting a UDP socket only makes the OS pick the route and source address.
+pub fn global_ipv6_addr() -> Option<Ipv6Addr> {
+    let sock = std::net::UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)).ok()?;
+    // Any address in 2000::/3 works, this one is Google's public DNS.
+    sock.connect((
+        Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888),
+        443,
+    ))
+    .ok()?;
+    match sock.local_addr().ok()?.ip() {
+        std::net::IpAddr::V6(ip) if is_global_unicast(&ip) => Some(ip),
+        _ => None,
+    }
+}
+
+pub fn is_global_unicast(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xe000 == 0x2000
+}
+
+/// Which IPv6 address, if any, to send to HTTP trackers as "ipv6=".
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnnounceIpv6 {
+    #[default]
+    None,
+    /// Always announce this address, e.g. the one we listen on.
+    Fixed(Ipv6Addr),
+    /// Look up [global_ipv6_addr] before every announce, as it can change while we run
+    /// (e.g. with privacy extensions or after a network change).
+    Global,
+}
+
+impl AnnounceIpv6 {
+    fn resolve(&self) -> Option<Ipv6Addr> {
+        match self {
+            AnnounceIpv6::None => None,
+            AnnounceIpv6::Fixed(ip) => Some(*ip),
+            AnnounceIpv6::Global => global_ipv6_addr(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TrackerCommsOptions {
+    /// Announce with this interval instead of the one returned by trackers. Also used as the
+    /// minimum delay before retrying a failed tracker.
+    pub force_interval: Option<Duration>,
+    /// Give up on an HTTP announce that takes longer than this.
+    pub announce_timeout: Duration,
+    /// Skip UDP trackers, e.g. when the traffic should go through a proxy that only does TCP.
+    pub disable_udp: bool,
+    /// Delay the first announce by a random duration up to this, and add up to this much to
+    /// the following ones, so that many torrents started at once don't announce all at once.
+    pub announce_jitter: Duration,
+    /// Sent to HTTP trackers as "ipv6=", so that peers can reach us over IPv6 even if the
+    /// tracker is only reachable over IPv4.
+    pub announce_ipv6: AnnounceIpv6,
+}
+
+impl Default for TrackerCommsOptions {
+    fn default() -> Self {
+        Self {
+            force_interval: None,
+            announce_timeout: DEFAULT_ANNOUNCE_TIMEOUT,
+            disable_udp: false,
+            announce_jitter: Duration::ZERO,
+            announce_ipv6: AnnounceIpv6::None,
+        }
+    }
+}
+
+impl TrackerCommsOptions {
+    // Delay before retrying a tracker that failed this many times in a row.
+    fn retry_interval(&self, failures: u32) -> Duration {
+        let min = self.force_interval.unwrap_or(MIN_RETRY_INTERVAL);
+        min.saturating_mul(1 << failures.saturating_sub(1).min(16))
+            .min(MAX_RETRY_INTERVAL.max(min))
+    }
+
+    fn with_jitter(&self, interval: Duration) -> Duration {
+        if self.announce_jitter.is_zero() {
+            return interval;
+        }
+        interval.saturating_add(self.announce_jitter.mul_f64(rand::random::<f64>()))
+    }
+}
 
 pub struct TrackerComms {
     info_hash: Id20,
     peer_id: Id20,
     stats: Box<dyn TorrentStatsProvider>,
-    force_tracker_interval: Option<Duration>,
+    opts: TrackerCommsOptions,
     tx: Sender,
     // This MUST be set as trackers don't work with 0 port.
     announce_port: u16,
     reqwest_client: reqwest::Client,
     key: u32,
+    // Trackers that accepted our "started" announce. These get sent "stopped" when the
+    // comms are dropped.
+    announced: Mutex<Vec<AnnouncedTracker>>,
+}
+
+enum AnnouncedTracker {
+    Http(Url),
+    Udp(TrackerAddr),
 }
 
 #[derive(Default)]
@@ -70,8 +181,52 @@ impl TrackerCommsStats {
     }
 }
 
+/// Swarm size reported by a tracker.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SwarmStats {
+    pub seeders: u32,
+    pub leechers: u32,
+    /// Completed downloads. Only scrape responses have this, announce responses don't.
+    pub downloaded: Option<u32>,
+}
+
+impl From<ScrapeStats> for SwarmStats {
+    fn from(s: ScrapeStats) -> Self {
+        Self {
+            seeders: s.seeders,
+            leechers: s.leechers,
+            downloaded: Some(s.completed),
+        }
+    }
+}
+
+impl From<&tracker_comms_http::ScrapeFile> for SwarmStats {
+    fn from(f: &tracker_comms_http::ScrapeFile) -> Self {
+        Self {
+            seeders: saturating_u32(f.complete),
+            leechers: saturating_u32(f.incomplete),
+            downloaded: Some(saturating_u32(f.downloaded)),
+        }
+    }
+}
+
+fn saturating_u32(v: u64) -> u32 {
+    v.try_into().unwrap_or(u32::MAX)
+}
+
 pub trait TorrentStatsProvider: Send + Sync {
     fn get(&self) -> TrackerCommsStats;
+
+    /// Called after every successful announce with the swarm size the tracker reported. The
+    /// tracker is scraped right after the announce, so this includes completed downloads if
+    /// the tracker supports scrape.
+    fn on_swarm_stats(&self, _tracker: &Url, _stats: SwarmStats) {}
+
+    /// Resolves when the torrent finishes downloading, so that the "completed" event can be
+    /// announced right away instead of on the next regular announce.
+    fn wait_until_completed(&self) -> BoxFuture<'static, ()> {
+        futures::future::pending().boxed()
+    }
 }
 
 impl TorrentStatsProvider for () {
@@ -87,6 +242,23 @@ enum SupportedTracker {
     Http(Url),
 }
 
+struct TierTracker {
+    tracker: SupportedTracker,
+    // If we announced "started" to it.
+    started: bool,
+    // Consecutive failed announces, and when to try again after the last one.
+    failures: u32,
+    retry_at: Option<Instant>,
+    // The last addresses a UDP tracker resolved to, used when resolving fails.
+    udp_addrs: Option<UdpTrackerResolveResult>,
+}
+
+impl std::fmt::Debug for TierTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.tracker, f)
+    }
+}
+
 impl std::fmt::Debug for SupportedTracker {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -138,35 +310,64 @@ async fn udp_tracker_to_socket_addrs(
 }
 
 impl TrackerComms {
+    /// Announce to the trackers of all tiers (BEP-12) and stream the peers they return.
+    ///
+    /// Tiers are announced to in parallel. Within a tier, trackers are shuffled and then tried
+    /// one by one until one responds. That one is moved to the front of its tier, so it's
+    /// tried first next time. A tracker present in several tiers is only used in the first one.
+    ///
+    /// A tracker that fails is skipped with exponential backoff, up to MAX_RETRY_INTERVAL.
+    /// Trackers are never given up on, as they are often down only temporarily.
     // TODO: fix too many args
     #[allow(clippy::too_many_arguments)]
     pub fn start(
         info_hash: Id20,
         peer_id: Id20,
-        trackers: HashSet<Url>,
+        tiers: Vec<Vec<Url>>,
         stats: Box<dyn TorrentStatsProvider>,
-        force_interval: Option<Duration>,
+        opts: TrackerCommsOptions,
         announce_port: u16,
         reqwest_client: reqwest::Client,
         udp_client: UdpTrackerClient,
     ) -> Option<BoxStream<'static, SocketAddr>> {
-        let trackers = trackers
+        let mut seen = HashSet::new();
+        let tiers = tiers
             .into_iter()
-            .filter_map(|t| match t.scheme() {
-                "http" | "https" => Some(SupportedTracker::Http(t)),
-                "udp" => Some(SupportedTracker::Udp(t)),
-                _ => {
-                    debug!("unsupported tracker URL: {}", t);
-                    None
-                }
+            .map(|tier| {
+                let mut tier = tier
+                    .into_iter()
+                    .filter(|t| seen.insert(t.clone()))
+                    .filter_map(|t| match t.scheme() {
+                        "http" | "https" => Some(SupportedTracker::Http(t)),
+                        "udp" if opts.disable_udp => {
+                            warn!("UDP trackers are disabled, skipping {}", t);
+                            None
+                        }
+                        "udp" => Some(SupportedTracker::Udp(t)),
+                        _ => {
+                            debug!("unsupported tracker URL: {}", t);
+                            None
+                        }
+                    })
+                    .map(|tracker| TierTracker {
+                        tracker,
+                        started: false,
+                        failures: 0,
+                        retry_at: None,
+                        udp_addrs: None,
+                    })
+                    .collect::<Vec<_>>();
+                tier.shuffle(&mut rand::rng());
+                tier
             })
+            .filter(|tier| !tier.is_empty())
             .collect::<Vec<_>>();
-        if trackers.is_empty() {
+        if tiers.is_empty() {
             debug!(?info_hash, "trackers list is empty");
             return None;
         }
 
-        tracing::trace!(?trackers);
+        tracing::trace!(?tiers);
 
         let (tx, mut rx) = tokio::sync::mpsc::channel::<SocketAddr>(16);
 
@@ -176,15 +377,21 @@ impl TrackerComms {
                 info_hash,
                 peer_id,
                 stats,
-                force_tracker_interval: force_interval,
+                opts,
                 tx,
                 announce_port,
                 reqwest_client,
                 key: rand::random(),
+                announced: Default::default(),
             });
+            let _stopped_guard = StoppedAnnounceGuard {
+                comms: comms.clone(),
+                udp_client: udp_client.clone(),
+            };
             let mut futures = FuturesUnordered::new();
-            for tracker in trackers {
-                futures.push(comms.add_tracker(tracker, &udp_client))
+            for tier in tiers {
+                let span = debug_span!(parent: None, "tracker_tier", info_hash = ?info_hash);
+                futures.push(comms.task_tier_monitor(tier, &udp_client).instrument(span))
             }
             while !(futures.is_empty()) {
                 tokio::select! {
@@ -205,58 +412,119 @@ impl TrackerComms {
         Some(s.boxed())
     }
 
-    fn add_tracker(
+    async fn task_tier_monitor(
         &self,
-        url: SupportedTracker,
-        client: &UdpTrackerClient,
-    ) -> Either<
-        impl std::future::Future<Output = anyhow::Result<()>> + '_ + Send,
-        impl std::future::Future<Output = anyhow::Result<()>> + '_ + Send,
-    > {
-        let info_hash = self.info_hash;
-        match url {
-            SupportedTracker::Udp(url) => {
-                let span = debug_span!(parent: None, "udp_tracker", tracker = %url, info_hash = ?info_hash);
-                self.task_single_tracker_monitor_udp(url, client.clone())
-                    .instrument(span)
-                    .right_future()
+        mut tier: Vec<TierTracker>,
+        udp_client: &UdpTrackerClient,
+    ) -> anyhow::Result<()> {
+        use tracker_comms_http::TrackerRequestEvent;
+
+        trace!(?tier, "starting monitor");
+        let mut completed = self.completed_watcher();
+        let mut announce_completed = false;
+
+        let delay = self.opts.with_jitter(Duration::ZERO);
+        if !delay.is_zero() {
+            debug!(?delay, "delaying the first announce");
+            tokio::time::sleep(delay).await;
+        }
+
+        loop {
+            let mut interval = None;
+            for idx in 0..tier.len() {
+                let t = &mut tier[idx];
+                if t.retry_at.is_some_and(|at| at > Instant::now()) {
+                    continue;
+                }
+                let event = if !t.started {
+                    Some(TrackerRequestEvent::Started)
+                } else if announce_completed {
+                    Some(TrackerRequestEvent::Completed)
+                } else {
+                    None
+                };
+                let span = debug_span!("announce", tracker = ?t.tracker);
+                match self.announce(t, event, udp_client).instrument(span).await {
+                    Ok(i) => {
+                        t.started = true;
+                        t.failures = 0;
+                        t.retry_at = None;
+                        tier[..=idx].rotate_right(1);
+                        interval = Some(i);
+                        break;
+                    }
+                    Err(e) => {
+                        t.failures += 1;
+                        let retry_in = self.opts.retry_interval(t.failures);
+                        t.retry_at = Some(Instant::now() + retry_in);
+                        debug!(
+                            tracker = ?t.tracker,
+                            failures = t.failures,
+                            ?retry_in,
+                            "error calling tracker: {e:#}"
+                        );
+                    }
+                }
             }
-            SupportedTracker::Http(url) => {
-                let span = debug_span!(
-                    parent: None,
-                    "http_tracker",
-                    tracker = %url,
-                    info_hash = ?info_hash
-                );
-                self.task_single_tracker_monitor_http(url)
-                    .instrument(span)
-                    .left_future()
+
+            let interval = match interval {
+                Some(interval) => {
+                    announce_completed = false;
+                    self.opts
+                        .with_jitter(self.opts.force_interval.unwrap_or(interval))
+                }
+                // Wait for the first tracker that can be retried.
+                None => tier
+                    .iter()
+                    .filter_map(|t| t.retry_at)
+                    .min()
+                    .map(|at| at.saturating_duration_since(Instant::now()))
+                    .unwrap_or(MIN_RETRY_INTERVAL),
+            };
+            debug!("sleeping for {:?} after calling tracker", interval);
+            if self.sleep_or_completed(interval, &mut completed).await {
+                announce_completed = true;
             }
         }
     }
 
-    async fn task_single_tracker_monitor_http(&self, tracker_url: Url) -> anyhow::Result<()> {
-        trace!(url=%tracker_url, "starting monitor");
-        let mut event = Some(tracker_comms_http::TrackerRequestEvent::Started);
-
-        loop {
-            let interval = (|| self.tracker_one_request_http(&tracker_url, event))
-                .retry(
-                    ExponentialBuilder::new()
-                        .without_max_times()
-                        .with_jitter()
-                        .with_factor(2.)
-                        .with_min_delay(Duration::from_secs(10))
-                        .with_max_delay(Duration::from_secs(600)),
-                )
-                .notify(|err, retry_in| debug!(?retry_in, "error calling tracker: {err:#}"))
-                .await
-                .context("this shouldn't fail")?;
+    async fn announce(
+        &self,
+        tracker: &mut TierTracker,
+        event: Option<tracker_comms_http::TrackerRequestEvent>,
+        udp_client: &UdpTrackerClient,
+    ) -> anyhow::Result<Duration> {
+        use tracker_comms_http::TrackerRequestEvent;
 
-            event = None;
-            let interval = self.force_tracker_interval.unwrap_or(interval);
-            debug!("sleeping for {:?} after calling tracker", interval);
-            tokio::time::sleep(interval).await;
+        match &tracker.tracker {
+            SupportedTracker::Http(url) => {
+                let (interval, mut swarm) = self.tracker_one_request_http(url, event).await?;
+                if event == Some(TrackerRequestEvent::Started) {
+                    self.announced
+                        .lock()
+                        .push(AnnouncedTracker::Http(url.clone()));
+                }
+                match self.tracker_scrape_http(url).await {
+                    Ok(Some(scraped)) => swarm = scraped,
+                    Ok(None) => {}
+                    Err(e) => debug!("error scraping: {e:#}"),
+                }
+                self.stats.on_swarm_stats(url, swarm);
+                Ok(interval)
+            }
+            SupportedTracker::Udp(url) => {
+                let event = match event {
+                    None => tracker_comms_udp::EVENT_NONE,
+                    Some(TrackerRequestEvent::Started) => tracker_comms_udp::EVENT_STARTED,
+                    Some(TrackerRequestEvent::Completed) => tracker_comms_udp::EVENT_COMPLETED,
+                    Some(TrackerRequestEvent::Stopped) => tracker_comms_udp::EVENT_STOPPED,
+                };
+                let (interval, swarm) = self
+                    .tracker_announce_udp(url, &mut tracker.udp_addrs, event, udp_client)
+                    .await?;
+                self.stats.on_swarm_stats(url, swarm);
+                Ok(interval)
+            }
         }
     }
 
@@ -264,7 +532,7 @@ impl TrackerComms {
         &self,
         tracker_url: &Url,
         event: Option<tracker_comms_http::TrackerRequestEvent>,
-    ) -> anyhow::Result<Duration> {
+    ) -> anyhow::Result<(Duration, SwarmStats)> {
         let stats = self.stats.get();
         let request = tracker_comms_http::TrackerRequest {
             info_hash: &self.info_hash,
@@ -277,6 +545,7 @@ impl TrackerComms {
             no_peer_id: false,
             event,
             ip: None,
+            ipv6: self.opts.announce_ipv6.resolve(),
             numwant: None,
             key: Some(self.key),
             trackerid: None,
@@ -290,11 +559,7 @@ impl TrackerComms {
         }
         url.set_query(Some(&queries));
 
-        let response: reqwest::Response = self.reqwest_client.get(url).send().await?;
-        if !response.status().is_success() {
-            anyhow::bail!("tracker responded with {:?}", response.status());
-        }
-        let bytes = response.bytes().await?;
+        let bytes = self.http_get(url).await?;
         if let Ok((error, _)) =
             bencode::from_bytes_with_rest::<tracker_comms_http::TrackerError>(&bytes)
         {
@@ -310,90 +575,142 @@ impl TrackerComms {
             })?
             .0;
 
-        for peer in response.iter_peers() {
-            self.tx.send(peer).await?;
+        // When stopping, nobody is listening for peers anymore.
+        if event != Some(tracker_comms_http::TrackerRequestEvent::Stopped) {
+            for peer in response.iter_peers() {
+                self.tx.send(peer).await?;
+            }
         }
-        Ok(Duration::from_secs(
-            response.min_interval.unwrap_or(response.interval),
+        Ok((
+            Duration::from_secs(response.min_interval.unwrap_or(response.interval)),
+            SwarmStats {
+                seeders: saturating_u32(response.complete),
+                leechers: saturating_u32(response.incomplete),
+                downloaded: None,
+            },
         ))
     }
 
-    async fn task_single_tracker_monitor_udp(
-        &self,
-        url: Url,
-        client: UdpTrackerClient,
-    ) -> anyhow::Result<()> {
-        if url.scheme() != "udp" {
-            bail!("expected UDP scheme in {}", url);
+    // Returns None if the tracker doesn't support scrape.
+    async fn tracker_scrape_http(&self, tracker_url: &Url) -> anyhow::Result<Option<SwarmStats>> {
+        let Some(mut url) = tracker_comms_http::scrape_url(tracker_url) else {
+            return Ok(None);
+        };
+        let mut queries = format!(
+            "info_hash={}",
+            urlencoding::encode_binary(&self.info_hash.0)
+        );
+        if let Some(url_query) = url.query() {
+            queries.push_str(&format!("&{}", url_query));
         }
+        url.set_query(Some(&queries));
+
+        let bytes = self.http_get(url).await?;
+        let response = bencode::from_bytes_with_rest::<tracker_comms_http::ScrapeResponse>(&bytes)
+            .map_err(|e| e.into_kind())?
+            .0;
+        let file = response
+            .files
+            .get(&self.info_hash)
+            .context("torrent missing from scrape response")?;
+        Ok(Some(file.into()))
+    }
+
+    async fn http_get(&self, url: Url) -> anyhow::Result<bytes::Bytes> {
+        tokio::time::timeout(self.opts.announce_timeout, async {
+            let response: reqwest::Response = self.reqwest_client.get(url).send().await?;
+            if !response.status().is_success() {
+                anyhow::bail!("tracker responded with {:?}", response.status());
+            }
+            Ok(response.bytes().await?)
+        })
+        .await
+        .with_context(|| format!("timed out after {:?}", self.opts.announce_timeout))?
+    }
+
+    async fn tracker_announce_udp(
+        &self,
+        url: &Url,
+        prev_addrs: &mut Option<UdpTrackerResolveResult>,
+        event: u32,
+        client: &UdpTrackerClient,
+    ) -> anyhow::Result<(Duration, SwarmStats)> {
         let (host, port) = (
             url.host().context("missing host")?,
             url.port().context("missing port")?,
         );
-
-        let mut sleep_interval: Option<Duration> = None;
-        let mut prev_addrs: Option<UdpTrackerResolveResult> = None;
-        loop {
-            if let Some(i) = sleep_interval {
-                trace!(interval=?sleep_interval, "sleeping");
-                tokio::time::sleep(i).await;
+        // Let the proxy resolve the hostname, looking it up here would bypass the proxy.
+        if client.is_proxied()
+            && let url::Host::Domain(name) = &host
+        {
+            return self
+                .tracker_announce_udp_one(TrackerAddr::Host(name.to_string(), port), client, event)
+                .await;
+        }
+        let addrs = match udp_tracker_to_socket_addrs(host.clone(), port)
+            .instrument(trace_span!("resolve", ?host))
+            .await
+        {
+            Ok(addrs) => *prev_addrs.insert(addrs),
+            Err(e) => {
+                let addrs = prev_addrs.ok_or(e)?;
+                debug!(
+                    ?addrs,
+                    "error resolving tracker, using the previous addresses"
+                );
+                addrs
             }
+        };
 
-            // This should retry forever until the addrs are resolved.
-            let addrs = (async || {
-                udp_tracker_to_socket_addrs(host.clone(), port)
-                    .instrument(trace_span!("resolve", ?host))
+        match addrs {
+            UdpTrackerResolveResult::One(addr) => {
+                self.tracker_announce_udp_one(addr.into(), client, event)
                     .await
-                    .or_else(|err| prev_addrs.ok_or(err))
-            })
-            .retry(
-                ExponentialBuilder::new()
-                    .without_max_times()
-                    .with_max_delay(Duration::from_secs(60))
-                    .with_jitter(),
-            )
-            .notify(|err, retry| debug!(retry_in=?retry, "error resolving tracker: {err:#}"))
-            .await
-            .context("this shouldn't happen: failed resolving tracker addrs")?;
-
-            prev_addrs = Some(addrs);
-
-            match addrs {
-                UdpTrackerResolveResult::One(addr) => {
-                    match self
-                        .tracker_one_request_udp(addr, &client)
-                        .instrument(trace_span!("udp request", ?addr))
-                        .await
-                    {
-                        Ok(sleep) => sleep_interval = Some(sleep),
-                        Err(_) => {
-                            sleep_interval = Some(sleep_interval.unwrap_or(Duration::from_secs(60)))
-                        }
-                    }
+            }
+            UdpTrackerResolveResult::Two(v4, v6) => {
+                let (v4, v6) = (
+                    TrackerAddr::from(SocketAddr::V4(v4)),
+                    TrackerAddr::from(SocketAddr::V6(v6)),
+                );
+                let (r4, r6) = tokio::join!(
+                    self.tracker_one_request_udp(&v4, client, event)
+                        .instrument(trace_span!("udp request", addr=%v4)),
+                    self.tracker_one_request_udp(&v6, client, event)
+                        .instrument(trace_span!("udp request", addr=%v6))
+                );
+                if r4.is_ok() {
+                    self.on_udp_announced(v4.clone(), event);
                 }
-                UdpTrackerResolveResult::Two(v4, v6) => {
-                    let (r4, r6) = tokio::join!(
-                        self.tracker_one_request_udp(v4.into(), &client)
-                            .instrument(trace_span!("udp request", addr=?v4)),
-                        self.tracker_one_request_udp(v6.into(), &client)
-                            .instrument(trace_span!("udp request", addr=?v6))
-                    );
-                    sleep_interval = Some(
-                        r4.or(r6)
-                            .ok()
-                            .or(sleep_interval)
-                            .unwrap_or(Duration::from_secs(60)),
-                    )
+                if r6.is_ok() {
+                    self.on_udp_announced(v6.clone(), event);
                 }
+                let addr = if r4.is_ok() { v4 } else { v6 };
+                let (interval, swarm) = r4.or(r6)?;
+                Ok((interval, self.scrape_udp(&addr, client, swarm).await))
             }
         }
     }
 
+    async fn tracker_announce_udp_one(
+        &self,
+        addr: TrackerAddr,
+        client: &UdpTrackerClient,
+        event: u32,
+    ) -> anyhow::Result<(Duration, SwarmStats)> {
+        let (interval, swarm) = self
+            .tracker_one_request_udp(&addr, client, event)
+            .instrument(trace_span!("udp request", %addr))
+            .await?;
+        self.on_udp_announced(addr.clone(), event);
+        Ok((interval, self.scrape_udp(&addr, client, swarm).await))
+    }
+
     async fn tracker_one_request_udp(
         &self,
-        addr: SocketAddr,
+        addr: &TrackerAddr,
         client: &UdpTrackerClient,
-    ) -> anyhow::Result<Duration> {
+        event: u32,
+    ) -> anyhow::Result<(Duration, SwarmStats)> {
         use tracker_comms_udp::*;
 
         let stats = self.stats.get();
@@ -403,31 +720,28 @@ impl TrackerComms {
             downloaded: stats.downloaded_bytes,
             left: stats.get_left_to_download_bytes(),
             uploaded: stats.uploaded_bytes,
-            event: match stats.torrent_state {
-                TrackerCommsStatsState::None => EVENT_NONE,
-                TrackerCommsStatsState::Initializing => EVENT_STARTED,
-                TrackerCommsStatsState::Paused => EVENT_STOPPED,
-                TrackerCommsStatsState::Live => {
-                    if stats.is_completed() {
-                        EVENT_COMPLETED
-                    } else {
-                        EVENT_STARTED
-                    }
-                }
-            },
+            event,
             key: self.key,
             port: self.announce_port,
         };
 
-        match client.announce(addr, request).await {
+        match client.announce(addr.clone(), request).await {
             Ok(response) => {
                 trace!(len = response.addrs.len(), "received announce response");
-                for addr in response.addrs {
-                    self.tx.send(addr).await.context("rx closed")?;
+                // When stopping, nobody is listening for peers anymore.
+                if event != EVENT_STOPPED {
+                    for addr in response.addrs {
+                        self.tx.send(addr).await.context("rx closed")?;
+                    }
                 }
                 let sleep = response.interval.max(5);
                 let sleep = Duration::from_secs(sleep as u64);
-                Ok(sleep)
+                let swarm = SwarmStats {
+                    seeders: response.seeders,
+                    leechers: response.leechers,
+                    downloaded: None,
+                };
+                Ok((sleep, swarm))
             }
             Err(e) => {
                 debug!(?addr, "error reading announce response: {e:#}");
@@ -435,4 +749,623 @@ impl TrackerComms {
             }
         }
     }
+
+    // Scrape counts are preferred as only they include completed downloads. Fall back to the
+    // announce counts if scraping fails.
+    async fn scrape_udp(
+        &self,
+        addr: &TrackerAddr,
+        client: &UdpTrackerClient,
+        announced: SwarmStats,
+    ) -> SwarmStats {
+        match client.scrape(addr.clone(), &[self.info_hash]).await {
+            Ok(stats) => stats[0].into(),
+            Err(e) => {
+                debug!(%addr, "error scraping: {e:#}");
+                announced
+            }
+        }
+    }
+
+    fn on_udp_announced(&self, addr: TrackerAddr, event: u32) {
+        if event == tracker_comms_udp::EVENT_STARTED {
+            self.announced.lock().push(AnnouncedTracker::Udp(addr));
+        }
+    }
+
+    // Returns None if the torrent was already complete, as then there's nothing to announce.
+    fn completed_watcher(&self) -> Option<BoxFuture<'static, ()>> {
+        // Subscribe before checking, so that completion can't slip in between.
+        let fut = self.stats.wait_until_completed();
+        if self.stats.get().is_completed() {
+            return None;
+        }
+        Some(fut)
+    }
+
+    // Sleep for the interval. Returns true if woken up early by the torrent completing.
+    async fn sleep_or_completed(
+        &self,
+        interval: Duration,
+        completed: &mut Option<BoxFuture<'static, ()>>,
+    ) -> bool {
+        let Some(fut) = completed.as_mut() else {
+            tokio::time::sleep(interval).await;
+            return false;
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => false,
+            _ = fut => {
+                debug!("torrent completed, announcing");
+                *completed = None;
+                true
+            }
+        }
+    }
+
+    async fn announce_stopped(&self, tracker: AnnouncedTracker, udp_client: &UdpTrackerClient) {
+        let res = match &tracker {
+            AnnouncedTracker::Http(url) => self
+                .tracker_one_request_http(
+                    url,
+                    Some(tracker_comms_http::TrackerRequestEvent::Stopped),
+                )
+                .await
+                .map(|_| ()),
+            AnnouncedTracker::Udp(addr) => self
+                .tracker_one_request_udp(addr, udp_client, tracker_comms_udp::EVENT_STOPPED)
+                .await
+                .map(|_| ()),
+        };
+        if let Err(e) = res {
+            debug!(?tracker, "error announcing stopped: {e:#}");
+        }
+    }
+}
+
+impl std::fmt::Debug for AnnouncedTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnnouncedTracker::Http(u) => std::fmt::Display::fmt(u, f),
+            AnnouncedTracker::Udp(a) => std::fmt::Display::fmt(a, f),
+        }
+    }
+}
+
+// Sends the "stopped" event to all trackers we announced to once the peer stream is dropped
+// (i.e. the torrent was paused or removed). This is best-effort and doesn't block the caller.
+struct StoppedAnnounceGuard {
+    comms: Arc<TrackerComms>,
+    udp_client: UdpTrackerClient,
+}
+
+impl Drop for StoppedAnnounceGuard {
+    fn drop(&mut self) {
+        let announced = std::mem::take(&mut *self.comms.announced.lock());
+        if announced.is_empty() {
+            return;
+        }
+        let Ok(rt) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let comms = self.comms.clone();
+        let udp_client = self.udp_client.clone();
+        let span = debug_span!(parent: None, "tracker_stopped", info_hash = ?comms.info_hash);
+        rt.spawn(
+            async move {
+                let futs = announced.into_iter().map(|tracker| {
+                    let comms = &comms;
+                    let udp_client = &udp_client;
+                    async move {
+                        if tokio::time::timeout(
+                            STOPPED_ANNOUNCE_TIMEOUT,
+                            comms.announce_stopped(tracker, udp_client),
+                        )
+                        .await
+                        .is_err()
+                        {
+                            debug!("timed out announcing stopped");
+                        }
+                    }
+                });
+                futures::future::join_all(futs).await;
+            }
+            .instrument(span),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use futures::{FutureExt, StreamExt, future::BoxFuture};
+    use librqbit_core::hash_id::Id20;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        sync::{Notify, mpsc},
+    };
+    use tokio_util::sync::CancellationToken;
+
+    use super::{
+        MAX_RETRY_INTERVAL, MIN_RETRY_INTERVAL, SwarmStats, TorrentStatsProvider, TrackerComms,
+        TrackerCommsOptions, TrackerCommsStats,
+    };
+    use crate::UdpTrackerClient;
+
+    struct Stats {
+        completed: Arc<AtomicBool>,
+        notify: Arc<Notify>,
+    }
+
+    impl TorrentStatsProvider for Stats {
+        fn get(&self) -> TrackerCommsStats {
+            TrackerCommsStats {
+                total_bytes: 100,
+                downloaded_bytes: if self.completed.load(Ordering::SeqCst) {
+                    100
+                } else {
+                    0
+                },
+                ..Default::default()
+            }
+        }
+
+        fn wait_until_completed(&self) -> BoxFuture<'static, ()> {
+            let notify = self.notify.clone();
+            async move { notify.notified().await }.boxed()
+        }
+    }
+
+    // Accepts HTTP announces and reports the "event" query parameter of each. Scrapes are
+    // answered but not reported.
+    async fn run_http_tracker(listener: tokio::net::TcpListener, tx: mpsc::Sender<String>) {
+        const BODY: &[u8] = b"d8:intervali1800e8:completei3e10:incompletei4e5:peers0:e";
+        let mut scrape_body = b"d5:filesd20:".to_vec();
+        scrape_body.extend_from_slice(&[0; 20]);
+        scrape_body.extend_from_slice(b"d8:completei5e10:downloadedi50e10:incompletei10eeee");
+        loop {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 1024];
+            while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = conn.read(&mut chunk).await.unwrap();
+                assert!(n > 0);
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            let request_line = String::from_utf8_lossy(&buf)
+                .lines()
+                .next()
+                .unwrap()
+                .to_owned();
+            let is_scrape = request_line.starts_with("GET /scrape");
+            let body = if is_scrape { &scrape_body[..] } else { BODY };
+            conn.write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+            conn.write_all(body).await.unwrap();
+            if is_scrape {
+                continue;
+            }
+            let event = request_line
+                .split(['?', '&', ' '])
+                .find_map(|kv| kv.strip_prefix("event="))
+                .unwrap_or("none")
+                .to_owned();
+            tx.send(event).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_announce_events() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/announce", listener.local_addr().unwrap());
+        let (events_tx, mut events_rx) = mpsc::channel(16);
+        let server = tokio::spawn(run_http_tracker(listener, events_tx));
+
+        let completed = Arc::new(AtomicBool::new(false));
+        let notify = Arc::new(Notify::new());
+        let cancel_token = CancellationToken::new();
+        let udp_client = UdpTrackerClient::new(cancel_token.clone(), None)
+            .await
+            .unwrap();
+
+        let mut peers = TrackerComms::start(
+            Id20::default(),
+            Id20::default(),
+            vec![vec![url.parse().unwrap()]],
+            Box::new(Stats {
+                completed: completed.clone(),
+                notify: notify.clone(),
+            }),
+            Default::default(),
+            4240,
+            reqwest::Client::new(),
+            udp_client,
+        )
+        .unwrap();
+        let stream_task = tokio::spawn(async move { while peers.next().await.is_some() {} });
+
+        let mut next_event = async || {
+            tokio::time::timeout(Duration::from_secs(5), events_rx.recv())
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        assert_eq!(next_event().await, "started");
+
+        completed.store(true, Ordering::SeqCst);
+        notify.notify_one();
+        assert_eq!(next_event().await, "completed");
+
+        // Dropping the peer stream announces "stopped" in the background.
+        stream_task.abort();
+        assert_eq!(next_event().await, "stopped");
+
+        server.abort();
+        cancel_token.cancel();
+    }
+
+    struct SwarmStatsRecorder(mpsc::UnboundedSender<(String, SwarmStats)>);
+
+    impl TorrentStatsProvider for SwarmStatsRecorder {
+        fn get(&self) -> TrackerCommsStats {
+            Default::default()
+        }
+
+        fn on_swarm_stats(&self, tracker: &url::Url, stats: SwarmStats) {
+            let _ = self.0.send((tracker.path().to_owned(), stats));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_swarm_stats() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (events_tx, _events_rx) = mpsc::channel(16);
+        let server = tokio::spawn(run_http_tracker(listener, events_tx));
+
+        let cancel_token = CancellationToken::new();
+        let udp_client = UdpTrackerClient::new(cancel_token.clone(), None)
+            .await
+            .unwrap();
+        let (stats_tx, mut stats_rx) = mpsc::unbounded_channel();
+        // The first tracker can be scraped, the second one can't, so only its announce
+        // counts are known.
+        let mut peers = TrackerComms::start(
+            Id20::default(),
+            Id20::default(),
+            vec![
+                vec![format!("http://{addr}/announce").parse().unwrap()],
+                vec![format!("http://{addr}/a").parse().unwrap()],
+            ],
+            Box::new(SwarmStatsRecorder(stats_tx)),
+            Default::default(),
+            4240,
+            reqwest::Client::new(),
+            udp_client,
+        )
+        .unwrap();
+        let stream_task = tokio::spawn(async move { while peers.next().await.is_some() {} });
+
+        let mut got = Vec::new();
+        for _ in 0..2 {
+            got.push(
+                tokio::time::timeout(Duration::from_secs(5), stats_rx.recv())
+                    .await
+                    .unwrap()
+                    .unwrap(),
+            );
+        }
+        got.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            got,
+            vec![
+                (
+                    "/a".to_owned(),
+                    SwarmStats {
+                        seeders: 3,
+                        leechers: 4,
+                        downloaded: None
+                    }
+                ),
+                (
+                    "/announce".to_owned(),
+                    SwarmStats {
+                        seeders: 5,
+                        leechers: 10,
+                        downloaded: Some(50)
+                    }
+                ),
+            ]
+        );
+
+        stream_task.abort();
+        server.abort();
+        cancel_token.cancel();
+    }
+
+    // Responds to every announce with an error and counts them.
+    async fn run_failing_http_tracker(listener: tokio::net::TcpListener, count: Arc<AtomicUsize>) {
+        loop {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = conn.read(&mut buf).await;
+            count.fetch_add(1, Ordering::SeqCst);
+            let _ = conn
+                .write_all(b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tier_failover() {
+        let good = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let bad = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tier = vec![
+            format!("http://{}/announce", good.local_addr().unwrap())
+                .parse()
+                .unwrap(),
+            format!("http://{}/announce", bad.local_addr().unwrap())
+                .parse()
+                .unwrap(),
+        ];
+        let (events_tx, mut events_rx) = mpsc::channel(16);
+        let good_server = tokio::spawn(run_http_tracker(good, events_tx));
+        let bad_count = Arc::new(AtomicUsize::new(0));
+        let bad_server = tokio::spawn(run_failing_http_tracker(bad, bad_count.clone()));
+
+        let cancel_token = CancellationToken::new();
+        let udp_client = UdpTrackerClient::new(cancel_token.clone(), None)
+            .await
+            .unwrap();
+        let mut peers = TrackerComms::start(
+            Id20::default(),
+            Id20::default(),
+            vec![tier],
+            Box::new(()),
+            TrackerCommsOptions {
+                force_interval: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+            4240,
+            reqwest::Client::new(),
+            udp_client,
+        )
+        .unwrap();
+        let stream_task = tokio::spawn(async move { while peers.next().await.is_some() {} });
+
+        let mut next_event = async || {
+            tokio::time::timeout(Duration::from_secs(5), events_rx.recv())
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        // Whatever the shuffled order, the working tracker ends up announced to, and then it's
+        // the only one asked as it was moved to the front of the tier.
+        assert_eq!(next_event().await, "started");
+        let bad_requests = bad_count.load(Ordering::SeqCst);
+        assert!(bad_requests <= 1);
+        for _ in 0..3 {
+            assert_eq!(next_event().await, "none");
+        }
+        assert_eq!(bad_count.load(Ordering::SeqCst), bad_requests);
+
+        stream_task.abort();
+        good_server.abort();
+        bad_server.abort();
+        cancel_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_failing_tracker_is_retried() {
+        let bad = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tier = vec![
+            format!("http://{}/announce", bad.local_addr().unwrap())
+                .parse()
+                .unwrap(),
+        ];
+        let bad_count = Arc::new(AtomicUsize::new(0));
+        let bad_server = tokio::spawn(run_failing_http_tracker(bad, bad_count.clone()));
+
+        let cancel_token = CancellationToken::new();
+        let udp_client = UdpTrackerClient::new(cancel_token.clone(), None)
+            .await
+            .unwrap();
+        let mut peers = TrackerComms::start(
+            Id20::default(),
+            Id20::default(),
+            vec![tier],
+            Box::new(()),
+            TrackerCommsOptions {
+                force_interval: Some(Duration::from_millis(1)),
+                ..Default::default()
+            },
+            4240,
+            reqwest::Client::new(),
+            udp_client,
+        )
+        .unwrap();
+        let stream_task = tokio::spawn(async move { while peers.next().await.is_some() {} });
+
+        // It's still asked after many failures in a row, just less and less often.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while bad_count.load(Ordering::SeqCst) < 12 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        stream_task.abort();
+        bad_server.abort();
+        cancel_token.cancel();
+    }
+
+    // Accepts connections but never responds.
+    async fn run_hanging_http_tracker(listener: tokio::net::TcpListener) {
+        let mut conns = Vec::new();
+        loop {
+            let (conn, _) = listener.accept().await.unwrap();
+            conns.push(conn);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_announce_timeout() {
+        let good = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hanging = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tier = vec![
+            format!("http://{}/announce", good.local_addr().unwrap())
+                .parse()
+                .unwrap(),
+            format!("http://{}/announce", hanging.local_addr().unwrap())
+                .parse()
+                .unwrap(),
+        ];
+        let (events_tx, mut events_rx) = mpsc::channel(16);
+        let good_server = tokio::spawn(run_http_tracker(good, events_tx));
+        let hanging_server = tokio::spawn(run_hanging_http_tracker(hanging));
+
+        let cancel_token = CancellationToken::new();
+        let udp_client = UdpTrackerClient::new(cancel_token.clone(), None)
+            .await
+            .unwrap();
+        let mut peers = TrackerComms::start(
+            Id20::default(),
+            Id20::default(),
+            vec![tier],
+            Box::new(()),
+            TrackerCommsOptions {
+                force_interval: Some(Duration::from_millis(50)),
+                announce_timeout: Duration::from_millis(200),
+                ..Default::default()
+            },
+            4240,
+            reqwest::Client::new(),
+            udp_client,
+        )
+        .unwrap();
+        let stream_task = tokio::spawn(async move { while peers.next().await.is_some() {} });
+
+        // Even if the hanging tracker is tried first, it times out and the next one is used.
+        let event = tokio::time::timeout(Duration::from_secs(2), events_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event, "started");
+
+        stream_task.abort();
+        good_server.abort();
+        hanging_server.abort();
+        cancel_token.cancel();
+    }
+
+    #[test]
+    fn test_retry_interval() {
+        let opts = TrackerCommsOptions::default();
+        assert_eq!(opts.retry_interval(1), MIN_RETRY_INTERVAL);
+        assert_eq!(opts.retry_interval(2), MIN_RETRY_INTERVAL * 2);
+        assert_eq!(opts.retry_interval(3), MIN_RETRY_INTERVAL * 4);
+        assert_eq!(opts.retry_interval(100), MAX_RETRY_INTERVAL);
+
+        // The forced interval is the floor, even if it's above the max.
+        let opts = TrackerCommsOptions {
+            force_interval: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        assert_eq!(opts.retry_interval(1), Duration::from_secs(1));
+        assert_eq!(opts.retry_interval(2), Duration::from_secs(2));
+        let opts = TrackerCommsOptions {
+            force_interval: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        assert_eq!(opts.retry_interval(5), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn test_is_global_unicast() {
+        for (ip, expected) in [
+            ("2001:db8::1", true),
+            ("2a00:1450::1", true),
+            ("::1", false),
+            ("fe80::1", false),
+            ("fd00::1", false),
+            ("::ffff:1.2.3.4", false),
+        ] {
+            assert_eq!(
+                super::is_global_unicast(&ip.parse().unwrap()),
+                expected,
+                "{ip}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_announce_ipv6_resolve() {
+        use super::AnnounceIpv6;
+        let ip = "2001:db8::1".parse().unwrap();
+        assert_eq!(AnnounceIpv6::None.resolve(), None);
+        assert_eq!(AnnounceIpv6::Fixed(ip).resolve(), Some(ip));
+        // Looked up again every time rather than remembered.
+        assert_eq!(AnnounceIpv6::Global.resolve(), super::global_ipv6_addr());
+    }
+
+    #[test]
+    fn test_with_jitter() {
+        let interval = Duration::from_secs(60);
+        assert_eq!(
+            TrackerCommsOptions::default().with_jitter(interval),
+            interval
+        );
+
+        let opts = TrackerCommsOptions {
+            announce_jitter: Duration::from_secs(30),
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            let d = opts.with_jitter(interval);
+            assert!(
+                d >= interval && d <= interval + Duration::from_secs(30),
+                "{d:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disable_udp() {
+        let cancel_token = CancellationToken::new();
+        let udp_client = UdpTrackerClient::new(cancel_token.clone(), None)
+            .await
+            .unwrap();
+        let peers = TrackerComms::start(
+            Id20::default(),
+            Id20::default(),
+            vec![vec!["udp://127.0.0.1:1/announce".parse().unwrap()]],
+            Box::new(()),
+            TrackerCommsOptions {
+                disable_udp: true,
+                ..Default::default()
+            },
+            4240,
+            reqwest::Client::new(),
+            udp_client,
+        );
+        assert!(peers.is_none());
+        cancel_token.cancel();
+    }
 }
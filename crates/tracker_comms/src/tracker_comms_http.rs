@@ -4,21 +4,21 @@ use serde::Deserializer;
 use serde_derive::Deserialize;
 use serde_with::serde_as;
 use std::{
+    collections::HashMap,
     marker::PhantomData,
-    net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
 };
 
 use librqbit_core::{
     compact_ip::{CompactListInBuffer, CompactSerialize, CompactSerializeFixedLen},
     hash_id::Id20,
 };
+use url::Url;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TrackerRequestEvent {
     Started,
-    #[allow(dead_code)]
     Stopped,
-    #[allow(dead_code)]
     Completed,
 }
 
@@ -34,6 +34,8 @@ pub struct TrackerRequest<'a> {
     pub no_peer_id: bool,
 
     pub ip: Option<IpAddr>,
+    // BEP 7: our IPv6 address, so that the tracker learns it even when announcing over IPv4.
+    pub ipv6: Option<Ipv6Addr>,
     pub numwant: Option<usize>,
     pub key: Option<u32>,
     pub trackerid: Option<&'a str>,
@@ -137,7 +139,6 @@ pub struct TrackerResponse<'a> {
     #[allow(dead_code)]
     #[serde(rename = "warning message", borrow)]
     pub warning_message: Option<ByteBuf<'a>>,
-    #[allow(dead_code)]
     #[serde(default)]
     pub complete: u64,
     pub interval: u64,
@@ -146,7 +147,6 @@ pub struct TrackerResponse<'a> {
     pub min_interval: Option<u64>,
     #[allow(dead_code)]
     pub tracker_id: Option<ByteBuf<'a>>,
-    #[allow(dead_code)]
     #[serde(default)]
     pub incomplete: u64,
     #[serde(borrow)]
@@ -161,6 +161,31 @@ impl TrackerResponse<'_> {
     }
 }
 
+/// The scrape URL of an HTTP tracker. Per BEP 48 it's only known if the last path component
+/// of the announce URL starts with "announce", which is then replaced with "scrape".
+pub fn scrape_url(announce_url: &Url) -> Option<Url> {
+    let last = announce_url.path_segments()?.next_back()?;
+    let scrape = format!("scrape{}", last.strip_prefix("announce")?);
+    let mut url = announce_url.clone();
+    url.path_segments_mut().ok()?.pop().push(&scrape);
+    Some(url)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScrapeFile {
+    #[serde(default)]
+    pub complete: u64,
+    #[serde(default)]
+    pub downloaded: u64,
+    #[serde(default)]
+    pub incomplete: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScrapeResponse {
+    pub files: HashMap<Id20, ScrapeFile>,
+}
+
 impl TrackerRequest<'_> {
     pub fn as_querystring(&self) -> String {
         use std::fmt::Write;
@@ -191,6 +216,9 @@ impl TrackerRequest<'_> {
         if let Some(ip) = &self.ip {
             write!(s, "&ip={ip}").unwrap();
         }
+        if let Some(ipv6) = &self.ipv6 {
+            write!(s, "&ipv6={}", u::encode(&ipv6.to_string())).unwrap();
+        }
         if let Some(numwant) = &self.numwant {
             write!(s, "&numwant={numwant}").unwrap();
         }
@@ -224,11 +252,13 @@ mod tests {
             no_peer_id: false,
             event: Some(TrackerRequestEvent::Started),
             ip: Some("127.0.0.1".parse().unwrap()),
+            ipv6: Some("2001:db8::1".parse().unwrap()),
             numwant: None,
             key: None,
             trackerid: None,
         };
-        dbg!(request.as_querystring());
+        let q = request.as_querystring();
+        assert!(q.contains("&ip=127.0.0.1&ipv6=2001%3Adb8%3A%3A1"), "{q}");
     }
 
     #[test]
@@ -247,6 +277,41 @@ mod tests {
         dbg!(response);
     }
 
+    #[test]
+    fn test_scrape_url() {
+        for (announce, expected) in [
+            (
+                "http://example.com/announce",
+                Some("http://example.com/scrape"),
+            ),
+            (
+                "http://example.com/x/announce.php?passkey=1",
+                Some("http://example.com/x/scrape.php?passkey=1"),
+            ),
+            ("http://example.com/a", None),
+            ("http://example.com/announce/x", None),
+        ] {
+            assert_eq!(
+                scrape_url(&announce.parse().unwrap()).map(|u| u.to_string()),
+                expected.map(|s| s.to_owned()),
+                "{announce}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_scrape_response() {
+        let mut data = b"d5:filesd20:".to_vec();
+        data.extend_from_slice(&[1; 20]);
+        data.extend_from_slice(b"d8:completei5e10:downloadedi50e10:incompletei10eeee");
+        let response = bencode::from_bytes::<ScrapeResponse>(&data).unwrap();
+        let file = &response.files[&Id20::new([1; 20])];
+        assert_eq!(
+            (file.complete, file.downloaded, file.incomplete),
+            (5, 50, 10)
+        );
+    }
+
     #[test]
     fn parse_peers_dict() {
         let buf = b"ld2:ip9:127.0.0.14:porti100eed2:ip39:6969:6969:6969:6969:6969:6969:6969:69694:porti101eee";
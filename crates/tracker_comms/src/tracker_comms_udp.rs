@@ -7,7 +7,11 @@ use std::{
 };
 
 use anyhow::{Context, bail};
-use librqbit_core::{hash_id::Id20, spawn_utils::spawn_with_cancel};
+use librqbit_core::{
+    hash_id::Id20,
+    socks5_udp::{RelayDst, Socks5UdpRelay},
+    spawn_utils::spawn_with_cancel,
+};
 use librqbit_dualstack_sockets::{BindDevice, UdpSocket};
 use parking_lot::RwLock;
 use rand::Rng;
@@ -16,7 +20,7 @@ use tracing::{debug, debug_span, trace, warn};
 
 const ACTION_CONNECT: u32 = 0;
 const ACTION_ANNOUNCE: u32 = 1;
-// const ACTION_SCRAPE: u32 = 2;
+const ACTION_SCRAPE: u32 = 2;
 const ACTION_ERROR: u32 = 3;
 
 pub const EVENT_NONE: u32 = 0;
@@ -29,11 +33,21 @@ const CONNECTION_ID_MAGIC: ConnectionId = 0x41727101980;
 
 pub type TransactionId = u32;
 
+// BEP 15 retransmits after 15 * 2 ^ n seconds, up to n = 8. That takes far too long to notice a
+// dead tracker and move on to the next one, so start lower and give up sooner.
+const RETRANSMIT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_RETRANSMITS: u32 = 2;
+// A connection id may be used for a minute after it was received.
+const CONNECTION_ID_TTL: Duration = Duration::from_secs(60);
+
+/// The most info hashes that can be scraped in one request.
+pub const MAX_SCRAPE_INFO_HASHES: usize = 74;
+
 pub fn new_transaction_id() -> TransactionId {
     rand::rng().random()
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AnnounceFields {
     pub info_hash: Id20,
     pub peer_id: Id20,
@@ -49,6 +63,7 @@ pub struct AnnounceFields {
 pub enum Request {
     Connect,
     Announce(ConnectionId, AnnounceFields),
+    Scrape(ConnectionId, Vec<Id20>),
 }
 
 impl Request {
@@ -95,6 +110,14 @@ impl Request {
                 w.extend_from_slice(&(-1i32).to_be_bytes())?; // num want -1
                 w.extend_from_slice(&fields.port.to_be_bytes())?;
             }
+            Request::Scrape(connection_id, info_hashes) => {
+                w.extend_from_slice(&connection_id.to_be_bytes())?;
+                w.extend_from_slice(&ACTION_SCRAPE.to_be_bytes())?;
+                w.extend_from_slice(&transaction_id.to_be_bytes())?;
+                for info_hash in info_hashes {
+                    w.extend_from_slice(&info_hash.0)?;
+                }
+            }
         }
         Ok(w.offset)
     }
@@ -103,17 +126,24 @@ impl Request {
 #[derive(Debug)]
 pub struct AnnounceResponse {
     pub interval: u32,
-    #[allow(dead_code)]
     pub leechers: u32,
-    #[allow(dead_code)]
     pub seeders: u32,
     pub addrs: Vec<SocketAddr>,
 }
 
+/// Scrape results of one torrent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScrapeStats {
+    pub seeders: u32,
+    pub completed: u32,
+    pub leechers: u32,
+}
+
 #[derive(Debug)]
 pub enum Response {
     Connect(ConnectionId),
     Announce(AnnounceResponse),
+    Scrape(Vec<ScrapeStats>),
     #[allow(dead_code)]
     Error(String),
     Unknown,
@@ -208,6 +238,21 @@ impl Response {
                     addrs,
                 })
             }
+            ACTION_SCRAPE => {
+                let mut stats = Vec::new();
+                while !buf.is_empty() {
+                    let (seeders, b) = u32::parse_num(buf).context("can't parse seeders")?;
+                    let (completed, b) = u32::parse_num(b).context("can't parse completed")?;
+                    let (leechers, b) = u32::parse_num(b).context("can't parse leechers")?;
+                    buf = b;
+                    stats.push(ScrapeStats {
+                        seeders,
+                        completed,
+                        leechers,
+                    });
+                }
+                Response::Scrape(stats)
+            }
             ACTION_ERROR => {
                 let msg = CStr::from_bytes_with_nul(buf)
                     .ok()
@@ -231,6 +276,29 @@ impl Response {
     }
 }
 
+/// Where to send requests to a tracker. Hostnames can only be used through a SOCKS5 relay, which
+/// resolves them itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TrackerAddr {
+    Addr(SocketAddr),
+    Host(String, u16),
+}
+
+impl From<SocketAddr> for TrackerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        TrackerAddr::Addr(addr)
+    }
+}
+
+impl std::fmt::Display for TrackerAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrackerAddr::Addr(addr) => std::fmt::Display::fmt(addr, f),
+            TrackerAddr::Host(host, port) => write!(f, "{host}:{port}"),
+        }
+    }
+}
+
 struct ConnectionIdMeta {
     id: ConnectionId,
     created: Instant,
@@ -238,13 +306,37 @@ struct ConnectionIdMeta {
 
 #[derive(Default)]
 struct ClientLocked {
-    connections: HashMap<SocketAddr, ConnectionIdMeta>,
+    connections: HashMap<TrackerAddr, ConnectionIdMeta>,
     transactions: HashMap<TransactionId, tokio::sync::oneshot::Sender<Response>>,
 }
 
 struct ClientShared {
     sock: UdpSocket,
+    // If set, all datagrams go through this SOCKS5 relay.
+    socks5: Option<Socks5UdpRelay>,
     locked: RwLock<ClientLocked>,
+    retransmit_timeout: Duration,
+}
+
+impl ClientShared {
+    async fn send_to(&self, buf: &[u8], addr: &TrackerAddr) -> std::io::Result<usize> {
+        match (self.socks5.as_ref(), addr) {
+            (Some(relay), addr) => {
+                let dst = match addr {
+                    TrackerAddr::Addr(addr) => RelayDst::Addr(*addr),
+                    TrackerAddr::Host(host, port) => RelayDst::Host(host, *port),
+                };
+                let mut wrapped = Vec::with_capacity(buf.len() + 262);
+                Socks5UdpRelay::encapsulate(dst, buf, &mut wrapped)?;
+                self.sock.send_to(&wrapped, relay.relay_addr()).await
+            }
+            (None, TrackerAddr::Addr(addr)) => self.sock.send_to(buf, *addr).await,
+            (None, TrackerAddr::Host(..)) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "hostnames can only be sent through a SOCKS5 relay",
+            )),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -268,6 +360,30 @@ impl UdpTrackerClient {
     pub async fn new(
         cancel_token: CancellationToken,
         bind_device: Option<&BindDevice>,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_retransmit_timeout(cancel_token, bind_device, None, RETRANSMIT_TIMEOUT).await
+    }
+
+    /// Send all requests through a SOCKS5 proxy's UDP relay.
+    pub async fn new_with_socks5_relay(
+        cancel_token: CancellationToken,
+        bind_device: Option<&BindDevice>,
+        relay: Socks5UdpRelay,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_retransmit_timeout(
+            cancel_token,
+            bind_device,
+            Some(relay),
+            RETRANSMIT_TIMEOUT,
+        )
+        .await
+    }
+
+    async fn new_with_retransmit_timeout(
+        cancel_token: CancellationToken,
+        bind_device: Option<&BindDevice>,
+        socks5: Option<Socks5UdpRelay>,
+        retransmit_timeout: Duration,
     ) -> anyhow::Result<Self> {
         let addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0);
         let sock = UdpSocket::bind_udp(
@@ -282,7 +398,9 @@ impl UdpTrackerClient {
         let client = Self {
             state: Arc::new(ClientShared {
                 sock,
+                socks5,
                 locked: RwLock::new(Default::default()),
+                retransmit_timeout,
             }),
         };
 
@@ -305,7 +423,22 @@ impl UdpTrackerClient {
                 }
             };
 
-            let (tid, response) = match Response::parse(&buf[..len], addr.is_ipv6()) {
+            let (data, addr) = match self.state.socks5.as_ref() {
+                Some(relay) if !relay.is_relay(addr) => {
+                    debug!(?addr, "ignoring datagram from outside the SOCKS5 relay");
+                    continue;
+                }
+                Some(_) => match Socks5UdpRelay::decapsulate(&buf[..len]) {
+                    Ok((addr, data)) => (data, addr),
+                    Err(e) => {
+                        debug!("error unwrapping datagram from the SOCKS5 relay: {e:#}");
+                        continue;
+                    }
+                },
+                None => (&buf[..len], addr),
+            };
+
+            let (tid, response) = match Response::parse(data, addr.is_ipv6()) {
                 Ok(r) => r,
                 Err(e) => {
                     debug!(?addr, "error parsing UDP response: {e:#}");
@@ -330,47 +463,89 @@ impl UdpTrackerClient {
         }
     }
 
-    async fn get_connection_id(&self, addr: SocketAddr) -> anyhow::Result<ConnectionId> {
-        if let Some(m) = self.state.locked.read().connections.get(&addr)
-            && m.created.elapsed() < Duration::from_secs(60)
+    async fn get_connection_id(&self, addr: &TrackerAddr) -> anyhow::Result<ConnectionId> {
+        if let Some(m) = self.state.locked.read().connections.get(addr)
+            && m.created.elapsed() < CONNECTION_ID_TTL
         {
             return Ok(m.id);
         }
 
-        let response = self.request(addr, Request::Connect).await?;
-        match response {
-            Response::Connect(connection_id) => {
-                self.state.locked.write().connections.insert(
-                    addr,
-                    ConnectionIdMeta {
-                        id: connection_id,
-                        created: Instant::now(),
-                    },
-                );
-                Ok(connection_id)
-            }
-            _ => anyhow::bail!("expected connect response"),
+        let (tx, mut rx) = tokio::sync::oneshot::channel();
+        let tid_g = self.reserve_transaction_id(tx)?;
+        for attempt in 0..=MAX_RETRANSMITS {
+            let response = match self
+                .send_and_wait(addr, tid_g.tid, &Request::Connect, &mut rx, attempt)
+                .await?
+            {
+                Some(r) => r,
+                None => continue,
+            };
+            return match response {
+                Response::Connect(connection_id) => {
+                    self.state.locked.write().connections.insert(
+                        addr.clone(),
+                        ConnectionIdMeta {
+                            id: connection_id,
+                            created: Instant::now(),
+                        },
+                    );
+                    Ok(connection_id)
+                }
+                _ => anyhow::bail!("expected connect response"),
+            };
         }
+        bail!("timeout connecting")
     }
 
-    async fn request(&self, addr: SocketAddr, request: Request) -> anyhow::Result<Response> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
+    // Send a request that needs a connection id, retransmitting it if there's no response.
+    // The connection id is looked up every time, as it may expire while retransmitting.
+    async fn request(
+        &self,
+        addr: &TrackerAddr,
+        request: impl Fn(ConnectionId) -> Request,
+    ) -> anyhow::Result<Response> {
+        let (tx, mut rx) = tokio::sync::oneshot::channel();
         let tid_g = self.reserve_transaction_id(tx)?;
+        for attempt in 0..=MAX_RETRANSMITS {
+            let request = request(self.get_connection_id(addr).await?);
+            if let Some(r) = self
+                .send_and_wait(addr, tid_g.tid, &request, &mut rx, attempt)
+                .await?
+            {
+                return Ok(r);
+            }
+        }
+        bail!("timeout waiting for response")
+    }
 
-        let mut write_buf = [0u8; 1024];
-        let len = request.serialize(tid_g.tid, &mut write_buf)?;
+    // Returns None if there was no response in time.
+    async fn send_and_wait(
+        &self,
+        addr: &TrackerAddr,
+        tid: TransactionId,
+        request: &Request,
+        rx: &mut tokio::sync::oneshot::Receiver<Response>,
+        attempt: u32,
+    ) -> anyhow::Result<Option<Response>> {
+        if attempt > 0 {
+            trace!(?addr, attempt, "no response, retransmitting");
+        }
+        let mut write_buf = [0u8; 2048];
+        let len = request.serialize(tid, &mut write_buf)?;
         self.state
-            .sock
             .send_to(&write_buf[..len], addr)
             .await
             .with_context(|| format!("error sending to {addr:?}"))?;
 
-        let response = tokio::time::timeout(Duration::from_secs(10), rx)
-            .await
-            .context("timeout connecting")?
-            .context("sender dead")?;
+        let timeout = self.state.retransmit_timeout * (1 << attempt);
+        let response = match tokio::time::timeout(timeout, rx).await {
+            Ok(r) => r.context("sender dead")?,
+            Err(_) => return Ok(None),
+        };
         match &response {
             Response::Error(e) => {
+                // The error may be about an expired connection id, get a new one next time.
+                self.state.locked.write().connections.remove(addr);
                 anyhow::bail!("remote errored: {e}")
             }
             Response::Unknown => {
@@ -378,7 +553,7 @@ impl UdpTrackerClient {
             }
             _ => {}
         }
-        Ok(response)
+        Ok(Some(response))
     }
 
     fn reserve_transaction_id(
@@ -402,31 +577,234 @@ impl UdpTrackerClient {
         bail!("cant generate transaction id")
     }
 
+    pub fn is_proxied(&self) -> bool {
+        self.state.socks5.is_some()
+    }
+
     pub async fn announce(
         &self,
-        tracker: SocketAddr,
+        tracker: impl Into<TrackerAddr>,
         fields: AnnounceFields,
     ) -> anyhow::Result<AnnounceResponse> {
-        let connection_id = self.get_connection_id(tracker).await?;
-        let request = Request::Announce(connection_id, fields);
-        let response = self.request(tracker, request).await?;
+        let response = self
+            .request(&tracker.into(), |connection_id| {
+                Request::Announce(connection_id, fields.clone())
+            })
+            .await?;
         match response {
             Response::Announce(r) => Ok(r),
             other => bail!("unexpected response {other:?}, expected announce"),
         }
     }
+
+    /// Get the number of seeders, leechers and completed downloads of the torrents, in the
+    /// same order. At most [`MAX_SCRAPE_INFO_HASHES`] at a time.
+    pub async fn scrape(
+        &self,
+        tracker: impl Into<TrackerAddr>,
+        info_hashes: &[Id20],
+    ) -> anyhow::Result<Vec<ScrapeStats>> {
+        if info_hashes.is_empty() || info_hashes.len() > MAX_SCRAPE_INFO_HASHES {
+            bail!(
+                "can scrape 1 to {MAX_SCRAPE_INFO_HASHES} torrents at a time, got {}",
+                info_hashes.len()
+            );
+        }
+        let response = self
+            .request(&tracker.into(), |connection_id| {
+                Request::Scrape(connection_id, info_hashes.to_vec())
+            })
+            .await?;
+        match response {
+            Response::Scrape(stats) if stats.len() == info_hashes.len() => Ok(stats),
+            other => bail!("unexpected response {other:?}, expected scrape"),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{io::Write, str::FromStr};
+    use std::{io::Write, net::SocketAddr, str::FromStr};
 
-    use librqbit_core::{hash_id::Id20, peer_id::generate_peer_id};
+    use librqbit_core::{hash_id::Id20, peer_id::generate_peer_id, socks5_udp::Socks5UdpRelay};
 
     use crate::tracker_comms_udp::{
-        AnnounceFields, EVENT_NONE, Request, Response, new_transaction_id,
+        ACTION_ANNOUNCE, ACTION_CONNECT, ACTION_SCRAPE, AnnounceFields, EVENT_NONE, Request,
+        Response, ScrapeStats, TrackerAddr, UdpTrackerClient, new_transaction_id,
     };
 
+    // A tracker that ignores the first announce, so that it has to be retransmitted.
+    async fn run_lossy_udp_tracker(sock: tokio::net::UdpSocket) {
+        let mut buf = [0u8; 2048];
+        let mut announces = 0;
+        loop {
+            let (len, addr) = sock.recv_from(&mut buf).await.unwrap();
+            let req = &buf[..len];
+            let action = u32::from_be_bytes(req[8..12].try_into().unwrap());
+            let mut reply = Vec::new();
+            reply.extend_from_slice(&req[8..16]); // action and transaction id
+            match action {
+                ACTION_CONNECT => reply.extend_from_slice(&42u64.to_be_bytes()),
+                ACTION_ANNOUNCE => {
+                    assert_eq!(req[..8], 42u64.to_be_bytes());
+                    announces += 1;
+                    if announces == 1 {
+                        continue;
+                    }
+                    for v in [1800u32, 1, 2] {
+                        reply.extend_from_slice(&v.to_be_bytes());
+                    }
+                }
+                ACTION_SCRAPE => {
+                    for (i, _) in req[16..].chunks(20).enumerate() {
+                        for v in [i, 10 + i, 20 + i] {
+                            reply.extend_from_slice(&u32::try_from(v).unwrap().to_be_bytes());
+                        }
+                    }
+                }
+                _ => panic!("unexpected action {action}"),
+            }
+            sock.send_to(&reply, addr).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retransmit_and_scrape() {
+        let sock = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let tracker = sock.local_addr().unwrap();
+        tokio::spawn(run_lossy_udp_tracker(sock));
+
+        let cancel_token = tokio_util::sync::CancellationToken::new();
+        let client = UdpTrackerClient::new_with_retransmit_timeout(
+            cancel_token.clone(),
+            None,
+            None,
+            std::time::Duration::from_millis(100),
+        )
+        .await
+        .unwrap();
+
+        let response = client
+            .announce(
+                tracker,
+                AnnounceFields {
+                    info_hash: Id20::new([1; 20]),
+                    peer_id: generate_peer_id(b"-xx1234-"),
+                    downloaded: 0,
+                    left: 0,
+                    uploaded: 0,
+                    event: EVENT_NONE,
+                    key: 0,
+                    port: 24563,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.interval, 1800);
+
+        let stats = client
+            .scrape(tracker, &[Id20::new([1; 20]), Id20::new([2; 20])])
+            .await
+            .unwrap();
+        assert_eq!(
+            stats,
+            vec![
+                ScrapeStats {
+                    seeders: 0,
+                    completed: 10,
+                    leechers: 20
+                },
+                ScrapeStats {
+                    seeders: 1,
+                    completed: 11,
+                    leechers: 21
+                }
+            ]
+        );
+        assert!(client.scrape(tracker, &[]).await.is_err());
+        cancel_token.cancel();
+    }
+
+    // A SOCKS5 proxy that sets up a UDP relay, which answers tracker requests itself. It checks
+    // that the tracker is addressed by its hostname.
+    async fn run_fake_socks5_tracker(listener: tokio::net::TcpListener) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let relay = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let (mut control, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 10];
+        control.read_exact(&mut buf[..3]).await.unwrap();
+        control.write_all(&[5, 0]).await.unwrap();
+        control.read_exact(&mut buf).await.unwrap();
+        let mut reply = vec![5, 0, 0, 1, 127, 0, 0, 1];
+        reply.extend_from_slice(&relay.local_addr().unwrap().port().to_be_bytes());
+        control.write_all(&reply).await.unwrap();
+
+        let mut buf = [0u8; 2048];
+        loop {
+            let (len, addr) = relay.recv_from(&mut buf).await.unwrap();
+            let host = b"tracker.example";
+            assert_eq!(buf[..5], [0, 0, 0, 3, host.len() as u8]);
+            assert_eq!(&buf[5..5 + host.len()], host);
+            let req = &buf[5 + host.len() + 2..len];
+            let action = u32::from_be_bytes(req[8..12].try_into().unwrap());
+            let mut reply = Vec::new();
+            reply.extend_from_slice(&req[8..16]);
+            match action {
+                ACTION_CONNECT => reply.extend_from_slice(&42u64.to_be_bytes()),
+                ACTION_ANNOUNCE => {
+                    for v in [900u32, 1, 2] {
+                        reply.extend_from_slice(&v.to_be_bytes());
+                    }
+                }
+                _ => panic!("unexpected action {action}"),
+            }
+            let mut wrapped = Vec::new();
+            Socks5UdpRelay::encapsulate(
+                "1.2.3.4:6969".parse::<SocketAddr>().unwrap(),
+                &reply,
+                &mut wrapped,
+            )
+            .unwrap();
+            relay.send_to(&wrapped, addr).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_announce_hostname_through_socks5_relay() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_port = listener.local_addr().unwrap().port();
+        tokio::spawn(run_fake_socks5_tracker(listener));
+
+        let relay = Socks5UdpRelay::associate("127.0.0.1", proxy_port, None)
+            .await
+            .unwrap();
+        let cancel_token = tokio_util::sync::CancellationToken::new();
+        let client = UdpTrackerClient::new_with_socks5_relay(cancel_token.clone(), None, relay)
+            .await
+            .unwrap();
+        assert!(client.is_proxied());
+
+        let response = client
+            .announce(
+                TrackerAddr::Host("tracker.example".to_owned(), 6969),
+                AnnounceFields {
+                    info_hash: Id20::new([1; 20]),
+                    peer_id: generate_peer_id(b"-xx1234-"),
+                    downloaded: 0,
+                    left: 0,
+                    uploaded: 0,
+                    event: EVENT_NONE,
+                    key: 0,
+                    port: 24563,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.interval, 900);
+        cancel_token.cancel();
+    }
+
     #[test]
     fn test_parse_announce() {
         let b = include_bytes!("../resources/test/udp-tracker-announce-response.bin");
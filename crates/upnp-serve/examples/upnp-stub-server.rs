@@ -1,10 +1,9 @@
 use std::{
-    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    net::{Ipv4Addr, SocketAddrV4},
     str::FromStr,
 };
 
 use anyhow::Context;
-use axum::routing::get;
 use librqbit_upnp_serve::{
     UpnpServer, UpnpServerOptions,
     services::content_directory::{
@@ -13,7 +12,7 @@ use librqbit_upnp_serve::{
     },
 };
 use mime_guess::Mime;
-use tracing::{error, info};
+use tracing::info;
 
 struct VecWrap(Vec<ItemOrContainer>);
 
@@ -49,21 +48,21 @@ async fn main() -> anyhow::Result<()> {
     const HTTP_PREFIX: &str = "/upnp";
 
     info!("Creating UpnpServer");
-    let mut server = UpnpServer::new(UpnpServerOptions {
+    let server = UpnpServer::new(UpnpServerOptions {
         friendly_name: "demo upnp server".to_owned(),
         http_listen_port: HTTP_PORT,
         http_prefix: HTTP_PREFIX.to_owned(),
         browse_provider: Box::new(items),
         cancellation_token: Default::default(),
+        enable_ipv6: true,
+        server_string: None,
+        manufacturer: None,
+        model_name: None,
+        model_number: None,
+        model_description: None,
     })
     .await?;
 
-    let app = axum::Router::new()
-        .route("/", get(|| async { "hello world" }))
-        .nest(HTTP_PREFIX, server.take_router()?)
-        .layer(tower_http::trace::TraceLayer::new_for_http())
-        .into_make_service_with_connect_info::<SocketAddr>();
-
     use tokio::net::TcpListener;
 
     let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, HTTP_PORT);
@@ -73,17 +72,11 @@ async fn main() -> anyhow::Result<()> {
         .await
         .with_context(|| format!("error binding to {addr}"))?;
 
-    tokio::spawn(async move {
-        let res = axum::serve(listener, app).await;
-        error!(error=?res, "error running HTTP server");
-    });
-
-    info!("Running SSDP");
+    info!("Running UPnP server");
     server
-        .run_ssdp_forever()
+        .serve(listener)
         .await
-        .context("error running SSDP")?;
+        .context("error running UPnP server")?;
 
-    error!("Unreachable");
     Ok(())
 }
@@ -3,7 +3,17 @@ pub const UPNP_DEVICE_MEDIASERVER: &str = "urn:schemas-upnp-org:device:MediaServ
 
 pub const SOAP_ACTION_CONTENT_DIRECTORY_BROWSE: &[u8] =
     b"\"urn:schemas-upnp-org:service:ContentDirectory:1#Browse\"";
+pub const SOAP_ACTION_CONTENT_DIRECTORY_SEARCH: &[u8] =
+    b"\"urn:schemas-upnp-org:service:ContentDirectory:1#Search\"";
+pub const SOAP_ACTION_GET_SEARCH_CAPABILITIES: &[u8] =
+    b"\"urn:schemas-upnp-org:service:ContentDirectory:1#GetSearchCapabilities\"";
 pub const SOAP_ACTION_GET_SYSTEM_UPDATE_ID: &[u8] =
     b"\"urn:schemas-upnp-org:service:ContentDirectory:1#GetSystemUpdateID\"";
 
+pub const DEFAULT_SERVER_STRING: &str = "Linux/3.4 UPnP/1.0 rqbit/1";
+pub const DEFAULT_MANUFACTURER: &str = "rqbit developers";
+pub const DEFAULT_MODEL_NAME: &str = "1.0.0";
+pub const DEFAULT_MODEL_NUMBER: &str = env!("CARGO_PKG_VERSION");
+pub const DEFAULT_MODEL_DESCRIPTION: &str = "rqbit BitTorrent client";
+
 pub const CONTENT_TYPE_XML_UTF8: &str = "text/xml; charset=\"utf-8\"";
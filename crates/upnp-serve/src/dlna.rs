@@ -0,0 +1,96 @@
+// DLNA profile detection for the protocolInfo attribute of DIDL-Lite <res> elements.
+//
+// Strict renderers (mostly TVs) refuse to play items that don't declare a DLNA.ORG_PN
+// profile they know. We can't inspect codecs, so the profile is guessed from the container
+// (file extension and MIME type), picking the most common profile for it.
+
+use mime_guess::Mime;
+
+// Streaming transfer mode, background transfer mode, connection stalling, DLNA v1.5.
+const DLNA_ORG_FLAGS: &str = "01700000000000000000000000000000";
+
+fn extension(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let (_, ext) = path
+        .rsplit_once('/')
+        .unwrap_or(("", path))
+        .1
+        .rsplit_once('.')?;
+    Some(ext.to_ascii_lowercase())
+}
+
+pub(crate) fn dlna_profile(mime: &Mime, url: &str) -> Option<&'static str> {
+    let by_ext = match extension(url).as_deref() {
+        Some("mp3") => Some("MP3"),
+        Some("m4a" | "aac") => Some("AAC_ISO_320"),
+        Some("wma") => Some("WMABASE"),
+        Some("jpg" | "jpeg") => Some("JPEG_LRG"),
+        Some("png") => Some("PNG_LRG"),
+        Some("gif") => Some("GIF_LRG"),
+        Some("mp4" | "m4v") => Some("AVC_MP4_MP_SD_AAC_MULT5"),
+        Some("mpg" | "mpeg") => Some("MPEG_PS_PAL"),
+        Some("ts" | "m2ts" | "mts") => Some("AVC_TS_MP_HD_AAC_MULT5"),
+        Some("wmv") => Some("WMVMED_BASE"),
+        Some("avi" | "mkv" | "webm" | "flac" | "ogg") => return None,
+        _ => None,
+    };
+    if by_ext.is_some() {
+        return by_ext;
+    }
+    match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("audio", "mpeg") => Some("MP3"),
+        ("audio", "mp4" | "aac" | "x-m4a") => Some("AAC_ISO_320"),
+        ("image", "jpeg") => Some("JPEG_LRG"),
+        ("image", "png") => Some("PNG_LRG"),
+        ("video", "mp4") => Some("AVC_MP4_MP_SD_AAC_MULT5"),
+        ("video", "mpeg") => Some("MPEG_PS_PAL"),
+        _ => None,
+    }
+}
+
+/// E.g. "http-get:*:video/mp4:DLNA.ORG_PN=AVC_MP4_MP_SD_AAC_MULT5;DLNA.ORG_OP=01;...".
+/// If the profile isn't known, the generic "*" is used instead, e.g. "http-get:*:video/webm:*".
+pub(crate) fn protocol_info(mime: &Mime, url: &str) -> String {
+    match dlna_profile(mime, url) {
+        Some(pn) => format!(
+            "http-get:*:{mime}:DLNA.ORG_PN={pn};DLNA.ORG_OP=01;DLNA.ORG_CI=0;DLNA.ORG_FLAGS={DLNA_ORG_FLAGS}"
+        ),
+        None => format!("http-get:*:{mime}:*"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use mime_guess::Mime;
+
+    use super::protocol_info;
+
+    #[test]
+    fn test_protocol_info() {
+        let mp4 = Mime::from_str("video/mp4").unwrap();
+        assert_eq!(
+            protocol_info(
+                &mp4,
+                "http://127.0.0.1:3030/torrents/1/stream/0/Some.File.MP4"
+            ),
+            "http-get:*:video/mp4:DLNA.ORG_PN=AVC_MP4_MP_SD_AAC_MULT5;DLNA.ORG_OP=01;DLNA.ORG_CI=0;DLNA.ORG_FLAGS=01700000000000000000000000000000"
+        );
+
+        let mp3 = Mime::from_str("audio/mpeg").unwrap();
+        assert!(protocol_info(&mp3, "http://host/stream/0/song").contains("DLNA.ORG_PN=MP3;"));
+
+        let mkv = Mime::from_str("video/x-matroska").unwrap();
+        assert_eq!(
+            protocol_info(&mkv, "http://host/stream/0/file.mkv"),
+            "http-get:*:video/x-matroska:*"
+        );
+
+        let unknown = Mime::from_str("application/octet-stream").unwrap();
+        assert_eq!(
+            protocol_info(&unknown, "http://host/stream/0/file.bin"),
+            "http-get:*:application/octet-stream:*"
+        );
+    }
+}
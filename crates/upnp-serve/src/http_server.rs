@@ -25,6 +25,8 @@ pub struct RootDescriptionInputs<'a> {
     pub friendly_name: &'a str,
     pub manufacturer: &'a str,
     pub model_name: &'a str,
+    pub model_number: &'a str,
+    pub model_description: &'a str,
     pub unique_id: &'a str,
     pub http_prefix: &'a str,
 }
@@ -35,29 +37,25 @@ pub fn render_root_description_xml(input: &RootDescriptionInputs<'_>) -> String
         friendly_name = input.friendly_name,
         manufacturer = input.manufacturer,
         model_name = input.model_name,
+        model_number = input.model_number,
+        model_description = input.model_description,
         unique_id = input.unique_id,
         http_prefix = input.http_prefix
     )
 }
 
-pub fn make_router(
-    friendly_name: String,
-    http_prefix: String,
-    upnp_usn: String,
+pub fn make_state(
+    root_desc: &RootDescriptionInputs<'_>,
     browse_provider: Box<dyn ContentDirectoryBrowseProvider>,
     cancellation_token: CancellationToken,
-) -> anyhow::Result<axum::Router> {
-    let root_desc = render_root_description_xml(&RootDescriptionInputs {
-        friendly_name: &friendly_name,
-        manufacturer: "rqbit developers",
-        model_name: "1.0.0",
-        unique_id: &upnp_usn,
-        http_prefix: &http_prefix,
-    });
+) -> anyhow::Result<UnpnServerState> {
+    let root_desc = render_root_description_xml(root_desc);
 
-    let state = UpnpServerStateInner::new(root_desc.into(), browse_provider, cancellation_token)
-        .context("error creating UPNP server")?;
+    UpnpServerStateInner::new(root_desc.into(), browse_provider, cancellation_token)
+        .context("error creating UPNP server")
+}
 
+pub fn make_router(state: UnpnServerState) -> axum::Router {
     let content_dir_sub_handler = {
         let state = state.clone();
         move |request: axum::extract::Request| async move {
@@ -80,7 +78,7 @@ pub fn make_router(
         }
     };
 
-    let app = axum::Router::new()
+    axum::Router::new()
         .route("/description.xml", get(description_xml))
         .route(
             "/scpd/ContentDirectory.xml",
@@ -106,7 +104,5 @@ pub fn make_router(
             "/subscribe/ConnectionManager",
             connection_manager_sub_handler.into_service(),
         )
-        .with_state(state);
-
-    Ok(app)
+        .with_state(state)
 }
@@ -1,18 +1,18 @@
-use std::{
-    io::{Cursor, Write},
-    time::Duration,
-};
+use std::{sync::Arc, time::Duration};
 
-use anyhow::Context;
+use anyhow::{Context, bail};
 use gethostname::gethostname;
-use rand::{Rng, SeedableRng};
 use services::content_directory::ContentDirectoryBrowseProvider;
+use sha1w::{ISha256, Sha256};
 use ssdp::SsdpRunner;
+use state::UnpnServerState;
 
+use tokio::net::TcpListener;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, info};
 
 mod constants;
+mod dlna;
 mod http_server;
 pub mod services;
 mod ssdp;
@@ -20,41 +20,106 @@ pub mod state;
 mod subscriptions;
 mod templates;
 
+pub use state::UpnpServerHandle;
+
 pub struct UpnpServerOptions {
     pub friendly_name: String,
     pub http_listen_port: u16,
     pub http_prefix: String,
     pub browse_provider: Box<dyn ContentDirectoryBrowseProvider>,
     pub cancellation_token: CancellationToken,
+    pub enable_ipv6: bool,
+
+    /// The "Server" header of SSDP announcements and responses. Some renderers enable quirks
+    /// based on it. Defaults to "Linux/3.4 UPnP/1.0 rqbit/1".
+    pub server_string: Option<String>,
+    /// Device metadata shown in description.xml. Defaults are used for unset fields.
+    pub manufacturer: Option<String>,
+    pub model_name: Option<String>,
+    pub model_number: Option<String>,
+    pub model_description: Option<String>,
+}
+
+// These end up both in XML and in HTTP headers, and aren't escaped.
+fn validate_metadata_field(name: &str, value: &str) -> anyhow::Result<()> {
+    if let Some(c) = value
+        .chars()
+        .find(|c| c.is_control() || matches!(c, '<' | '>' | '&'))
+    {
+        bail!("invalid character {c:?} in UPnP server option {name}: {value:?}");
+    }
+    Ok(())
+}
+
+fn metadata_field<'a>(
+    name: &str,
+    value: &'a Option<String>,
+    default: &'a str,
+) -> anyhow::Result<&'a str> {
+    let value = value.as_deref().unwrap_or(default);
+    validate_metadata_field(name, value)?;
+    Ok(value)
 }
 
 pub struct UpnpServer {
     axum_router: Option<axum::Router>,
+    state: UnpnServerState,
     ssdp_runner: SsdpRunner,
+    http_prefix: String,
+    cancellation_token: CancellationToken,
 }
 
-fn create_usn(opts: &UpnpServerOptions) -> anyhow::Result<String> {
-    let mut buf = [0u8; 32];
-    let mut cursor = Cursor::new(&mut buf[..]);
-
-    let _ = cursor.write_all(gethostname().as_encoded_bytes());
-    let _ = write!(
-        &mut cursor,
-        "{}{}{}",
-        opts.friendly_name, opts.http_listen_port, opts.http_prefix
-    );
-
-    let mut uuid = [0u8; 16];
-    rand::rngs::SmallRng::from_seed(buf).fill(&mut uuid);
-    let uuid = uuid::Builder::from_slice(&uuid)
-        .context("error generating UUID")?
-        .into_uuid();
-    Ok(format!("uuid:{uuid}"))
+fn create_usn(opts: &UpnpServerOptions) -> String {
+    create_usn_from(
+        gethostname().as_encoded_bytes(),
+        &opts.friendly_name,
+        opts.http_listen_port,
+        &opts.http_prefix,
+    )
+}
+
+// The USN must stay the same across restarts, otherwise renderers show duplicate servers.
+// So it's a name-based UUID derived from all the inputs.
+fn create_usn_from(hostname: &[u8], friendly_name: &str, port: u16, http_prefix: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        hostname,
+        friendly_name.as_bytes(),
+        &port.to_be_bytes(),
+        http_prefix.as_bytes(),
+    ] {
+        // Length-prefix each part so that different splits of the same bytes don't collide.
+        hasher.update(&(part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    let hash = hasher.finish();
+    // A custom (version 8) UUID, as the name-based versions only cover MD5 and SHA-1.
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    let uuid = uuid::Builder::from_custom_bytes(bytes).into_uuid();
+    format!("uuid:{uuid}")
 }
 
 impl UpnpServer {
     pub async fn new(opts: UpnpServerOptions) -> anyhow::Result<Self> {
-        let usn = create_usn(&opts).context("error generating USN")?;
+        use constants::*;
+
+        let usn = create_usn(&opts);
+        let server_string =
+            metadata_field("server_string", &opts.server_string, DEFAULT_SERVER_STRING)?;
+        let root_desc = http_server::RootDescriptionInputs {
+            friendly_name: &opts.friendly_name,
+            manufacturer: metadata_field("manufacturer", &opts.manufacturer, DEFAULT_MANUFACTURER)?,
+            model_name: metadata_field("model_name", &opts.model_name, DEFAULT_MODEL_NAME)?,
+            model_number: metadata_field("model_number", &opts.model_number, DEFAULT_MODEL_NUMBER)?,
+            model_description: metadata_field(
+                "model_description",
+                &opts.model_description,
+                DEFAULT_MODEL_DESCRIPTION,
+            )?,
+            unique_id: &usn,
+            http_prefix: &opts.http_prefix,
+        };
 
         let description_http_location = {
             let port = opts.http_listen_port;
@@ -72,27 +137,66 @@ impl UpnpServer {
         let ssdp_runner = crate::ssdp::SsdpRunner::new(ssdp::SsdpRunnerOptions {
             usn: usn.clone(),
             description_http_location,
-            server_string: "Linux/3.4 UPnP/1.0 rqbit/1".to_owned(),
+            server_string: server_string.to_owned(),
             notify_interval: Duration::from_secs(60),
             shutdown: opts.cancellation_token.clone(),
+            enable_ipv6: opts.enable_ipv6,
         })
         .await
         .context("error initializing SsdpRunner")?;
 
-        let router = crate::http_server::make_router(
-            opts.friendly_name,
-            opts.http_prefix,
-            usn,
+        let state = crate::http_server::make_state(
+            &root_desc,
             opts.browse_provider,
-            opts.cancellation_token,
+            opts.cancellation_token.clone(),
         )?;
 
         Ok(Self {
-            axum_router: Some(router),
+            axum_router: Some(crate::http_server::make_router(state.clone())),
+            state,
             ssdp_runner,
+            http_prefix: opts.http_prefix,
+            cancellation_token: opts.cancellation_token,
         })
     }
 
+    /// A handle to notify the server about content changes. It stays usable after the server
+    /// is moved into [`UpnpServer::serve`].
+    pub fn handle(&self) -> UpnpServerHandle {
+        UpnpServerHandle {
+            state: Arc::downgrade(&self.state),
+        }
+    }
+
+    /// Serve the UPnP HTTP endpoints on the listener and run SSDP announcements until the
+    /// cancellation token fires.
+    ///
+    /// On cancellation the server stops accepting connections and waits for in-flight
+    /// responses (e.g. active media streams) to finish.
+    ///
+    /// This is the recommended way to run the server. The listener must be bound to
+    /// `http_listen_port`, as that's the port announced over SSDP.
+    pub async fn serve(mut self, listener: TcpListener) -> anyhow::Result<()> {
+        let router = self.take_router()?;
+        let router = match self.http_prefix.as_str() {
+            "" | "/" => router,
+            prefix => axum::Router::new().nest(prefix, router),
+        };
+        let token = self.cancellation_token.clone();
+        let http = async move {
+            axum::serve(listener, router)
+                .with_graceful_shutdown(token.cancelled_owned())
+                .await
+                .context("error running UPnP HTTP server")
+        };
+        tokio::try_join!(http, self.run_ssdp_forever())?;
+        Ok(())
+    }
+
+    /// Take the HTTP router to serve it yourself, e.g. nested into another axum app.
+    ///
+    /// The caller is then responsible for nesting it under `http_prefix` and shutting it down.
+    /// Prefer [`UpnpServer::serve`] unless you need this.
     pub fn take_router(&mut self) -> anyhow::Result<axum::Router> {
         self.axum_router
             .take()
@@ -107,3 +211,42 @@ impl UpnpServer {
             .context("error running SSDP loop")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{create_usn_from, validate_metadata_field};
+
+    #[test]
+    fn test_usn_is_stable() {
+        let a = create_usn_from(b"host", "rqbit", 3030, "/upnp");
+        assert_eq!(a, create_usn_from(b"host", "rqbit", 3030, "/upnp"));
+        // Must never change, or renderers will show the server twice after an upgrade.
+        assert_eq!(a, "uuid:f8a89d0c-f439-8dbb-8892-a6f0c0d85be4");
+
+        assert_ne!(a, create_usn_from(b"host", "rqbit", 3031, "/upnp"));
+        assert_ne!(a, create_usn_from(b"hostr", "qbit", 3030, "/upnp"));
+    }
+
+    #[test]
+    fn test_usn_long_hostname_not_truncated() {
+        let hostname = [b'x'; 64];
+        assert_ne!(
+            create_usn_from(&hostname, "rqbit 1", 3030, "/upnp"),
+            create_usn_from(&hostname, "rqbit 2", 3030, "/upnp")
+        );
+    }
+
+    #[test]
+    fn test_validate_metadata_field() {
+        validate_metadata_field("model_name", "Windows Media Player Sharing").unwrap();
+        validate_metadata_field("server_string", "Linux/3.4 UPnP/1.0 rqbit/1").unwrap();
+        validate_metadata_field("manufacturer", "").unwrap();
+
+        for bad in ["a\r\nX-Injected: 1", "a<b", "Tom & Jerry", "tab\there"] {
+            assert!(
+                validate_metadata_field("manufacturer", bad).is_err(),
+                "{bad:?}"
+            );
+        }
+    }
+}
@@ -9,6 +9,7 @@ use tracing::{debug, trace};
 use crate::{
     constants::{
         CONTENT_TYPE_XML_UTF8, SOAP_ACTION_CONTENT_DIRECTORY_BROWSE,
+        SOAP_ACTION_CONTENT_DIRECTORY_SEARCH, SOAP_ACTION_GET_SEARCH_CAPABILITIES,
         SOAP_ACTION_GET_SYSTEM_UPDATE_ID,
     },
     state::UnpnServerState,
@@ -85,15 +86,35 @@ pub mod browse {
             Item(Item),
         }
 
+        pub(crate) const CONTAINER_UPNP_CLASS: &str = "object.container.storageFolder";
+
+        // Items without a class can't be played by renderers, so they aren't listed.
+        pub(crate) fn item_upnp_class(item: &Item) -> Option<&'static str> {
+            match item.mime_type.as_ref()?.type_().as_str() {
+                "video" => Some("object.item.videoItem"),
+                "audio" => Some("object.item.audioItem.musicTrack"),
+                "image" => Some("object.item.imageItem.photo"),
+                _ => None,
+            }
+        }
+
         pub(crate) fn render(items: impl IntoIterator<Item = ItemOrContainer>) -> String {
+            render_page("Browse", items, 0, 0)
+        }
+
+        // Render a Browse or Search response.
+        // requested_count=0 means all items starting from starting_index.
+        pub(crate) fn render_page(
+            action: &str,
+            items: impl IntoIterator<Item = ItemOrContainer>,
+            starting_index: usize,
+            requested_count: usize,
+        ) -> String {
             fn item_or_container(item_or_container: &ItemOrContainer) -> Option<String> {
                 fn item(item: &Item) -> Option<String> {
                     let mime = item.mime_type.as_ref()?;
-                    let upnp_class = match mime.type_().as_str() {
-                        "video" => "object.item.videoItem",
-                        _ => return None,
-                    };
-                    let mime = mime.to_string();
+                    let upnp_class = item_upnp_class(item)?;
+                    let protocol_info = crate::dlna::protocol_info(mime, &item.url);
 
                     Some(format!(
                         include_str!(
@@ -101,7 +122,7 @@ pub mod browse {
                         ),
                         id = item.id,
                         parent_id = item.parent_id,
-                        mime_type = mime,
+                        protocol_info = protocol_info,
                         url = item.url,
                         upnp_class = upnp_class,
                         title = item.title,
@@ -121,6 +142,7 @@ pub mod browse {
                         id = item.id,
                         parent_id = item.parent_id.map(|p| p as isize).unwrap_or(-1),
                         title = item.title,
+                        upnp_class = CONTAINER_UPNP_CLASS,
                         childCountTag = child_count_tag
                     )
                 }
@@ -132,6 +154,7 @@ pub mod browse {
             }
 
             struct Envelope<'a> {
+                action: &'a str,
                 items: &'a str,
                 number_returned: usize,
                 total_matches: usize,
@@ -156,6 +179,7 @@ pub mod browse {
                     include_str!(
                         "../resources/templates/content_directory/control/browse/response.tmpl.xml"
                     ),
+                    action = envelope.action,
                     items_encoded = items_encoded,
                     number_returned = envelope.number_returned,
                     total_matches = envelope.total_matches,
@@ -168,7 +192,18 @@ pub mod browse {
                 .filter_map(|item| item_or_container(&item))
                 .collect::<Vec<_>>();
             let total = all_items.len();
-            let all_items = all_items.join("");
+            let page = all_items
+                .iter()
+                .skip(starting_index)
+                .take(if requested_count == 0 {
+                    usize::MAX
+                } else {
+                    requested_count
+                })
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>();
+            let number_returned = page.len();
+            let all_items = page.join("");
 
             use std::time::{SystemTime, UNIX_EPOCH};
             let update_id = SystemTime::now()
@@ -177,8 +212,9 @@ pub mod browse {
                 .unwrap_or(0);
 
             render_response(&Envelope {
+                action,
                 items: &all_items,
-                number_returned: total,
+                number_returned,
                 total_matches: total,
                 update_id,
             })
@@ -186,13 +222,241 @@ pub mod browse {
     }
 }
 
+pub mod search {
+    pub mod request {
+        use anyhow::Context;
+        use serde_derive::Deserialize;
+
+        #[derive(Deserialize)]
+        struct Envelope {
+            #[serde(rename = "Body")]
+            body: Body,
+        }
+
+        #[derive(Deserialize)]
+        struct Body {
+            #[serde(rename = "Search")]
+            search: ContentDirectorySearchRequest,
+        }
+
+        #[derive(Deserialize, Debug)]
+        pub struct ContentDirectorySearchRequest {
+            #[serde(rename = "ContainerID")]
+            pub container_id: usize,
+            #[serde(rename = "SearchCriteria", default)]
+            pub search_criteria: String,
+            #[serde(rename = "StartingIndex", default)]
+            pub starting_index: usize,
+            #[serde(rename = "RequestedCount", default)]
+            pub requested_count: usize,
+        }
+
+        impl ContentDirectorySearchRequest {
+            pub fn parse(s: &str) -> anyhow::Result<Self> {
+                let envelope: Envelope =
+                    quick_xml::de::from_str(s).context("error deserializing")?;
+                Ok(envelope.body.search)
+            }
+        }
+    }
+
+    use anyhow::{Context, bail};
+
+    use super::browse::response::{CONTAINER_UPNP_CLASS, ItemOrContainer, item_upnp_class};
+
+    /// Properties we can search on. Reported through GetSearchCapabilities.
+    pub const SEARCH_CAPABILITIES: &str = "dc:title,upnp:class";
+
+    /// The supported subset of UPnP ContentDirectory search criteria, e.g.
+    /// `dc:title contains "foo" and upnp:class derivedfrom "object.item.videoItem"`.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum SearchCriteria {
+        /// "*", matches everything.
+        All,
+        Const(bool),
+        TitleContains(String),
+        TitleEquals(String),
+        ClassDerivedFrom(String),
+        ClassEquals(String),
+        And(Box<SearchCriteria>, Box<SearchCriteria>),
+        Or(Box<SearchCriteria>, Box<SearchCriteria>),
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Token<'a> {
+        LParen,
+        RParen,
+        Word(&'a str),
+        Quoted(String),
+    }
+
+    fn tokenize(s: &str) -> anyhow::Result<Vec<Token<'_>>> {
+        let mut tokens = Vec::new();
+        let mut chars = s.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            match c {
+                c if c.is_whitespace() => {}
+                '(' => tokens.push(Token::LParen),
+                ')' => tokens.push(Token::RParen),
+                '"' => {
+                    let mut value = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '\\')) => {
+                                value.push(chars.next().context("unterminated escape")?.1)
+                            }
+                            Some((_, '"')) => break,
+                            Some((_, c)) => value.push(c),
+                            None => bail!("unterminated string"),
+                        }
+                    }
+                    tokens.push(Token::Quoted(value));
+                }
+                _ => {
+                    let mut end = start + c.len_utf8();
+                    while let Some(&(idx, c)) = chars.peek() {
+                        if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                            break;
+                        }
+                        end = idx + c.len_utf8();
+                        chars.next();
+                    }
+                    tokens.push(Token::Word(&s[start..end]));
+                }
+            }
+        }
+        Ok(tokens)
+    }
+
+    struct Parser<'a> {
+        tokens: std::iter::Peekable<std::vec::IntoIter<Token<'a>>>,
+    }
+
+    impl Parser<'_> {
+        fn next_is_word(&mut self, word: &str) -> bool {
+            matches!(self.tokens.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(word))
+        }
+
+        // or has lower precedence than and.
+        fn parse_or(&mut self) -> anyhow::Result<SearchCriteria> {
+            let mut left = self.parse_and()?;
+            while self.next_is_word("or") {
+                self.tokens.next();
+                left = SearchCriteria::Or(Box::new(left), Box::new(self.parse_and()?));
+            }
+            Ok(left)
+        }
+
+        fn parse_and(&mut self) -> anyhow::Result<SearchCriteria> {
+            let mut left = self.parse_term()?;
+            while self.next_is_word("and") {
+                self.tokens.next();
+                left = SearchCriteria::And(Box::new(left), Box::new(self.parse_term()?));
+            }
+            Ok(left)
+        }
+
+        fn parse_term(&mut self) -> anyhow::Result<SearchCriteria> {
+            let property = match self.tokens.next() {
+                Some(Token::LParen) => {
+                    let inner = self.parse_or()?;
+                    if self.tokens.next() != Some(Token::RParen) {
+                        bail!("expected \")\"");
+                    }
+                    return Ok(inner);
+                }
+                Some(Token::Word(w)) => w,
+                t => bail!("expected property, got {t:?}"),
+            };
+            let op = match self.tokens.next() {
+                Some(Token::Word(op)) => op,
+                t => bail!("expected operator, got {t:?}"),
+            };
+            let value = match self.tokens.next() {
+                Some(Token::Quoted(v)) => v,
+                Some(Token::Word(v)) => v.to_owned(),
+                t => bail!("expected value, got {t:?}"),
+            };
+
+            if op.eq_ignore_ascii_case("exists") {
+                let value = match value.as_str() {
+                    "true" => true,
+                    "false" => false,
+                    _ => bail!("expected true or false after \"exists\""),
+                };
+                // Title and class are always present, nothing else is.
+                let present = matches!(property, "dc:title" | "upnp:class");
+                return Ok(SearchCriteria::Const(present == value));
+            }
+
+            Ok(match (property, op.to_ascii_lowercase().as_str()) {
+                ("dc:title", "contains") => SearchCriteria::TitleContains(value),
+                ("dc:title", "=") => SearchCriteria::TitleEquals(value),
+                ("upnp:class", "derivedfrom") => SearchCriteria::ClassDerivedFrom(value),
+                ("upnp:class", "=") => SearchCriteria::ClassEquals(value),
+                (property, op) => bail!("unsupported search criteria: {property} {op}"),
+            })
+        }
+    }
+
+    impl SearchCriteria {
+        pub fn parse(s: &str) -> anyhow::Result<Self> {
+            let s = s.trim();
+            if s.is_empty() || s == "*" {
+                return Ok(SearchCriteria::All);
+            }
+            let mut parser = Parser {
+                tokens: tokenize(s)?.into_iter().peekable(),
+            };
+            let criteria = parser.parse_or()?;
+            if let Some(t) = parser.tokens.next() {
+                bail!("unexpected trailing token {t:?}");
+            }
+            Ok(criteria)
+        }
+
+        pub fn matches(&self, item: &ItemOrContainer) -> bool {
+            let (title, class) = match item {
+                ItemOrContainer::Container(c) => (&c.title, Some(CONTAINER_UPNP_CLASS)),
+                ItemOrContainer::Item(i) => (&i.title, item_upnp_class(i)),
+            };
+            match self {
+                SearchCriteria::All => true,
+                SearchCriteria::Const(v) => *v,
+                SearchCriteria::TitleContains(v) => {
+                    title.to_lowercase().contains(&v.to_lowercase())
+                }
+                SearchCriteria::TitleEquals(v) => title == v,
+                SearchCriteria::ClassDerivedFrom(v) => class.is_some_and(|c| {
+                    c.strip_prefix(v.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+                }),
+                SearchCriteria::ClassEquals(v) => class == Some(v.as_str()),
+                SearchCriteria::And(l, r) => l.matches(item) && r.matches(item),
+                SearchCriteria::Or(l, r) => l.matches(item) || r.matches(item),
+            }
+        }
+    }
+
+    pub(crate) fn render_search_capabilities() -> String {
+        format!(
+            include_str!(
+                "../resources/templates/content_directory/control/get_search_capabilities/response.tmpl.xml"
+            ),
+            capabilities = SEARCH_CAPABILITIES
+        )
+    }
+}
+
 pub mod get_system_update_id {
-    pub(crate) fn render_notify(update_id: u64) -> String {
+    // ContainerUpdateIDs is a comma-separated list of "container id,update id" pairs.
+    pub(crate) fn render_notify(update_id: u64, container_update_ids: &str) -> String {
         format!(
             include_str!(
-                "../resources/templates/content_directory/subscriptions/system_update_id.tmpl.xml"
+                "../resources/templates/content_directory/subscriptions/propertyset.tmpl.xml"
             ),
-            system_update_id = update_id
+            system_update_id = update_id,
+            container_update_ids = container_update_ids
         )
     }
 
@@ -230,6 +494,7 @@ pub mod subscription {
         sid: &str,
         seq: u64,
         system_update_id: u64,
+        container_update_ids: &str,
     ) -> anyhow::Result<()> {
         // NOTIFY /callback_path HTTP/1.1
         // CONTENT-TYPE: text/xml; charset="utf-8"
@@ -238,7 +503,8 @@ pub mod subscription {
         // SID: uuid:<Subscription ID>
         // SEQ: <sequence number>
         //
-        let body = super::get_system_update_id::render_notify(system_update_id);
+        let body =
+            super::get_system_update_id::render_notify(system_update_id, container_update_ids);
 
         let resp = reqwest::Client::builder()
             .build()?
@@ -320,6 +586,60 @@ pub(crate) async fn http_handler(
                     .into_response(),
             }
         }
+        SOAP_ACTION_CONTENT_DIRECTORY_SEARCH => {
+            let http_hostname = match headers
+                .get("host")
+                .and_then(|h| std::str::from_utf8(h.as_bytes()).ok())
+            {
+                Some(h) => h,
+                None => return StatusCode::BAD_REQUEST.into_response(),
+            };
+
+            let request = match std::str::from_utf8(body)
+                .map_err(anyhow::Error::from)
+                .and_then(search::request::ContentDirectorySearchRequest::parse)
+            {
+                Ok(req) => req,
+                Err(e) => {
+                    debug!(error=?e, "error parsing XML");
+                    return (StatusCode::BAD_REQUEST, "cannot parse request").into_response();
+                }
+            };
+
+            let criteria = match search::SearchCriteria::parse(&request.search_criteria) {
+                Ok(c) => c,
+                Err(e) => {
+                    debug!(criteria=request.search_criteria, error=?e, "error parsing search criteria");
+                    return (StatusCode::BAD_REQUEST, "unsupported search criteria")
+                        .into_response();
+                }
+            };
+
+            match state
+                .provider
+                .search(request.container_id, &criteria, http_hostname)
+            {
+                Ok(items) => (
+                    [(CONTENT_TYPE, CONTENT_TYPE_XML_UTF8)],
+                    browse::response::render_page(
+                        "Search",
+                        items,
+                        request.starting_index,
+                        request.requested_count,
+                    ),
+                )
+                    .into_response(),
+                Err(e) => {
+                    debug!(error=?e, "search failed");
+                    (StatusCode::NOT_IMPLEMENTED, "").into_response()
+                }
+            }
+        }
+        SOAP_ACTION_GET_SEARCH_CAPABILITIES => (
+            [(CONTENT_TYPE, CONTENT_TYPE_XML_UTF8)],
+            search::render_search_capabilities(),
+        )
+            .into_response(),
         SOAP_ACTION_GET_SYSTEM_UPDATE_ID => {
             let update_id = state.system_update_id.load(Ordering::Relaxed);
             (
@@ -339,6 +659,17 @@ pub trait ContentDirectoryBrowseProvider: Send + Sync {
     fn browse_direct_children(&self, parent_id: usize, http_hostname: &str)
     -> Vec<ItemOrContainer>;
     fn browse_metadata(&self, object_id: usize, http_hostname: &str) -> Vec<ItemOrContainer>;
+
+    /// Find all descendants of the container matching the criteria.
+    fn search(
+        &self,
+        container_id: usize,
+        criteria: &search::SearchCriteria,
+        http_hostname: &str,
+    ) -> anyhow::Result<Vec<ItemOrContainer>> {
+        let _ = (container_id, criteria, http_hostname);
+        anyhow::bail!("search is not supported")
+    }
 }
 
 #[cfg(test)]
@@ -352,4 +683,43 @@ mod tests {
         assert_eq!(req.object_id, 5);
         assert_eq!(req.browse_flag, BrowseFlag::BrowseDirectChildren)
     }
+
+    #[test]
+    fn test_search_criteria() {
+        use super::browse::response::{Container, Item, ItemOrContainer};
+        use super::search::SearchCriteria;
+
+        let video = ItemOrContainer::Item(Item {
+            id: 1,
+            parent_id: 0,
+            title: "Big Buck Bunny.mkv".to_owned(),
+            mime_type: Some("video/x-matroska".parse().unwrap()),
+            url: "http://localhost/1.mkv".to_owned(),
+            size: 1,
+        });
+        let folder = ItemOrContainer::Container(Container {
+            id: 2,
+            parent_id: Some(0),
+            children_count: None,
+            title: "bunny".to_owned(),
+        });
+
+        let c = SearchCriteria::parse(r#"dc:title contains "BUNNY""#).unwrap();
+        assert!(c.matches(&video));
+        assert!(c.matches(&folder));
+
+        let c = SearchCriteria::parse(
+            r#"(upnp:class derivedfrom "object.item.videoItem" or upnp:class derivedfrom "object.item.audioItem") and @refID exists false"#,
+        )
+        .unwrap();
+        assert!(c.matches(&video));
+        assert!(!c.matches(&folder));
+
+        let c = SearchCriteria::parse(r#"upnp:class derivedfrom "object.item.video""#).unwrap();
+        assert!(!c.matches(&video));
+
+        assert_eq!(SearchCriteria::parse("*").unwrap(), SearchCriteria::All);
+        assert!(SearchCriteria::parse(r#"upnp:artist contains "x""#).is_err());
+        assert!(SearchCriteria::parse(r#"dc:title contains "x"#).is_err());
+    }
 }
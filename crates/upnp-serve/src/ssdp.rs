@@ -5,7 +5,9 @@ use std::{
 
 use anyhow::{Context, bail};
 use bstr::BStr;
+use futures::{StreamExt, stream::FuturesUnordered};
 use librqbit_dualstack_sockets::{MulticastOpts, MulticastUdpSocket};
+use rand::Rng;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, trace, warn};
 
@@ -14,7 +16,6 @@ use crate::constants::{UPNP_DEVICE_MEDIASERVER, UPNP_DEVICE_ROOT};
 const SSDP_PORT: u16 = 1900;
 const SSDP_MCAST_IPV4: SocketAddrV4 =
     SocketAddrV4::new(Ipv4Addr::new(239, 255, 255, 250), SSDP_PORT);
-#[allow(unused)]
 const SSDP_MCAST_IPV6_LINK_LOCAL: SocketAddrV6 = SocketAddrV6::new(
     Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xc),
     SSDP_PORT,
@@ -31,6 +32,13 @@ const SSDP_MCAST_IPV6_SITE_LOCAL: SocketAddrV6 = SocketAddrV6::new(
 const NTS_ALIVE: &str = "ssdp:alive";
 const NTS_BYEBYE: &str = "ssdp:byebye";
 
+// UPnP 1.1 says control points shouldn't send MX above 5, and devices should treat larger values as 5.
+const MAX_MX: Duration = Duration::from_secs(5);
+// MX is required for multicast M-SEARCH, but some control points omit it.
+const DEFAULT_MX: Duration = Duration::from_secs(1);
+// Responses waiting for their delay. Anything above this is dropped to avoid response storms.
+const MAX_PENDING_RESPONSES: usize = 128;
+
 #[derive(Debug)]
 pub enum SsdpMessage<'a, 'h> {
     MSearch(SsdpMSearchRequest<'a>),
@@ -46,9 +54,21 @@ pub struct SsdpMSearchRequest<'a> {
     pub host: &'a BStr,
     pub man: &'a BStr,
     pub st: &'a BStr,
+    pub mx: Option<&'a BStr>,
 }
 
 impl SsdpMSearchRequest<'_> {
+    // How long to wait at most before responding, as asked by the control point.
+    fn max_response_delay(&self) -> Duration {
+        let mx = self
+            .mx
+            .and_then(|mx| std::str::from_utf8(mx).ok())
+            .and_then(|mx| mx.trim().parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_MX);
+        mx.min(MAX_MX)
+    }
+
     fn matches_media_server(&self) -> bool {
         if self.man != "\"ssdp:discover\"" {
             return false;
@@ -78,12 +98,14 @@ pub fn try_parse_ssdp<'a, 'h>(
             let mut host = None;
             let mut man = None;
             let mut st = None;
+            let mut mx = None;
 
             for header in req.headers.iter() {
                 match header.name {
                     "HOST" | "Host" | "host" => host = Some(header.value),
                     "MAN" | "Man" | "man" => man = Some(header.value),
                     "ST" | "St" | "st" => st = Some(header.value),
+                    "MX" | "Mx" | "mx" => mx = Some(BStr::new(header.value)),
                     other => trace!(header=?BStr::new(other), "ignoring SSDP header"),
                 }
             }
@@ -93,6 +115,7 @@ pub fn try_parse_ssdp<'a, 'h>(
                     host: BStr::new(host),
                     man: BStr::new(man),
                     st: BStr::new(st),
+                    mx,
                 })),
                 _ => bail!("not all of host, man and st are set"),
             }
@@ -107,6 +130,9 @@ pub struct SsdpRunnerOptions {
     pub server_string: String,
     pub notify_interval: Duration,
     pub shutdown: CancellationToken,
+    /// If set, the socket is bound dualstack and also joins the IPv6 SSDP groups
+    /// (FF02::C link-local and FF05::C site-local). Otherwise it's IPv4-only.
+    pub enable_ipv6: bool,
 }
 
 pub struct SsdpRunner {
@@ -116,12 +142,19 @@ pub struct SsdpRunner {
 
 impl SsdpRunner {
     pub async fn new(opts: SsdpRunnerOptions) -> anyhow::Result<Self> {
+        let (bind_addr, link_local): (SocketAddr, _) = if opts.enable_ipv6 {
+            (
+                (Ipv6Addr::UNSPECIFIED, SSDP_PORT).into(),
+                Some(SSDP_MCAST_IPV6_LINK_LOCAL),
+            )
+        } else {
+            ((Ipv4Addr::UNSPECIFIED, SSDP_PORT).into(), None)
+        };
         let socket = MulticastUdpSocket::new(
-            (Ipv6Addr::UNSPECIFIED, SSDP_PORT).into(),
+            bind_addr,
             SSDP_MCAST_IPV4,
             SSDP_MCAST_IPV6_SITE_LOCAL,
-            None,
-            // Some(SSDP_MCAST_IPV6_LINK_LOCAL),
+            link_local,
             None,
         )
         .await
@@ -135,13 +168,20 @@ impl SsdpRunner {
         device_kind: &str,
         nts: &str,
         opts: &MulticastOpts,
-    ) -> String {
+    ) -> Option<String> {
+        if !is_addr_enabled(opts.mcast_addr().ip(), self.opts.enable_ipv6) {
+            return None;
+        }
+        if matches!(opts.iface_ip(), IpAddr::V6(a) if a.is_unicast_link_local()) {
+            // See the comment in generate_ssdp_discover_response(), link-local locations are useless.
+            trace!(?opts, "not sending NOTIFY from a link-local address");
+            return None;
+        }
         let usn: &str = &self.opts.usn;
         let server: &str = &self.opts.server_string;
         let host = addr_no_scope(&opts.mcast_addr());
-        let mut location = self.opts.description_http_location.clone();
-        let _ = location.set_ip_host(opts.iface_ip());
-        format!(
+        let location = self.location_for(opts.iface_ip());
+        Some(format!(
             "NOTIFY * HTTP/1.1\r
 Host: {host}\r
 Cache-Control: max-age=75\r
@@ -152,7 +192,14 @@ Server: {server}\r
 USN: {usn}::{device_kind}\r
 \r
 "
-        )
+        ))
+    }
+
+    // IPv6 literals are bracketed by set_ip_host(), e.g. "http://[fd00::1]:9005/upnp/description.xml".
+    fn location_for(&self, ip: IpAddr) -> url::Url {
+        let mut location = self.opts.description_http_location.clone();
+        let _ = location.set_ip_host(ip);
+        location
     }
 
     fn generate_ssdp_discover_response(
@@ -160,6 +207,10 @@ USN: {usn}::{device_kind}\r
         st: &str,
         addr: SocketAddr,
     ) -> anyhow::Result<Option<String>> {
+        if !is_addr_enabled(addr.ip(), self.opts.enable_ipv6) {
+            debug!(?addr, "IPv6 is disabled, not replying");
+            return Ok(None);
+        }
         if matches!(addr.ip(), IpAddr::V6(a) if a.is_unicast_link_local()) {
             // VLC doesn't work with link-local URLs no matter what I tried. Furthermore, it probably
             // wants an interface name in its scope id, which we of course don't know as its local to
@@ -168,11 +219,7 @@ USN: {usn}::{device_kind}\r
             return Ok(None);
         }
         let local_ip = ::librqbit_upnp::get_local_ip_relative_to(addr, self.socket.nics())?;
-        let location = {
-            let mut loc = self.opts.description_http_location.clone();
-            let _ = loc.set_ip_host(local_ip);
-            loc
-        };
+        let location = self.location_for(local_ip);
         let usn = &self.opts.usn;
         let server = &self.opts.server_string;
         Ok(Some(format!(
@@ -191,7 +238,6 @@ Content-Length: 0\r\n\r\n"
         self.socket
             .try_send_mcast_everywhere(&|opts| {
                 self.generate_notify_message(UPNP_DEVICE_MEDIASERVER, nts, opts)
-                    .into()
             })
             .await
     }
@@ -204,7 +250,12 @@ Content-Length: 0\r\n\r\n"
         }
     }
 
-    async fn process_incoming_message(&self, msg: &[u8], addr: SocketAddr) -> anyhow::Result<()> {
+    // Returns the response to an M-SEARCH along with the random delay to send it after.
+    fn process_incoming_message(
+        &self,
+        msg: &[u8],
+        addr: SocketAddr,
+    ) -> anyhow::Result<Option<(Duration, String)>> {
         let mut headers = [httparse::EMPTY_HEADER; 16];
         trace!(content = ?BStr::new(msg), ?addr, "received message");
         let parsed = try_parse_ssdp(msg, &mut headers);
@@ -212,46 +263,63 @@ Content-Length: 0\r\n\r\n"
             Ok(SsdpMessage::MSearch(msg)) => msg,
             Ok(m) => {
                 trace!("ignoring {m:?}");
-                return Ok(());
+                return Ok(None);
             }
             Err(e) => {
                 debug!(error=?e, "error parsing SSDP message");
-                return Ok(());
+                return Ok(None);
             }
         };
         if !msg.matches_media_server() {
             trace!("not a media server request, ignoring");
-            return Ok(());
+            return Ok(None);
         }
 
-        if let Ok(st) = std::str::from_utf8(msg.st) {
-            let response = self.generate_ssdp_discover_response(st, addr)?;
-            if let Some(response) = response {
-                trace!(content = response, ?addr, "sending SSDP discover response");
-                self.socket
-                    .send_to(response.as_bytes(), addr)
-                    .await
-                    .context("error sending")?;
-            }
-        }
+        let Ok(st) = std::str::from_utf8(msg.st) else {
+            return Ok(None);
+        };
+        let Some(response) = self.generate_ssdp_discover_response(st, addr)? else {
+            return Ok(None);
+        };
+        let delay = rand::rng().random_range(Duration::ZERO..=msg.max_response_delay());
+        Ok(Some((delay, response)))
+    }
 
-        Ok(())
+    async fn send_response_after(&self, delay: Duration, response: String, addr: SocketAddr) {
+        tokio::time::sleep(delay).await;
+        trace!(content = response, ?addr, "sending SSDP discover response");
+        if let Err(e) = self.socket.send_to(response.as_bytes(), addr).await {
+            warn!(error=?e, ?addr, "error sending SSDP discover response")
+        }
     }
 
     async fn task_respond_on_msearches(&self) {
         let mut buf = vec![0u8; 16184];
+        let mut pending = FuturesUnordered::new();
 
         loop {
-            let (sz, addr) = match self.socket.recv_from(&mut buf).await {
-                Ok((sz, addr)) => (sz, addr),
-                Err(e) => {
-                    warn!(error=?e, "error receiving");
-                    return;
-                }
+            let (sz, addr) = tokio::select! {
+                r = self.socket.recv_from(&mut buf) => match r {
+                    Ok((sz, addr)) => (sz, addr),
+                    Err(e) => {
+                        warn!(error=?e, "error receiving");
+                        return;
+                    }
+                },
+                Some(()) = pending.next(), if !pending.is_empty() => continue,
             };
             let msg = &buf[..sz];
-            if let Err(e) = self.process_incoming_message(msg, addr).await {
-                warn!(error=?e, ?addr, "error processing incoming SSDP message")
+            match self.process_incoming_message(msg, addr) {
+                Ok(Some((delay, response))) => {
+                    if pending.len() >= MAX_PENDING_RESPONSES {
+                        debug!(?addr, "too many pending SSDP responses, dropping");
+                        continue;
+                    }
+                    trace!(?delay, ?addr, "scheduling SSDP discover response");
+                    pending.push(self.send_response_after(delay, response, addr));
+                }
+                Ok(None) => {}
+                Err(e) => warn!(error=?e, ?addr, "error processing incoming SSDP message"),
             }
         }
     }
@@ -259,6 +327,9 @@ Content-Length: 0\r\n\r\n"
     async fn try_send_example_msearch(&self) {
         self.socket
             .try_send_mcast_everywhere(&|opts| {
+                if !is_addr_enabled(opts.mcast_addr().ip(), self.opts.enable_ipv6) {
+                    return None;
+                }
                 let dest = addr_no_scope(&opts.mcast_addr());
                 format!(
                     "M-SEARCH * HTTP/1.1\r
@@ -293,6 +364,11 @@ MX: 2\r\n\r\n"
     }
 }
 
+// IPv4-mapped IPv6 addresses (seen on dualstack sockets) count as IPv4.
+fn is_addr_enabled(addr: IpAddr, enable_ipv6: bool) -> bool {
+    enable_ipv6 || addr.to_canonical().is_ipv4()
+}
+
 fn addr_no_scope(addr: &SocketAddr) -> SocketAddr {
     match addr {
         SocketAddr::V4(a) => SocketAddr::V4(*a),
@@ -303,3 +379,77 @@ fn addr_no_scope(addr: &SocketAddr) -> SocketAddr {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{SsdpMessage, is_addr_enabled, try_parse_ssdp};
+
+    fn parse_msearch(msg: &str, f: impl FnOnce(&super::SsdpMSearchRequest<'_>)) {
+        let mut headers = [httparse::EMPTY_HEADER; 16];
+        match try_parse_ssdp(msg.as_bytes(), &mut headers).unwrap() {
+            SsdpMessage::MSearch(m) => f(&m),
+            other => panic!("expected M-SEARCH, got {other:?}"),
+        }
+    }
+
+    fn msearch(st: &str, mx: Option<&str>) -> String {
+        let mx = mx.map(|mx| format!("MX: {mx}\r\n")).unwrap_or_default();
+        format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nST: {st}\r\n{mx}\r\n"
+        )
+    }
+
+    #[test]
+    fn test_msearch_mx() {
+        let st = "urn:schemas-upnp-org:device:MediaServer:1";
+        for (mx, expected) in [
+            (Some("3"), 3),
+            (Some(" 0 "), 0),
+            (Some("120"), 5),
+            (Some("garbage"), 1),
+            (None, 1),
+        ] {
+            parse_msearch(&msearch(st, mx), |m| {
+                assert_eq!(
+                    m.max_response_delay(),
+                    Duration::from_secs(expected),
+                    "{mx:?}"
+                )
+            });
+        }
+    }
+
+    #[test]
+    fn test_msearch_st_filter() {
+        for (st, expected) in [
+            ("upnp:rootdevice", true),
+            ("urn:schemas-upnp-org:device:MediaServer:1", true),
+            ("urn:schemas-upnp-org:device:MediaRenderer:1", false),
+            ("urn:dial-multiscreen-org:service:dial:1", false),
+        ] {
+            parse_msearch(&msearch(st, Some("2")), |m| {
+                assert_eq!(m.matches_media_server(), expected, "{st}")
+            });
+        }
+    }
+
+    #[test]
+    fn test_is_addr_enabled() {
+        for (addr, enable_ipv6, expected) in [
+            ("239.255.255.250", false, true),
+            ("ff05::c", false, false),
+            ("fd00::1", false, false),
+            ("::ffff:192.168.1.2", false, true),
+            ("239.255.255.250", true, true),
+            ("ff05::c", true, true),
+        ] {
+            assert_eq!(
+                is_addr_enabled(addr.parse().unwrap(), enable_ipv6),
+                expected,
+                "{addr} enable_ipv6={enable_ipv6}"
+            );
+        }
+    }
+}
@@ -1,16 +1,14 @@
 use std::{
     sync::{
-        Arc,
+        Arc, Weak,
         atomic::{AtomicU64, Ordering},
     },
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::Context;
 use axum::body::Bytes;
-use librqbit_core::spawn_utils::spawn_with_cancel;
 use tokio_util::sync::CancellationToken;
-use tracing::{Span, debug_span};
+use tracing::{Span, debug_span, trace};
 
 use crate::{ContentDirectoryBrowseProvider, subscriptions::Subscriptions};
 
@@ -27,6 +25,7 @@ pub struct UpnpServerStateInner {
     _drop_guard: tokio_util::sync::DropGuard,
 }
 
+// Start from the current time so that the ID keeps increasing across restarts.
 fn new_system_update_id() -> anyhow::Result<u64> {
     Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
 }
@@ -49,31 +48,38 @@ impl UpnpServerStateInner {
             connection_manager_subscriptions: Default::default(),
             system_update_bcast_tx: btx,
             _drop_guard: drop_guard,
-            span: span.clone(),
-            cancel_token: cancel_token.clone(),
-        });
-
-        spawn_with_cancel::<anyhow::Error>(
-            debug_span!(parent: span, "system_update_id_updater"),
-            "system_update_id_updater",
+            span,
             cancel_token,
-            {
-                let state = Arc::downgrade(&state);
-                async move {
-                    let mut interval = tokio::time::interval(Duration::from_secs(10));
-                    loop {
-                        interval.tick().await;
-                        let new_value = new_system_update_id()?;
-                        let state = state.upgrade().context("upnp server is dead")?;
-                        state.system_update_id.store(new_value, Ordering::Relaxed);
-                        let _ = state.system_update_bcast_tx.send(new_value);
-                    }
-                }
-            },
-        );
+        });
 
         Ok(state)
     }
+
+    pub(crate) fn notify_content_changed(&self) {
+        let new_value = self.system_update_id.fetch_add(1, Ordering::Relaxed) + 1;
+        trace!(system_update_id = new_value, "content changed");
+        // Errors only mean there are no subscribers.
+        let _ = self.system_update_bcast_tx.send(new_value);
+    }
+}
+
+/// A handle for the app owning [`crate::UpnpServer`] to tell it about changes.
+///
+/// Doesn't keep the server alive.
+#[derive(Clone)]
+pub struct UpnpServerHandle {
+    pub(crate) state: Weak<UpnpServerStateInner>,
+}
+
+impl UpnpServerHandle {
+    /// Signal that the browse tree changed, e.g. items were added or removed.
+    ///
+    /// This bumps the ContentDirectory SystemUpdateID and notifies subscribed control points.
+    pub fn notify_content_changed(&self) {
+        if let Some(state) = self.state.upgrade() {
+            state.notify_content_changed();
+        }
+    }
 }
 
 pub type UnpnServerState = Arc<UpnpServerStateInner>;
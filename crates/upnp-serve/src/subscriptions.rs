@@ -10,14 +10,19 @@ use std::{
     time::Duration,
 };
 use tokio::sync::{Notify, broadcast::error::RecvError};
+use tokio_util::sync::CancellationToken;
 use tracing::{Instrument, debug, debug_span, trace, warn};
 
+const INITIAL_EVENT_DELAY: Duration = Duration::from_millis(100);
+
 pub struct Subscription {
     #[allow(dead_code)]
     pub url: url::Url,
     pub seq: u64,
     pub timeout: Duration,
     pub refresh_notify: Arc<Notify>,
+    // Stops the task sending events to this subscriber.
+    pub cancel_token: CancellationToken,
 }
 
 #[derive(Default)]
@@ -26,7 +31,12 @@ pub struct Subscriptions {
 }
 
 impl Subscriptions {
-    pub fn add(&self, url: url::Url, timeout: Duration) -> (String, Arc<Notify>) {
+    pub fn add(
+        &self,
+        url: url::Url,
+        timeout: Duration,
+        cancel_token: CancellationToken,
+    ) -> (String, Arc<Notify>) {
         let sid = format!("uuid:{}", uuid::Uuid::new_v4());
         let notify = Arc::new(Notify::default());
         self.subs.write().insert(
@@ -36,6 +46,7 @@ impl Subscriptions {
                 seq: 0,
                 timeout,
                 refresh_notify: notify.clone(),
+                cancel_token,
             },
         );
         (sid, notify)
@@ -66,6 +77,7 @@ impl Subscriptions {
     pub fn remove(&self, sid: &str) -> anyhow::Result<Subscription> {
         let mut g = self.subs.write();
         let s = g.remove(sid).context("no such subscription")?;
+        s.cancel_token.cancel();
         Ok(s)
     }
 }
@@ -80,6 +92,9 @@ pub enum SubscribeRequest {
         sid: String,
         timeout: Duration,
     },
+    Unsubscribe {
+        sid: String,
+    },
 }
 
 impl core::fmt::Display for SubscribeRequest {
@@ -91,15 +106,19 @@ impl core::fmt::Display for SubscribeRequest {
             SubscribeRequest::Renew { sid, timeout } => {
                 write!(f, "renew;sid={sid};timeout={timeout:?}")
             }
+            SubscribeRequest::Unsubscribe { sid } => {
+                write!(f, "unsubscribe;sid={sid}")
+            }
         }
     }
 }
 
 impl SubscribeRequest {
-    fn timeout(&self) -> Duration {
+    fn timeout(&self) -> Option<Duration> {
         match self {
-            SubscribeRequest::Create { timeout, .. } => *timeout,
-            SubscribeRequest::Renew { timeout, .. } => *timeout,
+            SubscribeRequest::Create { timeout, .. } => Some(*timeout),
+            SubscribeRequest::Renew { timeout, .. } => Some(*timeout),
+            SubscribeRequest::Unsubscribe { .. } => None,
         }
     }
 }
@@ -109,9 +128,11 @@ impl SubscribeRequest {
     pub fn parse(
         request: axum::extract::Request,
     ) -> Result<SubscribeRequest, axum::response::Response> {
-        if request.method().as_str() != "SUBSCRIBE" {
-            return Err(StatusCode::METHOD_NOT_ALLOWED.into_response());
-        }
+        let is_unsubscribe = match request.method().as_str() {
+            "SUBSCRIBE" => false,
+            "UNSUBSCRIBE" => true,
+            _ => return Err(StatusCode::METHOD_NOT_ALLOWED.into_response()),
+        };
 
         let (parts, _body) = request.into_parts();
         let is_event = parts
@@ -143,6 +164,15 @@ impl SubscribeRequest {
 
         let timeout = timeout.unwrap_or(DEFAULT_TIMEOUT).min(DEFAULT_TIMEOUT);
 
+        if is_unsubscribe {
+            return match subscription_id {
+                Some(sid) => Ok(SubscribeRequest::Unsubscribe {
+                    sid: sid.to_owned(),
+                }),
+                None => Err(StatusCode::PRECONDITION_FAILED.into_response()),
+            };
+        }
+
         match (is_event, callback, subscription_id) {
             (true, Some(callback), None) => Ok(SubscribeRequest::Create { callback, timeout }),
             (_, _, Some(sid)) => Ok(SubscribeRequest::Renew {
@@ -158,13 +188,15 @@ impl SubscribeRequest {
 pub(crate) enum SubscriptionResult {
     Renewed { sid: String },
     Created { sid: String },
+    Removed,
 }
 
 impl SubscriptionResult {
-    fn sid(&self) -> &str {
+    fn sid(&self) -> Option<&str> {
         match self {
-            SubscriptionResult::Renewed { sid } => sid,
-            SubscriptionResult::Created { sid } => sid,
+            SubscriptionResult::Renewed { sid } => Some(sid),
+            SubscriptionResult::Created { sid } => Some(sid),
+            SubscriptionResult::Removed => None,
         }
     }
 }
@@ -183,14 +215,17 @@ pub(crate) fn subscription_into_response(
         }
     };
 
-    (
-        StatusCode::OK,
-        [
-            ("SID", result.sid().to_owned()),
-            ("TIMEOUT", format!("Second-{}", request.timeout().as_secs())),
-        ],
-    )
-        .into_response()
+    match (result.sid(), request.timeout()) {
+        (Some(sid), Some(timeout)) => (
+            StatusCode::OK,
+            [
+                ("SID", sid.to_owned()),
+                ("TIMEOUT", format!("Second-{}", timeout.as_secs())),
+            ],
+        )
+            .into_response(),
+        _ => StatusCode::OK.into_response(),
+    }
 }
 
 impl UpnpServerStateInner {
@@ -208,6 +243,10 @@ impl UpnpServerStateInner {
                     .update_timeout(sid, *timeout)?;
                 Ok(SubscriptionResult::Renewed { sid: sid.clone() })
             }
+            SubscribeRequest::Unsubscribe { sid } => {
+                self.content_directory_subscriptions.remove(sid)?;
+                Ok(SubscriptionResult::Removed)
+            }
         }
     }
 
@@ -225,6 +264,10 @@ impl UpnpServerStateInner {
                     .update_timeout(sid, *timeout)?;
                 Ok(SubscriptionResult::Renewed { sid: sid.clone() })
             }
+            SubscribeRequest::Unsubscribe { sid } => {
+                self.connection_manager_subscriptions.remove(sid)?;
+                Ok(SubscriptionResult::Removed)
+            }
         }
     }
 
@@ -233,10 +276,10 @@ impl UpnpServerStateInner {
         url: url::Url,
         timeout: Duration,
     ) -> anyhow::Result<String> {
-        let (sid, refresh_notify) = self
-            .content_directory_subscriptions
-            .add(url.clone(), timeout);
         let token = self.cancel_token.child_token();
+        let (sid, refresh_notify) =
+            self.content_directory_subscriptions
+                .add(url.clone(), timeout, token.clone());
 
         // Spawn a task that will notify it of system id changes.
         // Spawn a task that will wait for timeout or subscription refreshes.
@@ -252,31 +295,44 @@ impl UpnpServerStateInner {
             async move {
                 use crate::services::content_directory::subscription::notify_system_id_update;
                 let system_update_id_notifier = async {
+                    // The initial event carries the current values. Give the control point a
+                    // moment to process the SUBSCRIBE response first, so it knows the SID.
+                    tokio::time::sleep(INITIAL_EVENT_DELAY).await;
+                    {
+                        let state = state.upgrade().context("upnp server dead")?;
+                        let seq = state.content_directory_subscriptions.next_seq(&sid)?;
+                        let system_update_id = state.system_update_id.load(Ordering::Relaxed);
+                        if let Err(e) =
+                            notify_system_id_update(&url, &sid, seq, system_update_id, "").await
+                        {
+                            debug!(error=?e, "error sending initial event to UPNP subscriber");
+                        }
+                    }
                     loop {
                         let res = brx.recv().await;
                         let state = state.upgrade().context("upnp server dead")?;
-                        let seq = state.content_directory_subscriptions.next_seq(&sid)?;
-                        match res {
-                            Ok(system_update_id) => {
-                                trace!(system_update_id, "notifying SystemUpdateId update");
-                                if let Err(e) =
-                                    notify_system_id_update(&url, &sid, seq, system_update_id).await
-                                {
-                                    debug!(error=?e, "error updating UPNP subscription");
-                                }
-                            }
+                        let system_update_id = match res {
+                            Ok(system_update_id) => system_update_id,
                             Err(RecvError::Lagged(by)) => {
                                 warn!(by, "UPNP subscription lagged");
-                                let seq = state.content_directory_subscriptions.next_seq(&sid)?;
-                                let system_update_id =
-                                    state.system_update_id.load(Ordering::Relaxed);
-                                if let Err(e) =
-                                    notify_system_id_update(&url, &sid, seq, system_update_id).await
-                                {
-                                    debug!(error=?e, "error updating UPNP subscription");
-                                }
+                                state.system_update_id.load(Ordering::Relaxed)
                             }
                             Err(RecvError::Closed) => return Ok(()),
+                        };
+                        let seq = state.content_directory_subscriptions.next_seq(&sid)?;
+                        trace!(system_update_id, "notifying SystemUpdateId update");
+                        // The whole tree may have changed, so report it on the root container.
+                        let container_update_ids = format!("0,{system_update_id}");
+                        if let Err(e) = notify_system_id_update(
+                            &url,
+                            &sid,
+                            seq,
+                            system_update_id,
+                            &container_update_ids,
+                        )
+                        .await
+                        {
+                            debug!(error=?e, "error updating UPNP subscription");
                         }
                     }
                 }
@@ -321,10 +377,10 @@ impl UpnpServerStateInner {
         url: url::Url,
         timeout: Duration,
     ) -> anyhow::Result<String> {
-        let (sid, refresh_notify) = self
-            .connection_manager_subscriptions
-            .add(url.clone(), timeout);
-        let token = self.cancel_token.clone();
+        let token = self.cancel_token.child_token();
+        let (sid, refresh_notify) =
+            self.connection_manager_subscriptions
+                .add(url.clone(), timeout, token.clone());
 
         // Spawn a task that will notify it of system id changes.
         // Spawn a task that will wait for timeout or subscription refreshes.
@@ -365,3 +421,59 @@ impl UpnpServerStateInner {
         Ok(sid)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SubscribeRequest;
+
+    fn request(method: &str, headers: &[(&str, &str)]) -> axum::extract::Request {
+        let mut b = http::Request::builder()
+            .method(http::Method::from_bytes(method.as_bytes()).unwrap())
+            .uri("/subscribe/ContentDirectory");
+        for (k, v) in headers {
+            b = b.header(*k, *v);
+        }
+        b.body(axum::body::Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_subscribe_requests() {
+        let r = SubscribeRequest::parse(request(
+            "SUBSCRIBE",
+            &[
+                ("CALLBACK", "<http://192.168.1.2:1234/cb>"),
+                ("NT", "upnp:event"),
+                ("TIMEOUT", "Second-300"),
+            ],
+        ))
+        .unwrap();
+        match r {
+            SubscribeRequest::Create { callback, timeout } => {
+                assert_eq!(callback.as_str(), "http://192.168.1.2:1234/cb");
+                assert_eq!(timeout, Duration::from_secs(300));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let r = SubscribeRequest::parse(request(
+            "SUBSCRIBE",
+            &[("SID", "uuid:1"), ("TIMEOUT", "Second-100000")],
+        ))
+        .unwrap();
+        match r {
+            SubscribeRequest::Renew { sid, timeout } => {
+                assert_eq!(sid, "uuid:1");
+                assert_eq!(timeout, Duration::from_secs(1800));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let r = SubscribeRequest::parse(request("UNSUBSCRIBE", &[("SID", "uuid:1")])).unwrap();
+        assert!(matches!(r, SubscribeRequest::Unsubscribe { sid } if sid == "uuid:1"));
+
+        assert!(SubscribeRequest::parse(request("UNSUBSCRIBE", &[])).is_err());
+        assert!(SubscribeRequest::parse(request("GET", &[("SID", "uuid:1")])).is_err());
+    }
+}